@@ -0,0 +1,103 @@
+use std::env;
+use std::fs::metadata;
+use std::path::PathBuf;
+use std::process::exit;
+
+use filecoin_proofs::param::{
+    get_full_path_for_file_within_cache, verify_parameter_cache_with_progress, ParameterFileStatus,
+};
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{error, info, warn};
+use storage_proofs_core::parameter_cache::{parameter_cache_dir_name, PARAMETERS, SRS_PARAMETERS};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "paramverify",
+    about = "Verifies the digests of the files in the parameter cache against the manifest.\n\n\
+    Set the $FIL_PROOFS_PARAMETER_CACHE env-var to specify the path to the parameter cache \
+    directory, otherwise '/var/tmp/filecoin-proof-parameters/' is used."
+)]
+struct Opt {
+    #[structopt(
+        short = "j",
+        long,
+        default_value = "0",
+        help = "The number of files to hash at once, 0 uses one thread per core."
+    )]
+    parallelism: usize,
+    #[structopt(
+        help = "The files to verify, by their name within the cache. If none are given, all files \
+        of the manifest that are in the cache are verified."
+    )]
+    files: Vec<String>,
+}
+
+pub fn main() {
+    // Log all log levels to stderr.
+    env::set_var("RUST_LOG", "paramverify");
+    fil_logger::init();
+
+    let opts = Opt::from_args();
+    info!("using parameter cache: {}", parameter_cache_dir_name());
+
+    let paths: Vec<PathBuf> = if opts.files.is_empty() {
+        PARAMETERS
+            .keys()
+            .chain(SRS_PARAMETERS.keys())
+            .map(|filename| get_full_path_for_file_within_cache(filename))
+            .filter(|path| path.exists())
+            .collect()
+    } else {
+        opts.files
+            .iter()
+            .map(|filename| get_full_path_for_file_within_cache(filename))
+            .collect()
+    };
+
+    let total_bytes = paths
+        .iter()
+        .filter_map(|path| metadata(path).ok())
+        .map(|metadata| metadata.len())
+        .sum();
+    let progress_bar = ProgressBar::new(total_bytes);
+    progress_bar.set_style(
+        ProgressStyle::default_bar()
+            .template("[{elapsed_precise}] {bar:40} {bytes}/{total_bytes} ({eta})"),
+    );
+
+    let reports = verify_parameter_cache_with_progress(&paths, opts.parallelism, |_, bytes| {
+        progress_bar.inc(bytes)
+    })
+    .unwrap_or_else(|err| {
+        error!("failed to verify parameter cache: {:?}", err);
+        exit(1);
+    });
+    progress_bar.finish();
+
+    let mut failed = false;
+    for report in &reports {
+        match &report.status {
+            ParameterFileStatus::Valid => info!("valid: {}", report.path.display()),
+            ParameterFileStatus::Unknown => {
+                warn!("not in manifest: {}", report.path.display())
+            }
+            status => {
+                error!("{}: {:?}", report.path.display(), status);
+                failed = true;
+            }
+        }
+    }
+
+    println!(
+        "verified {} files, {} valid",
+        reports.len(),
+        reports
+            .iter()
+            .filter(|report| report.status == ParameterFileStatus::Valid)
+            .count()
+    );
+    if failed {
+        exit(1);
+    }
+}
@@ -2,6 +2,7 @@
 //#![warn(clippy::unwrap_used)]
 
 use std::str::FromStr;
+use std::time::Duration;
 
 use anyhow::Result;
 use byte_unit::Byte;
@@ -12,8 +13,10 @@ use storage_proofs_core::api_version::ApiVersion;
 mod hash_fns;
 mod merkleproofs;
 mod porep;
+mod synthetic_porep;
 mod window_post;
 mod window_post_fake;
+mod window_post_faults;
 mod winning_post;
 
 fn main() -> Result<()> {
@@ -158,6 +161,57 @@ fn main() -> Result<()> {
                 .takes_value(true),
         );
 
+    let window_post_faults_cmd = Command::new("window-post-faults")
+        .about("Benchmark the detection of faulty sectors and Window PoSt skipping them")
+        .arg(
+            Arg::new("size")
+                .long("size")
+                .required(true)
+                .help("The data size (e.g. 2KiB)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("sectors")
+                .long("sectors")
+                .help("The number of sectors to seal (default: 4)")
+                .default_value("4")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("faulty")
+                .long("faulty")
+                .help("The number of sectors to corrupt (default: 1)")
+                .default_value("1")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("corruption")
+                .long("corruption")
+                .help("How the sectors are corrupted (default: flip-replica)")
+                .possible_values(["flip-replica", "truncate-tree-r-last"])
+                .default_value("flip-replica")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("fake")
+                .long("fake")
+                .help("Use fake replicas (default: false)")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::new("synthetic")
+                .long("synthetic")
+                .help("Use Synthetic PoRep (default: false)")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::new("api_version")
+                .long("api-version")
+                .help("The api_version to use (default: 1.2.0)")
+                .default_value("1.2.0")
+                .takes_value(true),
+        );
+
     let hash_cmd =
         Command::new("hash-constraints").about("Benchmark hash function inside of a circuit");
 
@@ -242,6 +296,46 @@ fn main() -> Result<()> {
                 .takes_value(true),
         );
 
+    let synthetic_porep_cmd = Command::new("synthetic-porep")
+        .about("Benchmark the Synthetic PoRep flow, with the time and disk usage of each stage")
+        .arg(
+            Arg::new("preserve-cache")
+                .long("preserve-cache")
+                .required(false)
+                .help("Preserve the directory where cached files are persisted")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::new("cache")
+                .long("cache")
+                .required(false)
+                .help("The directory where cached files are persisted")
+                .default_value("")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("size")
+                .long("size")
+                .required(true)
+                .help("The data size (e.g. 2KiB)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("wait_seed")
+                .long("wait-seed")
+                .required(false)
+                .help("The seconds to wait for the seed after the synthetic proofs were generated")
+                .default_value("0")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("api_version")
+                .long("api-version")
+                .help("The api_version to use (default: 1.2.0)")
+                .default_value("1.2.0")
+                .takes_value(true),
+        );
+
     let merkleproof_cmd = Command::new("merkleproofs")
         .about("Benchmark merkle proof generation")
         .arg(
@@ -273,9 +367,11 @@ fn main() -> Result<()> {
         .arg_required_else_help(true)
         .subcommand(window_post_cmd)
         .subcommand(window_post_fake_cmd)
+        .subcommand(window_post_faults_cmd)
         .subcommand(winning_post_cmd)
         .subcommand(hash_cmd)
         .subcommand(porep_cmd)
+        .subcommand(synthetic_porep_cmd)
         .subcommand(merkleproof_cmd)
         .get_matches();
 
@@ -352,6 +448,25 @@ fn main() -> Result<()> {
             let use_synthetic = m.is_present("synthetic");
             window_post_fake::run(sector_size, fake_replica, api_version, use_synthetic)?;
         }
+        Some(("window-post-faults", m)) => {
+            let sector_size = Byte::from_str(m.value_of_t::<String>("size")?)?.get_bytes() as usize;
+            let sectors = m.value_of_t::<usize>("sectors")?;
+            let faulty_sectors = m.value_of_t::<usize>("faulty")?;
+            let corruption =
+                window_post_faults::Corruption::from_str(&m.value_of_t::<String>("corruption")?)?;
+            let fake_replica = m.is_present("fake");
+            let api_version = ApiVersion::from_str(&m.value_of_t::<String>("api_version")?)?;
+            let use_synthetic = m.is_present("synthetic");
+            window_post_faults::run(
+                sector_size,
+                sectors,
+                faulty_sectors,
+                corruption,
+                fake_replica,
+                api_version,
+                use_synthetic,
+            )?;
+        }
         Some(("hash-constraints", _m)) => {
             hash_fns::run()?;
         }
@@ -387,6 +502,21 @@ fn main() -> Result<()> {
                 use_synthetic,
             )?;
         }
+        Some(("synthetic-porep", m)) => {
+            let preserve_cache = m.is_present("preserve-cache");
+            let cache_dir = m.value_of_t::<String>("cache")?;
+            let sector_size = Byte::from_str(m.value_of_t::<String>("size")?)?.get_bytes() as usize;
+            let wait_seed = Duration::from_secs(m.value_of_t::<u64>("wait_seed")?);
+            let api_version = ApiVersion::from_str(&m.value_of_t::<String>("api_version")?)?;
+
+            synthetic_porep::run(
+                sector_size,
+                api_version,
+                cache_dir,
+                preserve_cache,
+                wait_seed,
+            )?;
+        }
         _ => unreachable!(),
     }
 
@@ -1,5 +1,5 @@
 use std::fs::{create_dir_all, read, read_to_string, remove_dir_all, File, OpenOptions};
-use std::io::{stdout, Seek, Write};
+use std::io::{stdout, BufReader, BufWriter, Seek, Write};
 use std::path::PathBuf;
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -10,12 +10,13 @@ use fil_proofs_tooling::shared::{PROVER_ID, TICKET_BYTES};
 use fil_proofs_tooling::{measure, Metadata};
 use filecoin_proofs::types::{
     PaddedBytesAmount, PieceInfo, PoRepConfig, SealCommitPhase1Output, SealPreCommitOutput,
-    SealPreCommitPhase1Output, UnpaddedBytesAmount,
+    SealPreCommitPhase1Output, Ticket, UnpaddedBytesAmount,
 };
 use filecoin_proofs::{
     add_piece, clear_synthetic_proofs, generate_piece_commitment, generate_synth_proofs,
-    seal_commit_phase1, seal_commit_phase2, seal_pre_commit_phase1, seal_pre_commit_phase2,
-    validate_cache_for_commit, validate_cache_for_precommit_phase2, with_shape,
+    read_seal_commit_phase1_output, seal_commit_phase1, seal_commit_phase2, seal_pre_commit_phase1,
+    seal_pre_commit_phase2, validate_cache_for_commit, validate_cache_for_precommit_phase2,
+    with_shape, write_seal_commit_phase1_output,
 };
 use log::info;
 use serde::{Deserialize, Serialize};
@@ -388,7 +389,7 @@ pub fn run_porep_bench<Tree: 'static + MerkleTreeTrait>(
         res
     };
 
-    let seed = [1u8; 32];
+    let seed = Ticket::from([1u8; 32]);
     let sector_id = SectorId::from(SECTOR_ID);
     let porep_config = get_porep_config(sector_size, api_version, use_synthetic);
 
@@ -448,20 +449,21 @@ pub fn run_porep_bench<Tree: 'static + MerkleTreeTrait>(
 
             // Persist commit phase1_output here
             let phase1_output_path = cache_dir.join(COMMIT_PHASE1_OUTPUT_FILE);
-            let mut f = File::create(&phase1_output_path).with_context(|| {
+            let f = File::create(&phase1_output_path).with_context(|| {
                 format!(
                     "could not create file phase1_output_path={:?}",
                     phase1_output_path
                 )
             })?;
             info!("*** Created commit phase1 output file");
-            let phase1_output_bytes = serialize(&phase1_output)?;
-            f.write_all(&phase1_output_bytes).with_context(|| {
-                format!(
-                    "could not write to file phase1_output_path={:?}",
-                    phase1_output_path
-                )
-            })?;
+            write_seal_commit_phase1_output(&phase1_output, BufWriter::new(f)).with_context(
+                || {
+                    format!(
+                        "could not write to file phase1_output_path={:?}",
+                        phase1_output_path
+                    )
+                },
+            )?;
             info!("Persisted commit phase1 output to {:?}", phase1_output_path);
 
             (
@@ -482,15 +484,15 @@ pub fn run_porep_bench<Tree: 'static + MerkleTreeTrait>(
         let commit_phase1_output = {
             let commit_phase1_output_path = cache_dir.join(COMMIT_PHASE1_OUTPUT_FILE);
             info!("*** Restoring commit phase1 output file");
-            let commit_phase1_output_bytes =
-                read(&commit_phase1_output_path).with_context(|| {
-                    format!(
-                        "could not read file commit_phase1_output_path={:?}",
-                        commit_phase1_output_path
-                    )
-                })?;
+            let f = File::open(&commit_phase1_output_path).with_context(|| {
+                format!(
+                    "could not open file commit_phase1_output_path={:?}",
+                    commit_phase1_output_path
+                )
+            })?;
 
-            let res: SealCommitPhase1Output<Tree> = deserialize(&commit_phase1_output_bytes)?;
+            let res: SealCommitPhase1Output<Tree> =
+                read_seal_commit_phase1_output(BufReader::new(f))?;
 
             res
         };
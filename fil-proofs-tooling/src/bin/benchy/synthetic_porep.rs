@@ -0,0 +1,329 @@
+use std::fs::{create_dir_all, read_dir, remove_dir_all, OpenOptions};
+use std::io::{stdout, Seek};
+use std::path::{Path, PathBuf};
+use std::thread::sleep;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{ensure, Result};
+use fil_proofs_tooling::shared::{create_piece, PROVER_ID, TICKET_BYTES};
+use fil_proofs_tooling::{measure, Metadata};
+use filecoin_proofs::types::{PaddedBytesAmount, PoRepConfig, Ticket, UnpaddedBytesAmount};
+use filecoin_proofs::{
+    add_piece, clear_layer_data, clear_synthetic_proofs, generate_piece_commitment,
+    generate_synth_proofs, seal_commit_phase1, seal_commit_phase2, seal_pre_commit_phase1,
+    seal_pre_commit_phase2, seal_scratch_space, verify_seal, with_shape,
+};
+use log::info;
+use serde::Serialize;
+use storage_proofs_core::{
+    api_version::{ApiFeature, ApiVersion},
+    merkle::MerkleTreeTrait,
+    sector::SectorId,
+};
+
+const SECTOR_ID: u64 = 0;
+const SEED: Ticket = Ticket([1; 32]);
+
+const STAGED_FILE: &str = "staged-file";
+const SEALED_FILE: &str = "sealed-file";
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Inputs {
+    sector_size: u64,
+    wait_seed_ms: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Outputs {
+    seal_pre_commit_phase1_cpu_time_ms: u64,
+    seal_pre_commit_phase1_wall_time_ms: u64,
+    seal_pre_commit_phase1_disk_usage_bytes: u64,
+    seal_pre_commit_phase2_cpu_time_ms: u64,
+    seal_pre_commit_phase2_wall_time_ms: u64,
+    seal_pre_commit_phase2_disk_usage_bytes: u64,
+    generate_synth_proofs_cpu_time_ms: u64,
+    generate_synth_proofs_wall_time_ms: u64,
+    generate_synth_proofs_disk_usage_bytes: u64,
+    // The disk usage while waiting for the seed, after the layers were cleared.
+    wait_seed_disk_usage_bytes: u64,
+    seal_commit_phase1_cpu_time_ms: u64,
+    seal_commit_phase1_wall_time_ms: u64,
+    seal_commit_phase2_cpu_time_ms: u64,
+    seal_commit_phase2_wall_time_ms: u64,
+    seal_commit_phase2_disk_usage_bytes: u64,
+    estimated_peak_disk_usage_bytes: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Report {
+    inputs: Inputs,
+    outputs: Outputs,
+}
+
+impl Report {
+    /// Print all results to stdout
+    pub fn print(&self) {
+        let wrapped = Metadata::wrap(&self).expect("failed to retrieve metadata");
+        serde_json::to_writer(stdout(), &wrapped).expect("cannot write report JSON to stdout");
+    }
+}
+
+/// Returns the size of all files within `path`, recursively.
+fn disk_usage(path: &Path) -> Result<u64> {
+    let mut size = 0;
+    for entry in read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            size += disk_usage(&entry.path())?;
+        } else {
+            size += metadata.len();
+        }
+    }
+    Ok(size)
+}
+
+fn run_synthetic_porep_bench<Tree: 'static + MerkleTreeTrait>(
+    sector_size: u64,
+    api_version: ApiVersion,
+    cache_dir: PathBuf,
+    preserve_cache: bool,
+    wait_seed: Duration,
+) -> Result<()> {
+    let arbitrary_porep_id = [99; 32];
+    let porep_config = PoRepConfig::new_groth16(sector_size, arbitrary_porep_id, api_version)
+        .with_feature(ApiFeature::SyntheticPoRep);
+    let sector_id = SectorId::from(SECTOR_ID);
+
+    let estimated_peak_disk_usage_bytes = seal_scratch_space::<Tree>(&porep_config)?.peak();
+
+    // Stage the sector.
+    let sector_size_unpadded_bytes_amount =
+        UnpaddedBytesAmount::from(PaddedBytesAmount(sector_size));
+    let mut piece_file = create_piece(sector_size_unpadded_bytes_amount, true);
+    let piece_info =
+        generate_piece_commitment(piece_file.as_file_mut(), sector_size_unpadded_bytes_amount)?;
+    piece_file.as_file_mut().rewind()?;
+
+    let staged_file_path = cache_dir.join(STAGED_FILE);
+    let mut staged_file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(&staged_file_path)?;
+    add_piece(
+        piece_file.as_file_mut(),
+        &mut staged_file,
+        sector_size_unpadded_bytes_amount,
+        &[],
+    )?;
+    let piece_infos = vec![piece_info];
+
+    let sealed_file_path = cache_dir.join(SEALED_FILE);
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(&sealed_file_path)?;
+
+    let seal_pre_commit_phase1_measurement = measure(|| {
+        seal_pre_commit_phase1::<_, _, _, Tree>(
+            &porep_config,
+            cache_dir.clone(),
+            staged_file_path.clone(),
+            sealed_file_path.clone(),
+            PROVER_ID,
+            sector_id,
+            TICKET_BYTES,
+            &piece_infos,
+        )
+    })
+    .expect("failed in seal_pre_commit_phase1");
+    let seal_pre_commit_phase1_output = seal_pre_commit_phase1_measurement.return_value;
+    let seal_pre_commit_phase1_disk_usage_bytes = disk_usage(&cache_dir)?;
+
+    let seal_pre_commit_phase2_measurement = measure(|| {
+        seal_pre_commit_phase2::<_, _, Tree>(
+            &porep_config,
+            seal_pre_commit_phase1_output,
+            cache_dir.clone(),
+            sealed_file_path.clone(),
+        )
+    })
+    .expect("failed in seal_pre_commit_phase2");
+    let seal_pre_commit_output = seal_pre_commit_phase2_measurement.return_value;
+    let seal_pre_commit_phase2_disk_usage_bytes = disk_usage(&cache_dir)?;
+
+    let generate_synth_proofs_measurement = measure(|| {
+        generate_synth_proofs::<_, Tree>(
+            &porep_config,
+            &cache_dir,
+            &sealed_file_path,
+            PROVER_ID,
+            sector_id,
+            TICKET_BYTES,
+            seal_pre_commit_output.clone(),
+            &piece_infos,
+        )
+    })
+    .expect("failed to generate synthetic proofs");
+    let generate_synth_proofs_disk_usage_bytes = disk_usage(&cache_dir)?;
+
+    // Only the synthetic proofs are needed to extract the commit proofs, the layers can be
+    // discarded while waiting for the seed.
+    clear_layer_data::<Tree>(&cache_dir)?;
+    let wait_seed_disk_usage_bytes = disk_usage(&cache_dir)?;
+    info!("Simulating the wait for the seed for {:?}", wait_seed);
+    sleep(wait_seed);
+
+    let seal_commit_phase1_measurement = measure(|| {
+        seal_commit_phase1::<_, Tree>(
+            &porep_config,
+            cache_dir.clone(),
+            sealed_file_path.clone(),
+            PROVER_ID,
+            sector_id,
+            TICKET_BYTES,
+            SEED,
+            seal_pre_commit_output.clone(),
+            &piece_infos,
+        )
+    })
+    .expect("failed to extract the commit phase1 output from the synthetic proofs");
+    let seal_commit_phase1_output = seal_commit_phase1_measurement.return_value;
+    clear_synthetic_proofs::<Tree>(&cache_dir)?;
+
+    let seal_commit_phase2_measurement = measure(|| {
+        seal_commit_phase2::<Tree>(
+            &porep_config,
+            seal_commit_phase1_output,
+            PROVER_ID,
+            sector_id,
+        )
+    })
+    .expect("failed in seal_commit_phase2");
+    let seal_commit_output = seal_commit_phase2_measurement.return_value;
+    let seal_commit_phase2_disk_usage_bytes = disk_usage(&cache_dir)?;
+
+    ensure!(
+        verify_seal::<Tree>(
+            &porep_config,
+            seal_pre_commit_output.comm_r,
+            seal_pre_commit_output.comm_d,
+            PROVER_ID,
+            sector_id,
+            TICKET_BYTES,
+            SEED,
+            &seal_commit_output.proof,
+        )?,
+        "the synthetic PoRep proof does not verify"
+    );
+
+    if preserve_cache {
+        info!("Preserving cache directory {:?}", cache_dir);
+    } else {
+        info!("Removing cache directory {:?}", cache_dir);
+        remove_dir_all(cache_dir)?;
+    }
+
+    let report = Report {
+        inputs: Inputs {
+            sector_size,
+            wait_seed_ms: wait_seed.as_millis() as u64,
+        },
+        outputs: Outputs {
+            seal_pre_commit_phase1_cpu_time_ms: seal_pre_commit_phase1_measurement
+                .cpu_time
+                .as_millis() as u64,
+            seal_pre_commit_phase1_wall_time_ms: seal_pre_commit_phase1_measurement
+                .wall_time
+                .as_millis() as u64,
+            seal_pre_commit_phase1_disk_usage_bytes,
+            seal_pre_commit_phase2_cpu_time_ms: seal_pre_commit_phase2_measurement
+                .cpu_time
+                .as_millis() as u64,
+            seal_pre_commit_phase2_wall_time_ms: seal_pre_commit_phase2_measurement
+                .wall_time
+                .as_millis() as u64,
+            seal_pre_commit_phase2_disk_usage_bytes,
+            generate_synth_proofs_cpu_time_ms: generate_synth_proofs_measurement
+                .cpu_time
+                .as_millis() as u64,
+            generate_synth_proofs_wall_time_ms: generate_synth_proofs_measurement
+                .wall_time
+                .as_millis() as u64,
+            generate_synth_proofs_disk_usage_bytes,
+            wait_seed_disk_usage_bytes,
+            seal_commit_phase1_cpu_time_ms: seal_commit_phase1_measurement.cpu_time.as_millis()
+                as u64,
+            seal_commit_phase1_wall_time_ms: seal_commit_phase1_measurement.wall_time.as_millis()
+                as u64,
+            seal_commit_phase2_cpu_time_ms: seal_commit_phase2_measurement.cpu_time.as_millis()
+                as u64,
+            seal_commit_phase2_wall_time_ms: seal_commit_phase2_measurement.wall_time.as_millis()
+                as u64,
+            seal_commit_phase2_disk_usage_bytes,
+            estimated_peak_disk_usage_bytes,
+        },
+    };
+
+    // Create a JSON serializable report that we print to stdout (that will later be parsed using
+    // the CLI JSON parser `jq`).
+    report.print();
+    Ok(())
+}
+
+pub fn run(
+    sector_size: usize,
+    api_version: ApiVersion,
+    cache: String,
+    preserve_cache: bool,
+    wait_seed: Duration,
+) -> Result<()> {
+    info!(
+        "Benchy Synthetic PoRep: sector-size={}, api_version={}, preserve_cache={}, wait_seed={:?}",
+        sector_size, api_version, preserve_cache, wait_seed
+    );
+    ensure!(
+        api_version.supports_feature(&ApiFeature::SyntheticPoRep),
+        "Synthetic PoRep is not supported by api version {}",
+        api_version
+    );
+
+    let cache_dir_specified = !cache.is_empty();
+    if preserve_cache {
+        ensure!(
+            cache_dir_specified && !PathBuf::from(&cache).exists(),
+            "The 'preserve_cache' option cannot be used with a cache_dir that already exists"
+        );
+    }
+
+    let (cache_dir, preserve_cache) = if cache_dir_specified {
+        // If a cache dir was specified, automatically preserve it.
+        (PathBuf::from(cache), true)
+    } else {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        (
+            std::env::temp_dir().join(format!("synthetic-porep-bench-{}", timestamp)),
+            preserve_cache,
+        )
+    };
+
+    if !cache_dir.exists() {
+        create_dir_all(&cache_dir)?;
+    }
+    info!("Using cache directory {:?}", cache_dir);
+
+    with_shape!(
+        sector_size as u64,
+        run_synthetic_porep_bench,
+        sector_size as u64,
+        api_version,
+        cache_dir,
+        preserve_cache,
+        wait_seed,
+    )
+}
@@ -1,6 +1,6 @@
 use std::collections::BTreeMap;
 use std::fs::{create_dir_all, read, read_to_string, remove_dir_all, File, OpenOptions};
-use std::io::{stdout, Seek, Write};
+use std::io::{stdout, BufReader, BufWriter, Seek, Write};
 use std::path::PathBuf;
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -12,13 +12,14 @@ use fil_proofs_tooling::{measure, Metadata};
 use filecoin_proofs::constants::{WINDOW_POST_CHALLENGE_COUNT, WINDOW_POST_SECTOR_COUNT};
 use filecoin_proofs::types::{
     PaddedBytesAmount, PieceInfo, PoRepConfig, PoStConfig, SealCommitPhase1Output,
-    SealPreCommitOutput, SealPreCommitPhase1Output, SectorSize, UnpaddedBytesAmount,
+    SealPreCommitOutput, SealPreCommitPhase1Output, SectorSize, Ticket, UnpaddedBytesAmount,
 };
 use filecoin_proofs::{
     add_piece, generate_piece_commitment, generate_synth_proofs, generate_window_post,
-    seal_commit_phase1, seal_commit_phase2, seal_pre_commit_phase1, seal_pre_commit_phase2,
-    validate_cache_for_commit, validate_cache_for_precommit_phase2, verify_window_post, with_shape,
-    PoStType, PrivateReplicaInfo, PublicReplicaInfo,
+    read_seal_commit_phase1_output, seal_commit_phase1, seal_commit_phase2, seal_pre_commit_phase1,
+    seal_pre_commit_phase2, validate_cache_for_commit, validate_cache_for_precommit_phase2,
+    verify_window_post, with_shape, write_seal_commit_phase1_output, PoStType, PrivateReplicaInfo,
+    PublicReplicaInfo,
 };
 use log::info;
 use serde::{Deserialize, Serialize};
@@ -396,7 +397,7 @@ pub fn run_window_post_bench<Tree: 'static + MerkleTreeTrait>(
         res
     };
 
-    let seed = [1u8; 32];
+    let seed = Ticket::from([1u8; 32]);
     let comm_r = seal_pre_commit_output.comm_r;
 
     let sector_id = SectorId::from(SECTOR_ID);
@@ -453,15 +454,14 @@ pub fn run_window_post_bench<Tree: 'static + MerkleTreeTrait>(
 
         // Persist commit phase1_output here
         let phase1_output_path = cache_dir.join(COMMIT_PHASE1_OUTPUT_FILE);
-        let mut f = File::create(&phase1_output_path).with_context(|| {
+        let f = File::create(&phase1_output_path).with_context(|| {
             format!(
                 "could not create file phase1_output_path={:?}",
                 phase1_output_path
             )
         })?;
         info!("*** Created commit phase1 output file");
-        let phase1_output_bytes = serialize(&phase1_output)?;
-        f.write_all(&phase1_output_bytes).with_context(|| {
+        write_seal_commit_phase1_output(&phase1_output, BufWriter::new(f)).with_context(|| {
             format!(
                 "could not write to file phase1_output_path={:?}",
                 phase1_output_path
@@ -484,15 +484,15 @@ pub fn run_window_post_bench<Tree: 'static + MerkleTreeTrait>(
         let commit_phase1_output = {
             let commit_phase1_output_path = cache_dir.join(COMMIT_PHASE1_OUTPUT_FILE);
             info!("*** Restoring commit phase1 output file");
-            let commit_phase1_output_bytes =
-                read(&commit_phase1_output_path).with_context(|| {
-                    format!(
-                        "could not read file commit_phase1_output_path={:?}",
-                        commit_phase1_output_path
-                    )
-                })?;
+            let f = File::open(&commit_phase1_output_path).with_context(|| {
+                format!(
+                    "could not open file commit_phase1_output_path={:?}",
+                    commit_phase1_output_path
+                )
+            })?;
 
-            let res: SealCommitPhase1Output<Tree> = deserialize(&commit_phase1_output_bytes)?;
+            let res: SealCommitPhase1Output<Tree> =
+                read_seal_commit_phase1_output(BufReader::new(f))?;
 
             res
         };
@@ -0,0 +1,316 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{read_dir, remove_dir_all, remove_file, OpenOptions};
+use std::io::{stdout, Read, Seek, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{bail, ensure, Context, Result};
+use fil_proofs_tooling::shared::{create_replicas, PROVER_ID, RANDOMNESS};
+use fil_proofs_tooling::{measure, Metadata};
+use filecoin_proofs::constants::{WINDOW_POST_CHALLENGE_COUNT, WINDOW_POST_SECTOR_COUNT};
+use filecoin_proofs::types::{PoStConfig, SectorSize};
+use filecoin_proofs::{
+    generate_fallback_sector_challenges, generate_single_vanilla_proof, generate_window_post,
+    verify_window_post, with_shape, PoStType, PrivateReplicaInfo, PublicReplicaInfo,
+};
+use log::{info, warn};
+use serde::Serialize;
+use storage_proofs_core::{
+    api_version::{ApiFeature, ApiVersion},
+    cache_key::CacheKey,
+    merkle::MerkleTreeTrait,
+    sector::SectorId,
+    util::NODE_SIZE,
+};
+
+/// The ways a sector is corrupted.
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Corruption {
+    /// Truncates the persisted tree_r_last to half of its size.
+    TruncateTreeRLast,
+    /// Flips the lowest bit of every node of the replica.
+    FlipReplica,
+}
+
+impl FromStr for Corruption {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "truncate-tree-r-last" => Ok(Corruption::TruncateTreeRLast),
+            "flip-replica" => Ok(Corruption::FlipReplica),
+            _ => bail!("unknown corruption: {}", s),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Inputs {
+    sector_size: u64,
+    sectors: usize,
+    faulty_sectors: usize,
+    corruption: Corruption,
+    fake_replica: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Outputs {
+    detect_faults_cpu_time_ms: u64,
+    detect_faults_wall_time_ms: u64,
+    detected_faulty_sectors: usize,
+    /// Whether exactly the corrupted sectors were detected.
+    detection_correct: bool,
+    gen_window_post_skipping_faults_cpu_time_ms: u64,
+    gen_window_post_skipping_faults_wall_time_ms: u64,
+    /// Whether the proof over the remaining sectors verifies.
+    skipping_faults_proof_valid: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Report {
+    inputs: Inputs,
+    outputs: Outputs,
+}
+
+impl Report {
+    /// Print all results to stdout
+    pub fn print(&self) {
+        let wrapped = Metadata::wrap(&self).expect("failed to retrieve metadata");
+        serde_json::to_writer(stdout(), &wrapped).expect("cannot write report JSON to stdout");
+    }
+}
+
+fn truncate_tree_r_last(cache_dir: &Path) -> Result<()> {
+    let tree_r_last = CacheKey::CommRLastTree.to_string();
+    let mut truncated = false;
+    for entry in read_dir(cache_dir)? {
+        let path = entry?.path();
+        let is_tree_r_last = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map_or(false, |name| name.contains(&tree_r_last));
+        if is_tree_r_last {
+            let file = OpenOptions::new().write(true).open(&path)?;
+            let len = file.metadata()?.len();
+            file.set_len(len / 2)?;
+            truncated = true;
+        }
+    }
+    ensure!(truncated, "no tree_r_last found in {:?}", cache_dir);
+
+    Ok(())
+}
+
+fn flip_replica(replica_path: &Path) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(replica_path)?;
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    // Only the lowest bit is flipped, so that the nodes are still valid field elements.
+    for node in data.chunks_mut(NODE_SIZE) {
+        node[0] ^= 1;
+    }
+    file.rewind()?;
+    file.write_all(&data)?;
+    file.sync_all()?;
+
+    Ok(())
+}
+
+fn corrupt<Tree: MerkleTreeTrait>(
+    replica: &PrivateReplicaInfo<Tree>,
+    corruption: Corruption,
+) -> Result<()> {
+    match corruption {
+        Corruption::TruncateTreeRLast => truncate_tree_r_last(replica.cache_dir_path()),
+        Corruption::FlipReplica => flip_replica(replica.replica_path()),
+    }
+}
+
+pub fn run_window_post_faults_bench<Tree: 'static + MerkleTreeTrait>(
+    sector_size: u64,
+    sectors: usize,
+    faulty_sectors: usize,
+    corruption: Corruption,
+    fake_replica: bool,
+    api_version: ApiVersion,
+    api_features: Vec<ApiFeature>,
+) -> Result<()> {
+    let arbitrary_porep_id = [66; 32];
+    let sector_count = *WINDOW_POST_SECTOR_COUNT
+        .read()
+        .expect("WINDOW_POST_SECTOR_COUNT poisoned")
+        .get(&sector_size)
+        .expect("unknown sector size");
+
+    let (_porep_config, replicas) = create_replicas::<Tree>(
+        SectorSize(sector_size),
+        sectors,
+        false,
+        fake_replica,
+        arbitrary_porep_id,
+        api_version,
+        api_features,
+    );
+    let (replicas, _) = replicas.expect("create_replicas() failed with only_add == false");
+
+    let mut pub_replica_info: BTreeMap<SectorId, PublicReplicaInfo> = BTreeMap::new();
+    let mut priv_replica_info: BTreeMap<SectorId, PrivateReplicaInfo<Tree>> = BTreeMap::new();
+    for (sector_id, replica_output) in &replicas {
+        pub_replica_info.insert(*sector_id, replica_output.public_replica_info.clone());
+        priv_replica_info.insert(*sector_id, replica_output.private_replica_info.clone());
+    }
+
+    let corrupted: BTreeSet<SectorId> = priv_replica_info
+        .keys()
+        .take(faulty_sectors)
+        .copied()
+        .collect();
+    for sector_id in &corrupted {
+        info!("Corrupting sector {:?} with {:?}", sector_id, corruption);
+        corrupt(&priv_replica_info[sector_id], corruption)?;
+    }
+
+    let post_config = PoStConfig {
+        sector_size: SectorSize(sector_size),
+        challenge_count: WINDOW_POST_CHALLENGE_COUNT,
+        sector_count,
+        typ: PoStType::Window,
+        priority: false,
+        api_version,
+    };
+
+    // Detect the faults the same way a storage provider checks its sectors before proving, by
+    // generating the vanilla proofs of every sector on its own.
+    let detect_faults_measurement = measure(|| {
+        let sector_ids: Vec<SectorId> = priv_replica_info.keys().copied().collect();
+        let challenges = generate_fallback_sector_challenges::<Tree>(
+            &post_config,
+            &RANDOMNESS,
+            &sector_ids,
+            PROVER_ID,
+        )?;
+
+        let mut faults = BTreeSet::new();
+        for (sector_id, replica) in &priv_replica_info {
+            if let Err(err) = generate_single_vanilla_proof::<Tree>(
+                &post_config,
+                *sector_id,
+                replica,
+                &challenges[sector_id],
+            ) {
+                warn!("Sector {:?} is faulty: {:?}", sector_id, err);
+                faults.insert(*sector_id);
+            }
+        }
+        Ok(faults)
+    })
+    .context("failed to detect faulty sectors")?;
+    let detected = detect_faults_measurement.return_value;
+
+    // Prove the remaining sectors.
+    let remaining_priv: BTreeMap<SectorId, PrivateReplicaInfo<Tree>> = priv_replica_info
+        .iter()
+        .filter(|(sector_id, _)| !detected.contains(sector_id))
+        .map(|(sector_id, replica)| (*sector_id, replica.clone()))
+        .collect();
+    let remaining_pub: BTreeMap<SectorId, PublicReplicaInfo> = pub_replica_info
+        .iter()
+        .filter(|(sector_id, _)| !detected.contains(sector_id))
+        .map(|(sector_id, replica)| (*sector_id, replica.clone()))
+        .collect();
+    ensure!(
+        !remaining_priv.is_empty(),
+        "all sectors are faulty, there is nothing left to prove"
+    );
+
+    let gen_window_post_measurement = measure(|| {
+        generate_window_post::<Tree>(&post_config, &RANDOMNESS, &remaining_priv, PROVER_ID)
+    })
+    .context("failed to generate window post skipping the faulty sectors")?;
+    let skipping_faults_proof_valid = verify_window_post::<Tree>(
+        &post_config,
+        &RANDOMNESS,
+        &remaining_pub,
+        PROVER_ID,
+        &gen_window_post_measurement.return_value,
+    )?;
+
+    // Clean-up sealed files and cache dirs.
+    for replica in priv_replica_info.values() {
+        remove_file(replica.replica_path())?;
+        remove_dir_all(replica.cache_dir_path())?;
+    }
+
+    let report = Report {
+        inputs: Inputs {
+            sector_size,
+            sectors,
+            faulty_sectors,
+            corruption,
+            fake_replica,
+        },
+        outputs: Outputs {
+            detect_faults_cpu_time_ms: detect_faults_measurement.cpu_time.as_millis() as u64,
+            detect_faults_wall_time_ms: detect_faults_measurement.wall_time.as_millis() as u64,
+            detected_faulty_sectors: detected.len(),
+            detection_correct: detected == corrupted,
+            gen_window_post_skipping_faults_cpu_time_ms: gen_window_post_measurement
+                .cpu_time
+                .as_millis() as u64,
+            gen_window_post_skipping_faults_wall_time_ms: gen_window_post_measurement
+                .wall_time
+                .as_millis() as u64,
+            skipping_faults_proof_valid,
+        },
+    };
+
+    // Create a JSON serializable report that we print to stdout (that will later be parsed using
+    // the CLI JSON parser `jq`).
+    report.print();
+    Ok(())
+}
+
+pub fn run(
+    sector_size: usize,
+    sectors: usize,
+    faulty_sectors: usize,
+    corruption: Corruption,
+    fake_replica: bool,
+    api_version: ApiVersion,
+    use_synthetic_porep: bool,
+) -> Result<()> {
+    info!(
+        "Benchy Window PoSt Faults: sector-size={}, sectors={}, faulty_sectors={}, \
+         corruption={:?}, fake_replica={}, api_version={}",
+        sector_size, sectors, faulty_sectors, corruption, fake_replica, api_version
+    );
+    ensure!(
+        faulty_sectors < sectors,
+        "at least one sector must not be faulty"
+    );
+
+    let api_features = if use_synthetic_porep {
+        vec![ApiFeature::SyntheticPoRep]
+    } else {
+        Vec::new()
+    };
+
+    with_shape!(
+        sector_size as u64,
+        run_window_post_faults_bench,
+        sector_size as u64,
+        sectors,
+        faulty_sectors,
+        corruption,
+        fake_replica,
+        api_version,
+        api_features,
+    )
+}
@@ -1,82 +1,17 @@
 use std::str::FromStr;
 
-use bellperson::{util_cs::bench_cs::BenchCS, Circuit};
-use blstrs::Scalar as Fr;
 use dialoguer::{theme::ColorfulTheme, MultiSelect};
 use filecoin_proofs::{
-    parameters::{public_params, window_post_public_params, winning_post_public_params},
-    with_shape, DefaultPieceHasher, PoRepConfig, PoRepProofPartitions, PoStConfig, PoStType,
-    SectorSize, POREP_PARTITIONS, PUBLISHED_SECTOR_SIZES, WINDOW_POST_CHALLENGE_COUNT,
-    WINDOW_POST_SECTOR_COUNT, WINNING_POST_CHALLENGE_COUNT, WINNING_POST_SECTOR_COUNT,
+    circuit_info, post_circuit_info, update_circuit_info, CircuitInfo, PoRepConfig,
+    PoRepProofPartitions, PoStConfig, PoStType, SectorSize, POREP_PARTITIONS,
+    PUBLISHED_SECTOR_SIZES, WINDOW_POST_CHALLENGE_COUNT, WINDOW_POST_SECTOR_COUNT,
+    WINNING_POST_CHALLENGE_COUNT, WINNING_POST_SECTOR_COUNT,
 };
 use humansize::{file_size_opts, FileSize};
 use log::{info, warn};
-use storage_proofs_core::{
-    api_version::ApiVersion, compound_proof::CompoundProof, merkle::MerkleTreeTrait,
-};
-use storage_proofs_porep::stacked::{StackedCompound, StackedDrg};
-use storage_proofs_post::fallback::{FallbackPoSt, FallbackPoStCircuit, FallbackPoStCompound};
+use storage_proofs_core::api_version::ApiVersion;
 use structopt::StructOpt;
 
-struct CircuitInfo {
-    constraints: usize,
-    inputs: usize,
-}
-
-fn circuit_info<C: Circuit<Fr>>(circuit: C) -> CircuitInfo {
-    let mut cs_blank = BenchCS::new();
-    circuit
-        .synthesize(&mut cs_blank)
-        .expect("failed to synthesize");
-
-    CircuitInfo {
-        constraints: cs_blank.num_constraints(),
-        inputs: cs_blank.num_inputs(),
-    }
-}
-
-fn get_porep_info<Tree: 'static + MerkleTreeTrait>(porep_config: PoRepConfig) -> CircuitInfo {
-    info!("PoRep info");
-
-    let public_params =
-        public_params(&porep_config).expect("failed to get public params from config");
-
-    let circuit = <StackedCompound<Tree, DefaultPieceHasher> as CompoundProof<
-        StackedDrg<Tree, DefaultPieceHasher>,
-        _,
-    >>::blank_circuit(&public_params);
-
-    circuit_info(circuit)
-}
-
-fn get_winning_post_info<Tree: 'static + MerkleTreeTrait>(post_config: &PoStConfig) -> CircuitInfo {
-    info!("Winning PoSt info");
-
-    let post_public_params = winning_post_public_params::<Tree>(post_config)
-        .expect("failed to get public params from config");
-
-    let circuit: FallbackPoStCircuit<Tree> = <FallbackPoStCompound<Tree> as CompoundProof<
-        FallbackPoSt<Tree>,
-        FallbackPoStCircuit<Tree>,
-    >>::blank_circuit(&post_public_params);
-
-    circuit_info(circuit)
-}
-
-fn get_window_post_info<Tree: 'static + MerkleTreeTrait>(post_config: &PoStConfig) -> CircuitInfo {
-    info!("Window PoSt info");
-
-    let post_public_params = window_post_public_params::<Tree>(post_config)
-        .expect("failed to get public params from config");
-
-    let circuit: FallbackPoStCircuit<Tree> = <FallbackPoStCompound<Tree> as CompoundProof<
-        FallbackPoSt<Tree>,
-        FallbackPoStCircuit<Tree>,
-    >>::blank_circuit(&post_public_params);
-
-    circuit_info(circuit)
-}
-
 #[derive(Debug, StructOpt)]
 #[structopt(name = "circuitinfo")]
 struct Opt {
@@ -86,6 +21,8 @@ struct Opt {
     window: bool,
     #[structopt(long)]
     porep: bool,
+    #[structopt(long)]
+    update: bool,
     #[structopt(short = "z", long, use_delimiter = true)]
     constraints_for_sector_sizes: Vec<u64>,
     #[structopt(default_value = "1.0.0", long)]
@@ -93,37 +30,33 @@ struct Opt {
 }
 
 fn winning_post_info(sector_size: u64, api_version: ApiVersion) -> CircuitInfo {
-    with_shape!(
-        sector_size,
-        get_winning_post_info,
-        &PoStConfig {
-            sector_size: SectorSize(sector_size),
-            challenge_count: WINNING_POST_CHALLENGE_COUNT,
-            sector_count: WINNING_POST_SECTOR_COUNT,
-            typ: PoStType::Winning,
-            priority: true,
-            api_version,
-        }
-    )
+    info!("Winning PoSt info");
+    post_circuit_info(&PoStConfig {
+        sector_size: SectorSize(sector_size),
+        challenge_count: WINNING_POST_CHALLENGE_COUNT,
+        sector_count: WINNING_POST_SECTOR_COUNT,
+        typ: PoStType::Winning,
+        priority: true,
+        api_version,
+    })
+    .expect("failed to get winning post circuit info")
 }
 
 fn window_post_info(sector_size: u64, api_version: ApiVersion) -> CircuitInfo {
-    with_shape!(
-        sector_size,
-        get_window_post_info,
-        &PoStConfig {
-            sector_size: SectorSize(sector_size),
-            challenge_count: WINDOW_POST_CHALLENGE_COUNT,
-            sector_count: *WINDOW_POST_SECTOR_COUNT
-                .read()
-                .expect("WINDOW_POST_SECTOR_COUNT poisoned")
-                .get(&sector_size)
-                .expect("unknown sector size"),
-            typ: PoStType::Window,
-            priority: true,
-            api_version,
-        }
-    )
+    info!("Window PoSt info");
+    post_circuit_info(&PoStConfig {
+        sector_size: SectorSize(sector_size),
+        challenge_count: WINDOW_POST_CHALLENGE_COUNT,
+        sector_count: *WINDOW_POST_SECTOR_COUNT
+            .read()
+            .expect("WINDOW_POST_SECTOR_COUNT poisoned")
+            .get(&sector_size)
+            .expect("unknown sector size"),
+        typ: PoStType::Window,
+        priority: true,
+        api_version,
+    })
+    .expect("failed to get window post circuit info")
 }
 
 fn porep_info(sector_size: u64, api_version: ApiVersion) -> (CircuitInfo, usize) {
@@ -134,14 +67,18 @@ fn porep_info(sector_size: u64, api_version: ApiVersion) -> (CircuitInfo, usize)
             .get(&sector_size)
             .expect("unknown sector size"),
     );
-    let info = with_shape!(
-        sector_size,
-        get_porep_info,
-        PoRepConfig::new_groth16(sector_size, [0; 32], api_version)
-    );
+    info!("PoRep info");
+    let info = circuit_info(&PoRepConfig::new_groth16(sector_size, [0; 32], api_version))
+        .expect("failed to get porep circuit info");
     (info, partitions.into())
 }
 
+fn update_info(sector_size: u64, api_version: ApiVersion) -> CircuitInfo {
+    info!("Empty sector update info");
+    update_circuit_info(&PoRepConfig::new_groth16(sector_size, [0; 32], api_version))
+        .expect("failed to get empty sector update circuit info")
+}
+
 // Run this from the command-line to get info about circuits.
 pub fn main() {
     // The logger is used and every message from this tool is also logged into those logs.
@@ -207,6 +144,7 @@ pub fn main() {
     let count_winning = opts.winning;
     let count_window = opts.window;
     let count_porep = opts.porep;
+    let count_update = opts.update;
     let api_version = ApiVersion::from_str(&opts.api_version)
         .expect("Failed to parse api_version from semver string");
 
@@ -239,5 +177,13 @@ pub fn main() {
                 human_size, info.constraints, info.inputs, partitions
             );
         }
+
+        if count_update {
+            let info = update_info(sector_size, api_version);
+            println!(
+                "{} Empty sector update constraints (per partition): {}, public inputs (per partition): {}",
+                human_size, info.constraints, info.inputs
+            );
+        }
     }
 }
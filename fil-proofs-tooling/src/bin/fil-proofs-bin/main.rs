@@ -0,0 +1,556 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, ensure, Context, Result};
+use clap::{Arg, ArgMatches, Command};
+use filecoin_proofs::{
+    convert_window_post_vanilla_proofs_with_domain, gc_cache,
+    generate_fallback_sector_challenges_with_domain, generate_single_vanilla_proof,
+    read_seal_commit_phase1_output, seal_commit_phase2, with_shape, ChallengeDomain,
+    FallbackPoStSectorProof, GcPolicy, MerkleTreeTrait, PoRepConfig, PoStConfig, PoStType,
+    PrivateReplicaInfo, ProverId, RegisteredSealProof, SealCommitPhase1Output, SectorSize,
+    PUBLISHED_SECTOR_SIZES,
+};
+use log::info;
+use rayon::prelude::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use storage_proofs_core::{
+    api_version::{ApiFeature, ApiVersion},
+    sector::SectorId,
+};
+
+/// A replica that vanilla proofs are generated from.
+#[derive(Debug, Deserialize)]
+struct Replica {
+    replica_path: PathBuf,
+    cache_dir: PathBuf,
+    /// Hex encoded.
+    comm_r: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ConvertWindowPostParameters {
+    sector_size: u64,
+    api_version: ApiVersion,
+    #[serde(default)]
+    challenge_domain: ChallengeDomain,
+    /// The number of challenges per sector in the new layout.
+    challenge_count: usize,
+    /// The number of sectors per partition in the new layout.
+    sector_count: usize,
+    /// Hex encoded.
+    randomness: String,
+    prover_id: ProverId,
+    /// All sectors of the PoSt.
+    sectors: Vec<SectorId>,
+    /// Files holding the bincode encoded vanilla proofs, as generated for the old layout.
+    vanilla_proofs: Vec<PathBuf>,
+    #[serde(default)]
+    replicas: BTreeMap<SectorId, Replica>,
+    /// The directory the converted vanilla proofs are written to, one file per sector.
+    output_dir: PathBuf,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct ConvertWindowPostOutput {
+    /// The number of challenges that were taken from the given vanilla proofs.
+    reused_challenges: usize,
+    /// The number of challenges that were proven from the replicas.
+    proven_challenges: usize,
+    /// The challenges that could neither be reused nor proven, by sector. No vanilla proofs are
+    /// written if there are any.
+    missing_challenges: BTreeMap<SectorId, Vec<u64>>,
+    vanilla_proofs: Vec<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+enum PostType {
+    Winning,
+    Window,
+}
+
+impl From<PostType> for PoStType {
+    fn from(typ: PostType) -> Self {
+        match typ {
+            PostType::Winning => PoStType::Winning,
+            PostType::Window => PoStType::Window,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PostVanillaProofsParameters {
+    sector_size: u64,
+    api_version: ApiVersion,
+    #[serde(default)]
+    challenge_domain: ChallengeDomain,
+    typ: PostType,
+    /// The number of challenges per sector.
+    challenge_count: usize,
+    /// The number of sectors per partition.
+    sector_count: usize,
+    /// Hex encoded.
+    randomness: String,
+    prover_id: ProverId,
+    /// The sectors to prove, by sector id.
+    replicas: BTreeMap<SectorId, Replica>,
+    /// The maximum number of sectors that are proven at the same time. The proofs are bound by
+    /// reading the challenged nodes, so this should match what the storage can serve. If it is
+    /// `0`, the number of CPUs is used.
+    #[serde(default)]
+    max_parallel_sectors: usize,
+    /// The directory the vanilla proofs are written to, one file per sector.
+    output_dir: PathBuf,
+}
+
+#[derive(Debug, Serialize)]
+struct PostVanillaProofsOutput {
+    vanilla_proofs: Vec<PathBuf>,
+}
+
+/// The sealing flow the commit phase 1 output was generated with. It determines which challenges
+/// the public inputs of the proof are derived from.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+enum PoRepVariant {
+    /// The challenges are derived from the interactive seed.
+    #[default]
+    Interactive,
+    /// The challenges are a seed derived subset of the synthetic challenges.
+    Synthetic,
+    /// The challenges are derived without a seed, over more partitions.
+    NonInteractive,
+}
+
+#[derive(Debug, Deserialize)]
+struct SnarkProofParameters {
+    sector_size: u64,
+    api_version: ApiVersion,
+    #[serde(default)]
+    challenge_domain: ChallengeDomain,
+    #[serde(default)]
+    porep_variant: PoRepVariant,
+    /// Hex encoded.
+    porep_id: String,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    /// File holding the output of commit phase 1, either framed or bincode encoded.
+    commit_phase1_output: PathBuf,
+    /// The file the proof is written to.
+    output: PathBuf,
+    /// Hex encoded seed of the proof randomness, makes the proof reproducible. Requires the
+    /// `deterministic-proofs` feature, the proof is not zero-knowledge.
+    #[serde(default)]
+    rng_seed: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct SnarkProofOutput {
+    proof: PathBuf,
+    proof_len: usize,
+}
+
+fn parse_bytes32(value: &str, name: &str) -> Result<[u8; 32]> {
+    let bytes = hex::decode(value.trim_start_matches("0x"))
+        .with_context(|| format!("{} is not hex encoded", name))?;
+    ensure!(bytes.len() == 32, "{} must be 32 bytes", name);
+
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&bytes);
+    Ok(out)
+}
+
+#[cfg(feature = "deterministic-proofs")]
+fn seed_proof_rng(seed: &str) -> Result<()> {
+    storage_proofs_core::proof_rng::set_proof_seed(Some(parse_bytes32(seed, "rng_seed")?));
+    Ok(())
+}
+
+#[cfg(not(feature = "deterministic-proofs"))]
+fn seed_proof_rng(_seed: &str) -> Result<()> {
+    bail!("rng_seed requires the deterministic-proofs feature")
+}
+
+fn write_vanilla_proof<Tree: MerkleTreeTrait>(
+    output_dir: &Path,
+    proof: &FallbackPoStSectorProof<Tree>,
+) -> Result<PathBuf> {
+    let path = output_dir.join(format!("vanilla-proof-{}", u64::from(proof.sector_id)));
+    fs::write(&path, bincode::serialize(proof)?)
+        .with_context(|| format!("could not write {:?}", path))?;
+
+    Ok(path)
+}
+
+fn convert_window_post<Tree: 'static + MerkleTreeTrait>(
+    params: &ConvertWindowPostParameters,
+) -> Result<ConvertWindowPostOutput> {
+    let post_config = PoStConfig {
+        sector_size: SectorSize(params.sector_size),
+        challenge_count: params.challenge_count,
+        sector_count: params.sector_count,
+        typ: PoStType::Window,
+        priority: false,
+        api_version: params.api_version,
+    };
+    let randomness = parse_bytes32(&params.randomness, "randomness")?;
+
+    let mut sectors = params.sectors.clone();
+    sectors.sort();
+    sectors.dedup();
+
+    let vanilla_proofs = params
+        .vanilla_proofs
+        .iter()
+        .map(|path| {
+            let file = File::open(path).with_context(|| format!("could not open {:?}", path))?;
+            bincode::deserialize_from(BufReader::new(file))
+                .with_context(|| format!("could not decode vanilla proof {:?}", path))
+        })
+        .collect::<Result<Vec<FallbackPoStSectorProof<Tree>>>>()?;
+
+    let mut converted = convert_window_post_vanilla_proofs_with_domain::<Tree>(
+        &post_config,
+        &randomness,
+        params.prover_id,
+        &sectors,
+        &vanilla_proofs,
+        &params.challenge_domain,
+    )?;
+    let mut output = ConvertWindowPostOutput {
+        reused_challenges: converted.reused_challenge_count(),
+        ..Default::default()
+    };
+
+    for (sector_id, challenges) in converted.missing_challenges() {
+        let replica = match params.replicas.get(&sector_id) {
+            Some(replica) => replica,
+            None => {
+                output.missing_challenges.insert(sector_id, challenges);
+                continue;
+            }
+        };
+
+        info!(
+            "proving {} missing challenges of sector {}",
+            challenges.len(),
+            sector_id
+        );
+        let replica = PrivateReplicaInfo::<Tree>::new(
+            replica.replica_path.clone(),
+            parse_bytes32(&replica.comm_r, "comm_r")?,
+            replica.cache_dir.clone(),
+        )?;
+        let proof =
+            generate_single_vanilla_proof::<Tree>(&post_config, sector_id, &replica, &challenges)?;
+        converted.add_proof(&proof)?;
+        output.proven_challenges += challenges.len();
+    }
+
+    if !output.missing_challenges.is_empty() {
+        return Ok(output);
+    }
+
+    fs::create_dir_all(&params.output_dir)
+        .with_context(|| format!("could not create {:?}", params.output_dir))?;
+    for proof in converted.into_vanilla_proofs()? {
+        output
+            .vanilla_proofs
+            .push(write_vanilla_proof(&params.output_dir, &proof)?);
+    }
+
+    Ok(output)
+}
+
+fn post_vanilla_proofs<Tree: 'static + MerkleTreeTrait>(
+    params: &PostVanillaProofsParameters,
+) -> Result<PostVanillaProofsOutput> {
+    let post_config = PoStConfig {
+        sector_size: SectorSize(params.sector_size),
+        challenge_count: params.challenge_count,
+        sector_count: params.sector_count,
+        typ: params.typ.into(),
+        priority: false,
+        api_version: params.api_version,
+    };
+    let randomness = parse_bytes32(&params.randomness, "randomness")?;
+
+    let replicas = params
+        .replicas
+        .iter()
+        .map(|(sector_id, replica)| {
+            let replica = PrivateReplicaInfo::<Tree>::new(
+                replica.replica_path.clone(),
+                parse_bytes32(&replica.comm_r, "comm_r")?,
+                replica.cache_dir.clone(),
+            )?;
+            Ok((*sector_id, replica))
+        })
+        .collect::<Result<BTreeMap<_, _>>>()?;
+    let sectors: Vec<SectorId> = replicas.keys().copied().collect();
+    let challenges = generate_fallback_sector_challenges_with_domain::<Tree>(
+        &post_config,
+        &randomness,
+        &sectors,
+        params.prover_id,
+        &params.challenge_domain,
+    )?;
+
+    fs::create_dir_all(&params.output_dir)
+        .with_context(|| format!("could not create {:?}", params.output_dir))?;
+
+    let max_parallel_sectors = match params.max_parallel_sectors {
+        0 => rayon::current_num_threads(),
+        max_parallel_sectors => max_parallel_sectors,
+    };
+    info!(
+        "proving {} sectors, {} at a time",
+        sectors.len(),
+        max_parallel_sectors
+    );
+    // Proving a sector is parallel itself, so a thread waiting for it may pick up further
+    // sectors. Proving the sectors in chunks bounds how many are read at the same time.
+    let challenges: Vec<_> = challenges.into_iter().collect();
+    let mut vanilla_proofs = Vec::with_capacity(challenges.len());
+    for chunk in challenges.chunks(max_parallel_sectors) {
+        let proofs = chunk
+            .par_iter()
+            .map(|(sector_id, challenges)| {
+                let proof = generate_single_vanilla_proof::<Tree>(
+                    &post_config,
+                    *sector_id,
+                    &replicas[sector_id],
+                    challenges,
+                )
+                .with_context(|| format!("could not prove sector {}", sector_id))?;
+                write_vanilla_proof(&params.output_dir, &proof)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        vanilla_proofs.extend(proofs);
+    }
+
+    Ok(PostVanillaProofsOutput { vanilla_proofs })
+}
+
+fn snark_proof<Tree: 'static + MerkleTreeTrait>(
+    params: &SnarkProofParameters,
+) -> Result<SnarkProofOutput> {
+    let mut porep_config = PoRepConfig::new_groth16(
+        params.sector_size,
+        parse_bytes32(&params.porep_id, "porep_id")?,
+        params.api_version,
+    )
+    .with_challenge_domain(params.challenge_domain);
+    match params.porep_variant {
+        PoRepVariant::Interactive => {}
+        PoRepVariant::Synthetic => porep_config.enable_feature(ApiFeature::SyntheticPoRep),
+        PoRepVariant::NonInteractive => {
+            bail!("non-interactive porep is not supported by this version")
+        }
+    }
+
+    let path = &params.commit_phase1_output;
+    let file = File::open(path).with_context(|| format!("could not open {:?}", path))?;
+    let phase1_output: SealCommitPhase1Output<Tree> =
+        read_seal_commit_phase1_output(BufReader::new(file))
+            .with_context(|| format!("could not decode commit phase 1 output {:?}", path))?;
+
+    let output = seal_commit_phase2::<Tree>(
+        &porep_config,
+        phase1_output,
+        params.prover_id,
+        params.sector_id,
+    )?;
+    fs::write(&params.output, &output.proof)
+        .with_context(|| format!("could not write {:?}", params.output))?;
+
+    Ok(SnarkProofOutput {
+        proof: params.output.clone(),
+        proof_len: output.proof.len(),
+    })
+}
+
+fn read_parameters<T: DeserializeOwned>(matches: &ArgMatches) -> Result<T> {
+    let mut input = String::new();
+    match matches.value_of("input") {
+        Some(path) => {
+            File::open(Path::new(path))
+                .with_context(|| format!("could not open {}", path))?
+                .read_to_string(&mut input)?;
+        }
+        None => {
+            io::stdin().read_to_string(&mut input)?;
+        }
+    }
+
+    serde_json::from_str(&input).context("invalid parameters")
+}
+
+fn run_convert_window_post(matches: &ArgMatches) -> Result<()> {
+    let params: ConvertWindowPostParameters = read_parameters(matches)?;
+    ensure!(
+        PUBLISHED_SECTOR_SIZES.contains(&params.sector_size),
+        "unsupported sector size {}",
+        params.sector_size
+    );
+    ensure!(params.sector_count > 0, "sector_count must be non-zero");
+
+    let output = with_shape!(params.sector_size, convert_window_post, &params,)?;
+    println!("{}", serde_json::to_string_pretty(&output)?);
+
+    ensure!(
+        output.missing_challenges.is_empty(),
+        "{} sectors have challenges without proofs, add their replicas to prove them",
+        output.missing_challenges.len()
+    );
+
+    Ok(())
+}
+
+fn run_post_vanilla_proofs(matches: &ArgMatches) -> Result<()> {
+    let params: PostVanillaProofsParameters = read_parameters(matches)?;
+    ensure!(
+        PUBLISHED_SECTOR_SIZES.contains(&params.sector_size),
+        "unsupported sector size {}",
+        params.sector_size
+    );
+    ensure!(params.sector_count > 0, "sector_count must be non-zero");
+    ensure!(!params.replicas.is_empty(), "no replicas to prove");
+
+    let output = with_shape!(params.sector_size, post_vanilla_proofs, &params,)?;
+    println!("{}", serde_json::to_string_pretty(&output)?);
+
+    Ok(())
+}
+
+fn run_snark_proof(matches: &ArgMatches) -> Result<()> {
+    let params: SnarkProofParameters = read_parameters(matches)?;
+    ensure!(
+        PUBLISHED_SECTOR_SIZES.contains(&params.sector_size),
+        "unsupported sector size {}",
+        params.sector_size
+    );
+    if let Some(rng_seed) = &params.rng_seed {
+        seed_proof_rng(rng_seed)?;
+    }
+
+    let output = with_shape!(params.sector_size, snark_proof, &params,)?;
+    println!("{}", serde_json::to_string_pretty(&output)?);
+
+    Ok(())
+}
+
+fn run_gc_cache(matches: &ArgMatches) -> Result<()> {
+    let root_dir = matches.value_of("root").expect("root is required");
+    let registered_proof = matches
+        .value_of_t::<u64>("registered-proof")
+        .context("--registered-proof must be a registered seal proof id")?;
+    let porep_config = RegisteredSealProof::try_from(registered_proof)?.as_porep_config();
+    let mut policy = GcPolicy {
+        dry_run: !matches.is_present("delete"),
+        ..Default::default()
+    };
+    if let Some(min_age) = matches.value_of("min-age") {
+        let secs = min_age
+            .parse()
+            .with_context(|| format!("invalid min-age {}", min_age))?;
+        policy.min_age = Duration::from_secs(secs);
+    }
+
+    let report = gc_cache(Path::new(root_dir), &porep_config, &policy)?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+
+    Ok(())
+}
+
+fn input_arg() -> Arg<'static> {
+    Arg::new("input")
+        .long("input")
+        .help("The file to read the parameters from, defaults to stdin")
+        .takes_value(true)
+}
+
+fn main() -> Result<()> {
+    fil_logger::init();
+
+    let convert_window_post_cmd = Command::new("convert-window-post")
+        .about(
+            "Regroups window PoSt vanilla proofs for another partition layout. Challenges that \
+             the given proofs don't cover are proven from the replicas, if they are given. The \
+             parameters are read as JSON, the result is written as JSON to stdout.",
+        )
+        .arg(input_arg());
+
+    let post_vanilla_proofs_cmd = Command::new("post-vanilla-proofs")
+        .about(
+            "Generates the window or winning PoSt vanilla proofs of a set of sectors, one file \
+             per sector. `max_parallel_sectors` limits how many sectors are read at the same \
+             time. The parameters are read as JSON, the result is written as JSON to stdout.",
+        )
+        .arg(input_arg());
+
+    let snark_proof_cmd = Command::new("snark-proof")
+        .about(
+            "Generates the seal proof (commit phase 2) from a commit phase 1 output. The \
+             `porep_variant` parameter selects the interactive (default) or synthetic porep \
+             public inputs. The parameters are read as JSON, the result is written as JSON to \
+             stdout.",
+        )
+        .arg(input_arg());
+
+    let gc_cache_cmd = Command::new("gc-cache")
+        .about(
+            "Reports the sealing artifacts in the cache directories within a root directory that \
+             no sector references, e.g. left overs of crashed seals. They are only deleted with \
+             --delete, and only from cache directories with a readable sector manifest or t_aux. \
+             The result is written as JSON to stdout.",
+        )
+        .arg(
+            Arg::new("root")
+                .long("root")
+                .help("The directory containing the cache directories of the sectors")
+                .required(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("registered-proof")
+                .long("registered-proof")
+                .help("The registered seal proof id the sectors were sealed with")
+                .required(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("delete")
+                .long("delete")
+                .help("Delete the orphaned artifacts, rather than only reporting them"),
+        )
+        .arg(
+            Arg::new("min-age")
+                .long("min-age")
+                .help("Only artifacts not modified for this many seconds are collected")
+                .default_value("86400")
+                .takes_value(true),
+        );
+
+    let matches = Command::new("fil-proofs-bin")
+        .version("0.1")
+        .about("Tools operating on the outputs of the proving API")
+        .subcommand(convert_window_post_cmd)
+        .subcommand(gc_cache_cmd)
+        .subcommand(post_vanilla_proofs_cmd)
+        .subcommand(snark_proof_cmd)
+        .get_matches();
+
+    match matches.subcommand() {
+        Some(("convert-window-post", m)) => run_convert_window_post(m),
+        Some(("gc-cache", m)) => run_gc_cache(m),
+        Some(("post-vanilla-proofs", m)) => run_post_vanilla_proofs(m),
+        Some(("snark-proof", m)) => run_snark_proof(m),
+        _ => panic!("Unrecognized subcommand"),
+    }
+}
@@ -1,9 +1,9 @@
-use std::collections::BTreeMap;
-use std::fs::File;
-use std::io::BufWriter;
-use std::path::Path;
+use std::fs::{create_dir_all, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-use anyhow::Result;
+use anyhow::{ensure, Context, Result};
 use clap::{Arg, Command};
 use filecoin_hashers::sha256::Sha256Hasher;
 use filecoin_proofs::{
@@ -12,23 +12,27 @@ use filecoin_proofs::{
 };
 use serde::{Deserialize, Serialize};
 use storage_proofs_core::{api_version::ApiVersion, merkle::MerkleTreeTrait, proof::ProofScheme};
-use storage_proofs_porep::stacked::{LayerChallenges, SetupParams, StackedDrg};
+use storage_proofs_porep::stacked::{
+    read_parent_cache_manifest, write_parent_cache_manifest, LayerChallenges, ParentCacheData,
+    ParentCacheDataMap, SetupParams, StackedDrg, TREE_D_ARITY,
+};
 
 const PARENT_CACHE_JSON_OUTPUT: &str = "./parent_cache.json";
 
-pub type ParentCacheSummaryMap = BTreeMap<String, ParentCacheSummary>;
-
+/// An entry of the `--config` file, which lists the graphs to generate parent caches for.
 #[derive(Debug, Deserialize, Serialize)]
-pub struct ParentCacheSummary {
-    pub sector_size: usize,
-    pub digest: String,
+struct GraphCacheConfig {
+    sector_size: u64,
+    porep_id: [u8; 32],
+    api_version: String,
 }
 
 fn gen_graph_cache<Tree: 'static + MerkleTreeTrait>(
     sector_size: usize,
     porep_id: [u8; 32],
     api_version: ApiVersion,
-    parent_cache_summary_map: &mut ParentCacheSummaryMap,
+    output_dir: Option<&Path>,
+    parent_cache_summary_map: &mut ParentCacheDataMap,
 ) -> Result<()> {
     let nodes = sector_size / 32;
 
@@ -46,14 +50,25 @@ fn gen_graph_cache<Tree: 'static + MerkleTreeTrait>(
         layer_challenges,
         api_version,
         api_features: vec![],
+        tree_d_arity: TREE_D_ARITY,
     };
 
     let pp = StackedDrg::<Tree, Sha256Hasher>::setup(&sp).expect("failed to setup DRG");
-    let parent_cache = pp.graph.parent_cache()?;
+    let parent_cache = match output_dir {
+        Some(dir) => pp.graph.parent_cache_in(dir)?,
+        None => pp.graph.parent_cache()?,
+    };
 
-    let data = ParentCacheSummary {
+    // The digest of an existing cache is only computed if verification is enabled.
+    ensure!(
+        !parent_cache.digest.is_empty(),
+        "the digest of the existing {:?} is unknown, set FIL_PROOFS_VERIFY_CACHE=1 to compute it",
+        parent_cache.path
+    );
+
+    let data = ParentCacheData {
         digest: parent_cache.digest,
-        sector_size: parent_cache.sector_size,
+        sector_size: parent_cache.sector_size as u64,
     };
     parent_cache_summary_map.insert(
         parent_cache
@@ -87,6 +102,24 @@ fn main() -> Result<()> {
                 .help("Generate and/or verify the graph cache files for a single sector size")
                 .default_value("0"),
         )
+        .arg(
+            Arg::new("output-dir")
+                .long("output-dir")
+                .help(
+                    "Generates the graph cache files into this directory, together with a \
+                     manifest of their digests for distribution to sealing workers",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .help(
+                    "A json file with a list of {sector_size, porep_id, api_version} entries to \
+                     generate the graph cache files for, instead of the production ones",
+                )
+                .takes_value(true),
+        )
         .get_matches();
 
     // NOTE: The porep_ids below are tied to the versioned values provided in
@@ -96,7 +129,7 @@ fn main() -> Result<()> {
     //
     // If this value changes, previously existing cache files will no longer be
     // used and new cache files will be generated.
-    let sector_sizes_and_porep_ids: Vec<(u64, [u8; 32], ApiVersion)> = vec![
+    let mut sector_sizes_and_porep_ids: Vec<(u64, [u8; 32], ApiVersion)> = vec![
         (
             SECTOR_SIZE_2_KIB,
             [
@@ -179,11 +212,29 @@ fn main() -> Result<()> {
         ),
     ];
 
+    if let Some(config_path) = matches.value_of("config") {
+        let file = File::open(config_path)
+            .with_context(|| format!("could not open config {}", config_path))?;
+        let config: Vec<GraphCacheConfig> = serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("invalid config {}", config_path))?;
+        sector_sizes_and_porep_ids = config
+            .into_iter()
+            .map(|entry| {
+                let api_version = ApiVersion::from_str(&entry.api_version)?;
+                Ok((entry.sector_size, entry.porep_id, api_version))
+            })
+            .collect::<Result<_>>()?;
+    }
+    let output_dir = matches.value_of("output-dir").map(PathBuf::from);
+    if let Some(dir) = &output_dir {
+        create_dir_all(dir)?;
+    }
+
     let supported_sector_sizes = sector_sizes_and_porep_ids
         .iter()
         .map(|vals| vals.0)
         .collect::<Vec<u64>>();
-    let mut parent_cache_summary_map: ParentCacheSummaryMap = BTreeMap::new();
+    let mut parent_cache_summary_map = ParentCacheDataMap::new();
 
     let size = matches
         .value_of_t::<u64>("size")
@@ -219,10 +270,24 @@ fn main() -> Result<()> {
             sector_size as usize,
             porep_id,
             api_version,
+            output_dir.as_deref(),
             &mut parent_cache_summary_map,
         )?;
     }
 
+    // Add the generated caches to the manifest of the output directory, which is used to verify
+    // them when they are opened for sealing.
+    if let Some(dir) = &output_dir {
+        let mut manifest = read_parent_cache_manifest(dir)?;
+        manifest.extend(
+            parent_cache_summary_map
+                .iter()
+                .map(|(id, data)| (id.clone(), data.clone())),
+        );
+        write_parent_cache_manifest(dir, &manifest)?;
+        println!("Wrote manifest to {:?}", dir);
+    }
+
     // Output all json to PARENT_CACHE_JSON_OUTPUT in the current
     // directory.
     if json {
@@ -0,0 +1,75 @@
+use std::convert::TryFrom;
+
+use anyhow::{ensure, Context, Result};
+use clap::{Arg, ArgMatches, Command};
+use filecoin_proofs::{compute_replica_id, RegisteredSealProof, ReplicaIdInputs};
+use storage_proofs_core::sector::SectorId;
+
+fn parse_bytes32(matches: &ArgMatches, name: &str) -> Result<[u8; 32]> {
+    let value = matches
+        .value_of(name)
+        .with_context(|| format!("--{} is required", name))?;
+    let bytes = hex::decode(value.trim_start_matches("0x"))
+        .with_context(|| format!("--{} is not hex encoded", name))?;
+    ensure!(bytes.len() == 32, "--{} must be 32 bytes", name);
+
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&bytes);
+    Ok(out)
+}
+
+fn hex_arg(name: &'static str, help: &'static str) -> Arg<'static> {
+    Arg::new(name)
+        .long(name)
+        .help(help)
+        .required(true)
+        .takes_value(true)
+}
+
+fn run(matches: &ArgMatches) -> Result<[u8; 32]> {
+    let registered_proof = matches
+        .value_of_t::<u64>("registered-proof")
+        .context("--registered-proof must be a registered seal proof id")?;
+    let registered_proof = RegisteredSealProof::try_from(registered_proof)?;
+    let sector_id = matches
+        .value_of_t::<u64>("sector-id")
+        .context("--sector-id must be a number")?;
+
+    let inputs = ReplicaIdInputs::new(
+        &registered_proof.as_porep_config(),
+        parse_bytes32(matches, "prover-id")?.into(),
+        SectorId::from(sector_id),
+        parse_bytes32(matches, "ticket")?.into(),
+        parse_bytes32(matches, "comm-d")?,
+    );
+    compute_replica_id(&inputs)
+}
+
+fn main() {
+    fil_logger::init();
+
+    let matches = Command::new("replica-id")
+        .version("0.1")
+        .about("Derive the replica id of a sector and print it hex encoded")
+        .arg(
+            Arg::new("registered-proof")
+                .long("registered-proof")
+                .help("The id of the registered seal proof, e.g. 8 for StackedDrg32GiBV1_1")
+                .required(true)
+                .takes_value(true),
+        )
+        .arg(hex_arg("prover-id", "The hex encoded prover id"))
+        .arg(
+            Arg::new("sector-id")
+                .long("sector-id")
+                .help("The sector number")
+                .required(true)
+                .takes_value(true),
+        )
+        .arg(hex_arg("ticket", "The hex encoded ticket"))
+        .arg(hex_arg("comm-d", "The hex encoded comm_d"))
+        .get_matches();
+
+    let replica_id = run(&matches).expect("failed to derive replica id");
+    println!("{}", hex::encode(replica_id));
+}
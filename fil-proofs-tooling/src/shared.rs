@@ -5,8 +5,8 @@ use std::io::{BufWriter, Seek, Write};
 use filecoin_proofs::{
     add_piece, clear_cache, fauxrep_aux, generate_synth_proofs, seal_pre_commit_phase1,
     seal_pre_commit_phase2, validate_cache_for_commit, validate_cache_for_precommit_phase2,
-    MerkleTreeTrait, PaddedBytesAmount, PieceInfo, PoRepConfig, PrivateReplicaInfo,
-    PublicReplicaInfo, SealPreCommitOutput, SealPreCommitPhase1Output, SectorSize,
+    MerkleTreeTrait, PaddedBytesAmount, PieceInfo, PoRepConfig, PrivateReplicaInfo, ProverId,
+    PublicReplicaInfo, SealPreCommitOutput, SealPreCommitPhase1Output, SectorSize, Ticket,
     UnpaddedBytesAmount,
 };
 use log::info;
@@ -25,9 +25,9 @@ use tempfile::{tempdir, NamedTempFile};
 
 use crate::{measure, FuncMeasurement};
 
-pub const PROVER_ID: [u8; 32] = [9; 32];
+pub const PROVER_ID: ProverId = ProverId([9; 32]);
 pub const RANDOMNESS: [u8; 32] = [44; 32];
-pub const TICKET_BYTES: [u8; 32] = [1; 32];
+pub const TICKET_BYTES: Ticket = Ticket([1; 32]);
 
 pub struct PreCommitReplicaOutput<Tree: 'static + MerkleTreeTrait> {
     pub piece_info: Vec<PieceInfo>,
@@ -207,6 +207,8 @@ pub fn create_replicas<Tree: 'static + MerkleTreeTrait>(
                         labels: Labels::new(vec![tmp_store_config.clone(); cache_dirs.len()]),
                         config: tmp_store_config,
                         comm_d: [0; 32],
+                        comm_c: None,
+                        without_tree_d: false,
                     }
                 })
                 .collect::<Vec<_>>();
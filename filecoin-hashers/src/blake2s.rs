@@ -1,5 +1,5 @@
 use std::fmt::{self, Debug, Formatter};
-use std::hash::Hasher as StdHasher;
+use std::hash::{Hash as StdHash, Hasher as StdHasher};
 use std::panic::panic_any;
 
 use anyhow::ensure;
@@ -18,8 +18,11 @@ use merkletree::{
 };
 use rand::RngCore;
 use serde::{Deserialize, Serialize};
+use subtle::{Choice, ConstantTimeEq};
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
 
-use crate::types::{Domain, HashFunction, Hasher};
+use crate::types::{tag_len, Domain, HashFunction, Hasher};
 
 #[derive(Default, Copy, Clone, PartialEq, Eq, Debug)]
 pub struct Blake2sHasher {}
@@ -68,11 +71,34 @@ impl StdHasher for Blake2sFunction {
     }
 }
 
-#[derive(
-    Copy, Clone, PartialEq, Eq, Debug, PartialOrd, Ord, Default, Serialize, Deserialize, Hash,
-)]
+#[derive(Copy, Clone, Eq, Debug, PartialOrd, Ord, Default, Serialize, Deserialize)]
 pub struct Blake2sDomain(pub [u8; 32]);
 
+impl StdHash for Blake2sDomain {
+    fn hash<H: StdHasher>(&self, state: &mut H) {
+        StdHash::hash(&self.0, state);
+    }
+}
+
+impl PartialEq for Blake2sDomain {
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(other).into()
+    }
+}
+
+impl ConstantTimeEq for Blake2sDomain {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.0.ct_eq(&other.0)
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Zeroize for Blake2sDomain {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
 impl AsRef<Blake2sDomain> for Blake2sDomain {
     fn as_ref(&self) -> &Self {
         self
@@ -176,6 +202,17 @@ impl HashFunction<Blake2sDomain> for Blake2sFunction {
             .into()
     }
 
+    fn hash_tagged(tag: &[u8], data: &[u8]) -> Blake2sDomain {
+        Blake2s::new()
+            .hash_length(32)
+            .to_state()
+            .update(&tag_len(tag))
+            .update(tag)
+            .update(data)
+            .finalize()
+            .into()
+    }
+
     fn hash2(a: &Blake2sDomain, b: &Blake2sDomain) -> Blake2sDomain {
         Blake2s::new()
             .hash_length(32)
@@ -0,0 +1,304 @@
+use std::fmt::{self, Debug, Formatter};
+use std::hash::{Hash as StdHash, Hasher as StdHasher};
+use std::panic::panic_any;
+
+use anyhow::ensure;
+use bellperson::{
+    gadgets::{boolean::Boolean, num::AllocatedNum},
+    ConstraintSystem, SynthesisError,
+};
+use blake3::{Hash as Blake3Hash, Hasher as State};
+use blstrs::Scalar as Fr;
+use ff::{Field, PrimeField};
+use merkletree::{
+    hash::{Algorithm, Hashable},
+    merkle::Element,
+};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use subtle::{Choice, ConstantTimeEq};
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
+
+use crate::types::{Domain, HashFunction, Hasher};
+
+#[derive(Default, Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Blake3Hasher {}
+
+impl Hasher for Blake3Hasher {
+    type Domain = Blake3Domain;
+    type Function = Blake3Function;
+
+    fn name() -> String {
+        "Blake3Hasher".into()
+    }
+}
+
+#[derive(Clone)]
+pub struct Blake3Function(State);
+
+impl Default for Blake3Function {
+    fn default() -> Self {
+        Blake3Function(State::new())
+    }
+}
+
+impl PartialEq for Blake3Function {
+    fn eq(&self, other: &Self) -> bool {
+        format!("{:?}", self) == format!("{:?}", other)
+    }
+}
+
+impl Eq for Blake3Function {}
+
+impl Debug for Blake3Function {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Blake3Function({:?})", self.0)
+    }
+}
+
+impl StdHasher for Blake3Function {
+    #[inline]
+    fn write(&mut self, msg: &[u8]) {
+        self.0.update(msg);
+    }
+
+    #[inline]
+    fn finish(&self) -> u64 {
+        unreachable!("unused by Function -- should never be called")
+    }
+}
+
+#[derive(Copy, Clone, Eq, Debug, PartialOrd, Ord, Default, Serialize, Deserialize)]
+pub struct Blake3Domain(pub [u8; 32]);
+
+impl StdHash for Blake3Domain {
+    fn hash<H: StdHasher>(&self, state: &mut H) {
+        StdHash::hash(&self.0, state);
+    }
+}
+
+impl PartialEq for Blake3Domain {
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(other).into()
+    }
+}
+
+impl ConstantTimeEq for Blake3Domain {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.0.ct_eq(&other.0)
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Zeroize for Blake3Domain {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl AsRef<Blake3Domain> for Blake3Domain {
+    fn as_ref(&self) -> &Self {
+        self
+    }
+}
+
+impl Blake3Domain {
+    pub fn trim_to_fr32(&mut self) {
+        // strip last two bits, to ensure result is in Fr.
+        self.0[31] &= 0b0011_1111;
+    }
+}
+
+impl AsRef<[u8]> for Blake3Domain {
+    fn as_ref(&self) -> &[u8] {
+        &self.0[..]
+    }
+}
+
+impl Hashable<Blake3Function> for Blake3Domain {
+    fn hash(&self, state: &mut Blake3Function) {
+        state.write(self.as_ref())
+    }
+}
+
+impl From<Fr> for Blake3Domain {
+    fn from(val: Fr) -> Self {
+        Blake3Domain(val.to_repr())
+    }
+}
+
+impl Element for Blake3Domain {
+    fn byte_len() -> usize {
+        32
+    }
+
+    fn from_slice(bytes: &[u8]) -> Self {
+        match Blake3Domain::try_from_bytes(bytes) {
+            Ok(res) => res,
+            Err(err) => panic_any(err),
+        }
+    }
+
+    fn copy_to_slice(&self, bytes: &mut [u8]) {
+        bytes.copy_from_slice(&self.0);
+    }
+}
+
+impl From<Blake3Domain> for Fr {
+    fn from(val: Blake3Domain) -> Self {
+        Fr::from_repr_vartime(val.0).expect("from_repr failure")
+    }
+}
+
+impl Domain for Blake3Domain {
+    fn into_bytes(&self) -> Vec<u8> {
+        self.0.to_vec()
+    }
+
+    fn try_from_bytes(raw: &[u8]) -> anyhow::Result<Self> {
+        ensure!(
+            raw.len() == 32 && u32::from(raw[31]) <= Fr::NUM_BITS,
+            "invalid amount of bytes"
+        );
+
+        let mut res = Blake3Domain::default();
+        res.0.copy_from_slice(&raw[0..32]);
+        Ok(res)
+    }
+
+    fn write_bytes(&self, dest: &mut [u8]) -> anyhow::Result<()> {
+        ensure!(dest.len() >= 32, "too many bytes");
+        dest[0..32].copy_from_slice(&self.0[..]);
+        Ok(())
+    }
+
+    fn random<R: RngCore>(rng: &mut R) -> Self {
+        // generating an Fr and converting it, to ensure we stay in the field
+        Fr::random(rng).into()
+    }
+}
+
+#[allow(clippy::from_over_into)]
+impl Into<Blake3Domain> for Blake3Hash {
+    fn into(self) -> Blake3Domain {
+        let mut res = Blake3Domain::default();
+        res.0[..].copy_from_slice(self.as_bytes());
+        res.trim_to_fr32();
+
+        res
+    }
+}
+
+impl HashFunction<Blake3Domain> for Blake3Function {
+    fn hash(data: &[u8]) -> Blake3Domain {
+        blake3::hash(data).into()
+    }
+
+    fn hash2(a: &Blake3Domain, b: &Blake3Domain) -> Blake3Domain {
+        State::new()
+            .update(a.as_ref())
+            .update(b.as_ref())
+            .finalize()
+            .into()
+    }
+
+    // There is no blake3 gadget, circuits using blake3 can't be satisfied.
+
+    fn hash_multi_leaf_circuit<Arity, CS: ConstraintSystem<Fr>>(
+        _cs: CS,
+        _leaves: &[AllocatedNum<Fr>],
+        _height: usize,
+    ) -> Result<AllocatedNum<Fr>, SynthesisError> {
+        Err(SynthesisError::Unsatisfiable)
+    }
+
+    fn hash_leaf_bits_circuit<CS: ConstraintSystem<Fr>>(
+        _cs: CS,
+        _left: &[Boolean],
+        _right: &[Boolean],
+        _height: usize,
+    ) -> Result<AllocatedNum<Fr>, SynthesisError> {
+        Err(SynthesisError::Unsatisfiable)
+    }
+
+    fn hash_circuit<CS: ConstraintSystem<Fr>>(
+        _cs: CS,
+        _bits: &[Boolean],
+    ) -> Result<AllocatedNum<Fr>, SynthesisError> {
+        Err(SynthesisError::Unsatisfiable)
+    }
+
+    fn hash2_circuit<CS>(
+        _cs: CS,
+        _a: &AllocatedNum<Fr>,
+        _b: &AllocatedNum<Fr>,
+    ) -> Result<AllocatedNum<Fr>, SynthesisError>
+    where
+        CS: ConstraintSystem<Fr>,
+    {
+        Err(SynthesisError::Unsatisfiable)
+    }
+}
+
+impl Algorithm<Blake3Domain> for Blake3Function {
+    #[inline]
+    fn hash(&mut self) -> Blake3Domain {
+        self.0.finalize().into()
+    }
+
+    #[inline]
+    fn reset(&mut self) {
+        self.0.reset();
+    }
+
+    fn leaf(&mut self, leaf: Blake3Domain) -> Blake3Domain {
+        leaf
+    }
+
+    fn node(&mut self, left: Blake3Domain, right: Blake3Domain, _height: usize) -> Blake3Domain {
+        left.hash(self);
+        right.hash(self);
+        self.hash()
+    }
+
+    fn multi_node(&mut self, parts: &[Blake3Domain], _height: usize) -> Blake3Domain {
+        for part in parts {
+            part.hash(self)
+        }
+        self.hash()
+    }
+}
+
+impl From<[u8; 32]> for Blake3Domain {
+    #[inline]
+    fn from(val: [u8; 32]) -> Self {
+        Blake3Domain(val)
+    }
+}
+
+impl From<Blake3Domain> for [u8; 32] {
+    #[inline]
+    fn from(val: Blake3Domain) -> Self {
+        val.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bellperson::util_cs::test_cs::TestConstraintSystem;
+
+    #[test]
+    fn test_blake3_circuit_is_unsatisfiable() {
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let a = AllocatedNum::alloc(cs.namespace(|| "a"), || Ok(Fr::ONE)).expect("alloc failed");
+        let b = AllocatedNum::alloc(cs.namespace(|| "b"), || Ok(Fr::ONE)).expect("alloc failed");
+
+        assert!(matches!(
+            Blake3Function::hash2_circuit(cs.namespace(|| "hash2"), &a, &b),
+            Err(SynthesisError::Unsatisfiable)
+        ));
+    }
+}
@@ -6,9 +6,13 @@
 
 #[cfg(feature = "blake2s")]
 pub mod blake2s;
+#[cfg(feature = "blake3")]
+pub mod blake3;
 #[cfg(feature = "poseidon")]
 pub mod poseidon;
 #[cfg(feature = "poseidon")]
+pub mod poseidon_sponge;
+#[cfg(feature = "poseidon")]
 mod poseidon_types;
 #[cfg(feature = "sha256")]
 pub mod sha256;
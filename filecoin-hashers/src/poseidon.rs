@@ -9,7 +9,7 @@ use bellperson::{
 };
 use blstrs::Scalar as Fr;
 use ff::{Field, PrimeField};
-use generic_array::typenum::{marker_traits::Unsigned, U2};
+use generic_array::typenum::{marker_traits::Unsigned, U11, U16, U2, U24, U36, U4, U8};
 use merkletree::{
     hash::{Algorithm as LightAlgorithm, Hashable},
     merkle::Element,
@@ -17,10 +17,13 @@ use merkletree::{
 use neptune::{circuit::poseidon_hash, poseidon::Poseidon};
 use rand::RngCore;
 use serde::{Deserialize, Serialize};
+use subtle::{Choice, ConstantTimeEq};
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
 
 use crate::types::{
-    Domain, HashFunction, Hasher, PoseidonArity, PoseidonMDArity, POSEIDON_CONSTANTS_16,
-    POSEIDON_CONSTANTS_2, POSEIDON_CONSTANTS_4, POSEIDON_CONSTANTS_8, POSEIDON_MD_CONSTANTS,
+    Domain, HashFunction, Hasher, PoseidonArity, PoseidonMDArity, POSEIDON_CONSTANTS_2,
+    POSEIDON_MD_CONSTANTS,
 };
 
 #[derive(Default, Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -73,7 +76,20 @@ impl StdHash for PoseidonDomain {
 
 impl PartialEq for PoseidonDomain {
     fn eq(&self, other: &Self) -> bool {
-        self.0 == other.0
+        self.ct_eq(other).into()
+    }
+}
+
+impl ConstantTimeEq for PoseidonDomain {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.0.ct_eq(&other.0)
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Zeroize for PoseidonDomain {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
     }
 }
 
@@ -173,31 +189,121 @@ fn shared_hash(data: &[u8]) -> PoseidonDomain {
 }
 
 fn shared_hash_frs(preimage: &[Fr]) -> Fr {
-    match preimage.len() {
-        2 => {
-            let mut p = Poseidon::new_with_preimage(preimage, &POSEIDON_CONSTANTS_2);
-            p.hash()
-        }
-        4 => {
-            let mut p = Poseidon::new_with_preimage(preimage, &POSEIDON_CONSTANTS_4);
-            p.hash()
-        }
-        8 => {
-            let mut p = Poseidon::new_with_preimage(preimage, &POSEIDON_CONSTANTS_8);
-            p.hash()
-        }
-        16 => {
-            let mut p = Poseidon::new_with_preimage(preimage, &POSEIDON_CONSTANTS_16);
-            p.hash()
-        }
-
-        _ => panic_any(format!(
+    hash_frs_dyn(preimage).unwrap_or_else(|| {
+        panic_any(format!(
             "Unsupported arity for Poseidon hasher: {}",
             preimage.len()
-        )),
+        ))
+    })
+}
+
+/// Arities for which Poseidon constants are instantiated, and thus can be selected at runtime.
+pub const SUPPORTED_POSEIDON_ARITIES: [usize; 7] = [2, 4, 8, 11, 16, 24, 36];
+
+fn hash_frs_with<A: PoseidonArity>(preimage: &[Fr]) -> Fr {
+    let mut p = Poseidon::new_with_preimage(preimage, A::PARAMETERS());
+    p.hash()
+}
+
+/// Hashes `preimage` with the Poseidon constants of arity `preimage.len()`, returns `None` if
+/// there are no constants for that arity.
+fn hash_frs_dyn(preimage: &[Fr]) -> Option<Fr> {
+    let hash = match preimage.len() {
+        2 => hash_frs_with::<U2>(preimage),
+        4 => hash_frs_with::<U4>(preimage),
+        8 => hash_frs_with::<U8>(preimage),
+        11 => hash_frs_with::<U11>(preimage),
+        16 => hash_frs_with::<U16>(preimage),
+        24 => hash_frs_with::<U24>(preimage),
+        36 => hash_frs_with::<U36>(preimage),
+        _ => return None,
+    };
+    Some(hash)
+}
+
+/// Poseidon hash function with an arity chosen at runtime.
+///
+/// The constants are looked up by arity among the instantiated ones (see
+/// `SUPPORTED_POSEIDON_ARITIES`), this allows experimenting with tree shapes without threading
+/// a compile-time arity through the whole stack. The results are identical to the compile-time
+/// variants of the same arity.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PoseidonDynFunction {
+    arity: usize,
+}
+
+impl PoseidonDynFunction {
+    pub fn new(arity: usize) -> anyhow::Result<Self> {
+        ensure!(
+            SUPPORTED_POSEIDON_ARITIES.contains(&arity),
+            "unsupported Poseidon arity {} (supported: {:?})",
+            arity,
+            SUPPORTED_POSEIDON_ARITIES
+        );
+        Ok(PoseidonDynFunction { arity })
+    }
+
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    pub fn hash_frs(&self, preimage: &[Fr]) -> anyhow::Result<Fr> {
+        ensure!(
+            preimage.len() == self.arity,
+            "invalid preimage length {} for Poseidon arity {}",
+            preimage.len(),
+            self.arity
+        );
+        Ok(hash_frs_dyn(preimage).expect("arity checked on construction"))
+    }
+
+    pub fn hash(&self, preimage: &[PoseidonDomain]) -> anyhow::Result<PoseidonDomain> {
+        let preimage = preimage
+            .iter()
+            .map(|domain| (*domain).into())
+            .collect::<Vec<Fr>>();
+        self.hash_frs(&preimage).map(Into::into)
+    }
+
+    pub fn hash_circuit<CS: ConstraintSystem<Fr>>(
+        &self,
+        cs: CS,
+        preimage: &[AllocatedNum<Fr>],
+    ) -> Result<AllocatedNum<Fr>, SynthesisError> {
+        assert_eq!(
+            preimage.len(),
+            self.arity,
+            "invalid preimage length for Poseidon arity"
+        );
+        let preimage = preimage.to_vec();
+        match self.arity {
+            2 => poseidon_hash::<CS, Fr, U2>(cs, preimage, U2::PARAMETERS()),
+            4 => poseidon_hash::<CS, Fr, U4>(cs, preimage, U4::PARAMETERS()),
+            8 => poseidon_hash::<CS, Fr, U8>(cs, preimage, U8::PARAMETERS()),
+            11 => poseidon_hash::<CS, Fr, U11>(cs, preimage, U11::PARAMETERS()),
+            16 => poseidon_hash::<CS, Fr, U16>(cs, preimage, U16::PARAMETERS()),
+            24 => poseidon_hash::<CS, Fr, U24>(cs, preimage, U24::PARAMETERS()),
+            36 => poseidon_hash::<CS, Fr, U36>(cs, preimage, U36::PARAMETERS()),
+            _ => unreachable!("arity checked on construction"),
+        }
     }
 }
 
+// Packs `bytes` into field elements of 31 bytes each, preceded by the number of bytes. There are
+// at least two elements, as needed by `hash_md`.
+fn pack(bytes: &[u8]) -> Vec<PoseidonDomain> {
+    let mut elements = vec![PoseidonDomain::from(Fr::from(bytes.len() as u64))];
+    elements.extend(bytes.chunks(31).map(|chunk| {
+        let mut repr = <Fr as PrimeField>::Repr::default();
+        repr[..chunk.len()].copy_from_slice(chunk);
+        PoseidonDomain(repr)
+    }));
+    if elements.len() == 1 {
+        elements.push(PoseidonDomain::default());
+    }
+    elements
+}
+
 impl HashFunction<PoseidonDomain> for PoseidonFunction {
     fn hash(data: &[u8]) -> PoseidonDomain {
         shared_hash(data)
@@ -234,6 +340,14 @@ impl HashFunction<PoseidonDomain> for PoseidonFunction {
             .into()
     }
 
+    /// Field elements can't simply be prefixed by bytes, instead the tag and `data` are each
+    /// packed into field elements (31 bytes each, so that any bytes are canonical), preceded by
+    /// their length, and compressed into a single element with `hash_md`. The two are then
+    /// hashed together.
+    fn hash_tagged(tag: &[u8], data: &[u8]) -> PoseidonDomain {
+        Self::hash2(&Self::hash_md(&pack(tag)), &Self::hash_md(&pack(data)))
+    }
+
     fn hash_leaf_circuit<CS: ConstraintSystem<Fr>>(
         cs: CS,
         left: &AllocatedNum<Fr>,
@@ -519,6 +633,29 @@ mod tests {
         assert_eq!(val, val_back);
     }
 
+    #[test]
+    fn test_hash_tagged() {
+        let a = PoseidonFunction::hash_tagged(b"Filecoin_A", b"data");
+        assert_eq!(a, PoseidonFunction::hash_tagged(b"Filecoin_A", b"data"));
+        assert_ne!(a, PoseidonFunction::hash_tagged(b"Filecoin_B", b"data"));
+        // Moving bytes between the tag and the data must change the hash.
+        assert_ne!(a, PoseidonFunction::hash_tagged(b"Filecoin_", b"Adata"));
+        assert_ne!(a, PoseidonFunction::hash_tagged(b"Filecoin_Ad", b"ata"));
+
+        // Any data is accepted, including data that is no field element.
+        let empty = PoseidonFunction::hash_tagged(b"Filecoin_A", &[]);
+        assert_ne!(
+            empty,
+            PoseidonFunction::hash_tagged(b"Filecoin_A", &[0; 32])
+        );
+        assert_ne!(
+            empty,
+            PoseidonFunction::hash_tagged(b"Filecoin_A", &[0; 31])
+        );
+        let _ = PoseidonFunction::hash_tagged(b"Filecoin_A", &[0xff; 32]);
+        let _ = PoseidonFunction::hash_tagged(b"Filecoin_A", &[1; 100]);
+    }
+
     #[test]
     fn test_hash_md() {
         // let arity = PoseidonMDArity::to_usize();
@@ -567,4 +704,89 @@ mod tests {
             circuit_hashed.get_value().expect("get_value failure")
         );
     }
+
+    #[test]
+    fn test_poseidon_dyn_function() {
+        assert!(PoseidonDynFunction::new(3).is_err());
+
+        let leaves = (0..8u64)
+            .map(|i| PoseidonDomain::from(Fr::from(i)))
+            .collect::<Vec<_>>();
+
+        let binary = PoseidonDynFunction::new(2).expect("new failure");
+        assert_eq!(
+            binary.hash(&leaves[..2]).expect("hash failure"),
+            PoseidonFunction::hash2(&leaves[0], &leaves[1])
+        );
+        assert!(binary.hash(&leaves[..4]).is_err());
+
+        let oct = PoseidonDynFunction::new(8).expect("new failure");
+        let mut a = PoseidonFunction::default();
+        assert_eq!(
+            oct.hash(&leaves).expect("hash failure"),
+            a.multi_node(&leaves, 0)
+        );
+
+        let frs = (0..11u64).map(Fr::from).collect::<Vec<_>>();
+        let eleven = PoseidonDynFunction::new(11).expect("new failure");
+        let hashed = eleven.hash_frs(&frs).expect("hash failure");
+
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let circuit_frs = frs
+            .iter()
+            .enumerate()
+            .map(|(i, fr)| {
+                AllocatedNum::alloc(cs.namespace(|| format!("input {}", i)), || Ok(*fr))
+                    .expect("alloc failure")
+            })
+            .collect::<Vec<_>>();
+        let circuit_hashed = eleven
+            .hash_circuit(cs.namespace(|| "hash"), &circuit_frs)
+            .expect("hash_circuit failure");
+
+        assert!(cs.is_satisfied());
+        assert_eq!(
+            hashed,
+            circuit_hashed.get_value().expect("get_value failure")
+        );
+    }
+
+    #[test]
+    fn test_canonical_bytes() {
+        let val = PoseidonDomain::from(Fr::from(42u64));
+        assert_eq!(
+            val.to_canonical_bytes().expect("canonical bytes failure"),
+            val.0
+        );
+        assert_eq!(
+            val.try_into_fr().expect("try_into_fr failure"),
+            Fr::from(42u64)
+        );
+
+        // The modulus itself is not a canonical encoding.
+        let modulus = PoseidonDomain((Fr::ZERO - Fr::ONE).to_repr());
+        let mut bytes = modulus.0;
+        bytes[0] += 1;
+        let non_canonical = PoseidonDomain(bytes);
+        assert!(non_canonical.to_canonical_bytes().is_err());
+        assert!(non_canonical.try_into_fr().is_err());
+    }
+
+    #[test]
+    fn test_hash_tagged() {
+        let data = [
+            PoseidonDomain(Fr::ONE.to_repr()),
+            PoseidonDomain(Fr::ZERO.to_repr()),
+        ]
+        .iter()
+        .flat_map(|el| el.0)
+        .collect::<Vec<_>>();
+
+        let a = PoseidonFunction::hash_tagged(b"Filecoin_A", &data);
+        assert_eq!(a, PoseidonFunction::hash_tagged(b"Filecoin_A", &data));
+        assert_ne!(a, PoseidonFunction::hash_tagged(b"Filecoin_B", &data));
+        assert_ne!(a, PoseidonFunction::hash_tagged(b"", &data));
+        assert_ne!(a, PoseidonFunction::hash_tagged(b"Filecoin_A", &data[..32]));
+        assert_ne!(a, PoseidonFunction::hash(&data));
+    }
 }
@@ -0,0 +1,164 @@
+//! Variable-length hashing of field elements with the Poseidon sponge (SAFE API).
+//!
+//! Unlike the fixed arity hashes in `poseidon`, the sponge absorbs any number of elements and can
+//! squeeze any number of outputs. The whole sequence of absorb and squeeze calls has to be
+//! declared upfront as an `IOPattern`, it is part of the domain separation.
+
+use anyhow::{ensure, Result};
+use bellperson::{gadgets::num::AllocatedNum, ConstraintSystem, SynthesisError};
+use blstrs::Scalar as Fr;
+use generic_array::typenum::U2;
+use lazy_static::lazy_static;
+use neptune::{
+    circuit2::Elt,
+    poseidon::PoseidonConstants,
+    sponge::{
+        api::SpongeAPI,
+        circuit::SpongeCircuit,
+        vanilla::{Mode, Sponge, SpongeTrait},
+    },
+    Strength,
+};
+
+pub use neptune::sponge::api::{IOPattern, SpongeOp};
+
+/// Rate of the sponge, i.e. the number of elements absorbed per permutation.
+pub type PoseidonSpongeArity = U2;
+
+lazy_static! {
+    pub static ref POSEIDON_SPONGE_CONSTANTS: PoseidonConstants<Fr, PoseidonSpongeArity> =
+        Sponge::<Fr, PoseidonSpongeArity>::api_constants(Strength::Standard);
+}
+
+/// A Poseidon sponge following a fixed `IOPattern`.
+pub struct PoseidonSponge {
+    sponge: Sponge<'static, Fr, PoseidonSpongeArity>,
+}
+
+impl PoseidonSponge {
+    /// Starts a sponge for the given IO pattern, `domain_separator` allows distinguishing
+    /// different uses of the same pattern.
+    pub fn new(io_pattern: IOPattern, domain_separator: Option<u32>) -> Self {
+        let mut sponge = Sponge::new_with_constants(&POSEIDON_SPONGE_CONSTANTS, Mode::Simplex);
+        sponge.start(io_pattern, domain_separator, &mut ());
+        PoseidonSponge { sponge }
+    }
+
+    pub fn absorb(&mut self, elements: &[Fr]) {
+        SpongeAPI::absorb(&mut self.sponge, elements.len() as u32, elements, &mut ());
+    }
+
+    pub fn squeeze(&mut self, length: usize) -> Vec<Fr> {
+        SpongeAPI::squeeze(&mut self.sponge, length as u32, &mut ())
+    }
+
+    /// Ends the sponge, fails if the calls didn't follow the declared IO pattern.
+    pub fn finish(mut self) -> Result<()> {
+        self.sponge
+            .finish(&mut ())
+            .map_err(|err| anyhow::anyhow!("sponge IO pattern mismatch: {:?}", err))
+    }
+}
+
+fn hash_pattern(input_len: usize, output_len: usize) -> IOPattern {
+    IOPattern(vec![
+        SpongeOp::Absorb(input_len as u32),
+        SpongeOp::Squeeze(output_len as u32),
+    ])
+}
+
+/// Hashes `elements` into `output_len` field elements.
+pub fn sponge_hash(elements: &[Fr], output_len: usize) -> Result<Vec<Fr>> {
+    ensure!(output_len > 0, "sponge output must not be empty");
+
+    let mut sponge = PoseidonSponge::new(hash_pattern(elements.len(), output_len), None);
+    sponge.absorb(elements);
+    let output = sponge.squeeze(output_len);
+    sponge.finish()?;
+
+    Ok(output)
+}
+
+/// Circuit equivalent of `sponge_hash`.
+pub fn sponge_hash_circuit<CS: ConstraintSystem<Fr>>(
+    cs: &mut CS,
+    elements: &[AllocatedNum<Fr>],
+    output_len: usize,
+) -> Result<Vec<AllocatedNum<Fr>>, SynthesisError> {
+    let mut sponge = SpongeCircuit::new_with_constants(&POSEIDON_SPONGE_CONSTANTS, Mode::Simplex);
+    let elements = elements
+        .iter()
+        .cloned()
+        .map(Elt::Allocated)
+        .collect::<Vec<_>>();
+
+    let output = {
+        let mut ns = cs.namespace(|| "sponge");
+        let acc = &mut ns;
+
+        sponge.start(hash_pattern(elements.len(), output_len), None, acc);
+        SpongeAPI::absorb(&mut sponge, elements.len() as u32, &elements, acc);
+        let output = SpongeAPI::squeeze(&mut sponge, output_len as u32, acc);
+        sponge
+            .finish(acc)
+            .map_err(|_| SynthesisError::Unsatisfiable)?;
+        output
+    };
+
+    output
+        .iter()
+        .enumerate()
+        .map(|(i, elt)| {
+            Elt::ensure_allocated(elt, &mut cs.namespace(|| format!("output {}", i)), true)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bellperson::util_cs::test_cs::TestConstraintSystem;
+
+    #[test]
+    fn test_sponge_hash() {
+        let elements = (0..7u64).map(Fr::from).collect::<Vec<_>>();
+
+        let hashed = sponge_hash(&elements, 2).expect("sponge_hash failure");
+        assert_eq!(hashed.len(), 2);
+        assert_eq!(
+            hashed,
+            sponge_hash(&elements, 2).expect("sponge_hash failure")
+        );
+        assert_ne!(
+            hashed[0],
+            sponge_hash(&elements[..6], 2).expect("sponge_hash failure")[0]
+        );
+
+        // Not following the declared pattern is an error.
+        let mut sponge = PoseidonSponge::new(hash_pattern(7, 1), None);
+        sponge.absorb(&elements[..6]);
+        assert!(sponge.finish().is_err());
+
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let circuit_elements = elements
+            .iter()
+            .enumerate()
+            .map(|(i, fr)| {
+                AllocatedNum::alloc(cs.namespace(|| format!("input {}", i)), || Ok(*fr))
+                    .expect("alloc failure")
+            })
+            .collect::<Vec<_>>();
+        let circuit_hashed =
+            sponge_hash_circuit(&mut cs, &circuit_elements, 2).expect("circuit failure");
+
+        assert!(cs.is_satisfied());
+        assert_eq!(
+            circuit_hashed
+                .iter()
+                .map(|num| num.get_value().expect("get_value failure"))
+                .collect::<Vec<_>>(),
+            hashed
+        );
+    }
+}
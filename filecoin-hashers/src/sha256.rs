@@ -1,5 +1,5 @@
 use std::fmt::{self, Debug, Formatter};
-use std::hash::Hasher as StdHasher;
+use std::hash::{Hash as StdHash, Hasher as StdHasher};
 use std::panic::panic_any;
 
 use anyhow::ensure;
@@ -16,8 +16,12 @@ use merkletree::{
 use rand::RngCore;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use sha2raw::Sha256 as Sha256Raw;
+use subtle::{Choice, ConstantTimeEq};
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
 
-use crate::types::{Domain, HashFunction, Hasher};
+use crate::types::{tag_len, Domain, HashFunction, Hasher};
 
 #[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Sha256Hasher {}
@@ -46,9 +50,34 @@ impl StdHasher for Sha256Function {
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize, Hash)]
+#[derive(Copy, Clone, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
 pub struct Sha256Domain(pub [u8; 32]);
 
+impl StdHash for Sha256Domain {
+    fn hash<H: StdHasher>(&self, state: &mut H) {
+        StdHash::hash(&self.0, state);
+    }
+}
+
+impl PartialEq for Sha256Domain {
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(other).into()
+    }
+}
+
+impl ConstantTimeEq for Sha256Domain {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.0.ct_eq(&other.0)
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Zeroize for Sha256Domain {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
 impl Debug for Sha256Domain {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(f, "Sha256Domain({})", hex::encode(self.0))
@@ -150,6 +179,34 @@ impl HashFunction<Sha256Domain> for Sha256Function {
         res
     }
 
+    fn hash_tagged(tag: &[u8], data: &[u8]) -> Sha256Domain {
+        let hashed = Sha256::new()
+            .chain_update(tag_len(tag))
+            .chain_update(tag)
+            .chain_update(data)
+            .finalize();
+        let mut res = Sha256Domain::default();
+        res.0.copy_from_slice(&hashed[..]);
+        res.trim_to_fr32();
+        res
+    }
+
+    /// Hashes several parents at once with the multi-buffer hasher, see
+    /// `sha2raw::Sha256::digest_many`.
+    fn hash_nodes_batch(nodes: &[Sha256Domain], arity: usize, _height: usize) -> Vec<Sha256Domain> {
+        assert_eq!(nodes.len() % arity, 0, "nodes must be a multiple of arity");
+        let blocks = nodes.iter().map(|node| &node.0[..]).collect::<Vec<_>>();
+        let messages = blocks.chunks(arity).collect::<Vec<_>>();
+        Sha256Raw::digest_many(&messages)
+            .into_iter()
+            .map(|hashed| {
+                let mut res = Sha256Domain(hashed);
+                res.trim_to_fr32();
+                res
+            })
+            .collect()
+    }
+
     fn hash2(a: &Sha256Domain, b: &Sha256Domain) -> Sha256Domain {
         let hashed = Sha256::new().chain_update(a).chain_update(b).finalize();
         let mut res = Sha256Domain::default();
@@ -317,3 +374,62 @@ impl From<Sha256Domain> for [u8; 32] {
         val.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+
+    #[test]
+    fn test_hash_tagged() {
+        let a = Sha256Function::hash_tagged(b"Filecoin_A", b"data");
+        assert_eq!(a, Sha256Function::hash_tagged(b"Filecoin_A", b"data"));
+        assert_ne!(a, Sha256Function::hash_tagged(b"Filecoin_B", b"data"));
+        // Moving bytes between the tag and the data must change the hash.
+        assert_ne!(a, Sha256Function::hash_tagged(b"Filecoin_", b"Adata"));
+        assert_ne!(a, Sha256Function::hash_tagged(b"Filecoin_Ad", b"ata"));
+        assert_ne!(a, Sha256Function::hash(b"Filecoin_Adata"));
+    }
+
+    #[test]
+    fn test_hash_batch() {
+        let mut rng = XorShiftRng::from_seed([
+            0x59, 0x62, 0xbe, 0x5d, 0x76, 0x3d, 0x31, 0x8d, 0x17, 0xdb, 0x37, 0x32, 0x54, 0x06,
+            0xbc, 0xe5,
+        ]);
+        let nodes = (0..24)
+            .map(|_| Sha256Domain::random(&mut rng))
+            .collect::<Vec<_>>();
+
+        for arity in &[1, 2, 3, 4, 8] {
+            let nodes = &nodes[..nodes.len() / arity * arity];
+            let expected = nodes
+                .chunks(*arity)
+                .map(|children| Sha256Function::default().multi_node(children, 0))
+                .collect::<Vec<_>>();
+            assert_eq!(Sha256Function::hash_nodes_batch(nodes, *arity, 0), expected);
+        }
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        let a = Sha256Domain([1u8; 32]);
+        let mut b = a;
+        assert!(bool::from(a.ct_eq(&b)));
+        assert_eq!(a, b);
+
+        b.0[31] ^= 1;
+        assert!(!bool::from(a.ct_eq(&b)));
+        assert_ne!(a, b);
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn test_zeroize() {
+        let mut domain = Sha256Domain([0xff; 32]);
+        domain.zeroize();
+        assert_eq!(domain, Sha256Domain::default());
+    }
+}
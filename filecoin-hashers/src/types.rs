@@ -4,6 +4,7 @@ use std::hash::Hash as StdHash;
 #[cfg(feature = "poseidon")]
 pub use crate::poseidon_types::*;
 
+use anyhow::ensure;
 use bellperson::{
     gadgets::{boolean::Boolean, num::AllocatedNum},
     ConstraintSystem, SynthesisError,
@@ -42,11 +43,52 @@ pub trait Domain:
     fn write_bytes(&self, _: &mut [u8]) -> anyhow::Result<()>;
 
     fn random<R: RngCore>(rng: &mut R) -> Self;
+
+    /// Returns the bytes of this element, failing if they are not the canonical (reduced)
+    /// little-endian encoding of a field element.
+    fn to_canonical_bytes(&self) -> anyhow::Result<<Fr as PrimeField>::Repr> {
+        let mut repr = <Fr as PrimeField>::Repr::default();
+        ensure!(
+            self.as_ref().len() == repr.len(),
+            "invalid domain element length"
+        );
+        repr.copy_from_slice(self.as_ref());
+        ensure!(
+            Fr::from_repr_vartime(repr).is_some(),
+            "non-canonical field element"
+        );
+        Ok(repr)
+    }
+
+    /// Checked conversion into a field element, unlike `Into<Fr>` this fails instead of panicking
+    /// on non-canonical values.
+    fn try_into_fr(&self) -> anyhow::Result<Fr> {
+        let repr = self.to_canonical_bytes()?;
+        Ok(Fr::from_repr_vartime(repr).expect("checked above"))
+    }
+}
+
+const TAG_LEN_BYTES: usize = 8;
+
+/// The length prefix of a tag in `HashFunction::hash_tagged`.
+pub(crate) fn tag_len(tag: &[u8]) -> [u8; TAG_LEN_BYTES] {
+    (tag.len() as u64).to_le_bytes()
 }
 
 pub trait HashFunction<T: Domain>: Clone + Debug + Send + Sync + LightAlgorithm<T> {
     fn hash(data: &[u8]) -> T;
     fn hash2(a: &T, b: &T) -> T;
+    /// Domain separated hashing of `data` under `tag`, so that hashes for different purposes
+    /// can never collide. Defaults to `hash(len(tag) || tag || data)`, the length of the tag is
+    /// a `u64` in little endian, so that no tag can be a prefix of another tag and its data.
+    fn hash_tagged(tag: &[u8], data: &[u8]) -> T {
+        let mut preimage = Vec::with_capacity(TAG_LEN_BYTES + tag.len() + data.len());
+        preimage.extend_from_slice(&tag_len(tag));
+        preimage.extend_from_slice(tag);
+        preimage.extend_from_slice(data);
+        Self::hash(&preimage)
+    }
+
     fn hash_md(input: &[T]) -> T {
         // Default to binary.
         assert!(input.len() > 1, "hash_md needs more than one element.");
@@ -63,6 +105,20 @@ pub trait HashFunction<T: Domain>: Clone + Debug + Send + Sync + LightAlgorithm<
         a.leaf(item_hash)
     }
 
+    /// Hashes each consecutive group of `arity` nodes into its parent on `height`, exactly as
+    /// `multi_node` does. `nodes.len()` must be a multiple of `arity`.
+    fn hash_nodes_batch(nodes: &[T], arity: usize, height: usize) -> Vec<T> {
+        assert_eq!(nodes.len() % arity, 0, "nodes must be a multiple of arity");
+        let mut a = Self::default();
+        nodes
+            .chunks(arity)
+            .map(|children| {
+                a.reset();
+                a.multi_node(children, height)
+            })
+            .collect()
+    }
+
     fn hash_single_node(data: &dyn LightHashable<Self>) -> T {
         let mut a = Self::default();
         data.hash(&mut a);
@@ -4,7 +4,7 @@ use std::time::Duration;
 use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
 use filecoin_proofs::{
     caches::{get_stacked_srs_key, get_stacked_srs_verifier_key},
-    get_seal_inputs, PoRepConfig, SectorShape2KiB, SectorShape32GiB, SECTOR_SIZE_2_KIB,
+    get_seal_inputs, PoRepConfig, ProverId, SectorShape2KiB, SectorShape32GiB, SECTOR_SIZE_2_KIB,
     SECTOR_SIZE_32_GIB,
 };
 use rand::{thread_rng, Rng};
@@ -32,7 +32,7 @@ fn bench_seal_inputs(c: &mut Criterion) {
 
     let comm_r = [5u8; 32];
     let comm_d = [6u8; 32];
-    let prover_id = [7u8; 32];
+    let prover_id = ProverId::from([7u8; 32]);
 
     let ticket = rng.gen();
     let seed = rng.gen();
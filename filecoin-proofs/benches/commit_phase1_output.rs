@@ -0,0 +1,127 @@
+use std::io::{Seek, SeekFrom, Write};
+use std::time::Duration;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use filecoin_proofs::{
+    add_piece, generate_piece_commitment, read_seal_commit_phase1_output, seal_commit_phase1,
+    seal_pre_commit_phase1, seal_pre_commit_phase2, write_seal_commit_phase1_output, PoRepConfig,
+    ProverId, SealCommitPhase1Output, SectorShape2KiB, SECTOR_SIZE_2_KIB,
+};
+use rand::{thread_rng, Rng};
+use storage_proofs_core::{api_version::ApiVersion, is_legacy_porep_id};
+use tempfile::{tempdir, NamedTempFile};
+
+fn seal_commit_phase1_output() -> SealCommitPhase1Output<SectorShape2KiB> {
+    let mut rng = thread_rng();
+
+    let porep_id_v1_1: u64 = 5; // This is a RegisteredSealProof value
+
+    let mut porep_id = [0u8; 32];
+    porep_id[..8].copy_from_slice(&porep_id_v1_1.to_le_bytes());
+    assert!(!is_legacy_porep_id(porep_id));
+
+    let config = PoRepConfig::new_groth16(SECTOR_SIZE_2_KIB, porep_id, ApiVersion::V1_1_0);
+    let prover_id = ProverId::from([7u8; 32]);
+    let sector_id = rng.gen::<u64>().into();
+    let ticket = rng.gen();
+    let seed = rng.gen();
+
+    let piece_size = config.unpadded_bytes_amount();
+    let data: Vec<u8> = (0..u64::from(piece_size)).map(|_| rng.gen()).collect();
+    let mut piece_file = NamedTempFile::new().expect("failed to create piece file");
+    piece_file
+        .write_all(&data)
+        .expect("failed to write piece file");
+    piece_file
+        .seek(SeekFrom::Start(0))
+        .expect("failed to seek piece file");
+    let piece_info = generate_piece_commitment(piece_file.as_file_mut(), piece_size)
+        .expect("failed to generate piece commitment");
+    piece_file
+        .seek(SeekFrom::Start(0))
+        .expect("failed to seek piece file");
+    let mut staged_sector_file = NamedTempFile::new().expect("failed to create staged sector");
+    add_piece(&mut piece_file, &mut staged_sector_file, piece_size, &[])
+        .expect("failed to add piece");
+    let piece_infos = vec![piece_info];
+
+    let sealed_sector_file = NamedTempFile::new().expect("failed to create sealed sector");
+    let cache_dir = tempdir().expect("failed to create cache dir");
+    let phase1_output = seal_pre_commit_phase1::<_, _, _, SectorShape2KiB>(
+        &config,
+        cache_dir.path(),
+        staged_sector_file.path(),
+        sealed_sector_file.path(),
+        prover_id,
+        sector_id,
+        ticket,
+        &piece_infos,
+    )
+    .expect("seal pre commit phase1 failed");
+    let pre_commit = seal_pre_commit_phase2(
+        &config,
+        phase1_output,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+    )
+    .expect("seal pre commit phase2 failed");
+
+    seal_commit_phase1::<_, SectorShape2KiB>(
+        &config,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+        prover_id,
+        sector_id,
+        ticket,
+        seed,
+        pre_commit,
+        &piece_infos,
+    )
+    .expect("seal commit phase1 failed")
+}
+
+fn bench_commit_phase1_output(c: &mut Criterion) {
+    let phase1_output = seal_commit_phase1_output();
+
+    let encoded = bincode::serialize(&phase1_output).expect("failed to encode with bincode");
+    let mut framed = Vec::new();
+    write_seal_commit_phase1_output(&phase1_output, &mut framed)
+        .expect("failed to write framed output");
+
+    let mut group = c.benchmark_group("commit-phase1-output");
+    group
+        .throughput(Throughput::Bytes(encoded.len() as u64))
+        .sample_size(10)
+        .warm_up_time(Duration::from_secs(1));
+    group.bench_function("write-bincode", |b| {
+        b.iter(|| black_box(bincode::serialize(&phase1_output).expect("failed to encode")))
+    });
+    group.bench_function("write-framed", |b| {
+        b.iter(|| {
+            let mut buf = Vec::with_capacity(framed.len());
+            write_seal_commit_phase1_output(&phase1_output, &mut buf).expect("failed to write");
+            black_box(buf)
+        })
+    });
+    group.bench_function("read-bincode", |b| {
+        b.iter(|| {
+            black_box(
+                read_seal_commit_phase1_output::<SectorShape2KiB, _>(&encoded[..])
+                    .expect("failed to read"),
+            )
+        })
+    });
+    group.bench_function("read-framed", |b| {
+        b.iter(|| {
+            black_box(
+                read_seal_commit_phase1_output::<SectorShape2KiB, _>(&framed[..])
+                    .expect("failed to read"),
+            )
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_commit_phase1_output);
+criterion_main!(benches);
@@ -3,7 +3,7 @@ use std::time::Duration;
 
 use criterion::{criterion_group, criterion_main, Criterion, Throughput};
 use filecoin_proofs::{
-    add_piece, get_seal_inputs, PaddedBytesAmount, PoRepConfig, SectorShape2KiB,
+    add_piece, get_seal_inputs, PaddedBytesAmount, PoRepConfig, ProverId, SectorShape2KiB,
     UnpaddedBytesAmount, SECTOR_SIZE_2_KIB,
 };
 use fr32::Fr32Reader;
@@ -117,7 +117,7 @@ fn get_seal_inputs_benchmark(c: &mut Criterion) {
     let config = PoRepConfig::new_groth16(SECTOR_SIZE_2_KIB, porep_id, ApiVersion::V1_1_0);
     let comm_r: [u8; 32] = [5u8; 32];
     let comm_d: [u8; 32] = [6u8; 32];
-    let prover_id: [u8; 32] = [7u8; 32];
+    let prover_id = ProverId::from([7u8; 32]);
 
     let ticket = rng.gen();
     let seed = rng.gen();
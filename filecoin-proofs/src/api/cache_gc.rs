@@ -0,0 +1,523 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use log::{info, warn};
+use merkletree::store::StoreConfig;
+use serde::{Deserialize, Serialize};
+use storage_proofs_core::{cache_key::CacheKey, merkle::MerkleTreeTrait};
+use storage_proofs_porep::stacked::{
+    TemporaryAux, SYNTHETIC_POREP_VANILLA_PROOFS_EXT, SYNTHETIC_POREP_VANILLA_PROOFS_KEY,
+};
+
+use crate::{
+    api::{
+        get_seal_status,
+        incremental_tree_d::{frontier_path, tree_path},
+        list_files, read_sector_manifest,
+        seal_status::default_t_aux,
+        SectorState, SECTOR_MANIFEST, WITHOUT_TREE_D,
+    },
+    constants::{DefaultOctTree, DefaultPieceHasher},
+    parameters::setup_params,
+    types::PoRepConfig,
+    with_shape,
+};
+
+/// How [`gc_cache`] treats orphaned artifacts.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GcPolicy {
+    /// Only report the orphaned artifacts, nothing is deleted.
+    pub dry_run: bool,
+    /// Artifacts that were modified more recently are never collected, they may belong to a
+    /// sealing operation that is still running.
+    pub min_age: Duration,
+}
+
+impl Default for GcPolicy {
+    fn default() -> Self {
+        GcPolicy {
+            dry_run: true,
+            min_age: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+}
+
+/// A file of a cache directory that is not referenced by its sector.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrphanedArtifact {
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GcReport {
+    /// The number of cache directories that were inspected.
+    pub cache_dirs: usize,
+    /// The orphaned artifacts, sorted by path. They were deleted unless the policy is a dry run.
+    pub orphaned: Vec<OrphanedArtifact>,
+    /// The unreferenced artifacts of cache directories without a readable sector manifest or
+    /// t_aux, sorted by path. They may belong to a sector that is still being sealed or whose
+    /// metadata is damaged, so they are only reported, never deleted.
+    pub unverified: Vec<OrphanedArtifact>,
+    /// The number of bytes that were freed.
+    pub freed_bytes: u64,
+}
+
+/// The files a sector references, relative to its cache directory.
+struct References {
+    /// The files listed in the sector manifest.
+    manifest: Option<Vec<PathBuf>>,
+    /// The stores the sector references besides the manifest, which may have been written after
+    /// the manifest was generated.
+    stores: Stores,
+}
+
+enum Stores {
+    /// The files of the stores of t_aux, and the metadata next to it.
+    TAux(Vec<StoreConfig>),
+    /// Pre-commit phase 1 completed, but there is no t_aux yet. The stores of pre-commit phase 1
+    /// and of a phase 2 that may be running are referenced.
+    PreCommit1(Vec<StoreConfig>),
+    /// There is no readable t_aux, and pre-commit phase 1 did not complete.
+    Unknown,
+}
+
+impl References {
+    fn read(cache_dir: &Path, porep_config: &PoRepConfig) -> Self {
+        let manifest = if cache_dir.join(SECTOR_MANIFEST).exists() {
+            match read_sector_manifest(cache_dir) {
+                Ok(manifest) => Some(
+                    manifest
+                        .cache
+                        .into_iter()
+                        .map(|artifact| artifact.path)
+                        .collect(),
+                ),
+                Err(e) => {
+                    warn!("ignoring invalid sector manifest in {:?}: {}", cache_dir, e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        References {
+            manifest,
+            stores: Stores::read(cache_dir, porep_config),
+        }
+    }
+
+    /// Returns whether the references are known for sure, i.e. the files that are not
+    /// referenced may be deleted.
+    fn is_verified(&self) -> bool {
+        self.manifest.is_some() || matches!(self.stores, Stores::TAux(_))
+    }
+
+    fn contains(&self, file: &Path) -> bool {
+        // The frontier of an incrementally built tree_d is kept until sealing starts.
+        if file == tree_path(Path::new("")) || file == frontier_path(Path::new("")) {
+            return true;
+        }
+
+        let in_manifest = self.manifest.as_ref().map_or(false, |files| {
+            file == Path::new(SECTOR_MANIFEST) || files.iter().any(|path| path == file)
+        });
+        in_manifest || self.stores.contains(file)
+    }
+}
+
+impl Stores {
+    fn read(cache_dir: &Path, porep_config: &PoRepConfig) -> Self {
+        if cache_dir.join(CacheKey::TAux.to_string()).exists() {
+            match read_t_aux(cache_dir) {
+                Ok(t_aux) => {
+                    let mut configs = t_aux.labels.labels;
+                    configs.push(t_aux.tree_d_config);
+                    configs.push(t_aux.tree_c_config);
+                    configs.push(t_aux.tree_r_last_config);
+                    return Stores::TAux(configs);
+                }
+                Err(e) => warn!("ignoring invalid t_aux in {:?}: {}", cache_dir, e),
+            }
+        }
+
+        let sector_size = u64::from(porep_config.sector_size);
+        match with_shape!(sector_size, pre_commit1_configs, porep_config, cache_dir,) {
+            Ok(Some(configs)) => Stores::PreCommit1(configs),
+            Ok(None) => Stores::Unknown,
+            Err(e) => {
+                warn!("could not read the seal status of {:?}: {}", cache_dir, e);
+                Stores::Unknown
+            }
+        }
+    }
+
+    fn contains(&self, file: &Path) -> bool {
+        match self {
+            Stores::TAux(configs) | Stores::PreCommit1(configs) => {
+                let metadata = [
+                    CacheKey::PAux.to_string(),
+                    CacheKey::TAux.to_string(),
+                    SECTOR_MANIFEST.to_string(),
+                    WITHOUT_TREE_D.to_string(),
+                    format!(
+                        "{}.{}",
+                        SYNTHETIC_POREP_VANILLA_PROOFS_KEY, SYNTHETIC_POREP_VANILLA_PROOFS_EXT
+                    ),
+                ];
+                metadata.iter().any(|name| file == Path::new(name))
+                    || configs.iter().any(|config| is_store_file(config, file))
+            }
+            Stores::Unknown => false,
+        }
+    }
+}
+
+/// Returns the stores of the sector in `cache_dir` at their default locations, if its
+/// pre-commit phase 1 completed according to [`get_seal_status`].
+fn pre_commit1_configs<Tree: 'static + MerkleTreeTrait>(
+    porep_config: &PoRepConfig,
+    cache_dir: &Path,
+) -> Result<Option<Vec<StoreConfig>>> {
+    // The replica is not known, which only matters for the state after pre-commit phase 2.
+    let status = get_seal_status::<Tree>(porep_config, cache_dir, Path::new(""))?;
+    if status.sector_state() != SectorState::PreCommit1 {
+        return Ok(None);
+    }
+
+    let params = setup_params(porep_config)?;
+    let t_aux = default_t_aux::<Tree>(cache_dir, params.nodes, params.layer_challenges.layers());
+    let mut configs = t_aux.labels.labels;
+    configs.push(t_aux.tree_d_config);
+    configs.push(t_aux.tree_c_config);
+    configs.push(t_aux.tree_r_last_config);
+    Ok(Some(configs))
+}
+
+fn read_t_aux(cache_dir: &Path) -> Result<TemporaryAux<DefaultOctTree, DefaultPieceHasher>> {
+    let t_aux_path = cache_dir.join(CacheKey::TAux.to_string());
+    let t_aux_bytes =
+        fs::read(&t_aux_path).with_context(|| format!("could not read {:?}", t_aux_path))?;
+    // The tree type is not part of the serialized t_aux, any tree can be used to read it.
+    Ok(bincode::deserialize(&t_aux_bytes)?)
+}
+
+/// Returns whether `file` is the data file of the store, or of one of the stores it is split
+/// into if there are several base trees.
+fn is_store_file(config: &StoreConfig, file: &Path) -> bool {
+    let name = match file.to_str() {
+        Some(name) => name,
+        None => return false,
+    };
+    let data_path = StoreConfig::data_path(&PathBuf::new(), &config.id);
+    let data_name = data_path.to_str().expect("store ids are valid strings");
+    let (prefix, suffix) = data_name.split_at(data_name.len() - ".dat".len());
+
+    name == data_name
+        || name
+            .strip_prefix(prefix)
+            .and_then(|rest| rest.strip_prefix('-'))
+            .and_then(|rest| rest.strip_suffix(suffix))
+            .map(|index| !index.is_empty() && index.bytes().all(|b| b.is_ascii_digit()))
+            .unwrap_or(false)
+}
+
+fn is_old_enough(path: &Path, min_age: Duration) -> Result<bool> {
+    let modified = fs::symlink_metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .with_context(|| format!("could not read the modification time of {:?}", path))?;
+    // Files from the future are treated as being modified just now.
+    let age = SystemTime::now()
+        .duration_since(modified)
+        .unwrap_or_default();
+    Ok(age >= min_age)
+}
+
+/// Finds (and deletes, unless `policy` is a dry run) the sealing artifacts in the cache
+/// directories within `root_dir` that no sector references. Every directory within `root_dir` is
+/// treated as the cache directory of a sector sealed with `porep_config`.
+///
+/// The files of a sector are referenced by its sector manifest if there is a valid one, and by its
+/// t_aux: the stores it lists, p_aux, t_aux and the synthetic proofs. The manifest may predate
+/// files that were written later, e.g. by pre-commit phase 2, which are referenced by t_aux.
+/// Temporary files are never referenced, the tree_d frontier always is. Only files older than
+/// `policy.min_age` are collected.
+///
+/// A cache directory without a readable manifest or t_aux may belong to a sector that is still
+/// being sealed, or whose metadata is damaged. If its pre-commit phase 1 completed, the stores
+/// at their default locations are referenced. Its unreferenced files are reported as
+/// [`GcReport::unverified`] and never deleted.
+pub fn gc_cache(
+    root_dir: &Path,
+    porep_config: &PoRepConfig,
+    policy: &GcPolicy,
+) -> Result<GcReport> {
+    info!("gc_cache:start: {:?}", root_dir);
+
+    let mut report = GcReport::default();
+    let mut cache_dirs = Vec::new();
+    let entries =
+        fs::read_dir(root_dir).with_context(|| format!("could not read {:?}", root_dir))?;
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            cache_dirs.push(entry.path());
+        }
+    }
+    cache_dirs.sort();
+
+    for cache_dir in cache_dirs {
+        report.cache_dirs += 1;
+        let references = References::read(&cache_dir, porep_config);
+        for file in list_files(&cache_dir)? {
+            let path = cache_dir.join(&file);
+            if references.contains(&file) || !is_old_enough(&path, policy.min_age)? {
+                continue;
+            }
+
+            let size = fs::symlink_metadata(&path)?.len();
+            if !references.is_verified() {
+                report.unverified.push(OrphanedArtifact { path, size });
+                continue;
+            }
+            if !policy.dry_run {
+                fs::remove_file(&path).with_context(|| format!("could not remove {:?}", path))?;
+                report.freed_bytes += size;
+            }
+            report.orphaned.push(OrphanedArtifact { path, size });
+        }
+    }
+
+    info!(
+        "gc_cache:finish: {} orphaned artifacts, {} unverified, {} bytes freed",
+        report.orphaned.len(),
+        report.unverified.len(),
+        report.freed_bytes
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use storage_proofs_core::api_version::ApiVersion;
+    use storage_proofs_porep::stacked::Labels;
+    use tempfile::tempdir;
+
+    use crate::{api::generate_sector_manifest, constants::SECTOR_SIZE_2_KIB};
+
+    fn orphaned_names(report: &GcReport, root: &Path) -> Vec<PathBuf> {
+        report
+            .orphaned
+            .iter()
+            .map(|artifact| {
+                artifact
+                    .path
+                    .strip_prefix(root)
+                    .expect("orphan outside of root")
+                    .to_path_buf()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_gc_cache() {
+        let dir = tempdir().expect("failed to create tempdir");
+        let root = dir.path().join("cache");
+        let porep_config = PoRepConfig::new_groth16(SECTOR_SIZE_2_KIB, [0; 32], ApiVersion::V1_2_0);
+
+        // A sector with a manifest, and a temporary file written after it.
+        let manifest_dir = root.join("manifest");
+        fs::create_dir_all(&manifest_dir).expect("failed to create cache dir");
+        let replica_path = dir.path().join("sealed");
+        fs::write(&replica_path, [1u8; 1024]).expect("failed to write replica");
+        fs::write(manifest_dir.join("p_aux"), [2u8; 64]).expect("failed to write p_aux");
+        generate_sector_manifest(&replica_path, &manifest_dir).expect("failed to generate");
+        fs::write(manifest_dir.join("layer.tmp"), [3u8; 16]).expect("failed to write tmp");
+
+        // A sector with t_aux, split trees and a left over layer of a previous attempt.
+        let t_aux_dir = root.join("t_aux");
+        fs::create_dir_all(&t_aux_dir).expect("failed to create cache dir");
+        let store_config = |id: String| StoreConfig::new(&t_aux_dir, id, 0);
+        let t_aux = TemporaryAux::<DefaultOctTree, DefaultPieceHasher> {
+            labels: Labels::new(vec![
+                store_config(CacheKey::label_layer(1)),
+                store_config(CacheKey::label_layer(2)),
+            ]),
+            tree_d_config: store_config(CacheKey::CommDTree.to_string()),
+            tree_r_last_config: store_config(CacheKey::CommRLastTree.to_string()),
+            tree_c_config: store_config(CacheKey::CommCTree.to_string()),
+            _g: Default::default(),
+        };
+        fs::write(
+            t_aux_dir.join("t_aux"),
+            bincode::serialize(&t_aux).expect("failed to serialize t_aux"),
+        )
+        .expect("failed to write t_aux");
+        for name in [
+            "p_aux",
+            "tree-d-frontier.json",
+            "sc-02-data-tree-d-frontier.dat",
+            "sc-02-data-layer-1.dat",
+            "sc-02-data-layer-2.dat",
+            "sc-02-data-layer-3.dat",
+            "sc-02-data-tree-d.dat",
+            "sc-02-data-tree-r-last-0.dat",
+            "sc-02-data-tree-r-last-1.dat",
+            "sc-02-data-tree-r-last-x.dat",
+        ] {
+            fs::write(t_aux_dir.join(name), [4u8; 32]).expect("failed to write store");
+        }
+
+        // An aborted seal, or one that is still running.
+        let aborted_dir = root.join("aborted");
+        fs::create_dir_all(&aborted_dir).expect("failed to create cache dir");
+        fs::write(aborted_dir.join("sc-02-data-layer-1.dat"), [5u8; 8])
+            .expect("failed to write layer");
+
+        // A sector with a damaged t_aux.
+        let damaged_dir = root.join("damaged");
+        fs::create_dir_all(&damaged_dir).expect("failed to create cache dir");
+        fs::write(damaged_dir.join("t_aux"), [6u8; 3]).expect("failed to write t_aux");
+        fs::write(damaged_dir.join("sc-02-data-tree-r-last.dat"), [6u8; 32])
+            .expect("failed to write store");
+
+        // A sector that completed pre-commit phase 1, and a left over of a previous attempt.
+        let pc1_dir = root.join("pc1");
+        fs::create_dir_all(&pc1_dir).expect("failed to create cache dir");
+        let layers = setup_params(&porep_config)
+            .expect("setup_params failed")
+            .layer_challenges
+            .layers();
+        for layer in 1..=layers {
+            fs::write(
+                pc1_dir.join(format!("sc-02-data-layer-{}.dat", layer)),
+                [7u8; 64 * 32],
+            )
+            .expect("failed to write layer");
+        }
+        fs::write(pc1_dir.join("sc-02-data-tree-d.dat"), [7u8; 127 * 32])
+            .expect("failed to write tree_d");
+        fs::write(pc1_dir.join("sc-02-data-layer.tmp"), [7u8; 8]).expect("failed to write tmp");
+
+        let expected = vec![
+            PathBuf::from("manifest/layer.tmp"),
+            PathBuf::from("t_aux/sc-02-data-layer-3.dat"),
+            PathBuf::from("t_aux/sc-02-data-tree-r-last-x.dat"),
+        ];
+        let expected_unverified = vec![
+            PathBuf::from("aborted/sc-02-data-layer-1.dat"),
+            PathBuf::from("damaged/sc-02-data-tree-r-last.dat"),
+            PathBuf::from("damaged/t_aux"),
+            PathBuf::from("pc1/sc-02-data-layer.tmp"),
+        ];
+        let unverified_names = |report: &GcReport| {
+            let report = GcReport {
+                orphaned: report.unverified.clone(),
+                ..Default::default()
+            };
+            orphaned_names(&report, &root)
+        };
+
+        let recent = gc_cache(&root, &porep_config, &GcPolicy::default()).expect("gc failed");
+        assert_eq!(recent.cache_dirs, 5);
+        assert!(recent.orphaned.is_empty());
+        assert!(recent.unverified.is_empty());
+
+        let policy = GcPolicy {
+            dry_run: true,
+            min_age: Duration::ZERO,
+        };
+        let dry_run = gc_cache(&root, &porep_config, &policy).expect("gc failed");
+        assert_eq!(orphaned_names(&dry_run, &root), expected);
+        assert_eq!(unverified_names(&dry_run), expected_unverified);
+        assert_eq!(dry_run.freed_bytes, 0);
+        assert!(manifest_dir.join("layer.tmp").exists());
+
+        let policy = GcPolicy {
+            dry_run: false,
+            ..policy
+        };
+        let report = gc_cache(&root, &porep_config, &policy).expect("gc failed");
+        assert_eq!(orphaned_names(&report, &root), expected);
+        assert_eq!(unverified_names(&report), expected_unverified);
+        assert_eq!(report.freed_bytes, 16 + 32 + 32);
+        for path in expected {
+            assert!(!root.join(path).exists());
+        }
+        // Unverified artifacts are never deleted.
+        for path in expected_unverified {
+            assert!(root.join(path).exists());
+        }
+        assert!(t_aux_dir.join("sc-02-data-tree-r-last-1.dat").exists());
+        assert!(t_aux_dir.join("tree-d-frontier.json").exists());
+        assert!(manifest_dir.join("p_aux").exists());
+
+        assert!(gc_cache(&root, &porep_config, &policy)
+            .expect("gc failed")
+            .orphaned
+            .is_empty());
+    }
+
+    #[test]
+    fn test_gc_cache_manifest_predates_t_aux() {
+        let dir = tempdir().expect("failed to create tempdir");
+        let root = dir.path().join("cache");
+        let porep_config = PoRepConfig::new_groth16(SECTOR_SIZE_2_KIB, [0; 32], ApiVersion::V1_2_0);
+
+        let cache_dir = root.join("sector");
+        fs::create_dir_all(&cache_dir).expect("failed to create cache dir");
+        let replica_path = dir.path().join("sealed");
+        fs::write(&replica_path, [1u8; 1024]).expect("failed to write replica");
+        fs::write(cache_dir.join("sc-02-data-layer-1.dat"), [2u8; 32])
+            .expect("failed to write layer");
+        generate_sector_manifest(&replica_path, &cache_dir).expect("failed to generate");
+
+        // Pre-commit phase 2 ran after the manifest was generated.
+        let store_config = |id: String| StoreConfig::new(&cache_dir, id, 0);
+        let t_aux = TemporaryAux::<DefaultOctTree, DefaultPieceHasher> {
+            labels: Labels::new(vec![store_config(CacheKey::label_layer(1))]),
+            tree_d_config: store_config(CacheKey::CommDTree.to_string()),
+            tree_r_last_config: store_config(CacheKey::CommRLastTree.to_string()),
+            tree_c_config: store_config(CacheKey::CommCTree.to_string()),
+            _g: Default::default(),
+        };
+        fs::write(
+            cache_dir.join("t_aux"),
+            bincode::serialize(&t_aux).expect("failed to serialize t_aux"),
+        )
+        .expect("failed to write t_aux");
+        for name in [
+            "p_aux",
+            "sc-02-data-tree-c-0.dat",
+            "sc-02-data-tree-r-last-0.dat",
+            "layer.tmp",
+        ] {
+            fs::write(cache_dir.join(name), [3u8; 32]).expect("failed to write store");
+        }
+
+        let policy = GcPolicy {
+            dry_run: false,
+            min_age: Duration::ZERO,
+        };
+        let report = gc_cache(&root, &porep_config, &policy).expect("gc failed");
+        assert_eq!(
+            orphaned_names(&report, &root),
+            vec![PathBuf::from("sector/layer.tmp")]
+        );
+        assert!(report.unverified.is_empty());
+        for name in [
+            "t_aux",
+            "p_aux",
+            "sc-02-data-layer-1.dat",
+            "sc-02-data-tree-c-0.dat",
+            "sc-02-data-tree-r-last-0.dat",
+        ] {
+            assert!(cache_dir.join(name).exists(), "{} was collected", name);
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use storage_proofs_core::api_version::{ApiFeature, ApiVersion};
+
+use crate::{
+    constants::PUBLISHED_SECTOR_SIZES,
+    types::{RegisteredPoStProof, RegisteredSealProof},
+};
+
+/// The kinds of proofs that can be generated and verified.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProofType {
+    Seal,
+    SealAggregation,
+    WinningPoSt,
+    WindowPoSt,
+    EmptySectorUpdate,
+}
+
+impl ProofType {
+    pub const ALL: [ProofType; 5] = [
+        ProofType::Seal,
+        ProofType::SealAggregation,
+        ProofType::WinningPoSt,
+        ProofType::WindowPoSt,
+        ProofType::EmptySectorUpdate,
+    ];
+}
+
+/// The API versions a feature can be used with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FeatureSupport {
+    pub feature: ApiFeature,
+    pub first_supported_version: ApiVersion,
+    pub last_supported_version: Option<ApiVersion>,
+}
+
+impl FeatureSupport {
+    fn new(feature: ApiFeature) -> Self {
+        Self {
+            feature,
+            first_supported_version: feature.first_supported_version(),
+            last_supported_version: feature.last_supported_version(),
+        }
+    }
+}
+
+/// A description of what this library supports, so that callers can negotiate at runtime instead
+/// of hard-coding versions and features.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    /// The supported API versions, in ascending order.
+    pub api_versions: Vec<ApiVersion>,
+    pub features: Vec<FeatureSupport>,
+    /// The sector sizes for which parameters have been published.
+    pub sector_sizes: Vec<u64>,
+    pub proof_types: Vec<ProofType>,
+    pub registered_seal_proofs: Vec<RegisteredSealProof>,
+    pub registered_post_proofs: Vec<RegisteredPoStProof>,
+}
+
+impl Capabilities {
+    #[inline]
+    pub fn supports_api_version(&self, api_version: ApiVersion) -> bool {
+        self.api_versions.contains(&api_version)
+    }
+
+    #[inline]
+    pub fn supports_sector_size(&self, sector_size: u64) -> bool {
+        self.sector_sizes.contains(&sector_size)
+    }
+
+    #[inline]
+    pub fn supports_proof_type(&self, proof_type: ProofType) -> bool {
+        self.proof_types.contains(&proof_type)
+    }
+
+    /// Returns whether all of the given features can be used with the given API version.
+    pub fn supports_features(&self, api_version: ApiVersion, features: &[ApiFeature]) -> bool {
+        self.supports_api_version(api_version)
+            && features.iter().all(|feature| {
+                self.features
+                    .iter()
+                    .any(|support| support.feature == *feature)
+                    && api_version.supports_feature(feature)
+            })
+    }
+
+    /// Returns the highest API version that supports all of the given features, if there is one.
+    pub fn negotiate_api_version(&self, features: &[ApiFeature]) -> Option<ApiVersion> {
+        self.api_versions
+            .iter()
+            .rev()
+            .copied()
+            .find(|api_version| self.supports_features(*api_version, features))
+    }
+
+    /// Returns the registered seal proof with the given id, if it is known.
+    pub fn registered_seal_proof(&self, id: u64) -> Option<RegisteredSealProof> {
+        self.registered_seal_proofs
+            .iter()
+            .copied()
+            .find(|proof| proof.id() == id)
+    }
+
+    /// Returns the registered PoSt proof with the given id, if it is known.
+    pub fn registered_post_proof(&self, id: u64) -> Option<RegisteredPoStProof> {
+        self.registered_post_proofs
+            .iter()
+            .copied()
+            .find(|proof| proof.id() == id)
+    }
+}
+
+/// Returns the capabilities of this library.
+pub fn capabilities() -> Capabilities {
+    Capabilities {
+        api_versions: ApiVersion::ALL.to_vec(),
+        features: ApiFeature::ALL
+            .iter()
+            .copied()
+            .map(FeatureSupport::new)
+            .collect(),
+        sector_sizes: PUBLISHED_SECTOR_SIZES.to_vec(),
+        proof_types: ProofType::ALL.to_vec(),
+        registered_seal_proofs: RegisteredSealProof::ALL.to_vec(),
+        registered_post_proofs: RegisteredPoStProof::ALL.to_vec(),
+    }
+}
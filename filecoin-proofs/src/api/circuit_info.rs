@@ -0,0 +1,348 @@
+use anyhow::{bail, ensure, Result};
+use bellperson::{
+    util_cs::test_cs::TestConstraintSystem, Circuit, ConstraintSystem, Index, LinearCombination,
+    SynthesisError, Variable,
+};
+use blstrs::Scalar as Fr;
+use log::info;
+use storage_proofs_core::{compound_proof::CompoundProof, merkle::MerkleTreeTrait};
+use storage_proofs_porep::stacked::{StackedCompound, StackedDrg};
+use storage_proofs_post::fallback::{FallbackPoSt, FallbackPoStCircuit, FallbackPoStCompound};
+use storage_proofs_update::{
+    circuit::EmptySectorUpdateCircuit, compound::EmptySectorUpdateCompound, constants::TreeRHasher,
+    EmptySectorUpdate, PublicParams,
+};
+
+use crate::{
+    constants::{DefaultPieceHasher, PUBLISHED_SECTOR_SIZES},
+    parameters::{public_params, window_post_public_params, winning_post_public_params},
+    types::{PoRepConfig, PoStConfig, PoStType},
+    with_shape,
+};
+
+/// The size and shape of the circuit of a single partition proof.
+///
+/// The densities are the number of distinct variables, inputs and auxiliary ones, that appear in
+/// the A, B and C linear combinations of the constraints. Together with the number of constraints
+/// they determine the size of the Groth16 parameters.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CircuitInfo {
+    pub constraints: usize,
+    /// The number of public inputs, including the constant one input.
+    pub inputs: usize,
+    /// The number of auxiliary (private) variables.
+    pub aux: usize,
+    pub a_density: usize,
+    pub b_density: usize,
+    pub c_density: usize,
+}
+
+/// Tracks which variables of a circuit are used.
+#[derive(Default)]
+struct Density {
+    inputs: Vec<u64>,
+    aux: Vec<u64>,
+}
+
+impl Density {
+    fn add(&mut self, lc: &LinearCombination<Fr>) {
+        for (var, _) in lc.iter() {
+            let (bits, index) = match var.get_unchecked() {
+                Index::Input(index) => (&mut self.inputs, index),
+                Index::Aux(index) => (&mut self.aux, index),
+            };
+            if bits.len() <= index / 64 {
+                bits.resize(index / 64 + 1, 0);
+            }
+            bits[index / 64] |= 1 << (index % 64);
+        }
+    }
+
+    fn count(&self) -> usize {
+        self.inputs
+            .iter()
+            .chain(&self.aux)
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+}
+
+/// A constraint system that only counts, the values of the variables are never computed.
+struct CountingCS {
+    info: CircuitInfo,
+    a: Density,
+    b: Density,
+    c: Density,
+}
+
+impl CountingCS {
+    fn into_info(self) -> CircuitInfo {
+        CircuitInfo {
+            a_density: self.a.count(),
+            b_density: self.b.count(),
+            c_density: self.c.count(),
+            ..self.info
+        }
+    }
+}
+
+impl ConstraintSystem<Fr> for CountingCS {
+    type Root = Self;
+
+    fn new() -> Self {
+        CountingCS {
+            info: CircuitInfo {
+                // The constant one input.
+                inputs: 1,
+                ..Default::default()
+            },
+            a: Density::default(),
+            b: Density::default(),
+            c: Density::default(),
+        }
+    }
+
+    fn alloc<F, A, AR>(&mut self, _annotation: A, _f: F) -> Result<Variable, SynthesisError>
+    where
+        F: FnOnce() -> Result<Fr, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.info.aux += 1;
+        Ok(Variable::new_unchecked(Index::Aux(self.info.aux - 1)))
+    }
+
+    fn alloc_input<F, A, AR>(&mut self, _annotation: A, _f: F) -> Result<Variable, SynthesisError>
+    where
+        F: FnOnce() -> Result<Fr, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.info.inputs += 1;
+        Ok(Variable::new_unchecked(Index::Input(self.info.inputs - 1)))
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, _annotation: A, a: LA, b: LB, c: LC)
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+        LA: FnOnce(LinearCombination<Fr>) -> LinearCombination<Fr>,
+        LB: FnOnce(LinearCombination<Fr>) -> LinearCombination<Fr>,
+        LC: FnOnce(LinearCombination<Fr>) -> LinearCombination<Fr>,
+    {
+        self.a.add(&a(LinearCombination::zero()));
+        self.b.add(&b(LinearCombination::zero()));
+        self.c.add(&c(LinearCombination::zero()));
+        self.info.constraints += 1;
+    }
+
+    fn push_namespace<NR, N>(&mut self, _name_fn: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+    }
+
+    fn pop_namespace(&mut self) {}
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+}
+
+fn count<C: Circuit<Fr>>(circuit: C) -> Result<CircuitInfo> {
+    let mut cs = CountingCS::new();
+    circuit.synthesize(&mut cs)?;
+    Ok(cs.into_info())
+}
+
+/// Synthesizes `circuit` with its witness and checks that all constraints are satisfied and that
+/// its public inputs equal `pub_inputs` (without the constant one input).
+///
+/// The error names the first unsatisfied constraint. This keeps every constraint in memory, so
+/// it is meant for debugging only, a failing proof is usually only noticed at verification.
+pub fn check_circuit_satisfied<C: Circuit<Fr>>(circuit: C, pub_inputs: &[Fr]) -> Result<()> {
+    info!("check_circuit_satisfied:start");
+    let mut cs = TestConstraintSystem::<Fr>::new();
+    circuit.synthesize(&mut cs)?;
+
+    if let Some(constraint) = cs.which_is_unsatisfied() {
+        bail!("circuit constraint is not satisfied: {}", constraint);
+    }
+    ensure!(
+        cs.num_inputs() == pub_inputs.len() + 1,
+        "circuit has {} public inputs, expected {}",
+        cs.num_inputs() - 1,
+        pub_inputs.len()
+    );
+    ensure!(
+        cs.verify(pub_inputs),
+        "circuit public inputs do not match the expected ones"
+    );
+    info!("check_circuit_satisfied:finish");
+
+    Ok(())
+}
+
+fn ensure_published(sector_size: u64) -> Result<()> {
+    ensure!(
+        PUBLISHED_SECTOR_SIZES.contains(&sector_size),
+        "unsupported sector size {}",
+        sector_size
+    );
+    Ok(())
+}
+
+/// Returns the shape of the circuit of a single seal (PoRep) partition.
+pub fn circuit_info(porep_config: &PoRepConfig) -> Result<CircuitInfo> {
+    let sector_size = u64::from(porep_config.sector_size);
+    ensure_published(sector_size)?;
+    with_shape!(sector_size, seal_circuit_info_with_shape, porep_config,)
+}
+
+fn seal_circuit_info_with_shape<Tree: 'static + MerkleTreeTrait>(
+    porep_config: &PoRepConfig,
+) -> Result<CircuitInfo> {
+    info!("seal_circuit_info:start");
+    let public_params = public_params::<Tree>(porep_config)?;
+    let circuit = <StackedCompound<Tree, DefaultPieceHasher> as CompoundProof<
+        StackedDrg<'_, Tree, DefaultPieceHasher>,
+        _,
+    >>::blank_circuit(&public_params);
+    let info = count(circuit)?;
+    info!("seal_circuit_info:finish");
+
+    Ok(info)
+}
+
+/// Returns the shape of the circuit of a single Window or Winning PoSt partition.
+pub fn post_circuit_info(post_config: &PoStConfig) -> Result<CircuitInfo> {
+    let sector_size = u64::from(post_config.sector_size);
+    ensure_published(sector_size)?;
+    with_shape!(sector_size, post_circuit_info_with_shape, post_config,)
+}
+
+fn post_circuit_info_with_shape<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+) -> Result<CircuitInfo> {
+    info!("post_circuit_info:start");
+    let public_params = match post_config.typ {
+        PoStType::Winning => winning_post_public_params::<Tree>(post_config)?,
+        PoStType::Window => window_post_public_params::<Tree>(post_config)?,
+    };
+    let circuit: FallbackPoStCircuit<Tree> = <FallbackPoStCompound<Tree> as CompoundProof<
+        FallbackPoSt<'_, Tree>,
+        FallbackPoStCircuit<Tree>,
+    >>::blank_circuit(&public_params);
+    let info = count(circuit)?;
+    info!("post_circuit_info:finish");
+
+    Ok(info)
+}
+
+/// Returns the shape of the circuit of a single empty sector update partition.
+pub fn update_circuit_info(porep_config: &PoRepConfig) -> Result<CircuitInfo> {
+    let sector_size = u64::from(porep_config.sector_size);
+    ensure_published(sector_size)?;
+    with_shape!(sector_size, update_circuit_info_with_shape, sector_size,)
+}
+
+fn update_circuit_info_with_shape<Tree: 'static + MerkleTreeTrait<Hasher = TreeRHasher>>(
+    sector_size: u64,
+) -> Result<CircuitInfo> {
+    info!("update_circuit_info:start");
+    let public_params = PublicParams::from_sector_size(sector_size);
+    let circuit = <EmptySectorUpdateCompound<Tree> as CompoundProof<
+        EmptySectorUpdate<Tree>,
+        EmptySectorUpdateCircuit<Tree>,
+    >>::blank_circuit(&public_params);
+    let info = count(circuit)?;
+    info!("update_circuit_info:finish");
+
+    Ok(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bellperson::{gadgets::num::AllocatedNum, util_cs::bench_cs::BenchCS};
+    use storage_proofs_core::api_version::ApiVersion;
+
+    use crate::{
+        constants::{
+            SectorShape2KiB, SECTOR_SIZE_2_KIB, WINNING_POST_CHALLENGE_COUNT,
+            WINNING_POST_SECTOR_COUNT,
+        },
+        types::SectorSize,
+    };
+
+    #[test]
+    fn test_post_circuit_info() {
+        let post_config = PoStConfig {
+            sector_size: SectorSize(SECTOR_SIZE_2_KIB),
+            challenge_count: WINNING_POST_CHALLENGE_COUNT,
+            sector_count: WINNING_POST_SECTOR_COUNT,
+            typ: PoStType::Winning,
+            priority: false,
+            api_version: ApiVersion::V1_2_0,
+        };
+        let info = post_circuit_info(&post_config).expect("post_circuit_info failed");
+
+        let public_params = winning_post_public_params::<SectorShape2KiB>(&post_config)
+            .expect("public params failed");
+        let circuit = <FallbackPoStCompound<SectorShape2KiB> as CompoundProof<
+            FallbackPoSt<'_, SectorShape2KiB>,
+            FallbackPoStCircuit<SectorShape2KiB>,
+        >>::blank_circuit(&public_params);
+        let mut cs = BenchCS::new();
+        circuit.synthesize(&mut cs).expect("synthesize failed");
+
+        assert_eq!(info.constraints, cs.num_constraints());
+        assert_eq!(info.inputs, cs.num_inputs());
+        for density in [info.a_density, info.b_density, info.c_density] {
+            assert!(density > 0);
+            assert!(density <= info.inputs + info.aux);
+        }
+
+        let mut unpublished = post_config;
+        unpublished.sector_size = SectorSize(3 * 1024);
+        assert!(post_circuit_info(&unpublished).is_err());
+    }
+
+    /// Proves knowledge of `x` with `x * x == y`, `y` being the public input.
+    struct Square {
+        x: Fr,
+        y: Fr,
+    }
+
+    impl Circuit<Fr> for Square {
+        fn synthesize<CS: ConstraintSystem<Fr>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let x = AllocatedNum::alloc(cs.namespace(|| "x"), || Ok(self.x))?;
+            let y = AllocatedNum::alloc_input(cs.namespace(|| "y"), || Ok(self.y))?;
+            cs.enforce(
+                || "x squared",
+                |lc| lc + x.get_variable(),
+                |lc| lc + x.get_variable(),
+                |lc| lc + y.get_variable(),
+            );
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_check_circuit_satisfied() {
+        let three = Fr::from(3u64);
+        let nine = Fr::from(9u64);
+
+        check_circuit_satisfied(Square { x: three, y: nine }, &[nine])
+            .expect("circuit should be satisfied");
+
+        let err = check_circuit_satisfied(Square { x: three, y: three }, &[three])
+            .expect_err("circuit should not be satisfied");
+        assert!(err.to_string().contains("x squared"));
+
+        assert!(check_circuit_satisfied(Square { x: three, y: nine }, &[three]).is_err());
+        assert!(check_circuit_satisfied(Square { x: three, y: nine }, &[]).is_err());
+    }
+}
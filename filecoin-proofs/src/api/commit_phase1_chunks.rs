@@ -0,0 +1,279 @@
+use std::io::{Cursor, Read, Write};
+
+use anyhow::{ensure, Context, Result};
+use filecoin_hashers::Hasher;
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+use storage_proofs_core::merkle::MerkleTreeTrait;
+
+use crate::types::{
+    Commitment, SealCommitPhase1Chunk, SealCommitPhase1Output, Ticket, VanillaSealProof,
+};
+
+/// Starts the framed format written by [`write_seal_commit_phase1_output`]. Read as the length
+/// of the partitions vector, which a bincode encoded output starts with, it is far too large to
+/// be mistaken for one.
+const FRAMED_OUTPUT_MAGIC: [u8; 8] = *b"FILC1OUT";
+
+/// Number of vanilla proofs that are encoded in parallel before their frames are written.
+const FRAMES_PER_BATCH: usize = 64;
+
+/// The fields of a [`SealCommitPhase1Output`] besides the vanilla proofs, and the number of
+/// vanilla proofs of every partition.
+type FramedOutputHeader<D> = (Vec<u64>, Commitment, Commitment, D, Ticket, Ticket);
+
+/// Splits the output of `seal_commit_phase1` into one chunk per partition, so that the chunks
+/// can be transferred (and proven) independently. They are reassembled with
+/// [`join_seal_commit_phase1_chunks`].
+pub fn split_seal_commit_phase1_output<Tree: MerkleTreeTrait>(
+    phase1_output: SealCommitPhase1Output<Tree>,
+) -> Vec<SealCommitPhase1Chunk<Tree>> {
+    let SealCommitPhase1Output {
+        vanilla_proofs,
+        comm_r,
+        comm_d,
+        replica_id,
+        seed,
+        ticket,
+    } = phase1_output;
+
+    let partitions = vanilla_proofs.len();
+    vanilla_proofs
+        .into_iter()
+        .enumerate()
+        .map(|(partition, vanilla_proofs)| SealCommitPhase1Chunk {
+            partition,
+            partitions,
+            vanilla_proofs,
+            comm_r,
+            comm_d,
+            replica_id,
+            seed,
+            ticket,
+        })
+        .collect()
+}
+
+/// Reassembles the output of `seal_commit_phase1` from the chunks returned by
+/// [`split_seal_commit_phase1_output`], in any order. Fails if a partition is missing or
+/// duplicated, or if the chunks belong to different phase 1 outputs.
+pub fn join_seal_commit_phase1_chunks<Tree: MerkleTreeTrait>(
+    mut chunks: Vec<SealCommitPhase1Chunk<Tree>>,
+) -> Result<SealCommitPhase1Output<Tree>> {
+    ensure!(!chunks.is_empty(), "no commit phase1 chunks to join");
+    chunks.sort_by_key(|chunk| chunk.partition);
+
+    let first = &chunks[0];
+    ensure!(
+        chunks.len() == first.partitions,
+        "expected {} commit phase1 chunks, got {}",
+        first.partitions,
+        chunks.len()
+    );
+    for (partition, chunk) in chunks.iter().enumerate() {
+        ensure!(
+            chunk.partition == partition,
+            "commit phase1 chunk of partition {} is missing",
+            partition
+        );
+        ensure!(
+            chunk.partitions == first.partitions
+                && chunk.comm_r == first.comm_r
+                && chunk.comm_d == first.comm_d
+                && chunk.replica_id == first.replica_id
+                && chunk.seed == first.seed
+                && chunk.ticket == first.ticket,
+            "commit phase1 chunk of partition {} belongs to a different sector",
+            partition
+        );
+    }
+
+    let (comm_r, comm_d, replica_id, seed, ticket) = (
+        first.comm_r,
+        first.comm_d,
+        first.replica_id,
+        first.seed,
+        first.ticket,
+    );
+    Ok(SealCommitPhase1Output {
+        vanilla_proofs: chunks
+            .into_iter()
+            .map(|chunk| chunk.vanilla_proofs)
+            .collect(),
+        comm_r,
+        comm_d,
+        replica_id,
+        seed,
+        ticket,
+    })
+}
+
+fn write_frame<W: Write>(writer: &mut W, frame: &[u8]) -> Result<()> {
+    writer.write_all(&(frame.len() as u64).to_le_bytes())?;
+    writer.write_all(frame)?;
+    Ok(())
+}
+
+fn read_frame<R: Read>(reader: &mut R) -> Result<Vec<u8>> {
+    let mut len = [0u8; 8];
+    reader.read_exact(&mut len)?;
+    let len = u64::from_le_bytes(len);
+
+    let mut frame = Vec::new();
+    reader.take(len).read_to_end(&mut frame)?;
+    ensure!(frame.len() as u64 == len, "truncated commit phase1 output");
+    Ok(frame)
+}
+
+/// Writes the output of `seal_commit_phase1` in a framed format, in which every vanilla proof is
+/// a length prefixed frame. The frames are encoded in parallel in batches of `FRAMES_PER_BATCH`,
+/// which is a lot faster than encoding the whole output with bincode for large sectors, and only
+/// a batch is buffered at a time. It is read with [`read_seal_commit_phase1_output`].
+pub fn write_seal_commit_phase1_output<Tree: 'static + MerkleTreeTrait, W: Write>(
+    phase1_output: &SealCommitPhase1Output<Tree>,
+    mut writer: W,
+) -> Result<()> {
+    let header = bincode::serialize(&(
+        phase1_output
+            .vanilla_proofs
+            .iter()
+            .map(|proofs| proofs.len() as u64)
+            .collect::<Vec<_>>(),
+        phase1_output.comm_r,
+        phase1_output.comm_d,
+        &phase1_output.replica_id,
+        phase1_output.seed,
+        phase1_output.ticket,
+    ))?;
+
+    writer.write_all(&FRAMED_OUTPUT_MAGIC)?;
+    write_frame(&mut writer, &header)?;
+    let proofs: Vec<_> = phase1_output.vanilla_proofs.iter().flatten().collect();
+    for batch in proofs.chunks(FRAMES_PER_BATCH) {
+        let frames = batch
+            .par_iter()
+            .map(bincode::serialize)
+            .collect::<bincode::Result<Vec<_>>>()?;
+        for frame in &frames {
+            write_frame(&mut writer, frame)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads the output of `seal_commit_phase1`, either in the framed format written by
+/// [`write_seal_commit_phase1_output`], whose frames are decoded in parallel, or encoded with
+/// bincode as a whole.
+pub fn read_seal_commit_phase1_output<Tree: 'static + MerkleTreeTrait, R: Read>(
+    mut reader: R,
+) -> Result<SealCommitPhase1Output<Tree>> {
+    let mut magic = [0u8; 8];
+    reader
+        .read_exact(&mut magic)
+        .context("could not read commit phase1 output")?;
+    if magic != FRAMED_OUTPUT_MAGIC {
+        return bincode::deserialize_from(Cursor::new(magic).chain(reader))
+            .context("could not decode commit phase1 output");
+    }
+
+    let header = read_frame(&mut reader)?;
+    let (proof_counts, comm_r, comm_d, replica_id, seed, ticket): FramedOutputHeader<
+        <Tree::Hasher as Hasher>::Domain,
+    > = bincode::deserialize(&header).context("could not decode commit phase1 output header")?;
+
+    let frames = proof_counts
+        .iter()
+        .flat_map(|count| 0..*count)
+        .map(|_| read_frame(&mut reader))
+        .collect::<Result<Vec<_>>>()?;
+    let mut proofs = frames
+        .par_iter()
+        .map(|frame| bincode::deserialize(frame))
+        .collect::<bincode::Result<Vec<VanillaSealProof<Tree>>>>()
+        .context("could not decode commit phase1 vanilla proofs")?
+        .into_iter();
+    let vanilla_proofs = proof_counts
+        .iter()
+        .map(|count| proofs.by_ref().take(*count as usize).collect())
+        .collect();
+
+    Ok(SealCommitPhase1Output {
+        vanilla_proofs,
+        comm_r,
+        comm_d,
+        replica_id,
+        seed,
+        ticket,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::constants::SectorShape2KiB;
+
+    type Tree = SectorShape2KiB;
+
+    fn phase1_output(partitions: usize, comm_r: Commitment) -> SealCommitPhase1Output<Tree> {
+        SealCommitPhase1Output {
+            vanilla_proofs: (0..partitions).map(|_| Vec::new()).collect(),
+            comm_r,
+            comm_d: [2u8; 32],
+            replica_id: Default::default(),
+            seed: [3u8; 32],
+            ticket: [4u8; 32],
+        }
+    }
+
+    #[test]
+    fn test_split_join_round_trip() {
+        let mut chunks = split_seal_commit_phase1_output(phase1_output(3, [1u8; 32]));
+        assert_eq!(chunks.len(), 3);
+        for (partition, chunk) in chunks.iter().enumerate() {
+            assert_eq!(chunk.partition, partition);
+            assert_eq!(chunk.partitions, 3);
+        }
+
+        chunks.reverse();
+        let joined = join_seal_commit_phase1_chunks(chunks).expect("failed to join chunks");
+        assert_eq!(
+            bincode::serialize(&joined).expect("failed to serialize"),
+            bincode::serialize(&phase1_output(3, [1u8; 32])).expect("failed to serialize")
+        );
+    }
+
+    #[test]
+    fn test_join_missing_chunk() {
+        assert!(join_seal_commit_phase1_chunks::<Tree>(Vec::new()).is_err());
+
+        let mut chunks = split_seal_commit_phase1_output(phase1_output(3, [1u8; 32]));
+        chunks.remove(1);
+        assert!(join_seal_commit_phase1_chunks(chunks).is_err());
+    }
+
+    #[test]
+    fn test_join_duplicate_chunk() {
+        // Too many chunks.
+        let mut chunks = split_seal_commit_phase1_output(phase1_output(3, [1u8; 32]));
+        chunks.push(split_seal_commit_phase1_output(phase1_output(3, [1u8; 32])).remove(0));
+        assert!(join_seal_commit_phase1_chunks(chunks).is_err());
+
+        // The right number of chunks, but one partition twice.
+        let mut chunks = split_seal_commit_phase1_output(phase1_output(3, [1u8; 32]));
+        chunks[1] = split_seal_commit_phase1_output(phase1_output(3, [1u8; 32])).remove(0);
+        assert!(join_seal_commit_phase1_chunks(chunks).is_err());
+    }
+
+    #[test]
+    fn test_join_mismatched_chunks() {
+        let mut chunks = split_seal_commit_phase1_output(phase1_output(2, [1u8; 32]));
+        chunks[1] = split_seal_commit_phase1_output(phase1_output(2, [5u8; 32])).remove(1);
+        assert!(join_seal_commit_phase1_chunks(chunks).is_err());
+
+        // The same sector, split into a different number of partitions.
+        let mut chunks = split_seal_commit_phase1_output(phase1_output(2, [1u8; 32]));
+        chunks[1] = split_seal_commit_phase1_output(phase1_output(3, [1u8; 32])).remove(1);
+        assert!(join_seal_commit_phase1_chunks(chunks).is_err());
+    }
+}
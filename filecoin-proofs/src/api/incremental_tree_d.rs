@@ -0,0 +1,517 @@
+use std::convert::TryInto;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Context, Result};
+use log::{info, trace, warn};
+use merkletree::store::StoreConfig;
+use serde::{Deserialize, Serialize};
+use storage_proofs_core::{cache_key::CacheKey, util::NODE_SIZE};
+
+use crate::{
+    api::add_piece,
+    pieces::{piece_hash, sum_piece_bytes_with_alignment},
+    types::{Commitment, PaddedBytesAmount, PieceInfo, SectorSize, UnpaddedBytesAmount},
+};
+
+/// The identifier of the files of an incrementally built tree_d within the cache directory.
+pub const TREE_D_FRONTIER: &str = "tree-d-frontier";
+
+const TREE_D_FRONTIER_VERSION: u32 = 1;
+
+// The number of zero subtree roots written at once when the tree is finalized.
+const ZERO_FILL_NODES: usize = 1 << 15;
+
+/// The frontier of a partially built tree_d, i.e. everything needed to continue hashing where the
+/// previous piece left off.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct TreeDFrontier {
+    version: u32,
+    sector_size: u64,
+    /// The number of leaves hashed so far.
+    leaves: u64,
+    /// The bytes of a leaf that was only partially written.
+    partial_leaf: Vec<u8>,
+    /// For every level below the root, the last node if it is still waiting for its sibling.
+    pending: Vec<Option<Commitment>>,
+    /// The root, once the sector is full.
+    root: Option<Commitment>,
+}
+
+/// A sha256 tree_d that is built while pieces are added to a staged sector, so that
+/// `seal_pre_commit_phase1` only needs to hash the empty remainder of the sector.
+///
+/// The tree is written into the cache directory in the layout of the tree_d store, its frontier
+/// is persisted with [`IncrementalTreeD::persist`], so that pieces can be added across restarts.
+#[derive(Debug)]
+pub struct IncrementalTreeD {
+    cache_path: PathBuf,
+    file: File,
+    frontier: TreeDFrontier,
+}
+
+impl IncrementalTreeD {
+    /// Opens the tree_d of the sector staged for `cache_path`, or starts a new one if there is
+    /// none yet.
+    pub fn open_or_create(cache_path: &Path, sector_size: SectorSize) -> Result<Self> {
+        if let Some(tree_d) = Self::open(cache_path)? {
+            ensure!(
+                tree_d.frontier.sector_size == u64::from(sector_size),
+                "tree_d frontier in {:?} is for sector size {}, not {}",
+                cache_path,
+                tree_d.frontier.sector_size,
+                u64::from(sector_size),
+            );
+            return Ok(tree_d);
+        }
+
+        let sector_size = u64::from(sector_size);
+        let leaves = sector_size / NODE_SIZE as u64;
+        ensure!(
+            leaves.is_power_of_two(),
+            "sector size {} is not a power of two",
+            sector_size
+        );
+
+        let tree_path = tree_path(cache_path);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&tree_path)
+            .with_context(|| format!("could not create {:?}", tree_path))?;
+        file.set_len(tree_len(leaves) * NODE_SIZE as u64)?;
+
+        let tree_d = IncrementalTreeD {
+            cache_path: cache_path.to_path_buf(),
+            file,
+            frontier: TreeDFrontier {
+                version: TREE_D_FRONTIER_VERSION,
+                sector_size,
+                leaves: 0,
+                partial_leaf: Vec::new(),
+                pending: vec![None; leaves.trailing_zeros() as usize],
+                root: None,
+            },
+        };
+        tree_d.persist()?;
+        Ok(tree_d)
+    }
+
+    /// Opens the tree_d of the sector staged for `cache_path`, returns `None` if there is none.
+    pub fn open(cache_path: &Path) -> Result<Option<Self>> {
+        let frontier_path = frontier_path(cache_path);
+        if !frontier_path.exists() {
+            return Ok(None);
+        }
+
+        let frontier: TreeDFrontier = serde_json::from_slice(
+            &fs::read(&frontier_path)
+                .with_context(|| format!("could not read {:?}", frontier_path))?,
+        )
+        .with_context(|| format!("invalid tree_d frontier {:?}", frontier_path))?;
+        ensure!(
+            frontier.version == TREE_D_FRONTIER_VERSION,
+            "unsupported tree_d frontier version {}",
+            frontier.version
+        );
+
+        let tree_path = tree_path(cache_path);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&tree_path)
+            .with_context(|| format!("could not open {:?}", tree_path))?;
+        let leaves = frontier.sector_size / NODE_SIZE as u64;
+        ensure!(
+            file.metadata()?.len() == tree_len(leaves) * NODE_SIZE as u64,
+            "{:?} has an invalid size",
+            tree_path
+        );
+
+        Ok(Some(IncrementalTreeD {
+            cache_path: cache_path.to_path_buf(),
+            file,
+            frontier,
+        }))
+    }
+
+    /// The number of (padded) bytes of the staged sector that were hashed so far.
+    pub fn bytes_written(&self) -> PaddedBytesAmount {
+        PaddedBytesAmount(
+            self.frontier.leaves * NODE_SIZE as u64 + self.frontier.partial_leaf.len() as u64,
+        )
+    }
+
+    /// Returns a writer that writes to `inner` and hashes everything written into the tree.
+    pub fn writer<W: Write>(&mut self, inner: W) -> TreeDWriter<'_, W> {
+        TreeDWriter {
+            inner,
+            tree_d: self,
+        }
+    }
+
+    /// Persists the frontier, the tree can be re-opened with [`IncrementalTreeD::open`]
+    /// afterwards.
+    pub fn persist(&self) -> Result<()> {
+        self.file.sync_data()?;
+
+        // Write the frontier atomically, so that a crash leaves the previous one intact.
+        let frontier_path = frontier_path(&self.cache_path);
+        let tmp_path = frontier_path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_vec(&self.frontier)?)
+            .with_context(|| format!("could not write {:?}", tmp_path))?;
+        fs::rename(&tmp_path, &frontier_path)
+            .with_context(|| format!("could not write {:?}", frontier_path))?;
+        Ok(())
+    }
+
+    fn num_leaves(&self) -> u64 {
+        self.frontier.sector_size / NODE_SIZE as u64
+    }
+
+    // The offset of the first node of `level` within the tree file.
+    fn level_offset(&self, level: usize) -> u64 {
+        let leaves = self.num_leaves();
+        (0..level).map(|l| leaves >> l).sum::<u64>() * NODE_SIZE as u64
+    }
+
+    fn write_nodes(&mut self, level: usize, index: u64, nodes: &[u8]) -> Result<()> {
+        let offset = self.level_offset(level) + index * NODE_SIZE as u64;
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(nodes)?;
+        Ok(())
+    }
+
+    fn append(&mut self, mut data: &[u8]) -> Result<()> {
+        ensure!(
+            u64::from(self.bytes_written()) + data.len() as u64 <= self.frontier.sector_size,
+            "more data written than fits into the sector"
+        );
+
+        let mut leaf_bytes = Vec::with_capacity(self.frontier.partial_leaf.len() + data.len());
+        if !self.frontier.partial_leaf.is_empty() {
+            let missing = NODE_SIZE - self.frontier.partial_leaf.len();
+            if data.len() < missing {
+                self.frontier.partial_leaf.extend_from_slice(data);
+                return Ok(());
+            }
+            leaf_bytes.append(&mut self.frontier.partial_leaf);
+            leaf_bytes.extend_from_slice(&data[..missing]);
+            data = &data[missing..];
+        }
+        let complete = data.len() - data.len() % NODE_SIZE;
+        leaf_bytes.extend_from_slice(&data[..complete]);
+        self.frontier.partial_leaf = data[complete..].to_vec();
+
+        if leaf_bytes.is_empty() {
+            return Ok(());
+        }
+
+        let first_leaf = self.frontier.leaves;
+        self.write_nodes(0, first_leaf, &leaf_bytes)?;
+        self.frontier.leaves += (leaf_bytes.len() / NODE_SIZE) as u64;
+
+        // Hash the new nodes level by level, the parents of every level are contiguous.
+        let mut nodes: Vec<Commitment> = leaf_bytes
+            .chunks_exact(NODE_SIZE)
+            .map(|leaf| leaf.try_into().expect("leaf of node size"))
+            .collect();
+        for level in 0..self.frontier.pending.len() {
+            let mut children = self.frontier.pending[level].take().into_iter().chain(nodes);
+            let mut parents = Vec::new();
+            while let Some(left) = children.next() {
+                match children.next() {
+                    Some(right) => parents.push(hash_nodes(&left, &right)),
+                    None => self.frontier.pending[level] = Some(left),
+                }
+            }
+            if parents.is_empty() {
+                break;
+            }
+
+            let first_parent = self.frontier.leaves_at(level + 1) - parents.len() as u64;
+            self.write_nodes(level + 1, first_parent, &parents.concat())?;
+            if level + 1 == self.frontier.pending.len() {
+                self.frontier.root = Some(parents[0]);
+            }
+            nodes = parents;
+        }
+
+        Ok(())
+    }
+
+    /// Completes the tree as if the rest of the sector was filled with zeros, and moves it to the
+    /// location of tree_d in the cache directory. Returns the config of the tree_d store and
+    /// comm_d.
+    pub fn finalize(mut self) -> Result<(StoreConfig, Commitment)> {
+        info!("incremental tree_d: finalizing {:?}", self.cache_path);
+        ensure!(
+            self.frontier.partial_leaf.is_empty(),
+            "the staged sector ends within a node"
+        );
+
+        let height = self.frontier.pending.len();
+        let mut zero = [0u8; NODE_SIZE];
+        // The node at the boundary between the data and the zero padding of the current level.
+        let mut boundary: Option<Commitment> = None;
+        for level in 0..=height {
+            let complete = self.frontier.leaves_at(level);
+            let total = self.num_leaves() >> level;
+
+            if level > 0 {
+                let below = self.frontier.pending[level - 1];
+                let child_zero = zero;
+                zero = hash_nodes(&zero, &zero);
+                boundary = match (below, boundary) {
+                    (Some(left), right) => Some(hash_nodes(&left, &right.unwrap_or(child_zero))),
+                    (None, Some(left)) => Some(hash_nodes(&left, &child_zero)),
+                    (None, None) => None,
+                };
+            }
+
+            let mut index = complete;
+            if let Some(node) = boundary {
+                self.write_nodes(level, index, &node)?;
+                index += 1;
+            }
+            // Zero leaves are written as well, the file may contain data of an aborted piece.
+            let chunk = zero.repeat(ZERO_FILL_NODES);
+            while index < total {
+                let count = ((total - index) as usize).min(ZERO_FILL_NODES);
+                self.write_nodes(level, index, &chunk[..count * NODE_SIZE])?;
+                index += count as u64;
+            }
+        }
+        self.file.sync_data()?;
+
+        let comm_d = match (self.frontier.root, boundary) {
+            (Some(root), _) | (None, Some(root)) => root,
+            (None, None) => zero,
+        };
+
+        let mut config = StoreConfig::new(&self.cache_path, CacheKey::CommDTree.to_string(), 0);
+        config.size = Some(tree_len(self.num_leaves()) as usize);
+        let tree_d_path = StoreConfig::data_path(&config.path, &config.id);
+        fs::rename(tree_path(&self.cache_path), &tree_d_path)
+            .with_context(|| format!("could not move tree_d to {:?}", tree_d_path))?;
+        fs::remove_file(frontier_path(&self.cache_path))?;
+
+        trace!("incremental tree_d: comm_d {:?}", comm_d);
+        Ok((config, comm_d))
+    }
+}
+
+/// Opens the tree_d that was built while the pieces of the sector staged at `staged_path` were
+/// added, if there is one and it covers exactly the staged data.
+pub(crate) fn open_staged_tree_d(
+    cache_path: &Path,
+    staged_path: &Path,
+    sector_size: SectorSize,
+) -> Result<Option<IncrementalTreeD>> {
+    let tree_d = match IncrementalTreeD::open(cache_path)? {
+        Some(tree_d) => tree_d,
+        None => return Ok(None),
+    };
+
+    let staged_len = fs::metadata(staged_path)?.len();
+    if tree_d.frontier.sector_size != u64::from(sector_size)
+        || u64::from(tree_d.bytes_written()) != staged_len
+    {
+        warn!(
+            "tree_d frontier in {:?} does not match the staged sector ({} bytes), ignoring it",
+            cache_path, staged_len
+        );
+        return Ok(None);
+    }
+    Ok(Some(tree_d))
+}
+
+impl TreeDFrontier {
+    // The number of complete nodes at `level`.
+    fn leaves_at(&self, level: usize) -> u64 {
+        self.leaves >> level
+    }
+}
+
+/// A writer that hashes everything written through it into an [`IncrementalTreeD`].
+#[derive(Debug)]
+pub struct TreeDWriter<'a, W> {
+    inner: W,
+    tree_d: &'a mut IncrementalTreeD,
+}
+
+impl<W: Write> Write for TreeDWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.tree_d
+            .append(&buf[..written])
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Like [`add_piece`], but also hashes the piece into the tree_d of the staged sector, see
+/// [`IncrementalTreeD`]. `piece_lengths` must be the pieces that were previously added through
+/// `tree_d`.
+pub fn add_piece_incremental<R, W>(
+    source: R,
+    target: W,
+    piece_size: UnpaddedBytesAmount,
+    piece_lengths: &[UnpaddedBytesAmount],
+    tree_d: &mut IncrementalTreeD,
+) -> Result<(PieceInfo, UnpaddedBytesAmount)>
+where
+    R: Read,
+    W: Write,
+{
+    let written_before = PaddedBytesAmount::from(sum_piece_bytes_with_alignment(piece_lengths));
+    ensure!(
+        tree_d.bytes_written() == written_before,
+        "tree_d has {:?} bytes, but the previous pieces have {:?}",
+        tree_d.bytes_written(),
+        written_before,
+    );
+
+    let (piece_info, written) =
+        add_piece(source, tree_d.writer(target), piece_size, piece_lengths)?;
+    // `add_piece` flushes its buffer on drop, where errors are lost, hence check that all bytes
+    // reached the tree.
+    ensure!(
+        tree_d.bytes_written() == written_before + PaddedBytesAmount::from(written),
+        "failed to hash the piece into tree_d"
+    );
+    tree_d.persist()?;
+
+    Ok((piece_info, written))
+}
+
+fn hash_nodes(left: &Commitment, right: &Commitment) -> Commitment {
+    let mut node = [0u8; NODE_SIZE];
+    node.copy_from_slice(piece_hash(left, right).as_ref());
+    node
+}
+
+// The number of nodes of a binary tree with `leaves` leaves.
+fn tree_len(leaves: u64) -> u64 {
+    2 * leaves - 1
+}
+
+pub(crate) fn tree_path(cache_path: &Path) -> PathBuf {
+    StoreConfig::data_path(cache_path, TREE_D_FRONTIER)
+}
+
+pub(crate) fn frontier_path(cache_path: &Path) -> PathBuf {
+    cache_path.join(format!("{}.json", TREE_D_FRONTIER))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use filecoin_hashers::Hasher;
+    use rand::{Rng, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+    use storage_proofs_core::merkle::{create_base_merkle_tree, BinaryMerkleTree};
+    use tempfile::tempdir;
+
+    use crate::{
+        api::compute_comm_d,
+        constants::{DefaultPieceHasher, TEST_SEED},
+    };
+
+    #[test]
+    fn test_incremental_tree_d() {
+        let mut rng = XorShiftRng::from_seed(TEST_SEED);
+        let sector_size = SectorSize(4096);
+        let cache_dir = tempdir().expect("failed to create tempdir");
+
+        let mut staged = Vec::new();
+        let mut piece_lengths = Vec::new();
+        let mut piece_infos = Vec::new();
+        for padded_size in [128, 512, 256, 1024] {
+            let piece_size = UnpaddedBytesAmount::from(PaddedBytesAmount(padded_size));
+            let piece: Vec<u8> = (0..u64::from(piece_size)).map(|_| rng.gen()).collect();
+
+            // Re-open the tree for every piece, as if the pieces were added across restarts.
+            let mut tree_d = IncrementalTreeD::open_or_create(cache_dir.path(), sector_size)
+                .expect("failed to open tree_d");
+            let (piece_info, _) = add_piece_incremental(
+                &piece[..],
+                &mut staged,
+                piece_size,
+                &piece_lengths,
+                &mut tree_d,
+            )
+            .expect("failed to add piece");
+            piece_lengths.push(piece_size);
+            piece_infos.push(piece_info);
+        }
+
+        let tree_d = IncrementalTreeD::open(cache_dir.path())
+            .expect("failed to open tree_d")
+            .expect("tree_d frontier missing");
+        assert_eq!(
+            tree_d.bytes_written(),
+            PaddedBytesAmount(staged.len() as u64)
+        );
+        let (config, comm_d) = tree_d.finalize().expect("failed to finalize tree_d");
+        assert!(IncrementalTreeD::open(cache_dir.path())
+            .expect("failed to open tree_d")
+            .is_none());
+
+        assert_eq!(
+            comm_d,
+            compute_comm_d(sector_size, &piece_infos).expect("failed to compute comm_d")
+        );
+
+        // The store matches the one built from the whole sector at once.
+        staged.resize(u64::from(sector_size) as usize, 0);
+        let expected_dir = tempdir().expect("failed to create tempdir");
+        let expected_config =
+            StoreConfig::new(expected_dir.path(), CacheKey::CommDTree.to_string(), 0);
+        let expected = create_base_merkle_tree::<BinaryMerkleTree<DefaultPieceHasher>>(
+            Some(expected_config.clone()),
+            staged.len() / NODE_SIZE,
+            &staged,
+        )
+        .expect("failed to build tree_d");
+        assert_eq!(config.size, Some(expected.len()));
+        let root: <DefaultPieceHasher as Hasher>::Domain = expected.root();
+        assert_eq!(AsRef::<[u8]>::as_ref(&root), &comm_d[..]);
+
+        let tree_d_bytes =
+            fs::read(StoreConfig::data_path(&config.path, &config.id)).expect("failed to read");
+        let expected_bytes = fs::read(StoreConfig::data_path(
+            &expected_config.path,
+            &expected_config.id,
+        ))
+        .expect("failed to read");
+        assert!(tree_d_bytes == expected_bytes, "tree_d stores differ");
+    }
+
+    #[test]
+    fn test_incremental_tree_d_out_of_sync() {
+        let cache_dir = tempdir().expect("failed to create tempdir");
+        let mut tree_d = IncrementalTreeD::open_or_create(cache_dir.path(), SectorSize(2048))
+            .expect("failed to open tree_d");
+        let piece_size = UnpaddedBytesAmount(127);
+        let piece = vec![1u8; 127];
+
+        // The tree has no data yet, but the caller claims a previous piece.
+        assert!(add_piece_incremental(
+            &piece[..],
+            Vec::new(),
+            piece_size,
+            &[piece_size],
+            &mut tree_d,
+        )
+        .is_err());
+    }
+}
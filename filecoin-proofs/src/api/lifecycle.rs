@@ -0,0 +1,518 @@
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use storage_proofs_core::{api_version::ApiFeature, sector::SectorId};
+use storage_proofs_update::constants::TreeRHasher;
+
+use crate::{
+    api::{
+        encode_into, generate_empty_sector_update_proof, generate_synth_proofs, seal_commit_phase1,
+        seal_commit_phase2, seal_pre_commit_phase1, seal_pre_commit_phase2,
+        verify_empty_sector_update_proof, verify_seal,
+    },
+    types::{
+        Commitment, EmptySectorUpdateProof, MerkleTreeTrait, PieceInfo, PoRepConfig, ProverId,
+        RegisteredSealProof, SealCommitPhase1Output, SealPreCommitOutput,
+        SealPreCommitPhase1Output, Ticket,
+    },
+};
+
+/// The states of a sector, in the order they are passed through. A sealed sector is `Proven`,
+/// it can then be updated with SnapDeals, which goes through `UpdateEncoded` to `UpdateProven`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SectorState {
+    /// The data is staged and the sector is ready to be sealed.
+    Staged,
+    /// `seal_pre_commit_phase1` is done.
+    PreCommit1,
+    /// `seal_pre_commit_phase2` is done, `comm_r` is known.
+    PreCommit2,
+    /// The pre-commit was submitted and the sector waits for the interactive seed.
+    WaitSeed,
+    /// `seal_commit_phase1` is done.
+    Commit1,
+    /// `seal_commit_phase2` is done, the proof was not verified yet.
+    Commit2,
+    /// The seal proof was verified.
+    Proven,
+    /// New data was encoded into the sector with `encode_into`.
+    UpdateEncoded,
+    /// The empty sector update proof was generated and verified.
+    UpdateProven,
+}
+
+impl SectorState {
+    /// Returns whether a sector can go from this state to `next`.
+    pub fn can_transition_to(self, next: SectorState) -> bool {
+        use SectorState::*;
+
+        matches!(
+            (self, next),
+            (Staged, PreCommit1)
+                | (PreCommit1, PreCommit2)
+                | (PreCommit2, WaitSeed)
+                | (WaitSeed, Commit1)
+                | (Commit1, Commit2)
+                | (Commit2, Proven)
+                | (Proven, UpdateEncoded)
+                | (UpdateEncoded, UpdateProven)
+        )
+    }
+}
+
+/// The outputs of a SnapDeals update of a sector.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SectorUpdateState {
+    pub replica_path: PathBuf,
+    pub cache_path: PathBuf,
+    pub comm_r_new: Commitment,
+    pub comm_r_last_new: Commitment,
+    pub comm_d_new: Commitment,
+    pub proof: Option<Vec<u8>>,
+}
+
+/// The lifecycle of a single sector, from staged data to a proven sector, optionally followed by
+/// a SnapDeals update.
+///
+/// Each step calls the corresponding API function and moves the sector to the next state, steps
+/// that are called in the wrong state return an error and leave the lifecycle untouched. The
+/// lifecycle can be persisted after each step with [`SectorLifecycle::save`], so that a sealing
+/// service can resume it after a restart with [`SectorLifecycle::load`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SectorLifecycle<Tree: MerkleTreeTrait> {
+    pub registered_proof: RegisteredSealProof,
+    pub sector_id: SectorId,
+    pub prover_id: ProverId,
+    pub ticket: Ticket,
+    pub staged_path: PathBuf,
+    pub cache_path: PathBuf,
+    pub replica_path: PathBuf,
+    pub piece_infos: Vec<PieceInfo>,
+    state: SectorState,
+    #[serde(bound(
+        serialize = "SealPreCommitPhase1Output<Tree>: Serialize",
+        deserialize = "SealPreCommitPhase1Output<Tree>: Deserialize<'de>"
+    ))]
+    pre_commit_phase1_output: Option<SealPreCommitPhase1Output<Tree>>,
+    pre_commit_output: Option<SealPreCommitOutput>,
+    seed: Option<Ticket>,
+    #[serde(bound(
+        serialize = "SealCommitPhase1Output<Tree>: Serialize",
+        deserialize = "SealCommitPhase1Output<Tree>: Deserialize<'de>"
+    ))]
+    commit_phase1_output: Option<SealCommitPhase1Output<Tree>>,
+    proof: Option<Vec<u8>>,
+    update: Option<SectorUpdateState>,
+}
+
+impl<Tree: 'static + MerkleTreeTrait> SectorLifecycle<Tree> {
+    /// Creates the lifecycle of a sector whose data is staged at `staged_path`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        registered_proof: RegisteredSealProof,
+        sector_id: SectorId,
+        prover_id: ProverId,
+        ticket: Ticket,
+        staged_path: PathBuf,
+        cache_path: PathBuf,
+        replica_path: PathBuf,
+        piece_infos: Vec<PieceInfo>,
+    ) -> Self {
+        Self {
+            registered_proof,
+            sector_id,
+            prover_id,
+            ticket,
+            staged_path,
+            cache_path,
+            replica_path,
+            piece_infos,
+            state: SectorState::Staged,
+            pre_commit_phase1_output: None,
+            pre_commit_output: None,
+            seed: None,
+            commit_phase1_output: None,
+            proof: None,
+            update: None,
+        }
+    }
+
+    #[inline]
+    pub fn state(&self) -> SectorState {
+        self.state
+    }
+
+    #[inline]
+    pub fn porep_config(&self) -> PoRepConfig {
+        self.registered_proof.as_porep_config()
+    }
+
+    /// The output of pre-commit phase 2, once the sector reached `PreCommit2`.
+    #[inline]
+    pub fn pre_commit_output(&self) -> Option<&SealPreCommitOutput> {
+        self.pre_commit_output.as_ref()
+    }
+
+    #[inline]
+    pub fn seed(&self) -> Option<Ticket> {
+        self.seed
+    }
+
+    /// The seal proof, once the sector reached `Commit2`.
+    #[inline]
+    pub fn proof(&self) -> Option<&[u8]> {
+        self.proof.as_deref()
+    }
+
+    /// The SnapDeals update, once the sector reached `UpdateEncoded`.
+    #[inline]
+    pub fn update(&self) -> Option<&SectorUpdateState> {
+        self.update.as_ref()
+    }
+
+    fn ensure_transition(&self, next: SectorState) -> Result<()> {
+        ensure!(
+            self.state.can_transition_to(next),
+            "sector {:?} cannot transition from {:?} to {:?}",
+            self.sector_id,
+            self.state,
+            next
+        );
+        Ok(())
+    }
+
+    fn transition(&mut self, next: SectorState) {
+        info!(
+            "sector {:?}: {:?} -> {:?}",
+            self.sector_id, self.state, next
+        );
+        self.state = next;
+    }
+
+    /// Runs `seal_pre_commit_phase1`.
+    pub fn pre_commit_phase1(&mut self) -> Result<()> {
+        self.ensure_transition(SectorState::PreCommit1)?;
+
+        let output = seal_pre_commit_phase1::<_, _, _, Tree>(
+            &self.porep_config(),
+            &self.cache_path,
+            &self.staged_path,
+            &self.replica_path,
+            self.prover_id,
+            self.sector_id,
+            self.ticket,
+            &self.piece_infos,
+        )?;
+
+        self.pre_commit_phase1_output = Some(output);
+        self.transition(SectorState::PreCommit1);
+        Ok(())
+    }
+
+    /// Runs `seal_pre_commit_phase2`, and generates the synthetic proofs if synthetic PoRep is
+    /// used.
+    pub fn pre_commit_phase2(&mut self) -> Result<SealPreCommitOutput> {
+        self.ensure_transition(SectorState::PreCommit2)?;
+
+        let porep_config = self.porep_config();
+        // The phase 1 output is kept until phase 2 and the synthetic proofs succeeded, so that a
+        // failed attempt can be retried without running phase 1 again.
+        let phase1_output = self
+            .pre_commit_phase1_output
+            .clone()
+            .context("missing pre-commit phase 1 output")?;
+        let output = seal_pre_commit_phase2::<_, _, Tree>(
+            &porep_config,
+            phase1_output,
+            &self.cache_path,
+            &self.replica_path,
+        )?;
+
+        if porep_config.feature_enabled(ApiFeature::SyntheticPoRep) {
+            generate_synth_proofs::<_, Tree>(
+                &porep_config,
+                self.cache_path.as_path(),
+                self.replica_path.as_path(),
+                self.prover_id,
+                self.sector_id,
+                self.ticket,
+                output.clone(),
+                &self.piece_infos,
+            )?;
+        }
+
+        self.pre_commit_phase1_output = None;
+        self.pre_commit_output = Some(output.clone());
+        self.transition(SectorState::PreCommit2);
+        Ok(output)
+    }
+
+    /// Marks the pre-commit as submitted, the sector then waits for the seed.
+    pub fn wait_seed(&mut self) -> Result<()> {
+        self.ensure_transition(SectorState::WaitSeed)?;
+        self.transition(SectorState::WaitSeed);
+        Ok(())
+    }
+
+    /// Runs `seal_commit_phase1` with the interactive seed.
+    pub fn commit_phase1(&mut self, seed: Ticket) -> Result<()> {
+        self.ensure_transition(SectorState::Commit1)?;
+
+        let pre_commit_output = self
+            .pre_commit_output
+            .clone()
+            .context("missing pre-commit output")?;
+        let output = seal_commit_phase1::<_, Tree>(
+            &self.porep_config(),
+            self.cache_path.as_path(),
+            self.replica_path.as_path(),
+            self.prover_id,
+            self.sector_id,
+            self.ticket,
+            seed,
+            pre_commit_output,
+            &self.piece_infos,
+        )?;
+
+        self.seed = Some(seed);
+        self.commit_phase1_output = Some(output);
+        self.transition(SectorState::Commit1);
+        Ok(())
+    }
+
+    /// Runs `seal_commit_phase2`.
+    pub fn commit_phase2(&mut self) -> Result<Vec<u8>> {
+        self.ensure_transition(SectorState::Commit2)?;
+
+        // The phase 1 output is kept until phase 2 succeeds, so that it can be retried.
+        let phase1_output = self
+            .commit_phase1_output
+            .clone()
+            .context("missing commit phase 1 output")?;
+        let output = seal_commit_phase2::<Tree>(
+            &self.porep_config(),
+            phase1_output,
+            self.prover_id,
+            self.sector_id,
+        )?;
+
+        self.commit_phase1_output = None;
+        self.proof = Some(output.proof.clone());
+        self.transition(SectorState::Commit2);
+        Ok(output.proof)
+    }
+
+    /// Verifies the seal proof with `verify_seal`, the sector is proven if it is valid.
+    pub fn verify(&mut self) -> Result<bool> {
+        self.ensure_transition(SectorState::Proven)?;
+
+        let pre_commit_output = self
+            .pre_commit_output
+            .as_ref()
+            .context("missing pre-commit output")?;
+        let valid = verify_seal::<Tree>(
+            &self.porep_config(),
+            pre_commit_output.comm_r,
+            pre_commit_output.comm_d,
+            self.prover_id,
+            self.sector_id,
+            self.ticket,
+            self.seed.context("missing seed")?,
+            self.proof.as_deref().context("missing proof")?,
+        )?;
+
+        if valid {
+            self.transition(SectorState::Proven);
+        }
+        Ok(valid)
+    }
+
+    /// Writes the lifecycle to `path`. The file is replaced atomically, so that a crash while
+    /// saving doesn't lose the previous state.
+    pub fn save(&self, path: &Path) -> Result<()>
+    where
+        Self: Serialize,
+    {
+        // Append to the whole file name, `with_extension` would map `a.json` and `a.bin` to the
+        // same temporary file.
+        let mut tmp_name = path
+            .file_name()
+            .with_context(|| format!("invalid lifecycle path {:?}", path))?
+            .to_os_string();
+        tmp_name.push(".tmp");
+        let tmp_path = path.with_file_name(tmp_name);
+        {
+            let file = File::create(&tmp_path)
+                .with_context(|| format!("could not create file {:?}", tmp_path))?;
+            let mut writer = BufWriter::new(file);
+            serde_json::to_writer(&mut writer, self)?;
+            writer
+                .into_inner()
+                .map_err(|err| err.into_error())?
+                .sync_all()?;
+        }
+        fs::rename(&tmp_path, path)
+            .with_context(|| format!("could not move {:?} to {:?}", tmp_path, path))?;
+
+        Ok(())
+    }
+
+    /// Reads a lifecycle that was written with [`SectorLifecycle::save`].
+    pub fn load(path: &Path) -> Result<Self>
+    where
+        Self: for<'de> Deserialize<'de>,
+    {
+        let file = File::open(path).with_context(|| format!("could not open file {:?}", path))?;
+        let lifecycle = serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("could not read sector lifecycle from {:?}", path))?;
+
+        Ok(lifecycle)
+    }
+}
+
+impl<Tree: 'static + MerkleTreeTrait<Hasher = TreeRHasher>> SectorLifecycle<Tree> {
+    /// Encodes the data staged at `staged_data_path` into a new replica with `encode_into`.
+    pub fn encode_update(
+        &mut self,
+        new_replica_path: PathBuf,
+        new_cache_path: PathBuf,
+        staged_data_path: &Path,
+        piece_infos: &[PieceInfo],
+    ) -> Result<()> {
+        self.ensure_transition(SectorState::UpdateEncoded)?;
+
+        let encoded = encode_into::<Tree>(
+            &self.porep_config(),
+            &new_replica_path,
+            &new_cache_path,
+            &self.replica_path,
+            &self.cache_path,
+            staged_data_path,
+            piece_infos,
+        )?;
+
+        self.update = Some(SectorUpdateState {
+            replica_path: new_replica_path,
+            cache_path: new_cache_path,
+            comm_r_new: encoded.comm_r_new,
+            comm_r_last_new: encoded.comm_r_last_new,
+            comm_d_new: encoded.comm_d_new,
+            proof: None,
+        });
+        self.transition(SectorState::UpdateEncoded);
+        Ok(())
+    }
+
+    /// Generates and verifies the empty sector update proof. The sector is updated if the proof
+    /// is valid.
+    pub fn prove_update(&mut self) -> Result<EmptySectorUpdateProof> {
+        self.ensure_transition(SectorState::UpdateProven)?;
+
+        let porep_config = self.porep_config();
+        let comm_r_old = self
+            .pre_commit_output
+            .as_ref()
+            .context("missing pre-commit output")?
+            .comm_r;
+        let update = self.update.as_ref().context("missing update")?;
+
+        let proof = generate_empty_sector_update_proof::<Tree>(
+            &porep_config,
+            comm_r_old,
+            update.comm_r_new,
+            update.comm_d_new,
+            &self.replica_path,
+            &self.cache_path,
+            &update.replica_path,
+            &update.cache_path,
+        )?;
+        let valid = verify_empty_sector_update_proof::<Tree>(
+            &porep_config,
+            &proof.0,
+            comm_r_old,
+            update.comm_r_new,
+            update.comm_d_new,
+        )?;
+        if !valid {
+            bail!("invalid empty sector update proof for {:?}", self.sector_id);
+        }
+
+        if let Some(update) = self.update.as_mut() {
+            update.proof = Some(proof.0.clone());
+        }
+        self.transition(SectorState::UpdateProven);
+        Ok(proof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::tempdir;
+
+    use crate::constants::SectorShape2KiB;
+
+    #[test]
+    fn test_sector_state_transitions() {
+        use SectorState::*;
+
+        let states = [
+            Staged,
+            PreCommit1,
+            PreCommit2,
+            WaitSeed,
+            Commit1,
+            Commit2,
+            Proven,
+            UpdateEncoded,
+            UpdateProven,
+        ];
+        for (i, from) in states.iter().enumerate() {
+            for (j, to) in states.iter().enumerate() {
+                assert_eq!(
+                    from.can_transition_to(*to),
+                    j == i + 1,
+                    "{:?} -> {:?}",
+                    from,
+                    to
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_sector_lifecycle_persistence() {
+        let dir = tempdir().expect("failed to create temp dir");
+        let mut lifecycle = SectorLifecycle::<SectorShape2KiB>::new(
+            RegisteredSealProof::StackedDrg2KiBV1_1,
+            SectorId::from(7),
+            ProverId::from([1; 32]),
+            Ticket::from([2; 32]),
+            dir.path().join("staged"),
+            dir.path().join("cache"),
+            dir.path().join("sealed"),
+            Vec::new(),
+        );
+
+        // Steps that don't match the state must fail without changing it.
+        assert!(lifecycle.commit_phase1(Ticket::from([3; 32])).is_err());
+        assert!(lifecycle.wait_seed().is_err());
+        assert_eq!(lifecycle.state(), SectorState::Staged);
+
+        let path = dir.path().join("lifecycle.json");
+        lifecycle.save(&path).expect("failed to save lifecycle");
+        assert!(!dir.path().join("lifecycle.json.tmp").exists());
+        let loaded =
+            SectorLifecycle::<SectorShape2KiB>::load(&path).expect("failed to load lifecycle");
+        assert_eq!(loaded.state(), SectorState::Staged);
+        assert_eq!(loaded.registered_proof, lifecycle.registered_proof);
+        assert_eq!(loaded.sector_id, lifecycle.sector_id);
+        assert_eq!(loaded.ticket, lifecycle.ticket);
+        assert_eq!(loaded.replica_path, lifecycle.replica_path);
+    }
+}
@@ -4,9 +4,9 @@ use std::io::{self, BufReader, BufWriter, Read, Write};
 use std::path::{Path, PathBuf};
 
 use anyhow::{ensure, Context, Result};
-use filecoin_hashers::Hasher;
+use filecoin_hashers::{Domain, Hasher};
 use fr32::{write_unpadded, Fr32Reader};
-use log::{info, trace};
+use log::{info, trace, warn};
 use memmap2::MmapOptions;
 use merkletree::store::{DiskStore, LevelCacheStore, StoreConfig};
 use storage_proofs_core::{
@@ -34,17 +34,47 @@ use crate::{
     },
 };
 
+mod cache_gc;
+mod capabilities;
+mod circuit_info;
+mod commit_phase1_chunks;
 mod fake_seal;
+mod incremental_tree_d;
+mod lifecycle;
+mod post_layout;
 mod post_util;
+mod proof_archive;
+mod replica_id;
+mod scratch_space;
 mod seal;
+mod seal_status;
+mod sector_archive;
+mod sector_check;
+mod sector_manifest;
+mod unseal_cache;
 mod update;
 mod util;
 mod window_post;
 mod winning_post;
 
+pub use cache_gc::*;
+pub use capabilities::*;
+pub use circuit_info::*;
+pub use commit_phase1_chunks::*;
 pub use fake_seal::*;
+pub use incremental_tree_d::*;
+pub use lifecycle::*;
+pub use post_layout::*;
 pub use post_util::*;
+pub use proof_archive::*;
+pub use replica_id::*;
+pub use scratch_space::*;
 pub use seal::*;
+pub use seal_status::*;
+pub use sector_archive::*;
+pub use sector_check::*;
+pub use sector_manifest::*;
+pub use unseal_cache::*;
 pub use update::*;
 pub use util::*;
 pub use window_post::*;
@@ -108,6 +138,19 @@ pub fn clear_synthetic_proofs<Tree>(cache_dir: &Path) -> Result<()> {
     result
 }
 
+/// Discards the persisted tree c of a sector after precommit, in order to save disk space. The
+/// tree is then regenerated from the labels during `seal_commit_phase1` (and removed afterwards),
+/// which trades CPU time for disk space.
+pub fn clear_tree_c(cache_dir: &Path) -> Result<()> {
+    info!("clear_tree_c:start");
+
+    let result = stacked::clear_tree_c(cache_dir);
+
+    info!("clear_tree_c:finish");
+
+    result
+}
+
 /// Unseals the sector at `sealed_path` and returns the bytes for a piece
 /// whose first (unpadded) byte begins at `offset` and ends at `offset` plus
 /// `num_bytes`, inclusive. Note that the entire sector is unsealed each time
@@ -205,9 +248,9 @@ where
         as_safe_commitment::<<DefaultPieceHasher as Hasher>::Domain, _>(&comm_d, "comm_d")?;
 
     let replica_id = generate_replica_id::<Tree::Hasher, _>(
-        &prover_id,
+        prover_id.as_bytes(),
         sector_id.into(),
-        &ticket,
+        ticket.as_bytes(),
         comm_d,
         &porep_config.porep_id,
     );
@@ -272,9 +315,9 @@ where
         as_safe_commitment::<<DefaultPieceHasher as Hasher>::Domain, _>(&comm_d, "comm_d")?;
 
     let replica_id = generate_replica_id::<Tree::Hasher, _>(
-        &prover_id,
+        prover_id.as_bytes(),
         sector_id.into(),
-        &ticket,
+        ticket.as_bytes(),
         comm_d,
         &porep_config.porep_id,
     );
@@ -299,6 +342,117 @@ where
     result
 }
 
+/// Like [`unseal_range`], but consults `unseal_cache` first. If all windows of the requested range
+/// are cached, `sealed_sector` is not read at all. Otherwise the entire sector is unsealed and the
+/// windows of the requested range are added to the cache.
+#[allow(clippy::too_many_arguments)]
+pub fn unseal_range_cached<P, R, W, Tree>(
+    porep_config: &PoRepConfig,
+    cache_path: P,
+    mut sealed_sector: R,
+    mut unsealed_output: W,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    comm_d: Commitment,
+    ticket: Ticket,
+    offset: UnpaddedByteIndex,
+    num_bytes: UnpaddedBytesAmount,
+    unseal_cache: &UnsealCache,
+) -> Result<UnpaddedBytesAmount>
+where
+    P: Into<PathBuf> + AsRef<Path>,
+    R: Read,
+    W: Write,
+    Tree: 'static + MerkleTreeTrait,
+{
+    info!("unseal_range_cached:start");
+    ensure!(comm_d != [0; 32], "Invalid all zero commitment (comm_d)");
+
+    let comm_d =
+        as_safe_commitment::<<DefaultPieceHasher as Hasher>::Domain, _>(&comm_d, "comm_d")?;
+
+    let replica_id = generate_replica_id::<Tree::Hasher, _>(
+        prover_id.as_bytes(),
+        sector_id.into(),
+        ticket.as_bytes(),
+        comm_d,
+        &porep_config.porep_id,
+    );
+    let mut replica_id_bytes = [0u8; 32];
+    replica_id.write_bytes(&mut replica_id_bytes)?;
+
+    let sector_size = u64::from(porep_config.sector_size);
+    let window_size = unseal_cache.window_size().min(sector_size);
+    let start = u64::from(PaddedBytesAmount::from(UnpaddedBytesAmount::from(offset)));
+    let end = start + u64::from(PaddedBytesAmount::from(num_bytes));
+    ensure!(end <= sector_size, "range exceeds the sector size");
+    let windows = start / window_size..(end + window_size - 1) / window_size;
+
+    let mut cached = Vec::new();
+    for window in windows.clone() {
+        match unseal_cache.get(&replica_id_bytes, window) {
+            Ok(Some(data)) if data.len() as u64 == window_size => cached.extend(data),
+            Ok(_) => break,
+            Err(err) => {
+                warn!("could not read unseal cache window {}: {:#}", window, err);
+                break;
+            }
+        }
+    }
+
+    let first = windows.start * window_size;
+    let padded = if cached.len() as u64 == (windows.end - windows.start) * window_size {
+        trace!("unseal_range_cached: serving {:?} from the cache", windows);
+        cached
+    } else {
+        let mut data = Vec::new();
+        sealed_sector.read_to_end(&mut data)?;
+        decode_sector::<_, Tree>(porep_config, cache_path, &mut data, replica_id)?;
+
+        // The sector is unsealed even if it can't be cached.
+        for window in windows.clone() {
+            let window_start = (window * window_size) as usize;
+            let window_data = &data[window_start..window_start + window_size as usize];
+            if let Err(err) = unseal_cache.insert(&replica_id_bytes, window, window_data) {
+                warn!("could not cache unsealed window {}: {:#}", window, err);
+            }
+        }
+        data.truncate((windows.end * window_size) as usize);
+        data.drain(..first as usize);
+        data
+    };
+
+    let unsealed = &padded[(start - first) as usize..(end - first) as usize];
+    let written = write_unpadded(unsealed, &mut unsealed_output, 0, num_bytes.into())
+        .context("write_unpadded failed")?;
+
+    info!("unseal_range_cached:finish");
+    Ok(UnpaddedBytesAmount(written as u64))
+}
+
+/// Decodes the sealed sector `data` in place.
+fn decode_sector<P, Tree>(
+    porep_config: &PoRepConfig,
+    cache_path: P,
+    data: &mut [u8],
+    replica_id: <Tree::Hasher as Hasher>::Domain,
+) -> Result<()>
+where
+    P: Into<PathBuf> + AsRef<Path>,
+    Tree: 'static + MerkleTreeTrait,
+{
+    let config = StoreConfig::new(cache_path.as_ref(), CacheKey::CommDTree.to_string(), 0);
+    let pp: PublicParams<Tree> = public_params(porep_config)?;
+
+    StackedDrg::<Tree, DefaultPieceHasher>::extract_and_invert_transform_layers(
+        &pp.graph,
+        &pp.layer_challenges,
+        &replica_id,
+        data,
+        config,
+    )
+}
+
 /// Unseals the sector read from `sealed_sector` and returns the bytes for a
 /// piece whose first (unpadded) byte begins at `offset` and ends at `offset`
 /// plus `num_bytes`, inclusive. Note that the entire sector is unsealed each
@@ -333,19 +487,10 @@ where
 {
     trace!("unseal_range_inner:start");
 
-    let config = StoreConfig::new(cache_path.as_ref(), CacheKey::CommDTree.to_string(), 0);
-    let pp: PublicParams<Tree> = public_params(porep_config)?;
-
     let offset_padded: PaddedBytesAmount = UnpaddedBytesAmount::from(offset).into();
     let num_bytes_padded: PaddedBytesAmount = num_bytes.into();
 
-    StackedDrg::<Tree, DefaultPieceHasher>::extract_and_invert_transform_layers(
-        &pp.graph,
-        &pp.layer_challenges,
-        &replica_id,
-        data,
-        config,
-    )?;
+    decode_sector::<_, Tree>(porep_config, cache_path, data, replica_id)?;
     let start: usize = offset_padded.into();
     let end = start + usize::from(num_bytes_padded);
     let unsealed = &data[start..end];
@@ -585,6 +730,12 @@ fn verify_store(config: &StoreConfig, arity: usize, required_configs: usize) ->
     Ok(())
 }
 
+// Checks if a DiskStore specified by a config (or any of its split stores) exists on disk.
+fn store_exists(config: &StoreConfig) -> bool {
+    StoreConfig::data_path(&config.path, &config.id).exists()
+        || StoreConfig::data_path(&config.path, &format!("{}-0", config.id)).exists()
+}
+
 // Verifies if a LevelCacheStore specified by a config is consistent.
 fn verify_level_cache_store<Tree: MerkleTreeTrait>(config: &StoreConfig) -> Result<()> {
     let store_path = StoreConfig::data_path(&config.path, &config.id);
@@ -697,11 +848,35 @@ where
     );
     config.path = cache_path.as_ref().into();
 
-    let result = verify_store(
-        &config,
-        <DefaultBinaryTree as MerkleTreeTrait>::Arity::to_usize(),
-        get_base_tree_count::<Tree>(),
-    );
+    // tree_d is not built if comm_d was computed from the pieces, see
+    // `seal_pre_commit_phase1_without_tree_d`. The mode must also be recorded in the cache, it is
+    // all that commit phase 1 has to go on.
+    let result = if seal_precommit_phase1_output.without_tree_d {
+        let marker = cache_path.as_ref().join(WITHOUT_TREE_D);
+        ensure!(
+            marker.exists(),
+            "Missing marker of a sector without tree_d: {}",
+            marker.display()
+        );
+        Ok(())
+    } else {
+        verify_store(
+            &config,
+            <DefaultBinaryTree as MerkleTreeTrait>::Arity::to_usize(),
+            get_base_tree_count::<Tree>(),
+        )
+    };
+
+    // tree_c is not built again if it was built in phase1.
+    if seal_precommit_phase1_output.comm_c.is_some() {
+        let tree_c_config =
+            StoreConfig::new(cache_path.as_ref(), CacheKey::CommCTree.to_string(), 0);
+        ensure!(
+            store_exists(&tree_c_config),
+            "Missing tree_c built in phase1: {}",
+            StoreConfig::data_path(&tree_c_config.path, &tree_c_config.id).display()
+        );
+    }
 
     info!("validate_cache_for_precommit_phase2:finish");
     result
@@ -739,23 +914,36 @@ where
     // Make sure p_aux exists and is valid.
     let _ = util::get_p_aux::<Tree>(cache)?;
 
-    let t_aux = util::get_t_aux::<Tree>(cache, metadata.len())?;
+    // Without a porep config, the number of layers t_aux is reconstructed with is looked up by
+    // the sector size.
+    #[cfg(feature = "fixed-rows-to-discard")]
+    let layers = crate::constants::ProtocolConstants::from_globals().layers(metadata.len())?;
+    #[cfg(not(feature = "fixed-rows-to-discard"))]
+    let layers = 0;
+    let t_aux = util::get_t_aux::<Tree>(cache, metadata.len(), layers)?;
 
     // Verify all stores/labels within the Labels object.
     let cache = cache_path.as_ref().to_path_buf();
     t_aux.labels.verify_stores(verify_store, &cache)?;
 
-    // Verify each tree disk store.
-    verify_store(
-        &t_aux.tree_d_config,
-        <DefaultBinaryTree as MerkleTreeTrait>::Arity::to_usize(),
-        get_base_tree_count::<Tree>(),
-    )?;
-    verify_store(
-        &t_aux.tree_c_config,
-        <DefaultOctTree as MerkleTreeTrait>::Arity::to_usize(),
-        get_base_tree_count::<Tree>(),
-    )?;
+    // Verify each tree disk store. Tree d is not built if the sector was marked so in pre-commit
+    // phase 1, the data is then opened from the staged sector, see
+    // `seal_commit_phase1_with_staged_data`.
+    if !cache.join(WITHOUT_TREE_D).exists() {
+        verify_store(
+            &t_aux.tree_d_config,
+            <DefaultBinaryTree as MerkleTreeTrait>::Arity::to_usize(),
+            get_base_tree_count::<Tree>(),
+        )?;
+    }
+    // Tree c may have been cleared, it is then regenerated from the (verified) labels.
+    if store_exists(&t_aux.tree_c_config) {
+        verify_store(
+            &t_aux.tree_c_config,
+            <DefaultOctTree as MerkleTreeTrait>::Arity::to_usize(),
+            get_base_tree_count::<Tree>(),
+        )?;
+    }
     verify_level_cache_store::<DefaultOctTree>(&t_aux.tree_r_last_config)?;
 
     info!("validate_cache_for_commit:finish");
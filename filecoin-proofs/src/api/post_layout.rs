@@ -0,0 +1,262 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{bail, ensure, Context, Result};
+use filecoin_hashers::Hasher;
+use log::info;
+use storage_proofs_core::{
+    merkle::{MerkleProof, MerkleProofTrait, MerkleTreeTrait},
+    sector::SectorId,
+};
+use storage_proofs_post::fallback::SectorProof;
+
+use crate::{
+    api::generate_fallback_sector_challenges_with_domain,
+    types::{
+        ChallengeDomain, ChallengeSeed, FallbackPoStSectorProof, PoStConfig, PoStType, ProverId,
+        VanillaProof,
+    },
+};
+
+type InclusionProof<Tree> = MerkleProof<
+    <Tree as MerkleTreeTrait>::Hasher,
+    <Tree as MerkleTreeTrait>::Arity,
+    <Tree as MerkleTreeTrait>::SubTreeArity,
+    <Tree as MerkleTreeTrait>::TopTreeArity,
+>;
+
+struct ConvertedSector<Tree: MerkleTreeTrait> {
+    comm_r: Option<<Tree::Hasher as Hasher>::Domain>,
+    comm_c: Option<<Tree::Hasher as Hasher>::Domain>,
+    comm_r_last: Option<<Tree::Hasher as Hasher>::Domain>,
+    /// The leaf challenges of the sector in the new layout.
+    challenges: Vec<u64>,
+    /// The inclusion proofs of the challenges, `None` while a challenge isn't proven yet.
+    inclusion_proofs: Vec<Option<InclusionProof<Tree>>>,
+}
+
+impl<Tree: MerkleTreeTrait> ConvertedSector<Tree> {
+    /// Takes the inclusion proofs of `proof` that prove challenges of the new layout. Returns
+    /// the number of challenges that were proven by them.
+    fn add_proof(
+        &mut self,
+        sector_id: SectorId,
+        proof: &FallbackPoStSectorProof<Tree>,
+    ) -> Result<usize> {
+        ensure!(
+            *self.comm_r.get_or_insert(proof.comm_r) == proof.comm_r,
+            "vanilla proofs of sector {} have different comm_r",
+            sector_id
+        );
+
+        let mut added = 0;
+        for sector_proof in &proof.vanilla_proof.sectors {
+            ensure!(
+                *self.comm_c.get_or_insert(sector_proof.comm_c) == sector_proof.comm_c
+                    && *self.comm_r_last.get_or_insert(sector_proof.comm_r_last)
+                        == sector_proof.comm_r_last,
+                "vanilla proofs of sector {} have different comm_c or comm_r_last",
+                sector_id
+            );
+
+            let by_leaf: HashMap<u64, &InclusionProof<Tree>> = sector_proof
+                .inclusion_proofs
+                .iter()
+                .map(|inclusion_proof| (inclusion_proof.path_index() as u64, inclusion_proof))
+                .collect();
+            for (challenge, inclusion_proof) in
+                self.challenges.iter().zip(self.inclusion_proofs.iter_mut())
+            {
+                if inclusion_proof.is_some() {
+                    continue;
+                }
+                if let Some(leaf_proof) = by_leaf.get(challenge) {
+                    ensure!(
+                        leaf_proof.root() == sector_proof.comm_r_last,
+                        "inclusion proof of sector {} does not match its comm_r_last",
+                        sector_id
+                    );
+                    *inclusion_proof = Some((*leaf_proof).clone());
+                    added += 1;
+                }
+            }
+        }
+
+        Ok(added)
+    }
+
+    fn missing_challenges(&self) -> Vec<u64> {
+        self.challenges
+            .iter()
+            .zip(&self.inclusion_proofs)
+            .filter(|(_, inclusion_proof)| inclusion_proof.is_none())
+            .map(|(challenge, _)| *challenge)
+            .collect()
+    }
+}
+
+/// Window PoSt vanilla proofs regrouped for another partition layout, see
+/// [`convert_window_post_vanilla_proofs`].
+pub struct ConvertedVanillaProofs<Tree: MerkleTreeTrait> {
+    sectors: BTreeMap<SectorId, ConvertedSector<Tree>>,
+    reused: usize,
+}
+
+impl<Tree: MerkleTreeTrait> ConvertedVanillaProofs<Tree> {
+    /// The number of challenges of the new layout that were proven by the original proofs.
+    pub fn reused_challenge_count(&self) -> usize {
+        self.reused
+    }
+
+    /// The challenges of the new layout that aren't proven yet, by sector. They can be proven
+    /// with [`generate_single_vanilla_proof`] and added with
+    /// [`ConvertedVanillaProofs::add_proof`].
+    ///
+    /// [`generate_single_vanilla_proof`]: crate::generate_single_vanilla_proof
+    pub fn missing_challenges(&self) -> BTreeMap<SectorId, Vec<u64>> {
+        self.sectors
+            .iter()
+            .map(|(sector_id, sector)| (*sector_id, sector.missing_challenges()))
+            .filter(|(_, challenges)| !challenges.is_empty())
+            .collect()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.sectors
+            .values()
+            .all(|sector| sector.inclusion_proofs.iter().all(Option::is_some))
+    }
+
+    /// Adds a vanilla proof of (some of) the missing challenges of a sector.
+    pub fn add_proof(&mut self, proof: &FallbackPoStSectorProof<Tree>) -> Result<()> {
+        let sector = self
+            .sectors
+            .get_mut(&proof.sector_id)
+            .with_context(|| format!("sector {} is not part of the PoSt", proof.sector_id))?;
+        sector.add_proof(proof.sector_id, proof)?;
+
+        Ok(())
+    }
+
+    /// Returns the vanilla proofs of the new layout, one per sector, as expected by
+    /// [`generate_window_post_with_vanilla`]. All challenges need to be proven.
+    ///
+    /// [`generate_window_post_with_vanilla`]: crate::generate_window_post_with_vanilla
+    pub fn into_vanilla_proofs(self) -> Result<Vec<FallbackPoStSectorProof<Tree>>> {
+        self.sectors
+            .into_iter()
+            .map(|(sector_id, sector)| {
+                let missing = sector.missing_challenges();
+                ensure!(
+                    missing.is_empty(),
+                    "sector {} is missing proofs of challenges {:?}",
+                    sector_id,
+                    missing
+                );
+                let (comm_r, comm_c, comm_r_last) =
+                    match (sector.comm_r, sector.comm_c, sector.comm_r_last) {
+                        (Some(comm_r), Some(comm_c), Some(comm_r_last)) => {
+                            (comm_r, comm_c, comm_r_last)
+                        }
+                        _ => bail!("no vanilla proof of sector {} was given", sector_id),
+                    };
+
+                Ok(FallbackPoStSectorProof {
+                    sector_id,
+                    comm_r,
+                    vanilla_proof: VanillaProof::<Tree> {
+                        sectors: vec![SectorProof {
+                            inclusion_proofs: sector
+                                .inclusion_proofs
+                                .into_iter()
+                                .map(|inclusion_proof| inclusion_proof.expect("prechecked"))
+                                .collect(),
+                            comm_c,
+                            comm_r_last,
+                        }],
+                    },
+                })
+            })
+            .collect()
+    }
+}
+
+/// Regroups the vanilla proofs of a window PoSt for the partition layout of `post_config`.
+///
+/// The leaf challenges of a sector only depend on the randomness, the sector and the challenge
+/// index. So if only the number of sectors per partition changes, all challenges are covered by
+/// the given proofs. If the number of challenges per sector changes, the challenges that aren't
+/// covered are reported by [`ConvertedVanillaProofs::missing_challenges`], only those need to be
+/// proven. `sectors` are all sectors of the PoSt, `vanilla_proofs` may have been generated for
+/// any layout.
+pub fn convert_window_post_vanilla_proofs<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomness: &ChallengeSeed,
+    prover_id: ProverId,
+    sectors: &[SectorId],
+    vanilla_proofs: &[FallbackPoStSectorProof<Tree>],
+) -> Result<ConvertedVanillaProofs<Tree>> {
+    convert_window_post_vanilla_proofs_with_domain::<Tree>(
+        post_config,
+        randomness,
+        prover_id,
+        sectors,
+        vanilla_proofs,
+        &ChallengeDomain::Mainnet,
+    )
+}
+
+/// Like [`convert_window_post_vanilla_proofs`], with the challenges derived in `challenge_domain`.
+pub fn convert_window_post_vanilla_proofs_with_domain<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomness: &ChallengeSeed,
+    prover_id: ProverId,
+    sectors: &[SectorId],
+    vanilla_proofs: &[FallbackPoStSectorProof<Tree>],
+    challenge_domain: &ChallengeDomain,
+) -> Result<ConvertedVanillaProofs<Tree>> {
+    info!("convert_window_post_vanilla_proofs:start");
+    ensure!(
+        post_config.typ == PoStType::Window,
+        "invalid post config type"
+    );
+    ensure!(!sectors.is_empty(), "empty sector set is invalid");
+
+    let challenges = generate_fallback_sector_challenges_with_domain::<Tree>(
+        post_config,
+        randomness,
+        sectors,
+        prover_id,
+        challenge_domain,
+    )?;
+    let mut converted = ConvertedVanillaProofs {
+        sectors: challenges
+            .into_iter()
+            .map(|(sector_id, challenges)| {
+                let sector = ConvertedSector {
+                    comm_r: None,
+                    comm_c: None,
+                    comm_r_last: None,
+                    inclusion_proofs: vec![None; challenges.len()],
+                    challenges,
+                };
+                (sector_id, sector)
+            })
+            .collect(),
+        reused: 0,
+    };
+
+    for proof in vanilla_proofs {
+        let sector = converted
+            .sectors
+            .get_mut(&proof.sector_id)
+            .with_context(|| format!("sector {} is not part of the PoSt", proof.sector_id))?;
+        converted.reused += sector.add_proof(proof.sector_id, proof)?;
+    }
+
+    info!(
+        "convert_window_post_vanilla_proofs:finish: {} challenges reused",
+        converted.reused
+    );
+
+    Ok(converted)
+}
@@ -1,18 +1,28 @@
 use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 use anyhow::{anyhow, ensure, Context, Result};
 use filecoin_hashers::Hasher;
 use log::{debug, info};
-use storage_proofs_core::{merkle::MerkleTreeTrait, proof::ProofScheme, sector::SectorId};
+use rayon::{
+    prelude::{IntoParallelRefIterator, ParallelIterator},
+    ThreadPoolBuilder,
+};
+use storage_proofs_core::{
+    merkle::{BackendLCTree, MerkleProof, MerkleTreeTrait, MerkleTreeWrapper, NodeBackend},
+    proof::ProofScheme,
+    sector::SectorId,
+};
 use storage_proofs_post::fallback::{
     self, generate_leaf_challenge, get_challenge_index, FallbackPoSt, SectorProof,
 };
 
 use crate::{
-    api::as_safe_commitment,
+    api::{as_safe_commitment, generate_winning_post_sector_challenge_with_domain},
     types::{
-        ChallengeSeed, FallbackPoStSectorProof, PoStConfig, PrivateReplicaInfo, ProverId,
-        VanillaProof,
+        ChallengeDomain, ChallengeSeed, FallbackPoStSectorProof, PoStConfig, PrefetchedReplica,
+        PrivateReplicaInfo, ProverId, VanillaProof,
     },
     PartitionSnarkProof, PoStType, SnarkProof, SINGLE_PARTITION_PROOF_LEN,
 };
@@ -24,6 +34,23 @@ pub fn generate_fallback_sector_challenges<Tree: 'static + MerkleTreeTrait>(
     randomness: &ChallengeSeed,
     pub_sectors: &[SectorId],
     _prover_id: ProverId,
+) -> Result<BTreeMap<SectorId, Vec<u64>>> {
+    generate_fallback_sector_challenges_with_domain::<Tree>(
+        post_config,
+        randomness,
+        pub_sectors,
+        _prover_id,
+        &ChallengeDomain::Mainnet,
+    )
+}
+
+/// Like [`generate_fallback_sector_challenges`], with the challenges derived in `challenge_domain`.
+pub fn generate_fallback_sector_challenges_with_domain<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomness: &ChallengeSeed,
+    pub_sectors: &[SectorId],
+    _prover_id: ProverId,
+    challenge_domain: &ChallengeDomain,
 ) -> Result<BTreeMap<SectorId, Vec<u64>>> {
     info!("generate_sector_challenges:start");
     ensure!(
@@ -31,16 +58,6 @@ pub fn generate_fallback_sector_challenges<Tree: 'static + MerkleTreeTrait>(
         "invalid post config type"
     );
 
-    let randomness_safe: <Tree::Hasher as Hasher>::Domain =
-        as_safe_commitment(randomness, "randomness")?;
-
-    let public_params = fallback::PublicParams {
-        sector_size: u64::from(post_config.sector_size),
-        challenge_count: post_config.challenge_count,
-        sector_count: post_config.sector_count,
-        api_version: post_config.api_version,
-    };
-
     let mut sector_challenges: BTreeMap<SectorId, Vec<u64>> = BTreeMap::new();
 
     let num_sectors_per_chunk = post_config.sector_count;
@@ -57,38 +74,234 @@ pub fn generate_fallback_sector_challenges<Tree: 'static + MerkleTreeTrait>(
             .nth(partition_index)
             .ok_or_else(|| anyhow!("invalid number of sectors/partition index"))?;
 
-        for (i, sector) in sectors.iter().enumerate() {
-            let mut challenges = Vec::new();
+        sector_challenges.extend(generate_sector_challenges_from::<Tree>(
+            post_config,
+            randomness,
+            sectors,
+            partition_index * num_sectors_per_chunk,
+            challenge_domain,
+        )?);
+    }
+
+    info!("generate_sector_challenges:finish");
+
+    Ok(sector_challenges)
+}
+
+/// Generates the challenges of `sectors`, which are a consecutive run of the challenged sectors
+/// starting at index `first_sector_index`.
+pub(crate) fn generate_sector_challenges_from<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomness: &ChallengeSeed,
+    sectors: &[SectorId],
+    first_sector_index: usize,
+    challenge_domain: &ChallengeDomain,
+) -> Result<BTreeMap<SectorId, Vec<u64>>> {
+    let randomness_safe: <Tree::Hasher as Hasher>::Domain =
+        as_safe_commitment(randomness, "randomness")?;
+
+    let public_params = fallback::PublicParams {
+        sector_size: u64::from(post_config.sector_size),
+        challenge_count: post_config.challenge_count,
+        sector_count: post_config.sector_count,
+        api_version: post_config.api_version,
+        challenge_domain: *challenge_domain,
+    };
 
-            for n in 0..post_config.challenge_count {
-                let sector_index = match post_config.typ {
-                    PoStType::Window => partition_index * num_sectors_per_chunk + i,
-                    PoStType::Winning => partition_index * post_config.sector_count + i,
-                };
+    let mut sector_challenges: BTreeMap<SectorId, Vec<u64>> = BTreeMap::new();
+    for (i, sector) in sectors.iter().enumerate() {
+        let challenges = (0..post_config.challenge_count)
+            .map(|n| {
                 let challenge_index = get_challenge_index(
                     post_config.api_version,
-                    sector_index,
+                    first_sector_index + i,
                     post_config.challenge_count,
                     n,
                 );
-                let challenged_leaf = generate_leaf_challenge(
+                generate_leaf_challenge(
                     &public_params,
                     randomness_safe,
                     u64::from(*sector),
                     challenge_index,
-                );
-                challenges.push(challenged_leaf);
-            }
+                )
+            })
+            .collect();
 
-            sector_challenges.insert(*sector, challenges);
-        }
+        sector_challenges.insert(*sector, challenges);
     }
 
-    info!("generate_sector_challenges:finish");
-
     Ok(sector_challenges)
 }
 
+/// The sectors challenged by a proof-of-spacetime and the leaf challenges for each of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FallbackPoStChallenges {
+    /// The challenged sectors, in the order they need to be proven. For Winning PoSt this is
+    /// the selection from the sector set, for Window PoSt it is the whole sector set.
+    pub sectors: Vec<SectorId>,
+    /// The challenged leaf indexes per sector.
+    pub challenges: BTreeMap<SectorId, Vec<u64>>,
+}
+
+/// Generates all challenges of a Window or Winning proof-of-spacetime from the given
+/// randomness, without requiring any access to the replicas.
+///
+/// This is deterministic in its inputs, so it can be used to pre-compute or independently
+/// check the challenges of a proof.
+pub fn generate_fallback_post_challenges<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomness: &ChallengeSeed,
+    prover_id: ProverId,
+    sector_set: &[SectorId],
+) -> Result<FallbackPoStChallenges> {
+    generate_fallback_post_challenges_with_domain::<Tree>(
+        post_config,
+        randomness,
+        prover_id,
+        sector_set,
+        &ChallengeDomain::Mainnet,
+    )
+}
+
+/// Like [`generate_fallback_post_challenges`], with the challenges derived in `challenge_domain`.
+pub fn generate_fallback_post_challenges_with_domain<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomness: &ChallengeSeed,
+    prover_id: ProverId,
+    sector_set: &[SectorId],
+    challenge_domain: &ChallengeDomain,
+) -> Result<FallbackPoStChallenges> {
+    info!("generate_fallback_post_challenges:start");
+    ensure!(!sector_set.is_empty(), "empty sector set is invalid");
+
+    let sectors = match post_config.typ {
+        PoStType::Window => sector_set.to_vec(),
+        PoStType::Winning => generate_winning_post_sector_challenge_with_domain::<Tree>(
+            post_config,
+            randomness,
+            sector_set.len() as u64,
+            prover_id,
+            challenge_domain,
+        )?
+        .into_iter()
+        .map(|index| sector_set[index as usize])
+        .collect(),
+    };
+
+    let challenges = generate_fallback_sector_challenges_with_domain::<Tree>(
+        post_config,
+        randomness,
+        &sectors,
+        prover_id,
+        challenge_domain,
+    )?;
+
+    info!("generate_fallback_post_challenges:finish");
+
+    Ok(FallbackPoStChallenges {
+        sectors,
+        challenges,
+    })
+}
+
+/// Reads ahead the replica and tree_r_last data needed to prove the given sector challenges into
+/// the page cache, so that the proof generation does not stall on random reads. The replicas are
+/// read in parallel on a dedicated pool of `num_threads` threads, while the reads within one
+/// replica are issued in ascending order. Returns the number of bytes read.
+///
+/// The data is not kept, use [`prefetch_fallback_post_replicas`] to hand it to the prover.
+pub fn prefetch_fallback_post_challenges<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    replicas: &BTreeMap<SectorId, PrivateReplicaInfo<Tree>>,
+    challenges: &BTreeMap<SectorId, Vec<u64>>,
+    num_threads: usize,
+) -> Result<u64> {
+    prefetch_fallback_post_challenges_until(
+        post_config,
+        replicas,
+        challenges,
+        num_threads,
+        &AtomicBool::new(false),
+    )
+}
+
+/// Same as `prefetch_fallback_post_challenges`, but no more replicas are read once `done` is set,
+/// e.g. because the proof that needed the data was generated.
+pub(crate) fn prefetch_fallback_post_challenges_until<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    replicas: &BTreeMap<SectorId, PrivateReplicaInfo<Tree>>,
+    challenges: &BTreeMap<SectorId, Vec<u64>>,
+    num_threads: usize,
+    done: &AtomicBool,
+) -> Result<u64> {
+    info!("prefetch_fallback_post_challenges:start");
+
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .thread_name(|index| format!("post-prefetch-{}", index))
+        .build()
+        .context("failed to create prefetch thread pool")?;
+
+    let bytes = pool.install(|| {
+        challenges
+            .par_iter()
+            .map(|(sector_id, sector_challenges)| {
+                if done.load(Ordering::Relaxed) {
+                    return Ok(0);
+                }
+                let replica = replicas
+                    .get(sector_id)
+                    .with_context(|| format!("missing replica for sector {}", sector_id))?;
+                replica
+                    .prefetch_challenges(post_config.sector_size, sector_challenges)
+                    .with_context(|| format!("failed to prefetch sector {}", sector_id))
+            })
+            .try_reduce(|| 0, |a, b| Ok(a + b))
+    })?;
+
+    info!("prefetch_fallback_post_challenges:finish: {} bytes", bytes);
+
+    Ok(bytes)
+}
+
+/// Reads ahead the replica data needed to prove the given sector challenges like
+/// [`prefetch_fallback_post_challenges`], but keeps it in memory. The vanilla proof of each
+/// sector is generated from its prefetched replica with
+/// [`generate_single_vanilla_proof_with_backend`], without reading the replica again.
+pub fn prefetch_fallback_post_replicas<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    replicas: &BTreeMap<SectorId, PrivateReplicaInfo<Tree>>,
+    challenges: &BTreeMap<SectorId, Vec<u64>>,
+    num_threads: usize,
+) -> Result<BTreeMap<SectorId, Arc<PrefetchedReplica>>> {
+    info!("prefetch_fallback_post_replicas:start");
+
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .thread_name(|index| format!("post-prefetch-{}", index))
+        .build()
+        .context("failed to create prefetch thread pool")?;
+
+    let prefetched = pool.install(|| {
+        challenges
+            .par_iter()
+            .map(|(sector_id, sector_challenges)| {
+                let replica = replicas
+                    .get(sector_id)
+                    .with_context(|| format!("missing replica for sector {}", sector_id))?;
+                let prefetched = replica
+                    .prefetch_replica(post_config.sector_size, sector_challenges)
+                    .with_context(|| format!("failed to prefetch sector {}", sector_id))?;
+                Ok((*sector_id, Arc::new(prefetched)))
+            })
+            .collect::<Result<BTreeMap<_, _>>>()
+    })?;
+
+    info!("prefetch_fallback_post_replicas:finish");
+
+    Ok(prefetched)
+}
+
 /// Generates a single vanilla proof required for either Window proof-of-spacetime
 /// or Winning proof-of-spacetime.
 pub fn generate_single_vanilla_proof<Tree: 'static + MerkleTreeTrait>(
@@ -107,6 +320,70 @@ pub fn generate_single_vanilla_proof<Tree: 'static + MerkleTreeTrait>(
                 sector_id
             )
         })?;
+
+    single_vanilla_proof::<Tree, Tree>(sector_id, replica, tree, challenges)
+}
+
+/// Like [`generate_single_vanilla_proof`], but the replica is read through `backend`, see
+/// [`PrivateReplicaInfo::merkle_tree_with_backend`].
+pub fn generate_single_vanilla_proof_with_backend<Tree, B>(
+    post_config: &PoStConfig,
+    sector_id: SectorId,
+    replica: &PrivateReplicaInfo<Tree>,
+    backend: B,
+    challenges: &[u64],
+) -> Result<FallbackPoStSectorProof<Tree>>
+where
+    Tree: 'static
+        + MerkleTreeTrait<
+            Proof = MerkleProof<
+                <Tree as MerkleTreeTrait>::Hasher,
+                <Tree as MerkleTreeTrait>::Arity,
+                <Tree as MerkleTreeTrait>::SubTreeArity,
+                <Tree as MerkleTreeTrait>::TopTreeArity,
+            >,
+        >,
+    B: 'static + NodeBackend + Clone,
+{
+    info!(
+        "generate_single_vanilla_proof_with_backend:start: {:?}",
+        sector_id
+    );
+
+    let tree = &replica
+        .merkle_tree_with_backend(post_config.sector_size, backend)
+        .with_context(|| {
+            format!(
+                "generate_single_vanilla_proof_with_backend: merkle_tree failed: {:?}",
+                sector_id
+            )
+        })?;
+
+    single_vanilla_proof::<
+        Tree,
+        BackendLCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity, B>,
+    >(sector_id, replica, tree, challenges)
+}
+
+// Proves the challenges of `replica` against `tree`, which may read the replica from somewhere
+// else than `Tree` does, but produces the same inclusion proofs.
+#[allow(clippy::type_complexity)]
+fn single_vanilla_proof<Tree, ProofTree>(
+    sector_id: SectorId,
+    replica: &PrivateReplicaInfo<Tree>,
+    tree: &MerkleTreeWrapper<
+        ProofTree::Hasher,
+        ProofTree::Store,
+        ProofTree::Arity,
+        ProofTree::SubTreeArity,
+        ProofTree::TopTreeArity,
+    >,
+    challenges: &[u64],
+) -> Result<FallbackPoStSectorProof<Tree>>
+where
+    Tree: 'static + MerkleTreeTrait,
+    ProofTree: MerkleTreeTrait<Hasher = Tree::Hasher, Proof = Tree::Proof>,
+{
     let comm_r = replica.safe_comm_r().with_context(|| {
         format!(
             "generate_single_vanilla_poof: safe_comm_r failed: {:?}",
@@ -116,13 +393,13 @@ pub fn generate_single_vanilla_proof<Tree: 'static + MerkleTreeTrait>(
     let comm_c = replica.safe_comm_c();
     let comm_r_last = replica.safe_comm_r_last();
 
-    let priv_sectors = vec![fallback::PrivateSector {
+    let priv_sectors = vec![fallback::PrivateSector::<ProofTree> {
         tree,
         comm_c,
         comm_r_last,
     }];
 
-    let priv_inputs = fallback::PrivateInputs::<Tree> {
+    let priv_inputs = fallback::PrivateInputs::<ProofTree> {
         sectors: &priv_sectors,
     };
 
@@ -218,7 +495,7 @@ pub(crate) fn get_partitions_for_window_post(
     total_sector_count: usize,
     post_config: &PoStConfig,
 ) -> Option<usize> {
-    let partitions = (total_sector_count as f32 / post_config.sector_count as f32).ceil() as usize;
+    let partitions = fallback::partition_count(total_sector_count, post_config.sector_count);
 
     if partitions > 1 {
         Some(partitions)
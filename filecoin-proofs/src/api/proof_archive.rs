@@ -0,0 +1,341 @@
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+use anyhow::{bail, ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+use storage_proofs_core::{
+    api_version::{ApiFeature, ApiVersion},
+    merkle::MerkleTreeTrait,
+    parameter_cache::CacheableParameters,
+    sector::SectorId,
+};
+use storage_proofs_update::{
+    circuit::EmptySectorUpdateCircuit, compound::EmptySectorUpdateCompound, constants::TreeRHasher,
+    PublicParams,
+};
+
+use crate::{
+    api::{verify_empty_sector_update_proof, verify_seal, verify_window_post_with_domain},
+    codec::{ProofKind, CODEC_VERSION},
+    constants::PUBLISHED_SECTOR_SIZES,
+    types::{
+        ChallengeDomain, ChallengeSeed, Commitment, PoRepConfig, PoRepProofPartitions, PoStConfig,
+        PoStType, ProverId, PublicReplicaInfo, SealPublicInputs, SectorSize,
+    },
+    with_shape,
+};
+
+/// The public inputs of an archived proof, together with the parts of the proof configuration
+/// that cannot be derived from the sector size.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ArchivedPublicInputs {
+    Seal {
+        inputs: SealPublicInputs,
+        porep_id: [u8; 32],
+        partitions: u8,
+        api_features: Vec<ApiFeature>,
+    },
+    WindowPoSt {
+        randomness: ChallengeSeed,
+        prover_id: ProverId,
+        /// The replica commitments of the proven sectors.
+        replicas: BTreeMap<SectorId, Commitment>,
+        challenge_count: usize,
+        sector_count: usize,
+    },
+    EmptySectorUpdate {
+        comm_r_old: Commitment,
+        comm_r_new: Commitment,
+        comm_d_new: Commitment,
+        porep_id: [u8; 32],
+    },
+}
+
+/// A proof bundled with everything needed to verify it, so that it can be stored and verified
+/// long after the sector it was generated for is gone, see [`verify_archived`].
+///
+/// Archived proofs are encoded as CBOR, see [`ArchivedProof::write`], and versioned with the
+/// [`CODEC_VERSION`] of the proof formats.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchivedProof {
+    pub version: u8,
+    pub proof_type: ProofKind,
+    pub api_version: ApiVersion,
+    pub sector_size: u64,
+    pub challenge_domain: ChallengeDomain,
+    /// The identifier of the Groth16 parameters the proof was generated with, as used by the
+    /// parameter cache.
+    pub parameter_id: String,
+    pub public_inputs: ArchivedPublicInputs,
+    #[serde(with = "serde_bytes")]
+    pub proof: Vec<u8>,
+}
+
+impl ArchivedProof {
+    /// Archives a seal proof, as verified by [`verify_seal`].
+    pub fn seal<Tree: 'static + MerkleTreeTrait>(
+        porep_config: &PoRepConfig,
+        inputs: SealPublicInputs,
+        proof: &[u8],
+    ) -> Result<Self> {
+        Ok(ArchivedProof {
+            version: CODEC_VERSION,
+            proof_type: ProofKind::Seal,
+            api_version: porep_config.api_version,
+            sector_size: u64::from(porep_config.sector_size),
+            challenge_domain: porep_config.challenge_domain,
+            parameter_id: porep_config.get_cache_identifier::<Tree>()?,
+            public_inputs: ArchivedPublicInputs::Seal {
+                inputs,
+                porep_id: porep_config.porep_id,
+                partitions: porep_config.partitions.0,
+                api_features: porep_config.api_features.clone(),
+            },
+            proof: proof.to_vec(),
+        })
+    }
+
+    /// Archives a window PoSt, as verified by [`verify_window_post`](crate::verify_window_post).
+    pub fn window_post<Tree: 'static + MerkleTreeTrait>(
+        post_config: &PoStConfig,
+        randomness: &ChallengeSeed,
+        replicas: &BTreeMap<SectorId, PublicReplicaInfo>,
+        prover_id: ProverId,
+        proof: &[u8],
+    ) -> Result<Self> {
+        Self::window_post_with_domain::<Tree>(
+            post_config,
+            randomness,
+            replicas,
+            prover_id,
+            proof,
+            &ChallengeDomain::Mainnet,
+        )
+    }
+
+    /// Archives a window PoSt, as verified by [`verify_window_post_with_domain`].
+    pub fn window_post_with_domain<Tree: 'static + MerkleTreeTrait>(
+        post_config: &PoStConfig,
+        randomness: &ChallengeSeed,
+        replicas: &BTreeMap<SectorId, PublicReplicaInfo>,
+        prover_id: ProverId,
+        proof: &[u8],
+        challenge_domain: &ChallengeDomain,
+    ) -> Result<Self> {
+        ensure!(
+            post_config.typ == PoStType::Window,
+            "invalid post config type"
+        );
+
+        Ok(ArchivedProof {
+            version: CODEC_VERSION,
+            proof_type: ProofKind::WindowPoSt,
+            api_version: post_config.api_version,
+            sector_size: u64::from(post_config.sector_size),
+            challenge_domain: *challenge_domain,
+            parameter_id: post_config.get_cache_identifier::<Tree>()?,
+            public_inputs: ArchivedPublicInputs::WindowPoSt {
+                randomness: *randomness,
+                prover_id,
+                replicas: replicas
+                    .iter()
+                    .map(|(sector_id, replica)| (*sector_id, replica.comm_r()))
+                    .collect(),
+                challenge_count: post_config.challenge_count,
+                sector_count: post_config.sector_count,
+            },
+            proof: proof.to_vec(),
+        })
+    }
+
+    /// Archives an empty sector update proof, as verified by
+    /// [`verify_empty_sector_update_proof`].
+    pub fn update<Tree: 'static + MerkleTreeTrait<Hasher = TreeRHasher>>(
+        porep_config: &PoRepConfig,
+        comm_r_old: Commitment,
+        comm_r_new: Commitment,
+        comm_d_new: Commitment,
+        proof: &[u8],
+    ) -> Result<Self> {
+        let sector_size = u64::from(porep_config.sector_size);
+
+        Ok(ArchivedProof {
+            version: CODEC_VERSION,
+            proof_type: ProofKind::EmptySectorUpdate,
+            api_version: porep_config.api_version,
+            sector_size,
+            challenge_domain: porep_config.challenge_domain,
+            parameter_id: update_parameter_id::<Tree>(sector_size),
+            public_inputs: ArchivedPublicInputs::EmptySectorUpdate {
+                comm_r_old,
+                comm_r_new,
+                comm_d_new,
+                porep_id: porep_config.porep_id,
+            },
+            proof: proof.to_vec(),
+        })
+    }
+
+    /// Writes the archived proof as CBOR.
+    pub fn write<W: Write>(&self, writer: W) -> Result<()> {
+        ciborium::ser::into_writer(self, writer).context("failed to encode archived proof")
+    }
+
+    /// Reads an archived proof written by [`ArchivedProof::write`].
+    pub fn read<R: Read>(reader: R) -> Result<Self> {
+        let archived: ArchivedProof =
+            ciborium::de::from_reader(reader).context("failed to decode archived proof")?;
+        ensure!(
+            archived.version == CODEC_VERSION,
+            "unsupported archived proof version {} (supported: {})",
+            archived.version,
+            CODEC_VERSION,
+        );
+        Ok(archived)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        self.write(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        Self::read(bytes)
+    }
+}
+
+fn update_parameter_id<Tree: 'static + MerkleTreeTrait<Hasher = TreeRHasher>>(
+    sector_size: u64,
+) -> String {
+    let public_params = PublicParams::from_sector_size(sector_size);
+    <EmptySectorUpdateCompound<Tree> as CacheableParameters<
+        EmptySectorUpdateCircuit<Tree>,
+        _,
+    >>::cache_identifier(&public_params)
+}
+
+/// Verifies an archived proof using only the information it contains and the Groth16
+/// parameters it names.
+///
+/// An error is returned if the parameters the proof was generated with are no longer the ones
+/// this version derives for its configuration, rather than reporting the proof as invalid.
+pub fn verify_archived(archived: &ArchivedProof) -> Result<bool> {
+    ensure!(
+        PUBLISHED_SECTOR_SIZES.contains(&archived.sector_size),
+        "unsupported sector size {}",
+        archived.sector_size
+    );
+    with_shape!(archived.sector_size, verify_archived_with_shape, archived,)
+}
+
+fn verify_archived_with_shape<Tree: 'static + MerkleTreeTrait<Hasher = TreeRHasher>>(
+    archived: &ArchivedProof,
+) -> Result<bool> {
+    ensure!(
+        archived.version == CODEC_VERSION,
+        "unsupported archived proof version {}",
+        archived.version
+    );
+
+    let check_parameter_id = |parameter_id: String| {
+        ensure!(
+            parameter_id == archived.parameter_id,
+            "archived proof was generated with parameters {}, expected {}",
+            archived.parameter_id,
+            parameter_id,
+        );
+        Ok(())
+    };
+
+    match (&archived.proof_type, &archived.public_inputs) {
+        (
+            ProofKind::Seal,
+            ArchivedPublicInputs::Seal {
+                inputs,
+                porep_id,
+                partitions,
+                api_features,
+            },
+        ) => {
+            let mut porep_config =
+                PoRepConfig::new_groth16(archived.sector_size, *porep_id, archived.api_version)
+                    .with_challenge_domain(archived.challenge_domain);
+            porep_config.partitions = PoRepProofPartitions(*partitions);
+            for feature in api_features {
+                porep_config.enable_feature(*feature);
+            }
+            check_parameter_id(porep_config.get_cache_identifier::<Tree>()?)?;
+
+            verify_seal::<Tree>(
+                &porep_config,
+                inputs.comm_r,
+                inputs.comm_d,
+                inputs.prover_id,
+                inputs.sector_id,
+                inputs.ticket,
+                inputs.seed,
+                &archived.proof,
+            )
+        }
+        (
+            ProofKind::WindowPoSt,
+            ArchivedPublicInputs::WindowPoSt {
+                randomness,
+                prover_id,
+                replicas,
+                challenge_count,
+                sector_count,
+            },
+        ) => {
+            let post_config = PoStConfig {
+                sector_size: SectorSize(archived.sector_size),
+                challenge_count: *challenge_count,
+                sector_count: *sector_count,
+                typ: PoStType::Window,
+                priority: false,
+                api_version: archived.api_version,
+            };
+            check_parameter_id(post_config.get_cache_identifier::<Tree>()?)?;
+
+            let replicas = replicas
+                .iter()
+                .map(|(sector_id, comm_r)| Ok((*sector_id, PublicReplicaInfo::new(*comm_r)?)))
+                .collect::<Result<BTreeMap<_, _>>>()?;
+            verify_window_post_with_domain::<Tree>(
+                &post_config,
+                randomness,
+                &replicas,
+                *prover_id,
+                &archived.proof,
+                &archived.challenge_domain,
+            )
+        }
+        (
+            ProofKind::EmptySectorUpdate,
+            ArchivedPublicInputs::EmptySectorUpdate {
+                comm_r_old,
+                comm_r_new,
+                comm_d_new,
+                porep_id,
+            },
+        ) => {
+            let porep_config =
+                PoRepConfig::new_groth16(archived.sector_size, *porep_id, archived.api_version)
+                    .with_challenge_domain(archived.challenge_domain);
+            check_parameter_id(update_parameter_id::<Tree>(archived.sector_size))?;
+
+            verify_empty_sector_update_proof::<Tree>(
+                &porep_config,
+                &archived.proof,
+                *comm_r_old,
+                *comm_r_new,
+                *comm_d_new,
+            )
+        }
+        (proof_type, _) => bail!(
+            "public inputs do not match the archived proof type {:?}",
+            proof_type
+        ),
+    }
+}
@@ -0,0 +1,129 @@
+use anyhow::Result;
+use filecoin_hashers::{Domain, Hasher};
+use serde::{Deserialize, Serialize};
+use storage_proofs_core::sector::SectorId;
+use storage_proofs_porep::stacked::generate_replica_id;
+
+use crate::{
+    api::as_safe_commitment,
+    constants::{DefaultPieceHasher, DefaultTreeHasher},
+    types::{Commitment, PoRepConfig, ProverId, Ticket},
+};
+
+/// The protocol values a replica id is derived from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplicaIdInputs {
+    pub prover_id: ProverId,
+    pub sector_id: SectorId,
+    pub ticket: Ticket,
+    pub comm_d: Commitment,
+    /// The `porep_id` of the registered seal proof the sector is sealed with.
+    pub porep_seed: [u8; 32],
+}
+
+impl ReplicaIdInputs {
+    pub fn new(
+        porep_config: &PoRepConfig,
+        prover_id: ProverId,
+        sector_id: SectorId,
+        ticket: Ticket,
+        comm_d: Commitment,
+    ) -> Self {
+        ReplicaIdInputs {
+            prover_id,
+            sector_id,
+            ticket,
+            comm_d,
+            porep_seed: porep_config.porep_id,
+        }
+    }
+
+    /// Returns the replica id as an element of the domain of `H`.
+    pub fn replica_id<H: Hasher>(&self) -> Result<H::Domain> {
+        let comm_d = as_safe_commitment::<<DefaultPieceHasher as Hasher>::Domain, _>(
+            &self.comm_d,
+            "comm_d",
+        )?;
+
+        Ok(generate_replica_id::<H, _>(
+            self.prover_id.as_bytes(),
+            self.sector_id.into(),
+            self.ticket.as_bytes(),
+            comm_d,
+            &self.porep_seed,
+        ))
+    }
+}
+
+/// Returns the replica id of a sector, i.e.
+/// `sha256(prover_id || sector_id || ticket || comm_d || porep_seed)` with the sector id encoded
+/// as big-endian and the two most significant bits cleared, so that it is a valid field element.
+pub fn compute_replica_id(inputs: &ReplicaIdInputs) -> Result<Commitment> {
+    let replica_id = inputs.replica_id::<DefaultTreeHasher>()?;
+    let mut out = [0u8; 32];
+    replica_id.write_bytes(&mut out)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use storage_proofs_core::api_version::ApiVersion;
+
+    use crate::types::RegisteredSealProof;
+
+    fn inputs(proof: RegisteredSealProof) -> ReplicaIdInputs {
+        ReplicaIdInputs::new(
+            &proof.as_porep_config(),
+            ProverId::from([1u8; 32]),
+            SectorId::from(42),
+            Ticket::from([2u8; 32]),
+            [3u8; 32],
+        )
+    }
+
+    #[test]
+    fn test_replica_id_golden_vectors() {
+        // One 32GiB proof per API version, the porep seed is the only input that differs.
+        let vectors = [
+            (
+                RegisteredSealProof::StackedDrg32GiBV1,
+                ApiVersion::V1_0_0,
+                "ca59e83e77569c5d25ce471b50479a5cc0f2cd384c79336263fc22626fce6817",
+            ),
+            (
+                RegisteredSealProof::StackedDrg32GiBV1_1,
+                ApiVersion::V1_1_0,
+                "f050b35daef7ee40858ab23c9524b8336e2e378fb5bc867f02d07bc59f5fc43a",
+            ),
+            (
+                RegisteredSealProof::StackedDrg32GiBV1_1_Feat_SyntheticPoRep,
+                ApiVersion::V1_2_0,
+                "3e617fe4c6d4dc54c3148aa09677aa1d2be5b04b3cd5e6749853b5f963a56503",
+            ),
+        ];
+
+        for (proof, api_version, expected) in vectors {
+            assert_eq!(proof.api_version(), api_version);
+            let replica_id = compute_replica_id(&inputs(proof)).expect("invalid inputs");
+            assert_eq!(hex::encode(replica_id), expected, "{:?}", proof);
+        }
+    }
+
+    #[test]
+    fn test_replica_id_domains_agree() {
+        use filecoin_hashers::{poseidon::PoseidonHasher, sha256::Sha256Hasher};
+
+        let inputs = inputs(RegisteredSealProof::StackedDrg2KiBV1_1);
+        let poseidon = inputs
+            .replica_id::<PoseidonHasher>()
+            .expect("invalid inputs");
+        let sha256 = inputs.replica_id::<Sha256Hasher>().expect("invalid inputs");
+        assert_eq!(poseidon.into_bytes(), sha256.into_bytes());
+
+        let mut invalid = inputs;
+        invalid.comm_d = [0xff; 32];
+        assert!(compute_replica_id(&invalid).is_err());
+    }
+}
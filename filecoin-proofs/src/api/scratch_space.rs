@@ -0,0 +1,322 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::{ensure, Result};
+use merkletree::merkle::{get_merkle_tree_len, get_merkle_tree_row_count};
+use storage_proofs_core::{
+    api_version::ApiFeature,
+    merkle::{get_base_tree_count, MerkleTreeTrait},
+    util::{default_rows_to_discard, NODE_SIZE},
+};
+use storage_proofs_porep::stacked::{SynthProofs, DEFAULT_SYNTH_CHALLENGE_COUNT, TOTAL_PARENTS};
+use typenum::Unsigned;
+
+use crate::{api::SectorState, types::PoRepConfig};
+
+/// The disk space, in bytes, that the files of a sector take while it is sealed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SealScratchSpace {
+    /// All layers of labels.
+    pub labels: u64,
+    pub tree_d: u64,
+    /// All base trees of tree_c.
+    pub tree_c: u64,
+    /// The cached rows of all base trees of tree_r_last.
+    pub tree_r_last: u64,
+    pub replica: u64,
+    /// The synthetic vanilla proofs, zero if synthetic PoRep isn't used.
+    pub synth_proofs: u64,
+    /// The parents cache, which is shared by all sectors with the same sector size and porep id,
+    /// it's not part of the per-stage numbers.
+    pub parent_cache: u64,
+}
+
+impl SealScratchSpace {
+    /// Returns the space that is used while the step which moves a sector to `state` runs.
+    /// Sectors that reached `Proven` only keep the replica and tree_r_last, which are also needed
+    /// for a SnapDeals update.
+    pub fn for_state(&self, state: SectorState) -> u64 {
+        match state {
+            SectorState::Staged => 0,
+            SectorState::PreCommit1 => self.labels + self.tree_d,
+            SectorState::PreCommit2
+            | SectorState::WaitSeed
+            | SectorState::Commit1
+            | SectorState::Commit2 => {
+                self.labels
+                    + self.tree_d
+                    + self.tree_c
+                    + self.tree_r_last
+                    + self.replica
+                    + self.synth_proofs
+            }
+            SectorState::Proven | SectorState::UpdateEncoded | SectorState::UpdateProven => {
+                self.tree_r_last + self.replica
+            }
+        }
+    }
+
+    /// Returns the largest amount of space that is used at any point while sealing.
+    pub fn peak(&self) -> u64 {
+        self.for_state(SectorState::PreCommit2)
+    }
+}
+
+/// Returns the number of nodes a Merkle tree with `leafs` leaves stores on disk, when the
+/// leaves and `rows_to_discard` rows above them aren't stored.
+fn cached_tree_nodes(leafs: usize, arity: usize, rows_to_discard: usize) -> usize {
+    let row_count = get_merkle_tree_row_count(leafs, arity);
+    let mut row_len = leafs;
+    let mut nodes = 0;
+    for row in 0..row_count {
+        if row > rows_to_discard {
+            nodes += row_len;
+        }
+        row_len /= arity;
+    }
+    nodes
+}
+
+/// Computes the disk space that sealing a sector with the given configuration needs.
+pub fn seal_scratch_space<Tree: MerkleTreeTrait>(
+    porep_config: &PoRepConfig,
+) -> Result<SealScratchSpace> {
+    let sector_size = u64::from(porep_config.sector_size);
+    let sector_nodes = sector_size as usize / NODE_SIZE;
+    let layers = porep_config.layers;
+
+    let base_tree_count = get_base_tree_count::<Tree>();
+    ensure!(
+        sector_nodes % base_tree_count == 0,
+        "sector size {} is not a multiple of the base tree count {}",
+        sector_size,
+        base_tree_count
+    );
+    let base_tree_leafs = sector_nodes / base_tree_count;
+    let arity = Tree::Arity::to_usize();
+
+    let tree_d_nodes = get_merkle_tree_len(sector_nodes, 2)?;
+    let tree_c_nodes = base_tree_count * get_merkle_tree_len(base_tree_leafs, arity)?;
+    let tree_r_last_nodes = base_tree_count
+        * cached_tree_nodes(
+            base_tree_leafs,
+            arity,
+            default_rows_to_discard(base_tree_leafs, arity),
+        );
+
+    let synth_proofs = if porep_config.feature_enabled(ApiFeature::SyntheticPoRep) {
+        let num_proofs = sector_nodes.min(DEFAULT_SYNTH_CHALLENGE_COUNT);
+        // The proofs are preceded by the three roots.
+        3 * NODE_SIZE + num_proofs * SynthProofs::proof_size::<Tree>(sector_nodes, layers)
+    } else {
+        0
+    };
+
+    let parent_cache = sector_nodes * TOTAL_PARENTS * std::mem::size_of::<u32>();
+
+    Ok(SealScratchSpace {
+        labels: (layers * sector_nodes * NODE_SIZE) as u64,
+        tree_d: (tree_d_nodes * NODE_SIZE) as u64,
+        tree_c: (tree_c_nodes * NODE_SIZE) as u64,
+        tree_r_last: (tree_r_last_nodes * NODE_SIZE) as u64,
+        replica: sector_size,
+        synth_proofs: synth_proofs as u64,
+        parent_cache: parent_cache as u64,
+    })
+}
+
+#[derive(Debug)]
+struct Budget {
+    capacity: u64,
+    reserved: u64,
+}
+
+/// A budget of scratch space, which is shared by concurrent seals. Space is reserved before a
+/// step runs and released once the reservation is dropped, so that the steps can't overcommit
+/// the scratch volume.
+#[derive(Clone, Debug)]
+pub struct ScratchSpaceBudget(Arc<Mutex<Budget>>);
+
+impl ScratchSpaceBudget {
+    pub fn new(capacity: u64) -> Self {
+        ScratchSpaceBudget(Arc::new(Mutex::new(Budget {
+            capacity,
+            reserved: 0,
+        })))
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.0
+            .lock()
+            .expect("scratch space budget poisoned")
+            .capacity
+    }
+
+    /// Returns the space that is currently reserved.
+    pub fn reserved(&self) -> u64 {
+        self.0
+            .lock()
+            .expect("scratch space budget poisoned")
+            .reserved
+    }
+
+    pub fn available(&self) -> u64 {
+        let budget = self.0.lock().expect("scratch space budget poisoned");
+        budget.capacity - budget.reserved
+    }
+
+    /// Reserves `bytes`, it fails if not enough space is available.
+    pub fn reserve(&self, bytes: u64) -> Result<ScratchSpaceReservation> {
+        let mut budget = self.0.lock().expect("scratch space budget poisoned");
+        let available = budget.capacity - budget.reserved;
+        ensure!(
+            bytes <= available,
+            "cannot reserve {} bytes of scratch space, only {} bytes are available",
+            bytes,
+            available
+        );
+        budget.reserved += bytes;
+
+        Ok(ScratchSpaceReservation {
+            budget: self.clone(),
+            bytes,
+        })
+    }
+
+    /// Reserves the space that the step which moves a sector to `state` needs.
+    pub fn reserve_for_state(
+        &self,
+        space: &SealScratchSpace,
+        state: SectorState,
+    ) -> Result<ScratchSpaceReservation> {
+        self.reserve(space.for_state(state))
+    }
+
+    fn release(&self, bytes: u64) {
+        // This runs on drop, so don't panic on a poisoned lock, the counts are still valid.
+        let mut budget = self.0.lock().unwrap_or_else(|err| err.into_inner());
+        budget.reserved -= bytes;
+    }
+}
+
+/// Reserved scratch space, which is released when this is dropped.
+#[derive(Debug)]
+pub struct ScratchSpaceReservation {
+    budget: ScratchSpaceBudget,
+    bytes: u64,
+}
+
+impl ScratchSpaceReservation {
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Changes the reservation to `bytes`, e.g. when a sector moves on to the next step. It fails
+    /// if growing the reservation would exceed the budget, the reservation is unchanged then.
+    pub fn resize(&mut self, bytes: u64) -> Result<()> {
+        let mut budget = self.budget.0.lock().expect("scratch space budget poisoned");
+        if bytes > self.bytes {
+            let available = budget.capacity - budget.reserved;
+            ensure!(
+                bytes - self.bytes <= available,
+                "cannot grow the scratch space reservation to {} bytes, only {} more bytes are \
+                 available",
+                bytes,
+                available
+            );
+        }
+        budget.reserved = budget.reserved - self.bytes + bytes;
+        self.bytes = bytes;
+
+        Ok(())
+    }
+}
+
+impl Drop for ScratchSpaceReservation {
+    fn drop(&mut self) {
+        self.budget.release(self.bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use storage_proofs_core::api_version::ApiVersion;
+
+    use crate::constants::{
+        SectorShape2KiB, SectorShape32GiB, SECTOR_SIZE_2_KIB, SECTOR_SIZE_32_GIB,
+    };
+
+    #[test]
+    fn test_seal_scratch_space_2kib() {
+        let porep_config = PoRepConfig::new_groth16(SECTOR_SIZE_2_KIB, [1; 32], ApiVersion::V1_1_0);
+        let space =
+            seal_scratch_space::<SectorShape2KiB>(&porep_config).expect("failed to compute space");
+
+        // 64 nodes, 2 layers.
+        assert_eq!(space.labels, 2 * 2048);
+        // A binary tree with 64 leaves has 127 nodes.
+        assert_eq!(space.tree_d, 127 * 32);
+        // An oct tree with 64 leaves has 64 + 8 + 1 nodes.
+        assert_eq!(space.tree_c, 73 * 32);
+        // Of a tree with 3 rows only the root is stored.
+        assert_eq!(space.tree_r_last, 32);
+        assert_eq!(space.replica, 2048);
+        assert_eq!(space.synth_proofs, 0);
+        assert_eq!(space.parent_cache, 64 * 14 * 4);
+
+        assert_eq!(
+            space.for_state(SectorState::PreCommit1),
+            2 * 2048 + 127 * 32
+        );
+        assert_eq!(space.peak(), 2 * 2048 + 127 * 32 + 73 * 32 + 32 + 2048);
+        assert_eq!(space.for_state(SectorState::Proven), 32 + 2048);
+
+        let synthetic = seal_scratch_space::<SectorShape2KiB>(
+            &porep_config.with_feature(ApiFeature::SyntheticPoRep),
+        )
+        .expect("failed to compute space");
+        assert!(synthetic.synth_proofs > 0);
+        assert_eq!(synthetic.peak(), space.peak() + synthetic.synth_proofs);
+    }
+
+    #[test]
+    fn test_seal_scratch_space_32gib() {
+        let porep_config =
+            PoRepConfig::new_groth16(SECTOR_SIZE_32_GIB, [1; 32], ApiVersion::V1_1_0);
+        let space =
+            seal_scratch_space::<SectorShape32GiB>(&porep_config).expect("failed to compute space");
+
+        assert_eq!(space.labels, 11 * SECTOR_SIZE_32_GIB);
+        assert_eq!(space.tree_d, 2 * SECTOR_SIZE_32_GIB - 32);
+        // 8 base trees of 2^27 leaves, the rows of 2^18 nodes and above are stored.
+        let cached_nodes: u64 = (0..=6).map(|i| 1 << (3 * i)).sum();
+        assert_eq!(space.tree_r_last, 8 * cached_nodes * 32);
+    }
+
+    #[test]
+    fn test_scratch_space_budget() {
+        let budget = ScratchSpaceBudget::new(100);
+
+        let mut first = budget.reserve(60).expect("failed to reserve");
+        assert_eq!(budget.available(), 40);
+        assert!(budget.reserve(41).is_err());
+
+        {
+            let _second = budget.reserve(40).expect("failed to reserve");
+            assert_eq!(budget.available(), 0);
+            assert!(first.resize(70).is_err());
+            assert_eq!(first.bytes(), 60);
+        }
+        assert_eq!(budget.reserved(), 60);
+
+        first.resize(100).expect("failed to resize");
+        assert_eq!(budget.available(), 0);
+        first.resize(10).expect("failed to resize");
+        assert_eq!(budget.reserved(), 10);
+
+        drop(first);
+        assert_eq!(budget.reserved(), 0);
+        assert_eq!(budget.capacity(), 100);
+    }
+}
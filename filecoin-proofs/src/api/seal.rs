@@ -1,10 +1,11 @@
-use std::fs::{self, metadata, OpenOptions};
+use std::fs::{self, metadata, File, OpenOptions};
+use std::io::{BufReader, Cursor};
 use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, ensure, Context, Result};
 use bellperson::groth16;
 use blstrs::{Bls12, Scalar as Fr};
-use filecoin_hashers::{Domain, Hasher};
+use filecoin_hashers::{poseidon::PoseidonHasher, Domain, Hasher};
 use log::{info, trace};
 use memmap2::MmapOptions;
 use merkletree::store::{DiskStore, Store, StoreConfig};
@@ -17,39 +18,45 @@ use storage_proofs_core::{
     drgraph::Graph,
     measurements::{measure_op, Operation},
     merkle::{
-        create_base_merkle_tree, get_base_tree_count, split_config, BinaryMerkleTree,
-        MerkleTreeTrait,
+        create_base_merkle_tree, create_base_merkle_tree_chunked, get_base_tree_count,
+        split_config, BinaryMerkleTree, MerkleTreeTrait,
     },
     multi_proof::MultiProof,
     parameter_cache::SRS_MAX_PROOFS_TO_AGGREGATE,
     proof::ProofScheme,
     sector::SectorId,
+    settings::SETTINGS,
     util::{default_rows_to_discard, NODE_SIZE},
     Data,
 };
 use storage_proofs_porep::stacked::{
     self, generate_replica_id, ChallengeRequirements, Labels, LabelsCache, StackedCompound,
-    StackedDrg, Tau, TemporaryAuxCache,
+    StackedDrg, SynthProofs, Tau, TemporaryAuxCache, SYNTHETIC_POREP_VANILLA_PROOFS_EXT,
+    SYNTHETIC_POREP_VANILLA_PROOFS_KEY,
 };
 use storage_proofs_update::vanilla::prepare_tree_r_data;
+use tracing::info_span;
 use typenum::{Unsigned, U11, U2};
 
-use crate::POREP_MINIMUM_CHALLENGES;
 use crate::{
-    api::{as_safe_commitment, commitment_from_fr, get_base_tree_leafs, get_base_tree_size, util},
-    caches::{
-        get_stacked_params, get_stacked_srs_key, get_stacked_srs_verifier_key,
-        get_stacked_verifying_key,
+    api::{
+        as_safe_commitment, check_circuit_satisfied, commitment_from_fr, get_base_tree_leafs,
+        get_base_tree_size, incremental_tree_d::open_staged_tree_d, util,
     },
-    constants::{
-        DefaultBinaryTree, DefaultPieceDomain, DefaultPieceHasher, SINGLE_PARTITION_PROOF_LEN,
+    caches::{
+        get_stacked_params_with_piece_hasher, get_stacked_srs_key, get_stacked_srs_verifier_key,
+        get_stacked_verifying_key, get_stacked_verifying_key_with_piece_hasher,
     },
-    parameters::setup_params,
+    codec,
+    constants::{DefaultBinaryTree, DefaultPieceDomain, DefaultPieceHasher},
+    metrics::{self, StageTimer},
+    parameters::{public_params, setup_params},
     pieces::{self, verify_pieces},
+    priority::{enter_stage, ProvingPriority},
     types::{
-        AggregateSnarkProof, Commitment, PieceInfo, PoRepConfig, ProverId, SealCommitOutput,
-        SealCommitPhase1Output, SealPreCommitOutput, SealPreCommitPhase1Output, SectorSize, Ticket,
-        BINARY_ARITY,
+        AggregateSnarkProof, Commitment, PieceHasher, PieceInfo, PoRepConfig, ProverId,
+        SealCommitOutput, SealCommitPhase1Output, SealPreCommitOutput, SealPreCommitPhase1Output,
+        SealPublicInputs, SealVerifyInfo, SectorSize, Ticket, BINARY_ARITY,
     },
 };
 
@@ -69,6 +76,132 @@ where
     S: AsRef<Path>,
     T: AsRef<Path>,
 {
+    seal_pre_commit_phase1_inner(
+        porep_config,
+        cache_path,
+        in_path,
+        out_path,
+        prover_id,
+        sector_id,
+        ticket,
+        piece_infos,
+        true, /* build_tree_d */
+    )
+}
+
+/// The marker file within the cache directory of a sector whose tree_d is not built, see
+/// [`seal_pre_commit_phase1_without_tree_d`].
+pub const WITHOUT_TREE_D: &str = "without-tree-d";
+
+/// Like [`seal_pre_commit_phase1`], but tree_d is not built, comm_d is computed from
+/// `piece_infos` instead. The data is opened from the staged sector when committing, so
+/// `in_path` must be kept until [`seal_commit_phase1_with_staged_data`].
+///
+/// SyntheticPoRep is not supported, [`generate_synth_proofs`] opens the data from tree_d.
+#[allow(clippy::too_many_arguments)]
+pub fn seal_pre_commit_phase1_without_tree_d<R, S, T, Tree: 'static + MerkleTreeTrait>(
+    porep_config: &PoRepConfig,
+    cache_path: R,
+    in_path: S,
+    out_path: T,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    piece_infos: &[PieceInfo],
+) -> Result<SealPreCommitPhase1Output<Tree>>
+where
+    R: AsRef<Path>,
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    ensure!(
+        !porep_config.feature_enabled(ApiFeature::SyntheticPoRep),
+        "synth-porep requires tree_d to be built"
+    );
+    // comm_d is computed from the piece commitments, which are built with DefaultPieceHasher.
+    porep_config.check_piece_hasher::<DefaultPieceHasher>()?;
+    seal_pre_commit_phase1_inner(
+        porep_config,
+        cache_path,
+        in_path,
+        out_path,
+        prover_id,
+        sector_id,
+        ticket,
+        piece_infos,
+        false, /* build_tree_d */
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn seal_pre_commit_phase1_inner<R, S, T, Tree: 'static + MerkleTreeTrait>(
+    porep_config: &PoRepConfig,
+    cache_path: R,
+    in_path: S,
+    out_path: T,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    piece_infos: &[PieceInfo],
+    build_tree_d: bool,
+) -> Result<SealPreCommitPhase1Output<Tree>>
+where
+    R: AsRef<Path>,
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    match porep_config.piece_hasher {
+        PieceHasher::Sha256 => {
+            seal_pre_commit_phase1_with_piece_hasher::<_, _, _, Tree, DefaultPieceHasher>(
+                porep_config,
+                cache_path,
+                in_path,
+                out_path,
+                prover_id,
+                sector_id,
+                ticket,
+                piece_infos,
+                build_tree_d,
+            )
+        }
+        PieceHasher::Poseidon => {
+            seal_pre_commit_phase1_with_piece_hasher::<_, _, _, Tree, PoseidonHasher>(
+                porep_config,
+                cache_path,
+                in_path,
+                out_path,
+                prover_id,
+                sector_id,
+                ticket,
+                piece_infos,
+                build_tree_d,
+            )
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn seal_pre_commit_phase1_with_piece_hasher<R, S, T, Tree, G>(
+    porep_config: &PoRepConfig,
+    cache_path: R,
+    in_path: S,
+    out_path: T,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    piece_infos: &[PieceInfo],
+    build_tree_d: bool,
+) -> Result<SealPreCommitPhase1Output<Tree>>
+where
+    R: AsRef<Path>,
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+    Tree: 'static + MerkleTreeTrait,
+    G: 'static + Hasher,
+{
+    let _span = info_span!("seal_pre_commit_phase1", sector_id = u64::from(sector_id)).entered();
+    metrics::seal_started();
+    let timer = StageTimer::start("pre_commit_phase1");
     info!("seal_pre_commit_phase1:start: {:?}", sector_id);
 
     let in_path_is_dev_zero = in_path.as_ref() == Path::new("/dev/zero");
@@ -135,8 +268,8 @@ where
         priority: false,
     };
 
-    let compound_public_params = <StackedCompound<Tree, DefaultPieceHasher> as CompoundProof<
-        StackedDrg<'_, Tree, DefaultPieceHasher>,
+    let compound_public_params = <StackedCompound<Tree, G> as CompoundProof<
+        StackedDrg<'_, Tree, G>,
         _,
     >>::setup(&compound_setup_params)?;
 
@@ -156,13 +289,45 @@ where
             base_tree_leafs,
         );
 
+        if !build_tree_d {
+            drop(data);
+            trace!("computing comm_d from the pieces, tree_d is not built");
+            let mut config =
+                StoreConfig::new(cache_path.as_ref(), CacheKey::CommDTree.to_string(), 0);
+            config.size = Some(base_tree_size);
+            let comm_d = compute_comm_d(porep_config.sector_size, piece_infos)?;
+            return Ok((config, comm_d));
+        }
+
+        // If tree_d was built while the pieces were added, only the rest of the sector is hashed.
+        // The staged tree is built with DefaultPieceHasher.
+        if !in_path_is_dev_zero && porep_config.piece_hasher == PieceHasher::Sha256 {
+            if let Some(tree_d) = open_staged_tree_d(
+                cache_path.as_ref(),
+                in_path.as_ref(),
+                porep_config.sector_size,
+            )? {
+                drop(data);
+                return tree_d.finalize();
+            }
+        }
+
         let mut config = StoreConfig::new(cache_path.as_ref(), CacheKey::CommDTree.to_string(), 0);
 
-        let data_tree = create_base_merkle_tree::<BinaryMerkleTree<DefaultPieceHasher>>(
-            Some(config.clone()),
-            base_tree_leafs,
-            &data,
-        )?;
+        let data_tree = if SETTINGS.tree_d_max_memory > 0 {
+            create_base_merkle_tree_chunked::<G, U2>(
+                config.clone(),
+                base_tree_leafs,
+                &data,
+                SETTINGS.tree_d_max_memory,
+            )?
+        } else {
+            create_base_merkle_tree::<BinaryMerkleTree<G>>(
+                Some(config.clone()),
+                base_tree_leafs,
+                &data,
+            )?
+        };
         drop(data);
 
         config.size = Some(data_tree.len());
@@ -174,35 +339,64 @@ where
         Ok((config, comm_d))
     })?;
 
-    trace!("verifying pieces");
+    // The mode is also recorded in the cache directory, for the checks that have no phase1
+    // output, see `validate_cache_for_commit` and `get_seal_status`.
+    let without_tree_d_path = cache_path.as_ref().join(WITHOUT_TREE_D);
+    if build_tree_d {
+        if without_tree_d_path.exists() {
+            fs::remove_file(&without_tree_d_path)
+                .with_context(|| format!("could not remove {:?}", without_tree_d_path.display()))?;
+        }
+    } else {
+        File::create(&without_tree_d_path)
+            .with_context(|| format!("could not create {:?}", without_tree_d_path.display()))?;
+    }
 
-    ensure!(
-        verify_pieces(&comm_d, piece_infos, porep_config.sector_size)?,
-        "pieces and comm_d do not match"
-    );
+    // The piece commitments are built with DefaultPieceHasher, so they can only be checked
+    // against a comm_d of the same hasher.
+    if porep_config.piece_hasher == PieceHasher::Sha256 {
+        trace!("verifying pieces");
+
+        ensure!(
+            verify_pieces(&comm_d, piece_infos, porep_config.sector_size)?,
+            "pieces and comm_d do not match"
+        );
+    }
 
     let replica_id = generate_replica_id::<Tree::Hasher, _>(
-        &prover_id,
+        prover_id.as_bytes(),
         sector_id.into(),
-        &ticket,
+        ticket.as_bytes(),
         comm_d,
         &porep_config.porep_id,
     );
 
-    let (labels, _) = StackedDrg::<Tree, DefaultPieceHasher>::replicate_phase1(
-        &compound_public_params.vanilla_params,
-        &replica_id,
-        &config.path,
-    )?;
+    let (labels, comm_c) = if SETTINGS.build_tree_c_in_phase1 {
+        let (labels, _, comm_c) = StackedDrg::<Tree, G>::replicate_phase1_with_tree_c(
+            &compound_public_params.vanilla_params,
+            &replica_id,
+            &config.path,
+        )?;
+        (labels, Some(commitment_from_fr(comm_c.into())))
+    } else {
+        let (labels, _) = StackedDrg::<Tree, G>::replicate_phase1(
+            &compound_public_params.vanilla_params,
+            &replica_id,
+            &config.path,
+        )?;
+        (labels, None)
+    };
 
     let out = SealPreCommitPhase1Output {
         labels,
         config,
         comm_d,
+        comm_c,
+        without_tree_d: !build_tree_d,
     };
 
     info!("seal_pre_commit_phase1:finish: {:?}", sector_id);
-    Ok(out)
+    timer.finish(Ok(out))
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -216,6 +410,37 @@ where
     R: AsRef<Path>,
     S: AsRef<Path>,
 {
+    match porep_config.piece_hasher {
+        PieceHasher::Sha256 => seal_pre_commit_phase2_with_piece_hasher::<
+            _,
+            _,
+            Tree,
+            DefaultPieceHasher,
+        >(porep_config, phase1_output, cache_path, replica_path),
+        PieceHasher::Poseidon => seal_pre_commit_phase2_with_piece_hasher::<
+            _,
+            _,
+            Tree,
+            PoseidonHasher,
+        >(porep_config, phase1_output, cache_path, replica_path),
+    }
+}
+
+fn seal_pre_commit_phase2_with_piece_hasher<R, S, Tree, G>(
+    porep_config: &PoRepConfig,
+    phase1_output: SealPreCommitPhase1Output<Tree>,
+    cache_path: S,
+    replica_path: R,
+) -> Result<SealPreCommitOutput>
+where
+    R: AsRef<Path>,
+    S: AsRef<Path>,
+    Tree: 'static + MerkleTreeTrait,
+    G: 'static + Hasher,
+{
+    let _span = info_span!("seal_pre_commit_phase2").entered();
+    let timer = StageTimer::start("pre_commit_phase2");
+    let _priority = enter_stage(ProvingPriority::Background);
     info!("seal_pre_commit_phase2:start");
 
     // Sanity check all input path types.
@@ -232,7 +457,8 @@ where
         mut labels,
         mut config,
         comm_d,
-        ..
+        comm_c,
+        without_tree_d,
     } = phase1_output;
 
     labels.update_root(cache_path.as_ref());
@@ -258,8 +484,12 @@ where
     };
     let data: Data<'_> = (data, PathBuf::from(replica_path.as_ref())).into();
 
-    // Load data tree from disk
-    let data_tree = {
+    // Load data tree from disk, unless it was not built and comm_d was computed from the pieces,
+    // see `seal_pre_commit_phase1_without_tree_d`.
+    let data_tree = if without_tree_d {
+        info!("tree_d was not built, using comm_d of phase1");
+        None
+    } else {
         let base_tree_size = get_base_tree_size::<DefaultBinaryTree>(porep_config.sector_size)?;
         let base_tree_leafs = get_base_tree_leafs::<DefaultBinaryTree>(base_tree_size)?;
 
@@ -270,9 +500,12 @@ where
             0
         );
 
-        let store: DiskStore<DefaultPieceDomain> =
+        let store: DiskStore<G::Domain> =
             DiskStore::new_from_disk(base_tree_size, BINARY_ARITY, &config)?;
-        BinaryMerkleTree::<DefaultPieceHasher>::from_data_store(store, base_tree_leafs)?
+        Some(BinaryMerkleTree::<G>::from_data_store(
+            store,
+            base_tree_leafs,
+        )?)
     };
 
     let compound_setup_params = compound_proof::SetupParams {
@@ -281,21 +514,45 @@ where
         priority: false,
     };
 
-    let compound_public_params = <StackedCompound<Tree, DefaultPieceHasher> as CompoundProof<
-        StackedDrg<'_, Tree, DefaultPieceHasher>,
+    let compound_public_params = <StackedCompound<Tree, G> as CompoundProof<
+        StackedDrg<'_, Tree, G>,
         _,
     >>::setup(&compound_setup_params)?;
 
     // Silence Clippy warning for the case where `t_aux` is not written.
     #[allow(unused_variables)]
-    let (tau, (p_aux, t_aux)) = StackedDrg::<Tree, DefaultPieceHasher>::replicate_phase2(
-        &compound_public_params.vanilla_params,
-        labels,
-        data,
-        Some(data_tree),
-        cache_path.as_ref().to_path_buf(),
-        replica_path.as_ref().to_path_buf(),
-    )?;
+    // tree_c was built in phase1, see `Settings::build_tree_c_in_phase1`.
+    let comm_c = comm_c
+        .map(|comm_c| <Tree::Hasher as Hasher>::Domain::try_from_bytes(&comm_c))
+        .transpose()?;
+    let (tau, (p_aux, t_aux)) = match (data_tree, comm_c) {
+        (Some(data_tree), Some(comm_c)) => StackedDrg::<Tree, G>::replicate_phase2_with_tree_c(
+            &compound_public_params.vanilla_params,
+            labels,
+            comm_c,
+            data,
+            Some(data_tree),
+            cache_path.as_ref().to_path_buf(),
+            replica_path.as_ref().to_path_buf(),
+        )?,
+        (Some(data_tree), None) => StackedDrg::<Tree, G>::replicate_phase2(
+            &compound_public_params.vanilla_params,
+            labels,
+            data,
+            Some(data_tree),
+            cache_path.as_ref().to_path_buf(),
+            replica_path.as_ref().to_path_buf(),
+        )?,
+        (None, comm_c) => StackedDrg::<Tree, G>::replicate_phase2_with_comm_d(
+            &compound_public_params.vanilla_params,
+            labels,
+            G::Domain::try_from_bytes(&comm_d)?,
+            comm_c,
+            data,
+            cache_path.as_ref().to_path_buf(),
+            replica_path.as_ref().to_path_buf(),
+        )?,
+    };
 
     let comm_r = commitment_from_fr(tau.comm_r.into());
 
@@ -307,7 +564,7 @@ where
     let out = SealPreCommitOutput { comm_r, comm_d };
 
     info!("seal_pre_commit_phase2:finish");
-    Ok(out)
+    timer.finish(Ok(out))
 }
 
 #[inline]
@@ -326,10 +583,12 @@ pub fn generate_synth_proofs<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
         porep_config.feature_enabled(ApiFeature::SyntheticPoRep),
         "synth-porep must be enabled to generate synthetic proofs",
     );
+    let _span = info_span!("generate_synth_proofs", sector_id = u64::from(sector_id)).entered();
+    let timer = StageTimer::start("synth_proofs");
     info!("seal_gen_synth_proofs:start: {:?}", sector_id);
     // Ignore C1 output as it contains no vanilla proofs (they are stored on disk, rather than
     // in memory) and a bogus porep challenge seed.
-    seal_commit_phase1_inner::<T, Tree>(
+    seal_commit_phase1_inner::<T, Tree, DefaultPieceHasher>(
         porep_config,
         cache_path,
         replica_path,
@@ -340,8 +599,77 @@ pub fn generate_synth_proofs<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
         pre_commit,
         piece_infos,
         false, /* skip_labels */
+        None,
     )?;
     info!("seal_gen_synth_proofs:finish: {:?}", sector_id);
+    timer.finish(Ok(()))
+}
+
+/// Spot-checks `num_samples` randomly selected synthetic proofs stored in `cache_path` by
+/// `generate_synth_proofs`, returning an error if any of them is invalid.
+///
+/// This allows a corrupt synthetic proofs file to be detected (and regenerated) while waiting for
+/// the interactive porep challenge seed, rather than when committing.
+#[allow(clippy::too_many_arguments)]
+pub fn validate_synth_proofs<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
+    porep_config: &PoRepConfig,
+    cache_path: T,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    pre_commit: SealPreCommitOutput,
+    num_samples: usize,
+) -> Result<()> {
+    info!("validate_synth_proofs:start: {:?}", sector_id);
+    ensure!(
+        porep_config.feature_enabled(ApiFeature::SyntheticPoRep),
+        "synth-porep must be enabled to validate synthetic proofs",
+    );
+    porep_config.check_piece_hasher::<DefaultPieceHasher>()?;
+
+    let SealPreCommitOutput { comm_d, comm_r } = pre_commit;
+    ensure!(comm_d != [0; 32], "Invalid all zero commitment (comm_d)");
+    ensure!(comm_r != [0; 32], "Invalid all zero commitment (comm_r)");
+
+    let comm_r_safe = as_safe_commitment(&comm_r, "comm_r")?;
+    let comm_d_safe = DefaultPieceDomain::try_from_bytes(&comm_d)?;
+
+    let replica_id = generate_replica_id::<Tree::Hasher, _>(
+        prover_id.as_bytes(),
+        sector_id.into(),
+        ticket.as_bytes(),
+        comm_d_safe,
+        &porep_config.porep_id,
+    );
+
+    let public_inputs = stacked::PublicInputs {
+        replica_id,
+        tau: Some(stacked::Tau {
+            comm_d: comm_d_safe,
+            comm_r: comm_r_safe,
+        }),
+        k: None,
+        seed: None,
+    };
+    let pub_params = public_params::<Tree>(porep_config)?;
+
+    let path = cache_path.as_ref().join(format!(
+        "{}.{}",
+        SYNTHETIC_POREP_VANILLA_PROOFS_KEY, SYNTHETIC_POREP_VANILLA_PROOFS_EXT
+    ));
+    let file = File::open(&path)
+        .map(BufReader::new)
+        .with_context(|| format!("failed to open synthetic vanilla proofs file: {:?}", path))?;
+
+    SynthProofs::validate::<Tree, DefaultPieceHasher, _>(
+        file,
+        &pub_params,
+        &public_inputs,
+        num_samples,
+    )
+    .with_context(|| format!("invalid synthetic vanilla proofs file: {:?}", path))?;
+
+    info!("validate_synth_proofs:finish: {:?}", sector_id);
     Ok(())
 }
 
@@ -357,10 +685,44 @@ pub fn seal_commit_phase1<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
     pre_commit: SealPreCommitOutput,
     piece_infos: &[PieceInfo],
 ) -> Result<SealCommitPhase1Output<Tree>> {
+    seal_commit_phase1_with_piece_hasher::<T, Tree, DefaultPieceHasher>(
+        porep_config,
+        cache_path,
+        replica_path,
+        prover_id,
+        sector_id,
+        ticket,
+        seed,
+        pre_commit,
+        piece_infos,
+    )
+}
+
+/// Like [`seal_commit_phase1`], for a sector whose `tree_d` is built with `G`, see
+/// [`PoRepConfig::piece_hasher`].
+#[allow(clippy::too_many_arguments)]
+pub fn seal_commit_phase1_with_piece_hasher<T, Tree, G>(
+    porep_config: &PoRepConfig,
+    cache_path: T,
+    replica_path: T,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    seed: Ticket,
+    pre_commit: SealPreCommitOutput,
+    piece_infos: &[PieceInfo],
+) -> Result<SealCommitPhase1Output<Tree, G>>
+where
+    T: AsRef<Path>,
+    Tree: 'static + MerkleTreeTrait,
+    G: 'static + Hasher,
+{
+    let _span = info_span!("seal_commit_phase1", sector_id = u64::from(sector_id)).entered();
+    let timer = StageTimer::start("commit_phase1");
     info!("seal_commit_phase1:start: {:?}", sector_id);
 
     let skip_labels = porep_config.feature_enabled(ApiFeature::SyntheticPoRep);
-    let out = seal_commit_phase1_inner::<T, Tree>(
+    let out = seal_commit_phase1_inner::<T, Tree, G>(
         porep_config,
         cache_path,
         replica_path,
@@ -371,13 +733,55 @@ pub fn seal_commit_phase1<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
         pre_commit,
         piece_infos,
         skip_labels,
+        None,
     )?;
     info!("seal_commit_phase1:finish: {:?}", sector_id);
-    Ok(out)
+    timer.finish(Ok(out))
+}
+
+/// Like [`seal_commit_phase1`], for sectors whose tree_d was not built, see
+/// [`seal_pre_commit_phase1_without_tree_d`]. The data is opened from the staged sector in
+/// `staged_path`, i.e. the `in_path` of pre-commit phase 1, instead of tree_d.
+#[allow(clippy::too_many_arguments)]
+pub fn seal_commit_phase1_with_staged_data<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
+    porep_config: &PoRepConfig,
+    cache_path: T,
+    replica_path: T,
+    staged_path: T,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    seed: Ticket,
+    pre_commit: SealPreCommitOutput,
+    piece_infos: &[PieceInfo],
+) -> Result<SealCommitPhase1Output<Tree>> {
+    let _span = info_span!("seal_commit_phase1", sector_id = u64::from(sector_id)).entered();
+    let timer = StageTimer::start("commit_phase1");
+    info!("seal_commit_phase1_with_staged_data:start: {:?}", sector_id);
+
+    let skip_labels = porep_config.feature_enabled(ApiFeature::SyntheticPoRep);
+    let out = seal_commit_phase1_inner::<T, Tree, DefaultPieceHasher>(
+        porep_config,
+        cache_path,
+        replica_path,
+        prover_id,
+        sector_id,
+        ticket,
+        Some(seed),
+        pre_commit,
+        piece_infos,
+        skip_labels,
+        Some(staged_path.as_ref()),
+    )?;
+    info!(
+        "seal_commit_phase1_with_staged_data:finish: {:?}",
+        sector_id
+    );
+    timer.finish(Ok(out))
 }
 
 #[allow(clippy::too_many_arguments)]
-pub fn seal_commit_phase1_inner<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
+pub fn seal_commit_phase1_inner<T, Tree, G>(
     porep_config: &PoRepConfig,
     cache_path: T,
     replica_path: T,
@@ -389,8 +793,16 @@ pub fn seal_commit_phase1_inner<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>
     pre_commit: SealPreCommitOutput,
     piece_infos: &[PieceInfo],
     skip_labels: bool,
-) -> Result<SealCommitPhase1Output<Tree>> {
+    // The staged sector to open the data from, if tree_d was not built.
+    staged_path: Option<&Path>,
+) -> Result<SealCommitPhase1Output<Tree, G>>
+where
+    T: AsRef<Path>,
+    Tree: 'static + MerkleTreeTrait,
+    G: 'static + Hasher,
+{
     trace!("seal_commit_phase1_inner:start: {:?}", sector_id);
+    porep_config.check_piece_hasher::<G>()?;
 
     // Sanity check all input path types.
     ensure!(
@@ -411,27 +823,45 @@ pub fn seal_commit_phase1_inner<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>
 
     ensure!(comm_d != [0; 32], "Invalid all zero commitment (comm_d)");
     ensure!(comm_r != [0; 32], "Invalid all zero commitment (comm_r)");
-    ensure!(
-        verify_pieces(&comm_d, piece_infos, porep_config.sector_size)?,
-        "pieces and comm_d do not match"
-    );
+    // The piece commitments are built with DefaultPieceHasher, see `seal_pre_commit_phase1`.
+    if porep_config.piece_hasher == PieceHasher::Sha256 {
+        ensure!(
+            verify_pieces(&comm_d, piece_infos, porep_config.sector_size)?,
+            "pieces and comm_d do not match"
+        );
+    }
 
     let p_aux = util::get_p_aux::<Tree>(cache_path.as_ref())?;
-    let t_aux = util::get_t_aux::<Tree>(cache_path.as_ref(), u64::from(porep_config.sector_size))?;
+    let t_aux = util::get_t_aux::<Tree>(
+        cache_path.as_ref(),
+        u64::from(porep_config.sector_size),
+        porep_config.layers,
+    )?;
 
     // Convert TemporaryAux to TemporaryAuxCache, which instantiates all
     // elements based on the configs stored in TemporaryAux.
-    let t_aux_cache: TemporaryAuxCache<Tree, DefaultPieceHasher> =
+    let mut t_aux_cache: TemporaryAuxCache<Tree, G> =
         TemporaryAuxCache::new(&t_aux, replica_path.as_ref().to_path_buf(), skip_labels)
             .context("failed to restore contents of t_aux")?;
-
     let comm_r_safe = as_safe_commitment(&comm_r, "comm_r")?;
-    let comm_d_safe = DefaultPieceDomain::try_from_bytes(&comm_d)?;
+    let comm_d_safe = G::Domain::try_from_bytes(&comm_d)?;
+
+    // The data is only opened if the vanilla proofs are not read from the synthetic proofs.
+    if let (Some(staged_path), false) = (staged_path, skip_labels) {
+        t_aux_cache
+            .set_tree_d_data(staged_path)
+            .with_context(|| format!("could not open the staged sector {:?}", staged_path))?;
+        ensure!(
+            t_aux_cache.tree_d_root() == Some(comm_d_safe),
+            "the staged sector {:?} does not match comm_d",
+            staged_path
+        );
+    }
 
     let replica_id = generate_replica_id::<Tree::Hasher, _>(
-        &prover_id,
+        prover_id.as_bytes(),
         sector_id.into(),
-        &ticket,
+        ticket.as_bytes(),
         comm_d_safe,
         &porep_config.porep_id,
     );
@@ -443,10 +873,10 @@ pub fn seal_commit_phase1_inner<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>
             comm_r: comm_r_safe,
         }),
         k: None,
-        seed,
+        seed: seed.map(Ticket::into_bytes),
     };
 
-    let private_inputs = stacked::PrivateInputs::<Tree, DefaultPieceHasher> {
+    let private_inputs = stacked::PrivateInputs::<Tree, G> {
         p_aux,
         t_aux: t_aux_cache,
     };
@@ -457,8 +887,8 @@ pub fn seal_commit_phase1_inner<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>
         priority: false,
     };
 
-    let compound_public_params = <StackedCompound<Tree, DefaultPieceHasher> as CompoundProof<
-        StackedDrg<'_, Tree, DefaultPieceHasher>,
+    let compound_public_params = <StackedCompound<Tree, G> as CompoundProof<
+        StackedDrg<'_, Tree, G>,
         _,
     >>::setup(&compound_setup_params)?;
 
@@ -469,7 +899,7 @@ pub fn seal_commit_phase1_inner<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>
         StackedCompound::partition_count(&compound_public_params),
     )?;
 
-    let sanity_check = StackedDrg::<Tree, DefaultPieceHasher>::verify_all_partitions(
+    let sanity_check = StackedDrg::<Tree, G>::verify_all_partitions(
         &compound_public_params.vanilla_params,
         &public_inputs,
         &vanilla_proofs,
@@ -498,6 +928,30 @@ pub fn seal_commit_phase2<Tree: 'static + MerkleTreeTrait>(
     prover_id: ProverId,
     sector_id: SectorId,
 ) -> Result<SealCommitOutput> {
+    seal_commit_phase2_with_piece_hasher::<Tree, DefaultPieceHasher>(
+        porep_config,
+        phase1_output,
+        prover_id,
+        sector_id,
+    )
+}
+
+/// Like [`seal_commit_phase2`], for a sector whose `tree_d` is built with `G`, see
+/// [`PoRepConfig::piece_hasher`].
+pub fn seal_commit_phase2_with_piece_hasher<Tree, G>(
+    porep_config: &PoRepConfig,
+    phase1_output: SealCommitPhase1Output<Tree, G>,
+    prover_id: ProverId,
+    sector_id: SectorId,
+) -> Result<SealCommitOutput>
+where
+    Tree: 'static + MerkleTreeTrait,
+    G: 'static + Hasher,
+{
+    porep_config.check_piece_hasher::<G>()?;
+    let _span = info_span!("seal_commit_phase2", sector_id = u64::from(sector_id)).entered();
+    let timer = StageTimer::start("commit_phase2");
+    let _priority = enter_stage(ProvingPriority::Background);
     info!("seal_commit_phase2:start: {:?}", sector_id);
 
     let SealCommitPhase1Output {
@@ -521,7 +975,7 @@ pub fn seal_commit_phase2<Tree: 'static + MerkleTreeTrait>(
     );
 
     let comm_r_safe = as_safe_commitment(&comm_r, "comm_r")?;
-    let comm_d_safe = DefaultPieceDomain::try_from_bytes(&comm_d)?;
+    let comm_d_safe = G::Domain::try_from_bytes(&comm_d)?;
 
     let public_inputs = stacked::PublicInputs {
         replica_id,
@@ -530,10 +984,10 @@ pub fn seal_commit_phase2<Tree: 'static + MerkleTreeTrait>(
             comm_r: comm_r_safe,
         }),
         k: None,
-        seed: Some(seed),
+        seed: Some(seed.into_bytes()),
     };
 
-    let groth_params = get_stacked_params::<Tree>(porep_config)?;
+    let groth_params = get_stacked_params_with_piece_hasher::<Tree, G>(porep_config)?;
 
     trace!(
         "got groth params ({}) while sealing",
@@ -546,31 +1000,49 @@ pub fn seal_commit_phase2<Tree: 'static + MerkleTreeTrait>(
         priority: false,
     };
 
-    let compound_public_params = <StackedCompound<Tree, DefaultPieceHasher> as CompoundProof<
-        StackedDrg<'_, Tree, DefaultPieceHasher>,
+    let compound_public_params = <StackedCompound<Tree, G> as CompoundProof<
+        StackedDrg<'_, Tree, G>,
         _,
     >>::setup(&compound_setup_params)?;
 
+    if SETTINGS.check_circuit_before_proving {
+        let circuit =
+            <StackedCompound<Tree, G> as CompoundProof<StackedDrg<'_, Tree, G>, _>>::circuit(
+                &public_inputs,
+                Default::default(),
+                &vanilla_proofs[0],
+                &compound_public_params.vanilla_params,
+                Some(0),
+            )?;
+        let inputs = <StackedCompound<Tree, G> as CompoundProof<
+            StackedDrg<'_, Tree, G>,
+            _,
+        >>::generate_public_inputs(
+            &public_inputs,
+            &compound_public_params.vanilla_params,
+            Some(0),
+        )?;
+        check_circuit_satisfied(circuit, &inputs)
+            .context("seal circuit of partition 0 is not satisfied")?;
+    }
+
     trace!("snark_proof:start");
-    let groth_proofs = StackedCompound::<Tree, DefaultPieceHasher>::circuit_proofs(
-        &public_inputs,
-        vanilla_proofs,
-        &compound_public_params.vanilla_params,
-        &groth_params,
-        compound_public_params.priority,
-    )?;
+    let groth_proofs = metrics::time_proof("seal", || {
+        StackedCompound::<Tree, G>::circuit_proofs(
+            &public_inputs,
+            vanilla_proofs,
+            &compound_public_params.vanilla_params,
+            &groth_params,
+            compound_public_params.priority,
+        )
+    })?;
     trace!("snark_proof:finish");
 
-    let verifying_key = get_stacked_verifying_key::<Tree>(porep_config)?;
-    let proof = MultiProof::new(groth_proofs, &verifying_key);
-    let mut buf =
-        Vec::with_capacity(SINGLE_PARTITION_PROOF_LEN * usize::from(porep_config.partitions));
-
-    proof.write(&mut buf)?;
+    let buf = codec::encode_groth16_proofs(&groth_proofs)?;
 
     // Verification is cheap when parameters are cached,
     // and it is never correct to return a proof which does not verify.
-    verify_seal::<Tree>(
+    verify_seal_with_piece_hasher::<Tree, G>(
         porep_config,
         comm_r,
         comm_d,
@@ -583,9 +1055,10 @@ pub fn seal_commit_phase2<Tree: 'static + MerkleTreeTrait>(
     .context("post-seal verification sanity check failed")?;
 
     let out = SealCommitOutput { proof: buf };
+    metrics::seal_completed();
 
     info!("seal_commit_phase2:finish: {:?}", sector_id);
-    Ok(out)
+    timer.finish(Ok(out))
 }
 
 /// Given the specified arguments, this method returns the inputs that were used to
@@ -614,14 +1087,15 @@ pub fn get_seal_inputs<Tree: 'static + MerkleTreeTrait>(
     seed: Ticket,
 ) -> Result<Vec<Vec<Fr>>> {
     trace!("get_seal_inputs:start");
+    porep_config.check_piece_hasher::<DefaultPieceHasher>()?;
 
     ensure!(comm_d != [0; 32], "Invalid all zero commitment (comm_d)");
     ensure!(comm_r != [0; 32], "Invalid all zero commitment (comm_r)");
 
     let replica_id = generate_replica_id::<Tree::Hasher, _>(
-        &prover_id,
+        prover_id.as_bytes(),
         sector_id.into(),
-        &ticket,
+        ticket.as_bytes(),
         comm_d,
         &porep_config.porep_id,
     );
@@ -636,7 +1110,7 @@ pub fn get_seal_inputs<Tree: 'static + MerkleTreeTrait>(
             comm_r: comm_r_safe,
         }),
         k: None,
-        seed: Some(seed),
+        seed: Some(seed.into_bytes()),
     };
 
     let compound_setup_params = compound_proof::SetupParams {
@@ -761,11 +1235,12 @@ fn pad_inputs_to_target(
 pub fn aggregate_seal_commit_proofs<Tree: 'static + MerkleTreeTrait>(
     porep_config: &PoRepConfig,
     comm_rs: &[[u8; 32]],
-    seeds: &[[u8; 32]],
+    seeds: &[Ticket],
     commit_outputs: &[SealCommitOutput],
     aggregate_version: groth16::aggregate::AggregateVersion,
 ) -> Result<AggregateSnarkProof> {
     info!("aggregate_seal_commit_proofs:start");
+    porep_config.check_piece_hasher::<DefaultPieceHasher>()?;
 
     ensure!(
         !commit_outputs.is_empty(),
@@ -778,14 +1253,10 @@ pub fn aggregate_seal_commit_proofs<Tree: 'static + MerkleTreeTrait>(
         commit_outputs
             .iter()
             .try_fold(Vec::new(), |mut acc, commit_output| -> Result<_> {
-                acc.extend(
-                    MultiProof::new_from_reader(
-                        Some(partitions),
-                        &commit_output.proof[..],
-                        &verifying_key,
-                    )?
-                    .circuit_proofs,
-                );
+                acc.extend(codec::decode_partition_proofs(
+                    &commit_output.proof,
+                    partitions,
+                )?);
 
                 Ok(acc)
             })?;
@@ -826,8 +1297,7 @@ pub fn aggregate_seal_commit_proofs<Tree: 'static + MerkleTreeTrait>(
         proofs.as_slice(),
         aggregate_version,
     )?;
-    let mut aggregate_proof_bytes = Vec::new();
-    aggregate_proof.write(&mut aggregate_proof_bytes)?;
+    let aggregate_proof_bytes = codec::encode_aggregate_proof(&aggregate_proof)?;
 
     info!("aggregate_seal_commit_proofs:finish");
 
@@ -848,14 +1318,14 @@ pub fn verify_aggregate_seal_commit_proofs<Tree: 'static + MerkleTreeTrait>(
     porep_config: &PoRepConfig,
     aggregate_proof_bytes: AggregateSnarkProof,
     comm_rs: &[[u8; 32]],
-    seeds: &[[u8; 32]],
+    seeds: &[Ticket],
     commit_inputs: Vec<Vec<Fr>>,
     aggregate_version: groth16::aggregate::AggregateVersion,
 ) -> Result<bool> {
     info!("verify_aggregate_seal_commit_proofs:start");
+    porep_config.check_piece_hasher::<DefaultPieceHasher>()?;
 
-    let aggregate_proof =
-        groth16::aggregate::AggregateProof::read(std::io::Cursor::new(&aggregate_proof_bytes))?;
+    let aggregate_proof = codec::decode_aggregate_proof(&aggregate_proof_bytes)?;
 
     let aggregated_proofs_len = aggregate_proof.tmipp.gipa.nproofs as usize;
 
@@ -944,6 +1414,24 @@ pub fn compute_comm_d(sector_size: SectorSize, piece_infos: &[PieceInfo]) -> Res
     result
 }
 
+/// Returns the public inputs of the Groth16 proofs of a seal, one vector per partition, in the
+/// order that [`verify_seal`] passes them to the verifier. This is [`get_seal_inputs`] for the
+/// inputs bundled in a [`SealPublicInputs`].
+pub fn public_inputs_for_seal<Tree: 'static + MerkleTreeTrait>(
+    porep_config: &PoRepConfig,
+    pub_inputs: &SealPublicInputs,
+) -> Result<Vec<Vec<Fr>>> {
+    get_seal_inputs::<Tree>(
+        porep_config,
+        pub_inputs.comm_r,
+        pub_inputs.comm_d,
+        pub_inputs.prover_id,
+        pub_inputs.sector_id,
+        pub_inputs.ticket,
+        pub_inputs.seed,
+    )
+}
+
 /// Verifies the output of some previously-run seal operation.
 ///
 /// # Arguments
@@ -967,19 +1455,49 @@ pub fn verify_seal<Tree: 'static + MerkleTreeTrait>(
     seed: Ticket,
     proof_vec: &[u8],
 ) -> Result<bool> {
+    verify_seal_with_piece_hasher::<Tree, DefaultPieceHasher>(
+        porep_config,
+        comm_r_in,
+        comm_d_in,
+        prover_id,
+        sector_id,
+        ticket,
+        seed,
+        proof_vec,
+    )
+}
+
+/// Like [`verify_seal`], for a sector whose `tree_d` is built with `G`, see
+/// [`PoRepConfig::piece_hasher`].
+#[allow(clippy::too_many_arguments)]
+pub fn verify_seal_with_piece_hasher<Tree, G>(
+    porep_config: &PoRepConfig,
+    comm_r_in: Commitment,
+    comm_d_in: Commitment,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    seed: Ticket,
+    proof_vec: &[u8],
+) -> Result<bool>
+where
+    Tree: 'static + MerkleTreeTrait,
+    G: 'static + Hasher,
+{
     info!("verify_seal:start: {:?}", sector_id);
+    porep_config.check_piece_hasher::<G>()?;
 
     ensure!(comm_d_in != [0; 32], "Invalid all zero commitment (comm_d)");
     ensure!(comm_r_in != [0; 32], "Invalid all zero commitment (comm_r)");
     ensure!(!proof_vec.is_empty(), "Invalid proof bytes (empty vector)");
 
     let comm_r: <Tree::Hasher as Hasher>::Domain = as_safe_commitment(&comm_r_in, "comm_r")?;
-    let comm_d: DefaultPieceDomain = as_safe_commitment(&comm_d_in, "comm_d")?;
+    let comm_d: G::Domain = as_safe_commitment(&comm_d_in, "comm_d")?;
 
     let replica_id = generate_replica_id::<Tree::Hasher, _>(
-        &prover_id,
+        prover_id.as_bytes(),
         sector_id.into(),
-        &ticket,
+        ticket.as_bytes(),
         comm_d,
         &porep_config.porep_id,
     );
@@ -990,41 +1508,36 @@ pub fn verify_seal<Tree: 'static + MerkleTreeTrait>(
         priority: false,
     };
 
-    let compound_public_params: compound_proof::PublicParams<
-        '_,
-        StackedDrg<'_, Tree, DefaultPieceHasher>,
-    > = StackedCompound::setup(&compound_setup_params)?;
+    let compound_public_params: compound_proof::PublicParams<'_, StackedDrg<'_, Tree, G>> =
+        StackedCompound::setup(&compound_setup_params)?;
 
-    let public_inputs =
-        stacked::PublicInputs::<<Tree::Hasher as Hasher>::Domain, DefaultPieceDomain> {
-            replica_id,
-            tau: Some(Tau { comm_r, comm_d }),
-            seed: Some(seed),
-            k: None,
-        };
+    let public_inputs = stacked::PublicInputs::<<Tree::Hasher as Hasher>::Domain, G::Domain> {
+        replica_id,
+        tau: Some(Tau { comm_r, comm_d }),
+        seed: Some(seed.into_bytes()),
+        k: None,
+    };
 
     let result = {
         let sector_bytes = porep_config.padded_bytes_amount();
-        let verifying_key = get_stacked_verifying_key::<Tree>(porep_config)?;
+        let verifying_key = get_stacked_verifying_key_with_piece_hasher::<Tree, G>(porep_config)?;
 
         trace!(
             "got verifying key ({}) while verifying seal",
             u64::from(sector_bytes)
         );
 
-        let proof = MultiProof::new_from_reader(
-            Some(usize::from(porep_config.partitions)),
-            proof_vec,
+        let proof = MultiProof::new(
+            codec::decode_partition_proofs(proof_vec, usize::from(porep_config.partitions))?,
             &verifying_key,
-        )?;
+        );
 
         StackedCompound::verify(
             &compound_public_params,
             &public_inputs,
             &proof,
             &ChallengeRequirements {
-                minimum_challenges: POREP_MINIMUM_CHALLENGES
-                    .from_sector_size(u64::from(porep_config.sector_size)),
+                minimum_challenges: porep_config.minimum_challenges,
             },
         )
     };
@@ -1033,6 +1546,102 @@ pub fn verify_seal<Tree: 'static + MerkleTreeTrait>(
     result
 }
 
+/// Verifies the vanilla proofs of a synthetic porep sector, without generating or verifying a
+/// SNARK, so that a faulty sealing pipeline is detected before spending GPU time on commit phase 2.
+///
+/// # Arguments
+///
+/// * `porep_config` - this sector's porep config, synth-porep must be enabled.
+/// * `pub_inputs` - the commitments, replica id inputs and porep challenge seed of the sector.
+/// * `vanilla_proof_bytes` - the synthetic vanilla proofs, in the format written by
+///   [`generate_synth_proofs`]; the proofs of the porep challenges selected by the seed are read
+///   from it and verified.
+pub fn verify_seal_vanilla<Tree: 'static + MerkleTreeTrait>(
+    porep_config: &PoRepConfig,
+    pub_inputs: &SealPublicInputs,
+    vanilla_proof_bytes: &[u8],
+) -> Result<bool> {
+    let SealPublicInputs {
+        comm_r,
+        comm_d,
+        prover_id,
+        sector_id,
+        ticket,
+        seed,
+    } = *pub_inputs;
+    info!("verify_seal_vanilla:start: {:?}", sector_id);
+
+    ensure!(
+        porep_config.feature_enabled(ApiFeature::SyntheticPoRep),
+        "synth-porep must be enabled to verify synthetic vanilla proofs",
+    );
+    porep_config.check_piece_hasher::<DefaultPieceHasher>()?;
+    ensure!(comm_d != [0; 32], "Invalid all zero commitment (comm_d)");
+    ensure!(comm_r != [0; 32], "Invalid all zero commitment (comm_r)");
+
+    let comm_r_safe: <Tree::Hasher as Hasher>::Domain = as_safe_commitment(&comm_r, "comm_r")?;
+    let comm_d_safe: DefaultPieceDomain = as_safe_commitment(&comm_d, "comm_d")?;
+
+    let replica_id = generate_replica_id::<Tree::Hasher, _>(
+        prover_id.as_bytes(),
+        sector_id.into(),
+        ticket.as_bytes(),
+        comm_d_safe,
+        &porep_config.porep_id,
+    );
+
+    let public_inputs = stacked::PublicInputs {
+        replica_id,
+        tau: Some(Tau {
+            comm_d: comm_d_safe,
+            comm_r: comm_r_safe,
+        }),
+        k: None,
+        seed: Some(seed.into_bytes()),
+    };
+    let pub_params = public_params::<Tree>(porep_config)?;
+    let layer_challenges = &pub_params.layer_challenges;
+    let sector_nodes = pub_params.graph.size();
+    let num_layers = layer_challenges.layers();
+
+    let proof_size = SynthProofs::proof_size::<Tree>(sector_nodes, num_layers);
+    let proofs_len = vanilla_proof_bytes.len().checked_sub(3 * NODE_SIZE);
+    ensure!(
+        matches!(proofs_len, Some(len) if len % proof_size == 0),
+        "Invalid synthetic vanilla proofs size {}",
+        vanilla_proof_bytes.len()
+    );
+
+    let mut reader = Cursor::new(vanilla_proof_bytes);
+    let vanilla_proofs = (0..usize::from(porep_config.partitions) as u8)
+        .map(|k| {
+            let synth_indexes = layer_challenges.derive_synth_indexes(
+                sector_nodes,
+                &replica_id,
+                &comm_r_safe,
+                seed.as_bytes(),
+                k,
+            );
+            SynthProofs::read::<Tree, DefaultPieceHasher, _>(
+                &mut reader,
+                sector_nodes,
+                num_layers,
+                synth_indexes.into_iter(),
+            )
+            .with_context(|| format!("failed to read partition k={} synthetic proofs", k))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let result = StackedDrg::<Tree, DefaultPieceHasher>::verify_all_partitions(
+        &pub_params,
+        &public_inputs,
+        &vanilla_proofs,
+    );
+
+    info!("verify_seal_vanilla:finish: {:?}", sector_id);
+    result
+}
+
 /// Verifies a batch of outputs of some previously-run seal operations.
 ///
 /// # Arguments
@@ -1057,11 +1666,11 @@ pub fn verify_batch_seal<Tree: 'static + MerkleTreeTrait>(
     proof_vecs: &[&[u8]],
 ) -> Result<bool> {
     info!("verify_batch_seal:start");
+    porep_config.check_piece_hasher::<DefaultPieceHasher>()?;
     ensure!(!comm_r_ins.is_empty(), "Cannot prove empty batch");
     let l = comm_r_ins.len();
     ensure!(l == comm_d_ins.len(), "Inconsistent inputs");
     ensure!(l == prover_ids.len(), "Inconsistent inputs");
-    ensure!(l == prover_ids.len(), "Inconsistent inputs");
     ensure!(l == sector_ids.len(), "Inconsistent inputs");
     ensure!(l == tickets.len(), "Inconsistent inputs");
     ensure!(l == seeds.len(), "Inconsistent inputs");
@@ -1110,9 +1719,9 @@ pub fn verify_batch_seal<Tree: 'static + MerkleTreeTrait>(
         let comm_d = as_safe_commitment(&comm_d_ins[i], "comm_d")?;
 
         let replica_id = generate_replica_id::<Tree::Hasher, _>(
-            &prover_ids[i],
+            prover_ids[i].as_bytes(),
             sector_ids[i].into(),
-            &tickets[i],
+            tickets[i].as_bytes(),
             comm_d,
             &porep_config.porep_id,
         );
@@ -1123,14 +1732,13 @@ pub fn verify_batch_seal<Tree: 'static + MerkleTreeTrait>(
         > {
             replica_id,
             tau: Some(Tau { comm_r, comm_d }),
-            seed: Some(seeds[i]),
+            seed: Some(seeds[i].into_bytes()),
             k: None,
         });
-        proofs.push(MultiProof::new_from_reader(
-            Some(usize::from(porep_config.partitions)),
-            proof_vecs[i],
+        proofs.push(MultiProof::new(
+            codec::decode_partition_proofs(proof_vecs[i], usize::from(porep_config.partitions))?,
             &verifying_key,
-        )?);
+        ));
     }
 
     let result = StackedCompound::<Tree, DefaultPieceHasher>::batch_verify(
@@ -1138,8 +1746,7 @@ pub fn verify_batch_seal<Tree: 'static + MerkleTreeTrait>(
         &public_inputs,
         &proofs,
         &ChallengeRequirements {
-            minimum_challenges: POREP_MINIMUM_CHALLENGES
-                .from_sector_size(u64::from(porep_config.sector_size)),
+            minimum_challenges: porep_config.minimum_challenges,
         },
     )
     .map_err(Into::into);
@@ -1148,6 +1755,36 @@ pub fn verify_batch_seal<Tree: 'static + MerkleTreeTrait>(
     result
 }
 
+/// Verifies a batch of seal proofs of sectors that were sealed with `porep_config`.
+///
+/// The Groth16 proofs of all sectors are checked at once with a random linear combination, which
+/// is several times faster than verifying them one by one with [`verify_seal`]. The result is
+/// `false` if any of the proofs is invalid, the batch can then be bisected to find it.
+pub fn verify_batch_seal_infos<Tree: 'static + MerkleTreeTrait>(
+    porep_config: &PoRepConfig,
+    infos: &[SealVerifyInfo],
+) -> Result<bool> {
+    let inputs: Vec<&SealPublicInputs> = infos.iter().map(|info| &info.pub_inputs).collect();
+    let comm_r_ins: Vec<Commitment> = inputs.iter().map(|inputs| inputs.comm_r).collect();
+    let comm_d_ins: Vec<Commitment> = inputs.iter().map(|inputs| inputs.comm_d).collect();
+    let prover_ids: Vec<ProverId> = inputs.iter().map(|inputs| inputs.prover_id).collect();
+    let sector_ids: Vec<SectorId> = inputs.iter().map(|inputs| inputs.sector_id).collect();
+    let tickets: Vec<Ticket> = inputs.iter().map(|inputs| inputs.ticket).collect();
+    let seeds: Vec<Ticket> = inputs.iter().map(|inputs| inputs.seed).collect();
+    let proof_vecs: Vec<&[u8]> = infos.iter().map(|info| info.proof.as_slice()).collect();
+
+    verify_batch_seal::<Tree>(
+        porep_config,
+        &comm_r_ins,
+        &comm_d_ins,
+        &prover_ids,
+        &sector_ids,
+        &tickets,
+        &seeds,
+        &proof_vecs,
+    )
+}
+
 /// Generate the merkle tree on top of the replica (TreeRLast).
 ///
 /// The generated trees are stored in `output_dir`, usually the cache directory. The `replica_path`
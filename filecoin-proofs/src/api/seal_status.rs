@@ -0,0 +1,220 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use filecoin_hashers::Hasher;
+use log::info;
+use merkletree::{merkle::get_merkle_tree_len, store::StoreConfig};
+use serde::{Deserialize, Serialize};
+use storage_proofs_core::{
+    cache_key::CacheKey,
+    merkle::{create_disk_tree, get_base_tree_count, split_config, MerkleTreeTrait},
+};
+use storage_proofs_porep::stacked::{
+    Labels, PersistentAux, SynthProofsFile, TemporaryAux, TemporaryAuxCache, BINARY_ARITY,
+    SYNTHETIC_POREP_VANILLA_PROOFS_EXT, SYNTHETIC_POREP_VANILLA_PROOFS_KEY,
+};
+use typenum::Unsigned;
+
+use crate::{
+    api::{
+        store_exists, util, verify_level_cache_store, verify_store, SectorState, WITHOUT_TREE_D,
+    },
+    constants::{DefaultBinaryTree, DefaultOctTree, DefaultPieceHasher},
+    parameters::setup_params,
+    types::PoRepConfig,
+};
+
+/// The sealing artifacts of a sector that are present and consistent on disk, see
+/// [`get_seal_status`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SealStatus {
+    /// Whether the labels of a layer are present and consistent, the first entry is layer 1.
+    pub labels: Vec<bool>,
+    pub tree_d: bool,
+    /// Whether the sector is marked as sealed without tree_d, see
+    /// [`seal_pre_commit_phase1_without_tree_d`](crate::seal_pre_commit_phase1_without_tree_d).
+    pub without_tree_d: bool,
+    /// tree_c may have been cleared after pre-commit phase 2, it is then regenerated from the
+    /// labels.
+    pub tree_c: bool,
+    pub tree_r_last: bool,
+    /// Whether the replica exists and has the size of the sector.
+    pub replica: bool,
+    pub t_aux: bool,
+    /// `None` if there is no readable p_aux, otherwise whether its `comm_r_last` matches the root
+    /// of tree_r_last, and its `comm_c` the root of tree_c if it is present.
+    pub p_aux: Option<bool>,
+    /// Whether a synthetic vanilla proofs file of a valid size is present.
+    pub synth_proofs: bool,
+}
+
+impl SealStatus {
+    /// Returns whether the labels of all layers are present.
+    pub fn labels_complete(&self) -> bool {
+        self.labels.iter().all(|layer| *layer)
+    }
+
+    /// Returns the last state whose outputs are all present, sealing can be resumed with the
+    /// stage that follows it. `seal_pre_commit_phase1` must be re-run after `Staged`, since its
+    /// output is not stored in the cache directory.
+    pub fn sector_state(&self) -> SectorState {
+        let pre_commit1 = self.labels_complete() && (self.tree_d || self.without_tree_d);
+        let pre_commit2 = self.replica
+            && self.t_aux
+            && self.tree_r_last
+            && self.p_aux == Some(true)
+            && (self.tree_c || self.labels_complete() || self.synth_proofs);
+
+        if pre_commit2 {
+            SectorState::PreCommit2
+        } else if pre_commit1 {
+            SectorState::PreCommit1
+        } else {
+            SectorState::Staged
+        }
+    }
+}
+
+/// Reports which sealing artifacts of the sector sealed into `replica_path` are present in
+/// `cache_path` and consistent with each other.
+///
+/// Missing or inconsistent artifacts are reported in the status rather than as an error, an error
+/// is only returned if `porep_config` is invalid.
+pub fn get_seal_status<Tree: 'static + MerkleTreeTrait>(
+    porep_config: &PoRepConfig,
+    cache_path: &Path,
+    replica_path: &Path,
+) -> Result<SealStatus> {
+    info!("get_seal_status:start");
+
+    let params = setup_params(porep_config)?;
+    let sector_bytes = u64::from(porep_config.sector_size);
+    let tree_count = get_base_tree_count::<Tree>();
+
+    let has_t_aux = cache_path.join(CacheKey::TAux.to_string()).exists();
+    // Without t_aux (i.e. before pre-commit phase 2), the stores are looked up at their default
+    // locations.
+    let t_aux = util::get_t_aux::<Tree>(cache_path, sector_bytes, porep_config.layers)
+        .unwrap_or_else(|_| {
+            default_t_aux::<Tree>(cache_path, params.nodes, params.layer_challenges.layers())
+        });
+
+    let labels = t_aux
+        .labels
+        .labels
+        .iter()
+        .map(|config| verify_store(config, BINARY_ARITY, tree_count).is_ok())
+        .collect();
+    let tree_d = verify_store(
+        &t_aux.tree_d_config,
+        <DefaultBinaryTree as MerkleTreeTrait>::Arity::to_usize(),
+        tree_count,
+    )
+    .is_ok();
+    let without_tree_d = cache_path.join(WITHOUT_TREE_D).exists();
+    let tree_c = store_exists(&t_aux.tree_c_config)
+        && verify_store(
+            &t_aux.tree_c_config,
+            <DefaultOctTree as MerkleTreeTrait>::Arity::to_usize(),
+            tree_count,
+        )
+        .is_ok();
+    let tree_r_last =
+        has_t_aux && verify_level_cache_store::<DefaultOctTree>(&t_aux.tree_r_last_config).is_ok();
+    let replica = fs::metadata(replica_path)
+        .map(|metadata| metadata.is_file() && metadata.len() == sector_bytes)
+        .unwrap_or(false);
+
+    let p_aux = util::get_p_aux::<Tree>(cache_path).ok().map(|p_aux| {
+        tree_r_last
+            && replica
+            && p_aux_matches(&p_aux, &t_aux, replica_path, tree_c).unwrap_or(false)
+    });
+
+    let synth_proofs_path = cache_path.join(format!(
+        "{}.{}",
+        SYNTHETIC_POREP_VANILLA_PROOFS_KEY, SYNTHETIC_POREP_VANILLA_PROOFS_EXT
+    ));
+    let synth_proofs = SynthProofsFile::<Tree, DefaultPieceHasher>::open(
+        synth_proofs_path,
+        params.nodes,
+        params.layer_challenges.layers(),
+    )
+    .map(|file| !file.is_empty())
+    .unwrap_or(false);
+
+    info!("get_seal_status:finish");
+    Ok(SealStatus {
+        labels,
+        tree_d,
+        without_tree_d,
+        tree_c,
+        tree_r_last,
+        replica,
+        t_aux: has_t_aux,
+        p_aux,
+        synth_proofs,
+    })
+}
+
+// The store configs that sealing uses when none are configured otherwise.
+pub(crate) fn default_t_aux<Tree: MerkleTreeTrait>(
+    cache_path: &Path,
+    sector_nodes: usize,
+    num_layers: usize,
+) -> TemporaryAux<Tree, DefaultPieceHasher> {
+    let store_config = |id: String, size: Option<usize>| StoreConfig {
+        path: cache_path.to_path_buf(),
+        id,
+        size,
+        rows_to_discard: 0,
+    };
+    let tree_size = get_merkle_tree_len(
+        sector_nodes / get_base_tree_count::<Tree>(),
+        Tree::Arity::to_usize(),
+    )
+    .ok();
+
+    TemporaryAux {
+        labels: Labels::new(
+            (1..=num_layers)
+                .map(|layer| store_config(CacheKey::label_layer(layer), Some(sector_nodes)))
+                .collect(),
+        ),
+        tree_d_config: store_config(
+            CacheKey::CommDTree.to_string(),
+            get_merkle_tree_len(sector_nodes, BINARY_ARITY).ok(),
+        ),
+        tree_r_last_config: store_config(CacheKey::CommRLastTree.to_string(), tree_size),
+        tree_c_config: store_config(CacheKey::CommCTree.to_string(), tree_size),
+        _g: Default::default(),
+    }
+}
+
+// Checks the commitments of p_aux against the roots of tree_c (if present) and tree_r_last.
+fn p_aux_matches<Tree: 'static + MerkleTreeTrait>(
+    p_aux: &PersistentAux<<Tree::Hasher as Hasher>::Domain>,
+    t_aux: &TemporaryAux<Tree, DefaultPieceHasher>,
+    replica_path: &Path,
+    tree_c: bool,
+) -> Result<bool> {
+    if tree_c {
+        let tree_c_size = t_aux
+            .tree_c_config
+            .size
+            .context("tree_c size not configured")?;
+        let configs = split_config(t_aux.tree_c_config.clone(), get_base_tree_count::<Tree>())?;
+        let tree_c = create_disk_tree::<Tree>(tree_c_size, &configs)?;
+        if tree_c.root() != p_aux.comm_c {
+            return Ok(false);
+        }
+    }
+
+    let t_aux_cache = TemporaryAuxCache::<Tree, DefaultPieceHasher>::new(
+        t_aux,
+        replica_path.to_path_buf(),
+        true,
+    )?;
+    Ok(t_aux_cache.tree_r_last.root() == p_aux.comm_r_last)
+}
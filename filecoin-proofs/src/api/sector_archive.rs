@@ -0,0 +1,347 @@
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{bail, ensure, Context, Result};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::api::sector_manifest::list_files;
+
+const SECTOR_ARCHIVE_MAGIC: &[u8; 8] = b"FILCACHE";
+const SECTOR_ARCHIVE_VERSION: u32 = 1;
+const FLAG_GZIP: u32 = 1;
+
+/// Files are streamed in chunks of this size, chunks that are all zeros are stored as holes.
+const CHUNK_SIZE: usize = 1 << 20;
+const CHUNK_DATA: u8 = 0;
+const CHUNK_HOLE: u8 = 1;
+
+/// An upper bound for the index, so that a corrupted archive does not allocate arbitrary memory.
+const MAX_INDEX_LEN: u64 = 64 << 20;
+
+/// The compression of the body of a sector cache archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveCompression {
+    None,
+    Gzip,
+}
+
+/// A file within a sector cache archive.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SectorArchiveEntry {
+    /// The path relative to the cache directory.
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+/// Writes all files of the cache directory as a single archive to `writer`, e.g. to move a sector
+/// between machines without touching the files one by one.
+///
+/// The archive is written front to back and can be read the same way, so it can be piped through
+/// a socket. It consists of a header, the index of all files and then the contents of the files
+/// in index order. Runs of zeros, like the unused regions of sparse `LevelCache` trees, are stored
+/// as holes and are not written on import, so sparse files stay sparse.
+pub fn export_sector_cache<W: Write>(
+    cache_dir: &Path,
+    writer: W,
+    compression: ArchiveCompression,
+) -> Result<Vec<SectorArchiveEntry>> {
+    info!("export_sector_cache:start: {:?}", cache_dir);
+
+    let entries = list_files(cache_dir)?
+        .into_iter()
+        .map(|path| {
+            let size = fs::metadata(cache_dir.join(&path))
+                .with_context(|| format!("could not stat {:?}", path))?
+                .len();
+            Ok(SectorArchiveEntry { path, size })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut writer = BufWriter::new(writer);
+    writer.write_all(SECTOR_ARCHIVE_MAGIC)?;
+    writer.write_all(&SECTOR_ARCHIVE_VERSION.to_le_bytes())?;
+    let flags = match compression {
+        ArchiveCompression::None => 0,
+        ArchiveCompression::Gzip => FLAG_GZIP,
+    };
+    writer.write_all(&flags.to_le_bytes())?;
+
+    match compression {
+        ArchiveCompression::None => {
+            write_body(cache_dir, &entries, &mut writer)?;
+        }
+        ArchiveCompression::Gzip => {
+            let mut encoder = GzEncoder::new(&mut writer, Compression::fast());
+            write_body(cache_dir, &entries, &mut encoder)?;
+            encoder.finish()?;
+        }
+    }
+    writer.flush()?;
+
+    info!(
+        "export_sector_cache:finish: {:?}, {} files",
+        cache_dir,
+        entries.len()
+    );
+    Ok(entries)
+}
+
+fn write_body<W: Write>(
+    cache_dir: &Path,
+    entries: &[SectorArchiveEntry],
+    writer: &mut W,
+) -> Result<()> {
+    let index = serde_json::to_vec(entries)?;
+    writer.write_all(&(index.len() as u64).to_le_bytes())?;
+    writer.write_all(&index)?;
+
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    for entry in entries {
+        let path = cache_dir.join(&entry.path);
+        let mut file = File::open(&path).with_context(|| format!("could not open {:?}", path))?;
+
+        let mut remaining = entry.size;
+        while remaining > 0 {
+            let len = remaining.min(CHUNK_SIZE as u64) as usize;
+            file.read_exact(&mut buf[..len])
+                .with_context(|| format!("{:?} was truncated while exporting", path))?;
+            if buf[..len].iter().all(|byte| *byte == 0) {
+                writer.write_all(&[CHUNK_HOLE])?;
+                writer.write_all(&(len as u32).to_le_bytes())?;
+            } else {
+                writer.write_all(&[CHUNK_DATA])?;
+                writer.write_all(&(len as u32).to_le_bytes())?;
+                writer.write_all(&buf[..len])?;
+            }
+            remaining -= len as u64;
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads an archive written by [`export_sector_cache`] from `reader` and restores its files
+/// within the cache directory, which is created if it does not exist. Existing files of the
+/// archive are overwritten, other files of the cache directory are left untouched.
+pub fn import_sector_cache<R: Read>(
+    reader: R,
+    cache_dir: &Path,
+) -> Result<Vec<SectorArchiveEntry>> {
+    info!("import_sector_cache:start: {:?}", cache_dir);
+
+    let mut reader = BufReader::new(reader);
+    let mut magic = [0u8; 8];
+    reader
+        .read_exact(&mut magic)
+        .context("could not read sector archive header")?;
+    ensure!(&magic == SECTOR_ARCHIVE_MAGIC, "not a sector cache archive");
+    let version = read_u32(&mut reader)?;
+    ensure!(
+        version == SECTOR_ARCHIVE_VERSION,
+        "unsupported sector archive version {}",
+        version
+    );
+    let flags = read_u32(&mut reader)?;
+    ensure!(
+        flags & !FLAG_GZIP == 0,
+        "unsupported sector archive flags {:#x}",
+        flags
+    );
+
+    let entries = if flags & FLAG_GZIP != 0 {
+        read_body(&mut GzDecoder::new(reader), cache_dir)?
+    } else {
+        read_body(&mut reader, cache_dir)?
+    };
+
+    info!(
+        "import_sector_cache:finish: {:?}, {} files",
+        cache_dir,
+        entries.len()
+    );
+    Ok(entries)
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Paths of the index must stay within the cache directory.
+fn check_entry_path(path: &Path) -> Result<()> {
+    ensure!(
+        path.components().count() > 0
+            && path
+                .components()
+                .all(|component| matches!(component, Component::Normal(_))),
+        "invalid path {:?} in sector archive",
+        path
+    );
+    Ok(())
+}
+
+fn read_body<R: Read>(reader: &mut R, cache_dir: &Path) -> Result<Vec<SectorArchiveEntry>> {
+    let index_len = read_u64(reader).context("could not read sector archive index")?;
+    ensure!(
+        index_len <= MAX_INDEX_LEN,
+        "sector archive index too large: {}",
+        index_len
+    );
+    let mut index = vec![0u8; index_len as usize];
+    reader
+        .read_exact(&mut index)
+        .context("could not read sector archive index")?;
+    let entries: Vec<SectorArchiveEntry> =
+        serde_json::from_slice(&index).context("invalid sector archive index")?;
+    for entry in &entries {
+        check_entry_path(&entry.path)?;
+    }
+    fs::create_dir_all(cache_dir).with_context(|| format!("could not create {:?}", cache_dir))?;
+
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    for entry in &entries {
+        let path = cache_dir.join(&entry.path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("could not create {:?}", parent))?;
+        }
+        let mut file =
+            File::create(&path).with_context(|| format!("could not create {:?}", path))?;
+
+        let mut remaining = entry.size;
+        while remaining > 0 {
+            let mut kind = [0u8; 1];
+            reader
+                .read_exact(&mut kind)
+                .with_context(|| format!("sector archive truncated at {:?}", entry.path))?;
+            let len = read_u32(reader)? as usize;
+            ensure!(
+                len > 0 && len <= CHUNK_SIZE && len as u64 <= remaining,
+                "invalid chunk length {} in {:?}",
+                len,
+                entry.path
+            );
+            match kind[0] {
+                CHUNK_DATA => {
+                    reader
+                        .read_exact(&mut buf[..len])
+                        .with_context(|| format!("sector archive truncated at {:?}", entry.path))?;
+                    file.write_all(&buf[..len])?;
+                }
+                CHUNK_HOLE => {
+                    file.seek(SeekFrom::Current(i64::try_from(len)?))?;
+                }
+                kind => bail!("invalid chunk kind {} in {:?}", kind, entry.path),
+            }
+            remaining -= len as u64;
+        }
+        // Trailing holes were only skipped over.
+        file.set_len(entry.size)?;
+        file.sync_all()
+            .with_context(|| format!("could not sync {:?}", path))?;
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use tempfile::tempdir;
+
+    fn write_cache(cache_dir: &Path) {
+        fs::create_dir_all(cache_dir.join("sub")).expect("failed to create cache dir");
+        fs::write(cache_dir.join("p_aux"), [2u8; 64]).expect("failed to write p_aux");
+        fs::write(cache_dir.join("empty"), []).expect("failed to write empty");
+
+        // A tree with a leading, an inner and a trailing hole.
+        let mut tree = vec![0u8; 3 * CHUNK_SIZE + 100];
+        tree[CHUNK_SIZE..CHUNK_SIZE + 10].copy_from_slice(&[3u8; 10]);
+        tree[2 * CHUNK_SIZE + 5] = 7;
+        fs::write(cache_dir.join("sub").join("tree"), tree).expect("failed to write tree");
+        fs::write(
+            cache_dir.join("sub").join("zeros"),
+            vec![0u8; CHUNK_SIZE + 1],
+        )
+        .expect("failed to write zeros");
+    }
+
+    #[test]
+    fn test_sector_archive_roundtrip() {
+        let dir = tempdir().expect("failed to create tempdir");
+        let cache_dir = dir.path().join("cache");
+        write_cache(&cache_dir);
+
+        for (i, compression) in [ArchiveCompression::None, ArchiveCompression::Gzip]
+            .iter()
+            .enumerate()
+        {
+            let mut archive = Vec::new();
+            let exported = export_sector_cache(&cache_dir, &mut archive, *compression)
+                .expect("failed to export");
+            assert_eq!(exported.len(), 4);
+            if *compression == ArchiveCompression::None {
+                // The holes are not part of the archive.
+                assert!(archive.len() < 2 * CHUNK_SIZE);
+            }
+
+            let imported_dir = dir.path().join(format!("imported-{}", i));
+            let imported = import_sector_cache(Cursor::new(&archive), &imported_dir)
+                .expect("failed to import");
+            assert_eq!(imported, exported);
+            for entry in &exported {
+                assert_eq!(
+                    fs::read(imported_dir.join(&entry.path)).expect("failed to read"),
+                    fs::read(cache_dir.join(&entry.path)).expect("failed to read"),
+                    "{:?} differs",
+                    entry.path
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_sector_archive_invalid() {
+        let dir = tempdir().expect("failed to create tempdir");
+        let cache_dir = dir.path().join("cache");
+        write_cache(&cache_dir);
+
+        let mut archive = Vec::new();
+        export_sector_cache(&cache_dir, &mut archive, ArchiveCompression::None)
+            .expect("failed to export");
+
+        let target = dir.path().join("target");
+        assert!(import_sector_cache(Cursor::new(&archive[..4]), &target).is_err());
+        assert!(import_sector_cache(Cursor::new(&archive[..archive.len() - 1]), &target).is_err());
+
+        let mut corrupted = archive.clone();
+        corrupted[0] = b'X';
+        assert!(import_sector_cache(Cursor::new(&corrupted), &target).is_err());
+
+        // An index that escapes the cache directory.
+        let index = serde_json::to_vec(&[SectorArchiveEntry {
+            path: PathBuf::from("../escaped"),
+            size: 1,
+        }])
+        .expect("failed to serialize");
+        let mut escaping = archive[..16].to_vec();
+        escaping.extend_from_slice(&(index.len() as u64).to_le_bytes());
+        escaping.extend_from_slice(&index);
+        escaping.extend_from_slice(&[CHUNK_DATA, 1, 0, 0, 0, 1]);
+        assert!(import_sector_cache(Cursor::new(&escaping), &target).is_err());
+        assert!(!dir.path().join("escaped").exists());
+    }
+}
@@ -0,0 +1,199 @@
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use anyhow::{ensure, Context, Result};
+use filecoin_hashers::{Domain, HashFunction, Hasher};
+use log::info;
+use merkletree::{merkle::get_merkle_tree_len, store::StoreConfig};
+use rand::seq::index;
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+use storage_proofs_core::{
+    cache_key::CacheKey,
+    merkle::{MerkleProofTrait, MerkleTreeTrait},
+    util::{default_rows_to_discard, NODE_SIZE},
+};
+use storage_proofs_porep::stacked::BINARY_ARITY;
+use typenum::Unsigned;
+
+use crate::{
+    api::{verify_sector_manifest, SECTOR_MANIFEST},
+    types::{Commitment, PoRepConfig, PrivateReplicaInfo},
+};
+
+/// The outcome of [`check_sector`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SectorCheck {
+    /// Whether the sector was checked against its sector manifest, it is skipped if there is
+    /// none.
+    pub manifest_checked: bool,
+    /// Whether comm_d was checked against tree_d, it is skipped if tree_d was cleared.
+    pub comm_d_checked: bool,
+    /// The number of leaves of tree_r_last whose opening was checked.
+    pub challenges_checked: usize,
+    /// A description of every check that failed.
+    pub diagnostics: Vec<String>,
+}
+
+impl SectorCheck {
+    /// Returns whether all checks passed.
+    pub fn is_ok(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+}
+
+// Returns the root of the tree_d in the cache directory, if it is complete.
+fn read_tree_d_root(cache_path: &Path, sector_nodes: usize) -> Result<Option<Commitment>> {
+    let tree_d_path =
+        StoreConfig::data_path(&cache_path.to_path_buf(), &CacheKey::CommDTree.to_string());
+    let tree_len = get_merkle_tree_len(sector_nodes, BINARY_ARITY)?;
+    match fs::metadata(&tree_d_path) {
+        Ok(metadata) if metadata.len() == (tree_len * NODE_SIZE) as u64 => {}
+        _ => return Ok(None),
+    }
+
+    let mut file =
+        File::open(&tree_d_path).with_context(|| format!("could not open {:?}", tree_d_path))?;
+    file.seek(SeekFrom::End(-(NODE_SIZE as i64)))?;
+    let mut root = [0u8; NODE_SIZE];
+    file.read_exact(&mut root)?;
+    Ok(Some(root))
+}
+
+/// Checks that the finished sector sealed into `replica_path` matches the on-chain commitments,
+/// without modifying it, e.g. after the sector was moved to another storage system.
+///
+/// The checks are:
+///  - the replica and the cache directory against the sector manifest, if there is one.
+///  - `comm_r` against the commitments of p_aux, and `comm_r_last` of p_aux against the root of
+///    tree_r_last.
+///  - `comm_d` against the root of tree_d, if it was not cleared yet.
+///  - that tree_r_last still holds the rows needed for proving. It is opened as it is stored, the
+///    discarded rows are never rebuilt.
+///  - the openings of a random `sample_rate` fraction of the leaves of tree_r_last, which are read
+///    from the replica.
+///
+/// Failed checks are reported in the result, also when p_aux or tree_r_last can't be read, in
+/// which case the checks depending on them are skipped. An error is only returned if the sector
+/// can't be checked at all, e.g. because its cache directory can't be read.
+pub fn check_sector<Tree: 'static + MerkleTreeTrait>(
+    porep_config: &PoRepConfig,
+    comm_r: Commitment,
+    comm_d: Commitment,
+    replica_path: &Path,
+    cache_path: &Path,
+    sample_rate: f64,
+) -> Result<SectorCheck> {
+    info!("check_sector:start: {:?}", cache_path);
+    ensure!(
+        sample_rate > 0.0 && sample_rate <= 1.0,
+        "sample rate must be within (0, 1]"
+    );
+
+    let mut check = SectorCheck::default();
+
+    if cache_path.join(SECTOR_MANIFEST).exists() {
+        for mismatch in verify_sector_manifest(replica_path, cache_path)? {
+            check
+                .diagnostics
+                .push(format!("sector manifest mismatch: {:?}", mismatch));
+        }
+        check.manifest_checked = true;
+    }
+
+    let sector_nodes = u64::from(porep_config.sector_size) as usize / NODE_SIZE;
+    if let Some(tree_d_root) = read_tree_d_root(cache_path, sector_nodes)? {
+        if tree_d_root != comm_d {
+            check.diagnostics.push(format!(
+                "comm_d {} does not match the root of tree_d {}",
+                hex::encode(comm_d),
+                hex::encode(tree_d_root)
+            ));
+        }
+        check.comm_d_checked = true;
+    }
+
+    let replica = match PrivateReplicaInfo::<Tree>::new(
+        replica_path.to_path_buf(),
+        comm_r,
+        cache_path.to_path_buf(),
+    ) {
+        Ok(replica) => replica,
+        Err(e) => {
+            check
+                .diagnostics
+                .push(format!("could not read p_aux: {:#}", e));
+            return Ok(finish(cache_path, check));
+        }
+    };
+    let comm_c = replica.safe_comm_c();
+    let comm_r_last = replica.safe_comm_r_last();
+    let expected_comm_r = <Tree::Hasher as Hasher>::Function::hash2(&comm_c, &comm_r_last);
+    if replica.safe_comm_r()? != expected_comm_r {
+        check.diagnostics.push(format!(
+            "comm_r {} does not match the commitments of p_aux, which hash to {}",
+            hex::encode(comm_r),
+            hex::encode(expected_comm_r.into_bytes())
+        ));
+    }
+
+    // Opening the tree for proving could rebuild discarded rows, which would modify the sector.
+    let (tree, stored_rows_to_discard) =
+        match replica.merkle_tree_as_stored(porep_config.sector_size) {
+            Ok(opened) => opened,
+            Err(e) => {
+                check
+                    .diagnostics
+                    .push(format!("could not open tree_r_last: {:#}", e));
+                return Ok(finish(cache_path, check));
+            }
+        };
+    if tree.root() != comm_r_last {
+        check
+            .diagnostics
+            .push("the root of tree_r_last does not match comm_r_last of p_aux".to_string());
+    }
+
+    let leaves = tree.leafs();
+    let base_tree_leaves = leaves / stored_rows_to_discard.len();
+    let rows_to_discard = default_rows_to_discard(base_tree_leaves, Tree::Arity::to_usize());
+    for (index, stored) in stored_rows_to_discard.iter().enumerate() {
+        if *stored > rows_to_discard {
+            check.diagnostics.push(format!(
+                "base tree {} of tree_r_last was stored with rows_to_discard {}, the rows needed \
+                 for proving with {} are missing",
+                index, stored, rows_to_discard
+            ));
+        }
+    }
+
+    let sample_count = ((leaves as f64 * sample_rate).ceil() as usize).clamp(1, leaves);
+    // Sorted, so that the replica is read sequentially.
+    let mut challenges = index::sample(&mut rand::thread_rng(), leaves, sample_count).into_vec();
+    challenges.sort_unstable();
+
+    let failed: Vec<String> = challenges
+        .par_iter()
+        .filter_map(|challenge| {
+            let rows_to_discard = stored_rows_to_discard[challenge / base_tree_leaves];
+            match tree.gen_cached_proof(*challenge, Some(rows_to_discard)) {
+                Ok(proof) if proof.validate(*challenge) && proof.root() == comm_r_last => None,
+                Ok(_) => Some(format!("the opening of leaf {} is invalid", challenge)),
+                Err(e) => Some(format!("could not open leaf {}: {}", challenge, e)),
+            }
+        })
+        .collect();
+    check.challenges_checked = challenges.len();
+    check.diagnostics.extend(failed);
+
+    Ok(finish(cache_path, check))
+}
+
+fn finish(cache_path: &Path, check: SectorCheck) -> SectorCheck {
+    info!(
+        "check_sector:finish: {:?}, {} failed checks",
+        cache_path,
+        check.diagnostics.len()
+    );
+    check
+}
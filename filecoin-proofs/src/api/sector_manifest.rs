@@ -0,0 +1,252 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Context, Result};
+use log::info;
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
+
+/// The name of the manifest file within the cache directory.
+pub const SECTOR_MANIFEST: &str = "sector-manifest.json";
+
+const SECTOR_MANIFEST_VERSION: u32 = 1;
+
+/// The size and blake3 digest of a sector artifact.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SectorArtifact {
+    /// The path relative to the cache directory, or the file name of the replica.
+    pub path: PathBuf,
+    pub size: u64,
+    /// Hex encoded blake3 digest of the contents.
+    pub blake3: String,
+}
+
+/// The artifacts of a sector, used to check that a sector was not corrupted, e.g. while it was
+/// moved between storage systems.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SectorManifest {
+    pub version: u32,
+    pub replica: SectorArtifact,
+    /// All files of the cache directory except for the manifest itself, sorted by path.
+    pub cache: Vec<SectorArtifact>,
+}
+
+/// A difference between a sector and its manifest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SectorManifestMismatch {
+    /// A file of the manifest does not exist.
+    Missing(PathBuf),
+    /// A file exists that is not part of the manifest.
+    Unexpected(PathBuf),
+    Size {
+        path: PathBuf,
+        expected: u64,
+        actual: u64,
+    },
+    Digest(PathBuf),
+}
+
+fn hash_file(path: &Path, manifest_path: PathBuf) -> Result<SectorArtifact> {
+    let file = File::open(path).with_context(|| format!("could not open {:?}", path))?;
+    let size = file.metadata()?.len();
+    let mut hasher = blake3::Hasher::new();
+    io::copy(&mut BufReader::new(file), &mut hasher)
+        .with_context(|| format!("could not read {:?}", path))?;
+
+    Ok(SectorArtifact {
+        path: manifest_path,
+        size,
+        blake3: hasher.finalize().to_hex().to_string(),
+    })
+}
+
+/// Returns the paths of all files within `dir` relative to it, sorted.
+pub(crate) fn list_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    fn walk(dir: &Path, prefix: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+        for entry in fs::read_dir(dir).with_context(|| format!("could not read {:?}", dir))? {
+            let entry = entry?;
+            let relative = prefix.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                walk(&entry.path(), &relative, files)?;
+            } else {
+                files.push(relative);
+            }
+        }
+        Ok(())
+    }
+
+    let mut files = Vec::new();
+    walk(dir, Path::new(""), &mut files)?;
+    files.sort();
+    Ok(files)
+}
+
+/// Returns the paths of all files within the cache directory except for the manifest.
+fn cache_files(cache_dir: &Path) -> Result<Vec<PathBuf>> {
+    Ok(list_files(cache_dir)?
+        .into_iter()
+        .filter(|file| file != Path::new(SECTOR_MANIFEST))
+        .collect())
+}
+
+fn hash_sector(replica_path: &Path, cache_dir: &Path) -> Result<SectorManifest> {
+    let replica_name = replica_path
+        .file_name()
+        .with_context(|| format!("invalid replica path {:?}", replica_path))?;
+
+    let files = cache_files(cache_dir)?;
+
+    let replica = hash_file(replica_path, PathBuf::from(replica_name))?;
+    let cache = files
+        .par_iter()
+        .map(|file| hash_file(&cache_dir.join(file), file.clone()))
+        .collect::<Result<_>>()?;
+
+    Ok(SectorManifest {
+        version: SECTOR_MANIFEST_VERSION,
+        replica,
+        cache,
+    })
+}
+
+/// Hashes the replica and every file of the cache directory and writes the resulting manifest
+/// to [`SECTOR_MANIFEST`] within the cache directory.
+pub fn generate_sector_manifest(replica_path: &Path, cache_dir: &Path) -> Result<SectorManifest> {
+    info!("generate_sector_manifest:start: {:?}", cache_dir);
+
+    let manifest = hash_sector(replica_path, cache_dir)?;
+    let manifest_path = cache_dir.join(SECTOR_MANIFEST);
+    fs::write(&manifest_path, serde_json::to_vec_pretty(&manifest)?)
+        .with_context(|| format!("could not write {:?}", manifest_path))?;
+
+    info!("generate_sector_manifest:finish: {:?}", cache_dir);
+    Ok(manifest)
+}
+
+/// Reads the manifest of the cache directory.
+pub fn read_sector_manifest(cache_dir: &Path) -> Result<SectorManifest> {
+    let manifest_path = cache_dir.join(SECTOR_MANIFEST);
+    let manifest: SectorManifest = serde_json::from_slice(
+        &fs::read(&manifest_path).with_context(|| format!("could not read {:?}", manifest_path))?,
+    )
+    .with_context(|| format!("invalid sector manifest {:?}", manifest_path))?;
+    ensure!(
+        manifest.version == SECTOR_MANIFEST_VERSION,
+        "unsupported sector manifest version {}",
+        manifest.version
+    );
+    Ok(manifest)
+}
+
+/// Checks the replica and the cache directory against the manifest written by
+/// [`generate_sector_manifest`]. Returns all differences, the sector is intact if there are none.
+///
+/// The replica is checked by its contents only, it may have been renamed.
+pub fn verify_sector_manifest(
+    replica_path: &Path,
+    cache_dir: &Path,
+) -> Result<Vec<SectorManifestMismatch>> {
+    info!("verify_sector_manifest:start: {:?}", cache_dir);
+
+    let expected = read_sector_manifest(cache_dir)?;
+
+    let files = cache_files(cache_dir)?;
+
+    let mut mismatches = Vec::new();
+    let mut to_hash = vec![(replica_path.to_path_buf(), &expected.replica)];
+    for artifact in &expected.cache {
+        if files.contains(&artifact.path) {
+            to_hash.push((cache_dir.join(&artifact.path), artifact));
+        } else {
+            mismatches.push(SectorManifestMismatch::Missing(artifact.path.clone()));
+        }
+    }
+    for file in files {
+        if !expected.cache.iter().any(|artifact| artifact.path == file) {
+            mismatches.push(SectorManifestMismatch::Unexpected(file));
+        }
+    }
+
+    let hashed: Vec<(SectorArtifact, &SectorArtifact)> = to_hash
+        .par_iter()
+        .map(|(path, artifact)| Ok((hash_file(path, artifact.path.clone())?, *artifact)))
+        .collect::<Result<_>>()?;
+    for (actual, expected) in hashed {
+        if actual.size != expected.size {
+            mismatches.push(SectorManifestMismatch::Size {
+                path: actual.path,
+                expected: expected.size,
+                actual: actual.size,
+            });
+        } else if actual.blake3 != expected.blake3 {
+            mismatches.push(SectorManifestMismatch::Digest(actual.path));
+        }
+    }
+
+    info!(
+        "verify_sector_manifest:finish: {:?}, {} mismatches",
+        cache_dir,
+        mismatches.len()
+    );
+    Ok(mismatches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_sector_manifest() {
+        let dir = tempdir().expect("failed to create tempdir");
+        let cache_dir = dir.path().join("cache");
+        fs::create_dir_all(cache_dir.join("sub")).expect("failed to create cache dir");
+        let replica_path = dir.path().join("sealed");
+        fs::write(&replica_path, [1u8; 1024]).expect("failed to write replica");
+        fs::write(cache_dir.join("p_aux"), [2u8; 64]).expect("failed to write p_aux");
+        fs::write(cache_dir.join("sub").join("tree"), [3u8; 128]).expect("failed to write tree");
+
+        let manifest =
+            generate_sector_manifest(&replica_path, &cache_dir).expect("failed to generate");
+        assert_eq!(manifest.replica.size, 1024);
+        assert_eq!(
+            manifest
+                .cache
+                .iter()
+                .map(|artifact| artifact.path.clone())
+                .collect::<Vec<_>>(),
+            vec![PathBuf::from("p_aux"), PathBuf::from("sub/tree")]
+        );
+        assert_eq!(
+            read_sector_manifest(&cache_dir).expect("failed to read"),
+            manifest
+        );
+        assert!(verify_sector_manifest(&replica_path, &cache_dir)
+            .expect("failed to verify")
+            .is_empty());
+
+        fs::write(&replica_path, [9u8; 1024]).expect("failed to write replica");
+        fs::write(cache_dir.join("p_aux"), [2u8; 32]).expect("failed to write p_aux");
+        fs::remove_file(cache_dir.join("sub").join("tree")).expect("failed to remove tree");
+        fs::write(cache_dir.join("t_aux"), [4u8; 8]).expect("failed to write t_aux");
+
+        let mut mismatches =
+            verify_sector_manifest(&replica_path, &cache_dir).expect("failed to verify");
+        mismatches.sort_by_key(|mismatch| format!("{:?}", mismatch));
+        assert_eq!(
+            mismatches,
+            vec![
+                SectorManifestMismatch::Digest(PathBuf::from("sealed")),
+                SectorManifestMismatch::Missing(PathBuf::from("sub/tree")),
+                SectorManifestMismatch::Size {
+                    path: PathBuf::from("p_aux"),
+                    expected: 64,
+                    actual: 32,
+                },
+                SectorManifestMismatch::Unexpected(PathBuf::from("t_aux")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,345 @@
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{ensure, Context, Result};
+use log::{debug, warn};
+use rand::Rng;
+
+/// The default size of a cached window, in padded bytes.
+pub const DEFAULT_UNSEAL_CACHE_WINDOW_SIZE: u64 = 1 << 20;
+
+const TAG_LEN: usize = 32;
+const TMP_SUFFIX: &str = ".tmp";
+/// Temporary files that were not modified for this long were left behind by an interrupted
+/// insert, younger ones may belong to an insert of another process sharing the directory.
+const STALE_TMP_AGE: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Default)]
+struct Entries {
+    /// Incremented on every access, the entry with the lowest value is evicted first.
+    clock: u64,
+    /// The last access and the size on disk of every window file.
+    windows: HashMap<PathBuf, (u64, u64)>,
+    total_size: u64,
+}
+
+impl Entries {
+    fn touch(&mut self, path: &Path, size: u64) {
+        self.clock += 1;
+        match self.windows.insert(path.to_path_buf(), (self.clock, size)) {
+            Some((_, old_size)) => self.total_size = self.total_size - old_size + size,
+            None => self.total_size += size,
+        }
+    }
+
+    fn remove(&mut self, path: &Path) {
+        if let Some((_, size)) = self.windows.remove(path) {
+            self.total_size -= size;
+        }
+    }
+}
+
+/// A directory of decoded sector windows, so that repeated retrievals of popular data do not
+/// decode the entire sector again (see [`unseal_range_cached`](crate::unseal_range_cached)).
+///
+/// Windows are stored per replica id at `<dir>/<replica id>/<window index>`, followed by a blake3
+/// tag over the replica id, the window index and the data. Windows whose tag does not match are
+/// discarded. Once the windows exceed `max_size` bytes on disk, the least recently used ones are
+/// evicted; the access order of windows that were cached by a previous process is approximated
+/// by their modification time.
+#[derive(Debug)]
+pub struct UnsealCache {
+    dir: PathBuf,
+    max_size: u64,
+    window_size: u64,
+    entries: Mutex<Entries>,
+}
+
+impl UnsealCache {
+    /// Opens the cache at `dir`, creating it if needed, with [`DEFAULT_UNSEAL_CACHE_WINDOW_SIZE`]
+    /// windows.
+    pub fn new<P: Into<PathBuf>>(dir: P, max_size: u64) -> Result<Self> {
+        Self::with_window_size(dir, max_size, DEFAULT_UNSEAL_CACHE_WINDOW_SIZE)
+    }
+
+    /// Opens the cache at `dir` with windows of `window_size` padded bytes, a power of two of at
+    /// least 128 bytes. Windows must be the same size for all processes sharing the directory.
+    pub fn with_window_size<P: Into<PathBuf>>(
+        dir: P,
+        max_size: u64,
+        window_size: u64,
+    ) -> Result<Self> {
+        ensure!(
+            window_size.is_power_of_two() && window_size >= 128,
+            "invalid unseal cache window size {}",
+            window_size
+        );
+        let dir = dir.into();
+        fs::create_dir_all(&dir).with_context(|| format!("could not create {:?}", dir))?;
+
+        let mut existing = Vec::new();
+        for replica_dir in fs::read_dir(&dir)? {
+            let replica_dir = replica_dir?;
+            if !replica_dir.file_type()?.is_dir() {
+                continue;
+            }
+            for window in fs::read_dir(replica_dir.path())? {
+                let window = window?;
+                let path = window.path();
+                let metadata = window.metadata()?;
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                if path.to_string_lossy().ends_with(TMP_SUFFIX) {
+                    let stale = modified
+                        .elapsed()
+                        .map(|age| age >= STALE_TMP_AGE)
+                        .unwrap_or(false);
+                    if stale {
+                        let _ = fs::remove_file(&path);
+                    }
+                    continue;
+                }
+                existing.push((modified, path, metadata.len()));
+            }
+        }
+        existing.sort();
+
+        let mut entries = Entries::default();
+        for (_, path, size) in existing {
+            entries.touch(&path, size);
+        }
+
+        let cache = UnsealCache {
+            dir,
+            max_size,
+            window_size,
+            entries: Mutex::new(entries),
+        };
+        cache.evict()?;
+        Ok(cache)
+    }
+
+    pub fn window_size(&self) -> u64 {
+        self.window_size
+    }
+
+    /// The size of all cached windows on disk.
+    pub fn size(&self) -> u64 {
+        self.entries
+            .lock()
+            .expect("unseal cache poisoned")
+            .total_size
+    }
+
+    fn window_path(&self, replica_id: &[u8; 32], window: u64) -> PathBuf {
+        self.dir
+            .join(hex::encode(replica_id))
+            .join(window.to_string())
+    }
+
+    fn tag(replica_id: &[u8; 32], window: u64, data: &[u8]) -> [u8; TAG_LEN] {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(replica_id);
+        hasher.update(&window.to_le_bytes());
+        hasher.update(data);
+        hasher.finalize().into()
+    }
+
+    /// Returns the decoded window, or `None` if it is not cached or was corrupted.
+    pub fn get(&self, replica_id: &[u8; 32], window: u64) -> Result<Option<Vec<u8>>> {
+        let path = self.window_path(replica_id, window);
+        let mut data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err).with_context(|| format!("could not read {:?}", path)),
+        };
+
+        let mut entries = self.entries.lock().expect("unseal cache poisoned");
+        if data.len() < TAG_LEN
+            || data[data.len() - TAG_LEN..]
+                != Self::tag(replica_id, window, &data[..data.len() - TAG_LEN])
+        {
+            warn!("discarding corrupted unseal cache window {:?}", path);
+            entries.remove(&path);
+            fs::remove_file(&path).with_context(|| format!("could not remove {:?}", path))?;
+            return Ok(None);
+        }
+        entries.touch(&path, data.len() as u64);
+
+        data.truncate(data.len() - TAG_LEN);
+        Ok(Some(data))
+    }
+
+    /// Caches a decoded window and evicts the least recently used windows if the cache exceeds
+    /// its size.
+    pub fn insert(&self, replica_id: &[u8; 32], window: u64, data: &[u8]) -> Result<()> {
+        ensure!(
+            data.len() as u64 <= self.window_size,
+            "window of {} bytes exceeds the window size {}",
+            data.len(),
+            self.window_size
+        );
+        let size = (data.len() + TAG_LEN) as u64;
+        if size > self.max_size {
+            return Ok(());
+        }
+
+        let path = self.window_path(replica_id, window);
+        let parent = path.parent().expect("window path has a parent");
+        fs::create_dir_all(parent).with_context(|| format!("could not create {:?}", parent))?;
+
+        // Write to a temporary file first, so that readers never see a partial window. Every
+        // insert has its own, concurrent inserts of the same window each replace it completely.
+        let tmp_path = parent.join(format!(
+            "{}-{}-{:016x}{}",
+            window,
+            std::process::id(),
+            rand::thread_rng().gen::<u64>(),
+            TMP_SUFFIX
+        ));
+        let write_window = || -> Result<()> {
+            let mut file = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&tmp_path)
+                .with_context(|| format!("could not create {:?}", tmp_path))?;
+            file.write_all(data)?;
+            file.write_all(&Self::tag(replica_id, window, data))?;
+            drop(file);
+            fs::rename(&tmp_path, &path).with_context(|| format!("could not write {:?}", path))
+        };
+        write_window().map_err(|err| {
+            let _ = fs::remove_file(&tmp_path);
+            err
+        })?;
+
+        self.entries
+            .lock()
+            .expect("unseal cache poisoned")
+            .touch(&path, size);
+        self.evict()
+    }
+
+    /// Removes the least recently used windows until the cache fits its size.
+    fn evict(&self) -> Result<()> {
+        let mut entries = self.entries.lock().expect("unseal cache poisoned");
+        while entries.total_size > self.max_size {
+            let path = entries
+                .windows
+                .iter()
+                .min_by_key(|(_, (last_used, _))| *last_used)
+                .map(|(path, _)| path.clone())
+                .expect("non-zero size implies windows");
+            debug!("evicting unseal cache window {:?}", path);
+            entries.remove(&path);
+            match fs::remove_file(&path) {
+                Ok(()) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => {
+                    return Err(err).with_context(|| format!("could not remove {:?}", path))
+                }
+            }
+            if let Some(parent) = path.parent() {
+                // Only succeeds once the last window of the replica is gone.
+                let _ = fs::remove_dir(parent);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_unseal_cache() {
+        let dir = tempdir().expect("failed to create tempdir");
+        let window_size = 128;
+        let entry_size = window_size + TAG_LEN as u64;
+        let cache = UnsealCache::with_window_size(dir.path(), 3 * entry_size, window_size)
+            .expect("failed to open cache");
+        let replica_id = [7u8; 32];
+
+        assert!(cache.get(&replica_id, 0).expect("get failed").is_none());
+        for window in 0..3 {
+            cache
+                .insert(&replica_id, window, &[window as u8; 128])
+                .expect("insert failed");
+        }
+        assert_eq!(cache.size(), 3 * entry_size);
+        assert!(cache.insert(&replica_id, 9, &[0u8; 129]).is_err());
+
+        // Window 0 is used most recently, so window 1 is evicted.
+        assert_eq!(
+            cache.get(&replica_id, 0).expect("get failed"),
+            Some(vec![0u8; 128])
+        );
+        cache
+            .insert(&replica_id, 3, &[3u8; 128])
+            .expect("insert failed");
+        assert_eq!(cache.size(), 3 * entry_size);
+        assert!(cache.get(&replica_id, 1).expect("get failed").is_none());
+        assert!(cache.get(&replica_id, 2).expect("get failed").is_some());
+        assert!(cache.get(&[8u8; 32], 2).expect("get failed").is_none());
+
+        // Corrupted windows are discarded.
+        let path = cache.window_path(&replica_id, 2);
+        let mut data = fs::read(&path).expect("failed to read window");
+        data[0] ^= 1;
+        fs::write(&path, data).expect("failed to write window");
+        assert!(cache.get(&replica_id, 2).expect("get failed").is_none());
+        assert!(!path.exists());
+        assert_eq!(cache.size(), 2 * entry_size);
+
+        // The windows survive reopening the cache.
+        drop(cache);
+        let cache = UnsealCache::with_window_size(dir.path(), entry_size, window_size)
+            .expect("failed to open cache");
+        assert_eq!(cache.size(), entry_size);
+    }
+
+    #[test]
+    fn test_unseal_cache_shared() {
+        let dir = tempdir().expect("failed to create tempdir");
+        let window_size = 128;
+        let entry_size = window_size + TAG_LEN as u64;
+        let replica_id = [7u8; 32];
+        let open = || {
+            UnsealCache::with_window_size(dir.path(), 4 * entry_size, window_size)
+                .expect("failed to open cache")
+        };
+
+        // Concurrent inserts of the same window all succeed.
+        let caches = [open(), open()];
+        std::thread::scope(|scope| {
+            for cache in &caches {
+                scope.spawn(move || {
+                    for _ in 0..50 {
+                        cache
+                            .insert(&replica_id, 0, &[1u8; 128])
+                            .expect("insert failed");
+                    }
+                });
+            }
+        });
+        assert_eq!(
+            caches[0].get(&replica_id, 0).expect("get failed"),
+            Some(vec![1u8; 128])
+        );
+
+        // The temporary file of an insert in progress survives opening the cache.
+        let tmp_path = caches[0]
+            .window_path(&replica_id, 1)
+            .with_file_name(format!("1-0-0{}", TMP_SUFFIX));
+        fs::write(&tmp_path, [1u8; 16]).expect("failed to write tmp");
+        let cache = open();
+        assert!(tmp_path.exists());
+        assert_eq!(cache.size(), entry_size);
+    }
+}
@@ -1,40 +1,49 @@
 use std::cmp;
+use std::fs::metadata;
 use std::io::{Read, Write};
 use std::path::Path;
 
 use anyhow::{ensure, Context, Result};
+use bellperson::groth16;
+use blstrs::Scalar as Fr;
 use ff::PrimeField;
 use filecoin_hashers::{Domain, Hasher};
 use fr32::bytes_into_fr;
-use generic_array::typenum::Unsigned;
 use log::info;
-use merkletree::merkle::get_merkle_tree_len;
 use merkletree::store::StoreConfig;
 use storage_proofs_core::{
     compound_proof::{self, CompoundProof},
-    merkle::{get_base_tree_count, MerkleTreeTrait},
+    merkle::MerkleTreeTrait,
     multi_proof::MultiProof,
     proof::ProofScheme,
+    proof_rng::proof_rng,
     util::NODE_SIZE,
 };
 use storage_proofs_porep::stacked::TemporaryAux;
 use storage_proofs_update::{
-    constants::{h_default, TreeDArity, TreeDDomain, TreeRDomain, TreeRHasher},
+    constants::{h_default, TreeDDomain, TreeRDomain, TreeRHasher},
     phi,
     vanilla::Rhos,
     EmptySectorUpdate, EmptySectorUpdateCompound, PartitionProof, PrivateInputs, PublicInputs,
     PublicParams, SetupParams,
 };
+use tracing::info_span;
 
 use crate::{
     api::util,
-    caches::{get_empty_sector_update_params, get_empty_sector_update_verifying_key},
+    caches::{
+        get_empty_sector_update_params, get_empty_sector_update_verifying_key,
+        Bls12PreparedVerifyingKey,
+    },
     chunk_iter::ChunkIterator,
-    constants::{DefaultPieceDomain, DefaultPieceHasher},
+    codec,
+    constants::{DefaultPieceDomain, DefaultPieceHasher, SINGLE_PARTITION_PROOF_LEN},
+    metrics::{self, StageTimer},
     pieces::verify_pieces,
+    priority::{enter_stage, ProvingPriority},
     types::{
-        Commitment, EmptySectorUpdateEncoded, EmptySectorUpdateProof, PieceInfo, PoRepConfig,
-        SectorUpdateConfig,
+        Commitment, EmptySectorUpdateEncoded, EmptySectorUpdateProof, PartitionSnarkProof,
+        PieceInfo, PoRepConfig, SectorUpdateConfig, SectorUpdatePartitionInputs,
     },
 };
 
@@ -54,25 +63,18 @@ fn get_new_configs_from_t_aux_old<Tree: 'static + MerkleTreeTrait<Hasher = TreeR
     new_cache_path: &Path,
     nodes_count: usize,
 ) -> Result<(StoreConfig, StoreConfig)> {
-    let tree_count = get_base_tree_count::<Tree>();
-    let base_tree_nodes_count = nodes_count / tree_count;
-
-    let tree_d_new_config = StoreConfig {
-        path: new_cache_path.into(),
-        id: t_aux.tree_d_config.id.clone(),
-        size: Some(get_merkle_tree_len(nodes_count, TreeDArity::to_usize())?),
-        rows_to_discard: t_aux.tree_d_config.rows_to_discard,
-    };
-
-    let tree_r_last_new_config = StoreConfig {
-        path: new_cache_path.into(),
-        id: t_aux.tree_r_last_config.id.clone(),
-        size: Some(get_merkle_tree_len(
-            base_tree_nodes_count,
-            Tree::Arity::to_usize(),
-        )?),
-        rows_to_discard: t_aux.tree_r_last_config.rows_to_discard,
-    };
+    let tree_d_new_config = EmptySectorUpdate::<Tree>::new_tree_d_config(
+        nodes_count,
+        new_cache_path,
+        &t_aux.tree_d_config.id,
+        t_aux.tree_d_config.rows_to_discard,
+    )?;
+    let tree_r_last_new_config = EmptySectorUpdate::<Tree>::new_tree_r_last_config(
+        nodes_count,
+        new_cache_path,
+        &t_aux.tree_r_last_config.id,
+        t_aux.tree_r_last_config.rows_to_discard,
+    )?;
 
     Ok((tree_d_new_config, tree_r_last_new_config))
 }
@@ -91,27 +93,84 @@ pub fn encode_into<Tree: 'static + MerkleTreeTrait<Hasher = TreeRHasher>>(
     staged_data_path: &Path,
     piece_infos: &[PieceInfo],
 ) -> Result<EmptySectorUpdateEncoded> {
+    let _span = info_span!("encode_into").entered();
+    let timer = StageTimer::start("encode_update");
     info!("encode_into:start");
     let config = SectorUpdateConfig::from_porep_config(porep_config);
 
     let p_aux = util::get_p_aux::<Tree>(sector_key_cache_path)?;
-    let t_aux =
-        util::get_t_aux::<Tree>(sector_key_cache_path, u64::from(porep_config.sector_size))?;
+    let t_aux = util::get_t_aux::<Tree>(
+        sector_key_cache_path,
+        u64::from(porep_config.sector_size),
+        porep_config.layers,
+    )?;
+
+    ensure!(
+        metadata(new_cache_path)?.is_dir(),
+        "new_cache_path must be a directory"
+    );
 
     let (tree_d_new_config, tree_r_last_new_config) =
         get_new_configs_from_t_aux_old::<Tree>(&t_aux, new_cache_path, config.nodes_count)?;
 
+    let mut comm_c = [0; 32];
+    let mut comm_r_last_old = [0; 32];
+    p_aux.comm_c.write_bytes(&mut comm_c)?;
+    p_aux.comm_r_last.write_bytes(&mut comm_r_last_old)?;
+
+    let encoded = encode_into_with_configs::<Tree>(
+        porep_config,
+        tree_d_new_config,
+        tree_r_last_new_config,
+        comm_c,
+        comm_r_last_old,
+        new_replica_path,
+        sector_key_path,
+        staged_data_path,
+        piece_infos,
+    )?;
+
+    // Persist p_aux and t_aux into the new_cache_path here
+    let mut p_aux = p_aux;
+    p_aux.comm_r_last = <Tree::Hasher as Hasher>::Domain::try_from_bytes(&encoded.comm_r_last_new)?;
+    util::persist_p_aux::<Tree>(&p_aux, new_cache_path)?;
+    #[cfg(not(feature = "fixed-rows-to-discard"))]
+    util::persist_t_aux::<Tree>(&t_aux, new_cache_path)?;
+
+    info!("encode_into:finish");
+
+    timer.finish(Ok(encoded))
+}
+
+/// Encodes data into an existing replica, like [`encode_into`], but without relying on the
+/// layout of the sector caches. The new trees are written as described by `tree_d_new_config`
+/// and `tree_r_last_new_config`, `comm_c` and `comm_r_last_old` are the ones of the sector key.
+///
+/// Unlike [`encode_into`] no aux files are persisted, this is up to the caller.
+#[allow(clippy::too_many_arguments)]
+pub fn encode_into_with_configs<Tree: 'static + MerkleTreeTrait<Hasher = TreeRHasher>>(
+    porep_config: &PoRepConfig,
+    tree_d_new_config: StoreConfig,
+    tree_r_last_new_config: StoreConfig,
+    comm_c: Commitment,
+    comm_r_last_old: Commitment,
+    new_replica_path: &Path,
+    sector_key_path: &Path,
+    staged_data_path: &Path,
+    piece_infos: &[PieceInfo],
+) -> Result<EmptySectorUpdateEncoded> {
+    info!("encode_into_with_configs:start");
+    let config = SectorUpdateConfig::from_porep_config(porep_config);
+
     let (comm_r_domain, comm_r_last_domain, comm_d_domain) =
-        EmptySectorUpdate::<Tree>::encode_into(
+        EmptySectorUpdate::<Tree>::encode_into_with_configs(
             config.nodes_count,
             tree_d_new_config,
             tree_r_last_new_config,
-            <Tree::Hasher as Hasher>::Domain::try_from_bytes(&p_aux.comm_c.into_bytes())?,
-            <Tree::Hasher as Hasher>::Domain::try_from_bytes(&p_aux.comm_r_last.into_bytes())?,
+            <Tree::Hasher as Hasher>::Domain::try_from_bytes(&comm_c)?,
+            <Tree::Hasher as Hasher>::Domain::try_from_bytes(&comm_r_last_old)?,
             new_replica_path,
-            new_cache_path,
             sector_key_path,
-            sector_key_cache_path,
             staged_data_path,
             h_default(config.nodes_count),
         )?;
@@ -135,14 +194,7 @@ pub fn encode_into<Tree: 'static + MerkleTreeTrait<Hasher = TreeRHasher>>(
         "pieces and comm_d do not match"
     );
 
-    // Persist p_aux and t_aux into the new_cache_path here
-    let mut p_aux = p_aux;
-    p_aux.comm_r_last = comm_r_last_domain;
-    util::persist_p_aux::<Tree>(&p_aux, new_cache_path)?;
-    #[cfg(not(feature = "fixed-rows-to-discard"))]
-    util::persist_t_aux::<Tree>(&t_aux, new_cache_path)?;
-
-    info!("encode_into:finish");
+    info!("encode_into_with_configs:finish");
 
     Ok(EmptySectorUpdateEncoded {
         comm_r_new: comm_r,
@@ -274,7 +326,11 @@ pub fn remove_encoded_data<Tree: 'static + MerkleTreeTrait<Hasher = TreeRHasher>
     info!("remove_data:start");
 
     let p_aux = util::get_p_aux::<Tree>(replica_cache_path)?;
-    let t_aux = util::get_t_aux::<Tree>(replica_cache_path, u64::from(config.sector_size))?;
+    let t_aux = util::get_t_aux::<Tree>(
+        replica_cache_path,
+        u64::from(config.sector_size),
+        config.layers,
+    )?;
 
     let (_, tree_r_last_new_config) =
         get_new_configs_from_t_aux_old::<Tree>(&t_aux, sector_key_cache_path, config.nodes_count)?;
@@ -340,7 +396,11 @@ pub fn generate_single_partition_proof<Tree: 'static + MerkleTreeTrait<Hasher =
         h: config.h,
     };
 
-    let t_aux_old = util::get_t_aux::<Tree>(sector_key_cache_path, u64::from(config.sector_size))?;
+    let t_aux_old = util::get_t_aux::<Tree>(
+        sector_key_cache_path,
+        u64::from(config.sector_size),
+        config.layers,
+    )?;
 
     let (tree_d_new_config, tree_r_last_new_config) =
         get_new_configs_from_t_aux_old::<Tree>(&t_aux_old, replica_cache_path, config.nodes_count)?;
@@ -414,6 +474,52 @@ pub fn generate_partition_proofs<Tree: 'static + MerkleTreeTrait<Hasher = TreeRH
 ) -> Result<Vec<PartitionProof<Tree>>> {
     info!("generate_partition_proofs:start");
 
+    let p_aux_old = util::get_p_aux::<Tree>(sector_key_cache_path)?;
+    let t_aux_old = util::get_t_aux::<Tree>(
+        sector_key_cache_path,
+        u64::from(config.sector_size),
+        config.layers,
+    )?;
+
+    let (tree_d_new_config, tree_r_last_new_config) =
+        get_new_configs_from_t_aux_old::<Tree>(&t_aux_old, replica_cache_path, config.nodes_count)?;
+
+    let private_inputs: PrivateInputs = PrivateInputs {
+        comm_c: p_aux_old.comm_c,
+        tree_r_old_config: t_aux_old.tree_r_last_config,
+        old_replica_path: sector_key_path.to_path_buf(),
+        tree_d_new_config,
+        tree_r_new_config: tree_r_last_new_config,
+        replica_path: replica_path.to_path_buf(),
+    };
+
+    let partition_proofs = generate_partition_proofs_with_configs::<Tree>(
+        config,
+        comm_r_old,
+        comm_r_new,
+        comm_d_new,
+        &private_inputs,
+    )?;
+
+    info!("generate_partition_proofs:finish");
+
+    Ok(partition_proofs)
+}
+
+/// Generate all vanilla partition proofs across all partitions, like
+/// [`generate_partition_proofs`], but without relying on the layout of the sector caches. The
+/// locations of the old and new trees are taken from the store configs of `private_inputs`.
+pub fn generate_partition_proofs_with_configs<
+    Tree: 'static + MerkleTreeTrait<Hasher = TreeRHasher>,
+>(
+    config: SectorUpdateConfig,
+    comm_r_old: Commitment,
+    comm_r_new: Commitment,
+    comm_d_new: Commitment,
+    private_inputs: &PrivateInputs,
+) -> Result<Vec<PartitionProof<Tree>>> {
+    info!("generate_partition_proofs_with_configs:start");
+
     let comm_r_old_safe = <TreeRHasher as Hasher>::Domain::try_from_bytes(&comm_r_old)?;
     let comm_r_new_safe = <TreeRHasher as Hasher>::Domain::try_from_bytes(&comm_r_new)?;
 
@@ -422,8 +528,6 @@ pub fn generate_partition_proofs<Tree: 'static + MerkleTreeTrait<Hasher = TreeRH
     let public_params: storage_proofs_update::PublicParams =
         PublicParams::from_sector_size(u64::from(config.sector_size));
 
-    let p_aux_old = util::get_p_aux::<Tree>(sector_key_cache_path)?;
-
     let public_inputs: storage_proofs_update::PublicInputs = PublicInputs {
         k: usize::from(config.update_partitions),
         comm_r_old: comm_r_old_safe,
@@ -432,30 +536,117 @@ pub fn generate_partition_proofs<Tree: 'static + MerkleTreeTrait<Hasher = TreeRH
         h: config.h,
     };
 
-    let t_aux_old = util::get_t_aux::<Tree>(sector_key_cache_path, u64::from(config.sector_size))?;
+    let partition_proofs = EmptySectorUpdate::<Tree>::prove_all_partitions(
+        &public_params,
+        &public_inputs,
+        private_inputs,
+        usize::from(config.update_partitions),
+    )?;
+
+    info!("generate_partition_proofs_with_configs:finish");
+
+    Ok(partition_proofs)
+}
+
+/// Returns the inputs for proving each partition of an empty sector update separately, e.g. on
+/// different machines, see [`generate_single_partition_proof_with_inputs`].
+#[allow(clippy::too_many_arguments)]
+pub fn get_sector_update_partition_inputs<Tree: 'static + MerkleTreeTrait<Hasher = TreeRHasher>>(
+    config: SectorUpdateConfig,
+    comm_r_old: Commitment,
+    comm_r_new: Commitment,
+    comm_d_new: Commitment,
+    sector_key_path: &Path,
+    sector_key_cache_path: &Path,
+    replica_path: &Path,
+    replica_cache_path: &Path,
+) -> Result<Vec<SectorUpdatePartitionInputs>> {
+    let p_aux_old = util::get_p_aux::<Tree>(sector_key_cache_path)?;
+    let t_aux_old = util::get_t_aux::<Tree>(
+        sector_key_cache_path,
+        u64::from(config.sector_size),
+        config.layers,
+    )?;
 
     let (tree_d_new_config, tree_r_last_new_config) =
         get_new_configs_from_t_aux_old::<Tree>(&t_aux_old, replica_cache_path, config.nodes_count)?;
 
+    let mut comm_c = [0; 32];
+    p_aux_old.comm_c.write_bytes(&mut comm_c)?;
+
+    let partitions = usize::from(config.update_partitions);
+    Ok((0..partitions)
+        .map(|partition_index| SectorUpdatePartitionInputs {
+            partition_index,
+            comm_r_old,
+            comm_r_new,
+            comm_d_new,
+            comm_c,
+            tree_r_old_config: t_aux_old.tree_r_last_config.clone(),
+            old_replica_path: sector_key_path.to_path_buf(),
+            tree_d_new_config: tree_d_new_config.clone(),
+            tree_r_new_config: tree_r_last_new_config.clone(),
+            replica_path: replica_path.to_path_buf(),
+        })
+        .collect())
+}
+
+/// Generate the vanilla partition proof for the partition described by `inputs`.
+pub fn generate_single_partition_proof_with_inputs<
+    Tree: 'static + MerkleTreeTrait<Hasher = TreeRHasher>,
+>(
+    config: SectorUpdateConfig,
+    inputs: &SectorUpdatePartitionInputs,
+) -> Result<PartitionProof<Tree>> {
+    info!("generate_single_partition_proof_with_inputs:start");
+
+    let partitions = usize::from(config.update_partitions);
+    ensure!(
+        inputs.partition_index < partitions,
+        "invalid partition index"
+    );
+
+    let public_params: storage_proofs_update::PublicParams =
+        PublicParams::from_sector_size(u64::from(config.sector_size));
+    let public_inputs = partition_public_inputs(
+        &config,
+        inputs.partition_index,
+        inputs.comm_r_old,
+        inputs.comm_r_new,
+        inputs.comm_d_new,
+    )?;
+
     let private_inputs: PrivateInputs = PrivateInputs {
-        comm_c: p_aux_old.comm_c,
-        tree_r_old_config: t_aux_old.tree_r_last_config,
-        old_replica_path: sector_key_path.to_path_buf(),
-        tree_d_new_config,
-        tree_r_new_config: tree_r_last_new_config,
-        replica_path: replica_path.to_path_buf(),
+        comm_c: <TreeRHasher as Hasher>::Domain::try_from_bytes(&inputs.comm_c)?,
+        tree_r_old_config: inputs.tree_r_old_config.clone(),
+        old_replica_path: inputs.old_replica_path.clone(),
+        tree_d_new_config: inputs.tree_d_new_config.clone(),
+        tree_r_new_config: inputs.tree_r_new_config.clone(),
+        replica_path: inputs.replica_path.clone(),
     };
 
-    let partition_proofs = EmptySectorUpdate::<Tree>::prove_all_partitions(
-        &public_params,
-        &public_inputs,
-        &private_inputs,
-        usize::from(config.update_partitions),
-    )?;
+    let partition_proof =
+        EmptySectorUpdate::<Tree>::prove(&public_params, &public_inputs, &private_inputs)?;
 
-    info!("generate_partition_proofs:finish");
+    info!("generate_single_partition_proof_with_inputs:finish");
 
-    Ok(partition_proofs)
+    Ok(partition_proof)
+}
+
+fn partition_public_inputs(
+    config: &SectorUpdateConfig,
+    partition_index: usize,
+    comm_r_old: Commitment,
+    comm_r_new: Commitment,
+    comm_d_new: Commitment,
+) -> Result<PublicInputs> {
+    Ok(PublicInputs {
+        k: partition_index,
+        comm_r_old: <TreeRHasher as Hasher>::Domain::try_from_bytes(&comm_r_old)?,
+        comm_d_new: DefaultPieceDomain::try_from_bytes(&comm_d_new)?,
+        comm_r_new: <TreeRHasher as Hasher>::Domain::try_from_bytes(&comm_r_new)?,
+        h: config.h,
+    })
 }
 
 /// Verify all vanilla partition proofs across all partitions.
@@ -503,6 +694,7 @@ pub fn generate_empty_sector_update_proof_with_vanilla<
     comm_d_new: Commitment,
 ) -> Result<EmptySectorUpdateProof> {
     info!("generate_empty_sector_update_proof_with_vanilla:start");
+    let _priority = enter_stage(ProvingPriority::Background);
 
     let comm_r_old_safe = <TreeRHasher as Hasher>::Domain::try_from_bytes(&comm_r_old)?;
     let comm_r_new_safe = <TreeRHasher as Hasher>::Domain::try_from_bytes(&comm_r_new)?;
@@ -539,10 +731,84 @@ pub fn generate_empty_sector_update_proof_with_vanilla<
 
     info!("generate_empty_sector_update_proof_with_vanilla:finish");
 
-    let proofs_bytes = util::proofs_to_bytes(&proofs)?;
+    let proofs_bytes = codec::encode_groth16_proofs(&proofs)?;
     Ok(EmptySectorUpdateProof(proofs_bytes))
 }
 
+/// Generates the SNARK of a single partition of an empty sector update from its vanilla proof.
+/// The SNARKs of all partitions are combined with [`merge_empty_sector_update_partition_proofs`].
+pub fn generate_single_empty_sector_update_proof_with_vanilla<
+    Tree: 'static + MerkleTreeTrait<Hasher = TreeRHasher>,
+>(
+    porep_config: &PoRepConfig,
+    partition_index: usize,
+    vanilla_proof: PartitionProof<Tree>,
+    comm_r_old: Commitment,
+    comm_r_new: Commitment,
+    comm_d_new: Commitment,
+) -> Result<PartitionSnarkProof> {
+    info!("generate_single_empty_sector_update_proof_with_vanilla:start");
+
+    let config = SectorUpdateConfig::from_porep_config(porep_config);
+    let partitions = usize::from(config.update_partitions);
+    ensure!(partition_index < partitions, "invalid partition index");
+
+    let public_inputs =
+        partition_public_inputs(&config, partition_index, comm_r_old, comm_r_new, comm_d_new)?;
+    let public_params: storage_proofs_update::PublicParams =
+        PublicParams::from_sector_size(u64::from(config.sector_size));
+
+    let valid = EmptySectorUpdate::<Tree>::verify(&public_params, &public_inputs, &vanilla_proof)?;
+    ensure!(
+        valid,
+        "invalid vanilla proof for partition {}",
+        partition_index
+    );
+
+    // The partition index is passed explicitly, as the default circuit proving would number the
+    // partitions from zero.
+    let circuit = EmptySectorUpdateCompound::<Tree>::circuit(
+        &public_inputs,
+        (),
+        &vanilla_proof,
+        &public_params,
+        Some(partition_index),
+    )?;
+
+    let groth_params = get_empty_sector_update_params::<Tree>(porep_config)?;
+    let proofs =
+        groth16::create_random_proof_batch(vec![circuit], &*groth_params, &mut proof_rng())?;
+
+    info!("generate_single_empty_sector_update_proof_with_vanilla:finish");
+
+    let proofs_bytes = codec::encode_groth16_proofs(&proofs)?;
+    Ok(PartitionSnarkProof(proofs_bytes))
+}
+
+/// Combines the SNARKs of all partitions of an empty sector update, which must be given in the
+/// order of their partition index.
+pub fn merge_empty_sector_update_partition_proofs(
+    porep_config: &PoRepConfig,
+    proofs: Vec<PartitionSnarkProof>,
+) -> Result<EmptySectorUpdateProof> {
+    let config = SectorUpdateConfig::from_porep_config(porep_config);
+    ensure!(
+        proofs.len() == usize::from(config.update_partitions),
+        "invalid number of partition proofs"
+    );
+
+    let mut proof = Vec::with_capacity(proofs.len() * SINGLE_PARTITION_PROOF_LEN);
+    for p in proofs {
+        ensure!(
+            p.0.len() == SINGLE_PARTITION_PROOF_LEN,
+            "invalid partition proof length"
+        );
+        proof.extend(p.0);
+    }
+
+    Ok(EmptySectorUpdateProof(proof))
+}
+
 #[allow(clippy::too_many_arguments)]
 pub fn generate_empty_sector_update_proof<Tree: 'static + MerkleTreeTrait<Hasher = TreeRHasher>>(
     porep_config: &PoRepConfig,
@@ -554,6 +820,9 @@ pub fn generate_empty_sector_update_proof<Tree: 'static + MerkleTreeTrait<Hasher
     replica_path: &Path,
     replica_cache_path: &Path,
 ) -> Result<EmptySectorUpdateProof> {
+    let _span = info_span!("generate_empty_sector_update_proof").entered();
+    let timer = StageTimer::start("empty_sector_update_proof");
+    let _priority = enter_stage(ProvingPriority::Background);
     info!("generate_empty_sector_update_proof:start");
 
     let comm_r_old_safe = <TreeRHasher as Hasher>::Domain::try_from_bytes(&comm_r_old)?;
@@ -574,7 +843,11 @@ pub fn generate_empty_sector_update_proof<Tree: 'static + MerkleTreeTrait<Hasher
         h: config.h,
     };
 
-    let t_aux_old = util::get_t_aux::<Tree>(sector_key_cache_path, u64::from(config.sector_size))?;
+    let t_aux_old = util::get_t_aux::<Tree>(
+        sector_key_cache_path,
+        u64::from(config.sector_size),
+        config.layers,
+    )?;
 
     let (tree_d_new_config, tree_r_last_new_config) =
         get_new_configs_from_t_aux_old::<Tree>(&t_aux_old, replica_cache_path, config.nodes_count)?;
@@ -598,17 +871,19 @@ pub fn generate_empty_sector_update_proof<Tree: 'static + MerkleTreeTrait<Hasher
     let pub_params_compound = EmptySectorUpdateCompound::<Tree>::setup(&setup_params_compound)?;
 
     let groth_params = get_empty_sector_update_params::<Tree>(porep_config)?;
-    let proofs = EmptySectorUpdateCompound::prove(
-        &pub_params_compound,
-        &public_inputs,
-        &private_inputs,
-        &groth_params,
-    )?;
+    let proofs = metrics::time_proof("empty_sector_update", || {
+        EmptySectorUpdateCompound::prove(
+            &pub_params_compound,
+            &public_inputs,
+            &private_inputs,
+            &groth_params,
+        )
+    })?;
 
     info!("generate_empty_sector_update_proof:finish");
 
-    let proofs_bytes = util::proofs_to_bytes(&proofs)?;
-    Ok(EmptySectorUpdateProof(proofs_bytes))
+    let proofs_bytes = codec::encode_groth16_proofs(&proofs)?;
+    timer.finish(Ok(EmptySectorUpdateProof(proofs_bytes)))
 }
 
 pub fn verify_empty_sector_update_proof<Tree: 'static + MerkleTreeTrait<Hasher = TreeRHasher>>(
@@ -620,20 +895,37 @@ pub fn verify_empty_sector_update_proof<Tree: 'static + MerkleTreeTrait<Hasher =
 ) -> Result<bool> {
     info!("verify_empty_sector_update_proof:start");
 
-    let comm_r_old_safe = <TreeRHasher as Hasher>::Domain::try_from_bytes(&comm_r_old)?;
-    let comm_r_new_safe = <TreeRHasher as Hasher>::Domain::try_from_bytes(&comm_r_new)?;
+    let verifying_key = get_empty_sector_update_verifying_key::<Tree>(porep_config)?;
+    let valid = verify_empty_sector_update_proof_with_key::<Tree>(
+        porep_config,
+        &verifying_key,
+        proof_bytes,
+        comm_r_old,
+        comm_r_new,
+        comm_d_new,
+    )?;
 
-    let comm_d_new_safe = DefaultPieceDomain::try_from_bytes(&comm_d_new)?;
+    info!("verify_empty_sector_update_proof:finish");
 
+    Ok(valid)
+}
+
+/// Verifies an empty sector update proof with the given verifying key, instead of the one from
+/// the parameter cache. Besides the key, only the commitments and the proof are needed, no sector
+/// data or files are accessed.
+pub fn verify_empty_sector_update_proof_with_key<
+    Tree: 'static + MerkleTreeTrait<Hasher = TreeRHasher>,
+>(
+    porep_config: &PoRepConfig,
+    verifying_key: &Bls12PreparedVerifyingKey,
+    proof_bytes: &[u8],
+    comm_r_old: Commitment,
+    comm_r_new: Commitment,
+    comm_d_new: Commitment,
+) -> Result<bool> {
     let config = SectorUpdateConfig::from_porep_config(porep_config);
     let partitions = usize::from(config.update_partitions);
-    let public_inputs: storage_proofs_update::PublicInputs = PublicInputs {
-        k: partitions,
-        comm_r_old: comm_r_old_safe,
-        comm_d_new: comm_d_new_safe,
-        comm_r_new: comm_r_new_safe,
-        h: config.h,
-    };
+    let public_inputs = update_public_inputs(&config, comm_r_old, comm_r_new, comm_d_new)?;
     let setup_params_compound = compound_proof::SetupParams {
         vanilla_params: SetupParams {
             sector_bytes: u64::from(config.sector_size),
@@ -643,12 +935,59 @@ pub fn verify_empty_sector_update_proof<Tree: 'static + MerkleTreeTrait<Hasher =
     };
     let pub_params_compound = EmptySectorUpdateCompound::<Tree>::setup(&setup_params_compound)?;
 
-    let verifying_key = get_empty_sector_update_verifying_key::<Tree>(porep_config)?;
-    let multi_proof = MultiProof::new_from_bytes(Some(partitions), proof_bytes, &verifying_key)?;
+    let multi_proof = MultiProof::new(
+        codec::decode_partition_proofs(proof_bytes, partitions)?,
+        verifying_key,
+    );
     let valid =
         EmptySectorUpdateCompound::verify(&pub_params_compound, &public_inputs, &multi_proof, &())?;
 
-    info!("verify_empty_sector_update_proof:finish");
-
     Ok(valid)
 }
+
+fn update_public_inputs(
+    config: &SectorUpdateConfig,
+    comm_r_old: Commitment,
+    comm_r_new: Commitment,
+    comm_d_new: Commitment,
+) -> Result<PublicInputs> {
+    let comm_r_old_safe = <TreeRHasher as Hasher>::Domain::try_from_bytes(&comm_r_old)?;
+    let comm_r_new_safe = <TreeRHasher as Hasher>::Domain::try_from_bytes(&comm_r_new)?;
+
+    let comm_d_new_safe = DefaultPieceDomain::try_from_bytes(&comm_d_new)?;
+
+    Ok(PublicInputs {
+        k: usize::from(config.update_partitions),
+        comm_r_old: comm_r_old_safe,
+        comm_d_new: comm_d_new_safe,
+        comm_r_new: comm_r_new_safe,
+        h: config.h,
+    })
+}
+
+/// Returns the public inputs of the Groth16 proofs of an empty sector update, one vector per
+/// partition, in the order that [`verify_empty_sector_update_proof`] passes them to the verifier.
+pub fn public_inputs_for_empty_sector_update<
+    Tree: 'static + MerkleTreeTrait<Hasher = TreeRHasher>,
+>(
+    porep_config: &PoRepConfig,
+    comm_r_old: Commitment,
+    comm_r_new: Commitment,
+    comm_d_new: Commitment,
+) -> Result<Vec<Vec<Fr>>> {
+    let config = SectorUpdateConfig::from_porep_config(porep_config);
+    let public_inputs = update_public_inputs(&config, comm_r_old, comm_r_new, comm_d_new)?;
+    let pub_params = EmptySectorUpdate::<Tree>::setup(&SetupParams {
+        sector_bytes: u64::from(config.sector_size),
+    })?;
+
+    (0..usize::from(config.update_partitions))
+        .map(|k| {
+            EmptySectorUpdateCompound::<Tree>::generate_public_inputs(
+                &public_inputs,
+                &pub_params,
+                Some(k),
+            )
+        })
+        .collect()
+}
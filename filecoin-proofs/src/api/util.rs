@@ -1,8 +1,7 @@
 use std::{fs, mem::size_of, path::Path};
 
 use anyhow::{Context, Result};
-use bellperson::groth16::Proof;
-use blstrs::{Bls12, Scalar as Fr};
+use blstrs::Scalar as Fr;
 use filecoin_hashers::{Domain, Hasher};
 use fr32::{bytes_into_fr, fr_into_bytes};
 use log::trace;
@@ -48,14 +47,6 @@ pub fn get_base_tree_leafs<Tree: MerkleTreeTrait>(base_tree_size: usize) -> Resu
     get_merkle_tree_leafs(base_tree_size, Tree::Arity::to_usize())
 }
 
-pub(crate) fn proofs_to_bytes(proofs: &[Proof<Bls12>]) -> Result<Vec<u8>> {
-    let mut out = Vec::with_capacity(Proof::<Bls12>::size());
-    for proof in proofs {
-        proof.write(&mut out).context("known allocation target")?;
-    }
-    Ok(out)
-}
-
 /// Persist p_aux.
 pub(crate) fn persist_p_aux<Tree: MerkleTreeTrait>(
     p_aux: &PersistentAux<<Tree::Hasher as Hasher>::Domain>,
@@ -106,6 +97,7 @@ fn read_t_aux_file<Tree: MerkleTreeTrait>(
 pub(crate) fn get_t_aux<Tree: MerkleTreeTrait>(
     cache_path: &Path,
     sector_bytes: u64,
+    layers: usize,
 ) -> Result<TemporaryAux<Tree, DefaultPieceHasher>> {
     trace!(
         "Instantiating TemporaryAux from default values with cache_path at {:?}",
@@ -120,12 +112,6 @@ pub(crate) fn get_t_aux<Tree: MerkleTreeTrait>(
         read_t_aux_file(cache_path)
     } else {
         let sector_nodes = sector_bytes as usize / storage_proofs_core::util::NODE_SIZE;
-        let layers = *crate::constants::LAYERS
-            .read()
-            .expect("LAYERS poisoned")
-            .get(&sector_bytes)
-            .expect("unknown sector size");
-
         Ok(TemporaryAux::new(
             sector_nodes,
             layers,
@@ -139,9 +125,10 @@ pub(crate) fn get_t_aux<Tree: MerkleTreeTrait>(
 #[cfg(not(feature = "fixed-rows-to-discard"))]
 pub(crate) fn get_t_aux<Tree: MerkleTreeTrait>(
     cache_path: &Path,
-    // `sector_bytes` is ignored, it's only there to have the same API as if the
+    // `sector_bytes` and `layers` are ignored, they are only there to have the same API as if the
     // `fixed-rows-to-discard` feature was enabled.
     _sector_bytes: u64,
+    _layers: usize,
 ) -> Result<TemporaryAux<Tree, DefaultPieceHasher>> {
     read_t_aux_file(cache_path)
 }
@@ -173,7 +160,7 @@ mod tests {
     #[test]
     fn test_get_t_aux_defaults() {
         let dir_does_not_exist = Path::new("/path/does/not/exist");
-        let t_aux = get_t_aux::<SectorShape32GiB>(dir_does_not_exist, SECTOR_SIZE_32_GIB)
+        let t_aux = get_t_aux::<SectorShape32GiB>(dir_does_not_exist, SECTOR_SIZE_32_GIB, 11)
             .expect("t_aux should have been read");
         let expected_rows_to_discard = util::default_rows_to_discard(
             SECTOR_SIZE_32_GIB as usize / NODE_SIZE,
@@ -198,7 +185,7 @@ mod tests {
             11,
             cache_dir.clone(),
         );
-        let t_aux = get_t_aux::<SectorShape32GiB>(&cache_dir, SECTOR_SIZE_32_GIB)
+        let t_aux = get_t_aux::<SectorShape32GiB>(&cache_dir, SECTOR_SIZE_32_GIB, 11)
             .expect("t_aux should have been read");
         assert_eq!(
             t_aux.tree_r_last_config.rows_to_discard,
@@ -210,7 +197,7 @@ mod tests {
         custom_t_aux.tree_r_last_config.rows_to_discard = 5;
         persist_t_aux::<SectorShape32GiB>(&custom_t_aux, &cache_dir)
             .expect("t_aux should have been persisted");
-        let read_t_aux = get_t_aux::<SectorShape32GiB>(&cache_dir, SECTOR_SIZE_32_GIB)
+        let read_t_aux = get_t_aux::<SectorShape32GiB>(&cache_dir, SECTOR_SIZE_32_GIB, 11)
             .expect("t_aux should have been read");
         assert_ne!(
             read_t_aux.tree_r_last_config.rows_to_discard,
@@ -1,29 +1,43 @@
 use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
 
 use anyhow::{ensure, Context, Result};
+use bellperson::groth16::{self, verify_proofs_batch};
+use blstrs::{Bls12, Scalar as Fr};
 use filecoin_hashers::Hasher;
-use log::info;
+use log::{info, warn};
+use rand::rngs::OsRng;
 use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
 use storage_proofs_core::{
     compound_proof::{self, CompoundProof},
     merkle::MerkleTreeTrait,
     multi_proof::MultiProof,
+    proof::ProofScheme,
     sector::SectorId,
+    settings::SETTINGS,
 };
 use storage_proofs_post::fallback::{
     self, FallbackPoSt, FallbackPoStCompound, PrivateSector, PublicSector,
 };
+use tracing::info_span;
 
 use crate::{
     api::{
-        as_safe_commitment, get_partitions_for_window_post, partition_vanilla_proofs,
-        single_partition_vanilla_proofs, util,
+        as_safe_commitment, generate_fallback_sector_challenges_with_domain,
+        generate_single_vanilla_proof, get_partitions_for_window_post,
+        merge_window_post_partition_proofs, partition_vanilla_proofs,
+        post_util::generate_sector_challenges_from, prefetch_fallback_post_challenges_until,
+        single_partition_vanilla_proofs,
     },
     caches::{get_post_params, get_post_verifying_key},
-    parameters::window_post_setup_params,
+    codec,
+    metrics::{self, StageTimer},
+    parameters::window_post_setup_params_with_domain,
+    priority::{enter_stage, ProvingPriority},
     types::{
-        ChallengeSeed, FallbackPoStSectorProof, PoStConfig, PrivateReplicaInfo, ProverId,
-        PublicReplicaInfo, SnarkProof,
+        ChallengeDomain, ChallengeSeed, FallbackPoStSectorProof, PoStConfig, PrivateReplicaInfo,
+        ProverId, PublicReplicaInfo, SnarkProof,
     },
     PartitionSnarkProof, PoStType,
 };
@@ -34,8 +48,26 @@ pub fn generate_window_post_with_vanilla<Tree: 'static + MerkleTreeTrait>(
     randomness: &ChallengeSeed,
     prover_id: ProverId,
     vanilla_proofs: Vec<FallbackPoStSectorProof<Tree>>,
+) -> Result<SnarkProof> {
+    generate_window_post_with_vanilla_with_domain::<Tree>(
+        post_config,
+        randomness,
+        prover_id,
+        vanilla_proofs,
+        &ChallengeDomain::Mainnet,
+    )
+}
+
+/// Like [`generate_window_post_with_vanilla`], with the challenges derived in `challenge_domain`.
+pub fn generate_window_post_with_vanilla_with_domain<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomness: &ChallengeSeed,
+    prover_id: ProverId,
+    vanilla_proofs: Vec<FallbackPoStSectorProof<Tree>>,
+    challenge_domain: &ChallengeDomain,
 ) -> Result<SnarkProof> {
     info!("generate_window_post_with_vanilla:start");
+    let _priority = enter_stage(ProvingPriority::High);
     ensure!(
         post_config.typ == PoStType::Window,
         "invalid post config type"
@@ -44,9 +76,9 @@ pub fn generate_window_post_with_vanilla<Tree: 'static + MerkleTreeTrait>(
     let randomness_safe: <Tree::Hasher as Hasher>::Domain =
         as_safe_commitment(randomness, "randomness")?;
     let prover_id_safe: <Tree::Hasher as Hasher>::Domain =
-        as_safe_commitment(&prover_id, "prover_id")?;
+        as_safe_commitment(prover_id.as_bytes(), "prover_id")?;
 
-    let vanilla_params = window_post_setup_params(post_config);
+    let vanilla_params = window_post_setup_params_with_domain(post_config, challenge_domain);
     let partitions = get_partitions_for_window_post(vanilla_proofs.len(), post_config);
 
     let setup_params = compound_proof::SetupParams {
@@ -93,7 +125,7 @@ pub fn generate_window_post_with_vanilla<Tree: 'static + MerkleTreeTrait>(
 
     info!("generate_window_post_with_vanilla:finish");
 
-    util::proofs_to_bytes(&proofs)
+    codec::encode_groth16_proofs(&proofs)
 }
 
 /// Generates a Window proof-of-spacetime.
@@ -103,16 +135,36 @@ pub fn generate_window_post<Tree: 'static + MerkleTreeTrait>(
     replicas: &BTreeMap<SectorId, PrivateReplicaInfo<Tree>>,
     prover_id: ProverId,
 ) -> Result<SnarkProof> {
+    generate_window_post_with_domain::<Tree>(
+        post_config,
+        randomness,
+        replicas,
+        prover_id,
+        &ChallengeDomain::Mainnet,
+    )
+}
+
+/// Like [`generate_window_post`], with the challenges derived in `challenge_domain`.
+pub fn generate_window_post_with_domain<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomness: &ChallengeSeed,
+    replicas: &BTreeMap<SectorId, PrivateReplicaInfo<Tree>>,
+    prover_id: ProverId,
+    challenge_domain: &ChallengeDomain,
+) -> Result<SnarkProof> {
+    let _span = info_span!("generate_window_post", sectors = replicas.len()).entered();
+    let timer = StageTimer::start("window_post");
     info!("generate_window_post:start");
+    let _priority = enter_stage(ProvingPriority::High);
     ensure!(
         post_config.typ == PoStType::Window,
         "invalid post config type"
     );
 
     let randomness_safe = as_safe_commitment(randomness, "randomness")?;
-    let prover_id_safe = as_safe_commitment(&prover_id, "prover_id")?;
+    let prover_id_safe = as_safe_commitment(prover_id.as_bytes(), "prover_id")?;
 
-    let vanilla_params = window_post_setup_params(post_config);
+    let vanilla_params = window_post_setup_params_with_domain(post_config, challenge_domain);
     let partitions = get_partitions_for_window_post(replicas.len(), post_config);
 
     let sector_count = vanilla_params.sector_count;
@@ -126,55 +178,96 @@ pub fn generate_window_post<Tree: 'static + MerkleTreeTrait>(
         FallbackPoStCompound::setup(&setup_params)?;
     let groth_params = get_post_params::<Tree>(post_config)?;
 
-    let trees: Vec<_> = replicas
-        .par_iter()
-        .map(|(sector_id, replica)| {
-            replica
-                .merkle_tree(post_config.sector_size)
-                .with_context(|| {
-                    format!("generate_window_post: merkle_tree failed: {:?}", sector_id)
-                })
-        })
-        .collect::<Result<_>>()?;
+    let prefetch_challenges = if SETTINGS.window_post_prefetch_threads > 0 {
+        let sector_ids: Vec<SectorId> = replicas.keys().copied().collect();
+        Some(generate_fallback_sector_challenges_with_domain::<Tree>(
+            post_config,
+            randomness,
+            &sector_ids,
+            prover_id,
+            challenge_domain,
+        )?)
+    } else {
+        None
+    };
 
-    let mut pub_sectors = Vec::with_capacity(sector_count);
-    let mut priv_sectors = Vec::with_capacity(sector_count);
+    let prove = || -> Result<Vec<groth16::Proof<Bls12>>> {
+        let trees: Vec<_> = replicas
+            .par_iter()
+            .map(|(sector_id, replica)| {
+                replica
+                    .merkle_tree(post_config.sector_size)
+                    .with_context(|| {
+                        format!("generate_window_post: merkle_tree failed: {:?}", sector_id)
+                    })
+            })
+            .collect::<Result<_>>()?;
 
-    for ((sector_id, replica), tree) in replicas.iter().zip(trees.iter()) {
-        let comm_r = replica.safe_comm_r().with_context(|| {
-            format!("generate_window_post: safe_comm_r failed: {:?}", sector_id)
-        })?;
-        let comm_c = replica.safe_comm_c();
-        let comm_r_last = replica.safe_comm_r_last();
+        let mut pub_sectors = Vec::with_capacity(sector_count);
+        let mut priv_sectors = Vec::with_capacity(sector_count);
 
-        pub_sectors.push(PublicSector {
-            id: *sector_id,
-            comm_r,
-        });
-        priv_sectors.push(PrivateSector {
-            tree,
-            comm_c,
-            comm_r_last,
-        });
-    }
+        for ((sector_id, replica), tree) in replicas.iter().zip(trees.iter()) {
+            let comm_r = replica.safe_comm_r().with_context(|| {
+                format!("generate_window_post: safe_comm_r failed: {:?}", sector_id)
+            })?;
+            let comm_c = replica.safe_comm_c();
+            let comm_r_last = replica.safe_comm_r_last();
 
-    let pub_inputs = fallback::PublicInputs {
-        randomness: randomness_safe,
-        prover_id: prover_id_safe,
-        sectors: pub_sectors,
-        k: None,
+            pub_sectors.push(PublicSector {
+                id: *sector_id,
+                comm_r,
+            });
+            priv_sectors.push(PrivateSector {
+                tree,
+                comm_c,
+                comm_r_last,
+            });
+        }
+
+        let pub_inputs = fallback::PublicInputs {
+            randomness: randomness_safe,
+            prover_id: prover_id_safe,
+            sectors: pub_sectors,
+            k: None,
+        };
+
+        let priv_inputs = fallback::PrivateInputs::<Tree> {
+            sectors: &priv_sectors,
+        };
+
+        metrics::time_proof("window_post", || {
+            FallbackPoStCompound::prove(&pub_params, &pub_inputs, &priv_inputs, &groth_params)
+        })
     };
 
-    let priv_inputs = fallback::PrivateInputs::<Tree> {
-        sectors: &priv_sectors,
+    let proofs = match &prefetch_challenges {
+        None => prove()?,
+        // Read ahead the challenged data while the trees are opened and the vanilla proofs are
+        // generated, both go through the sectors in the same order.
+        Some(challenges) => {
+            let done = AtomicBool::new(false);
+            thread::scope(|scope| {
+                scope.spawn(|| {
+                    if let Err(err) = prefetch_fallback_post_challenges_until(
+                        post_config,
+                        replicas,
+                        challenges,
+                        SETTINGS.window_post_prefetch_threads,
+                        &done,
+                    ) {
+                        warn!("generate_window_post: read ahead failed: {:?}", err);
+                    }
+                });
+                let proofs = prove();
+                done.store(true, Ordering::Relaxed);
+                proofs
+            })?
+        }
     };
 
-    let proofs =
-        FallbackPoStCompound::prove(&pub_params, &pub_inputs, &priv_inputs, &groth_params)?;
-
     info!("generate_window_post:finish");
 
-    util::proofs_to_bytes(&proofs)
+    timer.finish(codec::encode_groth16_proofs(&proofs))
 }
 
 /// Verifies a window proof-of-spacetime.
@@ -184,6 +277,25 @@ pub fn verify_window_post<Tree: 'static + MerkleTreeTrait>(
     replicas: &BTreeMap<SectorId, PublicReplicaInfo>,
     prover_id: ProverId,
     proof: &[u8],
+) -> Result<bool> {
+    verify_window_post_with_domain::<Tree>(
+        post_config,
+        randomness,
+        replicas,
+        prover_id,
+        proof,
+        &ChallengeDomain::Mainnet,
+    )
+}
+
+/// Like [`verify_window_post`], with the challenges derived in `challenge_domain`.
+pub fn verify_window_post_with_domain<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomness: &ChallengeSeed,
+    replicas: &BTreeMap<SectorId, PublicReplicaInfo>,
+    prover_id: ProverId,
+    proof: &[u8],
+    challenge_domain: &ChallengeDomain,
 ) -> Result<bool> {
     info!("verify_window_post:start");
 
@@ -192,10 +304,7 @@ pub fn verify_window_post<Tree: 'static + MerkleTreeTrait>(
         "invalid post config type"
     );
 
-    let randomness_safe = as_safe_commitment(randomness, "randomness")?;
-    let prover_id_safe = as_safe_commitment(&prover_id, "prover_id")?;
-
-    let vanilla_params = window_post_setup_params(post_config);
+    let vanilla_params = window_post_setup_params_with_domain(post_config, challenge_domain);
     let partitions = get_partitions_for_window_post(replicas.len(), post_config);
 
     let setup_params = compound_proof::SetupParams {
@@ -205,6 +314,40 @@ pub fn verify_window_post<Tree: 'static + MerkleTreeTrait>(
     };
     let pub_params: compound_proof::PublicParams<'_, FallbackPoSt<'_, Tree>> =
         FallbackPoStCompound::setup(&setup_params)?;
+    let pub_inputs = window_post_public_inputs::<Tree>(randomness, replicas, prover_id)?;
+
+    let is_valid = {
+        let verifying_key = get_post_verifying_key::<Tree>(post_config)?;
+        let multi_proof = MultiProof::new(
+            codec::decode_partition_proofs(proof, partitions.unwrap_or(1))?,
+            &verifying_key,
+        );
+
+        FallbackPoStCompound::verify(
+            &pub_params,
+            &pub_inputs,
+            &multi_proof,
+            &fallback::ChallengeRequirements {
+                minimum_challenge_count: post_config.challenge_count * post_config.sector_count,
+            },
+        )?
+    };
+    if !is_valid {
+        return Ok(false);
+    }
+
+    info!("verify_window_post:finish");
+
+    Ok(true)
+}
+
+fn window_post_public_inputs<Tree: 'static + MerkleTreeTrait>(
+    randomness: &ChallengeSeed,
+    replicas: &BTreeMap<SectorId, PublicReplicaInfo>,
+    prover_id: ProverId,
+) -> Result<fallback::PublicInputs<<Tree::Hasher as Hasher>::Domain>> {
+    let randomness_safe = as_safe_commitment(randomness, "randomness")?;
+    let prover_id_safe = as_safe_commitment(prover_id.as_bytes(), "prover_id")?;
 
     let pub_sectors: Vec<_> = replicas
         .iter()
@@ -219,33 +362,164 @@ pub fn verify_window_post<Tree: 'static + MerkleTreeTrait>(
         })
         .collect::<Result<_>>()?;
 
-    let pub_inputs = fallback::PublicInputs {
+    Ok(fallback::PublicInputs {
         randomness: randomness_safe,
         prover_id: prover_id_safe,
         sectors: pub_sectors,
         k: None,
-    };
+    })
+}
 
-    let is_valid = {
-        let verifying_key = get_post_verifying_key::<Tree>(post_config)?;
-        let multi_proof = MultiProof::new_from_bytes(partitions, proof, &verifying_key)?;
+/// Returns the public inputs of the Groth16 proofs of a Window proof-of-spacetime, one vector per
+/// partition, in the order that [`verify_window_post`] passes them to the verifier.
+pub fn public_inputs_for_window_post<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomness: &ChallengeSeed,
+    replicas: &BTreeMap<SectorId, PublicReplicaInfo>,
+    prover_id: ProverId,
+) -> Result<Vec<Vec<Fr>>> {
+    public_inputs_for_window_post_with_domain::<Tree>(
+        post_config,
+        randomness,
+        replicas,
+        prover_id,
+        &ChallengeDomain::Mainnet,
+    )
+}
 
-        FallbackPoStCompound::verify(
-            &pub_params,
-            &pub_inputs,
-            &multi_proof,
-            &fallback::ChallengeRequirements {
-                minimum_challenge_count: post_config.challenge_count * post_config.sector_count,
-            },
-        )?
+/// Like [`public_inputs_for_window_post`], with the challenges derived in `challenge_domain`.
+pub fn public_inputs_for_window_post_with_domain<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomness: &ChallengeSeed,
+    replicas: &BTreeMap<SectorId, PublicReplicaInfo>,
+    prover_id: ProverId,
+    challenge_domain: &ChallengeDomain,
+) -> Result<Vec<Vec<Fr>>> {
+    ensure!(
+        post_config.typ == PoStType::Window,
+        "invalid post config type"
+    );
+
+    let pub_params = FallbackPoSt::<Tree>::setup(&window_post_setup_params_with_domain(
+        post_config,
+        challenge_domain,
+    ))?;
+    let pub_inputs = window_post_public_inputs::<Tree>(randomness, replicas, prover_id)?;
+    let partitions = get_partitions_for_window_post(replicas.len(), post_config).unwrap_or(1);
+
+    (0..partitions)
+        .map(|k| {
+            FallbackPoStCompound::<Tree>::generate_public_inputs(&pub_inputs, &pub_params, Some(k))
+        })
+        .collect()
+}
+
+/// A Window proof-of-spacetime of a single miner to verify.
+pub struct WindowPoStVerifyInfo<'a> {
+    pub randomness: ChallengeSeed,
+    pub prover_id: ProverId,
+    pub replicas: &'a BTreeMap<SectorId, PublicReplicaInfo>,
+    pub proof: &'a [u8],
+}
+
+/// Verifies the Window proofs-of-spacetime of several miners at once.
+///
+/// The pairing checks of all proofs are combined into a single randomized batch check. Only if
+/// that fails, every proof is checked on its own to find out which ones are invalid. The results
+/// are returned in the order of the inputs, an input that cannot be verified at all (e.g. because
+/// its proof bytes are malformed) is an error that does not affect the others. The outer `Result`
+/// fails only if the shared setup fails.
+pub fn verify_window_post_batch<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    infos: &[WindowPoStVerifyInfo<'_>],
+) -> Result<Vec<Result<bool>>> {
+    verify_window_post_batch_with_domain::<Tree>(post_config, infos, &ChallengeDomain::Mainnet)
+}
+
+/// Like [`verify_window_post_batch`], with the challenges derived in `challenge_domain`.
+pub fn verify_window_post_batch_with_domain<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    infos: &[WindowPoStVerifyInfo<'_>],
+    challenge_domain: &ChallengeDomain,
+) -> Result<Vec<Result<bool>>> {
+    info!("verify_window_post_batch:start: {} proofs", infos.len());
+    ensure!(
+        post_config.typ == PoStType::Window,
+        "invalid post config type"
+    );
+
+    let pub_params = FallbackPoSt::<Tree>::setup(&window_post_setup_params_with_domain(
+        post_config,
+        challenge_domain,
+    ))?;
+    let verifying_key = get_post_verifying_key::<Tree>(post_config)?;
+    let requirements = fallback::ChallengeRequirements {
+        minimum_challenge_count: post_config.challenge_count * post_config.sector_count,
     };
-    if !is_valid {
-        return Ok(false);
-    }
 
-    info!("verify_window_post:finish");
+    // The partition proofs and their public inputs, `None` if the proof does not satisfy the
+    // challenge requirements.
+    #[allow(clippy::type_complexity)]
+    let prepared: Vec<Result<Option<(Vec<groth16::Proof<Bls12>>, Vec<Vec<Fr>>)>>> = infos
+        .par_iter()
+        .map(|info| {
+            let partitions = get_partitions_for_window_post(info.replicas.len(), post_config);
+            let num_partitions = partitions.unwrap_or(1);
+            if !FallbackPoSt::<Tree>::satisfies_requirements(
+                &pub_params,
+                &requirements,
+                num_partitions,
+            ) {
+                return Ok(None);
+            }
+
+            let pub_inputs =
+                window_post_public_inputs::<Tree>(&info.randomness, info.replicas, info.prover_id)?;
+            let multi_proof = MultiProof::new(
+                codec::decode_partition_proofs(info.proof, num_partitions)?,
+                &verifying_key,
+            );
+            let inputs = (0..num_partitions)
+                .map(|k| {
+                    FallbackPoStCompound::<Tree>::generate_public_inputs(
+                        &pub_inputs,
+                        &pub_params,
+                        Some(k),
+                    )
+                })
+                .collect::<Result<_>>()?;
+            Ok(Some((multi_proof.circuit_proofs, inputs)))
+        })
+        .collect();
 
-    Ok(true)
+    let (proofs, inputs): (Vec<&groth16::Proof<Bls12>>, Vec<Vec<Fr>>) = prepared
+        .iter()
+        .flat_map(|prepared| prepared.iter().flatten())
+        .flat_map(|(proofs, inputs)| proofs.iter().zip(inputs.iter().cloned()))
+        .unzip();
+    let all_valid =
+        proofs.is_empty() || verify_proofs_batch(&verifying_key, &mut OsRng, &proofs, &inputs)?;
+
+    let results = prepared
+        .into_iter()
+        .map(|prepared| match prepared? {
+            None => Ok(false),
+            Some(_) if all_valid => Ok(true),
+            Some((proofs, inputs)) => {
+                let proofs: Vec<_> = proofs.iter().collect();
+                Ok(verify_proofs_batch(
+                    &verifying_key,
+                    &mut OsRng,
+                    &proofs,
+                    &inputs,
+                )?)
+            }
+        })
+        .collect();
+
+    info!("verify_window_post_batch:finish");
+
+    Ok(results)
 }
 
 /// Generates a Window proof-of-spacetime with provided vanilla proofs of a single partition.
@@ -255,8 +529,29 @@ pub fn generate_single_window_post_with_vanilla<Tree: 'static + MerkleTreeTrait>
     prover_id: ProverId,
     vanilla_proofs: Vec<FallbackPoStSectorProof<Tree>>,
     partition_index: usize,
+) -> Result<PartitionSnarkProof> {
+    generate_single_window_post_with_vanilla_with_domain::<Tree>(
+        post_config,
+        randomness,
+        prover_id,
+        vanilla_proofs,
+        partition_index,
+        &ChallengeDomain::Mainnet,
+    )
+}
+
+/// Like [`generate_single_window_post_with_vanilla`], with the challenges derived in
+/// `challenge_domain`.
+pub fn generate_single_window_post_with_vanilla_with_domain<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomness: &ChallengeSeed,
+    prover_id: ProverId,
+    vanilla_proofs: Vec<FallbackPoStSectorProof<Tree>>,
+    partition_index: usize,
+    challenge_domain: &ChallengeDomain,
 ) -> Result<PartitionSnarkProof> {
     info!("generate_single_window_post_with_vanilla:start");
+    let _priority = enter_stage(ProvingPriority::High);
     ensure!(
         post_config.typ == PoStType::Window,
         "invalid post config type"
@@ -265,9 +560,9 @@ pub fn generate_single_window_post_with_vanilla<Tree: 'static + MerkleTreeTrait>
     let randomness_safe: <Tree::Hasher as Hasher>::Domain =
         as_safe_commitment(randomness, "randomness")?;
     let prover_id_safe: <Tree::Hasher as Hasher>::Domain =
-        as_safe_commitment(&prover_id, "prover_id")?;
+        as_safe_commitment(prover_id.as_bytes(), "prover_id")?;
 
-    let vanilla_params = window_post_setup_params(post_config);
+    let vanilla_params = window_post_setup_params_with_domain(post_config, challenge_domain);
     let partitions = get_partitions_for_window_post(vanilla_proofs.len(), post_config);
 
     let setup_params = compound_proof::SetupParams {
@@ -311,6 +606,168 @@ pub fn generate_single_window_post_with_vanilla<Tree: 'static + MerkleTreeTrait>
 
     info!("generate_single_window_post_with_vanilla:finish");
 
-    let proofs_bytes = util::proofs_to_bytes(&proofs)?;
+    let proofs_bytes = codec::encode_groth16_proofs(&proofs)?;
     Ok(PartitionSnarkProof(proofs_bytes))
 }
+
+/// Generates the Window proof-of-spacetime of partition `partition_index` of `replicas`, the
+/// whole sector set of the deadline, without opening the trees of all of its sectors at once.
+///
+/// The partition's sectors are proven in sub-partitions of at most `sub_partition_size`
+/// sectors, only the trees of one sub-partition are held in memory at a time. The vanilla proofs
+/// of the sub-partitions are merged in sector order, which is the order the circuit expects, and
+/// proven with a single Groth16 proof. The result is therefore identical in format to the one of
+/// [`generate_single_window_post_with_vanilla`] and is merged and verified the same way; there is
+/// no separate proof per sub-partition.
+pub fn generate_single_window_post_in_sub_partitions<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomness: &ChallengeSeed,
+    replicas: &BTreeMap<SectorId, PrivateReplicaInfo<Tree>>,
+    prover_id: ProverId,
+    partition_index: usize,
+    sub_partition_size: usize,
+) -> Result<PartitionSnarkProof> {
+    generate_single_window_post_in_sub_partitions_with_domain::<Tree>(
+        post_config,
+        randomness,
+        replicas,
+        prover_id,
+        partition_index,
+        sub_partition_size,
+        &ChallengeDomain::Mainnet,
+    )
+}
+
+/// Like [`generate_single_window_post_in_sub_partitions`], with the challenges derived in
+/// `challenge_domain`.
+pub fn generate_single_window_post_in_sub_partitions_with_domain<
+    Tree: 'static + MerkleTreeTrait,
+>(
+    post_config: &PoStConfig,
+    randomness: &ChallengeSeed,
+    replicas: &BTreeMap<SectorId, PrivateReplicaInfo<Tree>>,
+    prover_id: ProverId,
+    partition_index: usize,
+    sub_partition_size: usize,
+    challenge_domain: &ChallengeDomain,
+) -> Result<PartitionSnarkProof> {
+    let _span = info_span!(
+        "generate_single_window_post_in_sub_partitions",
+        partition_index,
+        sub_partition_size
+    )
+    .entered();
+    info!("generate_single_window_post_in_sub_partitions:start");
+    ensure!(
+        post_config.typ == PoStType::Window,
+        "invalid post config type"
+    );
+    ensure!(sub_partition_size > 0, "sub-partitions cannot be empty");
+
+    let sector_ids: Vec<SectorId> = replicas.keys().copied().collect();
+    let partition_sector_ids = sector_ids
+        .chunks(post_config.sector_count)
+        .nth(partition_index)
+        .with_context(|| {
+            format!(
+                "invalid partition index {} for {} sectors",
+                partition_index,
+                sector_ids.len()
+            )
+        })?;
+
+    // Only the challenges of the partition's sectors are derived.
+    let challenges = generate_sector_challenges_from::<Tree>(
+        post_config,
+        randomness,
+        partition_sector_ids,
+        partition_index * post_config.sector_count,
+        challenge_domain,
+    )?;
+
+    let mut vanilla_proofs = Vec::with_capacity(partition_sector_ids.len());
+    for (i, sub_partition) in partition_sector_ids.chunks(sub_partition_size).enumerate() {
+        info!(
+            "proving sub-partition {} of partition {} ({} sectors)",
+            i,
+            partition_index,
+            sub_partition.len()
+        );
+        let sub_partition_proofs: Vec<_> = sub_partition
+            .par_iter()
+            .map(|sector_id| {
+                generate_single_vanilla_proof::<Tree>(
+                    post_config,
+                    *sector_id,
+                    &replicas[sector_id],
+                    &challenges[sector_id],
+                )
+            })
+            .collect::<Result<_>>()?;
+        vanilla_proofs.extend(sub_partition_proofs);
+    }
+
+    let proof = generate_single_window_post_with_vanilla_with_domain(
+        post_config,
+        randomness,
+        prover_id,
+        vanilla_proofs,
+        partition_index,
+        challenge_domain,
+    )?;
+
+    info!("generate_single_window_post_in_sub_partitions:finish");
+
+    Ok(proof)
+}
+
+/// Generates a Window proof-of-spacetime like [`generate_window_post`], but proves the sectors of
+/// each partition in sub-partitions of at most `sub_partition_size` sectors, see
+/// [`generate_single_window_post_in_sub_partitions`]. This bounds the memory to the trees of
+/// `sub_partition_size` sectors instead of the trees of all sectors.
+pub fn generate_window_post_in_sub_partitions<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomness: &ChallengeSeed,
+    replicas: &BTreeMap<SectorId, PrivateReplicaInfo<Tree>>,
+    prover_id: ProverId,
+    sub_partition_size: usize,
+) -> Result<SnarkProof> {
+    generate_window_post_in_sub_partitions_with_domain::<Tree>(
+        post_config,
+        randomness,
+        replicas,
+        prover_id,
+        sub_partition_size,
+        &ChallengeDomain::Mainnet,
+    )
+}
+
+/// Like [`generate_window_post_in_sub_partitions`], with the challenges derived in
+/// `challenge_domain`.
+pub fn generate_window_post_in_sub_partitions_with_domain<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomness: &ChallengeSeed,
+    replicas: &BTreeMap<SectorId, PrivateReplicaInfo<Tree>>,
+    prover_id: ProverId,
+    sub_partition_size: usize,
+    challenge_domain: &ChallengeDomain,
+) -> Result<SnarkProof> {
+    let timer = StageTimer::start("window_post");
+    let partitions = get_partitions_for_window_post(replicas.len(), post_config).unwrap_or(1);
+
+    let proofs = (0..partitions)
+        .map(|partition_index| {
+            generate_single_window_post_in_sub_partitions_with_domain(
+                post_config,
+                randomness,
+                replicas,
+                prover_id,
+                partition_index,
+                sub_partition_size,
+                challenge_domain,
+            )
+        })
+        .collect::<Result<_>>()?;
+
+    timer.finish(merge_window_post_partition_proofs(proofs))
+}
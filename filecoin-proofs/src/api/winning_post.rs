@@ -1,23 +1,32 @@
 use anyhow::{ensure, Context, Result};
+use bellperson::groth16;
+use blstrs::Scalar as Fr;
 use filecoin_hashers::Hasher;
 use log::info;
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
 use storage_proofs_core::{
     compound_proof::{self, CompoundProof},
     merkle::MerkleTreeTrait,
     multi_proof::MultiProof,
+    proof::ProofScheme,
+    proof_rng::proof_rng,
     sector::SectorId,
 };
 use storage_proofs_post::fallback::{
-    self, generate_sector_challenges, FallbackPoSt, FallbackPoStCompound, PrivateSector,
-    PublicSector,
+    self, generate_sector_challenges_with_domain, FallbackPoSt, FallbackPoStCompound,
+    PrivateSector, PublicSector,
 };
+use tracing::info_span;
 
 use crate::{
-    api::{as_safe_commitment, partition_vanilla_proofs, util},
+    api::{as_safe_commitment, partition_vanilla_proofs},
     caches::{get_post_params, get_post_verifying_key},
-    parameters::winning_post_setup_params,
+    codec,
+    metrics::{self, StageTimer},
+    parameters::winning_post_setup_params_with_domain,
+    priority::{enter_stage, ProvingPriority},
     types::{
-        ChallengeSeed, Commitment, FallbackPoStSectorProof, PoStConfig, PrivateReplicaInfo,
+        ChallengeDomain, ChallengeSeed, FallbackPoStSectorProof, PoStConfig, PrivateReplicaInfo,
         ProverId, PublicReplicaInfo, SnarkProof,
     },
     PoStType,
@@ -29,8 +38,26 @@ pub fn generate_winning_post_with_vanilla<Tree: 'static + MerkleTreeTrait>(
     randomness: &ChallengeSeed,
     prover_id: ProverId,
     vanilla_proofs: Vec<FallbackPoStSectorProof<Tree>>,
+) -> Result<SnarkProof> {
+    generate_winning_post_with_vanilla_with_domain::<Tree>(
+        post_config,
+        randomness,
+        prover_id,
+        vanilla_proofs,
+        &ChallengeDomain::Mainnet,
+    )
+}
+
+/// Like [`generate_winning_post_with_vanilla`], with the challenges derived in `challenge_domain`.
+pub fn generate_winning_post_with_vanilla_with_domain<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomness: &ChallengeSeed,
+    prover_id: ProverId,
+    vanilla_proofs: Vec<FallbackPoStSectorProof<Tree>>,
+    challenge_domain: &ChallengeDomain,
 ) -> Result<SnarkProof> {
     info!("generate_winning_post_with_vanilla:start");
+    let _priority = enter_stage(ProvingPriority::High);
     ensure!(
         post_config.typ == PoStType::Winning,
         "invalid post config type"
@@ -44,9 +71,9 @@ pub fn generate_winning_post_with_vanilla<Tree: 'static + MerkleTreeTrait>(
     let randomness_safe: <Tree::Hasher as Hasher>::Domain =
         as_safe_commitment(randomness, "randomness")?;
     let prover_id_safe: <Tree::Hasher as Hasher>::Domain =
-        as_safe_commitment(&prover_id, "prover_id")?;
+        as_safe_commitment(prover_id.as_bytes(), "prover_id")?;
 
-    let vanilla_params = winning_post_setup_params(post_config)?;
+    let vanilla_params = winning_post_setup_params_with_domain(post_config, challenge_domain)?;
 
     let setup_params = compound_proof::SetupParams {
         vanilla_params,
@@ -90,7 +117,7 @@ pub fn generate_winning_post_with_vanilla<Tree: 'static + MerkleTreeTrait>(
 
     info!("generate_winning_post_with_vanilla:finish");
 
-    util::proofs_to_bytes(&proofs)
+    codec::encode_groth16_proofs(&proofs)
 }
 
 /// Generates a Winning proof-of-spacetime.
@@ -100,25 +127,97 @@ pub fn generate_winning_post<Tree: 'static + MerkleTreeTrait>(
     replicas: &[(SectorId, PrivateReplicaInfo<Tree>)],
     prover_id: ProverId,
 ) -> Result<SnarkProof> {
+    generate_winning_post_with_domain::<Tree>(
+        post_config,
+        randomness,
+        replicas,
+        prover_id,
+        &ChallengeDomain::Mainnet,
+    )
+}
+
+/// Like [`generate_winning_post`], with the challenges derived in `challenge_domain`.
+pub fn generate_winning_post_with_domain<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomness: &ChallengeSeed,
+    replicas: &[(SectorId, PrivateReplicaInfo<Tree>)],
+    prover_id: ProverId,
+    challenge_domain: &ChallengeDomain,
+) -> Result<SnarkProof> {
+    let _span = info_span!("generate_winning_post").entered();
+    let timer = StageTimer::start("winning_post");
+    let _priority = enter_stage(ProvingPriority::High);
     info!("generate_winning_post:start");
     ensure!(
         post_config.typ == PoStType::Winning,
         "invalid post config type"
     );
 
-    ensure!(
-        replicas.len() == post_config.sector_count,
-        "invalid amount of replicas"
-    );
+    let vanilla_params = winning_post_setup_params_with_domain(post_config, challenge_domain)?;
+    let setup_params = compound_proof::SetupParams {
+        vanilla_params,
+        partitions: None,
+        priority: post_config.priority,
+    };
+    let pub_params: compound_proof::PublicParams<'_, FallbackPoSt<'_, Tree>> =
+        FallbackPoStCompound::setup(&setup_params)?;
+    let groth_params = get_post_params::<Tree>(post_config)?;
 
-    let randomness_safe: <Tree::Hasher as Hasher>::Domain =
-        as_safe_commitment(randomness, "randomness")?;
-    let prover_id_safe: <Tree::Hasher as Hasher>::Domain =
-        as_safe_commitment(&prover_id, "prover_id")?;
+    let input = WinningPoStInputs {
+        randomness: *randomness,
+        prover_id,
+        replicas,
+    };
 
-    let vanilla_params = winning_post_setup_params(post_config)?;
-    let param_sector_count = vanilla_params.sector_count;
+    let proofs = metrics::time_proof("winning_post", || -> Result<_> {
+        let (pub_inputs, vanilla_proofs) =
+            winning_post_vanilla_proofs(post_config, &pub_params, &input)?;
+        FallbackPoStCompound::<Tree>::prove_with_vanilla(
+            &pub_params,
+            &pub_inputs,
+            vanilla_proofs,
+            &groth_params,
+        )
+    })?;
+
+    info!("generate_winning_post:finish");
+
+    timer.finish(codec::encode_groth16_proofs(&proofs))
+}
+
+/// The inputs of the Winning proof-of-spacetime of a single miner.
+pub struct WinningPoStInputs<'a, Tree: MerkleTreeTrait> {
+    pub randomness: ChallengeSeed,
+    pub prover_id: ProverId,
+    pub replicas: &'a [(SectorId, PrivateReplicaInfo<Tree>)],
+}
+
+/// Generates the Winning proofs-of-spacetime of several independent miners at once.
+///
+/// The parameters are loaded once and the SNARKs of all miners are generated in a single batch,
+/// but every proof is only over its own inputs. The results are returned in the order of the
+/// inputs, an error for one miner does not affect the proofs of the others. The outer `Result`
+/// fails only if the shared setup fails.
+pub fn generate_winning_post_batch<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    inputs: &[WinningPoStInputs<'_, Tree>],
+) -> Result<Vec<Result<SnarkProof>>> {
+    generate_winning_post_batch_with_domain::<Tree>(post_config, inputs, &ChallengeDomain::Mainnet)
+}
 
+/// Like [`generate_winning_post_batch`], with the challenges derived in `challenge_domain`.
+pub fn generate_winning_post_batch_with_domain<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    inputs: &[WinningPoStInputs<'_, Tree>],
+    challenge_domain: &ChallengeDomain,
+) -> Result<Vec<Result<SnarkProof>>> {
+    info!("generate_winning_post_batch:start: {} miners", inputs.len());
+    ensure!(
+        post_config.typ == PoStType::Winning,
+        "invalid post config type"
+    );
+
+    let vanilla_params = winning_post_setup_params_with_domain(post_config, challenge_domain)?;
     let setup_params = compound_proof::SetupParams {
         vanilla_params,
         partitions: None,
@@ -128,7 +227,89 @@ pub fn generate_winning_post<Tree: 'static + MerkleTreeTrait>(
         FallbackPoStCompound::setup(&setup_params)?;
     let groth_params = get_post_params::<Tree>(post_config)?;
 
-    let trees = replicas
+    let vanilla_proofs: Vec<_> = inputs
+        .par_iter()
+        .map(|input| winning_post_vanilla_proofs(post_config, &pub_params, input))
+        .collect();
+
+    let mut results = Vec::with_capacity(inputs.len());
+    let mut circuits = Vec::new();
+    for vanilla_proof in vanilla_proofs {
+        let circuit = vanilla_proof.and_then(|(pub_inputs, vanilla_proofs)| {
+            vanilla_proofs
+                .iter()
+                .enumerate()
+                .map(|(k, vanilla_proof)| {
+                    FallbackPoStCompound::<Tree>::circuit(
+                        &pub_inputs,
+                        Default::default(),
+                        vanilla_proof,
+                        &pub_params.vanilla_params,
+                        Some(k),
+                    )
+                })
+                .collect::<Result<Vec<_>>>()
+        });
+        match circuit {
+            Ok(circuit) => {
+                results.push(Ok(circuit.len()));
+                circuits.extend(circuit);
+            }
+            Err(err) => results.push(Err(err)),
+        }
+    }
+
+    let groth_proofs = if circuits.is_empty() {
+        Vec::new()
+    } else {
+        let mut rng = proof_rng();
+        let groth_proofs = if post_config.priority {
+            groth16::create_random_proof_batch_in_priority(circuits, &*groth_params, &mut rng)
+        } else {
+            groth16::create_random_proof_batch(circuits, &*groth_params, &mut rng)
+        };
+        groth_proofs.context("failed to generate winning post snarks")?
+    };
+
+    let mut groth_proofs = groth_proofs.into_iter();
+    let proofs = results
+        .into_iter()
+        .map(|result| {
+            result.and_then(|num_proofs| {
+                let proofs: Vec<_> = groth_proofs.by_ref().take(num_proofs).collect();
+                codec::encode_groth16_proofs(&proofs)
+            })
+        })
+        .collect();
+
+    info!("generate_winning_post_batch:finish");
+
+    Ok(proofs)
+}
+
+/// Generates the vanilla proofs of a Winning proof-of-spacetime, together with the public inputs
+/// they were generated for.
+#[allow(clippy::type_complexity)]
+fn winning_post_vanilla_proofs<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    pub_params: &compound_proof::PublicParams<'_, FallbackPoSt<'_, Tree>>,
+    input: &WinningPoStInputs<'_, Tree>,
+) -> Result<(
+    fallback::PublicInputs<<Tree::Hasher as Hasher>::Domain>,
+    Vec<fallback::Proof<Tree::Proof>>,
+)> {
+    ensure!(
+        input.replicas.len() == post_config.sector_count,
+        "invalid amount of replicas"
+    );
+
+    let randomness_safe: <Tree::Hasher as Hasher>::Domain =
+        as_safe_commitment(&input.randomness, "randomness")?;
+    let prover_id_safe: <Tree::Hasher as Hasher>::Domain =
+        as_safe_commitment(input.prover_id.as_bytes(), "prover_id")?;
+
+    let trees = input
+        .replicas
         .iter()
         .map(|(sector_id, replica)| {
             replica
@@ -139,11 +320,12 @@ pub fn generate_winning_post<Tree: 'static + MerkleTreeTrait>(
         })
         .collect::<Result<Vec<_>>>()?;
 
+    let param_sector_count = pub_params.vanilla_params.sector_count;
     let mut pub_sectors = Vec::with_capacity(param_sector_count);
     let mut priv_sectors = Vec::with_capacity(param_sector_count);
 
     for _ in 0..param_sector_count {
-        for ((sector_id, replica), tree) in replicas.iter().zip(trees.iter()) {
+        for ((sector_id, replica), tree) in input.replicas.iter().zip(trees.iter()) {
             let comm_r = replica.safe_comm_r().with_context(|| {
                 format!("generate_winning_post: safe_comm_r failed: {:?}", sector_id)
             })?;
@@ -168,17 +350,25 @@ pub fn generate_winning_post<Tree: 'static + MerkleTreeTrait>(
         sectors: pub_sectors,
         k: None,
     };
-
     let priv_inputs = fallback::PrivateInputs::<Tree> {
         sectors: &priv_sectors,
     };
 
-    let proofs =
-        FallbackPoStCompound::<Tree>::prove(&pub_params, &pub_inputs, &priv_inputs, &groth_params)?;
-
-    info!("generate_winning_post:finish");
+    let partitions = FallbackPoStCompound::<Tree>::partition_count(pub_params);
+    let vanilla_proofs = FallbackPoSt::<Tree>::prove_all_partitions(
+        &pub_params.vanilla_params,
+        &pub_inputs,
+        &priv_inputs,
+        partitions,
+    )?;
+    let sanity_check = FallbackPoSt::<Tree>::verify_all_partitions(
+        &pub_params.vanilla_params,
+        &pub_inputs,
+        &vanilla_proofs,
+    )?;
+    ensure!(sanity_check, "sanity check failed");
 
-    util::proofs_to_bytes(&proofs)
+    Ok((pub_inputs, vanilla_proofs))
 }
 
 /// Given some randomness and the length of available sectors, generates the challenged sector.
@@ -189,7 +379,25 @@ pub fn generate_winning_post_sector_challenge<Tree: MerkleTreeTrait>(
     post_config: &PoStConfig,
     randomness: &ChallengeSeed,
     sector_set_size: u64,
-    prover_id: Commitment,
+    prover_id: ProverId,
+) -> Result<Vec<u64>> {
+    generate_winning_post_sector_challenge_with_domain::<Tree>(
+        post_config,
+        randomness,
+        sector_set_size,
+        prover_id,
+        &ChallengeDomain::Mainnet,
+    )
+}
+
+/// Like [`generate_winning_post_sector_challenge`], with the challenges derived in
+/// `challenge_domain`.
+pub fn generate_winning_post_sector_challenge_with_domain<Tree: MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomness: &ChallengeSeed,
+    sector_set_size: u64,
+    prover_id: ProverId,
+    challenge_domain: &ChallengeDomain,
 ) -> Result<Vec<u64>> {
     info!("generate_winning_post_sector_challenge:start");
     ensure!(sector_set_size != 0, "empty sector set is invalid");
@@ -199,15 +407,16 @@ pub fn generate_winning_post_sector_challenge<Tree: MerkleTreeTrait>(
     );
 
     let prover_id_safe: <Tree::Hasher as Hasher>::Domain =
-        as_safe_commitment(&prover_id, "prover_id")?;
+        as_safe_commitment(prover_id.as_bytes(), "prover_id")?;
 
     let randomness_safe: <Tree::Hasher as Hasher>::Domain =
         as_safe_commitment(randomness, "randomness")?;
-    let result = generate_sector_challenges(
+    let result = generate_sector_challenges_with_domain(
         randomness_safe,
         post_config.sector_count,
         sector_set_size,
         prover_id_safe,
+        challenge_domain,
     );
 
     info!("generate_winning_post_sector_challenge:finish");
@@ -226,6 +435,25 @@ pub fn verify_winning_post<Tree: 'static + MerkleTreeTrait>(
     replicas: &[(SectorId, PublicReplicaInfo)],
     prover_id: ProverId,
     proof: &[u8],
+) -> Result<bool> {
+    verify_winning_post_with_domain::<Tree>(
+        post_config,
+        randomness,
+        replicas,
+        prover_id,
+        proof,
+        &ChallengeDomain::Mainnet,
+    )
+}
+
+/// Like [`verify_winning_post`], with the challenges derived in `challenge_domain`.
+pub fn verify_winning_post_with_domain<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomness: &ChallengeSeed,
+    replicas: &[(SectorId, PublicReplicaInfo)],
+    prover_id: ProverId,
+    proof: &[u8],
+    challenge_domain: &ChallengeDomain,
 ) -> Result<bool> {
     info!("verify_winning_post:start");
 
@@ -238,12 +466,7 @@ pub fn verify_winning_post<Tree: 'static + MerkleTreeTrait>(
         "invalid amount of replicas provided"
     );
 
-    let randomness_safe: <Tree::Hasher as Hasher>::Domain =
-        as_safe_commitment(randomness, "randomness")?;
-    let prover_id_safe: <Tree::Hasher as Hasher>::Domain =
-        as_safe_commitment(&prover_id, "prover_id")?;
-
-    let vanilla_params = winning_post_setup_params(post_config)?;
+    let vanilla_params = winning_post_setup_params_with_domain(post_config, challenge_domain)?;
     let param_sector_count = vanilla_params.sector_count;
 
     let setup_params = compound_proof::SetupParams {
@@ -253,31 +476,13 @@ pub fn verify_winning_post<Tree: 'static + MerkleTreeTrait>(
     };
     let pub_params: compound_proof::PublicParams<'_, FallbackPoSt<'_, Tree>> =
         FallbackPoStCompound::setup(&setup_params)?;
-
-    let mut pub_sectors = Vec::with_capacity(param_sector_count);
-    for _ in 0..param_sector_count {
-        for (sector_id, replica) in replicas.iter() {
-            let comm_r = replica.safe_comm_r().with_context(|| {
-                format!("verify_winning_post: safe_comm_r failed: {:?}", sector_id)
-            })?;
-            pub_sectors.push(PublicSector {
-                id: *sector_id,
-                comm_r,
-            });
-        }
-    }
-
-    let pub_inputs = fallback::PublicInputs {
-        randomness: randomness_safe,
-        prover_id: prover_id_safe,
-        sectors: pub_sectors,
-        k: None,
-    };
+    let pub_inputs =
+        winning_post_public_inputs::<Tree>(param_sector_count, randomness, replicas, prover_id)?;
 
     let is_valid = {
         let verifying_key = get_post_verifying_key::<Tree>(post_config)?;
 
-        let single_proof = MultiProof::new_from_reader(None, proof, &verifying_key)?;
+        let single_proof = MultiProof::new(codec::decode_groth16_proofs(proof)?, &verifying_key);
         if single_proof.len() != 1 {
             return Ok(false);
         }
@@ -300,3 +505,86 @@ pub fn verify_winning_post<Tree: 'static + MerkleTreeTrait>(
 
     Ok(true)
 }
+
+fn winning_post_public_inputs<Tree: 'static + MerkleTreeTrait>(
+    param_sector_count: usize,
+    randomness: &ChallengeSeed,
+    replicas: &[(SectorId, PublicReplicaInfo)],
+    prover_id: ProverId,
+) -> Result<fallback::PublicInputs<<Tree::Hasher as Hasher>::Domain>> {
+    let randomness_safe: <Tree::Hasher as Hasher>::Domain =
+        as_safe_commitment(randomness, "randomness")?;
+    let prover_id_safe: <Tree::Hasher as Hasher>::Domain =
+        as_safe_commitment(prover_id.as_bytes(), "prover_id")?;
+
+    let mut pub_sectors = Vec::with_capacity(param_sector_count);
+    for _ in 0..param_sector_count {
+        for (sector_id, replica) in replicas.iter() {
+            let comm_r = replica.safe_comm_r().with_context(|| {
+                format!("verify_winning_post: safe_comm_r failed: {:?}", sector_id)
+            })?;
+            pub_sectors.push(PublicSector {
+                id: *sector_id,
+                comm_r,
+            });
+        }
+    }
+
+    Ok(fallback::PublicInputs {
+        randomness: randomness_safe,
+        prover_id: prover_id_safe,
+        sectors: pub_sectors,
+        k: None,
+    })
+}
+
+/// Returns the public inputs of the Groth16 proof of a Winning proof-of-spacetime, in the order
+/// that [`verify_winning_post`] passes them to the verifier. Winning PoSt proofs have a single
+/// partition, so the result contains a single vector.
+pub fn public_inputs_for_winning_post<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomness: &ChallengeSeed,
+    replicas: &[(SectorId, PublicReplicaInfo)],
+    prover_id: ProverId,
+) -> Result<Vec<Vec<Fr>>> {
+    public_inputs_for_winning_post_with_domain::<Tree>(
+        post_config,
+        randomness,
+        replicas,
+        prover_id,
+        &ChallengeDomain::Mainnet,
+    )
+}
+
+/// Like [`public_inputs_for_winning_post`], with the challenges derived in `challenge_domain`.
+pub fn public_inputs_for_winning_post_with_domain<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomness: &ChallengeSeed,
+    replicas: &[(SectorId, PublicReplicaInfo)],
+    prover_id: ProverId,
+    challenge_domain: &ChallengeDomain,
+) -> Result<Vec<Vec<Fr>>> {
+    ensure!(
+        post_config.typ == PoStType::Winning,
+        "invalid post config type"
+    );
+    ensure!(
+        post_config.sector_count == replicas.len(),
+        "invalid amount of replicas provided"
+    );
+
+    let pub_params = FallbackPoSt::<Tree>::setup(&winning_post_setup_params_with_domain(
+        post_config,
+        challenge_domain,
+    )?)?;
+    let pub_inputs = winning_post_public_inputs::<Tree>(
+        pub_params.sector_count,
+        randomness,
+        replicas,
+        prover_id,
+    )?;
+    let inputs =
+        FallbackPoStCompound::<Tree>::generate_public_inputs(&pub_inputs, &pub_params, Some(0))?;
+
+    Ok(vec![inputs])
+}
@@ -4,9 +4,11 @@ use std::sync::{Arc, Mutex};
 use anyhow::Result;
 use bellperson::groth16::{self, prepare_verifying_key};
 use blstrs::Bls12;
+use filecoin_hashers::Hasher;
 use lazy_static::lazy_static;
 use log::{info, trace};
 use once_cell::sync::OnceCell;
+#[cfg(not(feature = "dev-mode"))]
 use rand::rngs::OsRng;
 use storage_proofs_core::{
     compound_proof::CompoundProof, merkle::MerkleTreeTrait, parameter_cache::Bls12GrothParams,
@@ -18,9 +20,15 @@ use storage_proofs_update::{
     EmptySectorUpdate, PublicParams,
 };
 
+#[cfg(feature = "dev-mode")]
+use crate::constants::{SECTOR_SIZE_2_KIB, SECTOR_SIZE_4_KIB};
 use crate::{
     constants::{DefaultPieceHasher, PUBLISHED_SECTOR_SIZES},
-    parameters::{public_params, window_post_public_params, winning_post_public_params},
+    metrics,
+    parameters::{
+        public_params, public_params_with_piece_hasher, window_post_public_params,
+        winning_post_public_params,
+    },
     types::{PoRepConfig, PoStConfig, PoStType},
 };
 
@@ -28,6 +36,43 @@ pub type Bls12PreparedVerifyingKey = groth16::PreparedVerifyingKey<Bls12>;
 type Bls12ProverSRSKey = groth16::aggregate::ProverSRS<Bls12>;
 type Bls12VerifierSRSKey = groth16::aggregate::VerifierSRS<Bls12>;
 
+#[cfg(feature = "dev-mode")]
+type ParamsRng = rand_chacha::ChaChaRng;
+#[cfg(not(feature = "dev-mode"))]
+type ParamsRng = OsRng;
+
+/// The sector sizes whose parameters are generated on demand with the `dev-mode` feature.
+#[cfg(feature = "dev-mode")]
+pub const DEV_MODE_SECTOR_SIZES: [u64; 2] = [SECTOR_SIZE_2_KIB, SECTOR_SIZE_4_KIB];
+
+#[cfg(feature = "dev-mode")]
+const DEV_MODE_SEED: [u8; 32] = *b"filecoin-proofs insecure devmode";
+
+/// Returns the rng to generate the parameters of `sector_size` with, if they are missing from the
+/// parameter cache. Without it, missing parameters are an error.
+///
+/// With the `dev-mode` feature, the parameters of 2 KiB and 4 KiB sectors are generated from a
+/// fixed seed, so that all processes arrive at the same parameters. These parameters are INSECURE and
+/// don't match the digests of `parameters.json`, i.e. `FIL_PROOFS_VERIFY_PRODUCTION_PARAMS` must
+/// not be set. Published parameters that are already in the cache are used as usual.
+#[cfg(feature = "dev-mode")]
+fn params_rng(sector_size: u64) -> Option<ParamsRng> {
+    use rand::SeedableRng;
+
+    DEV_MODE_SECTOR_SIZES.contains(&sector_size).then(|| {
+        info!(
+            "dev-mode: using insecure parameters for sector size {}",
+            sector_size
+        );
+        ParamsRng::from_seed(DEV_MODE_SEED)
+    })
+}
+
+#[cfg(not(feature = "dev-mode"))]
+fn params_rng(_sector_size: u64) -> Option<ParamsRng> {
+    None
+}
+
 type Cache<G> = HashMap<String, Arc<G>>;
 type GrothMemCache = Cache<Bls12GrothParams>;
 type VerifyingKeyMemCache = Cache<Bls12PreparedVerifyingKey>;
@@ -118,11 +163,13 @@ where
 
         if let Some(entry) = cache.get(&identifier) {
             info!("found params in memory cache for {}", &identifier);
+            metrics::parameter_cache_lookup(true);
             return Ok(entry.clone());
         }
     }
 
     info!("no params in memory cache for {}", &identifier);
+    metrics::parameter_cache_lookup(false);
 
     let new_entry = Arc::new(generator()?);
     let res = new_entry.clone();
@@ -196,26 +243,40 @@ where
     )
 }
 
+/// The in-memory cache identifier of the PoRep parameters for a `tree_d` built with `G`.
+fn stacked_identifier<G: Hasher>(porep_config: &PoRepConfig) -> String {
+    let size = usize::from(porep_config.padded_bytes_amount());
+    if G::name() == DefaultPieceHasher::name() {
+        format!("STACKED[{}]", size)
+    } else {
+        format!("STACKED-{}[{}]", G::name(), size)
+    }
+}
+
 pub fn get_stacked_params<Tree: 'static + MerkleTreeTrait>(
     porep_config: &PoRepConfig,
 ) -> Result<Arc<Bls12GrothParams>> {
-    let public_params = public_params::<Tree>(porep_config)?;
+    get_stacked_params_with_piece_hasher::<Tree, DefaultPieceHasher>(porep_config)
+}
+
+/// Returns the PoRep parameters for a `tree_d` built with hasher `G`.
+pub fn get_stacked_params_with_piece_hasher<
+    Tree: 'static + MerkleTreeTrait,
+    G: 'static + Hasher,
+>(
+    porep_config: &PoRepConfig,
+) -> Result<Arc<Bls12GrothParams>> {
+    let public_params = public_params_with_piece_hasher::<Tree, G>(porep_config)?;
 
     let parameters_generator = || {
-        <StackedCompound<Tree, DefaultPieceHasher> as CompoundProof<
-            StackedDrg<'_, Tree, DefaultPieceHasher>,
-            _,
-        >>::groth_params::<OsRng>(None, &public_params)
+        <StackedCompound<Tree, G> as CompoundProof<StackedDrg<'_, Tree, G>, _>>::groth_params(
+            params_rng(u64::from(porep_config.sector_size)).as_mut(),
+            &public_params,
+        )
         .map_err(Into::into)
     };
 
-    lookup_groth_params(
-        format!(
-            "STACKED[{}]",
-            usize::from(porep_config.padded_bytes_amount())
-        ),
-        parameters_generator,
-    )
+    lookup_groth_params(stacked_identifier::<G>(porep_config), parameters_generator)
 }
 
 pub(crate) fn get_post_params<Tree: 'static + MerkleTreeTrait>(
@@ -229,7 +290,10 @@ pub(crate) fn get_post_params<Tree: 'static + MerkleTreeTrait>(
                 <FallbackPoStCompound<Tree> as CompoundProof<
                     FallbackPoSt<'_, Tree>,
                     FallbackPoStCircuit<Tree>,
-                >>::groth_params::<OsRng>(None, &post_public_params)
+                >>::groth_params(
+                    params_rng(u64::from(post_config.sector_size)).as_mut(),
+                    &post_public_params,
+                )
                 .map_err(Into::into)
             };
 
@@ -248,7 +312,10 @@ pub(crate) fn get_post_params<Tree: 'static + MerkleTreeTrait>(
                 <FallbackPoStCompound<Tree> as CompoundProof<
                     FallbackPoSt<'_, Tree>,
                     FallbackPoStCircuit<Tree>,
-                >>::groth_params::<OsRng>(None, &post_public_params)
+                >>::groth_params(
+                    params_rng(u64::from(post_config.sector_size)).as_mut(),
+                    &post_public_params,
+                )
                 .map_err(Into::into)
             };
 
@@ -273,7 +340,10 @@ pub fn get_empty_sector_update_params<Tree: 'static + MerkleTreeTrait<Hasher = T
         <EmptySectorUpdateCompound<Tree> as CompoundProof<
             EmptySectorUpdate<Tree>,
             EmptySectorUpdateCircuit<Tree>,
-        >>::groth_params::<OsRng>(None, &public_params)
+        >>::groth_params(
+            params_rng(u64::from(porep_config.sector_size)).as_mut(),
+            &public_params,
+        )
         .map_err(Into::into)
     };
 
@@ -289,23 +359,28 @@ pub fn get_empty_sector_update_params<Tree: 'static + MerkleTreeTrait<Hasher = T
 pub fn get_stacked_verifying_key<Tree: 'static + MerkleTreeTrait>(
     porep_config: &PoRepConfig,
 ) -> Result<Arc<Bls12PreparedVerifyingKey>> {
-    let public_params = public_params(porep_config)?;
+    get_stacked_verifying_key_with_piece_hasher::<Tree, DefaultPieceHasher>(porep_config)
+}
+
+/// Returns the PoRep verifying key for a `tree_d` built with hasher `G`.
+pub fn get_stacked_verifying_key_with_piece_hasher<
+    Tree: 'static + MerkleTreeTrait,
+    G: 'static + Hasher,
+>(
+    porep_config: &PoRepConfig,
+) -> Result<Arc<Bls12PreparedVerifyingKey>> {
+    let public_params = public_params_with_piece_hasher::<Tree, G>(porep_config)?;
 
     let vk_generator = || {
-        let vk = <StackedCompound<Tree, DefaultPieceHasher> as CompoundProof<
-            StackedDrg<'_, Tree, DefaultPieceHasher>,
-            _,
-        >>::verifying_key::<OsRng>(None, &public_params)?;
+        let vk =
+            <StackedCompound<Tree, G> as CompoundProof<StackedDrg<'_, Tree, G>, _>>::verifying_key(
+                params_rng(u64::from(porep_config.sector_size)).as_mut(),
+                &public_params,
+            )?;
         Ok(prepare_verifying_key(&vk))
     };
 
-    lookup_verifying_key(
-        format!(
-            "STACKED[{}]",
-            usize::from(porep_config.padded_bytes_amount())
-        ),
-        vk_generator,
-    )
+    lookup_verifying_key(stacked_identifier::<G>(porep_config), vk_generator)
 }
 
 pub fn get_post_verifying_key<Tree: 'static + MerkleTreeTrait>(
@@ -319,7 +394,10 @@ pub fn get_post_verifying_key<Tree: 'static + MerkleTreeTrait>(
                 let vk = <FallbackPoStCompound<Tree> as CompoundProof<
                     FallbackPoSt<'_, Tree>,
                     FallbackPoStCircuit<Tree>,
-                >>::verifying_key::<OsRng>(None, &post_public_params)?;
+                >>::verifying_key(
+                    params_rng(u64::from(post_config.sector_size)).as_mut(),
+                    &post_public_params,
+                )?;
                 Ok(prepare_verifying_key(&vk))
             };
 
@@ -338,7 +416,10 @@ pub fn get_post_verifying_key<Tree: 'static + MerkleTreeTrait>(
                 let vk = <FallbackPoStCompound<Tree> as CompoundProof<
                     FallbackPoSt<'_, Tree>,
                     FallbackPoStCircuit<Tree>,
-                >>::verifying_key::<OsRng>(None, &post_public_params)?;
+                >>::verifying_key(
+                    params_rng(u64::from(post_config.sector_size)).as_mut(),
+                    &post_public_params,
+                )?;
                 Ok(prepare_verifying_key(&vk))
             };
 
@@ -423,7 +504,10 @@ pub fn get_empty_sector_update_verifying_key<
         let vk = <EmptySectorUpdateCompound<Tree> as CompoundProof<
             EmptySectorUpdate<Tree>,
             EmptySectorUpdateCircuit<Tree>,
-        >>::verifying_key::<OsRng>(None, &public_params)?;
+        >>::verifying_key(
+            params_rng(u64::from(porep_config.sector_size)).as_mut(),
+            &public_params,
+        )?;
         Ok(prepare_verifying_key(&vk))
     };
 
@@ -0,0 +1,245 @@
+//! Byte formats of the proofs returned by this crate.
+//!
+//! The proofs that are submitted on chain are not framed in any way:
+//!
+//! - Seal, PoSt and empty sector update proofs are the Groth16 proofs of all partitions
+//!   concatenated in partition order. Each Groth16 proof is [`GROTH16_PROOF_LEN`] bytes, the
+//!   compressed `a` (G1), `b` (G2) and `c` (G1) points.
+//! - Aggregate seal proofs are a single SnarkPack proof as written by
+//!   `bellperson::groth16::aggregate::AggregateProof::write`.
+//!
+//! Those formats must not change, they are part of the protocol. Proofs that are persisted or
+//! transported outside of the chain should be wrapped with [`encode`], which prefixes them with
+//! the [`CODEC_VERSION`] and the [`ProofKind`], so that they can be told apart and decoded with
+//! [`decode`] once the formats evolve.
+
+use std::convert::TryFrom;
+use std::io::Cursor;
+
+use anyhow::{bail, ensure, Context, Result};
+use bellperson::groth16::{aggregate::AggregateProof, Proof};
+use blstrs::Bls12;
+use serde::{Deserialize, Serialize};
+
+/// The length of a single Groth16 proof.
+pub const GROTH16_PROOF_LEN: usize = 192;
+
+/// The version of the framing written by [`encode`].
+pub const CODEC_VERSION: u8 = 1;
+
+/// The kind of a framed proof.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum ProofKind {
+    Seal = 1,
+    AggregateSeal = 2,
+    WinningPoSt = 3,
+    WindowPoSt = 4,
+    EmptySectorUpdate = 5,
+}
+
+impl ProofKind {
+    pub const ALL: [ProofKind; 5] = [
+        ProofKind::Seal,
+        ProofKind::AggregateSeal,
+        ProofKind::WinningPoSt,
+        ProofKind::WindowPoSt,
+        ProofKind::EmptySectorUpdate,
+    ];
+
+    /// Returns `true` if the payload is a list of Groth16 proofs.
+    pub fn is_groth16(&self) -> bool {
+        !matches!(self, ProofKind::AggregateSeal)
+    }
+}
+
+impl TryFrom<u8> for ProofKind {
+    type Error = anyhow::Error;
+
+    fn try_from(tag: u8) -> Result<Self> {
+        ProofKind::ALL
+            .iter()
+            .copied()
+            .find(|kind| *kind as u8 == tag)
+            .with_context(|| format!("unknown proof kind {}", tag))
+    }
+}
+
+/// Returns the on-chain bytes of the Groth16 proofs of all partitions.
+pub fn encode_groth16_proofs(proofs: &[Proof<Bls12>]) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(GROTH16_PROOF_LEN * proofs.len());
+    for proof in proofs {
+        proof.write(&mut out).context("known allocation target")?;
+    }
+    Ok(out)
+}
+
+/// Parses the on-chain bytes of the Groth16 proofs of all partitions.
+pub fn decode_groth16_proofs(bytes: &[u8]) -> Result<Vec<Proof<Bls12>>> {
+    ensure!(
+        !bytes.is_empty() && bytes.len() % GROTH16_PROOF_LEN == 0,
+        "invalid Groth16 proofs length {}, expected a non-zero multiple of {}",
+        bytes.len(),
+        GROTH16_PROOF_LEN
+    );
+    bytes
+        .chunks(GROTH16_PROOF_LEN)
+        .enumerate()
+        .map(|(i, chunk)| {
+            Proof::read(chunk).with_context(|| format!("invalid Groth16 proof {}", i))
+        })
+        .collect()
+}
+
+/// Parses the on-chain bytes of a proof that must consist of exactly `partitions` Groth16 proofs.
+pub fn decode_partition_proofs(bytes: &[u8], partitions: usize) -> Result<Vec<Proof<Bls12>>> {
+    let proofs = decode_groth16_proofs(bytes)?;
+    ensure!(
+        proofs.len() == partitions,
+        "expected {} partition proofs, found {}",
+        partitions,
+        proofs.len()
+    );
+    Ok(proofs)
+}
+
+/// Returns the on-chain bytes of an aggregate seal proof.
+pub fn encode_aggregate_proof(proof: &AggregateProof<Bls12>) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    proof.write(&mut out).context("known allocation target")?;
+    Ok(out)
+}
+
+/// Parses the on-chain bytes of an aggregate seal proof.
+pub fn decode_aggregate_proof(bytes: &[u8]) -> Result<AggregateProof<Bls12>> {
+    AggregateProof::read(Cursor::new(bytes)).context("invalid aggregate proof")
+}
+
+/// Frames the on-chain bytes of a proof as `[CODEC_VERSION, kind, proof..]`.
+pub fn encode(kind: ProofKind, proof: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(2 + proof.len());
+    out.push(CODEC_VERSION);
+    out.push(kind as u8);
+    out.extend_from_slice(proof);
+    out
+}
+
+/// Returns the kind and the on-chain bytes of a proof framed by [`encode`]. The length of Groth16
+/// proofs is checked, the proofs themselves are not parsed.
+pub fn decode(bytes: &[u8]) -> Result<(ProofKind, &[u8])> {
+    let (version, kind, proof) = match bytes {
+        [version, kind, proof @ ..] => (*version, *kind, proof),
+        _ => bail!("proof is too short to be framed"),
+    };
+    ensure!(
+        version == CODEC_VERSION,
+        "unsupported proof codec version {}, expected {}",
+        version,
+        CODEC_VERSION
+    );
+    let kind = ProofKind::try_from(kind)?;
+    if kind.is_groth16() {
+        ensure!(
+            !proof.is_empty() && proof.len() % GROTH16_PROOF_LEN == 0,
+            "invalid {:?} proof length {}",
+            kind,
+            proof.len()
+        );
+    } else {
+        ensure!(!proof.is_empty(), "empty {:?} proof", kind);
+    }
+    Ok((kind, proof))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use blstrs::{G1Projective, G2Projective, Scalar as Fr};
+    use group::{Curve, Group};
+
+    use crate::constants::SINGLE_PARTITION_PROOF_LEN;
+
+    fn proofs(count: u64) -> Vec<Proof<Bls12>> {
+        (1..=count)
+            .map(|i| Proof {
+                a: (G1Projective::generator() * Fr::from(i)).to_affine(),
+                b: (G2Projective::generator() * Fr::from(i + 1)).to_affine(),
+                c: (G1Projective::generator() * Fr::from(i + 2)).to_affine(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_groth16_proof_len() {
+        assert_eq!(GROTH16_PROOF_LEN, Proof::<Bls12>::size());
+        assert_eq!(GROTH16_PROOF_LEN, SINGLE_PARTITION_PROOF_LEN);
+    }
+
+    #[test]
+    fn test_groth16_proofs_roundtrip() {
+        for count in [1, 2, 10] {
+            let proofs = proofs(count);
+            let bytes = encode_groth16_proofs(&proofs).expect("failed to encode");
+            assert_eq!(bytes.len(), count as usize * GROTH16_PROOF_LEN);
+            assert_eq!(
+                decode_groth16_proofs(&bytes).expect("failed to decode"),
+                proofs
+            );
+        }
+    }
+
+    #[test]
+    fn test_groth16_proofs_invalid() {
+        let bytes = encode_groth16_proofs(&proofs(2)).expect("failed to encode");
+        assert!(decode_groth16_proofs(&[]).is_err());
+        assert!(decode_groth16_proofs(&bytes[..bytes.len() - 1]).is_err());
+        assert!(decode_groth16_proofs(&[0xff; GROTH16_PROOF_LEN]).is_err());
+
+        assert_eq!(
+            decode_partition_proofs(&bytes, 2).expect("failed to decode"),
+            proofs(2)
+        );
+        assert!(decode_partition_proofs(&bytes, 1).is_err());
+        assert!(decode_partition_proofs(&bytes, 3).is_err());
+    }
+
+    #[test]
+    fn test_framed_roundtrip() {
+        let groth16 = encode_groth16_proofs(&proofs(3)).expect("failed to encode");
+        for kind in ProofKind::ALL {
+            let framed = encode(kind, &groth16);
+            assert_eq!(framed[..2], [CODEC_VERSION, kind as u8]);
+            let (decoded_kind, proof) = decode(&framed).expect("failed to decode");
+            assert_eq!(decoded_kind, kind);
+            assert_eq!(proof, &groth16[..]);
+        }
+    }
+
+    #[test]
+    fn test_framed_invalid() {
+        let groth16 = encode_groth16_proofs(&proofs(1)).expect("failed to encode");
+
+        assert!(decode(&[]).is_err());
+        assert!(decode(&[CODEC_VERSION]).is_err());
+
+        let mut framed = encode(ProofKind::WindowPoSt, &groth16);
+        framed[0] = CODEC_VERSION + 1;
+        assert!(decode(&framed).is_err(), "unknown version");
+
+        let mut framed = encode(ProofKind::WindowPoSt, &groth16);
+        framed[1] = 0;
+        assert!(decode(&framed).is_err(), "unknown kind");
+
+        let framed = encode(ProofKind::Seal, &groth16[1..]);
+        assert!(decode(&framed).is_err(), "truncated Groth16 proof");
+
+        assert!(decode(&encode(ProofKind::AggregateSeal, &[])).is_err());
+    }
+
+    #[test]
+    fn test_aggregate_proof_invalid() {
+        assert!(decode_aggregate_proof(&[]).is_err());
+        assert!(decode_aggregate_proof(&[0u8; 64]).is_err());
+    }
+}
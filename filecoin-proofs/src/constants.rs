@@ -4,6 +4,7 @@ use std::{collections::HashMap, sync::RwLockWriteGuard};
 pub use storage_proofs_core::drgraph::BASE_DEGREE as DRG_DEGREE;
 pub use storage_proofs_porep::stacked::EXP_DEGREE;
 
+use anyhow::{Context, Result};
 use filecoin_hashers::{poseidon::PoseidonHasher, sha256::Sha256Hasher, Hasher};
 use lazy_static::lazy_static;
 use storage_proofs_core::{
@@ -47,11 +48,25 @@ pub const PUBLISHED_SECTOR_SIZES: [u64; 10] = [
     SECTOR_SIZE_64_GIB,
 ];
 
-pub struct PorepMinimumChallenges(RwLock<HashMap<u64, usize>>);
-impl PorepMinimumChallenges {
-    fn new() -> Self {
-        Self(RwLock::new(
-            [
+/// The protocol constants that depend on the sector size, which may differ between networks.
+///
+/// Configurations read them from the process-global maps below, unless they are passed
+/// explicitly, e.g. to `PoRepConfig::new_groth16_with_constants`. Passing them makes it safe to
+/// handle sectors of several networks in a single process, the global maps are only kept as the
+/// default for compatibility.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProtocolConstants {
+    pub porep_minimum_challenges: HashMap<u64, usize>,
+    pub porep_partitions: HashMap<u64, u8>,
+    pub layers: HashMap<u64, usize>,
+    pub window_post_sector_count: HashMap<u64, usize>,
+}
+
+impl Default for ProtocolConstants {
+    /// Returns the constants of the Filecoin networks.
+    fn default() -> Self {
+        ProtocolConstants {
+            porep_minimum_challenges: [
                 (SECTOR_SIZE_2_KIB, 2),
                 (SECTOR_SIZE_4_KIB, 2),
                 (SECTOR_SIZE_16_KIB, 2),
@@ -66,6 +81,115 @@ impl PorepMinimumChallenges {
             .iter()
             .copied()
             .collect(),
+            porep_partitions: [
+                (SECTOR_SIZE_2_KIB, 1),
+                (SECTOR_SIZE_4_KIB, 1),
+                (SECTOR_SIZE_16_KIB, 1),
+                (SECTOR_SIZE_32_KIB, 1),
+                (SECTOR_SIZE_8_MIB, 1),
+                (SECTOR_SIZE_16_MIB, 1),
+                (SECTOR_SIZE_512_MIB, 1),
+                (SECTOR_SIZE_1_GIB, 1),
+                (SECTOR_SIZE_32_GIB, 10),
+                (SECTOR_SIZE_64_GIB, 10),
+            ]
+            .iter()
+            .copied()
+            .collect(),
+            layers: [
+                (SECTOR_SIZE_2_KIB, 2),
+                (SECTOR_SIZE_4_KIB, 2),
+                (SECTOR_SIZE_16_KIB, 2),
+                (SECTOR_SIZE_32_KIB, 2),
+                (SECTOR_SIZE_8_MIB, 2),
+                (SECTOR_SIZE_16_MIB, 2),
+                (SECTOR_SIZE_512_MIB, 2),
+                (SECTOR_SIZE_1_GIB, 2),
+                (SECTOR_SIZE_32_GIB, 11),
+                (SECTOR_SIZE_64_GIB, 11),
+            ]
+            .iter()
+            .copied()
+            .collect(),
+            // These numbers must match those used for Window PoSt scheduling in the miner actor.
+            // Please coordinate changes with actor code.
+            // https://github.com/filecoin-project/specs-actors/blob/master/actors/abi/sector.go
+            window_post_sector_count: [
+                (SECTOR_SIZE_2_KIB, 2),
+                (SECTOR_SIZE_4_KIB, 2),
+                (SECTOR_SIZE_16_KIB, 2),
+                (SECTOR_SIZE_32_KIB, 2),
+                (SECTOR_SIZE_8_MIB, 2),
+                (SECTOR_SIZE_16_MIB, 2),
+                (SECTOR_SIZE_512_MIB, 2),
+                (SECTOR_SIZE_1_GIB, 2),
+                (SECTOR_SIZE_32_GIB, 2349), // this gives 125,279,217 constraints, fitting in a single partition
+                (SECTOR_SIZE_64_GIB, 2300), // this gives 129,887,900 constraints, fitting in a single partition
+            ]
+            .iter()
+            .copied()
+            .collect(),
+        }
+    }
+}
+
+impl ProtocolConstants {
+    /// Returns a snapshot of the process-global maps.
+    pub fn from_globals() -> Self {
+        ProtocolConstants {
+            porep_minimum_challenges: POREP_MINIMUM_CHALLENGES
+                .0
+                .read()
+                .expect("POREP_MINIMUM_CHALLENGES poisoned")
+                .clone(),
+            porep_partitions: POREP_PARTITIONS
+                .read()
+                .expect("POREP_PARTITIONS poisoned")
+                .clone(),
+            layers: LAYERS.read().expect("LAYERS poisoned").clone(),
+            window_post_sector_count: WINDOW_POST_SECTOR_COUNT
+                .read()
+                .expect("WINDOW_POST_SECTOR_COUNT poisoned")
+                .clone(),
+        }
+    }
+
+    pub fn porep_minimum_challenges(&self, sector_size: u64) -> Result<usize> {
+        lookup(
+            &self.porep_minimum_challenges,
+            sector_size,
+            "porep minimum challenges",
+        )
+    }
+
+    pub fn porep_partitions(&self, sector_size: u64) -> Result<u8> {
+        lookup(&self.porep_partitions, sector_size, "porep partitions")
+    }
+
+    pub fn layers(&self, sector_size: u64) -> Result<usize> {
+        lookup(&self.layers, sector_size, "layers")
+    }
+
+    pub fn window_post_sector_count(&self, sector_size: u64) -> Result<usize> {
+        lookup(
+            &self.window_post_sector_count,
+            sector_size,
+            "window post sector count",
+        )
+    }
+}
+
+fn lookup<T: Copy>(map: &HashMap<u64, T>, sector_size: u64, name: &str) -> Result<T> {
+    map.get(&sector_size)
+        .copied()
+        .with_context(|| format!("no {} for sector size {}", name, sector_size))
+}
+
+pub struct PorepMinimumChallenges(RwLock<HashMap<u64, usize>>);
+impl PorepMinimumChallenges {
+    fn new() -> Self {
+        Self(RwLock::new(
+            ProtocolConstants::default().porep_minimum_challenges,
         ))
     }
 
@@ -86,62 +210,15 @@ impl PorepMinimumChallenges {
     }
 }
 
+// The process-global defaults of the protocol constants, see `ProtocolConstants`.
 lazy_static! {
     pub static ref POREP_MINIMUM_CHALLENGES: PorepMinimumChallenges = PorepMinimumChallenges::new();
-    pub static ref POREP_PARTITIONS: RwLock<HashMap<u64, u8>> = RwLock::new(
-        [
-            (SECTOR_SIZE_2_KIB, 1),
-            (SECTOR_SIZE_4_KIB, 1),
-            (SECTOR_SIZE_16_KIB, 1),
-            (SECTOR_SIZE_32_KIB, 1),
-            (SECTOR_SIZE_8_MIB, 1),
-            (SECTOR_SIZE_16_MIB, 1),
-            (SECTOR_SIZE_512_MIB, 1),
-            (SECTOR_SIZE_1_GIB, 1),
-            (SECTOR_SIZE_32_GIB, 10),
-            (SECTOR_SIZE_64_GIB, 10),
-        ]
-        .iter()
-        .copied()
-        .collect()
-    );
-    pub static ref LAYERS: RwLock<HashMap<u64, usize>> = RwLock::new(
-        [
-            (SECTOR_SIZE_2_KIB, 2),
-            (SECTOR_SIZE_4_KIB, 2),
-            (SECTOR_SIZE_16_KIB, 2),
-            (SECTOR_SIZE_32_KIB, 2),
-            (SECTOR_SIZE_8_MIB, 2),
-            (SECTOR_SIZE_16_MIB, 2),
-            (SECTOR_SIZE_512_MIB, 2),
-            (SECTOR_SIZE_1_GIB, 2),
-            (SECTOR_SIZE_32_GIB, 11),
-            (SECTOR_SIZE_64_GIB, 11),
-        ]
-        .iter()
-        .copied()
-        .collect()
-    );
-    // These numbers must match those used for Window PoSt scheduling in the miner actor.
-    // Please coordinate changes with actor code.
-    // https://github.com/filecoin-project/specs-actors/blob/master/actors/abi/sector.go
-    pub static ref WINDOW_POST_SECTOR_COUNT: RwLock<HashMap<u64, usize>> = RwLock::new(
-        [
-            (SECTOR_SIZE_2_KIB, 2),
-            (SECTOR_SIZE_4_KIB, 2),
-            (SECTOR_SIZE_16_KIB, 2),
-            (SECTOR_SIZE_32_KIB, 2),
-            (SECTOR_SIZE_8_MIB, 2),
-            (SECTOR_SIZE_16_MIB, 2),
-            (SECTOR_SIZE_512_MIB, 2),
-            (SECTOR_SIZE_1_GIB, 2),
-            (SECTOR_SIZE_32_GIB, 2349), // this gives 125,279,217 constraints, fitting in a single partition
-            (SECTOR_SIZE_64_GIB, 2300), // this gives 129,887,900 constraints, fitting in a single partition
-        ]
-        .iter()
-        .copied()
-        .collect()
-    );
+    pub static ref POREP_PARTITIONS: RwLock<HashMap<u64, u8>> =
+        RwLock::new(ProtocolConstants::default().porep_partitions);
+    pub static ref LAYERS: RwLock<HashMap<u64, usize>> =
+        RwLock::new(ProtocolConstants::default().layers);
+    pub static ref WINDOW_POST_SECTOR_COUNT: RwLock<HashMap<u64, usize>> =
+        RwLock::new(ProtocolConstants::default().window_post_sector_count);
 }
 
 /// The size of a single snark proof.
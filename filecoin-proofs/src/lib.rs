@@ -10,10 +10,15 @@ compile_error!(
 
 pub mod caches;
 pub mod chunk_iter;
+pub mod codec;
 pub mod constants;
+pub mod metrics;
 pub mod param;
 pub mod parameters;
 pub mod pieces;
+pub mod priority;
+#[cfg(feature = "tracing-subscriber")]
+pub mod telemetry;
 pub mod types;
 
 mod api;
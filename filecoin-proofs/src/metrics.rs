@@ -0,0 +1,243 @@
+//! Prometheus metrics for long-running proving services.
+//!
+//! With the `metrics-prometheus` feature the sealing, PoSt and SnapDeals entry points record into
+//! a [`registry`], which a service exposes by serving the output of [`gather`]. Without the
+//! feature nothing is recorded.
+
+#[cfg(feature = "metrics-prometheus")]
+use std::time::Duration;
+use std::time::Instant;
+
+#[cfg(feature = "metrics-prometheus")]
+use anyhow::Context;
+use anyhow::Result;
+#[cfg(feature = "metrics-prometheus")]
+use lazy_static::lazy_static;
+#[cfg(feature = "metrics-prometheus")]
+use prometheus::{
+    core::Collector, exponential_buckets, Encoder, GaugeVec, HistogramOpts, HistogramVec,
+    IntCounter, IntCounterVec, Opts, Registry, TextEncoder,
+};
+
+#[cfg(feature = "metrics-prometheus")]
+use crate::types::PoStType;
+
+#[cfg(feature = "metrics-prometheus")]
+lazy_static! {
+    static ref REGISTRY: Registry = Registry::new_custom(Some("filecoin_proofs".to_string()), None)
+        .expect("failed to create metrics registry");
+    static ref SEALS_STARTED: IntCounter = register(
+        IntCounter::new(
+            "seals_started_total",
+            "Number of seals that started pre-commit phase 1"
+        )
+        .expect("invalid metric"),
+    );
+    static ref SEALS_COMPLETED: IntCounter = register(
+        IntCounter::new(
+            "seals_completed_total",
+            "Number of seals that completed commit phase 2"
+        )
+        .expect("invalid metric"),
+    );
+    static ref STAGE_DURATION: HistogramVec = register(
+        HistogramVec::new(
+            HistogramOpts::new("stage_duration_seconds", "Duration of the proving stages")
+                .buckets(exponential_buckets(1.0, 2.0, 16).expect("invalid buckets")),
+            &["stage", "status"],
+        )
+        .expect("invalid metric"),
+    );
+    static ref PROOF_DURATION: HistogramVec = register(
+        HistogramVec::new(
+            HistogramOpts::new(
+                "groth16_proof_duration_seconds",
+                "Duration of the Groth16 proving, which runs on the GPU if one is available"
+            )
+            .buckets(exponential_buckets(0.1, 2.0, 14).expect("invalid buckets")),
+            &["proof"],
+        )
+        .expect("invalid metric"),
+    );
+    static ref POST_DEADLINE_MARGIN: GaugeVec = register(
+        GaugeVec::new(
+            Opts::new(
+                "post_deadline_margin_seconds",
+                "Time left until the deadline when the last PoSt was generated, negative if it \
+                 was missed"
+            ),
+            &["post_type"],
+        )
+        .expect("invalid metric"),
+    );
+    static ref POST_DEADLINES_MISSED: IntCounterVec = register(
+        IntCounterVec::new(
+            Opts::new(
+                "post_deadlines_missed_total",
+                "Number of PoSts generated after their deadline"
+            ),
+            &["post_type"],
+        )
+        .expect("invalid metric"),
+    );
+    static ref PARAMETER_CACHE_LOOKUPS: IntCounterVec = register(
+        IntCounterVec::new(
+            Opts::new(
+                "parameter_cache_lookups_total",
+                "Number of lookups in the in-memory parameter cache"
+            ),
+            &["result"],
+        )
+        .expect("invalid metric"),
+    );
+}
+
+#[cfg(feature = "metrics-prometheus")]
+fn register<M: Collector + Clone + 'static>(metric: M) -> M {
+    REGISTRY
+        .register(Box::new(metric.clone()))
+        .expect("failed to register metric");
+    metric
+}
+
+/// Returns the registry all metrics of this crate are registered with.
+#[cfg(feature = "metrics-prometheus")]
+pub fn registry() -> &'static Registry {
+    &REGISTRY
+}
+
+/// Returns all metrics in the Prometheus text exposition format.
+#[cfg(feature = "metrics-prometheus")]
+pub fn gather() -> Result<String> {
+    // Touch the metrics, so that they are listed even before anything was recorded.
+    lazy_static::initialize(&SEALS_STARTED);
+    lazy_static::initialize(&SEALS_COMPLETED);
+    lazy_static::initialize(&STAGE_DURATION);
+    lazy_static::initialize(&PROOF_DURATION);
+    lazy_static::initialize(&POST_DEADLINE_MARGIN);
+    lazy_static::initialize(&POST_DEADLINES_MISSED);
+    lazy_static::initialize(&PARAMETER_CACHE_LOOKUPS);
+
+    let mut buf = Vec::new();
+    TextEncoder::new()
+        .encode(&REGISTRY.gather(), &mut buf)
+        .context("failed to encode metrics")?;
+    String::from_utf8(buf).context("metrics are not valid utf-8")
+}
+
+/// Records how much time was left until the deadline of a PoSt once it was generated. The
+/// deadline is only known to the caller, e.g. the chain's proving period.
+#[cfg(feature = "metrics-prometheus")]
+pub fn observe_post_deadline(post_type: PoStType, elapsed: Duration, deadline: Duration) {
+    let label = match post_type {
+        PoStType::Winning => "winning",
+        PoStType::Window => "window",
+    };
+    let margin = deadline.as_secs_f64() - elapsed.as_secs_f64();
+    POST_DEADLINE_MARGIN.with_label_values(&[label]).set(margin);
+    if margin < 0.0 {
+        POST_DEADLINES_MISSED.with_label_values(&[label]).inc();
+    }
+}
+
+pub(crate) fn seal_started() {
+    #[cfg(feature = "metrics-prometheus")]
+    SEALS_STARTED.inc();
+}
+
+pub(crate) fn seal_completed() {
+    #[cfg(feature = "metrics-prometheus")]
+    SEALS_COMPLETED.inc();
+}
+
+pub(crate) fn parameter_cache_lookup(hit: bool) {
+    #[cfg(feature = "metrics-prometheus")]
+    PARAMETER_CACHE_LOOKUPS
+        .with_label_values(&[if hit { "hit" } else { "miss" }])
+        .inc();
+    #[cfg(not(feature = "metrics-prometheus"))]
+    let _ = hit;
+}
+
+/// Runs the Groth16 proving `f` and records its duration.
+pub(crate) fn time_proof<T, F: FnOnce() -> T>(proof: &'static str, f: F) -> T {
+    let start = Instant::now();
+    let res = f();
+    #[cfg(feature = "metrics-prometheus")]
+    PROOF_DURATION
+        .with_label_values(&[proof])
+        .observe(start.elapsed().as_secs_f64());
+    #[cfg(not(feature = "metrics-prometheus"))]
+    let _ = (proof, start);
+    res
+}
+
+/// Records the duration of a proving stage when it is dropped. The stage counts as failed unless
+/// it was ended with the successful result of [`StageTimer::finish`], so that early returns on
+/// errors are recorded with the `error` status.
+pub(crate) struct StageTimer {
+    stage: &'static str,
+    start: Instant,
+    succeeded: bool,
+}
+
+impl StageTimer {
+    pub(crate) fn start(stage: &'static str) -> Self {
+        StageTimer {
+            stage,
+            start: Instant::now(),
+            succeeded: false,
+        }
+    }
+
+    /// Ends the stage with the result it produced.
+    pub(crate) fn finish<T>(mut self, res: Result<T>) -> Result<T> {
+        self.succeeded = res.is_ok();
+        res
+    }
+}
+
+impl Drop for StageTimer {
+    fn drop(&mut self) {
+        #[cfg(feature = "metrics-prometheus")]
+        STAGE_DURATION
+            .with_label_values(&[self.stage, if self.succeeded { "ok" } else { "error" }])
+            .observe(self.start.elapsed().as_secs_f64());
+        #[cfg(not(feature = "metrics-prometheus"))]
+        let _ = (self.stage, self.start, self.succeeded);
+    }
+}
+
+#[cfg(all(test, feature = "metrics-prometheus"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gather() {
+        seal_started();
+        parameter_cache_lookup(true);
+        StageTimer::start("pre_commit_phase1")
+            .finish(Ok(()))
+            .expect("stage failed");
+        drop(StageTimer::start("pre_commit_phase2"));
+        time_proof("seal", || ());
+        observe_post_deadline(
+            PoStType::Window,
+            Duration::from_secs(40),
+            Duration::from_secs(30),
+        );
+
+        let metrics = gather().expect("failed to gather metrics");
+        assert!(metrics.contains("filecoin_proofs_seals_started_total"));
+        assert!(metrics.contains("filecoin_proofs_seals_completed_total"));
+        assert!(metrics.contains("filecoin_proofs_parameter_cache_lookups_total{result=\"hit\"}"));
+        assert!(metrics.contains("stage=\"pre_commit_phase1\",status=\"ok\""));
+        assert!(metrics.contains("stage=\"pre_commit_phase2\",status=\"error\""));
+        assert!(metrics.contains("proof=\"seal\""));
+        assert!(metrics
+            .contains("filecoin_proofs_post_deadline_margin_seconds{post_type=\"window\"} -10"));
+        assert!(
+            metrics.contains("filecoin_proofs_post_deadlines_missed_total{post_type=\"window\"} 1")
+        );
+    }
+}
@@ -1,12 +1,16 @@
 use std::collections::BTreeMap;
 use std::ffi::OsStr;
 use std::fs::File;
-use std::io;
+use std::io::{self, Read};
 use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
 use blake2b_simd::State as Blake2b;
+use log::info;
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+use rayon::ThreadPoolBuilder;
 use storage_proofs_core::parameter_cache::{
+    get_parameter_data_from_id, get_srs_parameter_data_from_id, mark_parameters_verified,
     parameter_cache_dir, CacheEntryMetadata, PARAMETER_METADATA_EXT,
 };
 
@@ -72,3 +76,165 @@ pub fn filename_to_parameter_id<'a, P: AsRef<Path> + 'a>(filename: P) -> Option<
         .and_then(OsStr::to_str)
         .map(ToString::to_string)
 }
+
+/// The outcome of verifying a single parameter file, see [`verify_parameter_cache`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParameterFileStatus {
+    /// The digest of the file matches the manifest.
+    Valid,
+    /// The file doesn't exist.
+    Missing,
+    /// The file isn't listed in the parameters or SRS manifest.
+    Unknown,
+    /// The digest of the file doesn't match the manifest.
+    InvalidDigest { expected: String, actual: String },
+    /// The file could not be read.
+    Error(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct ParameterFileReport {
+    pub path: PathBuf,
+    pub status: ParameterFileStatus,
+}
+
+/// Verifies the digests of the parameter files at `paths` against the manifests, hashing up to
+/// `parallelism` files at once (`0` uses one thread per core).
+///
+/// Files that are valid are recorded as verified, so they are not hashed again when they are
+/// loaded with `FIL_PROOFS_VERIFY_PRODUCTION_PARAMS` set. The returned reports are in the order of
+/// `paths`.
+pub fn verify_parameter_cache<P: AsRef<Path> + Sync>(
+    paths: &[P],
+    parallelism: usize,
+) -> Result<Vec<ParameterFileReport>> {
+    verify_parameter_cache_with_progress(paths, parallelism, |_, _| {})
+}
+
+/// Like [`verify_parameter_cache`], `progress` is called with the path of a file and the number of
+/// bytes of it that were just hashed.
+pub fn verify_parameter_cache_with_progress<P, F>(
+    paths: &[P],
+    parallelism: usize,
+    progress: F,
+) -> Result<Vec<ParameterFileReport>>
+where
+    P: AsRef<Path> + Sync,
+    F: Fn(&Path, u64) + Sync,
+{
+    info!(
+        "verifying {} parameter files (parallelism: {})",
+        paths.len(),
+        parallelism
+    );
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(parallelism)
+        .build()
+        .context("failed to build thread pool")?;
+
+    let reports = pool.install(|| {
+        paths
+            .par_iter()
+            .map(|path| {
+                let path = path.as_ref();
+                ParameterFileReport {
+                    path: path.to_path_buf(),
+                    status: verify_parameter_file(path, &progress),
+                }
+            })
+            .collect()
+    });
+
+    Ok(reports)
+}
+
+fn verify_parameter_file<F: Fn(&Path, u64)>(path: &Path, progress: &F) -> ParameterFileStatus {
+    let filename = match path.file_name().and_then(OsStr::to_str) {
+        Some(filename) => filename,
+        None => return ParameterFileStatus::Unknown,
+    };
+    let expected = match get_parameter_data_from_id(filename)
+        .or_else(|| get_srs_parameter_data_from_id(filename))
+    {
+        Some(data) => &data.digest,
+        None => return ParameterFileStatus::Unknown,
+    };
+    if !path.exists() {
+        return ParameterFileStatus::Missing;
+    }
+
+    match digest_file(path, progress) {
+        Ok(actual) if &actual == expected => {
+            mark_parameters_verified(filename.to_string());
+            ParameterFileStatus::Valid
+        }
+        Ok(actual) => ParameterFileStatus::InvalidDigest {
+            expected: expected.clone(),
+            actual,
+        },
+        Err(err) => ParameterFileStatus::Error(format!("{:#}", err)),
+    }
+}
+
+// Produces the truncated BLAKE2b checksum of a file, reporting the progress per chunk
+fn digest_file<F: Fn(&Path, u64)>(path: &Path, progress: &F) -> Result<String> {
+    let mut file = File::open(path).with_context(|| format!("could not open path={:?}", path))?;
+    let mut hasher = Blake2b::new();
+    let mut buf = vec![0u8; 1 << 20];
+
+    loop {
+        let read = match file.read(&mut buf) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        };
+        hasher.update(&buf[..read]);
+        progress(path, read as u64);
+    }
+
+    Ok(hasher.finalize().to_hex()[..32].into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use storage_proofs_core::parameter_cache::PARAMETERS;
+
+    #[test]
+    fn test_verify_parameter_cache() {
+        let dir = tempfile::tempdir().expect("failed to create temp dir");
+        let (known, data) = PARAMETERS.iter().next().expect("empty parameters manifest");
+
+        let invalid = dir.path().join(known);
+        fs::write(&invalid, b"not the parameters").expect("failed to write file");
+        let unknown = dir.path().join("v28-unknown.params");
+        fs::write(&unknown, b"unknown").expect("failed to write file");
+        let missing = dir
+            .path()
+            .join(PARAMETERS.keys().nth(1).expect("parameters missing"));
+
+        let hashed = AtomicU64::new(0);
+        let reports =
+            verify_parameter_cache_with_progress(&[&invalid, &unknown, &missing], 2, |_, bytes| {
+                hashed.fetch_add(bytes, Ordering::SeqCst);
+            })
+            .expect("failed to verify parameter cache");
+
+        assert_eq!(reports.len(), 3);
+        assert_eq!(reports[0].path, invalid);
+        match &reports[0].status {
+            ParameterFileStatus::InvalidDigest { expected, .. } => {
+                assert_eq!(expected, &data.digest)
+            }
+            status => panic!("unexpected status {:?}", status),
+        }
+        assert_eq!(reports[1].status, ParameterFileStatus::Unknown);
+        assert_eq!(reports[2].status, ParameterFileStatus::Missing);
+        assert_eq!(hashed.load(Ordering::SeqCst), 18);
+    }
+}
@@ -1,12 +1,14 @@
 use anyhow::{ensure, Result};
-use storage_proofs_core::{api_version::ApiFeature, proof::ProofScheme};
+use filecoin_hashers::Hasher;
+use storage_proofs_core::{
+    api_version::ApiFeature, challenge_domain::ChallengeDomain, proof::ProofScheme,
+};
 use storage_proofs_porep::stacked::{self, LayerChallenges, StackedDrg};
 use storage_proofs_post::fallback::{self, FallbackPoSt};
 
 use crate::{
-    constants::{DefaultPieceHasher, DRG_DEGREE, EXP_DEGREE, LAYERS},
+    constants::{DefaultPieceHasher, DRG_DEGREE, EXP_DEGREE},
     types::{MerkleTreeTrait, PoRepConfig, PoStConfig},
-    POREP_MINIMUM_CHALLENGES,
 };
 
 type WinningPostSetupParams = fallback::SetupParams;
@@ -18,7 +20,14 @@ pub type WindowPostPublicParams = fallback::PublicParams;
 pub fn public_params<Tree: 'static + MerkleTreeTrait>(
     porep_config: &PoRepConfig,
 ) -> Result<stacked::PublicParams<Tree>> {
-    StackedDrg::<Tree, DefaultPieceHasher>::setup(&setup_params(porep_config)?)
+    public_params_with_piece_hasher::<Tree, DefaultPieceHasher>(porep_config)
+}
+
+/// Returns the PoRep public parameters for a `tree_d` built with hasher `G`.
+pub fn public_params_with_piece_hasher<Tree: 'static + MerkleTreeTrait, G: 'static + Hasher>(
+    porep_config: &PoRepConfig,
+) -> Result<stacked::PublicParams<Tree>> {
+    StackedDrg::<Tree, G>::setup(&setup_params(porep_config)?)
 }
 
 pub fn winning_post_public_params<Tree: 'static + MerkleTreeTrait>(
@@ -28,6 +37,14 @@ pub fn winning_post_public_params<Tree: 'static + MerkleTreeTrait>(
 }
 
 pub fn winning_post_setup_params(post_config: &PoStConfig) -> Result<WinningPostSetupParams> {
+    winning_post_setup_params_with_domain(post_config, &ChallengeDomain::Mainnet)
+}
+
+/// Like [`winning_post_setup_params`], with the challenges derived in `challenge_domain`.
+pub fn winning_post_setup_params_with_domain(
+    post_config: &PoStConfig,
+    challenge_domain: &ChallengeDomain,
+) -> Result<WinningPostSetupParams> {
     ensure!(
         post_config.challenge_count % post_config.sector_count == 0,
         "sector count must divide challenge count"
@@ -49,6 +66,7 @@ pub fn winning_post_setup_params(post_config: &PoStConfig) -> Result<WinningPost
         challenge_count: param_challenge_count,
         sector_count: param_sector_count,
         api_version: post_config.api_version,
+        challenge_domain: *challenge_domain,
     })
 }
 
@@ -59,11 +77,20 @@ pub fn window_post_public_params<Tree: 'static + MerkleTreeTrait>(
 }
 
 pub fn window_post_setup_params(post_config: &PoStConfig) -> WindowPostSetupParams {
+    window_post_setup_params_with_domain(post_config, &ChallengeDomain::Mainnet)
+}
+
+/// Like [`window_post_setup_params`], with the challenges derived in `challenge_domain`.
+pub fn window_post_setup_params_with_domain(
+    post_config: &PoStConfig,
+    challenge_domain: &ChallengeDomain,
+) -> WindowPostSetupParams {
     fallback::SetupParams {
         sector_size: post_config.padded_sector_size().into(),
         challenge_count: post_config.challenge_count,
         sector_count: post_config.sector_count,
         api_version: post_config.api_version,
+        challenge_domain: *challenge_domain,
     }
 }
 
@@ -72,14 +99,11 @@ pub fn setup_params(porep_config: &PoRepConfig) -> Result<stacked::SetupParams>
     let sector_bytes = porep_config.padded_bytes_amount();
     let layer_challenges = select_challenges(
         usize::from(porep_config.partitions),
-        POREP_MINIMUM_CHALLENGES.from_sector_size(u64::from(sector_bytes)),
-        *LAYERS
-            .read()
-            .expect("LAYERS poisoned")
-            .get(&u64::from(sector_bytes))
-            .expect("unknown sector size"),
+        porep_config.minimum_challenges,
+        porep_config.layers,
         use_synthetic,
-    );
+    )
+    .with_domain(porep_config.challenge_domain);
     let sector_bytes = u64::from(sector_bytes);
 
     ensure!(
@@ -100,6 +124,7 @@ pub fn setup_params(porep_config: &PoRepConfig) -> Result<stacked::SetupParams>
         layer_challenges,
         api_version: porep_config.api_version,
         api_features: porep_config.api_features.clone(),
+        tree_d_arity: stacked::TREE_D_ARITY,
     })
 }
 
@@ -110,13 +135,12 @@ fn select_challenges(
     use_synthetic: bool,
 ) -> LayerChallenges {
     let mut count = 1;
-    let mut guess = LayerChallenges::new(layers, count);
+    let mut guess = LayerChallenges::from_parts(layers, count, use_synthetic);
     while partitions * guess.challenges_count_all() < minimum_total_challenges {
         count += 1;
-        guess = LayerChallenges::new(layers, count);
+        guess = LayerChallenges::from_parts(layers, count, use_synthetic);
     }
 
-    guess.use_synthetic = use_synthetic;
     guess
 }
 
@@ -156,4 +180,45 @@ mod tests {
         assert_eq!(params.challenge_count, 1);
         assert_eq!(params.sector_size, 2048);
     }
+
+    #[test]
+    fn test_piece_hasher_parameter_identifiers() {
+        use filecoin_hashers::poseidon::PoseidonHasher;
+        use storage_proofs_core::{api_version::ApiVersion, parameter_cache::ParameterSetMetadata};
+
+        use crate::SECTOR_SIZE_2_KIB;
+
+        let config = PoRepConfig::new_groth16(SECTOR_SIZE_2_KIB, [1u8; 32], ApiVersion::V1_1_0);
+
+        let sha_params = public_params::<DefaultOctLCTree>(&config).expect("public params");
+        let poseidon_params =
+            public_params_with_piece_hasher::<DefaultOctLCTree, PoseidonHasher>(&config)
+                .expect("public params");
+        assert_eq!(sha_params.identifier(), poseidon_params.identifier());
+
+        // The tree_d hasher is part of the circuit, so it must select distinct parameter files.
+        let sha_id = config
+            .get_cache_identifier::<DefaultOctLCTree>()
+            .expect("cache identifier");
+        let poseidon_id = config
+            .get_cache_identifier_with_piece_hasher::<DefaultOctLCTree, PoseidonHasher>()
+            .expect("cache identifier");
+        assert_ne!(sha_id, poseidon_id);
+        assert!(poseidon_id.contains(&PoseidonHasher::name()));
+
+        // The identifier follows the hasher selected by the configuration.
+        let poseidon_config = config.with_piece_hasher(crate::PieceHasher::Poseidon);
+        assert_eq!(
+            poseidon_config
+                .get_cache_identifier::<DefaultOctLCTree>()
+                .expect("cache identifier"),
+            poseidon_id
+        );
+        assert!(poseidon_config
+            .check_piece_hasher::<PoseidonHasher>()
+            .is_ok());
+        assert!(poseidon_config
+            .check_piece_hasher::<crate::DefaultPieceHasher>()
+            .is_err());
+    }
 }
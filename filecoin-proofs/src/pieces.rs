@@ -4,11 +4,12 @@ use std::io::{self, Cursor, Read};
 use std::iter::Iterator;
 use std::sync::Mutex;
 
-use anyhow::{ensure, Context, Result};
+use anyhow::{bail, ensure, Context, Result};
 use filecoin_hashers::{HashFunction, Hasher};
 use fr32::Fr32Reader;
 use lazy_static::lazy_static;
 use log::trace;
+use serde::{Deserialize, Serialize};
 use storage_proofs_core::util::NODE_SIZE;
 
 use crate::{
@@ -250,6 +251,154 @@ pub fn piece_hash(a: &[u8], b: &[u8]) -> <DefaultPieceHasher as Hasher>::Domain
     <DefaultPieceHasher as Hasher>::Function::hash(&buf)
 }
 
+/// A Merkle path from the commitment of a piece to the comm_d of the sector it is in, i.e. to the
+/// root of tree_d.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PieceInclusionProof {
+    /// The index of the piece among the subtrees of its size, i.e. its offset in the sector divided
+    /// by its padded size.
+    pub position: u64,
+    /// The sibling hashes from the level of the piece up to the root.
+    pub siblings: Vec<Commitment>,
+}
+
+/// Generates the inclusion proofs of the pieces of a sector, in the order of `piece_infos`.
+///
+/// The pieces must be given in the order they were added to the sector, the proofs are derived
+/// from the piece commitments alone, without rebuilding tree_d.
+pub fn generate_piece_inclusion_proofs(
+    sector_size: SectorSize,
+    piece_infos: &[PieceInfo],
+) -> Result<Vec<PieceInclusionProof>> {
+    let sector_level = node_level(u64::from(sector_size));
+    let zeros = zero_subtree_roots(sector_level);
+
+    // The start node, level and commitment of each piece, aligned the way `add_piece` aligns them.
+    let mut offset = 0;
+    let mut pieces = Vec::with_capacity(piece_infos.len());
+    for piece_info in piece_infos {
+        let piece_size = u64::from(PaddedBytesAmount::from(piece_info.size));
+        ensure!(
+            piece_size.is_power_of_two(),
+            "Piece size ({}) must be a power of 2.",
+            piece_size
+        );
+        let start = (offset + piece_size - 1) / piece_size * piece_size;
+        offset = start + piece_size;
+        ensure!(
+            offset <= u64::from(sector_size),
+            "Pieces are larger than sector."
+        );
+        pieces.push((
+            start / NODE_SIZE as u64,
+            node_level(piece_size),
+            piece_info.commitment,
+        ));
+    }
+
+    pieces
+        .iter()
+        .map(|(start, level, _)| {
+            let position = start >> level;
+            let siblings = (*level..sector_level)
+                .map(|sibling_level| {
+                    let index = (position >> (sibling_level - level)) ^ 1;
+                    subtree_root(&pieces, &zeros, sibling_level, index)
+                })
+                .collect::<Result<_>>()?;
+
+            Ok(PieceInclusionProof { position, siblings })
+        })
+        .collect()
+}
+
+/// Verifies that the piece described by `piece_info` is included in the sector with `comm_d`.
+pub fn verify_piece_inclusion(
+    comm_d: &Commitment,
+    piece_info: &PieceInfo,
+    sector_size: SectorSize,
+    proof: &PieceInclusionProof,
+) -> Result<bool> {
+    let piece_size = u64::from(PaddedBytesAmount::from(piece_info.size));
+    ensure!(
+        piece_size.is_power_of_two(),
+        "Piece size ({}) must be a power of 2.",
+        piece_size
+    );
+    ensure!(
+        piece_size <= u64::from(sector_size),
+        "Piece is larger than sector."
+    );
+
+    let depth = node_level(u64::from(sector_size)) - node_level(piece_size);
+    if proof.siblings.len() != depth as usize || proof.position >> depth != 0 {
+        return Ok(false);
+    }
+
+    let mut position = proof.position;
+    let mut hash = piece_info.commitment;
+    for sibling in &proof.siblings {
+        hash = if position & 1 == 0 {
+            hash_nodes(&hash, sibling)
+        } else {
+            hash_nodes(sibling, &hash)
+        };
+        position >>= 1;
+    }
+
+    Ok(&hash == comm_d)
+}
+
+// The level of a subtree of `size` padded bytes, with the leaves at level 0.
+fn node_level(size: u64) -> u32 {
+    (size / NODE_SIZE as u64).trailing_zeros()
+}
+
+fn hash_nodes(left: &Commitment, right: &Commitment) -> Commitment {
+    let mut commitment = [0u8; 32];
+    commitment.copy_from_slice(piece_hash(left, right).as_ref());
+    commitment
+}
+
+// The roots of the all zero subtrees up to `max_level`.
+fn zero_subtree_roots(max_level: u32) -> Vec<Commitment> {
+    let mut zeros = vec![[0u8; 32]];
+    for level in 0..max_level as usize {
+        zeros.push(hash_nodes(&zeros[level], &zeros[level]));
+    }
+    zeros
+}
+
+// Computes the root of the subtree at `level` and `index` from the pieces that overlap it.
+fn subtree_root(
+    pieces: &[(u64, u32, Commitment)],
+    zeros: &[Commitment],
+    level: u32,
+    index: u64,
+) -> Result<Commitment> {
+    let start = index << level;
+    let end = start + (1u64 << level);
+    let overlapping = pieces.iter().find(|(piece_start, piece_level, _)| {
+        *piece_start < end && piece_start + (1u64 << piece_level) > start
+    });
+
+    match overlapping {
+        None => Ok(zeros[level as usize]),
+        Some((piece_start, piece_level, commitment))
+            if *piece_start == start && *piece_level == level =>
+        {
+            Ok(*commitment)
+        }
+        Some((_, piece_level, _)) if *piece_level >= level => {
+            bail!("subtree {} at level {} lies within a piece", index, level)
+        }
+        Some(_) => Ok(hash_nodes(
+            &subtree_root(pieces, zeros, level - 1, index * 2)?,
+            &subtree_root(pieces, zeros, level - 1, index * 2 + 1)?,
+        )),
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct PieceAlignment {
     pub left_bytes: UnpaddedBytesAmount,
@@ -0,0 +1,277 @@
+//! Prioritization of proving operations that share the GPUs and CPUs of a machine.
+//!
+//! Deadline critical operations (window and winning PoSt) are registered with a
+//! [`PriorityManager`] at [`ProvingPriority::High`]. Background sealing work (pre-commit phase 2,
+//! commit phase 2 and sector updates) waits for them before it starts, so that queued PoSts run
+//! ahead of it. A PoSt that starts while background work is already proving preempts its GPU
+//! kernels through bellperson's priority lock, which is taken when `PoStConfig::priority` is set.
+//!
+//! Operations run through a [`ProverConfig`] with a priority are registered with a process wide
+//! manager by default, or the one injected with [`ProverConfig::with_priority_manager`]. The
+//! proving stages of all other callers only register themselves once a manager is installed with
+//! [`set_global_priority_manager`], so that their background work is never held back by PoSts
+//! unless that was asked for.
+//!
+//! [`ProverConfig`]: crate::types::ProverConfig
+//!
+//! [`ProverConfig::with_priority_manager`]: crate::types::ProverConfig::with_priority_manager
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, RwLock};
+
+use lazy_static::lazy_static;
+use log::trace;
+use rayon::ThreadPool;
+use storage_proofs_porep::stacked::PorepThreadPool;
+
+lazy_static! {
+    static ref GLOBAL_PRIORITY_MANAGER: RwLock<Arc<PriorityManager>> =
+        RwLock::new(Arc::new(PriorityManager::new()));
+}
+
+// Whether the proving stages register themselves with the global priority manager, see
+// `set_global_priority_manager`.
+static GLOBAL_PRIORITY_MANAGER_INSTALLED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    // The priority of the operation the current thread runs, set by `ProverConfig::install`.
+    static CURRENT: RefCell<Option<ProvingPriority>> = RefCell::new(None);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ProvingPriority {
+    /// Work that can be delayed, it waits for all high priority operations before it starts.
+    Background,
+    /// Work that neither waits for nor holds back other operations.
+    Normal,
+    /// Deadline critical work, background operations wait for it.
+    High,
+}
+
+/// Keeps track of the running proving operations by priority.
+#[derive(Default)]
+pub struct PriorityManager {
+    active: Mutex<HashMap<ProvingPriority, usize>>,
+    high_finished: Condvar,
+    thread_pools: HashMap<ProvingPriority, Arc<ThreadPool>>,
+    porep_thread_pools: HashMap<ProvingPriority, Arc<PorepThreadPool>>,
+}
+
+impl fmt::Debug for PriorityManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PriorityManager")
+            .field("active", &*self.lock())
+            .field(
+                "thread_pools",
+                &self
+                    .thread_pools
+                    .iter()
+                    .map(|(priority, pool)| (*priority, pool.current_num_threads()))
+                    .collect::<HashMap<_, _>>(),
+            )
+            .finish()
+    }
+}
+
+impl PriorityManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs the operations of `priority` on `thread_pool`, unless their [`ProverConfig`] has a
+    /// pool of its own. Sizing the pools weights the CPU time the priorities get, e.g. a small
+    /// pool for background work leaves cores to PoSt.
+    ///
+    /// The tree building and vanilla proving of storage-proofs-porep get a [`PorepThreadPool`] of
+    /// the same size.
+    ///
+    /// [`ProverConfig`]: crate::types::ProverConfig
+    pub fn with_thread_pool(
+        mut self,
+        priority: ProvingPriority,
+        thread_pool: Arc<ThreadPool>,
+    ) -> Self {
+        let porep_thread_pool = PorepThreadPool::new(thread_pool.current_num_threads());
+        self.porep_thread_pools
+            .insert(priority, Arc::new(porep_thread_pool));
+        self.thread_pools.insert(priority, thread_pool);
+        self
+    }
+
+    pub fn thread_pool(&self, priority: ProvingPriority) -> Option<&Arc<ThreadPool>> {
+        self.thread_pools.get(&priority)
+    }
+
+    pub(crate) fn porep_thread_pool(
+        &self,
+        priority: ProvingPriority,
+    ) -> Option<&Arc<PorepThreadPool>> {
+        self.porep_thread_pools.get(&priority)
+    }
+
+    /// The number of running operations of `priority`.
+    pub fn active(&self, priority: ProvingPriority) -> usize {
+        self.lock().get(&priority).copied().unwrap_or(0)
+    }
+
+    /// Registers an operation of `priority` until the returned guard is dropped. Background
+    /// operations block until no high priority operation is running.
+    pub fn enter(self: &Arc<Self>, priority: ProvingPriority) -> PriorityGuard {
+        let mut active = self.lock();
+        if priority == ProvingPriority::Background {
+            while active.get(&ProvingPriority::High).copied().unwrap_or(0) > 0 {
+                trace!("background operation waits for high priority operations");
+                active = self
+                    .high_finished
+                    .wait(active)
+                    .expect("priority manager poisoned");
+            }
+        }
+        *active.entry(priority).or_insert(0) += 1;
+
+        PriorityGuard {
+            manager: Arc::clone(self),
+            priority,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<ProvingPriority, usize>> {
+        self.active.lock().expect("priority manager poisoned")
+    }
+}
+
+/// Marks an operation as running, see [`PriorityManager::enter`].
+#[derive(Debug)]
+#[must_use]
+pub struct PriorityGuard {
+    manager: Arc<PriorityManager>,
+    priority: ProvingPriority,
+}
+
+impl PriorityGuard {
+    pub fn priority(&self) -> ProvingPriority {
+        self.priority
+    }
+}
+
+impl Drop for PriorityGuard {
+    fn drop(&mut self) {
+        let mut active = self.manager.lock();
+        if let Some(count) = active.get_mut(&self.priority) {
+            *count -= 1;
+        }
+        if self.priority == ProvingPriority::High {
+            self.manager.high_finished.notify_all();
+        }
+    }
+}
+
+/// Returns the process wide priority manager.
+pub fn global_priority_manager() -> Arc<PriorityManager> {
+    Arc::clone(
+        &GLOBAL_PRIORITY_MANAGER
+            .read()
+            .expect("GLOBAL_PRIORITY_MANAGER poisoned"),
+    )
+}
+
+/// Replaces the process wide priority manager. From then on, the proving stages that are not run
+/// through a [`ProverConfig`] with a priority register themselves with it at their default
+/// priority, i.e. pre-commit phase 2, commit phase 2 and sector updates wait for running PoSts.
+/// Operations that already entered the previous manager keep using it until they finish.
+///
+/// [`ProverConfig`]: crate::types::ProverConfig
+pub fn set_global_priority_manager(manager: Arc<PriorityManager>) {
+    *GLOBAL_PRIORITY_MANAGER
+        .write()
+        .expect("GLOBAL_PRIORITY_MANAGER poisoned") = manager;
+    GLOBAL_PRIORITY_MANAGER_INSTALLED.store(true, Ordering::SeqCst);
+}
+
+/// Runs `op` as an operation of `priority` on the current thread.
+pub(crate) fn run_with_priority<R>(priority: ProvingPriority, op: impl FnOnce() -> R) -> R {
+    struct Reset(Option<ProvingPriority>);
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            CURRENT.with(|current| *current.borrow_mut() = self.0);
+        }
+    }
+
+    let _reset = Reset(CURRENT.with(|current| current.borrow_mut().replace(priority)));
+    op()
+}
+
+/// Registers a proving stage with the global priority manager at `priority`, if one was installed
+/// with [`set_global_priority_manager`]. Stages run through a [`ProverConfig`] with a priority are
+/// not registered, the priority of the operation applies instead.
+///
+/// [`ProverConfig`]: crate::types::ProverConfig
+pub(crate) fn enter_stage(priority: ProvingPriority) -> Option<PriorityGuard> {
+    if !GLOBAL_PRIORITY_MANAGER_INSTALLED.load(Ordering::SeqCst)
+        || CURRENT.with(|current| current.borrow().is_some())
+    {
+        return None;
+    }
+    Some(global_priority_manager().enter(priority))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::mpsc::channel;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_background_waits_for_high_priority() {
+        let manager = Arc::new(PriorityManager::new());
+
+        let post = manager.enter(ProvingPriority::High);
+        // Normal operations are not held back.
+        let normal = manager.enter(ProvingPriority::Normal);
+        assert_eq!(manager.active(ProvingPriority::Normal), 1);
+        drop(normal);
+
+        let (started_tx, started_rx) = channel();
+        let background = {
+            let manager = Arc::clone(&manager);
+            thread::spawn(move || {
+                let _guard = manager.enter(ProvingPriority::Background);
+                started_tx.send(()).expect("failed to send");
+            })
+        };
+
+        assert!(started_rx.recv_timeout(Duration::from_millis(200)).is_err());
+        assert_eq!(manager.active(ProvingPriority::Background), 0);
+
+        drop(post);
+        started_rx
+            .recv_timeout(Duration::from_secs(10))
+            .expect("background operation did not start");
+        background.join().expect("background thread failed");
+        assert_eq!(manager.active(ProvingPriority::High), 0);
+        assert_eq!(manager.active(ProvingPriority::Background), 0);
+    }
+
+    #[test]
+    fn test_enter_stage_without_global_manager() {
+        // No test installs a global manager, stages must not wait for anything then.
+        let post = global_priority_manager().enter(ProvingPriority::High);
+        assert!(enter_stage(ProvingPriority::Background).is_none());
+        drop(post);
+    }
+
+    #[test]
+    fn test_enter_stage_within_priority() {
+        assert_eq!(CURRENT.with(|current| *current.borrow()), None);
+        run_with_priority(ProvingPriority::High, || {
+            // The stage is accounted for by the surrounding operation.
+            assert!(enter_stage(ProvingPriority::Background).is_none());
+        });
+        assert_eq!(CURRENT.with(|current| *current.borrow()), None);
+    }
+}
@@ -0,0 +1,22 @@
+//! A default subscriber for the `tracing` spans of the proving stages.
+//!
+//! The stages (sealing phases, labeling of each layer, tree building, circuit synthesis and the
+//! Groth16 proofs) are `tracing` spans, the sealing ones carry the `sector_id`. Any subscriber
+//! can be used to collect them, e.g. to produce flamegraphs, this module only provides one that
+//! prints them.
+
+use anyhow::{anyhow, Result};
+use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
+
+/// Installs a global subscriber that prints each span when it closes, together with its busy and
+/// idle time. What is printed is selected with the `RUST_LOG` environment variable, it defaults
+/// to `info`.
+pub fn init_tracing() -> Result<()> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(FmtSpan::CLOSE)
+        .try_init()
+        .map_err(|err| anyhow!("failed to install the tracing subscriber: {}", err))
+}
@@ -1,6 +1,6 @@
 use std::ops::{Add, Sub};
 
-use fr32::{to_padded_bytes, to_unpadded_bytes};
+use fr32::{to_padded_bytes, to_padded_offset, to_unpadded_bytes, to_unpadded_offset};
 use serde::{Deserialize, Serialize};
 
 pub struct PoStProofBytesAmount(pub usize);
@@ -10,6 +10,9 @@ pub struct PoRepProofBytesAmount(pub usize);
 #[derive(Debug, Default, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize, Eq, Ord)]
 pub struct UnpaddedByteIndex(pub u64);
 
+#[derive(Debug, Default, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize, Eq, Ord)]
+pub struct PaddedByteIndex(pub u64);
+
 #[derive(Debug, Default, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize, Eq, Ord)]
 pub struct UnpaddedBytesAmount(pub u64);
 
@@ -76,6 +79,38 @@ impl From<UnpaddedByteIndex> for usize {
     }
 }
 
+// Unlike the conversions between amounts, these map any offset, e.g. the start of a range
+// inside a piece, to the byte holding it in the other layout.
+impl From<UnpaddedByteIndex> for PaddedByteIndex {
+    fn from(n: UnpaddedByteIndex) -> Self {
+        PaddedByteIndex(to_padded_offset(n.0))
+    }
+}
+
+impl From<PaddedByteIndex> for UnpaddedByteIndex {
+    fn from(n: PaddedByteIndex) -> Self {
+        UnpaddedByteIndex(to_unpadded_offset(n.0))
+    }
+}
+
+impl From<PaddedBytesAmount> for PaddedByteIndex {
+    fn from(n: PaddedBytesAmount) -> Self {
+        PaddedByteIndex(n.0)
+    }
+}
+
+impl From<PaddedByteIndex> for u64 {
+    fn from(n: PaddedByteIndex) -> Self {
+        n.0
+    }
+}
+
+impl From<PaddedByteIndex> for usize {
+    fn from(n: PaddedByteIndex) -> Self {
+        n.0 as usize
+    }
+}
+
 impl Add for UnpaddedBytesAmount {
     type Output = UnpaddedBytesAmount;
 
@@ -161,4 +196,25 @@ mod tests {
         // assert_eq!(1u64 + u64::from(e), 3u64);
         // assert_eq!(1usize + usize::from(e), 3usize);
     }
+
+    #[test]
+    fn index_conversions() {
+        // Whole Frs map the same way as amounts.
+        let unpadded = UnpaddedByteIndex(127 * 4);
+        let padded = PaddedByteIndex::from(unpadded);
+        assert_eq!(padded, PaddedByteIndex(128 * 4));
+        assert_eq!(
+            padded,
+            PaddedByteIndex::from(PaddedBytesAmount::from(UnpaddedBytesAmount::from(unpadded)))
+        );
+
+        // Arbitrary offsets round trip.
+        for i in 0..300 {
+            let unpadded = UnpaddedByteIndex(i);
+            assert_eq!(
+                UnpaddedByteIndex::from(PaddedByteIndex::from(unpadded)),
+                unpadded
+            );
+        }
+    }
 }
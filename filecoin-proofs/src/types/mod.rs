@@ -1,7 +1,11 @@
 pub use merkletree::store::StoreConfig;
+pub use storage_proofs_core::challenge_domain::ChallengeDomain;
 pub use storage_proofs_core::merkle::{MerkleProof, MerkleTreeTrait};
+pub use storage_proofs_core::sector::{ProverId, Ticket};
 pub use storage_proofs_porep::stacked::{Labels, PersistentAux, TemporaryAux};
 
+use std::path::PathBuf;
+
 use filecoin_hashers::Hasher;
 use serde::{Deserialize, Serialize};
 use storage_proofs_core::{merkle::BinaryMerkleTree, sector::SectorId};
@@ -17,7 +21,9 @@ mod porep_proof_partitions;
 mod post_config;
 mod post_proof_partitions;
 mod private_replica_info;
+mod prover_config;
 mod public_replica_info;
+mod registered_proofs;
 mod sector_class;
 mod sector_size;
 mod sector_update_config;
@@ -30,7 +36,9 @@ pub use porep_proof_partitions::*;
 pub use post_config::*;
 pub use post_proof_partitions::*;
 pub use private_replica_info::*;
+pub use prover_config::*;
 pub use public_replica_info::*;
+pub use registered_proofs::*;
 pub use sector_class::*;
 pub use sector_size::*;
 pub use sector_update_config::*;
@@ -38,8 +46,6 @@ pub use update_proof_partitions::*;
 
 pub type Commitment = [u8; 32];
 pub type ChallengeSeed = [u8; 32];
-pub type ProverId = [u8; 32];
-pub type Ticket = [u8; 32];
 pub type DataTree = BinaryMerkleTree<DefaultPieceHasher>;
 
 /// Arity for oct trees, used for comm_r_last.
@@ -54,15 +60,50 @@ pub struct SealPreCommitOutput {
     pub comm_d: Commitment,
 }
 
-pub type VanillaSealProof<Tree> = stacked::Proof<Tree, DefaultPieceHasher>;
+/// The vanilla proof of a sector whose `tree_d` is built with `G`.
+pub type VanillaSealProof<Tree, G = DefaultPieceHasher> = stacked::Proof<Tree, G>;
+
+/// The phase 1 output of a sector whose `tree_d` is built with `G`, see [`PieceHasher`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SealCommitPhase1Output<Tree: MerkleTreeTrait, G: Hasher = DefaultPieceHasher> {
+    #[serde(bound(
+        serialize = "VanillaSealProof<Tree, G>: Serialize",
+        deserialize = "VanillaSealProof<Tree, G>: Deserialize<'de>"
+    ))]
+    pub vanilla_proofs: Vec<Vec<VanillaSealProof<Tree, G>>>,
+    pub comm_r: Commitment,
+    pub comm_d: Commitment,
+    pub replica_id: <Tree::Hasher as Hasher>::Domain,
+    pub seed: Ticket,
+    pub ticket: Ticket,
+}
+
+#[cfg(feature = "zeroize")]
+impl<Tree: MerkleTreeTrait, G: Hasher> zeroize::Zeroize for SealCommitPhase1Output<Tree, G>
+where
+    <Tree::Hasher as Hasher>::Domain: zeroize::Zeroize,
+{
+    /// Wipes the randomness, and the replica id derived from it.
+    fn zeroize(&mut self) {
+        self.replica_id.zeroize();
+        self.seed.0.zeroize();
+        self.ticket.0.zeroize();
+    }
+}
 
+/// The vanilla proofs of a single partition of a [`SealCommitPhase1Output`], together with
+/// everything needed to reassemble it, see `split_seal_commit_phase1_output`.
 #[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct SealCommitPhase1Output<Tree: MerkleTreeTrait> {
+pub struct SealCommitPhase1Chunk<Tree: MerkleTreeTrait> {
+    /// The index of the partition.
+    pub partition: usize,
+    /// The number of partitions of the phase 1 output this chunk was split from.
+    pub partitions: usize,
     #[serde(bound(
         serialize = "VanillaSealProof<Tree>: Serialize",
         deserialize = "VanillaSealProof<Tree>: Deserialize<'de>"
     ))]
-    pub vanilla_proofs: Vec<Vec<VanillaSealProof<Tree>>>,
+    pub vanilla_proofs: Vec<VanillaSealProof<Tree>>,
     pub comm_r: Commitment,
     pub comm_d: Commitment,
     pub replica_id: <Tree::Hasher as Hasher>::Domain,
@@ -70,11 +111,43 @@ pub struct SealCommitPhase1Output<Tree: MerkleTreeTrait> {
     pub ticket: Ticket,
 }
 
+#[cfg(feature = "zeroize")]
+impl<Tree: MerkleTreeTrait> zeroize::Zeroize for SealCommitPhase1Chunk<Tree>
+where
+    <Tree::Hasher as Hasher>::Domain: zeroize::Zeroize,
+{
+    /// Wipes the randomness, and the replica id derived from it.
+    fn zeroize(&mut self) {
+        self.replica_id.zeroize();
+        self.seed.0.zeroize();
+        self.ticket.0.zeroize();
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct SealCommitOutput {
     pub proof: Vec<u8>,
 }
 
+/// The public inputs that a sector's porep proofs are verified against.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SealPublicInputs {
+    pub comm_r: Commitment,
+    pub comm_d: Commitment,
+    pub prover_id: ProverId,
+    pub sector_id: SectorId,
+    pub ticket: Ticket,
+    /// The interactive porep challenge seed.
+    pub seed: Ticket,
+}
+
+/// A seal proof together with the public inputs it is verified against.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SealVerifyInfo {
+    pub pub_inputs: SealPublicInputs,
+    pub proof: Vec<u8>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SealPreCommitPhase1Output<Tree: MerkleTreeTrait> {
     #[serde(bound(
@@ -84,6 +157,25 @@ pub struct SealPreCommitPhase1Output<Tree: MerkleTreeTrait> {
     pub labels: Labels<Tree>,
     pub config: StoreConfig,
     pub comm_d: Commitment,
+    /// The root of tree_c, if it was built in phase1, see `Settings::build_tree_c_in_phase1`.
+    #[serde(default)]
+    pub comm_c: Option<Commitment>,
+    /// Whether tree_d was not built and comm_d was computed from the pieces, see
+    /// `seal_pre_commit_phase1_without_tree_d`.
+    #[serde(default)]
+    pub without_tree_d: bool,
+}
+
+impl<Tree: MerkleTreeTrait> Clone for SealPreCommitPhase1Output<Tree> {
+    fn clone(&self) -> Self {
+        Self {
+            labels: self.labels.clone(),
+            config: self.config.clone(),
+            comm_d: self.comm_d,
+            comm_c: self.comm_c,
+            without_tree_d: self.without_tree_d,
+        }
+    }
 }
 
 #[repr(transparent)]
@@ -113,6 +205,24 @@ pub struct FallbackPoStSectorProof<Tree: MerkleTreeTrait> {
     pub vanilla_proof: VanillaProof<Tree>, // Has comm_c, comm_r_last, inclusion_proofs
 }
 
+/// The inputs for proving a single partition of an empty sector update. They can be serialized,
+/// so that the partitions can be proven on different machines, the paths and store configs must
+/// be valid on the machine that does the proving.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SectorUpdatePartitionInputs {
+    pub partition_index: usize,
+    pub comm_r_old: Commitment,
+    pub comm_r_new: Commitment,
+    pub comm_d_new: Commitment,
+    /// The comm_c of the sector key.
+    pub comm_c: Commitment,
+    pub tree_r_old_config: StoreConfig,
+    pub old_replica_path: PathBuf,
+    pub tree_d_new_config: StoreConfig,
+    pub tree_r_new_config: StoreConfig,
+    pub replica_path: PathBuf,
+}
+
 pub struct EmptySectorUpdateEncoded {
     pub comm_r_new: Commitment,
     pub comm_r_last_new: Commitment,
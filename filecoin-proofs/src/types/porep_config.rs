@@ -1,8 +1,10 @@
 use std::path::PathBuf;
 
-use anyhow::Result;
+use anyhow::{ensure, Result};
+use filecoin_hashers::{poseidon::PoseidonHasher, sha256::Sha256Hasher, Hasher};
 use storage_proofs_core::{
     api_version::{ApiFeature, ApiVersion},
+    challenge_domain::ChallengeDomain,
     merkle::MerkleTreeTrait,
     parameter_cache::{
         parameter_cache_metadata_path, parameter_cache_params_path,
@@ -12,18 +14,62 @@ use storage_proofs_core::{
 use storage_proofs_porep::stacked::{StackedCircuit, StackedCompound};
 
 use crate::{
-    constants::DefaultPieceHasher,
-    parameters::public_params,
+    constants::{DefaultPieceHasher, ProtocolConstants},
+    parameters::public_params_with_piece_hasher,
     types::{PaddedBytesAmount, PoRepProofPartitions, SectorSize, UnpaddedBytesAmount},
-    POREP_PARTITIONS,
 };
 
+/// The hasher the data tree (`tree_d`) of a sector is built with, and so the hasher of comm_d.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PieceHasher {
+    /// [`DefaultPieceHasher`], the hasher of the Filecoin networks. Piece commitments are
+    /// always built with it, so only sectors with this hasher can be checked against their pieces.
+    Sha256,
+    Poseidon,
+}
+
+impl Default for PieceHasher {
+    fn default() -> Self {
+        PieceHasher::Sha256
+    }
+}
+
+impl PieceHasher {
+    /// Returns the name of the hasher, as returned by [`Hasher::name`].
+    pub fn name(&self) -> String {
+        match self {
+            PieceHasher::Sha256 => Sha256Hasher::name(),
+            PieceHasher::Poseidon => PoseidonHasher::name(),
+        }
+    }
+
+    /// Returns whether `G` is this hasher.
+    pub fn is<G: Hasher>(&self) -> bool {
+        self.name() == G::name()
+    }
+}
+
+/// PoRep configuration for a sector.
+///
+/// The data tree (`tree_d`) is built with the hasher selected by `piece_hasher`. The sealing
+/// API dispatches on it, the functions returning or taking vanilla proofs take the hasher as a
+/// type parameter instead, e.g. `seal_commit_phase1_with_piece_hasher`. Aggregation, batch
+/// verification and synthetic proofs only support [`PieceHasher::Sha256`].
 #[derive(Clone, Debug)]
 pub struct PoRepConfig {
     pub sector_size: SectorSize,
     pub partitions: PoRepProofPartitions,
+    /// The number of layers of the stacked DRG.
+    pub layers: usize,
+    /// The minimum number of porep challenges a proof must have.
+    pub minimum_challenges: usize,
     pub porep_id: [u8; 32],
     pub api_version: ApiVersion,
+    /// The domain the porep challenges are derived in, [`ChallengeDomain::Mainnet`] unless the
+    /// sector is sealed for another network.
+    pub challenge_domain: ChallengeDomain,
+    /// The hasher of `tree_d`, [`PieceHasher::Sha256`] unless selected otherwise.
+    pub piece_hasher: PieceHasher,
     pub api_features: Vec<ApiFeature>,
 }
 
@@ -56,21 +102,60 @@ impl From<PoRepConfig> for SectorSize {
 }
 
 impl PoRepConfig {
-    /// construct PoRepConfig by groth16
+    /// construct PoRepConfig by groth16, with the protocol constants of the process-global maps.
     pub fn new_groth16(sector_size: u64, porep_id: [u8; 32], api_version: ApiVersion) -> Self {
-        Self {
+        Self::new_groth16_with_constants(
+            sector_size,
+            porep_id,
+            api_version,
+            &ProtocolConstants::from_globals(),
+        )
+        .expect("unknown sector size")
+    }
+
+    /// construct PoRepConfig by groth16, with the given protocol constants.
+    pub fn new_groth16_with_constants(
+        sector_size: u64,
+        porep_id: [u8; 32],
+        api_version: ApiVersion,
+        constants: &ProtocolConstants,
+    ) -> Result<Self> {
+        Ok(Self {
             sector_size: SectorSize(sector_size),
-            partitions: PoRepProofPartitions(
-                *POREP_PARTITIONS
-                    .read()
-                    .expect("POREP_PARTITIONS poisoned")
-                    .get(&sector_size)
-                    .expect("unknown sector size"),
-            ),
+            partitions: PoRepProofPartitions(constants.porep_partitions(sector_size)?),
+            layers: constants.layers(sector_size)?,
+            minimum_challenges: constants.porep_minimum_challenges(sector_size)?,
             porep_id,
             api_version,
+            challenge_domain: ChallengeDomain::Mainnet,
+            piece_hasher: PieceHasher::Sha256,
             api_features: vec![],
-        }
+        })
+    }
+
+    /// Returns this configuration with the porep challenges derived in `challenge_domain`.
+    #[inline]
+    pub fn with_challenge_domain(mut self, challenge_domain: ChallengeDomain) -> Self {
+        self.challenge_domain = challenge_domain;
+        self
+    }
+
+    /// Returns this configuration with `tree_d` built with `piece_hasher`.
+    #[inline]
+    pub fn with_piece_hasher(mut self, piece_hasher: PieceHasher) -> Self {
+        self.piece_hasher = piece_hasher;
+        self
+    }
+
+    /// Ensures `G` is the hasher `tree_d` is built with.
+    pub fn check_piece_hasher<G: Hasher>(&self) -> Result<()> {
+        ensure!(
+            self.piece_hasher.is::<G>(),
+            "tree_d is built with {}, not {}",
+            self.piece_hasher.name(),
+            G::name()
+        );
+        Ok(())
     }
 
     #[inline]
@@ -103,14 +188,29 @@ impl PoRepConfig {
 
     /// Returns the cache identifier as used by `storage-proofs::parameter_cache`.
     pub fn get_cache_identifier<Tree: 'static + MerkleTreeTrait>(&self) -> Result<String> {
-        let params = public_params::<Tree>(self)?;
+        match self.piece_hasher {
+            PieceHasher::Sha256 => {
+                self.get_cache_identifier_with_piece_hasher::<Tree, DefaultPieceHasher>()
+            }
+            PieceHasher::Poseidon => {
+                self.get_cache_identifier_with_piece_hasher::<Tree, PoseidonHasher>()
+            }
+        }
+    }
 
-        Ok(
-            <StackedCompound<Tree, DefaultPieceHasher> as CacheableParameters<
-                StackedCircuit<'_, Tree, DefaultPieceHasher>,
-                _,
-            >>::cache_identifier(&params),
-        )
+    /// Returns the cache identifier of the parameters for a `tree_d` built with hasher `G`.
+    pub fn get_cache_identifier_with_piece_hasher<
+        Tree: 'static + MerkleTreeTrait,
+        G: 'static + Hasher,
+    >(
+        &self,
+    ) -> Result<String> {
+        let params = public_params_with_piece_hasher::<Tree, G>(self)?;
+
+        Ok(<StackedCompound<Tree, G> as CacheableParameters<
+            StackedCircuit<'_, Tree, G>,
+            _,
+        >>::cache_identifier(&params))
     }
 
     pub fn get_cache_metadata_path<Tree: 'static + MerkleTreeTrait>(&self) -> Result<PathBuf> {
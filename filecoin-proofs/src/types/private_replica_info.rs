@@ -1,20 +1,25 @@
-use std::cmp::Ordering;
+use std::cmp::{min, Ordering};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs::File;
 use std::hash::{Hash, Hasher as StdHasher};
+use std::io::{Read, Seek, SeekFrom};
 use std::marker::PhantomData;
 use std::path::{Path, PathBuf};
 
-use anyhow::{ensure, Result};
+use anyhow::{ensure, Context, Result};
 use filecoin_hashers::Hasher;
 use generic_array::typenum::Unsigned;
 use log::trace;
-use merkletree::store::StoreConfig;
+use merkletree::store::{ReplicaConfig, StoreConfig};
 use storage_proofs_core::{
     cache_key::CacheKey,
     merkle::{
-        create_tree, get_base_tree_count, split_config_and_replica, MerkleTreeTrait,
-        MerkleTreeWrapper,
+        create_lc_tree_with_backend, create_tree, get_base_tree_count, prepare_lc_tree_configs,
+        split_config_and_replica, stored_lc_tree_configs, BackendLCTree, FileBackend,
+        MerkleTreeTrait, MerkleTreeWrapper, NodeBackend,
     },
-    util::default_rows_to_discard,
+    util::{default_rows_to_discard, NODE_SIZE},
 };
 
 use crate::{
@@ -120,6 +125,9 @@ impl<Tree: 'static + MerkleTreeTrait> PrivateReplicaInfo<Tree> {
     }
 
     /// Generate the merkle tree of this particular replica.
+    ///
+    /// If the tree was stored with more rows discarded than the current `rows_to_discard`
+    /// setting, the missing rows are rebuilt from the replica first.
     pub fn merkle_tree(
         &self,
         sector_size: SectorSize,
@@ -144,6 +152,72 @@ impl<Tree: 'static + MerkleTreeTrait> PrivateReplicaInfo<Tree> {
             Tree::TopTreeArity::to_usize(),
         );
 
+        let (mut configs, replica_config) =
+            self.tree_r_last_configs(base_tree_size, base_tree_leafs)?;
+        prepare_lc_tree_configs::<Tree>(base_tree_leafs, &mut configs, &replica_config)?;
+
+        create_tree::<Tree>(base_tree_size, &configs, Some(&replica_config))
+    }
+
+    /// Like [`merkle_tree`](Self::merkle_tree), but the replica is read through `backend`
+    /// instead of from the replica path, e.g. to serve the PoSt challenges from remote storage.
+    /// The cached levels of the tree are still read from the cache directory, rebuilding
+    /// discarded rows reads the replica path like `merkle_tree` does.
+    #[allow(clippy::type_complexity)]
+    pub fn merkle_tree_with_backend<B: 'static + NodeBackend + Clone>(
+        &self,
+        sector_size: SectorSize,
+        backend: B,
+    ) -> Result<BackendLCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity, B>>
+    {
+        let base_tree_size = get_base_tree_size::<Tree>(sector_size)?;
+        let base_tree_leafs = get_base_tree_leafs::<Tree>(base_tree_size)?;
+
+        let (mut configs, replica_config) =
+            self.tree_r_last_configs(base_tree_size, base_tree_leafs)?;
+        prepare_lc_tree_configs::<Tree>(base_tree_leafs, &mut configs, &replica_config)?;
+
+        create_lc_tree_with_backend::<Tree, B>(base_tree_size, &configs, &replica_config, backend)
+    }
+
+    /// Opens the merkle tree of this replica without modifying it, unlike
+    /// [`merkle_tree`](Self::merkle_tree). The base trees are opened with the rows to discard
+    /// they were stored with, which are returned along with the tree.
+    #[allow(clippy::type_complexity)]
+    pub fn merkle_tree_as_stored(
+        &self,
+        sector_size: SectorSize,
+    ) -> Result<(
+        MerkleTreeWrapper<
+            Tree::Hasher,
+            Tree::Store,
+            Tree::Arity,
+            Tree::SubTreeArity,
+            Tree::TopTreeArity,
+        >,
+        Vec<usize>,
+    )> {
+        let base_tree_size = get_base_tree_size::<Tree>(sector_size)?;
+        let base_tree_leafs = get_base_tree_leafs::<Tree>(base_tree_size)?;
+
+        let (mut configs, replica_config) =
+            self.tree_r_last_configs(base_tree_size, base_tree_leafs)?;
+        stored_lc_tree_configs::<Tree>(base_tree_leafs, &mut configs)?;
+        let rows_to_discard = configs
+            .iter()
+            .map(|config| config.rows_to_discard)
+            .collect();
+
+        let tree = create_tree::<Tree>(base_tree_size, &configs, Some(&replica_config))?;
+        Ok((tree, rows_to_discard))
+    }
+
+    // The configs of the base trees of tree_r_last, with the default rows to discard.
+    fn tree_r_last_configs(
+        &self,
+        base_tree_size: usize,
+        base_tree_leafs: usize,
+    ) -> Result<(Vec<StoreConfig>, ReplicaConfig)> {
         let mut config = StoreConfig::new(
             self.cache_dir_path(),
             CacheKey::CommRLastTree.to_string(),
@@ -151,14 +225,250 @@ impl<Tree: 'static + MerkleTreeTrait> PrivateReplicaInfo<Tree> {
         );
         config.size = Some(base_tree_size);
 
-        let tree_count = get_base_tree_count::<Tree>();
-        let (configs, replica_config) = split_config_and_replica(
+        split_config_and_replica(
             config,
             self.replica_path().to_path_buf(),
             base_tree_leafs,
-            tree_count,
-        )?;
+            get_base_tree_count::<Tree>(),
+        )
+    }
 
-        create_tree::<Tree>(base_tree_size, &configs, Some(&replica_config))
+    /// Reads the parts of the replica and of tree_r_last that are needed to prove the given leaf
+    /// challenges, so that they are in the page cache once the proof is generated. Returns the
+    /// number of bytes read. Use [`prefetch_replica`](Self::prefetch_replica) to keep the read
+    /// replica data for the proof instead.
+    ///
+    /// The rows of tree_r_last that are not stored in the cache are rebuilt from the replica,
+    /// hence for every challenge the whole segment of leafs below the first stored row is read,
+    /// followed by the nodes of the stored rows on the path of the challenge. The reads of each
+    /// file are issued in ascending order, which keeps the seeks short on spinning disks.
+    pub fn prefetch_challenges(&self, sector_size: SectorSize, challenges: &[u64]) -> Result<u64> {
+        let replica = self.prefetch_replica(sector_size, challenges)?;
+        Ok(replica
+            .segments
+            .values()
+            .map(|data| data.len() as u64)
+            .sum::<u64>()
+            + replica.tree_bytes)
+    }
+
+    /// Like [`prefetch_challenges`](Self::prefetch_challenges), but the replica segments that
+    /// were read are kept in memory. The returned backend serves them to the proof, see
+    /// `generate_single_vanilla_proof_with_backend`, while tree_r_last is read from the page
+    /// cache.
+    pub fn prefetch_replica(
+        &self,
+        sector_size: SectorSize,
+        challenges: &[u64],
+    ) -> Result<PrefetchedReplica> {
+        let base_tree_size = get_base_tree_size::<Tree>(sector_size)?;
+        let base_tree_leafs = get_base_tree_leafs::<Tree>(base_tree_size)?;
+        let arity = Tree::Arity::to_usize();
+        let rows_to_discard = default_rows_to_discard(base_tree_leafs, arity);
+        let segment_leafs = min(arity.pow(rows_to_discard as u32 + 1), base_tree_leafs) as u64;
+        let sector_leafs = u64::from(sector_size) / NODE_SIZE as u64;
+
+        for challenge in challenges {
+            ensure!(
+                *challenge < sector_leafs,
+                "challenge {} is out of range",
+                challenge
+            );
+        }
+
+        let replica = FileBackend::open(self.replica_path())?;
+        let segment_bytes = segment_leafs as usize * NODE_SIZE;
+        let segments = challenges
+            .iter()
+            .map(|challenge| challenge / segment_leafs)
+            .collect::<BTreeSet<u64>>()
+            .into_iter()
+            .map(|segment| {
+                let mut data = vec![0u8; segment_bytes];
+                replica.read_at(segment as usize * segment_bytes, &mut data)?;
+                Ok((segment, data))
+            })
+            .collect::<Result<_>>()?;
+
+        let (configs, _) = self.tree_r_last_configs(base_tree_size, base_tree_leafs)?;
+        let rows = StoredRows::new(base_tree_leafs, arity, rows_to_discard);
+        let mut tree_bytes = 0;
+        for (index, config) in configs.iter().enumerate() {
+            let first_leaf = (index * base_tree_leafs) as u64;
+            let tree_challenges = challenges
+                .iter()
+                .filter(|challenge| {
+                    (first_leaf..first_leaf + base_tree_leafs as u64).contains(challenge)
+                })
+                .map(|challenge| (challenge - first_leaf) as usize)
+                .collect::<Vec<_>>();
+            if tree_challenges.is_empty() {
+                continue;
+            }
+
+            // A tree stored with other rows discarded is rebuilt when it is opened, none of its
+            // stored nodes are read in that case.
+            let path = StoreConfig::data_path(&config.path, &config.id);
+            let len = path
+                .metadata()
+                .with_context(|| format!("could not stat {:?}", path))?
+                .len();
+            if len != rows.len_bytes() {
+                trace!(
+                    "prefetch: {:?} has unexpected length {}, skipping",
+                    path,
+                    len
+                );
+                continue;
+            }
+            tree_bytes += read_ranges(&path, rows.path_ranges(&tree_challenges))?;
+        }
+
+        Ok(PrefetchedReplica {
+            segment_bytes,
+            segments,
+            tree_bytes,
+            replica,
+        })
+    }
+}
+
+/// The replica data of the challenges of a sector, read ahead by
+/// [`PrivateReplicaInfo::prefetch_replica`]. Reads within the prefetched segments are served
+/// from memory, all others from the replica file.
+pub struct PrefetchedReplica {
+    segment_bytes: usize,
+    // The prefetched segments by their index.
+    segments: BTreeMap<u64, Vec<u8>>,
+    // The number of bytes read from tree_r_last into the page cache.
+    tree_bytes: u64,
+    replica: FileBackend,
+}
+
+impl PrefetchedReplica {
+    /// The number of bytes held in memory.
+    pub fn prefetched_bytes(&self) -> usize {
+        self.segments.len() * self.segment_bytes
+    }
+}
+
+impl fmt::Debug for PrefetchedReplica {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PrefetchedReplica")
+            .field("replica", &self.replica)
+            .field("segment_bytes", &self.segment_bytes)
+            .field("segments", &self.segments.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl NodeBackend for PrefetchedReplica {
+    fn len(&self) -> usize {
+        self.replica.len()
+    }
+
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<()> {
+        let segment = offset / self.segment_bytes;
+        let start = offset % self.segment_bytes;
+        match self.segments.get(&(segment as u64)) {
+            Some(data) if start + buf.len() <= data.len() => {
+                buf.copy_from_slice(&data[start..start + buf.len()]);
+                Ok(())
+            }
+            _ => self.replica.read_at(offset, buf),
+        }
+    }
+}
+
+// The rows of a tree_r_last base tree that are stored in its cache file, i.e. all but the leafs
+// and the `rows_to_discard` rows above them.
+struct StoredRows {
+    arity: usize,
+    first_row: usize,
+    // The offset in nodes within the file and the width of each stored row.
+    rows: Vec<(usize, usize)>,
+}
+
+impl StoredRows {
+    fn new(leafs: usize, arity: usize, rows_to_discard: usize) -> Self {
+        let first_row = rows_to_discard + 1;
+        let mut rows = Vec::new();
+        let mut offset = 0;
+        let mut width = leafs / arity.pow(first_row as u32);
+        while width > 0 {
+            rows.push((offset, width));
+            offset += width;
+            width /= arity;
+        }
+
+        StoredRows {
+            arity,
+            first_row,
+            rows,
+        }
+    }
+
+    fn len_bytes(&self) -> u64 {
+        self.rows
+            .last()
+            .map_or(0, |(offset, width)| ((offset + width) * NODE_SIZE) as u64)
+    }
+
+    // The byte ranges of the nodes which are read when opening the given leafs, i.e. the nodes
+    // with their siblings on every stored row.
+    fn path_ranges(&self, leafs: &[usize]) -> BTreeSet<(u64, usize)> {
+        let mut ranges = BTreeSet::new();
+        for leaf in leafs {
+            for (row, (offset, width)) in self.rows.iter().enumerate() {
+                let index = leaf / self.arity.pow((self.first_row + row) as u32);
+                let (start, count) = if *width == 1 {
+                    (0, 1)
+                } else {
+                    (index / self.arity * self.arity, self.arity)
+                };
+                ranges.insert((((offset + start) * NODE_SIZE) as u64, count * NODE_SIZE));
+            }
+        }
+        ranges
+    }
+}
+
+// Reads the `(offset, length)` byte ranges of the file at `path` in the given order. Returns the
+// number of bytes read.
+fn read_ranges<I: IntoIterator<Item = (u64, usize)>>(path: &Path, ranges: I) -> Result<u64> {
+    let mut file = File::open(path).with_context(|| format!("could not open {:?}", path))?;
+    let mut buf = Vec::new();
+    let mut bytes = 0;
+    for (offset, len) in ranges {
+        buf.resize(len, 0);
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut buf)
+            .with_context(|| format!("could not read {:?}", path))?;
+        bytes += len as u64;
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use merkletree::merkle::get_merkle_tree_cache_size;
+
+    #[test]
+    fn test_stored_rows() {
+        for (leafs, arity) in [(64, 2), (4096, 8), (8usize.pow(6), 8)] {
+            let rows_to_discard = default_rows_to_discard(leafs, arity);
+            let rows = StoredRows::new(leafs, arity, rows_to_discard);
+            let cache_size = get_merkle_tree_cache_size(leafs, arity, rows_to_discard)
+                .expect("failed to get cache size");
+            assert_eq!(rows.len_bytes(), (cache_size * NODE_SIZE) as u64);
+
+            // One group of siblings per stored row, the root is alone.
+            let ranges = rows.path_ranges(&[leafs - 1]);
+            assert_eq!(ranges.len(), rows.rows.len());
+            let (last_offset, last_len) = ranges.iter().last().expect("no ranges");
+            assert_eq!(last_offset + *last_len as u64, rows.len_bytes());
+        }
     }
 }
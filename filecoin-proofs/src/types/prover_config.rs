@@ -0,0 +1,215 @@
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use storage_proofs_porep::stacked::{install_thread_pool, PorepThreadPool};
+
+use crate::priority::{
+    global_priority_manager, run_with_priority, PriorityManager, ProvingPriority,
+};
+
+/// Settings of a single proving operation, e.g. a seal stage or a PoSt.
+///
+/// By default all operations share rayon's global thread pool, so a long running operation like
+/// pre-commit phase 1 can starve a deadline critical PoSt. Operations that are run through
+/// [`ProverConfig::install`] with a dedicated pool are isolated from each other.
+///
+/// The pool runs the rayon based work of the operation, e.g. tree_d building, vanilla proving and
+/// constructing the circuits. Building tree_c (including the column hashing) and tree_r_last, and
+/// the scoped proving work of storage-proofs-porep, run on a [`PorepThreadPool`] of the same size
+/// that is installed alongside. The multicore SDR labeling threads, which are pinned to cores by
+/// their own settings, and the Groth16 multiexp/FFT workers are not affected. No API function
+/// takes a `ProverConfig`, the calls are wrapped in [`ProverConfig::install`] instead.
+///
+/// Operations with a [`ProvingPriority`] are registered with a [`PriorityManager`] while they
+/// run, see [`crate::priority`]. Without a priority, the proving stages only register themselves
+/// with their default priority if a global manager was installed, see
+/// [`set_global_priority_manager`](crate::priority::set_global_priority_manager).
+#[derive(Clone, Default)]
+pub struct ProverConfig {
+    thread_pool: Option<Arc<ThreadPool>>,
+    porep_thread_pool: Option<Arc<PorepThreadPool>>,
+    priority: Option<ProvingPriority>,
+    priority_manager: Option<Arc<PriorityManager>>,
+}
+
+impl fmt::Debug for ProverConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProverConfig")
+            .field(
+                "thread_pool_threads",
+                &self
+                    .thread_pool
+                    .as_ref()
+                    .map(|pool| pool.current_num_threads()),
+            )
+            .field("priority", &self.priority)
+            .field("priority_manager", &self.priority_manager)
+            .finish()
+    }
+}
+
+impl ProverConfig {
+    /// Runs operations on the given pool, which may be shared with other operations on purpose.
+    pub fn with_thread_pool(thread_pool: Arc<ThreadPool>) -> Self {
+        let porep_thread_pool = PorepThreadPool::new(thread_pool.current_num_threads());
+        ProverConfig {
+            thread_pool: Some(thread_pool),
+            porep_thread_pool: Some(Arc::new(porep_thread_pool)),
+            ..Default::default()
+        }
+    }
+
+    /// Runs operations on a new dedicated pool of `num_threads` threads.
+    pub fn with_threads(num_threads: usize, name: &str) -> Result<Self> {
+        let name = name.to_string();
+        let thread_pool = ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .thread_name(move |i| format!("{}-{}", name, i))
+            .build()
+            .context("failed to build prover thread pool")?;
+        Ok(Self::with_thread_pool(Arc::new(thread_pool)))
+    }
+
+    /// Runs operations at `priority`.
+    pub fn with_priority(mut self, priority: ProvingPriority) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    /// Registers operations with `priority_manager` instead of the global one.
+    pub fn with_priority_manager(mut self, priority_manager: Arc<PriorityManager>) -> Self {
+        self.priority_manager = Some(priority_manager);
+        self
+    }
+
+    pub fn thread_pool(&self) -> Option<&Arc<ThreadPool>> {
+        self.thread_pool.as_ref()
+    }
+
+    pub fn priority(&self) -> Option<ProvingPriority> {
+        self.priority
+    }
+
+    /// Runs the operation `op` within the configured pool, or the global pool if none is set.
+    ///
+    /// If a priority is configured, the operation is registered with the priority manager for its
+    /// duration, background operations wait for high priority ones first. Without a pool of its
+    /// own, the operation runs on the manager's pool for its priority, if there is one.
+    ///
+    /// ```ignore
+    /// let prover_config = ProverConfig::with_threads(16, "window-post")?;
+    /// let proof = prover_config.install(|| {
+    ///     generate_window_post::<Tree>(&config, &randomness, &replicas, prover_id)
+    /// })?;
+    /// ```
+    pub fn install<R, F>(&self, op: F) -> R
+    where
+        R: Send,
+        F: FnOnce() -> R + Send,
+    {
+        let priority = match self.priority {
+            Some(priority) => priority,
+            None => {
+                return install_in(
+                    self.thread_pool.as_ref(),
+                    self.porep_thread_pool.as_ref(),
+                    op,
+                )
+            }
+        };
+
+        let manager = self
+            .priority_manager
+            .clone()
+            .unwrap_or_else(global_priority_manager);
+        let _guard = manager.enter(priority);
+        let (thread_pool, porep_thread_pool) = match self.thread_pool {
+            Some(ref thread_pool) => (Some(thread_pool), self.porep_thread_pool.as_ref()),
+            None => (
+                manager.thread_pool(priority),
+                manager.porep_thread_pool(priority),
+            ),
+        };
+        install_in(thread_pool, porep_thread_pool, || {
+            run_with_priority(priority, op)
+        })
+    }
+}
+
+fn install_in<R, F>(
+    thread_pool: Option<&Arc<ThreadPool>>,
+    porep_thread_pool: Option<&Arc<PorepThreadPool>>,
+    op: F,
+) -> R
+where
+    R: Send,
+    F: FnOnce() -> R + Send,
+{
+    let op = move || match porep_thread_pool {
+        Some(porep_thread_pool) => install_thread_pool(Arc::clone(porep_thread_pool), op),
+        None => op(),
+    };
+    match thread_pool {
+        Some(thread_pool) => thread_pool.install(op),
+        None => op(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+
+    #[test]
+    fn test_install_uses_dedicated_pool() {
+        let config = ProverConfig::with_threads(3, "test-prover").expect("failed to build pool");
+        assert_eq!(config.install(rayon::current_num_threads), 3);
+
+        let names: Vec<String> = config.install(|| {
+            (0..64)
+                .into_par_iter()
+                .map(|_| {
+                    std::thread::current()
+                        .name()
+                        .unwrap_or_default()
+                        .to_string()
+                })
+                .collect()
+        });
+        assert!(names.iter().all(|name| name.starts_with("test-prover-")));
+
+        let default = ProverConfig::default();
+        assert!(default.thread_pool().is_none());
+        assert_eq!(
+            default.install(rayon::current_num_threads),
+            rayon::current_num_threads()
+        );
+    }
+
+    #[test]
+    fn test_install_with_priority() {
+        let background_pool = ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .expect("failed to build pool");
+        let manager = Arc::new(
+            PriorityManager::new()
+                .with_thread_pool(ProvingPriority::Background, Arc::new(background_pool)),
+        );
+
+        let post = ProverConfig::default()
+            .with_priority(ProvingPriority::High)
+            .with_priority_manager(Arc::clone(&manager));
+        assert_eq!(post.install(|| manager.active(ProvingPriority::High)), 1);
+        assert_eq!(manager.active(ProvingPriority::High), 0);
+
+        // Background operations run on the manager's pool for their priority.
+        let sealing = ProverConfig::default()
+            .with_priority(ProvingPriority::Background)
+            .with_priority_manager(Arc::clone(&manager));
+        assert_eq!(sealing.install(rayon::current_num_threads), 2);
+    }
+}
@@ -32,6 +32,10 @@ impl PublicReplicaInfo {
         Ok(PublicReplicaInfo { comm_r })
     }
 
+    pub fn comm_r(&self) -> Commitment {
+        self.comm_r
+    }
+
     pub fn safe_comm_r<T: Domain>(&self) -> Result<T> {
         as_safe_commitment(&self.comm_r, "comm_r")
     }
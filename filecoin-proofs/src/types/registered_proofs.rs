@@ -0,0 +1,460 @@
+use std::convert::TryFrom;
+
+use anyhow::{ensure, format_err, Error, Result};
+use serde::{Deserialize, Serialize};
+use storage_proofs_core::api_version::{ApiFeature, ApiVersion};
+
+use crate::{
+    constants::{
+        ProtocolConstants, SECTOR_SIZE_2_KIB, SECTOR_SIZE_32_GIB, SECTOR_SIZE_512_MIB,
+        SECTOR_SIZE_64_GIB, SECTOR_SIZE_8_MIB, WINDOW_POST_CHALLENGE_COUNT,
+        WINNING_POST_CHALLENGE_COUNT, WINNING_POST_SECTOR_COUNT,
+    },
+    types::{PoRepConfig, PoStConfig, PoStType, SectorSize},
+};
+
+/// The sector sizes that are used on the network. The registered proofs are assigned their ids
+/// in blocks of these sizes, in this order.
+pub const NETWORK_SECTOR_SIZES: [u64; 5] = [
+    SECTOR_SIZE_2_KIB,
+    SECTOR_SIZE_8_MIB,
+    SECTOR_SIZE_512_MIB,
+    SECTOR_SIZE_32_GIB,
+    SECTOR_SIZE_64_GIB,
+];
+
+/// The seal proofs that are registered on the network, the discriminant is the registered proof
+/// id, which is encoded in the first eight bytes of the `porep_id`.
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[repr(u64)]
+pub enum RegisteredSealProof {
+    StackedDrg2KiBV1,
+    StackedDrg8MiBV1,
+    StackedDrg512MiBV1,
+    StackedDrg32GiBV1,
+    StackedDrg64GiBV1,
+
+    StackedDrg2KiBV1_1,
+    StackedDrg8MiBV1_1,
+    StackedDrg512MiBV1_1,
+    StackedDrg32GiBV1_1,
+    StackedDrg64GiBV1_1,
+
+    StackedDrg2KiBV1_1_Feat_SyntheticPoRep,
+    StackedDrg8MiBV1_1_Feat_SyntheticPoRep,
+    StackedDrg512MiBV1_1_Feat_SyntheticPoRep,
+    StackedDrg32GiBV1_1_Feat_SyntheticPoRep,
+    StackedDrg64GiBV1_1_Feat_SyntheticPoRep,
+}
+
+impl RegisteredSealProof {
+    /// All registered seal proofs, ordered by their id.
+    pub const ALL: [RegisteredSealProof; 15] = [
+        RegisteredSealProof::StackedDrg2KiBV1,
+        RegisteredSealProof::StackedDrg8MiBV1,
+        RegisteredSealProof::StackedDrg512MiBV1,
+        RegisteredSealProof::StackedDrg32GiBV1,
+        RegisteredSealProof::StackedDrg64GiBV1,
+        RegisteredSealProof::StackedDrg2KiBV1_1,
+        RegisteredSealProof::StackedDrg8MiBV1_1,
+        RegisteredSealProof::StackedDrg512MiBV1_1,
+        RegisteredSealProof::StackedDrg32GiBV1_1,
+        RegisteredSealProof::StackedDrg64GiBV1_1,
+        RegisteredSealProof::StackedDrg2KiBV1_1_Feat_SyntheticPoRep,
+        RegisteredSealProof::StackedDrg8MiBV1_1_Feat_SyntheticPoRep,
+        RegisteredSealProof::StackedDrg512MiBV1_1_Feat_SyntheticPoRep,
+        RegisteredSealProof::StackedDrg32GiBV1_1_Feat_SyntheticPoRep,
+        RegisteredSealProof::StackedDrg64GiBV1_1_Feat_SyntheticPoRep,
+    ];
+
+    #[inline]
+    pub fn id(self) -> u64 {
+        self as u64
+    }
+
+    pub fn sector_size(self) -> SectorSize {
+        SectorSize(NETWORK_SECTOR_SIZES[self.id() as usize % NETWORK_SECTOR_SIZES.len()])
+    }
+
+    pub fn api_version(self) -> ApiVersion {
+        match self.id() as usize / NETWORK_SECTOR_SIZES.len() {
+            0 => ApiVersion::V1_0_0,
+            1 => ApiVersion::V1_1_0,
+            _ => ApiVersion::V1_2_0,
+        }
+    }
+
+    pub fn api_features(self) -> Vec<ApiFeature> {
+        match self.id() as usize / NETWORK_SECTOR_SIZES.len() {
+            0 | 1 => Vec::new(),
+            _ => vec![ApiFeature::SyntheticPoRep],
+        }
+    }
+
+    /// Returns the `porep_id`, which is the registered proof id followed by a nonce of zero.
+    pub fn porep_id(self) -> [u8; 32] {
+        let mut porep_id = [0u8; 32];
+        porep_id[..8].copy_from_slice(&self.id().to_le_bytes());
+        porep_id
+    }
+
+    /// Returns the PoRep configuration of this proof.
+    pub fn as_porep_config(self) -> PoRepConfig {
+        self.as_porep_config_with_constants(&ProtocolConstants::from_globals())
+            .expect("unknown sector size")
+    }
+
+    /// Returns the PoRep configuration of this proof, with the given protocol constants.
+    pub fn as_porep_config_with_constants(
+        self,
+        constants: &ProtocolConstants,
+    ) -> Result<PoRepConfig> {
+        let mut config = PoRepConfig::new_groth16_with_constants(
+            self.sector_size().into(),
+            self.porep_id(),
+            self.api_version(),
+            constants,
+        )?;
+        for feature in self.api_features() {
+            config.enable_feature(feature);
+        }
+        Ok(config)
+    }
+
+    /// Returns the Winning PoSt proof for sectors sealed with this proof.
+    pub fn registered_winning_post_proof(self) -> RegisteredPoStProof {
+        RegisteredPoStProof::ALL[self.id() as usize % NETWORK_SECTOR_SIZES.len()]
+    }
+
+    /// Returns the Window PoSt proof for sectors sealed with this proof, which depends on the
+    /// API version that is used for proving.
+    pub fn registered_window_post_proof(self, api_version: ApiVersion) -> RegisteredPoStProof {
+        let block = if api_version >= ApiVersion::V1_2_0 {
+            2
+        } else {
+            1
+        };
+        RegisteredPoStProof::ALL
+            [block * NETWORK_SECTOR_SIZES.len() + self.id() as usize % NETWORK_SECTOR_SIZES.len()]
+    }
+}
+
+impl TryFrom<u64> for RegisteredSealProof {
+    type Error = Error;
+
+    fn try_from(id: u64) -> Result<Self> {
+        RegisteredSealProof::ALL
+            .get(id as usize)
+            .copied()
+            .ok_or_else(|| format_err!("unknown registered seal proof id {}", id))
+    }
+}
+
+impl From<RegisteredSealProof> for PoRepConfig {
+    fn from(proof: RegisteredSealProof) -> Self {
+        proof.as_porep_config()
+    }
+}
+
+impl TryFrom<&PoRepConfig> for RegisteredSealProof {
+    type Error = Error;
+
+    fn try_from(config: &PoRepConfig) -> Result<Self> {
+        let mut id = [0u8; 8];
+        id.copy_from_slice(&config.porep_id[..8]);
+        let proof = RegisteredSealProof::try_from(u64::from_le_bytes(id))?;
+
+        ensure!(
+            proof.porep_id() == config.porep_id,
+            "porep_id {:?} has a non-zero nonce",
+            config.porep_id
+        );
+        ensure!(
+            proof.sector_size() == config.sector_size,
+            "sector size {} does not match {:?}",
+            u64::from(config.sector_size),
+            proof
+        );
+        ensure!(
+            proof.api_version() == config.api_version,
+            "api version {} does not match {:?}",
+            config.api_version,
+            proof
+        );
+        let features = proof.api_features();
+        ensure!(
+            features.len() == config.api_features.len()
+                && features
+                    .iter()
+                    .all(|feature| config.feature_enabled(*feature)),
+            "api features {:?} do not match {:?}",
+            config.api_features,
+            proof
+        );
+
+        Ok(proof)
+    }
+}
+
+/// The PoSt proofs that are registered on the network, the discriminant is the registered proof
+/// id.
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[repr(u64)]
+pub enum RegisteredPoStProof {
+    StackedDrgWinning2KiBV1,
+    StackedDrgWinning8MiBV1,
+    StackedDrgWinning512MiBV1,
+    StackedDrgWinning32GiBV1,
+    StackedDrgWinning64GiBV1,
+
+    StackedDrgWindow2KiBV1,
+    StackedDrgWindow8MiBV1,
+    StackedDrgWindow512MiBV1,
+    StackedDrgWindow32GiBV1,
+    StackedDrgWindow64GiBV1,
+
+    StackedDrgWindow2KiBV1_2,
+    StackedDrgWindow8MiBV1_2,
+    StackedDrgWindow512MiBV1_2,
+    StackedDrgWindow32GiBV1_2,
+    StackedDrgWindow64GiBV1_2,
+}
+
+impl RegisteredPoStProof {
+    /// All registered PoSt proofs, ordered by their id.
+    pub const ALL: [RegisteredPoStProof; 15] = [
+        RegisteredPoStProof::StackedDrgWinning2KiBV1,
+        RegisteredPoStProof::StackedDrgWinning8MiBV1,
+        RegisteredPoStProof::StackedDrgWinning512MiBV1,
+        RegisteredPoStProof::StackedDrgWinning32GiBV1,
+        RegisteredPoStProof::StackedDrgWinning64GiBV1,
+        RegisteredPoStProof::StackedDrgWindow2KiBV1,
+        RegisteredPoStProof::StackedDrgWindow8MiBV1,
+        RegisteredPoStProof::StackedDrgWindow512MiBV1,
+        RegisteredPoStProof::StackedDrgWindow32GiBV1,
+        RegisteredPoStProof::StackedDrgWindow64GiBV1,
+        RegisteredPoStProof::StackedDrgWindow2KiBV1_2,
+        RegisteredPoStProof::StackedDrgWindow8MiBV1_2,
+        RegisteredPoStProof::StackedDrgWindow512MiBV1_2,
+        RegisteredPoStProof::StackedDrgWindow32GiBV1_2,
+        RegisteredPoStProof::StackedDrgWindow64GiBV1_2,
+    ];
+
+    #[inline]
+    pub fn id(self) -> u64 {
+        self as u64
+    }
+
+    pub fn sector_size(self) -> SectorSize {
+        SectorSize(NETWORK_SECTOR_SIZES[self.id() as usize % NETWORK_SECTOR_SIZES.len()])
+    }
+
+    pub fn typ(self) -> PoStType {
+        match self.id() as usize / NETWORK_SECTOR_SIZES.len() {
+            0 => PoStType::Winning,
+            _ => PoStType::Window,
+        }
+    }
+
+    pub fn api_version(self) -> ApiVersion {
+        match self.id() as usize / NETWORK_SECTOR_SIZES.len() {
+            0 | 1 => ApiVersion::V1_0_0,
+            _ => ApiVersion::V1_2_0,
+        }
+    }
+
+    /// Returns the PoSt configuration of this proof.
+    pub fn as_post_config(self) -> PoStConfig {
+        self.as_post_config_with_constants(&ProtocolConstants::from_globals())
+            .expect("unknown sector size")
+    }
+
+    /// Returns the PoSt configuration of this proof, with the given protocol constants.
+    pub fn as_post_config_with_constants(
+        self,
+        constants: &ProtocolConstants,
+    ) -> Result<PoStConfig> {
+        let sector_size = self.sector_size();
+        let (challenge_count, sector_count) = match self.typ() {
+            PoStType::Winning => (WINNING_POST_CHALLENGE_COUNT, WINNING_POST_SECTOR_COUNT),
+            PoStType::Window => (
+                WINDOW_POST_CHALLENGE_COUNT,
+                constants.window_post_sector_count(u64::from(sector_size))?,
+            ),
+        };
+
+        Ok(PoStConfig {
+            sector_size,
+            challenge_count,
+            sector_count,
+            typ: self.typ(),
+            priority: false,
+            api_version: self.api_version(),
+        })
+    }
+}
+
+impl TryFrom<u64> for RegisteredPoStProof {
+    type Error = Error;
+
+    fn try_from(id: u64) -> Result<Self> {
+        RegisteredPoStProof::ALL
+            .get(id as usize)
+            .copied()
+            .ok_or_else(|| format_err!("unknown registered post proof id {}", id))
+    }
+}
+
+impl From<RegisteredPoStProof> for PoStConfig {
+    fn from(proof: RegisteredPoStProof) -> Self {
+        proof.as_post_config()
+    }
+}
+
+/// Winning PoSt has a single registered proof per sector size, which is used with any API
+/// version. Window PoSt with API version 1.2.0 or later maps to the `V1_2` proofs.
+impl TryFrom<&PoStConfig> for RegisteredPoStProof {
+    type Error = Error;
+
+    fn try_from(config: &PoStConfig) -> Result<Self> {
+        let sector_size = u64::from(config.sector_size);
+        let index = NETWORK_SECTOR_SIZES
+            .iter()
+            .position(|size| *size == sector_size)
+            .ok_or_else(|| {
+                format_err!("no registered post proof for sector size {}", sector_size)
+            })?;
+        let block = match config.typ {
+            PoStType::Winning => 0,
+            PoStType::Window if config.api_version >= ApiVersion::V1_2_0 => 2,
+            PoStType::Window => 1,
+        };
+
+        Ok(RegisteredPoStProof::ALL[block * NETWORK_SECTOR_SIZES.len() + index])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use storage_proofs_core::is_legacy_porep_id;
+
+    #[test]
+    fn test_registered_seal_proofs() {
+        for (id, proof) in RegisteredSealProof::ALL.iter().copied().enumerate() {
+            assert_eq!(proof.id(), id as u64);
+            assert_eq!(
+                RegisteredSealProof::try_from(id as u64).expect("unknown id"),
+                proof
+            );
+            assert_eq!(
+                is_legacy_porep_id(proof.porep_id()),
+                proof.api_version() == ApiVersion::V1_0_0
+            );
+
+            let config = PoRepConfig::from(proof);
+            assert_eq!(
+                RegisteredSealProof::try_from(&config).expect("config does not round trip"),
+                proof
+            );
+        }
+        assert!(RegisteredSealProof::try_from(15).is_err());
+
+        let proof = RegisteredSealProof::StackedDrg32GiBV1_1_Feat_SyntheticPoRep;
+        assert_eq!(u64::from(proof.sector_size()), SECTOR_SIZE_32_GIB);
+        assert_eq!(proof.api_version(), ApiVersion::V1_2_0);
+        assert!(proof
+            .as_porep_config()
+            .feature_enabled(ApiFeature::SyntheticPoRep));
+        assert_eq!(
+            proof.registered_winning_post_proof(),
+            RegisteredPoStProof::StackedDrgWinning32GiBV1
+        );
+        assert_eq!(
+            proof.registered_window_post_proof(ApiVersion::V1_2_0),
+            RegisteredPoStProof::StackedDrgWindow32GiBV1_2
+        );
+        assert_eq!(
+            proof.registered_window_post_proof(ApiVersion::V1_1_0),
+            RegisteredPoStProof::StackedDrgWindow32GiBV1
+        );
+    }
+
+    #[test]
+    fn test_mismatched_porep_config() {
+        let mut config = RegisteredSealProof::StackedDrg2KiBV1_1.as_porep_config();
+        config.api_version = ApiVersion::V1_0_0;
+        assert!(RegisteredSealProof::try_from(&config).is_err());
+
+        let mut config = RegisteredSealProof::StackedDrg2KiBV1_1.as_porep_config();
+        config.enable_feature(ApiFeature::SyntheticPoRep);
+        assert!(RegisteredSealProof::try_from(&config).is_err());
+
+        let mut config = RegisteredSealProof::StackedDrg2KiBV1_1.as_porep_config();
+        config.porep_id[8] = 1;
+        assert!(RegisteredSealProof::try_from(&config).is_err());
+    }
+
+    #[test]
+    fn test_registered_post_proofs() {
+        for (id, proof) in RegisteredPoStProof::ALL.iter().copied().enumerate() {
+            assert_eq!(proof.id(), id as u64);
+            assert_eq!(
+                RegisteredPoStProof::try_from(id as u64).expect("unknown id"),
+                proof
+            );
+
+            let config = PoStConfig::from(proof);
+            assert_eq!(config.typ, proof.typ());
+            assert_eq!(
+                RegisteredPoStProof::try_from(&config).expect("config does not round trip"),
+                proof
+            );
+        }
+        assert!(RegisteredPoStProof::try_from(15).is_err());
+
+        let config = RegisteredPoStProof::StackedDrgWindow64GiBV1_2.as_post_config();
+        assert_eq!(config.sector_count, 2300);
+        assert_eq!(config.challenge_count, WINDOW_POST_CHALLENGE_COUNT);
+    }
+
+    #[test]
+    fn test_protocol_constants() {
+        let mut constants = ProtocolConstants::default();
+        constants.porep_partitions.insert(SECTOR_SIZE_2_KIB, 2);
+        constants.layers.insert(SECTOR_SIZE_2_KIB, 4);
+        constants
+            .window_post_sector_count
+            .insert(SECTOR_SIZE_2_KIB, 5);
+
+        let config = RegisteredSealProof::StackedDrg2KiBV1_1
+            .as_porep_config_with_constants(&constants)
+            .expect("unknown sector size");
+        assert_eq!(usize::from(config.partitions), 2);
+        assert_eq!(config.layers, 4);
+        let setup_params = crate::parameters::setup_params(&config).expect("invalid config");
+        assert_eq!(setup_params.layer_challenges.layers(), 4);
+
+        let config = RegisteredPoStProof::StackedDrgWindow2KiBV1_2
+            .as_post_config_with_constants(&constants)
+            .expect("unknown sector size");
+        assert_eq!(config.sector_count, 5);
+
+        // The process-global defaults are not affected.
+        assert_eq!(
+            ProtocolConstants::from_globals(),
+            ProtocolConstants::default()
+        );
+        let config = RegisteredSealProof::StackedDrg2KiBV1_1.as_porep_config();
+        assert_eq!(usize::from(config.partitions), 1);
+        assert_eq!(config.layers, 2);
+
+        constants.layers.remove(&SECTOR_SIZE_2_KIB);
+        assert!(RegisteredSealProof::StackedDrg2KiBV1_1
+            .as_porep_config_with_constants(&constants)
+            .is_err());
+    }
+}
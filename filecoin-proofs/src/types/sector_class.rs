@@ -1,6 +1,13 @@
+use std::convert::TryFrom;
+
+use anyhow::{Error, Result};
 use storage_proofs_core::api_version::ApiVersion;
+use storage_proofs_core::challenge_domain::ChallengeDomain;
 
-use crate::types::{PoRepConfig, PoRepProofPartitions, SectorSize};
+use crate::{
+    constants::ProtocolConstants,
+    types::{PieceHasher, PoRepConfig, PoRepProofPartitions, SectorSize},
+};
 
 #[derive(Clone, Copy, Debug)]
 pub struct SectorClass {
@@ -10,20 +17,28 @@ pub struct SectorClass {
     pub api_version: ApiVersion,
 }
 
-impl From<SectorClass> for PoRepConfig {
-    fn from(x: SectorClass) -> Self {
+impl TryFrom<SectorClass> for PoRepConfig {
+    type Error = Error;
+
+    fn try_from(x: SectorClass) -> Result<Self> {
         let SectorClass {
             sector_size,
             partitions,
             porep_id,
             api_version,
         } = x;
-        PoRepConfig {
+        let constants = ProtocolConstants::from_globals();
+        let sector_bytes = u64::from(sector_size);
+        Ok(PoRepConfig {
             sector_size,
             partitions,
+            layers: constants.layers(sector_bytes)?,
+            minimum_challenges: constants.porep_minimum_challenges(sector_bytes)?,
             porep_id,
             api_version,
+            challenge_domain: ChallengeDomain::Mainnet,
+            piece_hasher: PieceHasher::Sha256,
             api_features: vec![],
-        }
+        })
     }
 }
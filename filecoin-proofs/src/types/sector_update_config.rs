@@ -9,6 +9,8 @@ pub struct SectorUpdateConfig {
     pub nodes_count: usize,
     pub update_partitions: UpdateProofPartitions,
     pub h: usize,
+    /// The number of layers of the sector key, see `PoRepConfig::layers`.
+    pub layers: usize,
 }
 
 impl SectorUpdateConfig {
@@ -20,6 +22,7 @@ impl SectorUpdateConfig {
             nodes_count,
             update_partitions: UpdateProofPartitions::from(partition_count(nodes_count)),
             h: h_default(nodes_count),
+            layers: porep_config.layers,
         }
     }
 }
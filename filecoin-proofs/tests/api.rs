@@ -2,53 +2,75 @@ use std::collections::BTreeMap;
 use std::fs::{metadata, read_dir, remove_file, File, OpenOptions};
 use std::io::{self, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use anyhow::{ensure, Context, Error, Result};
 use bellperson::groth16;
 use bincode::serialize;
 use blstrs::{Bls12, Scalar as Fr};
 use ff::Field;
-use filecoin_hashers::Hasher;
+use filecoin_hashers::{poseidon::PoseidonHasher, Hasher};
 use filecoin_proofs::{
-    add_piece, aggregate_seal_commit_proofs, clear_cache, clear_synthetic_proofs, compute_comm_d,
-    decode_from, decode_from_range, encode_into, fauxrep_aux, generate_empty_sector_update_proof,
+    add_piece, aggregate_seal_commit_proofs, check_sector, clear_cache, clear_synthetic_proofs,
+    codec::{self, ProofKind},
+    compute_comm_d, convert_window_post_vanilla_proofs, decode_from, decode_from_range,
+    encode_into, encode_into_with_configs, fauxrep_aux, generate_empty_sector_update_proof,
     generate_empty_sector_update_proof_with_vanilla, generate_fallback_sector_challenges,
-    generate_partition_proofs, generate_piece_commitment, generate_single_partition_proof,
-    generate_single_vanilla_proof, generate_single_window_post_with_vanilla, generate_synth_proofs,
-    generate_tree_c, generate_tree_r_last, generate_window_post, generate_window_post_with_vanilla,
-    generate_winning_post, generate_winning_post_sector_challenge,
+    generate_partition_proofs, generate_partition_proofs_with_configs, generate_piece_commitment,
+    generate_single_empty_sector_update_proof_with_vanilla, generate_single_partition_proof,
+    generate_single_partition_proof_with_inputs, generate_single_vanilla_proof,
+    generate_single_vanilla_proof_with_backend, generate_single_window_post_with_vanilla,
+    generate_synth_proofs, generate_tree_c, generate_tree_r_last, generate_window_post,
+    generate_window_post_in_sub_partitions, generate_window_post_with_vanilla,
+    generate_winning_post, generate_winning_post_batch, generate_winning_post_sector_challenge,
     generate_winning_post_with_vanilla, get_num_partition_for_fallback_post, get_seal_inputs,
-    merge_window_post_partition_proofs, remove_encoded_data, seal_commit_phase1,
-    seal_commit_phase2, seal_pre_commit_phase1, seal_pre_commit_phase2, unseal_range,
-    validate_cache_for_commit, validate_cache_for_precommit_phase2,
-    verify_aggregate_seal_commit_proofs, verify_empty_sector_update_proof, verify_partition_proofs,
-    verify_seal, verify_single_partition_proof, verify_window_post, verify_winning_post,
-    Commitment, DefaultTreeDomain, MerkleTreeTrait, PaddedBytesAmount, PieceInfo, PoRepConfig,
-    PoStConfig, PoStType, PrivateReplicaInfo, ProverId, PublicReplicaInfo, SealCommitOutput,
-    SealPreCommitOutput, SealPreCommitPhase1Output, SectorShape16KiB, SectorShape2KiB,
-    SectorShape32KiB, SectorShape4KiB, SectorUpdateConfig, UnpaddedByteIndex, UnpaddedBytesAmount,
-    SECTOR_SIZE_16_KIB, SECTOR_SIZE_2_KIB, SECTOR_SIZE_32_KIB, SECTOR_SIZE_4_KIB,
-    WINDOW_POST_CHALLENGE_COUNT, WINDOW_POST_SECTOR_COUNT, WINNING_POST_CHALLENGE_COUNT,
-    WINNING_POST_SECTOR_COUNT,
+    get_seal_status, get_sector_update_partition_inputs,
+    merge_empty_sector_update_partition_proofs, merge_window_post_partition_proofs,
+    prefetch_fallback_post_challenges, prefetch_fallback_post_replicas,
+    public_inputs_for_empty_sector_update, public_inputs_for_window_post,
+    read_seal_commit_phase1_output, remove_encoded_data, seal_commit_phase1,
+    seal_commit_phase1_with_piece_hasher, seal_commit_phase1_with_staged_data, seal_commit_phase2,
+    seal_commit_phase2_with_piece_hasher, seal_pre_commit_phase1,
+    seal_pre_commit_phase1_without_tree_d, seal_pre_commit_phase2, unseal_range,
+    unseal_range_cached, validate_cache_for_commit, validate_cache_for_precommit_phase2,
+    validate_synth_proofs, verify_aggregate_seal_commit_proofs, verify_archived,
+    verify_batch_seal_infos, verify_empty_sector_update_proof,
+    verify_empty_sector_update_proof_with_key, verify_partition_proofs, verify_seal,
+    verify_seal_vanilla, verify_seal_with_piece_hasher, verify_single_partition_proof,
+    verify_window_post, verify_window_post_batch, verify_winning_post,
+    write_seal_commit_phase1_output, ArchivedProof, Commitment, DefaultTreeDomain, MerkleTreeTrait,
+    PaddedBytesAmount, PersistentAux, PieceHasher, PieceInfo, PoRepConfig, PoStConfig, PoStType,
+    PrivateReplicaInfo, ProverId, PublicReplicaInfo, RegisteredSealProof, SealCommitOutput,
+    SealPreCommitOutput, SealPreCommitPhase1Output, SealPublicInputs, SealVerifyInfo,
+    SectorLifecycle, SectorShape16KiB, SectorShape2KiB, SectorShape32KiB, SectorShape4KiB,
+    SectorState, SectorUpdateConfig, SectorUpdatePartitionInputs, Ticket, UnpaddedByteIndex,
+    UnpaddedBytesAmount, UnsealCache, WindowPoStVerifyInfo, WinningPoStInputs, SECTOR_SIZE_16_KIB,
+    SECTOR_SIZE_2_KIB, SECTOR_SIZE_32_KIB, SECTOR_SIZE_4_KIB, WINDOW_POST_CHALLENGE_COUNT,
+    WINDOW_POST_SECTOR_COUNT, WINNING_POST_CHALLENGE_COUNT, WINNING_POST_SECTOR_COUNT,
 };
 use fr32::bytes_into_fr;
+use generic_array::typenum::Unsigned;
 use log::info;
 use memmap2::MmapOptions;
 use merkletree::store::StoreConfig;
-use rand::{random, Rng, SeedableRng};
+use rand::{random, rngs::OsRng, Rng, SeedableRng};
 use rand_xorshift::XorShiftRng;
 use sha2::{Digest, Sha256};
 use storage_proofs_core::{
     api_version::{ApiFeature, ApiVersion},
     cache_key::CacheKey,
     is_legacy_porep_id,
-    merkle::get_base_tree_count,
+    merkle::{get_base_tree_count, MerkleProof},
     sector::SectorId,
-    util::NODE_SIZE,
+    util::{default_rows_to_discard, NODE_SIZE},
 };
-use storage_proofs_update::constants::TreeRHasher;
+use storage_proofs_porep::stacked::{
+    SYNTHETIC_POREP_VANILLA_PROOFS_EXT, SYNTHETIC_POREP_VANILLA_PROOFS_KEY,
+};
+use storage_proofs_update::{constants::TreeRHasher, EmptySectorUpdate, PrivateInputs};
 use tempfile::{tempdir, NamedTempFile, TempDir};
 
+use filecoin_proofs::caches::{get_empty_sector_update_verifying_key, get_post_verifying_key};
 use filecoin_proofs::constants::MAX_LEGACY_REGISTERED_SEAL_PROOF_ID;
 
 #[cfg(feature = "big-tests")]
@@ -154,6 +176,19 @@ fn test_seal_lifecycle_upgrade_2kib_base_8() -> Result<()> {
     Ok(())
 }
 
+#[test]
+#[ignore]
+fn test_sector_lifecycle_2kib_base_8() -> Result<()> {
+    for registered_proof in [
+        RegisteredSealProof::StackedDrg2KiBV1_1,
+        RegisteredSealProof::StackedDrg2KiBV1_1_Feat_SyntheticPoRep,
+    ] {
+        sector_lifecycle::<SectorShape2KiB>(registered_proof)?;
+    }
+
+    Ok(())
+}
+
 #[test]
 #[ignore]
 fn test_seal_lifecycle_4kib_base_8() -> Result<()> {
@@ -407,8 +442,10 @@ fn test_seal_lifecycle_upgrade_64gib_top_8_8_2_v1_1() -> Result<()> {
 fn seal_lifecycle<Tree: 'static + MerkleTreeTrait>(porep_config: &PoRepConfig) -> Result<()> {
     let mut rng = XorShiftRng::from_seed(TEST_SEED);
     let prover_fr: DefaultTreeDomain = Fr::random(&mut rng).into();
-    let mut prover_id = [0u8; 32];
-    prover_id.copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
+    let mut prover_id = ProverId::default();
+    prover_id
+        .0
+        .copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
 
     info!(
         "Creating seal proof with ApiVersion {} and PoRep ID {:?}",
@@ -425,8 +462,10 @@ fn seal_lifecycle_upgrade<Tree: 'static + MerkleTreeTrait<Hasher = TreeRHasher>>
 ) -> Result<()> {
     let mut rng = &mut XorShiftRng::from_seed(TEST_SEED);
     let prover_fr: DefaultTreeDomain = Fr::random(&mut rng).into();
-    let mut prover_id = [0u8; 32];
-    prover_id.copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
+    let mut prover_id = ProverId::default();
+    prover_id
+        .0
+        .copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
 
     info!(
         "Creating seal proof for upgrade with ApiVersion {}",
@@ -481,6 +520,70 @@ fn test_seal_proof_aggregation_257_2kib_porep_id_v1_1_base_8() -> Result<()> {
     aggregate_proofs::<SectorShape2KiB>(SECTOR_SIZE_2_KIB, &porep_id, proofs_to_aggregate)
 }
 
+#[test]
+#[ignore]
+fn test_aggregate_proof_codec_roundtrip_2kib() -> Result<()> {
+    let mut rng = XorShiftRng::from_seed(TEST_SEED);
+    let prover_id = ProverId::from([7; 32]);
+    let porep_id = ARBITRARY_POREP_ID_V1_1_0;
+    let api_version = ApiVersion::V1_1_0;
+    let aggregate_version = groth16::aggregate::AggregateVersion::V2;
+
+    let (commit_output, commit_inputs, seed, comm_r) =
+        create_seal_for_aggregation::<_, SectorShape2KiB>(
+            &mut rng,
+            SECTOR_SIZE_2_KIB,
+            prover_id,
+            &porep_id,
+            api_version,
+        )?;
+    let config = porep_config(SECTOR_SIZE_2_KIB, porep_id, api_version);
+
+    // The commit proof decodes into one Groth16 proof per partition, and nothing else.
+    let partitions = usize::from(config.partitions);
+    let partition_proofs = codec::decode_partition_proofs(&commit_output.proof, partitions)?;
+    assert_eq!(
+        codec::encode_groth16_proofs(&partition_proofs)?,
+        commit_output.proof
+    );
+    assert!(codec::decode_partition_proofs(&commit_output.proof, partitions + 1).is_err());
+
+    let comm_rs = vec![comm_r, comm_r];
+    let seeds = vec![seed, seed];
+    let aggregate_proof = aggregate_seal_commit_proofs::<SectorShape2KiB>(
+        &config,
+        &comm_rs,
+        &seeds,
+        &[commit_output.clone(), commit_output],
+        aggregate_version,
+    )?;
+
+    // Decoding and encoding the aggregate proof, directly and framed, must give back the same
+    // bytes, which still verify.
+    let decoded = codec::decode_aggregate_proof(&aggregate_proof)?;
+    let reencoded = codec::encode_aggregate_proof(&decoded)?;
+    assert_eq!(reencoded, aggregate_proof);
+
+    let framed = codec::encode(ProofKind::AggregateSeal, &reencoded);
+    let (kind, unframed) = codec::decode(&framed)?;
+    assert_eq!(kind, ProofKind::AggregateSeal);
+    assert_eq!(unframed, &aggregate_proof[..]);
+
+    assert!(verify_aggregate_seal_commit_proofs::<SectorShape2KiB>(
+        &config,
+        unframed.to_vec(),
+        &comm_rs,
+        &seeds,
+        [commit_inputs.clone(), commit_inputs].concat(),
+        aggregate_version,
+    )?);
+
+    // A truncated aggregate proof must be rejected when decoding.
+    assert!(codec::decode_aggregate_proof(&aggregate_proof[..aggregate_proof.len() - 1]).is_err());
+
+    Ok(())
+}
+
 #[test]
 #[ignore]
 fn test_seal_proof_aggregation_2_4kib_porep_id_v1_1_base_8() -> Result<()> {
@@ -568,8 +671,10 @@ fn aggregate_proofs<Tree: 'static + MerkleTreeTrait>(
 ) -> Result<()> {
     let mut rng = XorShiftRng::from_seed(TEST_SEED);
     let prover_fr: DefaultTreeDomain = Fr::random(&mut rng).into();
-    let mut prover_id = [0u8; 32];
-    prover_id.copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
+    let mut prover_id = ProverId::default();
+    prover_id
+        .0
+        .copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
 
     let api_version = ApiVersion::V1_1_0;
     let aggregate_versions = vec![
@@ -713,6 +818,374 @@ fn test_resumable_seal_v1_1() {
     run_resumable_seal::<SectorShape2KiB>(false, 1, &porep_id, ApiVersion::V1_1_0);
 }
 
+#[test]
+fn test_check_sector_2kib() -> Result<()> {
+    fil_logger::maybe_init();
+
+    let mut rng = XorShiftRng::from_seed(TEST_SEED);
+    let porep_id = to_porep_id_verified(5, ApiVersion::V1_1_0);
+    let porep_config = PoRepConfig::new_groth16(SECTOR_SIZE_2_KIB, porep_id, ApiVersion::V1_1_0);
+    let (mut piece_file, _) = generate_piece_file(SECTOR_SIZE_2_KIB)?;
+    let sealed_sector_file = NamedTempFile::new()?;
+    let cache_dir = tempdir()?;
+
+    let (_, phase1_output) = run_seal_pre_commit_phase1::<SectorShape2KiB>(
+        &porep_config,
+        rng.gen(),
+        rng.gen::<u64>().into(),
+        rng.gen(),
+        &cache_dir,
+        &mut piece_file,
+        &sealed_sector_file,
+    )?;
+    let SealPreCommitOutput { comm_r, comm_d } = seal_pre_commit_phase2(
+        &porep_config,
+        phase1_output,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+    )?;
+
+    let check = |comm_d: Commitment| {
+        check_sector::<SectorShape2KiB>(
+            &porep_config,
+            comm_r,
+            comm_d,
+            sealed_sector_file.path(),
+            cache_dir.path(),
+            1.0,
+        )
+    };
+
+    let result = check(comm_d)?;
+    assert!(result.is_ok(), "{:?}", result.diagnostics);
+    assert!(result.comm_d_checked);
+    assert_eq!(
+        result.challenges_checked,
+        SECTOR_SIZE_2_KIB as usize / NODE_SIZE
+    );
+    assert!(!check([1; 32])?.is_ok());
+
+    // The replica is all that is needed once the cache is cleared.
+    clear_cache::<SectorShape2KiB>(cache_dir.path())?;
+    let result = check(comm_d)?;
+    assert!(result.is_ok(), "{:?}", result.diagnostics);
+    assert!(!result.comm_d_checked);
+
+    let mut replica = OpenOptions::new()
+        .write(true)
+        .open(sealed_sector_file.path())?;
+    replica.seek(SeekFrom::Start(NODE_SIZE as u64))?;
+    replica.write_all(&[0; NODE_SIZE])?;
+    drop(replica);
+    let result = check(comm_d)?;
+    assert!(!result.is_ok());
+    assert!(result
+        .diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.contains("leaf 1")));
+
+    // A missing p_aux is reported rather than failing the check.
+    remove_file(cache_dir.path().join(CacheKey::PAux.to_string()))?;
+    let result = check(comm_d)?;
+    assert!(!result.is_ok());
+    assert!(result
+        .diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.contains("p_aux")));
+
+    Ok(())
+}
+
+#[test]
+fn test_seal_without_tree_d_2kib() -> Result<()> {
+    fil_logger::maybe_init();
+
+    let mut rng = XorShiftRng::from_seed(TEST_SEED);
+    let porep_id = to_porep_id_verified(5, ApiVersion::V1_1_0);
+    let porep_config = PoRepConfig::new_groth16(SECTOR_SIZE_2_KIB, porep_id, ApiVersion::V1_1_0);
+    let prover_id: ProverId = rng.gen();
+    let sector_id: SectorId = rng.gen::<u64>().into();
+    let ticket: Ticket = rng.gen();
+    let seed: Ticket = rng.gen();
+
+    let (mut piece_file, _) = generate_piece_file(SECTOR_SIZE_2_KIB)?;
+    let piece_size = porep_config.unpadded_bytes_amount();
+    let piece_info = generate_piece_commitment(piece_file.as_file_mut(), piece_size)?;
+    piece_file.as_file_mut().rewind()?;
+    let mut staged_sector_file = NamedTempFile::new()?;
+    add_piece(&mut piece_file, &mut staged_sector_file, piece_size, &[])?;
+    let piece_infos = vec![piece_info];
+
+    let sealed_sector_file = NamedTempFile::new()?;
+    let cache_dir = tempdir()?;
+    let tree_d_path = StoreConfig::data_path(cache_dir.path(), &CacheKey::CommDTree.to_string());
+
+    let phase1_output = seal_pre_commit_phase1_without_tree_d::<_, _, _, SectorShape2KiB>(
+        &porep_config,
+        cache_dir.path(),
+        staged_sector_file.path(),
+        sealed_sector_file.path(),
+        prover_id,
+        sector_id,
+        ticket,
+        &piece_infos,
+    )?;
+    assert_eq!(
+        phase1_output.comm_d,
+        compute_comm_d(porep_config.sector_size, &piece_infos)?
+    );
+    assert!(phase1_output.without_tree_d);
+    let status = get_seal_status::<SectorShape2KiB>(
+        &porep_config,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+    )?;
+    assert!(status.without_tree_d && !status.tree_d);
+    assert!(status.sector_state() == SectorState::PreCommit1);
+    validate_cache_for_precommit_phase2(
+        cache_dir.path(),
+        staged_sector_file.path(),
+        &phase1_output,
+    )?;
+
+    // tree_d is only skipped if the phase1 output records it was not built.
+    let mut with_tree_d = phase1_output.clone();
+    with_tree_d.without_tree_d = false;
+    assert!(validate_cache_for_precommit_phase2(
+        cache_dir.path(),
+        staged_sector_file.path(),
+        &with_tree_d,
+    )
+    .is_err());
+    let pre_commit = seal_pre_commit_phase2(
+        &porep_config,
+        phase1_output,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+    )?;
+    assert!(!tree_d_path.exists(), "tree_d must not be built");
+    validate_cache_for_commit::<_, _, SectorShape2KiB>(
+        cache_dir.path(),
+        sealed_sector_file.path(),
+    )?;
+
+    // Without tree_d, the data can only be opened from the staged sector.
+    assert!(seal_commit_phase1::<_, SectorShape2KiB>(
+        &porep_config,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+        prover_id,
+        sector_id,
+        ticket,
+        seed,
+        pre_commit.clone(),
+        &piece_infos,
+    )
+    .is_err());
+
+    // The vanilla proofs are verified by commit phase 1.
+    let phase1_output = seal_commit_phase1_with_staged_data::<_, SectorShape2KiB>(
+        &porep_config,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+        staged_sector_file.path(),
+        prover_id,
+        sector_id,
+        ticket,
+        seed,
+        pre_commit.clone(),
+        &piece_infos,
+    )?;
+    assert_eq!(phase1_output.comm_d, pre_commit.comm_d);
+    assert_eq!(phase1_output.comm_r, pre_commit.comm_r);
+    assert!(!tree_d_path.exists(), "tree_d must not be built");
+
+    Ok(())
+}
+
+#[test]
+fn test_seal_commit_phase1_output_framed_2kib() -> Result<()> {
+    fil_logger::maybe_init();
+
+    let mut rng = XorShiftRng::from_seed(TEST_SEED);
+    let porep_id = to_porep_id_verified(5, ApiVersion::V1_1_0);
+    let porep_config = PoRepConfig::new_groth16(SECTOR_SIZE_2_KIB, porep_id, ApiVersion::V1_1_0);
+    let prover_id: ProverId = rng.gen();
+    let sector_id: SectorId = rng.gen::<u64>().into();
+    let ticket: Ticket = rng.gen();
+    let seed: Ticket = rng.gen();
+
+    let (mut piece_file, _) = generate_piece_file(SECTOR_SIZE_2_KIB)?;
+    let piece_size = porep_config.unpadded_bytes_amount();
+    let piece_info = generate_piece_commitment(piece_file.as_file_mut(), piece_size)?;
+    piece_file.as_file_mut().rewind()?;
+    let mut staged_sector_file = NamedTempFile::new()?;
+    add_piece(&mut piece_file, &mut staged_sector_file, piece_size, &[])?;
+    let piece_infos = vec![piece_info];
+
+    let sealed_sector_file = NamedTempFile::new()?;
+    let cache_dir = tempdir()?;
+    let phase1_output = seal_pre_commit_phase1::<_, _, _, SectorShape2KiB>(
+        &porep_config,
+        cache_dir.path(),
+        staged_sector_file.path(),
+        sealed_sector_file.path(),
+        prover_id,
+        sector_id,
+        ticket,
+        &piece_infos,
+    )?;
+    let pre_commit = seal_pre_commit_phase2(
+        &porep_config,
+        phase1_output,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+    )?;
+    let phase1_output = seal_commit_phase1::<_, SectorShape2KiB>(
+        &porep_config,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+        prover_id,
+        sector_id,
+        ticket,
+        seed,
+        pre_commit,
+        &piece_infos,
+    )?;
+    assert!(phase1_output
+        .vanilla_proofs
+        .iter()
+        .all(|proofs| !proofs.is_empty()));
+
+    let encoded = serialize(&phase1_output)?;
+    let mut framed = Vec::new();
+    write_seal_commit_phase1_output(&phase1_output, &mut framed)?;
+    assert_ne!(framed, encoded);
+
+    // The framed format round trips.
+    let from_framed = read_seal_commit_phase1_output::<SectorShape2KiB, _>(&framed[..])?;
+    assert_eq!(serialize(&from_framed)?, encoded);
+
+    // Outputs encoded with bincode as a whole can still be read.
+    let from_bincode = read_seal_commit_phase1_output::<SectorShape2KiB, _>(&encoded[..])?;
+    assert_eq!(serialize(&from_bincode)?, encoded);
+
+    // Truncated outputs are rejected.
+    assert!(
+        read_seal_commit_phase1_output::<SectorShape2KiB, _>(&framed[..framed.len() - 1]).is_err()
+    );
+    assert!(read_seal_commit_phase1_output::<SectorShape2KiB, _>(&framed[..4]).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_seal_with_poseidon_piece_hasher_2kib() -> Result<()> {
+    fil_logger::maybe_init();
+
+    let mut rng = XorShiftRng::from_seed(TEST_SEED);
+    let porep_id = to_porep_id_verified(5, ApiVersion::V1_1_0);
+    let porep_config = PoRepConfig::new_groth16(SECTOR_SIZE_2_KIB, porep_id, ApiVersion::V1_1_0)
+        .with_piece_hasher(PieceHasher::Poseidon);
+    let prover_id: ProverId = rng.gen();
+    let sector_id: SectorId = rng.gen::<u64>().into();
+    let ticket: Ticket = rng.gen();
+    let seed: Ticket = rng.gen();
+
+    let (mut piece_file, _) = generate_piece_file(SECTOR_SIZE_2_KIB)?;
+    let piece_size = porep_config.unpadded_bytes_amount();
+    let piece_info = generate_piece_commitment(piece_file.as_file_mut(), piece_size)?;
+    piece_file.as_file_mut().rewind()?;
+    let mut staged_sector_file = NamedTempFile::new()?;
+    add_piece(&mut piece_file, &mut staged_sector_file, piece_size, &[])?;
+    let piece_infos = vec![piece_info];
+
+    let sealed_sector_file = NamedTempFile::new()?;
+    let cache_dir = tempdir()?;
+    let phase1_output = seal_pre_commit_phase1::<_, _, _, SectorShape2KiB>(
+        &porep_config,
+        cache_dir.path(),
+        staged_sector_file.path(),
+        sealed_sector_file.path(),
+        prover_id,
+        sector_id,
+        ticket,
+        &piece_infos,
+    )?;
+    // comm_d is the root of a poseidon tree_d, not the one of the (sha256) piece commitments.
+    assert_ne!(
+        phase1_output.comm_d,
+        compute_comm_d(porep_config.sector_size, &piece_infos)?
+    );
+    let pre_commit = seal_pre_commit_phase2(
+        &porep_config,
+        phase1_output,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+    )?;
+    let comm_d = pre_commit.comm_d;
+    let comm_r = pre_commit.comm_r;
+
+    // The functions of the default piece hasher reject the sector.
+    assert!(seal_commit_phase1::<_, SectorShape2KiB>(
+        &porep_config,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+        prover_id,
+        sector_id,
+        ticket,
+        seed,
+        pre_commit.clone(),
+        &piece_infos,
+    )
+    .is_err());
+
+    let phase1_output = seal_commit_phase1_with_piece_hasher::<_, SectorShape2KiB, PoseidonHasher>(
+        &porep_config,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+        prover_id,
+        sector_id,
+        ticket,
+        seed,
+        pre_commit,
+        &piece_infos,
+    )?;
+    let commit_output = seal_commit_phase2_with_piece_hasher::<SectorShape2KiB, PoseidonHasher>(
+        &porep_config,
+        phase1_output,
+        prover_id,
+        sector_id,
+    )?;
+
+    assert!(verify_seal_with_piece_hasher::<
+        SectorShape2KiB,
+        PoseidonHasher,
+    >(
+        &porep_config,
+        comm_r,
+        comm_d,
+        prover_id,
+        sector_id,
+        ticket,
+        seed,
+        &commit_output.proof,
+    )?);
+    assert!(verify_seal::<SectorShape2KiB>(
+        &porep_config,
+        comm_r,
+        comm_d,
+        prover_id,
+        sector_id,
+        ticket,
+        seed,
+        &commit_output.proof,
+    )
+    .is_err());
+
+    Ok(())
+}
+
 /// Create a seal, delete a layer and resume
 ///
 /// The current code works on two layers only. The `layer_to_delete` specifies (zero-based) which
@@ -728,8 +1201,10 @@ fn run_resumable_seal<Tree: 'static + MerkleTreeTrait>(
     let sector_size = SECTOR_SIZE_2_KIB;
     let mut rng = XorShiftRng::from_seed(TEST_SEED);
     let prover_fr: DefaultTreeDomain = Fr::random(&mut rng).into();
-    let mut prover_id = [0u8; 32];
-    prover_id.copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
+    let mut prover_id = ProverId::default();
+    prover_id
+        .0
+        .copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
 
     let (mut piece_file, piece_bytes) =
         generate_piece_file(sector_size).expect("failed to generate piece file");
@@ -863,8 +1338,10 @@ fn test_winning_post_empty_sector_challenge() -> Result<()> {
     let mut rng = XorShiftRng::from_seed(TEST_SEED);
 
     let prover_fr: DefaultTreeDomain = Fr::random(&mut rng).into();
-    let mut prover_id = [0u8; 32];
-    prover_id.copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
+    let mut prover_id = ProverId::default();
+    prover_id
+        .0
+        .copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
 
     let sector_count = 0;
     let sector_size = SECTOR_SIZE_2_KIB;
@@ -909,8 +1386,10 @@ fn winning_post<Tree: 'static + MerkleTreeTrait>(
     let mut rng = XorShiftRng::from_seed(TEST_SEED);
 
     let prover_fr: DefaultTreeDomain = Fr::random(&mut rng).into();
-    let mut prover_id = [0u8; 32];
-    prover_id.copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
+    let mut prover_id = ProverId::default();
+    prover_id
+        .0
+        .copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
 
     let porep_id = match api_version {
         ApiVersion::V1_0_0 => ARBITRARY_POREP_ID_V1_0_0,
@@ -994,6 +1473,29 @@ fn winning_post<Tree: 'static + MerkleTreeTrait>(
         verify_winning_post::<Tree>(&config, &randomness, &pub_replicas[..], prover_id, &proof)?;
     assert!(valid, "proof did not verify");
 
+    //
+    // 3) Batched with another miner, whose failure must not affect this proof.
+    let inputs = [
+        WinningPoStInputs {
+            randomness,
+            prover_id,
+            replicas: &priv_replicas[..],
+        },
+        WinningPoStInputs {
+            randomness,
+            prover_id: ProverId::from([0xff; 32]),
+            replicas: &priv_replicas[..],
+        },
+    ];
+    let mut proofs = generate_winning_post_batch::<Tree>(&config, &inputs)?;
+    assert_eq!(proofs.len(), 2);
+    assert!(proofs[1].is_err(), "invalid prover id was accepted");
+    let proof = proofs.remove(0)?;
+
+    let valid =
+        verify_winning_post::<Tree>(&config, &randomness, &pub_replicas[..], prover_id, &proof)?;
+    assert!(valid, "proof did not verify");
+
     // Make files writeable again, so that the temporary directory can be removed.
     set_readonly_flag(replica.path(), false);
     set_readonly_flag(cache_dir.path(), false);
@@ -1215,13 +1717,24 @@ fn test_window_post_partition_matching_2kib_base_8() -> Result<()> {
 }
 
 #[allow(clippy::iter_kv_map)]
-fn partition_window_post<Tree: 'static + MerkleTreeTrait>(
+fn partition_window_post<Tree>(
     sector_size: u64,
     total_sector_count: usize,
     sector_count: usize,
     fake: bool,
     api_version: ApiVersion,
-) -> Result<()> {
+) -> Result<()>
+where
+    Tree: 'static
+        + MerkleTreeTrait<
+            Proof = MerkleProof<
+                <Tree as MerkleTreeTrait>::Hasher,
+                <Tree as MerkleTreeTrait>::Arity,
+                <Tree as MerkleTreeTrait>::SubTreeArity,
+                <Tree as MerkleTreeTrait>::TopTreeArity,
+            >,
+        >,
+{
     use anyhow::anyhow;
 
     let mut rng = XorShiftRng::from_seed(TEST_SEED);
@@ -1231,8 +1744,10 @@ fn partition_window_post<Tree: 'static + MerkleTreeTrait>(
     let mut priv_replicas = BTreeMap::new();
 
     let prover_fr: <Tree::Hasher as Hasher>::Domain = Fr::random(&mut rng).into();
-    let mut prover_id = [0u8; 32];
-    prover_id.copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
+    let mut prover_id = ProverId::default();
+    prover_id
+        .0
+        .copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
 
     let porep_id = match api_version {
         ApiVersion::V1_0_0 => ARBITRARY_POREP_ID_V1_0_0,
@@ -1283,6 +1798,15 @@ fn partition_window_post<Tree: 'static + MerkleTreeTrait>(
         prover_id,
     )?;
 
+    let prefetched = prefetch_fallback_post_challenges(&config, &priv_replicas, &challenges, 2)?;
+    assert!(prefetched > 0);
+    assert!(prefetched <= u64::from(sector_size) * total_sector_count as u64);
+
+    // The odd sectors are proven from their prefetched replica data.
+    let prefetched_replicas =
+        prefetch_fallback_post_replicas(&config, &priv_replicas, &challenges, 2)?;
+    assert_eq!(prefetched_replicas.len(), total_sector_count);
+
     let num_sectors_per_chunk = config.sector_count;
     let mut proofs = Vec::new();
 
@@ -1315,7 +1839,22 @@ fn partition_window_post<Tree: 'static + MerkleTreeTrait>(
                 sector_challenges,
             )?;
 
-            vanilla_proofs.push(single_proof);
+            let prefetched_replica = &prefetched_replicas[sector_id];
+            assert!(prefetched_replica.prefetched_bytes() > 0);
+            let prefetched_proof = generate_single_vanilla_proof_with_backend::<Tree, _>(
+                &config,
+                *sector_id,
+                sector,
+                Arc::clone(prefetched_replica),
+                sector_challenges,
+            )?;
+            assert_eq!(serialize(&prefetched_proof)?, serialize(&single_proof)?);
+
+            if u64::from(*sector_id) % 2 == 1 {
+                vanilla_proofs.push(prefetched_proof);
+            } else {
+                vanilla_proofs.push(single_proof);
+            }
         }
 
         let proof = generate_single_window_post_with_vanilla(
@@ -1334,6 +1873,23 @@ fn partition_window_post<Tree: 'static + MerkleTreeTrait>(
         verify_window_post::<Tree>(&config, &randomness, &pub_replicas, prover_id, &final_proof)?;
     assert!(valid, "proofs did not verify");
 
+    // Proving the partitions in sub-partitions of a single sector results in a valid proof as well.
+    let sub_partitioned_proof = generate_window_post_in_sub_partitions::<Tree>(
+        &config,
+        &randomness,
+        &priv_replicas,
+        prover_id,
+        1,
+    )?;
+    let valid = verify_window_post::<Tree>(
+        &config,
+        &randomness,
+        &pub_replicas,
+        prover_id,
+        &sub_partitioned_proof,
+    )?;
+    assert!(valid, "sub-partitioned proofs did not verify");
+
     Ok(())
 }
 
@@ -1365,8 +1921,10 @@ fn window_post<Tree: 'static + MerkleTreeTrait>(
     let mut priv_faulty_replicas = BTreeMap::new();
 
     let prover_fr: <Tree::Hasher as Hasher>::Domain = Fr::random(&mut rng).into();
-    let mut prover_id = [0u8; 32];
-    prover_id.copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
+    let mut prover_id = ProverId::default();
+    prover_id
+        .0
+        .copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
 
     let porep_id = match api_version {
         ApiVersion::V1_0_0 => ARBITRARY_POREP_ID_V1_0_0,
@@ -1433,6 +1991,41 @@ fn window_post<Tree: 'static + MerkleTreeTrait>(
     let valid = verify_window_post::<Tree>(&config, &randomness, &pub_replicas, prover_id, &proof)?;
     assert!(valid, "proof did not verify");
 
+    let archived =
+        ArchivedProof::window_post::<Tree>(&config, &randomness, &pub_replicas, prover_id, &proof)?;
+    let archived = ArchivedProof::from_bytes(&archived.to_bytes()?)?;
+    assert!(verify_archived(&archived)?, "archived proof did not verify");
+
+    // A batch with an invalid and a malformed proof identifies them.
+    let mut wrong_randomness = randomness;
+    wrong_randomness[0] ^= 1;
+    let infos = [
+        (randomness, &proof[..]),
+        (wrong_randomness, &proof[..]),
+        (randomness, &proof[..proof.len() / 2]),
+    ]
+    .map(|(randomness, proof)| WindowPoStVerifyInfo {
+        randomness,
+        prover_id,
+        replicas: &pub_replicas,
+        proof,
+    });
+    let results = verify_window_post_batch::<Tree>(&config, &infos)?;
+    assert!(matches!(results[0], Ok(true)), "valid proof did not verify");
+    assert!(matches!(results[1], Ok(false)), "invalid proof verified");
+    assert!(results[2].is_err(), "malformed proof did not fail");
+
+    // The public inputs reproduce the input packing of the verifier.
+    let inputs =
+        public_inputs_for_window_post::<Tree>(&config, &randomness, &pub_replicas, prover_id)?;
+    let groth_proofs = groth16::Proof::read_many(&proof, inputs.len())?;
+    let groth_proofs: Vec<_> = groth_proofs.iter().collect();
+    let verifying_key = get_post_verifying_key::<Tree>(&config)?;
+    assert!(
+        groth16::verify_proofs_batch(&verifying_key, &mut OsRng, &groth_proofs, &inputs)?,
+        "proof did not verify with the extracted public inputs"
+    );
+
     // 2)
     let replica_sectors = priv_replicas
         .iter()
@@ -1462,6 +2055,73 @@ fn window_post<Tree: 'static + MerkleTreeTrait>(
         vanilla_proofs.push(single_proof);
     }
 
+    // Regrouping the vanilla proofs into partitions of another size reuses all challenges.
+    let mut regrouped_config = config.clone();
+    regrouped_config.sector_count = std::cmp::max(1, sector_count / 2);
+    let regrouped = convert_window_post_vanilla_proofs::<Tree>(
+        &regrouped_config,
+        &randomness,
+        prover_id,
+        &replica_sectors,
+        &vanilla_proofs,
+    )?;
+    assert!(regrouped.missing_challenges().is_empty());
+    assert_eq!(
+        regrouped.reused_challenge_count(),
+        total_sector_count * config.challenge_count
+    );
+    assert_eq!(
+        serialize(&regrouped.into_vanilla_proofs()?)?,
+        serialize(&vanilla_proofs)?
+    );
+
+    // With more challenges per sector, only the ones that aren't covered need to be proven.
+    let mut extended_config = config.clone();
+    extended_config.challenge_count += 1;
+    let mut extended = convert_window_post_vanilla_proofs::<Tree>(
+        &extended_config,
+        &randomness,
+        prover_id,
+        &replica_sectors,
+        &vanilla_proofs,
+    )?;
+    if api_version == ApiVersion::V1_2_0 {
+        assert_eq!(
+            extended.reused_challenge_count(),
+            total_sector_count * config.challenge_count
+        );
+    }
+    for (sector_id, missing) in extended.missing_challenges() {
+        let proof = generate_single_vanilla_proof::<Tree>(
+            &extended_config,
+            sector_id,
+            &priv_replicas[&sector_id],
+            &missing,
+        )?;
+        extended.add_proof(&proof)?;
+    }
+    let extended_challenges = generate_fallback_sector_challenges::<Tree>(
+        &extended_config,
+        &randomness,
+        &replica_sectors,
+        prover_id,
+    )?;
+    let expected = priv_replicas
+        .iter()
+        .map(|(sector_id, replica)| {
+            generate_single_vanilla_proof::<Tree>(
+                &extended_config,
+                *sector_id,
+                replica,
+                &extended_challenges[sector_id],
+            )
+        })
+        .collect::<Result<Vec<_>>>()?;
+    assert_eq!(
+        serialize(&extended.into_vanilla_proofs()?)?,
+        serialize(&expected)?
+    );
+
     let proof =
         generate_window_post_with_vanilla::<Tree>(&config, &randomness, prover_id, vanilla_proofs)?;
 
@@ -1542,6 +2202,95 @@ fn generate_piece_file(sector_size: u64) -> Result<(NamedTempFile, Vec<u8>)> {
     Ok((piece_file, piece_bytes))
 }
 
+fn stage_piece_file(sector_size: u64) -> Result<(NamedTempFile, PieceInfo)> {
+    let (mut piece_file, _piece_bytes) = generate_piece_file(sector_size)?;
+    let number_of_bytes_in_piece = UnpaddedBytesAmount::from(PaddedBytesAmount(sector_size));
+
+    let piece_info = generate_piece_commitment(piece_file.as_file_mut(), number_of_bytes_in_piece)?;
+    piece_file.as_file_mut().rewind()?;
+
+    let mut staged_sector_file = NamedTempFile::new()?;
+    add_piece(
+        &mut piece_file,
+        &mut staged_sector_file,
+        number_of_bytes_in_piece,
+        &[],
+    )?;
+
+    Ok((staged_sector_file, piece_info))
+}
+
+fn sector_lifecycle<Tree: 'static + MerkleTreeTrait<Hasher = TreeRHasher>>(
+    registered_proof: RegisteredSealProof,
+) -> Result<()> {
+    fil_logger::maybe_init();
+
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let sector_size = u64::from(registered_proof.sector_size());
+    let (staged_sector_file, piece_info) = stage_piece_file(sector_size)?;
+    let sealed_sector_file = NamedTempFile::new()?;
+    let cache_dir = tempdir().expect("failed to create temp dir");
+    let state_dir = tempdir().expect("failed to create temp dir");
+    let state_path = state_dir.path().join("lifecycle.json");
+
+    let mut lifecycle = SectorLifecycle::<Tree>::new(
+        registered_proof,
+        rng.gen::<u64>().into(),
+        rng.gen(),
+        rng.gen(),
+        staged_sector_file.path().to_path_buf(),
+        cache_dir.path().to_path_buf(),
+        sealed_sector_file.path().to_path_buf(),
+        vec![piece_info],
+    );
+
+    lifecycle.pre_commit_phase1()?;
+    lifecycle.save(&state_path)?;
+
+    // Resume from the persisted state.
+    let mut lifecycle = SectorLifecycle::<Tree>::load(&state_path)?;
+    ensure!(
+        lifecycle.state() == SectorState::PreCommit1,
+        "unexpected state after loading"
+    );
+    ensure!(
+        lifecycle.commit_phase2().is_err(),
+        "commit phase 2 must not run before pre-commit phase 2"
+    );
+
+    lifecycle.pre_commit_phase2()?;
+    lifecycle.wait_seed()?;
+    lifecycle.commit_phase1(rng.gen())?;
+    lifecycle.commit_phase2()?;
+    ensure!(lifecycle.verify()?, "seal proof failed to verify");
+    ensure!(
+        lifecycle.state() == SectorState::Proven,
+        "sector must be proven"
+    );
+    lifecycle.save(&state_path)?;
+    let mut lifecycle = SectorLifecycle::<Tree>::load(&state_path)?;
+
+    // Upgrade the sector, the new replica must have the size of the sector.
+    let (new_staged_sector_file, new_piece_info) = stage_piece_file(sector_size)?;
+    let new_sealed_sector_file = NamedTempFile::new()?;
+    new_sealed_sector_file.as_file().set_len(sector_size)?;
+    let new_cache_dir = tempdir().expect("failed to create temp dir");
+
+    lifecycle.encode_update(
+        new_sealed_sector_file.path().to_path_buf(),
+        new_cache_dir.path().to_path_buf(),
+        new_staged_sector_file.path(),
+        &[new_piece_info],
+    )?;
+    lifecycle.prove_update()?;
+    ensure!(
+        lifecycle.state() == SectorState::UpdateProven,
+        "sector must be updated"
+    );
+
+    Ok(())
+}
+
 fn porep_config(sector_size: u64, porep_id: [u8; 32], api_version: ApiVersion) -> PoRepConfig {
     PoRepConfig::new_groth16(sector_size, porep_id, api_version)
 }
@@ -1550,7 +2299,7 @@ fn run_seal_pre_commit_phase1<Tree: 'static + MerkleTreeTrait>(
     config: &PoRepConfig,
     prover_id: ProverId,
     sector_id: SectorId,
-    ticket: [u8; 32],
+    ticket: Ticket,
     cache_dir: &TempDir,
     mut piece_file: &mut NamedTempFile,
     sealed_sector_file: &NamedTempFile,
@@ -1597,11 +2346,18 @@ fn generate_proof<Tree: 'static + MerkleTreeTrait>(
     sealed_sector_file: &NamedTempFile,
     prover_id: ProverId,
     sector_id: SectorId,
-    ticket: [u8; 32],
-    seed: [u8; 32],
+    ticket: Ticket,
+    seed: Ticket,
     pre_commit_output: &SealPreCommitOutput,
     piece_infos: &[PieceInfo],
-) -> Result<(SealCommitOutput, Vec<Vec<Fr>>, [u8; 32], [u8; 32])> {
+) -> Result<(SealCommitOutput, Vec<Vec<Fr>>, Ticket, [u8; 32])> {
+    let status = get_seal_status::<Tree>(config, cache_dir_path, sealed_sector_file.path())?;
+    ensure!(
+        status.sector_state() == SectorState::PreCommit2 && status.labels_complete(),
+        "unexpected seal status after pre-commit: {:?}",
+        status
+    );
+
     if config.feature_enabled(ApiFeature::SyntheticPoRep) {
         info!("SyntheticPoRep is enabled");
         generate_synth_proofs::<_, Tree>(
@@ -1614,7 +2370,47 @@ fn generate_proof<Tree: 'static + MerkleTreeTrait>(
             pre_commit_output.clone(),
             piece_infos,
         )?;
+        validate_synth_proofs::<_, Tree>(
+            config,
+            cache_dir_path,
+            prover_id,
+            sector_id,
+            ticket,
+            pre_commit_output.clone(),
+            16,
+        )?;
+
+        let synth_proofs = std::fs::read(cache_dir_path.join(format!(
+            "{}.{}",
+            SYNTHETIC_POREP_VANILLA_PROOFS_KEY, SYNTHETIC_POREP_VANILLA_PROOFS_EXT
+        )))?;
+        let mut pub_inputs = SealPublicInputs {
+            comm_r: pre_commit_output.comm_r,
+            comm_d: pre_commit_output.comm_d,
+            prover_id,
+            sector_id,
+            ticket,
+            seed,
+        };
+        ensure!(
+            verify_seal_vanilla::<Tree>(config, &pub_inputs, &synth_proofs)?,
+            "synthetic vanilla proofs failed to verify"
+        );
+        pub_inputs.ticket.0[0] ^= 1;
+        ensure!(
+            !verify_seal_vanilla::<Tree>(config, &pub_inputs, &synth_proofs)?,
+            "synthetic vanilla proofs verified for the wrong replica id"
+        );
+
         clear_cache::<Tree>(cache_dir_path)?;
+        let status = get_seal_status::<Tree>(config, cache_dir_path, sealed_sector_file.path())?;
+        ensure!(
+            status.sector_state() == SectorState::PreCommit2
+                && status.synth_proofs
+                && !status.labels_complete(),
+            "unexpected seal status after generating synthetic proofs: {:?}",
+            status
+        );
     } else {
         info!("SyntheticPoRep is NOT enabled");
         validate_cache_for_commit::<_, _, Tree>(cache_dir_path, sealed_sector_file.path())?;
@@ -1657,6 +2453,7 @@ fn generate_proof<Tree: 'static + MerkleTreeTrait>(
         phase1_output.ticket,
         phase1_output.seed,
     )?;
+
     let result = seal_commit_phase2(config, phase1_output, prover_id, sector_id)?;
 
     Ok((result, inputs, seed, comm_r))
@@ -1669,8 +2466,8 @@ fn unseal<Tree: 'static + MerkleTreeTrait>(
     sealed_sector_file: &NamedTempFile,
     prover_id: ProverId,
     sector_id: SectorId,
-    ticket: [u8; 32],
-    seed: [u8; 32],
+    ticket: Ticket,
+    seed: Ticket,
     pre_commit_output: &SealPreCommitOutput,
     piece_infos: &[PieceInfo],
     piece_bytes: &[u8],
@@ -1703,6 +2500,30 @@ fn unseal<Tree: 'static + MerkleTreeTrait>(
     assert_eq!(contents.len(), 508);
     assert_eq!(&piece_bytes[508..508 + 508], &contents[..]);
 
+    // The second cached unseal is served without reading the sealed sector.
+    let unseal_cache_dir = tempdir()?;
+    let unseal_cache = UnsealCache::with_window_size(unseal_cache_dir.path(), 1 << 20, 256)?;
+    for sealed_sector in [
+        File::open(sealed_sector_file.path())?,
+        File::open("/dev/null")?,
+    ] {
+        let mut contents = Vec::new();
+        unseal_range_cached::<_, _, _, Tree>(
+            config,
+            cache_dir_path,
+            sealed_sector,
+            &mut contents,
+            prover_id,
+            sector_id,
+            comm_d,
+            ticket,
+            UnpaddedByteIndex(508),
+            UnpaddedBytesAmount(508),
+            &unseal_cache,
+        )?;
+        assert_eq!(&piece_bytes[508..508 + 508], &contents[..]);
+    }
+
     let computed_comm_d = compute_comm_d(config.sector_size, piece_infos)?;
 
     assert_eq!(
@@ -1721,6 +2542,39 @@ fn unseal<Tree: 'static + MerkleTreeTrait>(
         &commit_output.proof,
     )?;
     assert!(verified, "failed to verify valid seal");
+
+    let pub_inputs = SealPublicInputs {
+        comm_r,
+        comm_d,
+        prover_id,
+        sector_id,
+        ticket,
+        seed,
+    };
+    let archived = ArchivedProof::seal::<Tree>(config, pub_inputs, &commit_output.proof)?;
+    let archived = ArchivedProof::from_bytes(&archived.to_bytes()?)?;
+    assert!(
+        verify_archived(&archived)?,
+        "failed to verify archived seal"
+    );
+    let mut tampered = archived.clone();
+    tampered.proof_type = ProofKind::EmptySectorUpdate;
+    assert!(verify_archived(&tampered).is_err());
+
+    let info = SealVerifyInfo {
+        pub_inputs,
+        proof: commit_output.proof.clone(),
+    };
+    let mut invalid = info.clone();
+    invalid.pub_inputs.seed.0[0] ^= 1;
+    assert!(
+        verify_batch_seal_infos::<Tree>(config, &[info.clone(), info.clone()])?,
+        "failed to batch verify valid seals"
+    );
+    assert!(
+        !verify_batch_seal_infos::<Tree>(config, &[info, invalid])?,
+        "batch verified an invalid seal"
+    );
     Ok(())
 }
 
@@ -1731,8 +2585,8 @@ fn proof_and_unseal<Tree: 'static + MerkleTreeTrait>(
     sealed_sector_file: &NamedTempFile,
     prover_id: ProverId,
     sector_id: SectorId,
-    ticket: [u8; 32],
-    seed: [u8; 32],
+    ticket: Ticket,
+    seed: Ticket,
     pre_commit_output: SealPreCommitOutput,
     piece_infos: &[PieceInfo],
     piece_bytes: &[u8],
@@ -1851,7 +2705,7 @@ fn create_seal_for_aggregation<R: Rng, Tree: 'static + MerkleTreeTrait>(
     prover_id: ProverId,
     porep_id: &[u8; 32],
     api_version: ApiVersion,
-) -> Result<(SealCommitOutput, Vec<Vec<Fr>>, [u8; 32], [u8; 32])> {
+) -> Result<(SealCommitOutput, Vec<Vec<Fr>>, Ticket, [u8; 32])> {
     fil_logger::maybe_init();
 
     let (mut piece_file, _piece_bytes) = generate_piece_file(sector_size)?;
@@ -2149,6 +3003,84 @@ fn create_seal_for_upgrade<R: Rng, Tree: 'static + MerkleTreeTrait<Hasher = Tree
     )?;
     ensure!(proofs_are_valid, "Partition proofs failed to verify");
 
+    // The same update, but with the new trees stored under explicit, non-default names.
+    let custom_sealed_sector_file = NamedTempFile::new()?;
+    custom_sealed_sector_file
+        .as_file()
+        .set_len(new_replica_target_len)?;
+    let custom_cache_dir = tempdir().expect("failed to create temp dir");
+
+    let p_aux: PersistentAux<<TreeRHasher as Hasher>::Domain> = bincode::deserialize(
+        &std::fs::read(cache_dir.path().join(CacheKey::PAux.to_string()))?,
+    )?;
+    let mut comm_c = [0u8; 32];
+    let mut comm_r_last_old = [0u8; 32];
+    comm_c.copy_from_slice(AsRef::<[u8]>::as_ref(&p_aux.comm_c));
+    comm_r_last_old.copy_from_slice(AsRef::<[u8]>::as_ref(&p_aux.comm_r_last));
+
+    let base_tree_leafs = config.nodes_count / get_base_tree_count::<Tree>();
+    let rows_to_discard = default_rows_to_discard(base_tree_leafs, Tree::Arity::to_usize());
+    let tree_d_new_config = EmptySectorUpdate::<Tree>::new_tree_d_config(
+        config.nodes_count,
+        custom_cache_dir.path(),
+        "custom-tree-d",
+        0,
+    )?;
+    let tree_r_last_new_config = EmptySectorUpdate::<Tree>::new_tree_r_last_config(
+        config.nodes_count,
+        custom_cache_dir.path(),
+        "custom-tree-r-last",
+        rows_to_discard,
+    )?;
+    let tree_r_old_config = EmptySectorUpdate::<Tree>::new_tree_r_last_config(
+        config.nodes_count,
+        cache_dir.path(),
+        &CacheKey::CommRLastTree.to_string(),
+        rows_to_discard,
+    )?;
+
+    let custom_encoded = encode_into_with_configs::<Tree>(
+        porep_config,
+        tree_d_new_config.clone(),
+        tree_r_last_new_config.clone(),
+        comm_c,
+        comm_r_last_old,
+        custom_sealed_sector_file.path(),
+        sealed_sector_file.path(),
+        new_staged_sector_file.path(),
+        &new_piece_infos,
+    )?;
+    assert_eq!(custom_encoded.comm_r_new, encoded.comm_r_new);
+    assert_eq!(custom_encoded.comm_r_last_new, encoded.comm_r_last_new);
+    assert_eq!(custom_encoded.comm_d_new, encoded.comm_d_new);
+
+    let private_inputs = PrivateInputs {
+        comm_c: p_aux.comm_c,
+        tree_r_old_config,
+        old_replica_path: sealed_sector_file.path().to_path_buf(),
+        tree_d_new_config,
+        tree_r_new_config: tree_r_last_new_config,
+        replica_path: custom_sealed_sector_file.path().to_path_buf(),
+    };
+    let custom_partition_proofs = generate_partition_proofs_with_configs::<Tree>(
+        config,
+        comm_r,
+        encoded.comm_r_new,
+        encoded.comm_d_new,
+        &private_inputs,
+    )?;
+    let proofs_are_valid = verify_partition_proofs::<Tree>(
+        config,
+        &custom_partition_proofs,
+        comm_r,
+        encoded.comm_r_new,
+        encoded.comm_d_new,
+    )?;
+    ensure!(
+        proofs_are_valid,
+        "Partition proofs with explicit configs failed to verify"
+    );
+
     let proof = generate_empty_sector_update_proof_with_vanilla::<Tree>(
         porep_config,
         partition_proofs,
@@ -2184,6 +3116,97 @@ fn create_seal_for_upgrade<R: Rng, Tree: 'static + MerkleTreeTrait<Hasher = Tree
     )?;
     ensure!(valid, "Compound proof failed to verify");
 
+    let archived = ArchivedProof::update::<Tree>(
+        porep_config,
+        comm_r,
+        encoded.comm_r_new,
+        encoded.comm_d_new,
+        &proof.0,
+    )?;
+    let archived = ArchivedProof::from_bytes(&archived.to_bytes()?)?;
+    ensure!(
+        verify_archived(&archived)?,
+        "Archived update proof failed to verify"
+    );
+
+    // Prove every partition separately, with the inputs passed around serialized, as if the
+    // partitions were proven on different machines.
+    let partition_inputs = get_sector_update_partition_inputs::<Tree>(
+        config,
+        comm_r,
+        encoded.comm_r_new,
+        encoded.comm_d_new,
+        sealed_sector_file.path(),
+        cache_dir.path(),
+        new_sealed_sector_file.path(),
+        new_cache_dir.path(),
+    )?;
+    assert_eq!(
+        partition_inputs.len(),
+        usize::from(config.update_partitions)
+    );
+    let partition_snarks = partition_inputs
+        .iter()
+        .map(|inputs| {
+            let inputs: SectorUpdatePartitionInputs =
+                serde_json::from_slice(&serde_json::to_vec(inputs)?)?;
+            let vanilla_proof =
+                generate_single_partition_proof_with_inputs::<Tree>(config, &inputs)?;
+            generate_single_empty_sector_update_proof_with_vanilla::<Tree>(
+                porep_config,
+                inputs.partition_index,
+                vanilla_proof,
+                inputs.comm_r_old,
+                inputs.comm_r_new,
+                inputs.comm_d_new,
+            )
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let proof = merge_empty_sector_update_partition_proofs(porep_config, partition_snarks)?;
+    let valid = verify_empty_sector_update_proof::<Tree>(
+        porep_config,
+        &proof.0,
+        comm_r,
+        encoded.comm_r_new,
+        encoded.comm_d_new,
+    )?;
+    ensure!(valid, "Merged partition proofs failed to verify");
+
+    // Verification with an explicitly passed verifying key.
+    let verifying_key = get_empty_sector_update_verifying_key::<Tree>(porep_config)?;
+    let valid = verify_empty_sector_update_proof_with_key::<Tree>(
+        porep_config,
+        &verifying_key,
+        &proof.0,
+        comm_r,
+        encoded.comm_r_new,
+        encoded.comm_d_new,
+    )?;
+    ensure!(valid, "Proof failed to verify with explicit verifying key");
+
+    // The public inputs reproduce the input packing of the verifier.
+    let inputs = public_inputs_for_empty_sector_update::<Tree>(
+        porep_config,
+        comm_r,
+        encoded.comm_r_new,
+        encoded.comm_d_new,
+    )?;
+    let groth_proofs = groth16::Proof::read_many(&proof.0, inputs.len())?;
+    let groth_proofs: Vec<_> = groth_proofs.iter().collect();
+    ensure!(
+        groth16::verify_proofs_batch(&verifying_key, &mut OsRng, &groth_proofs, &inputs)?,
+        "Proof failed to verify with the extracted public inputs"
+    );
+    let valid = verify_empty_sector_update_proof_with_key::<Tree>(
+        porep_config,
+        &verifying_key,
+        &proof.0,
+        encoded.comm_r_new,
+        comm_r,
+        encoded.comm_d_new,
+    )?;
+    ensure!(!valid, "Proof verified with swapped commitments");
+
     let decoded_sector_file = NamedTempFile::new()?;
     // New replica (new_sealed_sector_file) is currently 0 bytes --
     // set a length here to ensure proper mmap later.  Lotus will
@@ -0,0 +1,83 @@
+use filecoin_proofs::{
+    capabilities, ProofType, RegisteredPoStProof, RegisteredSealProof, PUBLISHED_SECTOR_SIZES,
+    SECTOR_SIZE_2_KIB,
+};
+use storage_proofs_core::{
+    api_version::{ApiFeature, ApiVersion},
+    is_legacy_porep_id,
+};
+
+#[test]
+fn test_capabilities() {
+    let capabilities = capabilities();
+
+    assert_eq!(capabilities.api_versions, ApiVersion::ALL.to_vec());
+    assert_eq!(capabilities.sector_sizes, PUBLISHED_SECTOR_SIZES.to_vec());
+    for proof_type in ProofType::ALL {
+        assert!(capabilities.supports_proof_type(proof_type));
+    }
+    assert!(capabilities.supports_sector_size(SECTOR_SIZE_2_KIB));
+    assert!(!capabilities.supports_sector_size(1 << 20));
+
+    let synthetic = capabilities
+        .features
+        .iter()
+        .find(|support| support.feature == ApiFeature::SyntheticPoRep)
+        .expect("synthetic porep is not listed");
+    assert_eq!(synthetic.first_supported_version, ApiVersion::V1_2_0);
+    assert_eq!(synthetic.last_supported_version, None);
+}
+
+#[test]
+fn test_negotiate_api_version() {
+    let capabilities = capabilities();
+
+    assert_eq!(
+        capabilities.negotiate_api_version(&[]),
+        Some(ApiVersion::V1_2_0)
+    );
+    assert_eq!(
+        capabilities.negotiate_api_version(&[ApiFeature::SyntheticPoRep]),
+        Some(ApiVersion::V1_2_0)
+    );
+    assert!(!capabilities.supports_features(ApiVersion::V1_1_0, &[ApiFeature::SyntheticPoRep]));
+    assert!(capabilities.supports_features(ApiVersion::V1_1_0, &[]));
+}
+
+#[test]
+fn test_registered_seal_proofs() {
+    let capabilities = capabilities();
+    assert_eq!(capabilities.registered_seal_proofs.len(), 15);
+
+    for proof in &capabilities.registered_seal_proofs {
+        assert!(capabilities.supports_sector_size(proof.sector_size().into()));
+        assert!(capabilities.supports_features(proof.api_version(), &proof.api_features()));
+        assert_eq!(
+            is_legacy_porep_id(proof.porep_id()),
+            proof.api_version() == ApiVersion::V1_0_0
+        );
+    }
+
+    assert_eq!(
+        capabilities.registered_seal_proof(3),
+        Some(RegisteredSealProof::StackedDrg32GiBV1)
+    );
+    assert_eq!(
+        capabilities.registered_seal_proof(9),
+        Some(RegisteredSealProof::StackedDrg64GiBV1_1)
+    );
+    assert_eq!(
+        capabilities.registered_seal_proof(10),
+        Some(RegisteredSealProof::StackedDrg2KiBV1_1_Feat_SyntheticPoRep)
+    );
+    assert!(capabilities.registered_seal_proof(15).is_none());
+
+    assert_eq!(capabilities.registered_post_proofs.len(), 15);
+    for proof in &capabilities.registered_post_proofs {
+        assert!(capabilities.supports_sector_size(proof.sector_size().into()));
+    }
+    assert_eq!(
+        capabilities.registered_post_proof(14),
+        Some(RegisteredPoStProof::StackedDrgWindow64GiBV1_2)
+    );
+}
@@ -6,8 +6,9 @@ use blstrs::Scalar as Fr;
 use filecoin_proofs::{
     add_piece, commitment_from_fr,
     pieces::{
-        compute_comm_d, get_piece_alignment, get_piece_start_byte, piece_hash, verify_pieces,
-        zero_padding, EmptySource, PieceAlignment,
+        compute_comm_d, generate_piece_inclusion_proofs, get_piece_alignment, get_piece_start_byte,
+        piece_hash, verify_piece_inclusion, verify_pieces, zero_padding, EmptySource,
+        PieceAlignment,
     },
     Commitment, DataTree, DefaultPieceHasher, PaddedBytesAmount, PieceInfo, SectorSize,
     UnpaddedByteIndex, UnpaddedBytesAmount, DRG_DEGREE, EXP_DEGREE, TEST_SEED,
@@ -366,6 +367,47 @@ fn test_verify_random_pieces() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_piece_inclusion_proofs() -> Result<()> {
+    let sector_size = SectorSize(32 * 128);
+    let piece_sizes: Vec<UnpaddedBytesAmount> = [128, 512, 256, 1024, 1024]
+        .iter()
+        .map(|size| PaddedBytesAmount(*size).into())
+        .collect();
+    let (comm_d, piece_infos) = build_sector(&piece_sizes, sector_size)?;
+
+    let proofs = generate_piece_inclusion_proofs(sector_size, &piece_infos)?;
+    assert_eq!(proofs.len(), piece_infos.len());
+    // The pieces are aligned to their size, the second one starts at 512 bytes.
+    assert_eq!(proofs[1].position, 1);
+
+    for (piece_info, proof) in piece_infos.iter().zip(&proofs) {
+        assert!(verify_piece_inclusion(
+            &comm_d,
+            piece_info,
+            sector_size,
+            proof
+        )?);
+    }
+
+    let mut wrong_position = proofs[2].clone();
+    wrong_position.position ^= 1;
+    assert!(!verify_piece_inclusion(
+        &comm_d,
+        &piece_infos[2],
+        sector_size,
+        &wrong_position
+    )?);
+    assert!(!verify_piece_inclusion(
+        &comm_d,
+        &piece_infos[4],
+        sector_size,
+        &proofs[3]
+    )?);
+
+    Ok(())
+}
+
 fn build_sector(
     piece_sizes: &[UnpaddedBytesAmount],
     sector_size: SectorSize,
@@ -0,0 +1,85 @@
+use std::collections::BTreeMap;
+
+use filecoin_proofs::{
+    generate_fallback_post_challenges, PoStConfig, PoStType, ProverId, SectorShape2KiB,
+    SECTOR_SIZE_2_KIB,
+};
+use storage_proofs_core::{api_version::ApiVersion, sector::SectorId};
+
+const RANDOMNESS: [u8; 32] = [1; 32];
+const PROVER_ID: ProverId = ProverId([2; 32]);
+
+fn post_config(typ: PoStType, api_version: ApiVersion) -> PoStConfig {
+    let (sector_count, challenge_count) = match typ {
+        PoStType::Window => (2, 3),
+        PoStType::Winning => (1, 4),
+    };
+    PoStConfig {
+        sector_size: SECTOR_SIZE_2_KIB.into(),
+        sector_count,
+        challenge_count,
+        typ,
+        priority: false,
+        api_version,
+    }
+}
+
+fn expected(vectors: &[(u64, [u64; 3])]) -> BTreeMap<SectorId, Vec<u64>> {
+    vectors
+        .iter()
+        .map(|(id, challenges)| (SectorId::from(*id), challenges.to_vec()))
+        .collect()
+}
+
+#[test]
+fn test_window_post_challenge_vectors() {
+    let sector_set: Vec<SectorId> = [1u64, 5, 9].iter().copied().map(SectorId::from).collect();
+
+    // Before V1_2_0 the challenge index includes the index of the sector in the proof.
+    let legacy = expected(&[(1, [53, 22, 28]), (5, [47, 60, 51]), (9, [19, 18, 48])]);
+    let current = expected(&[(1, [53, 22, 28]), (5, [7, 33, 28]), (9, [20, 48, 24])]);
+
+    for (api_version, challenges) in [
+        (ApiVersion::V1_0_0, &legacy),
+        (ApiVersion::V1_1_0, &legacy),
+        (ApiVersion::V1_2_0, &current),
+    ] {
+        let config = post_config(PoStType::Window, api_version);
+        let generated = generate_fallback_post_challenges::<SectorShape2KiB>(
+            &config,
+            &RANDOMNESS,
+            PROVER_ID,
+            &sector_set,
+        )
+        .expect("failed to generate challenges");
+
+        assert_eq!(generated.sectors, sector_set, "{}", api_version);
+        assert_eq!(&generated.challenges, challenges, "{}", api_version);
+    }
+}
+
+#[test]
+fn test_winning_post_challenge_vectors() {
+    let sector_set: Vec<SectorId> = (100u64..110).map(SectorId::from).collect();
+
+    // With a single challenged sector the challenge indexes are the same for all versions.
+    for api_version in [ApiVersion::V1_0_0, ApiVersion::V1_1_0, ApiVersion::V1_2_0] {
+        let config = post_config(PoStType::Winning, api_version);
+        let generated = generate_fallback_post_challenges::<SectorShape2KiB>(
+            &config,
+            &RANDOMNESS,
+            PROVER_ID,
+            &sector_set,
+        )
+        .expect("failed to generate challenges");
+
+        let sector = SectorId::from(108);
+        assert_eq!(generated.sectors, vec![sector], "{}", api_version);
+        assert_eq!(
+            generated.challenges.get(&sector),
+            Some(&vec![18, 1, 59, 62]),
+            "{}",
+            api_version
+        );
+    }
+}
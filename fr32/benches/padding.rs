@@ -0,0 +1,66 @@
+use std::io::Read;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use fr32::{write_padded, write_unpadded, Fr32Reader};
+use rand::{thread_rng, RngCore};
+
+fn padding_benchmark(c: &mut Criterion) {
+    let mut rng = thread_rng();
+    let mut data = vec![0u8; 127 * 8192];
+    rng.fill_bytes(&mut data);
+
+    let mut padded = Vec::new();
+    write_padded(&data, &mut padded).unwrap();
+
+    let mut group = c.benchmark_group("padding");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+
+    group.bench_function("fr32-reader", |b| {
+        b.iter(|| {
+            let mut buf = Vec::with_capacity(padded.len());
+            Fr32Reader::new(&data[..]).read_to_end(&mut buf).unwrap();
+            black_box(buf)
+        })
+    });
+
+    // Reads of a single Fr32 at a time never take the bulk path.
+    group.bench_function("fr32-reader-32", |b| {
+        b.iter(|| {
+            let mut reader = Fr32Reader::new(&data[..]);
+            let mut buf = [0u8; 32];
+            while reader.read(&mut buf).unwrap() != 0 {
+                black_box(&buf);
+            }
+        })
+    });
+
+    group.bench_function("write-padded", |b| {
+        b.iter(|| {
+            let mut buf = Vec::with_capacity(padded.len());
+            write_padded(&data, &mut buf).unwrap();
+            black_box(buf)
+        })
+    });
+
+    group.bench_function("write-unpadded", |b| {
+        b.iter(|| {
+            let mut buf = Vec::with_capacity(data.len());
+            write_unpadded(&padded, &mut buf, 0, data.len()).unwrap();
+            black_box(buf)
+        })
+    });
+
+    // Not starting at a block boundary uses the bitwise unpadding.
+    group.bench_function("write-unpadded-unaligned", |b| {
+        b.iter(|| {
+            let mut buf = Vec::with_capacity(data.len());
+            write_unpadded(&padded, &mut buf, 1, data.len() - 1).unwrap();
+            black_box(buf)
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, padding_benchmark);
+criterion_main!(benches);
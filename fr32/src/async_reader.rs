@@ -0,0 +1,180 @@
+use std::cmp::min;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, ReadBuf};
+
+use crate::block::{pad_block, PADDED_BLOCK_SIZE, RAW_BLOCK_SIZE};
+use crate::reader::{div_ceil, IN_BITS_FR, OUT_BITS_FR};
+
+/// The async equivalent of `Fr32Reader`, it converts unpadded input into valid `Fr32` padded
+/// output without blocking the executor while waiting for the source.
+pub struct Fr32AsyncReader<R> {
+    /// The source being padded.
+    source: R,
+    /// Currently read block.
+    in_buffer: [u8; RAW_BLOCK_SIZE],
+    /// How many bytes of `in_buffer` were filled so far.
+    in_len: usize,
+    /// Currently writing out block.
+    out_buffer: [u8; PADDED_BLOCK_SIZE],
+    /// The current offset into the `out_buffer` in bytes.
+    out_offset: usize,
+    /// How many bytes of `out_buffer` are left to be read.
+    available_bytes: usize,
+    /// Did the source run out of data?
+    source_done: bool,
+}
+
+impl<R: AsyncRead + Unpin> Fr32AsyncReader<R> {
+    pub fn new(source: R) -> Self {
+        Fr32AsyncReader {
+            source,
+            in_buffer: [0; RAW_BLOCK_SIZE],
+            in_len: 0,
+            out_buffer: [0; PADDED_BLOCK_SIZE],
+            out_offset: 0,
+            available_bytes: 0,
+            source_done: false,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.source
+    }
+
+    /// Fills `in_buffer` from the source. Partially read blocks are kept across `Pending`.
+    fn poll_fill_in_buffer(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.source_done && self.in_len < RAW_BLOCK_SIZE {
+            let mut buf = ReadBuf::new(&mut self.in_buffer[self.in_len..]);
+            match Pin::new(&mut self.source).poll_read(cx, &mut buf) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Ready(Ok(())) => {
+                    let n = buf.filled().len();
+                    if n == 0 {
+                        self.source_done = true;
+                    }
+                    self.in_len += n;
+                }
+            }
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// Pads the block in `in_buffer`, making its Frs available.
+    fn process_block(&mut self) {
+        // Clear unfilled memory.
+        for val in &mut self.in_buffer[self.in_len..] {
+            *val = 0;
+        }
+
+        pad_block(&self.in_buffer, &mut self.out_buffer);
+        self.out_offset = 0;
+        self.available_bytes = div_ceil(self.in_len * 8, IN_BITS_FR) * (OUT_BITS_FR / 8);
+        self.in_len = 0;
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Fr32AsyncReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        target: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let start = target.filled().len();
+
+        while target.remaining() > 0 {
+            if this.available_bytes == 0 {
+                match this.poll_fill_in_buffer(cx) {
+                    // Hand out what was padded so far, the source will wake us up for the rest.
+                    Poll::Pending if target.filled().len() > start => break,
+                    Poll::Pending => return Poll::Pending,
+                    Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                    Poll::Ready(Ok(())) => {}
+                }
+
+                // All data was read from the source, no new data in the buffer.
+                if this.in_len == 0 {
+                    break;
+                }
+
+                this.process_block();
+            }
+
+            // Write out as many bytes as available and requested, unlike `Fr32Reader` this
+            // keeps track of partially read Frs.
+            let len = min(this.available_bytes, target.remaining());
+            let out_start = this.out_offset;
+            target.put_slice(&this.out_buffer[out_start..out_start + len]);
+            this.out_offset += len;
+            this.available_bytes -= len;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Read;
+
+    use rand::{Rng, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+    use tokio::io::AsyncReadExt;
+
+    use crate::Fr32Reader;
+
+    const TEST_SEED: [u8; 16] = [
+        0x59, 0x62, 0xbe, 0x5d, 0x76, 0x3d, 0x31, 0x8d, 0x17, 0xdb, 0x37, 0x32, 0x54, 0x06, 0xbc,
+        0xe5,
+    ];
+
+    #[tokio::test]
+    async fn test_async_reader_matches_reader() {
+        let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+        for len in &[1, 31, 32, 127, 128, 127 * 3 + 40, 127 * 64] {
+            let data: Vec<u8> = (0..*len).map(|_| rng.gen()).collect();
+
+            let mut expected = Vec::new();
+            Fr32Reader::new(&data[..])
+                .read_to_end(&mut expected)
+                .expect("in-memory read failed");
+
+            let mut padded = Vec::new();
+            Fr32AsyncReader::new(&data[..])
+                .read_to_end(&mut padded)
+                .await
+                .expect("in-memory read failed");
+            assert_eq!(padded, expected, "{}", len);
+
+            // A source handing out a few bytes at a time, with odd sized reads on top.
+            let (mut tx, rx) = tokio::io::duplex(13);
+            let writer = {
+                let data = data.clone();
+                tokio::spawn(async move {
+                    tokio::io::AsyncWriteExt::write_all(&mut tx, &data)
+                        .await
+                        .expect("write failed");
+                })
+            };
+            let mut reader = Fr32AsyncReader::new(rx);
+            let mut padded = Vec::new();
+            let mut buf = [0u8; 45];
+            loop {
+                let n = reader.read(&mut buf).await.expect("read failed");
+                if n == 0 {
+                    break;
+                }
+                padded.extend_from_slice(&buf[..n]);
+            }
+            writer.await.expect("writer failed");
+            assert_eq!(padded, expected, "{}", len);
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//! Padding and unpadding of whole blocks, i.e. 127 raw bytes <-> 4 `Fr32`s (128 bytes).
+//!
+//! All Frs of a block start at a different bit offset in the raw data (0, 254, 508 and 762),
+//! but the offsets are the same for every block. This allows to do the conversion with a fixed
+//! sequence of 128-bit shifts, without any per-bit bookkeeping. On x86_64 the batch functions
+//! are compiled a second time with AVX2 enabled, which is picked at runtime if the CPU supports
+//! it. NEON is always available on aarch64, so the default build is vectorized there already.
+
+/// Number of raw bytes in a block.
+pub(crate) const RAW_BLOCK_SIZE: usize = 127;
+/// Number of padded bytes in a block.
+pub(crate) const PADDED_BLOCK_SIZE: usize = 128;
+
+const MASK_SKIP_HIGH_2: u128 = u128::MAX >> 2;
+
+#[inline(always)]
+fn load(bytes: &[u8], index: usize) -> u128 {
+    let mut word = [0u8; 16];
+    word.copy_from_slice(&bytes[index * 16..(index + 1) * 16]);
+    u128::from_le_bytes(word)
+}
+
+#[inline(always)]
+fn store(bytes: &mut [u8], index: usize, word: u128) {
+    bytes[index * 16..(index + 1) * 16].copy_from_slice(&word.to_le_bytes());
+}
+
+/// Pads the 127 bytes of `raw` into the 128 bytes of `padded`.
+#[inline(always)]
+pub(crate) fn pad_block(raw: &[u8], padded: &mut [u8]) {
+    debug_assert_eq!(raw.len(), RAW_BLOCK_SIZE);
+    debug_assert_eq!(padded.len(), PADDED_BLOCK_SIZE);
+
+    let mut input = [0u8; PADDED_BLOCK_SIZE];
+    input[..RAW_BLOCK_SIZE].copy_from_slice(raw);
+    let r = [
+        load(&input, 0),
+        load(&input, 1),
+        load(&input, 2),
+        load(&input, 3),
+        load(&input, 4),
+        load(&input, 5),
+        load(&input, 6),
+        load(&input, 7),
+    ];
+
+    // 0..254
+    store(padded, 0, r[0]);
+    store(padded, 1, r[1] & MASK_SKIP_HIGH_2);
+    // 254..508
+    store(padded, 2, r[1] >> 126 | r[2] << 2);
+    store(padded, 3, (r[2] >> 126 | r[3] << 2) & MASK_SKIP_HIGH_2);
+    // 508..762
+    store(padded, 4, r[3] >> 124 | r[4] << 4);
+    store(padded, 5, (r[4] >> 124 | r[5] << 4) & MASK_SKIP_HIGH_2);
+    // 762..1016
+    store(padded, 6, r[5] >> 122 | r[6] << 6);
+    store(padded, 7, (r[6] >> 122 | r[7] << 6) & MASK_SKIP_HIGH_2);
+}
+
+/// Unpads the 128 bytes of `padded` into the 127 bytes of `raw`, the padding bits are ignored.
+#[inline(always)]
+pub(crate) fn unpad_block(padded: &[u8], raw: &mut [u8]) {
+    debug_assert_eq!(padded.len(), PADDED_BLOCK_SIZE);
+    debug_assert_eq!(raw.len(), RAW_BLOCK_SIZE);
+
+    let lo0 = load(padded, 0);
+    let hi0 = load(padded, 1) & MASK_SKIP_HIGH_2;
+    let lo1 = load(padded, 2);
+    let hi1 = load(padded, 3) & MASK_SKIP_HIGH_2;
+    let lo2 = load(padded, 4);
+    let hi2 = load(padded, 5) & MASK_SKIP_HIGH_2;
+    let lo3 = load(padded, 6);
+    let hi3 = load(padded, 7) & MASK_SKIP_HIGH_2;
+
+    let mut output = [0u8; PADDED_BLOCK_SIZE];
+    store(&mut output, 0, lo0);
+    store(&mut output, 1, hi0 | lo1 << 126);
+    store(&mut output, 2, lo1 >> 2 | hi1 << 126);
+    store(&mut output, 3, hi1 >> 2 | lo2 << 124);
+    store(&mut output, 4, lo2 >> 4 | hi2 << 124);
+    store(&mut output, 5, hi2 >> 4 | lo3 << 122);
+    store(&mut output, 6, lo3 >> 6 | hi3 << 122);
+    store(&mut output, 7, hi3 >> 6);
+    raw.copy_from_slice(&output[..RAW_BLOCK_SIZE]);
+}
+
+#[inline(always)]
+fn pad_blocks_generic(raw: &[u8], padded: &mut [u8]) {
+    for (raw, padded) in raw
+        .chunks_exact(RAW_BLOCK_SIZE)
+        .zip(padded.chunks_exact_mut(PADDED_BLOCK_SIZE))
+    {
+        pad_block(raw, padded);
+    }
+}
+
+#[inline(always)]
+fn unpad_blocks_generic(padded: &[u8], raw: &mut [u8]) {
+    for (padded, raw) in padded
+        .chunks_exact(PADDED_BLOCK_SIZE)
+        .zip(raw.chunks_exact_mut(RAW_BLOCK_SIZE))
+    {
+        unpad_block(padded, raw);
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn pad_blocks_avx2(raw: &[u8], padded: &mut [u8]) {
+    pad_blocks_generic(raw, padded)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn unpad_blocks_avx2(padded: &[u8], raw: &mut [u8]) {
+    unpad_blocks_generic(padded, raw)
+}
+
+/// Pads all full blocks of `raw` into `padded`, which must have room for as many blocks.
+pub(crate) fn pad_blocks(raw: &[u8], padded: &mut [u8]) {
+    debug_assert_eq!(raw.len() % RAW_BLOCK_SIZE, 0);
+    debug_assert_eq!(raw.len() / RAW_BLOCK_SIZE, padded.len() / PADDED_BLOCK_SIZE);
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            // Safety: the CPU supports AVX2.
+            return unsafe { pad_blocks_avx2(raw, padded) };
+        }
+    }
+    pad_blocks_generic(raw, padded)
+}
+
+/// Unpads all full blocks of `padded` into `raw`, which must have room for as many blocks.
+pub(crate) fn unpad_blocks(padded: &[u8], raw: &mut [u8]) {
+    debug_assert_eq!(padded.len() % PADDED_BLOCK_SIZE, 0);
+    debug_assert_eq!(raw.len() / RAW_BLOCK_SIZE, padded.len() / PADDED_BLOCK_SIZE);
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            // Safety: the CPU supports AVX2.
+            return unsafe { unpad_blocks_avx2(padded, raw) };
+        }
+    }
+    unpad_blocks_generic(padded, raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{Rng, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+
+    use crate::write_unpadded;
+
+    const TEST_SEED: [u8; 16] = [
+        0x59, 0x62, 0xbe, 0x5d, 0x76, 0x3d, 0x31, 0x8d, 0x17, 0xdb, 0x37, 0x32, 0x54, 0x06, 0xbc,
+        0xe5,
+    ];
+
+    #[test]
+    fn test_pad_unpad_blocks() {
+        let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+        let num_blocks = 9;
+        let raw: Vec<u8> = (0..num_blocks * RAW_BLOCK_SIZE)
+            .map(|_| rng.gen())
+            .collect();
+
+        let mut padded = vec![0u8; num_blocks * PADDED_BLOCK_SIZE];
+        pad_blocks(&raw, &mut padded);
+        for fr in padded.chunks(32) {
+            assert_eq!(fr[31] & 0b1100_0000, 0, "invalid Fr32");
+        }
+
+        let mut unpadded = vec![0u8; raw.len()];
+        unpad_blocks(&padded, &mut unpadded);
+        assert_eq!(unpadded, raw);
+
+        // Bits in the padding positions must not leak into the raw data.
+        for fr in padded.chunks_mut(32) {
+            fr[31] |= 0b1100_0000;
+        }
+        unpad_blocks(&padded, &mut unpadded);
+        assert_eq!(unpadded, raw);
+
+        // Must agree with the bitwise unpadding, which is used for unaligned offsets.
+        let mut expected = Vec::new();
+        write_unpadded(&padded, &mut expected, 1, raw.len() - 1).expect("unpad failure");
+        assert_eq!(&unpadded[1..], &expected[..]);
+    }
+}
@@ -1,7 +1,12 @@
+#[cfg(feature = "async")]
+mod async_reader;
+mod block;
 mod convert;
 mod padding;
 mod reader;
 
+#[cfg(feature = "async")]
+pub use async_reader::*;
 pub use convert::*;
 pub use padding::*;
 pub use reader::*;
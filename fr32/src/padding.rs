@@ -1,5 +1,11 @@
 use std::cmp::{min, Ordering};
-use std::io::{self, Error, ErrorKind, Write};
+use std::io::{self, Error, ErrorKind, Read, Write};
+
+use crate::block::{pad_blocks, unpad_blocks, PADDED_BLOCK_SIZE, RAW_BLOCK_SIZE};
+use crate::Fr32Reader;
+
+/// Number of blocks (un)padded at once by `write_padded` and `write_unpadded`.
+const BULK_BLOCKS: usize = 1024;
 
 /** PaddingMap represents a mapping between data and its padded equivalent.
 
@@ -179,6 +185,30 @@ pub fn to_padded_bytes(unpadded_bytes: usize) -> usize {
     FR32_PADDING_MAP.transform_byte_offset(unpadded_bytes, true)
 }
 
+/// Bit position of the unpadded byte at `unpadded_offset` in the padded layout.
+pub fn to_padded_bit_offset(unpadded_offset: u64) -> u64 {
+    let data_bits = FR32_PADDING_MAP.data_bits as u64;
+    let element_bits = FR32_PADDING_MAP.element_bits as u64;
+    let bits = unpadded_offset * 8;
+    (bits / data_bits) * element_bits + bits % data_bits
+}
+
+/// Offset of the padded byte which holds the first bit of the unpadded byte at
+/// `unpadded_offset`. Unlike `to_padded_bytes` this works for any offset, not only for sizes.
+pub fn to_padded_offset(unpadded_offset: u64) -> u64 {
+    to_padded_bit_offset(unpadded_offset) / 8
+}
+
+/// Offset of the first unpadded byte starting at or after the padded byte at `padded_offset`.
+/// This is the inverse of `to_padded_offset`.
+pub fn to_unpadded_offset(padded_offset: u64) -> u64 {
+    let data_bits = FR32_PADDING_MAP.data_bits as u64;
+    let element_bits = FR32_PADDING_MAP.element_bits as u64;
+    let bits = padded_offset * 8;
+    let data = (bits / element_bits) * data_bits + min(bits % element_bits, data_bits);
+    (data + 7) / 8
+}
+
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 // BitByte represents a size expressed in bytes extended
 // with bit precision, that is, not rounded.
@@ -541,24 +571,38 @@ fn clear_right_bits(byte: &mut u8, offset: usize) {
 
 /** Padding process.
 
-Read a `source` of raw byte-aligned data, pad it in a bit stream and
-write a byte-aligned version of it in the `target`. The `target` needs
-to implement (besides `Write`) the `Read` and `Seek` traits since the
-last byte written may be incomplete and will need to be rewritten.
-
-The reader will always be byte-aligned, the writer will operate with
-bit precision since we may have (when calling this function multiple
-times) a written `target` with extra bits (that need to be overwritten)
-and also incomplete data units.
-The ideal alignment scenario is for the writer to be positioned at the
-byte-aligned element boundary and just write whole chunks of `data_chunk_bits`
-(full data units) followed by its corresponding padding. To get there then we
-need to handle the potential bit-level misalignments:
-  1. extra bits: the last byte is only partially valid so we
-     need to get some bits from the `source` to overwrite them.
-  2. Incomplete data unit: we need to fill the rest of it and add the padding
-     to form a element that would position the writer at the desired boundary.
+Read a `source` of raw byte-aligned data, pad it and write the result to the
+`target`, producing the same output as reading the `source` through an
+`Fr32Reader`. Returns the number of bytes written.
+
+All full 127 byte blocks are padded in bulk, only a trailing incomplete
+block goes through the reader.
 **/
+pub fn write_padded<W: ?Sized>(source: &[u8], target: &mut W) -> io::Result<usize>
+where
+    W: Write,
+{
+    let full_blocks = source.len() / RAW_BLOCK_SIZE;
+    let mut written = 0;
+
+    let mut padded = vec![0u8; min(full_blocks, BULK_BLOCKS) * PADDED_BLOCK_SIZE];
+    for raw in source[..full_blocks * RAW_BLOCK_SIZE].chunks(BULK_BLOCKS * RAW_BLOCK_SIZE) {
+        let padded = &mut padded[..raw.len() / RAW_BLOCK_SIZE * PADDED_BLOCK_SIZE];
+        pad_blocks(raw, padded);
+        target.write_all(padded)?;
+        written += padded.len();
+    }
+
+    let rest = &source[full_blocks * RAW_BLOCK_SIZE..];
+    if !rest.is_empty() {
+        let mut padded = Vec::with_capacity(PADDED_BLOCK_SIZE);
+        Fr32Reader::new(rest).read_to_end(&mut padded)?;
+        target.write_all(&padded)?;
+        written += padded.len();
+    }
+
+    Ok(written)
+}
 
 // offset and num_bytes are based on the unpadded data, so
 // if [0, 1, ..., 255] was the original unpadded data, offset 3 and len 4 would return
@@ -589,17 +633,38 @@ where
         ));
     }
 
+    // Starting at a block boundary, all full blocks can be unpadded in bulk.
+    let mut written = 0;
+    let mut offset = offset;
+    let mut len = len;
+    if offset % RAW_BLOCK_SIZE == 0 {
+        let start = offset / RAW_BLOCK_SIZE * PADDED_BLOCK_SIZE;
+        let full_blocks = min(
+            len / RAW_BLOCK_SIZE,
+            source.len().saturating_sub(start) / PADDED_BLOCK_SIZE,
+        );
+        let padded_source = &source[start..start + full_blocks * PADDED_BLOCK_SIZE];
+
+        let mut raw = vec![0u8; min(full_blocks, BULK_BLOCKS) * RAW_BLOCK_SIZE];
+        for padded in padded_source.chunks(BULK_BLOCKS * PADDED_BLOCK_SIZE) {
+            let raw = &mut raw[..padded.len() / PADDED_BLOCK_SIZE * RAW_BLOCK_SIZE];
+            unpad_blocks(padded, raw);
+            target.write_all(raw)?;
+            written += raw.len();
+        }
+        offset += written;
+        len -= written;
+        if len == 0 {
+            return Ok(written);
+        }
+    }
+
     // In order to optimize alignment in the common case of writing from an aligned start,
     // we should make the chunk a multiple of 128 (4 full elements in the padded layout).
     // n was hand-tuned to do reasonably well in the benchmarks.
     let n = 1000;
     let chunk_size = 128 * n;
 
-    let mut written = 0;
-
-    let mut offset = offset;
-    let mut len = len;
-
     for chunk in source.chunks(chunk_size) {
         let write_len = min(len, chunk.len());
 
@@ -764,6 +829,26 @@ mod tests {
         0xe5,
     ];
 
+    #[test]
+    fn test_offset_mapping() {
+        for unpadded in 0..2000 {
+            let padded = to_padded_offset(unpadded);
+            assert_eq!(to_unpadded_offset(padded), unpadded);
+            assert_eq!(
+                to_padded_bit_offset(unpadded),
+                FR32_PADDING_MAP.transform_bit_offset(unpadded as usize * 8, true) as u64
+            );
+        }
+
+        // Block boundaries line up.
+        assert_eq!(to_padded_offset(127 * 3), 128 * 3);
+        assert_eq!(to_unpadded_offset(128 * 3), 127 * 3);
+        // The padded bytes after the last full unpadded byte of an Fr map to the next Fr.
+        assert_eq!(to_padded_offset(32), 32);
+        assert_eq!(to_unpadded_offset(32), 32);
+        assert_eq!(to_padded_bit_offset(32), 258);
+    }
+
     #[test]
     fn test_position() {
         let mut bits = 0;
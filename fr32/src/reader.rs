@@ -1,136 +1,126 @@
 use std::cmp::min;
-use std::io::{self, Read};
-use std::mem::size_of;
+use std::io::{self, IoSliceMut, Read, Seek, SeekFrom};
 
-#[cfg(not(target_arch = "aarch64"))]
-use byte_slice_cast::AsSliceOf;
+use crate::block::{pad_block, pad_blocks, PADDED_BLOCK_SIZE, RAW_BLOCK_SIZE};
 
-use byte_slice_cast::AsByteSlice;
-
-/// The number of Frs per Block.
-const NUM_FRS_PER_BLOCK: usize = 4;
 /// The amount of bits in an Fr when not padded.
-const IN_BITS_FR: usize = 254;
+pub(crate) const IN_BITS_FR: usize = 254;
 /// The amount of bits in an Fr when padded.
-const OUT_BITS_FR: usize = 256;
-
-const NUM_BYTES_IN_BLOCK: usize = NUM_FRS_PER_BLOCK * IN_BITS_FR / 8;
-const NUM_BYTES_OUT_BLOCK: usize = NUM_FRS_PER_BLOCK * OUT_BITS_FR / 8;
-
-const NUM_U128S_PER_BLOCK: usize = NUM_BYTES_OUT_BLOCK / size_of::<u128>();
+pub(crate) const OUT_BITS_FR: usize = 256;
 
-const MASK_SKIP_HIGH_2: u128 = 0b0011_1111_1111_1111_1111_1111_1111_1111_1111_1111_1111_1111_1111_1111_1111_1111_1111_1111_1111_1111_1111_1111_1111_1111_1111_1111_1111_1111_1111_1111_1111_1111;
-
-#[repr(align(16))]
-struct AlignedBuffer([u8; NUM_BYTES_IN_BLOCK + 1]);
+/// Maximum number of blocks padded at once when the caller reads large chunks.
+const MAX_BULK_BLOCKS: usize = 1024;
 
 /// An `io::Reader` that converts unpadded input into valid `Fr32` padded output.
 pub struct Fr32Reader<R> {
     /// The source being padded.
     source: R,
     /// Currently read block.
-    /// This is padded to 128 bytes to allow reading all values as `u128`s, but only the first
-    /// 127 bytes are ever valid.
-    in_buffer: AlignedBuffer,
+    in_buffer: [u8; RAW_BLOCK_SIZE],
     /// Currently writing out block.
-    out_buffer: [u128; NUM_U128S_PER_BLOCK],
+    out_buffer: [u8; PADDED_BLOCK_SIZE],
     /// The current offset into the `out_buffer` in bytes.
     out_offset: usize,
-    /// How many `Fr32`s are available in the `out_buffer`.
-    available_frs: usize,
+    /// How many bytes of `out_buffer` are left to be read.
+    available_bytes: usize,
+    /// Position in the padded output.
+    pos: u64,
+    /// Raw data for reads spanning multiple blocks, these are padded straight into the target.
+    bulk_buffer: Vec<u8>,
     /// Are we done reading?
     done: bool,
 }
 
-macro_rules! process_fr {
-    (
-        $in_buffer:expr,
-        $out0:expr,
-        $out1:expr,
-        $bit_offset:expr
-    ) => {{
-        $out0 = $in_buffer[0] >> 128 - $bit_offset;
-        $out0 |= $in_buffer[1] << $bit_offset;
-        $out1 = $in_buffer[1] >> 128 - $bit_offset;
-        $out1 |= $in_buffer[2] << $bit_offset;
-        $out1 &= MASK_SKIP_HIGH_2; // zero high 2 bits
-    }};
+/// Reads from `source` until `buf` is full or the source is exhausted.
+fn fill_buffer<R: Read>(source: &mut R, mut buf: &mut [u8]) -> io::Result<usize> {
+    let mut bytes_read = 0;
+
+    while !buf.is_empty() {
+        match source.read(buf) {
+            Ok(0) => {
+                break;
+            }
+            Ok(n) => {
+                buf = &mut buf[n..];
+                bytes_read += n;
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(bytes_read)
 }
 
 impl<R: Read> Fr32Reader<R> {
     pub fn new(source: R) -> Self {
         Fr32Reader {
             source,
-            in_buffer: AlignedBuffer([0; NUM_BYTES_IN_BLOCK + 1]),
-            out_buffer: [0; NUM_U128S_PER_BLOCK],
+            in_buffer: [0; RAW_BLOCK_SIZE],
+            out_buffer: [0; PADDED_BLOCK_SIZE],
             out_offset: 0,
-            available_frs: 0,
+            available_bytes: 0,
+            pos: 0,
+            bulk_buffer: Vec::new(),
             done: false,
         }
     }
 
     /// Processes a single block in in_buffer, writing the result to out_buffer.
     fn process_block(&mut self) {
-        let in_buffer: &[u128] = {
-            #[cfg(target_arch = "aarch64")]
-            // Safety: This is safe because the struct/data is aligned on
-            // a 16 byte boundary and can therefore be casted from u128
-            // to u8 without alignment safety issues.
-            unsafe {
-                &mut (*(&self.in_buffer.0 as *const [u8] as *mut [u128]))
-            }
-            #[cfg(not(target_arch = "aarch64"))]
-            self.in_buffer.0.as_slice_of::<u128>().unwrap()
-        };
-        let out = &mut self.out_buffer;
-
-        // 0..254
-        {
-            out[0] = in_buffer[0];
-            out[1] = in_buffer[1] & MASK_SKIP_HIGH_2;
-        }
-        // 254..508
-        process_fr!(&in_buffer[1..], out[2], out[3], 2);
-        // 508..762
-        process_fr!(&in_buffer[3..], out[4], out[5], 4);
-        // 762..1016
-        process_fr!(&in_buffer[5..], out[6], out[7], 6);
+        pad_block(&self.in_buffer, &mut self.out_buffer);
 
         // Reset buffer offset.
         self.out_offset = 0;
     }
 
-    fn fill_in_buffer(&mut self) -> io::Result<usize> {
-        let mut bytes_read = 0;
-        let mut buf = &mut self.in_buffer.0[..NUM_BYTES_IN_BLOCK];
-
-        while !buf.is_empty() {
-            match self.source.read(buf) {
-                Ok(0) => {
-                    break;
-                }
-                Ok(n) => {
-                    buf = &mut buf[n..];
-                    bytes_read += n;
-                }
-                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
-                Err(e) => return Err(e),
-            }
-        }
-
+    /// Loads the first `len` bytes of `in_buffer` as the current block, clearing the rest.
+    fn load_block(&mut self, len: usize) {
         // Clear unfilled memory.
-        for val in &mut self.in_buffer.0[bytes_read..NUM_BYTES_IN_BLOCK] {
+        for val in &mut self.in_buffer[len..] {
             *val = 0;
         }
 
-        Ok(bytes_read)
+        self.process_block();
+
+        // Update state of how many new bytes are now available.
+        self.available_bytes = div_ceil(len * 8, IN_BITS_FR) * (OUT_BITS_FR / 8);
+    }
+
+    /// Pads up to `num_blocks` full blocks directly into `target`. Returns the number of bytes
+    /// written to `target`, a trailing partial block is loaded into the block buffers instead.
+    fn read_bulk(&mut self, target: &mut [u8], num_blocks: usize) -> io::Result<usize> {
+        let raw_len = num_blocks * RAW_BLOCK_SIZE;
+        self.bulk_buffer.resize(raw_len, 0);
+        let bytes_read = fill_buffer(&mut self.source, &mut self.bulk_buffer[..raw_len])?;
+
+        let full_blocks = bytes_read / RAW_BLOCK_SIZE;
+        let padded_len = full_blocks * PADDED_BLOCK_SIZE;
+        pad_blocks(
+            &self.bulk_buffer[..full_blocks * RAW_BLOCK_SIZE],
+            &mut target[..padded_len],
+        );
+
+        if bytes_read < raw_len {
+            // The source is exhausted, whatever is left is the last block.
+            let rest = bytes_read - full_blocks * RAW_BLOCK_SIZE;
+            if rest == 0 {
+                self.done = true;
+            } else {
+                self.in_buffer[..rest]
+                    .copy_from_slice(&self.bulk_buffer[bytes_read - rest..bytes_read]);
+                self.load_block(rest);
+            }
+        }
+
+        Ok(padded_len)
     }
 }
 
 /// Division of x by y, rounding up.
 /// x must be > 0
 #[inline]
-const fn div_ceil(x: usize, y: usize) -> usize {
+pub(crate) const fn div_ceil(x: usize, y: usize) -> usize {
     1 + ((x - 1) / y)
 }
 
@@ -146,9 +136,22 @@ impl<R: Read> Read for Fr32Reader<R> {
         let bytes_to_read = target.len();
 
         while bytes_read < bytes_to_read {
-            // Load and process the next block, if no Frs are available anymore.
-            if self.available_frs == 0 {
-                let bytes_read = self.fill_in_buffer()?;
+            if self.available_bytes == 0 {
+                // Pad full blocks straight into the target, if it has room for several.
+                let num_blocks = min(
+                    (bytes_to_read - bytes_read) / PADDED_BLOCK_SIZE,
+                    MAX_BULK_BLOCKS,
+                );
+                if num_blocks > 1 {
+                    bytes_read += self.read_bulk(&mut target[bytes_read..], num_blocks)?;
+                    if self.done {
+                        break;
+                    }
+                    continue;
+                }
+
+                // Load and process the next block, if no Frs are available anymore.
+                let bytes_read = fill_buffer(&mut self.source, &mut self.in_buffer)?;
 
                 // All data was read from the source, no new data in the buffer.
                 if bytes_read == 0 {
@@ -156,33 +159,99 @@ impl<R: Read> Read for Fr32Reader<R> {
                     break;
                 }
 
-                self.process_block();
-
-                // Update state of how many new Frs are now available.
-                self.available_frs = div_ceil(bytes_read * 8, IN_BITS_FR);
+                self.load_block(bytes_read);
             }
 
-            // Write out as many Frs as available and requested
+            // Write out as many bytes as available and requested
             {
-                let available_bytes = self.available_frs * (OUT_BITS_FR / 8);
-
                 let target_start = bytes_read;
-                let target_end = min(target_start + available_bytes, bytes_to_read);
+                let target_end = min(target_start + self.available_bytes, bytes_to_read);
                 let len = target_end - target_start;
 
                 let out_start = self.out_offset;
                 let out_end = out_start + len;
 
                 target[target_start..target_end]
-                    .copy_from_slice(&self.out_buffer.as_byte_slice()[out_start..out_end]);
+                    .copy_from_slice(&self.out_buffer[out_start..out_end]);
                 bytes_read += len;
                 self.out_offset += len;
-                self.available_frs -= div_ceil(len * 8, OUT_BITS_FR);
+                self.available_bytes -= len;
             }
         }
 
+        self.pos += bytes_read as u64;
         Ok(bytes_read)
     }
+
+    /// Fills the buffers in order, only stopping early when the source is exhausted.
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        let mut bytes_read = 0;
+        for buf in bufs.iter_mut().filter(|buf| !buf.is_empty()) {
+            let n = self.read(buf)?;
+            bytes_read += n;
+            if n < buf.len() {
+                break;
+            }
+        }
+
+        Ok(bytes_read)
+    }
+}
+
+/// Seeking is done in the padded output, the source is repositioned to the start of the block
+/// containing the new position.
+impl<R: Read + Seek> Seek for Fr32Reader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+            SeekFrom::End(delta) => {
+                let source_len = self.source.seek(SeekFrom::End(0))?;
+                let padded_len = if source_len == 0 {
+                    0
+                } else {
+                    div_ceil(source_len as usize * 8, IN_BITS_FR) as u64 * (OUT_BITS_FR / 8) as u64
+                };
+                padded_len.checked_add_signed(delta)
+            }
+        }
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+
+        let block = pos / PADDED_BLOCK_SIZE as u64;
+        self.source
+            .seek(SeekFrom::Start(block * RAW_BLOCK_SIZE as u64))?;
+
+        let bytes_read = fill_buffer(&mut self.source, &mut self.in_buffer)?;
+        self.pos = pos;
+
+        // Seeking past the end leaves nothing to read.
+        if bytes_read == 0 {
+            self.out_offset = 0;
+            self.available_bytes = 0;
+            self.done = true;
+            return Ok(pos);
+        }
+
+        self.done = false;
+        self.load_block(bytes_read);
+
+        // Skip the start of the block.
+        let skip = (pos % PADDED_BLOCK_SIZE as u64) as usize;
+        if skip >= self.available_bytes {
+            self.available_bytes = 0;
+            self.done = true;
+        } else {
+            self.out_offset = skip;
+            self.available_bytes -= skip;
+        }
+
+        Ok(pos)
+    }
 }
 
 #[cfg(test)]
@@ -194,10 +263,16 @@ mod tests {
     use bitvec::{order::Lsb0 as LittleEndian, vec::BitVec};
     use itertools::Itertools;
     use pretty_assertions::assert_eq;
-    use rand::random;
+    use rand::{random, RngCore, SeedableRng};
+    use rand_xorshift::XorShiftRng;
 
     use crate::bytes_into_fr;
 
+    const TEST_SEED: [u8; 16] = [
+        0x59, 0x62, 0xbe, 0x5d, 0x76, 0x3d, 0x31, 0x8d, 0x17, 0xdb, 0x37, 0x32, 0x54, 0x06, 0xbc,
+        0xe5,
+    ];
+
     const DATA_BITS: u64 = 254;
     const TARGET_BITS: u64 = 256;
 
@@ -303,10 +378,134 @@ mod tests {
         validate_fr32(&buf);
     }
 
+    #[test]
+    fn test_bulk_read() {
+        let mut rng = XorShiftRng::from_seed(TEST_SEED);
+        for len in &[127 * 2, 127 * 20, 127 * 20 + 50, 127 * 2000 + 1] {
+            let mut data = vec![0u8; *len];
+            rng.fill_bytes(&mut data);
+
+            // Large reads pad full blocks straight into the target.
+            let mut buf = vec![0u8; 128 * 64];
+            let mut padded = Vec::new();
+            let mut reader = Fr32Reader::new(Cursor::new(&data));
+            loop {
+                let n = reader.read(&mut buf).expect("in-memory read failed");
+                if n == 0 {
+                    break;
+                }
+                padded.extend_from_slice(&buf[..n]);
+            }
+
+            assert_eq!(padded.into_boxed_slice(), bit_vec_padding(data), "{}", len);
+        }
+    }
+
+    #[test]
+    fn test_read_vectored() {
+        let mut rng = XorShiftRng::from_seed(TEST_SEED);
+        let mut data = vec![0u8; 127 * 5 + 3];
+        rng.fill_bytes(&mut data);
+
+        let mut first = vec![0u8; 96];
+        let mut second = vec![0u8; 0];
+        let mut third = vec![0u8; 1000];
+        let mut reader = Fr32Reader::new(Cursor::new(&data));
+        let n = reader
+            .read_vectored(&mut [
+                IoSliceMut::new(&mut first),
+                IoSliceMut::new(&mut second),
+                IoSliceMut::new(&mut third),
+            ])
+            .expect("in-memory read failed");
+
+        let expected = bit_vec_padding(data);
+        assert_eq!(n, expected.len());
+        assert_eq!(&first[..], &expected[..96]);
+        assert_eq!(&third[..n - 96], &expected[96..]);
+    }
+
+    #[test]
+    fn test_seek() {
+        let mut rng = XorShiftRng::from_seed(TEST_SEED);
+        let mut data = vec![0u8; 127 * 9 + 70];
+        rng.fill_bytes(&mut data);
+        let expected = bit_vec_padding(data.clone());
+
+        let mut reader = Fr32Reader::new(Cursor::new(&data));
+        for pos in &[0, 1, 31, 32, 127, 128, 500, 1000, expected.len() - 3] {
+            assert_eq!(
+                reader
+                    .seek(SeekFrom::Start(*pos as u64))
+                    .expect("seek failed"),
+                *pos as u64
+            );
+            let mut buf = vec![0u8; 300];
+            let n = reader.read(&mut buf).expect("in-memory read failed");
+            assert_eq!(&buf[..n], &expected[*pos..min(*pos + 300, expected.len())]);
+        }
+
+        let end = reader.seek(SeekFrom::End(-40)).expect("seek failed");
+        assert_eq!(end as usize, expected.len() - 40);
+        reader.seek(SeekFrom::Current(-10)).expect("seek failed");
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).expect("in-memory read failed");
+        assert_eq!(&buf[..], &expected[expected.len() - 50..]);
+
+        // Past the end there is nothing to read.
+        reader
+            .seek(SeekFrom::Start(expected.len() as u64 + 5))
+            .expect("seek failed");
+        assert_eq!(reader.read(&mut buf).expect("read failed"), 0);
+        assert!(reader.seek(SeekFrom::Current(-100_000)).is_err());
+    }
+
+    #[test]
+    fn test_seek_block_aligned() {
+        let mut rng = XorShiftRng::from_seed(TEST_SEED);
+        let mut data = vec![0u8; 127 * 4];
+        rng.fill_bytes(&mut data);
+        let expected = bit_vec_padding(data.clone());
+        assert_eq!(expected.len(), 128 * 4);
+
+        let mut reader = Fr32Reader::new(Cursor::new(&data));
+        let mut buf = Vec::new();
+        assert_eq!(
+            reader.seek(SeekFrom::End(0)).expect("seek failed") as usize,
+            expected.len()
+        );
+        assert_eq!(reader.read_to_end(&mut buf).expect("read failed"), 0);
+
+        // One or more blocks past the end.
+        for pos in &[expected.len() + 128, expected.len() + 1000] {
+            reader
+                .seek(SeekFrom::Start(*pos as u64))
+                .expect("seek failed");
+            assert_eq!(reader.read_to_end(&mut buf).expect("read failed"), 0);
+        }
+
+        // Seeking back reads again.
+        reader.seek(SeekFrom::End(-130)).expect("seek failed");
+        reader.read_to_end(&mut buf).expect("read failed");
+        assert_eq!(&buf[..], &expected[expected.len() - 130..]);
+    }
+
+    #[test]
+    fn test_seek_empty() {
+        let data: Vec<u8> = Vec::new();
+        let mut reader = Fr32Reader::new(Cursor::new(&data));
+        let mut buf = Vec::new();
+        for pos in &[SeekFrom::Start(0), SeekFrom::End(0), SeekFrom::Start(200)] {
+            reader.seek(*pos).expect("seek failed");
+            assert_eq!(reader.read_to_end(&mut buf).expect("read failed"), 0);
+        }
+        assert_eq!(reader.seek(SeekFrom::End(0)).expect("seek failed"), 0);
+    }
+
     #[test]
     #[ignore]
     fn test_long() {
-        use rand::{thread_rng, RngCore};
+        use rand::thread_rng;
 
         let mut rng = thread_rng();
         for i in 1..100 {
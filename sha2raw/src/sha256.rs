@@ -1,7 +1,7 @@
 use byteorder::{ByteOrder, BE};
 use lazy_static::lazy_static;
 
-use crate::{consts::H256, platform::Implementation};
+use crate::{consts::H256, platform::Implementation, sha256_multi};
 
 lazy_static! {
     static ref IMPL: Implementation = Implementation::detect();
@@ -33,6 +33,32 @@ impl Sha256 {
         sha.finish()
     }
 
+    /// Hashes each of the `messages`, given as their 32 byte blocks like for [`Sha256::digest`].
+    /// All messages must have the same, non-zero number of blocks.
+    ///
+    /// With AVX2, [`LANES`](crate::LANES) messages are hashed at once, which is faster than
+    /// hashing them one by one even with the SHA extensions. Otherwise they are hashed one by one.
+    pub fn digest_many(messages: &[&[&[u8]]]) -> Vec<[u8; 32]> {
+        let mut out = vec![[0u8; 32]; messages.len()];
+        if sha256_multi::is_vectorized() {
+            sha256_multi::digest_many(messages, &mut out);
+            return out;
+        }
+
+        for (blocks, out) in messages.iter().zip(out.iter_mut()) {
+            let mut sha = Sha256::new();
+            *out = if blocks.len() % 2 == 0 {
+                sha.input(blocks);
+                sha.finish()
+            } else {
+                let (last, rest) = blocks.split_last().expect("messages must not be empty");
+                sha.input(rest);
+                sha.finish_with(last)
+            };
+        }
+        out
+    }
+
     pub fn input(&mut self, blocks: &[&[u8]]) {
         debug_assert_eq!(blocks.len() % 2, 0, "invalid block length");
 
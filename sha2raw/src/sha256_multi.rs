@@ -0,0 +1,281 @@
+//! Multi-buffer SHA-256: [`LANES`] independent messages are hashed at once, each message in one
+//! lane of a vector register.
+//!
+//! The rounds are written on `[u32; LANES]` and compiled twice, once for the baseline target and
+//! once with AVX2 enabled, where every operation is a single instruction on a 256 bit register.
+
+use std::ops::{Add, BitAnd, BitXor};
+
+use byteorder::{ByteOrder, BE};
+use lazy_static::lazy_static;
+
+use crate::consts::{H256, K32};
+
+/// The number of messages hashed at once.
+pub const LANES: usize = 8;
+
+#[derive(Clone, Copy)]
+struct U32xLanes([u32; LANES]);
+
+impl U32xLanes {
+    #[inline(always)]
+    fn splat(x: u32) -> Self {
+        U32xLanes([x; LANES])
+    }
+
+    #[inline(always)]
+    fn map(self, f: impl Fn(u32) -> u32) -> Self {
+        let mut out = self.0;
+        for x in out.iter_mut() {
+            *x = f(*x);
+        }
+        U32xLanes(out)
+    }
+
+    #[inline(always)]
+    fn zip(self, other: Self, f: impl Fn(u32, u32) -> u32) -> Self {
+        let mut out = self.0;
+        for (x, y) in out.iter_mut().zip(other.0.iter()) {
+            *x = f(*x, *y);
+        }
+        U32xLanes(out)
+    }
+
+    #[inline(always)]
+    fn rotr(self, n: u32) -> Self {
+        self.map(|x| x.rotate_right(n))
+    }
+
+    #[inline(always)]
+    fn shr(self, n: u32) -> Self {
+        self.map(|x| x >> n)
+    }
+
+    /// `!self & other`.
+    #[inline(always)]
+    fn andnot(self, other: Self) -> Self {
+        self.zip(other, |x, y| !x & y)
+    }
+}
+
+impl Add for U32xLanes {
+    type Output = Self;
+
+    #[inline(always)]
+    fn add(self, other: Self) -> Self {
+        self.zip(other, u32::wrapping_add)
+    }
+}
+
+impl BitAnd for U32xLanes {
+    type Output = Self;
+
+    #[inline(always)]
+    fn bitand(self, other: Self) -> Self {
+        self.zip(other, |x, y| x & y)
+    }
+}
+
+impl BitXor for U32xLanes {
+    type Output = Self;
+
+    #[inline(always)]
+    fn bitxor(self, other: Self) -> Self {
+        self.zip(other, |x, y| x ^ y)
+    }
+}
+
+type State = [U32xLanes; 8];
+type Block = [U32xLanes; 16];
+
+#[inline(always)]
+fn compress(state: &mut State, block: &Block) {
+    let mut w = [U32xLanes::splat(0); 64];
+    w[..16].copy_from_slice(block);
+    for t in 16..64 {
+        let s0 = w[t - 15].rotr(7) ^ w[t - 15].rotr(18) ^ w[t - 15].shr(3);
+        let s1 = w[t - 2].rotr(17) ^ w[t - 2].rotr(19) ^ w[t - 2].shr(10);
+        w[t] = w[t - 16] + s0 + w[t - 7] + s1;
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for (k, w) in K32.iter().zip(w.iter()) {
+        let s1 = e.rotr(6) ^ e.rotr(11) ^ e.rotr(25);
+        let ch = (e & f) ^ e.andnot(g);
+        let t1 = h + s1 + ch + U32xLanes::splat(*k) + *w;
+        let s0 = a.rotr(2) ^ a.rotr(13) ^ a.rotr(22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0 + maj;
+
+        h = g;
+        g = f;
+        f = e;
+        e = d + t1;
+        d = c;
+        c = b;
+        b = a;
+        a = t1 + t2;
+    }
+
+    for (s, x) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+        *s = *s + x;
+    }
+}
+
+fn compress_portable(state: &mut State, block: &Block) {
+    compress(state, block)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn compress_avx2(state: &mut State, block: &Block) {
+    compress(state, block)
+}
+
+lazy_static! {
+    static ref AVX2: bool = avx2_supported();
+}
+
+#[cfg(target_arch = "x86_64")]
+fn avx2_supported() -> bool {
+    cpufeatures::new!(cpuid_avx2, "avx2");
+    cpuid_avx2::get()
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn avx2_supported() -> bool {
+    false
+}
+
+/// Returns whether the CPU supports AVX2, i.e. the lanes are processed in a single instruction.
+pub fn is_vectorized() -> bool {
+    *AVX2
+}
+
+#[inline]
+fn compress_lanes(state: &mut State, block: &Block) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if *AVX2 {
+            // Safe as the CPU supports AVX2.
+            unsafe { compress_avx2(state, block) };
+            return;
+        }
+    }
+    compress_portable(state, block)
+}
+
+/// Sets the words of `lane` in `block` to the 64 bytes `lo || hi`.
+#[inline]
+fn load_lane(block: &mut Block, lane: usize, lo: &[u8], hi: &[u8]) {
+    for (i, word) in lo.chunks_exact(4).chain(hi.chunks_exact(4)).enumerate() {
+        block[i].0[lane] = BE::read_u32(word);
+    }
+}
+
+/// Hashes each of the `messages`, given as their 32 byte blocks, into `out`. All messages must
+/// have the same, non-zero number of blocks.
+pub fn digest_many(messages: &[&[&[u8]]], out: &mut [[u8; 32]]) {
+    assert_eq!(messages.len(), out.len(), "one digest per message");
+    if messages.is_empty() {
+        return;
+    }
+    let num_blocks = messages[0].len();
+    assert!(num_blocks > 0, "messages must not be empty");
+    assert!(
+        messages.iter().all(|message| message.len() == num_blocks),
+        "messages must have the same length"
+    );
+
+    // The final block holds the last 32 byte block if there is an odd number of them, the
+    // padding and the length of the message in bits.
+    let len_bits = (num_blocks as u64) << 8;
+
+    for (messages, out) in messages.chunks(LANES).zip(out.chunks_mut(LANES)) {
+        // Lanes without a message hash the first one again, the digest is dropped.
+        let lane_message = |lane: usize| messages.get(lane).unwrap_or(&messages[0]);
+
+        let mut state = H256.map(U32xLanes::splat);
+        let mut block = [U32xLanes::splat(0); 16];
+        for pair in 0..num_blocks / 2 {
+            for lane in 0..LANES {
+                let message = lane_message(lane);
+                load_lane(&mut block, lane, message[2 * pair], message[2 * pair + 1]);
+            }
+            compress_lanes(&mut state, &block);
+        }
+
+        for lane in 0..LANES {
+            let mut tail = [0u8; 64];
+            if num_blocks % 2 == 1 {
+                tail[..32].copy_from_slice(lane_message(lane)[num_blocks - 1]);
+                tail[32] = 0b1000_0000;
+            } else {
+                tail[0] = 0b1000_0000;
+            }
+            tail[56..].copy_from_slice(&len_bits.to_be_bytes());
+            load_lane(&mut block, lane, &tail[..32], &tail[32..]);
+        }
+        compress_lanes(&mut state, &block);
+
+        for (lane, out) in out.iter_mut().enumerate() {
+            for (i, word) in state.iter().enumerate() {
+                BE::write_u32(&mut out[4 * i..4 * (i + 1)], word.0[lane]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{RngCore, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+    use sha2::{Digest, Sha256 as Original};
+
+    #[test]
+    fn test_digest_many() {
+        let rng = &mut XorShiftRng::from_seed([
+            0x59, 0x62, 0xbe, 0x5d, 0x76, 0x3d, 0x31, 0x8d, 0x17, 0xdb, 0x37, 0x32, 0x54, 0x06,
+            0xbc, 0xe5,
+        ]);
+        // Both the odd and even number of blocks, and a partially filled last group of lanes.
+        for num_blocks in 1..6 {
+            for num_messages in [1, LANES - 1, LANES, 2 * LANES + 3] {
+                let mut input = vec![0u8; 32 * num_blocks * num_messages];
+                rng.fill_bytes(&mut input);
+                let blocks = input.chunks(32).collect::<Vec<_>>();
+                let messages = blocks.chunks(num_blocks).collect::<Vec<_>>();
+
+                let mut out = vec![[0u8; 32]; num_messages];
+                digest_many(&messages, &mut out);
+                for (message, digest) in input.chunks(32 * num_blocks).zip(out.iter()) {
+                    assert_eq!(&digest[..], &Original::digest(message)[..]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_compress_portable() {
+        let rng = &mut XorShiftRng::from_seed([
+            0x59, 0x62, 0xbe, 0x5d, 0x76, 0x3d, 0x31, 0x8d, 0x17, 0xdb, 0x37, 0x32, 0x54, 0x06,
+            0xbc, 0xe5,
+        ]);
+        let mut input = [0u8; 64 * LANES];
+        rng.fill_bytes(&mut input);
+
+        let mut block = [U32xLanes::splat(0); 16];
+        for (lane, data) in input.chunks(64).enumerate() {
+            load_lane(&mut block, lane, &data[..32], &data[32..]);
+        }
+
+        // The rounds give the same state whether or not they are vectorized.
+        let mut state = H256.map(U32xLanes::splat);
+        let mut dispatched = state;
+        compress_portable(&mut state, &block);
+        compress_lanes(&mut dispatched, &block);
+        assert!(state.iter().zip(dispatched.iter()).all(|(x, y)| x.0 == y.0));
+    }
+}
@@ -0,0 +1,57 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use storage_proofs_core::crypto::feistel::{
+    permute, permute_batch, precompute, FeistelBatchBuffers, FeistelRoundKeys, Index,
+};
+
+fn feistel_benchmark(c: &mut Criterion) {
+    // The search space of the expansion parents of a 32GiB sector.
+    let num_elements: Index = (1 << 30) * 8;
+    let keys = [1, 2, 3, 4];
+    let round_keys = FeistelRoundKeys::new(&keys);
+    let precomputed = precompute(num_elements);
+
+    let mut group = c.benchmark_group("feistel");
+    for batch in [8, 64, 1024] {
+        let indexes: Vec<Index> = (0..batch).collect();
+        group.throughput(Throughput::Elements(batch));
+
+        group.bench_with_input(
+            BenchmarkId::new("permute", batch),
+            &indexes,
+            |b, indexes| {
+                let mut permuted = vec![0; indexes.len()];
+                b.iter(|| {
+                    for (p, &index) in permuted.iter_mut().zip(indexes) {
+                        *p = permute(num_elements, index, &keys, precomputed);
+                    }
+                    black_box(&permuted);
+                })
+            },
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("permute_batch", batch),
+            &indexes,
+            |b, indexes| {
+                let mut permuted = vec![0; indexes.len()];
+                let mut buffers = FeistelBatchBuffers::default();
+                b.iter(|| {
+                    permute_batch(
+                        num_elements,
+                        indexes,
+                        &mut permuted,
+                        &round_keys,
+                        precomputed,
+                        &mut buffers,
+                    );
+                    black_box(&permuted);
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, feistel_benchmark);
+criterion_main!(benches);
@@ -74,6 +74,42 @@ fn sha256_raw_benchmark(c: &mut Criterion) {
     group.finish();
 }
 
+fn sha256_raw_many_benchmark(c: &mut Criterion) {
+    // Parents of binary and oct tree nodes, as hashed when building tree_d.
+    let params = vec![2, 8];
+    let num_messages = 1024;
+
+    let mut group = c.benchmark_group("hash-sha256-raw-many");
+    for blocks_per_message in params {
+        let mut rng = thread_rng();
+        let data: Vec<u8> = (0..32 * blocks_per_message * num_messages)
+            .map(|_| rng.gen())
+            .collect();
+        let blocks = data.chunks(32).collect::<Vec<_>>();
+        let messages = blocks.chunks(blocks_per_message).collect::<Vec<_>>();
+
+        group
+            .bench_function(format!("one-by-one-{}", blocks_per_message), |b| {
+                b.iter(|| {
+                    black_box(
+                        messages
+                            .iter()
+                            .map(|message| sha2raw::Sha256::digest(message))
+                            .collect::<Vec<_>>(),
+                    )
+                })
+            })
+            .throughput(Throughput::Bytes(data.len() as u64));
+        group
+            .bench_function(format!("many-{}", blocks_per_message), |b| {
+                b.iter(|| black_box(sha2raw::Sha256::digest_many(&messages)))
+            })
+            .throughput(Throughput::Bytes(data.len() as u64));
+    }
+
+    group.finish();
+}
+
 fn sha256_circuit_benchmark(c: &mut Criterion) {
     let mut rng1 = thread_rng();
 
@@ -131,6 +167,7 @@ criterion_group!(
     benches,
     sha256_benchmark,
     sha256_raw_benchmark,
+    sha256_raw_many_benchmark,
     sha256_circuit_benchmark
 );
 criterion_main!(benches);
@@ -0,0 +1,106 @@
+use std::sync::Arc;
+
+use bellperson::{
+    gadgets::{
+        boolean::{AllocatedBit, Boolean},
+        sha256::sha256 as sha256_circuit,
+    },
+    util_cs::bench_cs::BenchCS,
+    Circuit, ConstraintSystem, SynthesisError,
+};
+use blstrs::Scalar as Fr;
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use rand::{thread_rng, Rng};
+use storage_proofs_core::synthesis_cache::{circuit_shape, CachedCircuit};
+
+struct Sha256Example<'a> {
+    data: &'a [Option<bool>],
+}
+
+impl<'a> Circuit<Fr> for Sha256Example<'a> {
+    fn synthesize<CS: ConstraintSystem<Fr>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let data: Vec<Boolean> = self
+            .data
+            .iter()
+            .enumerate()
+            .map(|(i, b)| {
+                Ok(Boolean::from(AllocatedBit::alloc(
+                    cs.namespace(|| format!("bit {}", i)),
+                    *b,
+                )?))
+            })
+            .collect::<Result<Vec<_>, SynthesisError>>()?;
+
+        let cs = cs.namespace(|| "sha256");
+
+        let _res = sha256_circuit(cs, &data)?;
+        Ok(())
+    }
+}
+
+fn synthesis_cache_benchmark(c: &mut Criterion) {
+    let params = vec![64, 10 * 32];
+
+    let mut group = c.benchmark_group("synthesis-cache");
+    for bytes in params {
+        let mut rng = thread_rng();
+        let data: Vec<Option<bool>> = (0..bytes * 8).map(|_| Some(rng.gen())).collect();
+        let blank = vec![None; bytes * 8];
+        let shape = circuit_shape(&format!("bench-sha256-{}", bytes), || Sha256Example {
+            data: blank.as_slice(),
+        })
+        .unwrap();
+
+        group.bench_function(format!("synthesize-{}", bytes), |b| {
+            b.iter(|| {
+                let mut cs = BenchCS::<Fr>::new();
+                Sha256Example {
+                    data: data.as_slice(),
+                }
+                .synthesize(&mut cs)
+                .unwrap();
+
+                black_box(cs)
+            });
+        });
+        group.bench_function(format!("witness-{}", bytes), |b| {
+            b.iter(|| {
+                black_box(
+                    CachedCircuit::new(
+                        Arc::clone(&shape),
+                        Sha256Example {
+                            data: data.as_slice(),
+                        },
+                    )
+                    .unwrap(),
+                )
+            });
+        });
+        group.bench_function(format!("replay-{}", bytes), |b| {
+            b.iter_batched(
+                || {
+                    CachedCircuit::new(
+                        Arc::clone(&shape),
+                        Sha256Example {
+                            data: data.as_slice(),
+                        },
+                    )
+                    .unwrap()
+                },
+                |circuit| {
+                    let mut cs = BenchCS::<Fr>::new();
+                    circuit.synthesize(&mut cs).unwrap();
+
+                    black_box(cs)
+                },
+                BatchSize::LargeInput,
+            );
+        });
+    }
+
+    group.sample_size(20);
+    group.finish();
+}
+
+criterion_group!(benches, synthesis_cache_benchmark);
+criterion_main!(benches);
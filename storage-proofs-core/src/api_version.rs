@@ -4,6 +4,7 @@ use std::str::FromStr;
 
 use anyhow::{format_err, Error, Result};
 use semver::Version;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
 /// The ApiVersion enum is used for mandatory changes that the network
 /// must use and recognize.
@@ -29,6 +30,9 @@ impl PartialOrd for ApiVersion {
 }
 
 impl ApiVersion {
+    /// All known API versions, in ascending order.
+    pub const ALL: [ApiVersion; 3] = [ApiVersion::V1_0_0, ApiVersion::V1_1_0, ApiVersion::V1_2_0];
+
     pub fn as_semver(&self) -> Version {
         match self {
             ApiVersion::V1_0_0 => Version::new(1, 0, 0),
@@ -87,17 +91,34 @@ impl FromStr for ApiVersion {
     }
 }
 
+impl Serialize for ApiVersion {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ApiVersion {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
 /// The ApiFeature enum is used for optional features that the network
 /// can use and recognize, but in no way is required to be used.
 ///
 /// New features always require new network behaviour (i.e. for proper
 /// validation of others, even if not actively using)
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ApiFeature {
     SyntheticPoRep,
 }
 
 impl ApiFeature {
+    /// All known API features.
+    pub const ALL: [ApiFeature; 1] = [ApiFeature::SyntheticPoRep];
+
     #[inline]
     pub fn first_supported_version(&self) -> ApiVersion {
         match self {
@@ -145,3 +166,14 @@ fn test_api_feature_synthetic_porep() {
     assert!(feature.first_supported_version() == ApiVersion::V1_2_0);
     assert!(feature.last_supported_version().is_none());
 }
+
+#[test]
+fn test_api_version_serde() {
+    for api_version in ApiVersion::ALL {
+        let json = serde_json::to_string(&api_version).expect("failed to serialize");
+        assert_eq!(json, format!("\"{}\"", api_version));
+        let decoded: ApiVersion = serde_json::from_str(&json).expect("failed to deserialize");
+        assert_eq!(decoded, api_version);
+    }
+    assert!(serde_json::from_str::<ApiVersion>("\"1.3.0\"").is_err());
+}
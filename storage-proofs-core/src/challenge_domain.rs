@@ -0,0 +1,72 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// The domain that porep and PoSt challenges are derived in.
+///
+/// Networks that fork Filecoin use a custom domain, so that the challenges, and thereby the
+/// proofs, of one network are not valid on another. Mainnet derives challenges without a tag, so
+/// that existing proofs stay valid.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ChallengeDomain {
+    #[default]
+    Mainnet,
+    /// A tag that is hashed into every challenge derivation, ahead of all other inputs.
+    Custom([u8; 32]),
+}
+
+impl ChallengeDomain {
+    /// Returns the domain of the network with the given id, e.g. `"calibnet"`. The tag is derived
+    /// from the id, so all implementations agree on it.
+    pub fn network(network_id: &str) -> Self {
+        let tag = Sha256::new()
+            .chain_update(b"filecoin.io|ChallengeDomain|1|")
+            .chain_update(network_id.as_bytes())
+            .finalize();
+        ChallengeDomain::Custom(tag.into())
+    }
+
+    /// The tag to prefix challenge derivations with, `None` for mainnet.
+    pub fn tag(&self) -> Option<&[u8; 32]> {
+        match self {
+            ChallengeDomain::Mainnet => None,
+            ChallengeDomain::Custom(tag) => Some(tag),
+        }
+    }
+
+    pub fn is_mainnet(&self) -> bool {
+        self.tag().is_none()
+    }
+
+    /// Returns a sha256 hasher that the tag has already been written to.
+    pub fn sha256(&self) -> Sha256 {
+        let mut hasher = Sha256::new();
+        if let Some(tag) = self.tag() {
+            hasher.update(tag);
+        }
+        hasher
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_challenge_domain() {
+        assert_eq!(ChallengeDomain::default(), ChallengeDomain::Mainnet);
+        assert!(ChallengeDomain::Mainnet.is_mainnet());
+        assert_eq!(
+            ChallengeDomain::Mainnet.sha256().finalize(),
+            Sha256::new().finalize()
+        );
+
+        let calibnet = ChallengeDomain::network("calibnet");
+        assert!(!calibnet.is_mainnet());
+        assert_eq!(calibnet, ChallengeDomain::network("calibnet"));
+        assert_ne!(calibnet, ChallengeDomain::network("butterflynet"));
+        assert_ne!(
+            calibnet.sha256().finalize(),
+            ChallengeDomain::Mainnet.sha256().finalize()
+        );
+    }
+}
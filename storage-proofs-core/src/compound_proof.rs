@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use anyhow::{ensure, Context};
 use bellperson::{
     groth16::{
@@ -8,7 +10,7 @@ use bellperson::{
         create_random_proof_batch, create_random_proof_batch_in_priority, verify_proofs_batch,
         PreparedVerifyingKey,
     },
-    Circuit,
+    Circuit, ConstraintSystem, SynthesisError,
 };
 use blstrs::{Bls12, Scalar as Fr};
 use log::info;
@@ -16,6 +18,7 @@ use rand::{rngs::OsRng, RngCore};
 use rayon::prelude::{
     IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator, ParallelIterator,
 };
+use tracing::{info_span, Span};
 
 use crate::{
     error::Result,
@@ -23,6 +26,9 @@ use crate::{
     parameter_cache::{Bls12GrothParams, CacheableParameters, ParameterSetMetadata},
     partitions::partition_count,
     proof::ProofScheme,
+    proof_rng::proof_rng,
+    settings::SETTINGS,
+    synthesis_cache::{circuit_shape, CachedCircuit},
 };
 
 #[derive(Clone)]
@@ -205,16 +211,18 @@ where
         let inputs: Vec<_> = multi_proofs
             .par_iter()
             .zip(public_inputs.par_iter())
-            .flat_map(|(multi_proof, pub_inputs)| {
+            .map(|(multi_proof, pub_inputs)| {
                 (0..multi_proof.circuit_proofs.len())
                     .into_par_iter()
                     .map(|k| {
                         Self::generate_public_inputs(pub_inputs, vanilla_public_params, Some(k))
                     })
                     .collect::<Result<Vec<_>>>()
-                    .expect("Invalid public inputs") // TODO: improve error handling
             })
-            .collect::<Vec<_>>();
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .flatten()
+            .collect();
         let circuit_proofs: Vec<_> = multi_proofs
             .iter()
             .flat_map(|m| m.circuit_proofs.iter())
@@ -236,16 +244,19 @@ where
         groth_params: &Bls12GrothParams,
         priority: bool,
     ) -> Result<Vec<groth16::Proof<Bls12>>> {
-        let mut rng = OsRng;
+        let mut rng = proof_rng();
         ensure!(
             !vanilla_proofs.is_empty(),
             "cannot create a circuit proof over missing vanilla proofs"
         );
 
+        let _span = info_span!("circuit_proofs", partitions = vanilla_proofs.len()).entered();
+
         let circuits = vanilla_proofs
             .into_par_iter()
             .enumerate()
             .map(|(k, vanilla_proof)| {
+                let _span = info_span!("circuit_assignment", partition = k).entered();
                 Self::circuit(
                     pub_in,
                     C::ComponentPrivateInputs::default(),
@@ -256,10 +267,19 @@ where
             })
             .collect::<Result<Vec<_>>>()?;
 
-        let groth_proofs = if priority {
-            create_random_proof_batch_in_priority(circuits, groth_params, &mut rng)?
+        let groth_proofs = if SETTINGS.use_synthesis_cache {
+            // All partitions share the shape the groth parameters were generated for, only their
+            // witnesses are computed here.
+            let shape = circuit_shape(&Self::cache_identifier(pub_params), || {
+                Self::blank_circuit(pub_params)
+            })?;
+            let circuits = circuits
+                .into_par_iter()
+                .map(|circuit| CachedCircuit::new(Arc::clone(&shape), circuit))
+                .collect::<Result<Vec<_>>>()?;
+            create_proofs(circuits, groth_params, priority, &mut rng)?
         } else {
-            create_random_proof_batch(circuits, groth_params, &mut rng)?
+            create_proofs(circuits, groth_params, priority, &mut rng)?
         };
 
         groth_proofs
@@ -497,3 +517,45 @@ where
         Ok(res)
     }
 }
+
+fn create_proofs<C: Circuit<Fr> + Send, R: RngCore>(
+    circuits: Vec<C>,
+    groth_params: &Bls12GrothParams,
+    priority: bool,
+    rng: &mut R,
+) -> Result<Vec<groth16::Proof<Bls12>>> {
+    let span = info_span!("groth16_proofs", priority);
+    let _entered = span.enter();
+    let circuits: Vec<_> = circuits
+        .into_iter()
+        .enumerate()
+        .map(|(partition, circuit)| TracedCircuit {
+            circuit,
+            partition,
+            parent: span.clone(),
+        })
+        .collect();
+    let proofs = if priority {
+        create_random_proof_batch_in_priority(circuits, groth_params, rng)?
+    } else {
+        create_random_proof_batch(circuits, groth_params, rng)?
+    };
+    Ok(proofs)
+}
+
+/// Runs the synthesis of a circuit within a `circuit_synthesis` span. The circuits are
+/// synthesized by the prover, on its own threads, so the span needs an explicit parent.
+struct TracedCircuit<C> {
+    circuit: C,
+    partition: usize,
+    parent: Span,
+}
+
+impl<C: Circuit<Fr>> Circuit<Fr> for TracedCircuit<C> {
+    fn synthesize<CS: ConstraintSystem<Fr>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let _span =
+            info_span!(parent: &self.parent, "circuit_synthesis", partition = self.partition)
+                .entered();
+        self.circuit.synthesize(cs)
+    }
+}
@@ -1,6 +1,10 @@
 use std::mem::size_of;
 
-use blake2b_simd::blake2b;
+use blake2b_simd::{
+    blake2b,
+    many::{hash_many, HashManyJob},
+    Params,
+};
 
 pub const FEISTEL_ROUNDS: usize = 3;
 // 3 rounds is an acceptable value for a pseudo-random permutation,
@@ -11,6 +15,36 @@ pub type Index = u64;
 
 pub type FeistelPrecomputed = (Index, Index, Index);
 
+/// The round keys of the Feistel network in the byte representation used by the round function,
+/// so that they are serialized once per graph rather than on every round function evaluation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeistelRoundKeys([[u8; HALF_FEISTEL_BYTES]; FEISTEL_ROUNDS]);
+
+impl FeistelRoundKeys {
+    pub fn new(keys: &[Index]) -> Self {
+        assert!(keys.len() >= FEISTEL_ROUNDS, "not enough feistel keys");
+
+        let mut round_keys = [[0u8; HALF_FEISTEL_BYTES]; FEISTEL_ROUNDS];
+        for (round_key, key) in round_keys.iter_mut().zip(keys) {
+            *round_key = key.to_be_bytes();
+        }
+        FeistelRoundKeys(round_keys)
+    }
+}
+
+/// The buffers used by [`permute_batch`], kept by the caller so that permuting many batches does
+/// not allocate. They grow to the largest batch they were used with.
+#[derive(Debug, Default, Clone)]
+pub struct FeistelBatchBuffers {
+    pending: Vec<usize>,
+    lefts: Vec<Index>,
+    rights: Vec<Index>,
+    inputs: Vec<[u8; FEISTEL_BYTES]>,
+}
+
+// The number of round function inputs hashed together by `hash_many`.
+const HASH_MANY_BATCH: usize = 8;
+
 // Find the minimum number of even bits to represent `num_elements`
 // within a `u32` maximum. Returns the left and right masks evenly
 // distributed that together add up to that minimum number of bits.
@@ -50,6 +84,32 @@ pub fn permute(
     u
 }
 
+// Batched version of `permute`: writes the permutation of each element of `indexes` into the
+// corresponding element of `permuted`. The round function is evaluated for all indexes at once
+// using the multi-way (SIMD) blake2b implementation, which is considerably faster than hashing
+// each input on its own. `buffers` holds the intermediate values, nothing is allocated once it
+// has grown to the size of the batch.
+pub fn permute_batch(
+    num_elements: Index,
+    indexes: &[Index],
+    permuted: &mut [Index],
+    round_keys: &FeistelRoundKeys,
+    precomputed: FeistelPrecomputed,
+    buffers: &mut FeistelBatchBuffers,
+) {
+    assert_eq!(indexes.len(), permuted.len(), "output length mismatch");
+
+    permuted.copy_from_slice(indexes);
+    buffers.pending.clear();
+    buffers.pending.extend(0..permuted.len());
+
+    // Same cycle walking as `permute`: keep encoding the elements which are out of range.
+    while !buffers.pending.is_empty() {
+        encode_batch(permuted, buffers, round_keys, precomputed);
+        buffers.pending.retain(|&i| permuted[i] >= num_elements);
+    }
+}
+
 // Inverts the `permute` result to its starting value for the same `key`.
 pub fn invert_permute(
     num_elements: Index,
@@ -90,6 +150,67 @@ fn encode(index: Index, keys: &[Index], precomputed: FeistelPrecomputed) -> Inde
     (left << half_bits) | right
 }
 
+// Encodes the elements of `values` at the positions `buffers.pending` in place.
+fn encode_batch(
+    values: &mut [Index],
+    buffers: &mut FeistelBatchBuffers,
+    round_keys: &FeistelRoundKeys,
+    precomputed: FeistelPrecomputed,
+) {
+    let FeistelBatchBuffers {
+        pending: lanes,
+        lefts,
+        rights,
+        inputs,
+    } = buffers;
+    let (_, right_mask, half_bits) = precomputed;
+    let params = Params::new();
+
+    lefts.clear();
+    rights.clear();
+    for &lane in lanes.iter() {
+        let (left, right, _, _) = common_setup(values[lane], precomputed);
+        lefts.push(left);
+        rights.push(right);
+    }
+    inputs.resize(lanes.len(), [0u8; FEISTEL_BYTES]);
+
+    for round_key in round_keys.0.iter() {
+        for (input, right) in inputs.iter_mut().zip(rights.iter()) {
+            input[..HALF_FEISTEL_BYTES].copy_from_slice(&right.to_be_bytes());
+            input[HALF_FEISTEL_BYTES..].copy_from_slice(round_key);
+        }
+
+        let chunks = inputs
+            .chunks(HASH_MANY_BATCH)
+            .zip(lefts.chunks_mut(HASH_MANY_BATCH))
+            .zip(rights.chunks_mut(HASH_MANY_BATCH));
+        for ((inputs, lefts), rights) in chunks {
+            // The jobs past the end of a short chunk are never hashed.
+            let mut jobs: [HashManyJob<'_>; HASH_MANY_BATCH] = std::array::from_fn(|i| {
+                HashManyJob::new(&params, &inputs[i.min(inputs.len() - 1)])
+            });
+            let jobs = &mut jobs[..inputs.len()];
+            hash_many(jobs.iter_mut());
+
+            for ((left, right), job) in lefts.iter_mut().zip(rights.iter_mut()).zip(jobs.iter()) {
+                let hash = job.to_hash();
+                let mut head = [0u8; HALF_FEISTEL_BYTES];
+                head.copy_from_slice(&hash.as_bytes()[..HALF_FEISTEL_BYTES]);
+                let f = Index::from_be_bytes(head) & right_mask;
+
+                let (l, r) = (*right, *left ^ f);
+                *left = l;
+                *right = r;
+            }
+        }
+    }
+
+    for ((&lane, left), right) in lanes.iter().zip(lefts.iter()).zip(rights.iter()) {
+        values[lane] = (left << half_bits) | right;
+    }
+}
+
 fn decode(index: Index, keys: &[Index], precomputed: FeistelPrecomputed) -> Index {
     let (mut left, mut right, right_mask, half_bits) = common_setup(index, precomputed);
 
@@ -229,6 +350,31 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_feistel_permute_batch() {
+        let keys = [1, 2, 3, 4];
+        let round_keys = FeistelRoundKeys::new(&keys);
+        let mut buffers = FeistelBatchBuffers::default();
+
+        for &n in BAD_NS.iter().chain(&[16, 64, 1000]) {
+            let precomputed = precompute(n);
+            let indexes: Vec<Index> = (0..n).collect();
+            let mut permuted = vec![0; indexes.len()];
+            permute_batch(
+                n,
+                &indexes,
+                &mut permuted,
+                &round_keys,
+                precomputed,
+                &mut buffers,
+            );
+
+            for (&i, &p) in indexes.iter().zip(&permuted) {
+                assert_eq!(p, permute(n, i, &keys, precomputed), "n = {}, i = {}", n, i);
+            }
+        }
+    }
+
     #[test]
     #[ignore]
     fn test_feistel_valid_permutation() {
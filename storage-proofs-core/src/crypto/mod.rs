@@ -1,3 +1,4 @@
+use filecoin_hashers::{HashFunction, Hasher};
 use sha2::{Digest, Sha256};
 
 pub mod aes;
@@ -7,6 +8,17 @@ pub mod xor;
 
 pub struct DomainSeparationTag(&'static str);
 
+impl DomainSeparationTag {
+    pub fn as_bytes(&self) -> &'static [u8] {
+        self.0.as_bytes()
+    }
+
+    /// Hashes `data` with `H`, domain separated by this tag.
+    pub fn hash<H: Hasher>(&self, data: &[u8]) -> H::Domain {
+        H::Function::hash_tagged(self.as_bytes(), data)
+    }
+}
+
 pub const DRSAMPLE_DST: DomainSeparationTag = DomainSeparationTag("Filecoin_DRSample");
 pub const FEISTEL_DST: DomainSeparationTag = DomainSeparationTag("Filecoin_Feistel");
 
@@ -15,7 +27,7 @@ pub fn derive_porep_domain_seed(
     porep_id: [u8; 32],
 ) -> [u8; 32] {
     Sha256::new()
-        .chain_update(domain_separation_tag.0)
+        .chain_update(domain_separation_tag.as_bytes())
         .chain_update(porep_id)
         .finalize()
         .into()
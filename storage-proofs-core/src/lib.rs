@@ -13,6 +13,7 @@ use std::convert::TryInto;
 
 pub mod api_version;
 pub mod cache_key;
+pub mod challenge_domain;
 pub mod compound_proof;
 pub mod crypto;
 pub mod data;
@@ -23,12 +24,15 @@ pub mod measurements;
 pub mod merkle;
 pub mod multi_proof;
 pub mod parameter_cache;
+pub mod paramgen;
 pub mod partitions;
 pub mod pieces;
 pub mod por;
 pub mod proof;
+pub mod proof_rng;
 pub mod sector;
 pub mod settings;
+pub mod synthesis_cache;
 pub mod test_helper;
 pub mod util;
 
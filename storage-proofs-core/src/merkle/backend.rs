@@ -0,0 +1,469 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Debug, Formatter};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{ensure, Context, Result};
+use filecoin_hashers::Hasher;
+use generic_array::typenum::Unsigned;
+use merkletree::store::{ExternalReader, LevelCacheStore, ReplicaConfig, StoreConfig};
+
+use crate::merkle::{
+    create_tree_from_stores, get_base_tree_leafs, MerkleTreeTrait, MerkleTreeWrapper,
+};
+
+/// Abstraction over where the base layer nodes of a tree live.
+///
+/// The cached levels of an `LCTree` are small and stay in local files, the base layer (the
+/// replica) is where the bulk of the data is. Implementing this trait allows to serve those
+/// reads from anywhere, e.g. a local file, a database column family or ranged requests against
+/// object storage.
+pub trait NodeBackend: Debug + Send + Sync {
+    /// Total length of the data in bytes.
+    fn len(&self) -> usize;
+
+    /// Fills `buf` with the bytes starting at `offset`.
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<()>;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<B: NodeBackend + ?Sized> NodeBackend for Arc<B> {
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<()> {
+        (**self).read_at(offset, buf)
+    }
+}
+
+/// A backend reading from a local file. Reads are positioned, so that concurrent readers don't
+/// contend for the file.
+pub struct FileBackend {
+    path: PathBuf,
+    len: usize,
+    file: File,
+}
+
+impl FileBackend {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = File::open(&path).with_context(|| format!("could not open path={:?}", path))?;
+        let len = file.metadata()?.len() as usize;
+
+        Ok(FileBackend { path, len, file })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Debug for FileBackend {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileBackend")
+            .field("path", &self.path)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+impl NodeBackend for FileBackend {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<()> {
+        ensure!(
+            offset + buf.len() <= self.len,
+            "read of {} bytes at {} out of bounds ({})",
+            buf.len(),
+            offset,
+            self.len
+        );
+
+        read_exact_at(&self.file, offset as u64, buf)
+            .with_context(|| format!("failed to read from {:?}", self.path))
+    }
+}
+
+#[cfg(unix)]
+fn read_exact_at(file: &File, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+    use std::os::unix::fs::FileExt;
+
+    file.read_exact_at(buf, offset)
+}
+
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut offset: u64, mut buf: &mut [u8]) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+
+    while !buf.is_empty() {
+        match file.seek_read(buf, offset) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(read) => {
+                buf = &mut buf[read..];
+                offset += read as u64;
+            }
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+struct PageCache {
+    pages: HashMap<usize, (u64, Arc<Vec<u8>>)>,
+    /// The cached page indexes by the tick they were last used at, the first one is evicted.
+    by_use: BTreeMap<u64, usize>,
+    tick: u64,
+}
+
+impl PageCache {
+    fn get(&mut self, index: usize) -> Option<Arc<Vec<u8>>> {
+        self.tick += 1;
+        let tick = self.tick;
+        let (used, page) = self.pages.get_mut(&index)?;
+        self.by_use.remove(used);
+        self.by_use.insert(tick, index);
+        *used = tick;
+        Some(page.clone())
+    }
+
+    fn insert(&mut self, index: usize, page: Arc<Vec<u8>>, max_pages: usize) {
+        if let Some((used, _)) = self.pages.remove(&index) {
+            self.by_use.remove(&used);
+        }
+        if self.pages.len() >= max_pages {
+            let lru = self.by_use.keys().next().copied();
+            if let Some(index) = lru.and_then(|used| self.by_use.remove(&used)) {
+                self.pages.remove(&index);
+            }
+        }
+        self.tick += 1;
+        self.by_use.insert(self.tick, index);
+        self.pages.insert(index, (self.tick, page));
+    }
+}
+
+/// Keeps the most recently used fixed size pages of an inner backend in memory.
+///
+/// Meant to sit in front of backends where every read is expensive (e.g. a network round
+/// trip), so that the nodes of neighbouring challenges are only fetched once.
+pub struct CachedBackend<B: NodeBackend> {
+    inner: B,
+    page_size: usize,
+    max_pages: usize,
+    cache: Mutex<PageCache>,
+}
+
+impl<B: NodeBackend> CachedBackend<B> {
+    pub fn new(inner: B, page_size: usize, max_pages: usize) -> Result<Self> {
+        ensure!(page_size > 0, "page size must be non-zero");
+        ensure!(max_pages > 0, "at least one page must be cached");
+
+        Ok(CachedBackend {
+            inner,
+            page_size,
+            max_pages,
+            cache: Mutex::new(PageCache::default()),
+        })
+    }
+
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Number of pages currently held in memory.
+    pub fn cached_pages(&self) -> usize {
+        self.cache
+            .lock()
+            .expect("page cache lock poisoned")
+            .pages
+            .len()
+    }
+
+    fn page(&self, index: usize) -> Result<Arc<Vec<u8>>> {
+        if let Some(page) = self
+            .cache
+            .lock()
+            .expect("page cache lock poisoned")
+            .get(index)
+        {
+            return Ok(page);
+        }
+
+        // Fetch without holding the lock, so that other pages can be served meanwhile.
+        let start = index * self.page_size;
+        let end = usize::min(start + self.page_size, self.inner.len());
+        let mut data = vec![0u8; end - start];
+        self.inner.read_at(start, &mut data)?;
+        let page = Arc::new(data);
+
+        self.cache.lock().expect("page cache lock poisoned").insert(
+            index,
+            page.clone(),
+            self.max_pages,
+        );
+
+        Ok(page)
+    }
+}
+
+impl<B: NodeBackend> Debug for CachedBackend<B> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CachedBackend")
+            .field("inner", &self.inner)
+            .field("page_size", &self.page_size)
+            .field("max_pages", &self.max_pages)
+            .finish()
+    }
+}
+
+impl<B: NodeBackend> NodeBackend for CachedBackend<B> {
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<()> {
+        ensure!(
+            offset + buf.len() <= self.len(),
+            "read of {} bytes at {} out of bounds ({})",
+            buf.len(),
+            offset,
+            self.len()
+        );
+
+        let mut pos = offset;
+        let mut written = 0;
+        while written < buf.len() {
+            let page = self.page(pos / self.page_size)?;
+            let page_offset = pos % self.page_size;
+            let count = usize::min(page.len() - page_offset, buf.len() - written);
+            buf[written..written + count].copy_from_slice(&page[page_offset..page_offset + count]);
+            written += count;
+            pos += count;
+        }
+
+        Ok(())
+    }
+}
+
+/// Adapter making a `NodeBackend` usable as the external reader of a `LevelCacheStore`.
+#[derive(Debug)]
+pub struct BackendReader<B: NodeBackend> {
+    backend: B,
+    pos: usize,
+}
+
+impl<B: NodeBackend> BackendReader<B> {
+    pub fn new(backend: B) -> Self {
+        BackendReader { backend, pos: 0 }
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+}
+
+impl<B: NodeBackend> Read for BackendReader<B> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = usize::min(buf.len(), self.backend.len().saturating_sub(self.pos));
+        self.backend
+            .read_at(self.pos, &mut buf[..count])
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        self.pos += count;
+        Ok(count)
+    }
+}
+
+fn read_from_backend<B: NodeBackend>(
+    start: usize,
+    end: usize,
+    buf: &mut [u8],
+    source: &BackendReader<B>,
+) -> Result<usize> {
+    source.backend.read_at(start, &mut buf[..end - start])?;
+    Ok(end - start)
+}
+
+/// Creates an `ExternalReader` for a `LevelCacheStore`, which reads the base layer at `offset`
+/// of the given backend.
+pub fn backend_external_reader<B: NodeBackend>(
+    backend: B,
+    offset: usize,
+) -> ExternalReader<BackendReader<B>> {
+    ExternalReader {
+        offset,
+        source: BackendReader::new(backend),
+        read_fn: read_from_backend::<B>,
+    }
+}
+
+/// An `LCTree` whose base layer is read through a `NodeBackend`.
+pub type BackendLCTree<H, U, V, W, B> =
+    MerkleTreeWrapper<H, LevelCacheStore<<H as Hasher>::Domain, BackendReader<B>>, U, V, W>;
+
+/// Like `create_lc_tree`, but every base layer tree (one per config) reads its leaves from the
+/// matching backend instead of the replica file.
+#[allow(clippy::type_complexity)]
+pub fn create_lc_tree_with_backends<Tree: MerkleTreeTrait, B: 'static + NodeBackend>(
+    base_tree_len: usize,
+    configs: &[StoreConfig],
+    backends: Vec<B>,
+) -> Result<BackendLCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity, B>> {
+    ensure!(
+        configs.len() == backends.len(),
+        "expected one backend per config ({} != {})",
+        backends.len(),
+        configs.len()
+    );
+
+    let backends = backends.into_iter().map(|backend| (backend, 0)).collect();
+    create_lc_tree_from_readers::<Tree, B>(base_tree_len, configs, backends)
+}
+
+/// Like `create_lc_tree`, but the replica is read through `backend`, at the offsets of the base
+/// trees given by `replica_config`.
+#[allow(clippy::type_complexity)]
+pub fn create_lc_tree_with_backend<Tree: MerkleTreeTrait, B: 'static + NodeBackend + Clone>(
+    base_tree_len: usize,
+    configs: &[StoreConfig],
+    replica_config: &ReplicaConfig,
+    backend: B,
+) -> Result<BackendLCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity, B>> {
+    ensure!(
+        configs.len() == replica_config.offsets.len(),
+        "expected one replica offset per config ({} != {})",
+        replica_config.offsets.len(),
+        configs.len()
+    );
+
+    let backends = replica_config
+        .offsets
+        .iter()
+        .map(|offset| (backend.clone(), *offset))
+        .collect();
+    create_lc_tree_from_readers::<Tree, B>(base_tree_len, configs, backends)
+}
+
+#[allow(clippy::type_complexity)]
+fn create_lc_tree_from_readers<Tree: MerkleTreeTrait, B: 'static + NodeBackend>(
+    base_tree_len: usize,
+    configs: &[StoreConfig],
+    backends: Vec<(B, usize)>,
+) -> Result<BackendLCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity, B>> {
+    let base_tree_leafs = get_base_tree_leafs::<Tree>(base_tree_len)?;
+    let stores = configs
+        .iter()
+        .zip(backends.into_iter())
+        .map(|(config, (backend, offset))| {
+            LevelCacheStore::new_from_disk_with_reader(
+                base_tree_len,
+                Tree::Arity::to_usize(),
+                config,
+                backend_external_reader(backend, offset),
+            )
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    create_tree_from_stores::<Tree::Hasher, _, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>(
+        base_tree_leafs,
+        stores,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    use rand::{Rng, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+    use tempfile::NamedTempFile;
+
+    use crate::TEST_SEED;
+
+    fn test_data(len: usize) -> (Vec<u8>, NamedTempFile) {
+        let mut rng = XorShiftRng::from_seed(TEST_SEED);
+        let data: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+        let mut file = NamedTempFile::new().expect("tempfile failure");
+        file.write_all(&data).expect("write failure");
+        file.flush().expect("flush failure");
+        (data, file)
+    }
+
+    #[test]
+    fn test_file_backend() {
+        let (data, file) = test_data(1000);
+        let backend = FileBackend::open(file.path()).expect("open failure");
+        assert_eq!(backend.len(), data.len());
+
+        let mut buf = [0u8; 100];
+        backend.read_at(450, &mut buf).expect("read failure");
+        assert_eq!(&buf[..], &data[450..550]);
+
+        assert!(backend.read_at(950, &mut buf).is_err());
+    }
+
+    #[test]
+    fn test_cached_backend() {
+        let (data, file) = test_data(1000);
+        let backend =
+            CachedBackend::new(FileBackend::open(file.path()).expect("open failure"), 64, 4)
+                .expect("cached backend failure");
+        assert_eq!(backend.len(), data.len());
+
+        // Spans several pages, including the short last one.
+        let mut buf = vec![0u8; 300];
+        for offset in &[0, 10, 500, 700] {
+            backend.read_at(*offset, &mut buf).expect("read failure");
+            assert_eq!(&buf[..], &data[*offset..*offset + 300]);
+            assert!(backend.cached_pages() <= 4);
+        }
+
+        let mut reader = BackendReader::new(backend);
+        let mut read_back = Vec::new();
+        reader
+            .read_to_end(&mut read_back)
+            .expect("read_to_end failure");
+        assert_eq!(read_back, data);
+    }
+
+    #[test]
+    fn test_page_cache_evicts_least_recently_used() {
+        let mut cache = PageCache::default();
+        for index in 0..3 {
+            cache.insert(index, Arc::new(vec![index as u8]), 3);
+        }
+        assert!(cache.get(0).is_some());
+
+        // Page 1 is the least recently used one now.
+        cache.insert(3, Arc::new(vec![3]), 3);
+        assert!(cache.get(1).is_none());
+        for index in [0, 2, 3] {
+            assert_eq!(cache.get(index).map(|page| page[0]), Some(index as u8));
+        }
+        assert_eq!(cache.pages.len(), 3);
+        assert_eq!(cache.by_use.len(), 3);
+    }
+
+    #[test]
+    fn test_backend_external_reader() {
+        let (data, file) = test_data(1024);
+        let reader =
+            backend_external_reader(FileBackend::open(file.path()).expect("open failure"), 256);
+
+        let mut buf = vec![0u8; 128];
+        let read = reader.read(0, 128, &mut buf).expect("read failure");
+        assert_eq!(read, 128);
+        assert_eq!(&buf[..], &data[256..384]);
+    }
+}
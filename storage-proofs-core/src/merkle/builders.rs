@@ -1,21 +1,28 @@
-use std::any::Any;
-use std::fs::File;
-use std::io::Write;
+use std::any::{Any, TypeId};
+use std::cmp::Ordering;
+use std::fs::{create_dir_all, metadata, read, remove_file, rename, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::mem::size_of;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
-use anyhow::ensure;
-use filecoin_hashers::{Domain, Hasher, PoseidonArity};
+use anyhow::{ensure, Context};
+use filecoin_hashers::{Domain, HashFunction, Hasher, PoseidonArity};
 use generic_array::typenum::{Unsigned, U0};
-use log::trace;
+use log::{trace, warn};
 use merkletree::{
     merkle::{
-        get_merkle_tree_leafs, is_merkle_tree_size_valid, FromIndexedParallelIterator, MerkleTree,
+        get_merkle_tree_cache_size, get_merkle_tree_leafs, get_merkle_tree_len,
+        get_merkle_tree_row_count, is_merkle_tree_size_valid, FromIndexedParallelIterator,
+        MerkleTree,
     },
     store::{DiskStore, ExternalReader, LevelCacheStore, ReplicaConfig, Store, StoreConfig},
 };
 use rand::Rng;
-use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use rayon::prelude::{
+    IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator, ParallelIterator,
+    ParallelSlice, ParallelSliceMut,
+};
 
 use crate::{
     error::{Error, Result},
@@ -27,6 +34,13 @@ use crate::{
 /// disk, but only cached in memory.
 type LCMerkleTree<H, U> = LCTree<H, U, U0, U0>;
 
+/// Number of parents hashed in a single batch when building tree levels.
+const HASH_BATCH_SIZE: usize = 256;
+
+/// Size in bytes of the windows of leafs that are read from the replica at once when rebuilding
+/// discarded rows of a level cache store.
+const REBUILD_WINDOW_SIZE: usize = 1 << 26;
+
 // Create a DiskTree from the provided config(s), each representing a 'base' layer tree with 'base_tree_len' elements.
 pub fn create_disk_tree<Tree: MerkleTreeTrait>(
     base_tree_len: usize,
@@ -175,6 +189,48 @@ where
     }
 }
 
+// Assembles a tree from already opened base layer stores, one per base tree ('base_tree_leafs'
+// leafs each), following the shape given by the arities.
+pub fn create_tree_from_stores<
+    H: 'static + Hasher,
+    S: Store<H::Domain>,
+    U: 'static + PoseidonArity,
+    V: 'static + PoseidonArity,
+    W: 'static + PoseidonArity,
+>(
+    base_tree_leafs: usize,
+    mut stores: Vec<S>,
+) -> Result<MerkleTreeWrapper<H, S, U, V, W>> {
+    ensure!(!stores.is_empty(), "Cannot create a tree without stores");
+    if stores.len() == 1 {
+        ensure!(
+            V::to_usize() == 0 && W::to_usize() == 0,
+            "Cannot have a sub/top tree without more than 1 config"
+        );
+        let store = stores.pop().expect("store failure");
+        return MerkleTreeWrapper::from_data_store(store, base_tree_leafs);
+    }
+
+    let trees = stores
+        .into_iter()
+        .map(|store| MerkleTreeWrapper::<H, S, U, U0, U0>::from_data_store(store, base_tree_leafs))
+        .collect::<Result<Vec<_>>>()?;
+
+    if W::to_usize() > 0 {
+        ensure!(
+            V::to_usize() > 0,
+            "Invalid top arity specified without sub arity"
+        );
+        MerkleTreeWrapper::from_sub_trees_as_trees(trees)
+    } else {
+        ensure!(
+            V::to_usize() > 0,
+            "Cannot create a tree from multiple configs without sub arity"
+        );
+        MerkleTreeWrapper::from_trees(trees)
+    }
+}
+
 // Note: This method verifies that the tree can be build with the size
 // specified.  If the data on disk is longer, this method is safe to
 // use on the first 'size' nodes.
@@ -234,6 +290,120 @@ pub fn create_base_merkle_tree<Tree: MerkleTreeTrait>(
     Ok(Tree::from_merkle(tree))
 }
 
+/// Builds a base merkle tree over `data`, just like [`create_base_merkle_tree`], but with bounded
+/// memory usage.
+///
+/// The leafs are processed in windows which are hashed up to their sub-tree root, with every level
+/// of the window written to its final position in the store on disk. The window roots are then
+/// merged into the top of the tree. The window size is chosen, so that the nodes held in memory
+/// at any point don't exceed `max_memory` bytes.
+pub fn create_base_merkle_tree_chunked<H: Hasher, U: PoseidonArity>(
+    config: StoreConfig,
+    size: usize,
+    data: &[u8],
+    max_memory: usize,
+) -> Result<DiskTree<H, U, U0, U0>> {
+    let arity = U::to_usize();
+    ensure!(
+        data.len() == (NODE_SIZE * size),
+        Error::InvalidMerkleTreeArgs(data.len(), NODE_SIZE, size)
+    );
+    ensure!(
+        is_merkle_tree_size_valid(size, arity),
+        "Invalid merkle tree size given the arity"
+    );
+
+    // A window of `n` leafs needs at most `2 * n` nodes in memory (the leafs plus all levels
+    // above them).
+    let mut window_leafs = arity;
+    let mut window_levels = 1;
+    while window_leafs * arity <= size && 2 * window_leafs * arity * NODE_SIZE <= max_memory {
+        window_leafs *= arity;
+        window_levels += 1;
+    }
+    ensure!(
+        2 * window_leafs * NODE_SIZE <= max_memory,
+        "max_memory of {} bytes is too small to build a tree of arity {}",
+        max_memory,
+        arity,
+    );
+    trace!(
+        "create_base_merkle_tree_chunked called with size {}, window of {} leafs",
+        size,
+        window_leafs,
+    );
+
+    // The offsets (in nodes) of all levels within the store, the leafs come first.
+    let mut level_offsets = vec![0];
+    let mut width = size;
+    while width > 1 {
+        level_offsets.push(level_offsets[level_offsets.len() - 1] + width);
+        width /= arity;
+    }
+    let tree_len = get_merkle_tree_len(size, arity)?;
+
+    let data_path = StoreConfig::data_path(&config.path, &config.id);
+    if let Some(parent) = data_path.parent() {
+        create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&data_path)?;
+    file.set_len((tree_len * NODE_SIZE) as u64)?;
+
+    let mut write_nodes = |level: usize, index: usize, nodes: &[H::Domain]| -> Result<()> {
+        let mut buf = vec![0u8; nodes.len() * NODE_SIZE];
+        buf.par_chunks_mut(NODE_SIZE)
+            .zip(nodes.par_iter())
+            .try_for_each(|(chunk, node)| node.write_bytes(chunk))?;
+        file.seek(SeekFrom::Start(
+            ((level_offsets[level] + index) * NODE_SIZE) as u64,
+        ))?;
+        file.write_all(&buf)?;
+        Ok(())
+    };
+
+    // Hashes `nodes` on `level` into the nodes of the next level, in batches of parents.
+    let hash_level = |level: usize, nodes: &[H::Domain]| -> Vec<H::Domain> {
+        nodes
+            .par_chunks(arity * HASH_BATCH_SIZE)
+            .flat_map_iter(|children| H::Function::hash_nodes_batch(children, arity, level))
+            .collect()
+    };
+
+    let mut window_roots = Vec::with_capacity(size / window_leafs);
+    for (window, window_data) in data.chunks(window_leafs * NODE_SIZE).enumerate() {
+        let mut nodes = window_data
+            .par_chunks(NODE_SIZE)
+            .map(H::Domain::try_from_bytes)
+            .collect::<Result<Vec<_>>>()?;
+        write_nodes(0, window * window_leafs, &nodes)?;
+
+        for level in 0..window_levels {
+            nodes = hash_level(level, &nodes);
+            write_nodes(level + 1, window * nodes.len(), &nodes)?;
+        }
+        debug_assert_eq!(nodes.len(), 1);
+        window_roots.push(nodes[0]);
+    }
+
+    // Merge the window roots into the top of the tree.
+    let mut nodes = window_roots;
+    let mut level = window_levels;
+    while nodes.len() > 1 {
+        nodes = hash_level(level, &nodes);
+        level += 1;
+        write_nodes(level, 0, &nodes)?;
+    }
+    file.sync_all()?;
+    drop(file);
+
+    let store = DiskStore::new_from_disk(tree_len, arity, &config)?;
+    DiskTree::from_data_store(store, size)
+}
+
 /// Construct a new level cache merkle tree, given the specified
 /// config.
 ///
@@ -276,6 +446,262 @@ pub fn create_base_lcmerkle_tree<H: Hasher, BaseTreeArity: 'static + PoseidonAri
     Ok(lc_tree)
 }
 
+/// Returns the `rows_to_discard` the level cache store of `config` was written with. It's derived
+/// from the size of the store on disk, which only holds the cached rows.
+pub fn get_stored_rows_to_discard(
+    config: &StoreConfig,
+    base_tree_leafs: usize,
+    arity: usize,
+) -> Result<usize> {
+    let data_path = StoreConfig::data_path(&config.path, &config.id);
+    let store_size = metadata(&data_path)
+        .with_context(|| format!("could not read metadata of {:?}", data_path))?
+        .len();
+
+    let row_count = get_merkle_tree_row_count(base_tree_leafs, arity);
+    (0..row_count.saturating_sub(1))
+        .find(|rows_to_discard| {
+            get_merkle_tree_cache_size(base_tree_leafs, arity, *rows_to_discard)
+                .map(|cache_size| (cache_size * NODE_SIZE) as u64 == store_size)
+                .unwrap_or(false)
+        })
+        .with_context(|| {
+            format!(
+                "{:?} is not a level cache store of a tree with {} leafs",
+                data_path, base_tree_leafs
+            )
+        })
+}
+
+/// Rebuilds the rows of the level cache store of `config`, which were discarded as it was written
+/// with `stored_rows_to_discard`, but are needed when it's opened with `config.rows_to_discard`.
+///
+/// The rows are hashed from the leafs in the replica, starting at byte `replica_offset`. The first
+/// stored row is hashed as well and compared to the store, so that a replica which doesn't match
+/// the tree is detected. The new store is written to a temporary file of its own, which
+/// atomically replaces the old store only once it is complete, so concurrent rebuilds of the same
+/// store don't interfere and readers never see a partial store.
+pub fn rebuild_discarded_rows<H: Hasher>(
+    config: &StoreConfig,
+    stored_rows_to_discard: usize,
+    replica_path: &Path,
+    replica_offset: usize,
+    base_tree_leafs: usize,
+    arity: usize,
+) -> Result<()> {
+    ensure!(
+        config.rows_to_discard < stored_rows_to_discard,
+        "no discarded rows to rebuild"
+    );
+    let data_path = StoreConfig::data_path(&config.path, &config.id);
+    let stored = read(&data_path).with_context(|| format!("could not read {:?}", data_path))?;
+
+    // Another process may have rebuilt the store since its rows were counted.
+    if (get_merkle_tree_cache_size(base_tree_leafs, arity, config.rows_to_discard)? * NODE_SIZE)
+        == stored.len()
+    {
+        return Ok(());
+    }
+    ensure!(
+        get_merkle_tree_cache_size(base_tree_leafs, arity, stored_rows_to_discard)? * NODE_SIZE
+            == stored.len(),
+        "{:?} was not stored with rows_to_discard {}",
+        data_path,
+        stored_rows_to_discard,
+    );
+
+    // Rows are numbered from the leafs (row 0) up. The rebuilt rows go in front of the stored
+    // ones, `row_offsets` are their offsets (in nodes) within the new store.
+    let first_row = config.rows_to_discard + 1;
+    let last_row = stored_rows_to_discard;
+    let mut row_offsets = Vec::with_capacity(last_row + 1 - first_row);
+    let mut rebuilt_len = 0;
+    let mut width = base_tree_leafs / arity.pow(first_row as u32);
+    for _ in first_row..=last_row {
+        row_offsets.push(rebuilt_len);
+        rebuilt_len += width;
+        width /= arity;
+    }
+
+    // A window must hash to whole nodes of the first stored row.
+    let mut window_leafs = arity.pow(last_row as u32 + 1);
+    ensure!(
+        window_leafs <= base_tree_leafs,
+        "invalid rows_to_discard {} for a tree with {} leafs",
+        stored_rows_to_discard,
+        base_tree_leafs,
+    );
+    while window_leafs * arity <= base_tree_leafs
+        && window_leafs * arity * NODE_SIZE <= REBUILD_WINDOW_SIZE
+    {
+        window_leafs *= arity;
+    }
+
+    let mut replica = File::open(replica_path)
+        .with_context(|| format!("could not open replica {:?}", replica_path))?;
+    replica.seek(SeekFrom::Start(replica_offset as u64))?;
+
+    let mut tmp_path = data_path.clone().into_os_string();
+    tmp_path.push(format!(
+        ".rebuild-{}-{:016x}",
+        std::process::id(),
+        rand::thread_rng().gen::<u64>(),
+    ));
+    let tmp_path = PathBuf::from(tmp_path);
+    let mut write_store = || -> Result<()> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp_path)?;
+        file.set_len((rebuilt_len * NODE_SIZE + stored.len()) as u64)?;
+
+        let mut buf = vec![0u8; window_leafs * NODE_SIZE];
+        for window in 0..base_tree_leafs / window_leafs {
+            replica.read_exact(&mut buf)?;
+            let mut nodes = buf
+                .par_chunks(NODE_SIZE)
+                .map(H::Domain::try_from_bytes)
+                .collect::<Result<Vec<_>>>()?;
+
+            for row in 1..=last_row + 1 {
+                nodes = nodes
+                    .par_chunks(arity * HASH_BATCH_SIZE)
+                    .flat_map_iter(|children| {
+                        H::Function::hash_nodes_batch(children, arity, row - 1)
+                    })
+                    .collect();
+                let mut bytes = vec![0u8; nodes.len() * NODE_SIZE];
+                bytes
+                    .par_chunks_mut(NODE_SIZE)
+                    .zip(nodes.par_iter())
+                    .try_for_each(|(chunk, node)| node.write_bytes(chunk))?;
+
+                let index = window * nodes.len();
+                if row > last_row {
+                    let expected = &stored[index * NODE_SIZE..(index + nodes.len()) * NODE_SIZE];
+                    ensure!(
+                        bytes == expected,
+                        "replica {:?} does not match the tree stored in {:?}",
+                        replica_path,
+                        data_path,
+                    );
+                } else if row >= first_row {
+                    file.seek(SeekFrom::Start(
+                        ((row_offsets[row - first_row] + index) * NODE_SIZE) as u64,
+                    ))?;
+                    file.write_all(&bytes)?;
+                }
+            }
+        }
+
+        file.seek(SeekFrom::Start((rebuilt_len * NODE_SIZE) as u64))?;
+        file.write_all(&stored)?;
+        file.sync_all()?;
+        drop(file);
+
+        rename(&tmp_path, &data_path)
+            .with_context(|| format!("could not replace {:?}", data_path))?;
+
+        Ok(())
+    };
+
+    write_store().map_err(|err| {
+        let _ = remove_file(&tmp_path);
+        err
+    })
+}
+
+/// Sets the `rows_to_discard` of the level cache stores of `configs` to the value the stores were
+/// written with, so that they can be opened as they are. Unlike [`prepare_lc_tree_configs`] the
+/// stores are never modified, a returned value that is larger than the configured one means that
+/// rows needed for proving with the configured value were discarded. Trees that are not level
+/// cache trees are left alone.
+pub fn stored_lc_tree_configs<Tree: MerkleTreeTrait>(
+    base_tree_leafs: usize,
+    configs: &mut [StoreConfig],
+) -> Result<()>
+where
+    Tree::Store: 'static,
+{
+    if TypeId::of::<Tree::Store>()
+        != TypeId::of::<LevelCacheStore<<Tree::Hasher as Hasher>::Domain, File>>()
+    {
+        return Ok(());
+    }
+
+    let arity = Tree::Arity::to_usize();
+    for config in configs.iter_mut() {
+        config.rows_to_discard = get_stored_rows_to_discard(config, base_tree_leafs, arity)?;
+    }
+
+    Ok(())
+}
+
+/// Makes sure the level cache stores of `configs` can be opened for proving with their
+/// `rows_to_discard`, which may differ from the value the stores were written with.
+///
+/// If a store holds more rows than needed, its config is changed to the stored value. If rows
+/// that are needed were discarded, they are rebuilt from the replica, see
+/// [`rebuild_discarded_rows`]. Trees that are not level cache trees are left alone.
+pub fn prepare_lc_tree_configs<Tree: MerkleTreeTrait>(
+    base_tree_leafs: usize,
+    configs: &mut [StoreConfig],
+    replica_config: &ReplicaConfig,
+) -> Result<()>
+where
+    Tree::Store: 'static,
+{
+    if TypeId::of::<Tree::Store>()
+        != TypeId::of::<LevelCacheStore<<Tree::Hasher as Hasher>::Domain, File>>()
+    {
+        return Ok(());
+    }
+    ensure!(
+        configs.len() == replica_config.offsets.len(),
+        "every base tree needs a replica offset"
+    );
+
+    let arity = Tree::Arity::to_usize();
+    for (config, replica_offset) in configs.iter_mut().zip(&replica_config.offsets) {
+        let stored_rows_to_discard = get_stored_rows_to_discard(config, base_tree_leafs, arity)?;
+        match stored_rows_to_discard.cmp(&config.rows_to_discard) {
+            Ordering::Equal => {}
+            Ordering::Less => {
+                trace!(
+                    "{} was stored with rows_to_discard {}, using it instead of {}",
+                    config.id,
+                    stored_rows_to_discard,
+                    config.rows_to_discard,
+                );
+                config.rows_to_discard = stored_rows_to_discard;
+            }
+            Ordering::Greater => {
+                warn!(
+                    "{} was stored with rows_to_discard {}, rebuilding the rows needed for \
+                     rows_to_discard {} from the replica",
+                    config.id, stored_rows_to_discard, config.rows_to_discard,
+                );
+                let start = Instant::now();
+                rebuild_discarded_rows::<Tree::Hasher>(
+                    config,
+                    stored_rows_to_discard,
+                    &replica_config.path,
+                    *replica_offset,
+                    base_tree_leafs,
+                    arity,
+                )?;
+                warn!(
+                    "rebuilt the discarded rows of {} in {:.2}s",
+                    config.id,
+                    start.elapsed().as_secs_f64(),
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
 // Given a StoreConfig, generate additional ones with appended numbers
 // to uniquely identify them and return the results.  If count is 1,
 // the original config is not modified.
@@ -497,3 +923,229 @@ where
         generate_base_tree::<R, Tree>(rng, nodes, temp_path)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::read;
+
+    use filecoin_hashers::{poseidon::PoseidonHasher, sha256::Sha256Hasher};
+    use generic_array::typenum::{U2, U8};
+    use rand::thread_rng;
+    use tempfile::tempdir;
+
+    fn test_chunked_tree<H: 'static + Hasher, U: 'static + PoseidonArity>(
+        leafs: usize,
+        max_memory: usize,
+    ) {
+        let mut rng = thread_rng();
+        let data: Vec<u8> = (0..leafs)
+            .flat_map(|_| H::Domain::random(&mut rng).into_bytes())
+            .collect();
+        let temp_dir = tempdir().expect("tempdir failure");
+
+        let expected_config = StoreConfig::new(temp_dir.path(), "expected".to_string(), 0);
+        let expected = create_base_merkle_tree::<DiskTree<H, U, U0, U0>>(
+            Some(expected_config.clone()),
+            leafs,
+            &data,
+        )
+        .expect("create_base_merkle_tree failure");
+
+        let config = StoreConfig::new(temp_dir.path(), "chunked".to_string(), 0);
+        let tree =
+            create_base_merkle_tree_chunked::<H, U>(config.clone(), leafs, &data, max_memory)
+                .expect("create_base_merkle_tree_chunked failure");
+
+        assert_eq!(tree.root(), expected.root());
+        assert_eq!(tree.len(), expected.len());
+        assert_eq!(
+            read(StoreConfig::data_path(&config.path, &config.id)).expect("read failure"),
+            read(StoreConfig::data_path(
+                &expected_config.path,
+                &expected_config.id
+            ))
+            .expect("read failure"),
+        );
+    }
+
+    #[test]
+    fn test_create_base_merkle_tree_chunked() {
+        // Windows of 16 leafs.
+        test_chunked_tree::<Sha256Hasher, U2>(1024, 32 * NODE_SIZE);
+        // A single window covering the whole tree.
+        test_chunked_tree::<Sha256Hasher, U2>(1024, 1 << 20);
+        // Windows of 64 leafs.
+        test_chunked_tree::<PoseidonHasher, U8>(4096, 128 * NODE_SIZE);
+    }
+
+    #[test]
+    fn test_rebuild_discarded_rows() {
+        type Tree = LCTree<PoseidonHasher, U8, U0, U0>;
+
+        let leafs = 4096;
+        let mut rng = thread_rng();
+        let data: Vec<u8> = (0..leafs)
+            .flat_map(|_| <PoseidonHasher as Hasher>::Domain::random(&mut rng).into_bytes())
+            .collect();
+        let temp_dir = tempdir().expect("tempdir failure");
+        let replica_path = temp_dir.path().join("replica");
+        std::fs::write(&replica_path, &data).expect("failed to write replica");
+        let replica_config = ReplicaConfig {
+            path: replica_path,
+            offsets: vec![0],
+        };
+
+        let expected_config = StoreConfig::new(temp_dir.path(), "expected".to_string(), 1);
+        let expected = create_base_lcmerkle_tree::<PoseidonHasher, U8>(
+            expected_config.clone(),
+            leafs,
+            &data,
+            &replica_config,
+        )
+        .expect("create_base_lcmerkle_tree failure");
+
+        let stored_config = StoreConfig::new(temp_dir.path(), "stored".to_string(), 3);
+        create_base_lcmerkle_tree::<PoseidonHasher, U8>(
+            stored_config.clone(),
+            leafs,
+            &data,
+            &replica_config,
+        )
+        .expect("create_base_lcmerkle_tree failure");
+        assert_eq!(
+            get_stored_rows_to_discard(&stored_config, leafs, 8).expect("stored rows failure"),
+            3
+        );
+
+        // Reading the configs leaves the store as it is.
+        let stored_data = read(StoreConfig::data_path(
+            &stored_config.path,
+            &stored_config.id,
+        ))
+        .expect("read failure");
+        let mut configs = vec![StoreConfig {
+            rows_to_discard: 1,
+            ..stored_config.clone()
+        }];
+        stored_lc_tree_configs::<Tree>(leafs, &mut configs)
+            .expect("stored_lc_tree_configs failure");
+        assert_eq!(configs[0].rows_to_discard, 3);
+        assert_eq!(
+            read(StoreConfig::data_path(
+                &stored_config.path,
+                &stored_config.id
+            ))
+            .expect("read failure"),
+            stored_data
+        );
+
+        // The rows that are needed with fewer discarded rows are rebuilt.
+        let mut configs = vec![StoreConfig {
+            rows_to_discard: 1,
+            ..stored_config.clone()
+        }];
+        prepare_lc_tree_configs::<Tree>(leafs, &mut configs, &replica_config)
+            .expect("prepare_lc_tree_configs failure");
+        assert_eq!(configs[0].rows_to_discard, 1);
+        assert_eq!(
+            read(StoreConfig::data_path(
+                &stored_config.path,
+                &stored_config.id
+            ))
+            .expect("read failure"),
+            read(StoreConfig::data_path(
+                &expected_config.path,
+                &expected_config.id
+            ))
+            .expect("read failure"),
+        );
+
+        assert!(!has_rebuild_files(temp_dir.path()));
+
+        // A rebuild that raced with the one above leaves the rebuilt store alone.
+        rebuild_discarded_rows::<PoseidonHasher>(&configs[0], 3, &replica_config.path, 0, leafs, 8)
+            .expect("rebuild_discarded_rows failure");
+        assert_eq!(
+            get_stored_rows_to_discard(&stored_config, leafs, 8).expect("stored rows failure"),
+            1
+        );
+
+        let tree_len = get_merkle_tree_len(leafs, 8).expect("tree len failure");
+        let tree = create_lc_tree::<Tree>(tree_len, &configs, &replica_config)
+            .expect("create_lc_tree failure");
+        assert_eq!(tree.root(), expected.root());
+        let proof = tree
+            .gen_cached_proof(1234, Some(1))
+            .expect("gen_cached_proof failure");
+        assert!(proof.verify());
+
+        // A store holding more rows than needed is opened as it is.
+        configs[0].rows_to_discard = 2;
+        prepare_lc_tree_configs::<Tree>(leafs, &mut configs, &replica_config)
+            .expect("prepare_lc_tree_configs failure");
+        assert_eq!(configs[0].rows_to_discard, 1);
+    }
+
+    #[test]
+    fn test_rebuild_discarded_rows_wrong_replica() {
+        let leafs = 512;
+        let data = vec![0u8; leafs * NODE_SIZE];
+        let temp_dir = tempdir().expect("tempdir failure");
+        let replica_path = temp_dir.path().join("replica");
+        std::fs::write(&replica_path, &data).expect("failed to write replica");
+        let replica_config = ReplicaConfig {
+            path: replica_path.clone(),
+            offsets: vec![0],
+        };
+
+        let config = StoreConfig::new(temp_dir.path(), "stored".to_string(), 2);
+        create_base_lcmerkle_tree::<Sha256Hasher, U8>(
+            config.clone(),
+            leafs,
+            &data,
+            &replica_config,
+        )
+        .expect("create_base_lcmerkle_tree failure");
+        let data_path = StoreConfig::data_path(&config.path, &config.id);
+        let stored = read(&data_path).expect("read failure");
+
+        std::fs::write(&replica_path, vec![1u8; leafs * NODE_SIZE])
+            .expect("failed to write replica");
+        let rebuild_config = StoreConfig {
+            rows_to_discard: 0,
+            ..config
+        };
+        assert!(rebuild_discarded_rows::<Sha256Hasher>(
+            &rebuild_config,
+            2,
+            &replica_path,
+            0,
+            leafs,
+            8
+        )
+        .is_err());
+        // The stored tree is left untouched, and so is the directory.
+        assert_eq!(read(&data_path).expect("read failure"), stored);
+        assert!(!has_rebuild_files(temp_dir.path()));
+    }
+
+    fn has_rebuild_files(dir: &Path) -> bool {
+        std::fs::read_dir(dir)
+            .expect("read_dir failure")
+            .map(|entry| entry.expect("read_dir failure").file_name())
+            .any(|name| name.to_string_lossy().contains(".rebuild-"))
+    }
+
+    #[test]
+    fn test_create_base_merkle_tree_chunked_too_little_memory() {
+        let data = vec![0u8; 64 * NODE_SIZE];
+        let temp_dir = tempdir().expect("tempdir failure");
+        let config = StoreConfig::new(temp_dir.path(), "chunked".to_string(), 0);
+        assert!(
+            create_base_merkle_tree_chunked::<Sha256Hasher, U8>(config, 64, &data, NODE_SIZE)
+                .is_err()
+        );
+    }
+}
@@ -8,10 +8,12 @@ use filecoin_hashers::Hasher;
 use generic_array::typenum::{U0, U2};
 use merkletree::store::LevelCacheStore;
 
+mod backend;
 mod builders;
 mod proof;
 mod tree;
 
+pub use backend::*;
 pub use builders::*;
 pub use proof::*;
 pub use tree::*;
@@ -6,12 +6,12 @@ use std::slice::Iter;
 
 use anyhow::{ensure, Result};
 use blstrs::Scalar as Fr;
-use filecoin_hashers::{Hasher, PoseidonArity};
+use filecoin_hashers::{Domain, Hasher, PoseidonArity};
 use generic_array::typenum::{Unsigned, U0};
 use merkletree::hash::Algorithm;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use crate::drgraph::graph_height;
+use crate::{drgraph::graph_height, util::NODE_SIZE};
 
 /// Trait to abstract over the concept of Merkle Proof.
 pub trait MerkleProofTrait: Clone + Serialize + DeserializeOwned + Debug + Sync + Send {
@@ -537,6 +537,113 @@ impl<
     }
 }
 
+/// Canonical binary encoding of merkle proofs.
+///
+/// All integers are little-endian and all hashes are `NODE_SIZE` bytes, in the byte
+/// representation of the hasher's domain. The shape of the proof is given by the arities of the
+/// type, so only the number of base path elements is encoded:
+///
+/// ```text
+/// base_len: u32 | leaf | root | base_len * element(BaseArity)
+///     [| element(SubTreeArity), if SubTreeArity > 0]
+///     [| element(TopTreeArity), if TopTreeArity > 0]
+///
+/// element(A) = index: u8 | (A - 1) sibling hashes, ordered by their position in the node
+/// ```
+impl<
+        H: Hasher,
+        BaseArity: 'static + PoseidonArity,
+        SubTreeArity: 'static + PoseidonArity,
+        TopTreeArity: 'static + PoseidonArity,
+    > MerkleProof<H, BaseArity, SubTreeArity, TopTreeArity>
+{
+    /// Number of bytes of the canonical encoding of a proof with `base_len` base path elements.
+    pub fn serialized_len(base_len: usize) -> usize {
+        let element_len = |arity: usize| {
+            if arity == 0 {
+                0
+            } else {
+                1 + (arity - 1) * NODE_SIZE
+            }
+        };
+
+        4 + 2 * NODE_SIZE
+            + base_len * element_len(BaseArity::to_usize())
+            + element_len(SubTreeArity::to_usize())
+            + element_len(TopTreeArity::to_usize())
+    }
+
+    /// Encodes the proof in its canonical binary format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let path = self.path();
+        let mut base_len = path.len();
+        if SubTreeArity::to_usize() > 0 {
+            base_len -= 1;
+        }
+        if TopTreeArity::to_usize() > 0 {
+            base_len -= 1;
+        }
+
+        let mut bytes = Vec::with_capacity(Self::serialized_len(base_len));
+        bytes.extend_from_slice(&(base_len as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.leaf().into_bytes());
+        bytes.extend_from_slice(&self.root().into_bytes());
+        for (hashes, index) in path {
+            bytes.push(index as u8);
+            for hash in hashes {
+                bytes.extend_from_slice(&hash.into_bytes());
+            }
+        }
+        bytes
+    }
+
+    /// Decodes a proof from its canonical binary format, see `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        ensure!(bytes.len() >= 4, "merkle proof bytes too short");
+        let mut base_len = [0u8; 4];
+        base_len.copy_from_slice(&bytes[..4]);
+        let base_len = u32::from_le_bytes(base_len) as usize;
+        ensure!(
+            bytes.len() == Self::serialized_len(base_len),
+            "invalid merkle proof length {}, expected {} for {} base path elements",
+            bytes.len(),
+            Self::serialized_len(base_len),
+            base_len
+        );
+
+        let leaf = H::Domain::try_from_bytes(&bytes[4..4 + NODE_SIZE])?;
+        let root = H::Domain::try_from_bytes(&bytes[4 + NODE_SIZE..4 + 2 * NODE_SIZE])?;
+
+        let arities = std::iter::repeat(BaseArity::to_usize())
+            .take(base_len)
+            .chain(Some(SubTreeArity::to_usize()).filter(|&arity| arity > 0))
+            .chain(Some(TopTreeArity::to_usize()).filter(|&arity| arity > 0));
+
+        let mut pos = 4 + 2 * NODE_SIZE;
+        let mut path = Vec::with_capacity(base_len + 2);
+        for arity in arities {
+            let index = bytes[pos] as usize;
+            ensure!(
+                index < arity,
+                "invalid path index {} for arity {}",
+                index,
+                arity
+            );
+            pos += 1;
+
+            let hashes = bytes[pos..pos + (arity - 1) * NODE_SIZE]
+                .chunks(NODE_SIZE)
+                .map(H::Domain::try_from_bytes)
+                .collect::<Result<Vec<_>>>()?;
+            pos += (arity - 1) * NODE_SIZE;
+
+            path.push((hashes, index));
+        }
+
+        Ok(Self::from_parts(leaf, root, path))
+    }
+}
+
 /// Converts a merkle_light proof to a SingleProof
 fn proof_to_single<H: Hasher, Arity: PoseidonArity, TargetArity: PoseidonArity>(
     proof: &merkletree::proof::Proof<H::Domain, Arity>,
@@ -764,7 +871,8 @@ mod tests {
     use super::*;
 
     use filecoin_hashers::{
-        blake2s::Blake2sHasher, poseidon::PoseidonHasher, sha256::Sha256Hasher, Domain,
+        blake2s::Blake2sHasher, blake3::Blake3Hasher, poseidon::PoseidonHasher,
+        sha256::Sha256Hasher, Domain,
     };
     use generic_array::typenum::{U2, U4, U8};
     use rand::thread_rng;
@@ -797,6 +905,77 @@ mod tests {
         }
     }
 
+    fn merkleproof_bytes_roundtrip<Tree: 'static + MerkleTreeTrait>() {
+        let nodes = 64 * get_base_tree_count::<Tree>();
+
+        let mut rng = thread_rng();
+        let (_data, tree) = generate_tree::<Tree, _>(&mut rng, nodes, None);
+
+        for i in 0..nodes {
+            let proof = tree.gen_proof(i).expect("gen_proof failure");
+            let bytes = proof.to_bytes();
+
+            let decoded = MerkleProof::<
+                Tree::Hasher,
+                Tree::Arity,
+                Tree::SubTreeArity,
+                Tree::TopTreeArity,
+            >::from_bytes(&bytes)
+            .expect("from_bytes failure");
+            assert!(
+                decoded.validate(i),
+                "failed to validate decoded merkle path"
+            );
+            assert_eq!(decoded.root(), proof.root());
+            assert_eq!(decoded.leaf(), proof.leaf());
+            assert_eq!(decoded.path(), proof.path());
+            assert_eq!(decoded.to_bytes(), bytes);
+
+            assert!(MerkleProof::<
+                Tree::Hasher,
+                Tree::Arity,
+                Tree::SubTreeArity,
+                Tree::TopTreeArity,
+            >::from_bytes(&bytes[..bytes.len() - 1])
+            .is_err());
+        }
+    }
+
+    #[test]
+    fn merkleproof_bytes_roundtrip_poseidon() {
+        merkleproof_bytes_roundtrip::<
+            MerkleTreeWrapper<
+                PoseidonHasher,
+                DiskStore<<PoseidonHasher as Hasher>::Domain>,
+                U8,
+                U0,
+                U0,
+            >,
+        >();
+        merkleproof_bytes_roundtrip::<
+            MerkleTreeWrapper<
+                PoseidonHasher,
+                DiskStore<<PoseidonHasher as Hasher>::Domain>,
+                U8,
+                U4,
+                U2,
+            >,
+        >();
+    }
+
+    #[test]
+    fn merkleproof_bytes_roundtrip_sha256() {
+        merkleproof_bytes_roundtrip::<
+            MerkleTreeWrapper<
+                Sha256Hasher,
+                DiskStore<<Sha256Hasher as Hasher>::Domain>,
+                U2,
+                U4,
+                U0,
+            >,
+        >();
+    }
+
     #[test]
     fn merklepath_poseidon_2() {
         merklepath::<
@@ -965,4 +1144,30 @@ mod tests {
             >,
         >();
     }
+
+    #[test]
+    fn merklepath_blake3_2() {
+        merklepath::<
+            MerkleTreeWrapper<
+                Blake3Hasher,
+                DiskStore<<Blake3Hasher as Hasher>::Domain>,
+                U2,
+                U0,
+                U0,
+            >,
+        >();
+    }
+
+    #[test]
+    fn merklepath_blake3_8_4_2() {
+        merklepath::<
+            MerkleTreeWrapper<
+                Blake3Hasher,
+                DiskStore<<Blake3Hasher as Hasher>::Domain>,
+                U8,
+                U4,
+                U2,
+            >,
+        >();
+    }
 }
@@ -1,11 +1,12 @@
 #![allow(clippy::len_without_is_empty)]
 
 use std::fmt::{self, Debug, Formatter};
+use std::io::{self, Read};
 use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
 
-use anyhow::Result;
-use filecoin_hashers::{Hasher, PoseidonArity};
+use anyhow::{bail, Result};
+use filecoin_hashers::{Domain, Hasher, PoseidonArity};
 use generic_array::typenum::U0;
 use merkletree::{
     hash::Hashable,
@@ -14,7 +15,10 @@ use merkletree::{
 };
 use rayon::prelude::{IndexedParallelIterator, IntoParallelIterator};
 
-use crate::merkle::{LCTree, MerkleProof, MerkleProofTrait};
+use crate::{
+    merkle::{LCTree, MerkleProof, MerkleProofTrait},
+    util::NODE_SIZE,
+};
 
 /// Trait used to abstract over the way Merkle Trees are constructed and stored.
 pub trait MerkleTreeTrait: Send + Sync + Debug {
@@ -184,6 +188,11 @@ impl<
         Ok(tree.into())
     }
 
+    pub fn from_byte_slice(data: &[u8]) -> Result<Self> {
+        let tree = MerkleTree::from_byte_slice(data)?;
+        Ok(tree.into())
+    }
+
     pub fn from_byte_slice_with_config(data: &[u8], config: StoreConfig) -> Result<Self> {
         let tree = MerkleTree::from_byte_slice_with_config(data, config)?;
         Ok(tree.into())
@@ -305,6 +314,69 @@ impl<
         let tree = MerkleTree::from_par_iter_with_config(par_iter, config)?;
         Ok(tree.into())
     }
+
+    /// Builds a tree over the nodes read from `reader` until its end, e.g. data that arrives over
+    /// the network. The data must consist of whole nodes that are valid domain elements.
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self> {
+        Self::try_from_iter(NodeReader::<R, H::Domain>::new(reader))
+    }
+
+    /// Like [`Self::from_reader`], the tree is backed by the store described by `config`.
+    pub fn from_reader_with_config<R: Read>(reader: R, config: StoreConfig) -> Result<Self> {
+        Self::try_from_iter_with_config(NodeReader::<R, H::Domain>::new(reader), config)
+    }
+}
+
+/// Iterates over the nodes read from a reader.
+struct NodeReader<R: Read, D: Domain> {
+    reader: R,
+    done: bool,
+    _d: PhantomData<D>,
+}
+
+impl<R: Read, D: Domain> NodeReader<R, D> {
+    fn new(reader: R) -> Self {
+        NodeReader {
+            reader,
+            done: false,
+            _d: PhantomData,
+        }
+    }
+
+    // Returns `None` at the end of the reader.
+    fn read_node(&mut self) -> Result<Option<D>> {
+        let mut buf = [0u8; NODE_SIZE];
+        let mut filled = 0;
+        while filled < NODE_SIZE {
+            match self.reader.read(&mut buf[filled..]) {
+                Ok(0) => break,
+                Ok(read) => filled += read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        match filled {
+            0 => Ok(None),
+            NODE_SIZE => D::try_from_bytes(&buf).map(Some),
+            _ => bail!("data ends with a partial node of {} bytes", filled),
+        }
+    }
+}
+
+impl<R: Read, D: Domain> Iterator for NodeReader<R, D> {
+    type Item = Result<D>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let node = self.read_node().transpose();
+        // Stop after the end of the reader or the first error.
+        self.done = !matches!(node, Some(Ok(_)));
+        node
+    }
 }
 
 impl<
@@ -350,3 +422,55 @@ impl<
         &mut self.inner
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use filecoin_hashers::{poseidon::PoseidonHasher, sha256::Sha256Hasher};
+    use generic_array::typenum::{U2, U8};
+    use merkletree::store::VecStore;
+    use rand::thread_rng;
+    use tempfile::tempdir;
+
+    use crate::merkle::{create_base_merkle_tree, DiskTree};
+
+    fn test_from_reader<H: 'static + Hasher, U: 'static + PoseidonArity>(leafs: usize) {
+        let mut rng = thread_rng();
+        let data: Vec<u8> = (0..leafs)
+            .flat_map(|_| H::Domain::random(&mut rng).into_bytes())
+            .collect();
+        let expected = create_base_merkle_tree::<DiskTree<H, U, U0, U0>>(None, leafs, &data)
+            .expect("create_base_merkle_tree failure");
+
+        let tree =
+            MerkleTreeWrapper::<H, VecStore<H::Domain>, U, U0, U0>::from_reader(Cursor::new(&data))
+                .expect("from_reader failure");
+        assert_eq!(tree.root(), expected.root());
+        assert_eq!(tree.leaves(), leafs);
+
+        let temp_dir = tempdir().expect("tempdir failure");
+        let config = StoreConfig::new(temp_dir.path(), "from-reader".to_string(), 0);
+        let tree = DiskTree::<H, U, U0, U0>::from_reader_with_config(Cursor::new(&data), config)
+            .expect("from_reader_with_config failure");
+        assert_eq!(tree.root(), expected.root());
+
+        // A partial node at the end is rejected.
+        let truncated = &data[..data.len() - 1];
+        assert!(
+            MerkleTreeWrapper::<H, VecStore<H::Domain>, U, U0, U0>::from_reader(truncated).is_err()
+        );
+    }
+
+    #[test]
+    fn test_from_reader_sha256_binary() {
+        test_from_reader::<Sha256Hasher, U2>(64);
+    }
+
+    #[test]
+    fn test_from_reader_poseidon_oct() {
+        test_from_reader::<PoseidonHasher, U8>(64);
+    }
+}
@@ -441,10 +441,7 @@ pub fn verify_production_entry(
                 }
 
                 info!("parameter data is VALID [{}]", digest_hex);
-                VERIFIED_PARAMETERS
-                    .lock()
-                    .expect("verified parameters lock failed")
-                    .insert(cache_key);
+                mark_parameters_verified(cache_key);
             }
         }
         None => {
@@ -455,6 +452,15 @@ pub fn verify_production_entry(
     Ok(true)
 }
 
+/// Records that the parameter file named `cache_key` was verified against the manifest, so that
+/// [`verify_production_entry`] doesn't hash it again.
+pub fn mark_parameters_verified(cache_key: String) {
+    VERIFIED_PARAMETERS
+        .lock()
+        .expect("verified parameters lock failed")
+        .insert(cache_key);
+}
+
 /// Reads parameter from parameter cache.
 pub fn read_cached_params(cache_entry_path: &Path) -> Result<Bls12GrothParams> {
     info!("checking cache_path: {:?} for parameters", cache_entry_path);
@@ -596,7 +602,7 @@ fn read_cached_metadata(cache_entry_path: &Path) -> io::Result<CacheEntryMetadat
     })
 }
 
-fn write_cached_metadata(
+pub(crate) fn write_cached_metadata(
     cache_entry_path: &Path,
     value: CacheEntryMetadata,
 ) -> io::Result<CacheEntryMetadata> {
@@ -608,7 +614,7 @@ fn write_cached_metadata(
     })
 }
 
-fn write_cached_verifying_key(
+pub(crate) fn write_cached_verifying_key(
     cache_entry_path: &Path,
     value: groth16::VerifyingKey<Bls12>,
 ) -> io::Result<groth16::VerifyingKey<Bls12>> {
@@ -634,7 +640,7 @@ fn write_cached_srs_key(
     })
 }
 
-fn write_cached_params(
+pub(crate) fn write_cached_params(
     cache_entry_path: &Path,
     value: groth16::Parameters<Bls12>,
 ) -> io::Result<groth16::Parameters<Bls12>> {
@@ -0,0 +1,254 @@
+//! In-process generation of Groth16 parameters.
+//!
+//! The parameters generated here are NOT SECURE, the toxic waste of the initial setup is known to
+//! the process that generated them. They are meant for tests and for bootstrapping circuit
+//! configurations (e.g. custom sector sizes) that have no published parameters. Phase2 MPC
+//! contributions can be applied through the [`Phase2Contribution`] hook, the resulting
+//! transcript can be checked with [`verify_transcript`].
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+use anyhow::{ensure, Context};
+use bellperson::{groth16, Circuit};
+use blstrs::{pairing, Bls12, G1Affine, G2Affine, Scalar as Fr};
+use ff::Field;
+use log::info;
+use rand::RngCore;
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::{
+    error::Result,
+    parameter_cache::{
+        metadata_id, parameter_id, verifying_key_id, write_cached_metadata, write_cached_params,
+        write_cached_verifying_key, CacheableParameters, ParameterSetMetadata,
+    },
+};
+
+/// A phase2 MPC contribution to Groth16 parameters.
+pub trait Phase2Contribution {
+    /// Applies the contribution to `params` in place. A contribution may only change `delta` and
+    /// the `h` and `l` queries, see [`rescale_delta`].
+    fn contribute(&mut self, params: &mut groth16::Parameters<Bls12>) -> Result<()>;
+}
+
+/// A contribution that rescales `delta` by a random scalar, which is then discarded.
+#[derive(Debug)]
+pub struct RandomContribution<R: RngCore> {
+    rng: R,
+}
+
+impl<R: RngCore> RandomContribution<R> {
+    pub fn new(rng: R) -> Self {
+        RandomContribution { rng }
+    }
+}
+
+impl<R: RngCore> Phase2Contribution for RandomContribution<R> {
+    fn contribute(&mut self, params: &mut groth16::Parameters<Bls12>) -> Result<()> {
+        rescale_delta(params, Fr::random(&mut self.rng))
+    }
+}
+
+/// Multiplies `delta` of `params` by `factor` and divides the `h` and `l` queries by it, which
+/// keeps the parameters valid for the same circuit.
+pub fn rescale_delta(params: &mut groth16::Parameters<Bls12>, factor: Fr) -> Result<()> {
+    let factor_inv: Fr = Option::from(factor.invert()).context("delta factor must not be zero")?;
+
+    params.vk.delta_g1 = G1Affine::from(params.vk.delta_g1 * factor);
+    params.vk.delta_g2 = G2Affine::from(params.vk.delta_g2 * factor);
+
+    let rescale = |query: &[G1Affine]| -> Vec<G1Affine> {
+        query
+            .par_iter()
+            .map(|point| G1Affine::from(point * factor_inv))
+            .collect()
+    };
+    params.h = Arc::new(rescale(params.h.as_slice()));
+    params.l = Arc::new(rescale(params.l.as_slice()));
+
+    Ok(())
+}
+
+/// Generates random parameters for `circuit` and applies `contributions` to them in order.
+///
+/// Returns the parameters and the transcript, i.e. the verifying key of the initial parameters
+/// followed by the verifying key after each contribution.
+pub fn generate_params<C: Circuit<Fr>, R: RngCore>(
+    circuit: C,
+    rng: &mut R,
+    contributions: &mut [&mut dyn Phase2Contribution],
+) -> Result<(
+    groth16::Parameters<Bls12>,
+    Vec<groth16::VerifyingKey<Bls12>>,
+)> {
+    let start = Instant::now();
+    let mut params = groth16::generate_random_parameters::<Bls12, _, _>(circuit, rng)?;
+    info!(
+        "paramgen: generated random parameters in {:?}",
+        start.elapsed()
+    );
+
+    let mut transcript = Vec::with_capacity(contributions.len() + 1);
+    transcript.push(params.vk.clone());
+    for (i, contribution) in contributions.iter_mut().enumerate() {
+        contribution
+            .contribute(&mut params)
+            .with_context(|| format!("phase2 contribution {} failed", i))?;
+        ensure!(
+            verify_contribution(&transcript[transcript.len() - 1], &params.vk),
+            "phase2 contribution {} is inconsistent",
+            i
+        );
+        transcript.push(params.vk.clone());
+    }
+
+    Ok((params, transcript))
+}
+
+/// Checks that `after` is derived from `before` by rescaling `delta`, and that nothing else of
+/// the verifying key changed.
+///
+/// This does not check the `h` and `l` queries of the parameters.
+pub fn verify_contribution(
+    before: &groth16::VerifyingKey<Bls12>,
+    after: &groth16::VerifyingKey<Bls12>,
+) -> bool {
+    if bool::from(after.delta_g1.is_identity()) || bool::from(after.delta_g2.is_identity()) {
+        return false;
+    }
+
+    before.alpha_g1 == after.alpha_g1
+        && before.beta_g1 == after.beta_g1
+        && before.beta_g2 == after.beta_g2
+        && before.gamma_g2 == after.gamma_g2
+        && before.ic == after.ic
+        && pairing(&before.delta_g1, &after.delta_g2) == pairing(&after.delta_g1, &before.delta_g2)
+}
+
+/// Checks every contribution of a transcript returned by [`generate_params`].
+pub fn verify_transcript(transcript: &[groth16::VerifyingKey<Bls12>]) -> bool {
+    !transcript.is_empty()
+        && transcript
+            .windows(2)
+            .all(|pair| verify_contribution(&pair[0], &pair[1]))
+}
+
+/// The files written by [`generate_parameter_set`].
+#[derive(Debug)]
+pub struct GeneratedParameters {
+    pub cache_id: String,
+    pub params_path: PathBuf,
+    pub verifying_key_path: PathBuf,
+    pub metadata_path: PathBuf,
+    pub transcript: Vec<groth16::VerifyingKey<Bls12>>,
+}
+
+/// Generates the parameters of the circuit `CP` for `pub_params` and writes them into `out_dir`,
+/// named the way the parameter cache expects them. The directory can then be used as
+/// `FIL_PROOFS_PARAMETER_CACHE`, with `FIL_PROOFS_VERIFY_PRODUCTION_PARAMS` disabled.
+///
+/// Existing files are never overwritten.
+pub fn generate_parameter_set<C, P, CP, R>(
+    out_dir: &Path,
+    circuit: C,
+    pub_params: &P,
+    rng: &mut R,
+    contributions: &mut [&mut dyn Phase2Contribution],
+) -> Result<GeneratedParameters>
+where
+    C: Circuit<Fr>,
+    P: ParameterSetMetadata,
+    CP: CacheableParameters<C, P>,
+    R: RngCore,
+{
+    let cache_id = CP::cache_identifier(pub_params);
+    let params_path = out_dir.join(parameter_id(&cache_id));
+    let verifying_key_path = out_dir.join(verifying_key_id(&cache_id));
+    let metadata_path = out_dir.join(metadata_id(&cache_id));
+    for path in &[&params_path, &verifying_key_path, &metadata_path] {
+        ensure!(!path.exists(), "{} already exists", path.display());
+    }
+
+    info!("paramgen: generating parameters (id: {})", cache_id);
+    let (params, transcript) = generate_params(circuit, rng, contributions)?;
+
+    write_cached_params(&params_path, params)?;
+    write_cached_verifying_key(
+        &verifying_key_path,
+        transcript[transcript.len() - 1].clone(),
+    )?;
+    write_cached_metadata(&metadata_path, CP::cache_meta(pub_params))?;
+    info!("paramgen: wrote parameters to {:?}", out_dir);
+
+    Ok(GeneratedParameters {
+        cache_id,
+        params_path,
+        verifying_key_path,
+        metadata_path,
+        transcript,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bellperson::{ConstraintSystem, SynthesisError};
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+
+    use crate::TEST_SEED;
+
+    #[derive(Clone)]
+    struct SquareCircuit {
+        x: Option<Fr>,
+    }
+
+    impl Circuit<Fr> for SquareCircuit {
+        fn synthesize<CS: ConstraintSystem<Fr>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let x_value = self.x;
+            let x = cs.alloc(|| "x", || x_value.ok_or(SynthesisError::AssignmentMissing))?;
+            let y = cs.alloc_input(
+                || "y",
+                || {
+                    x_value
+                        .map(|x| x.square())
+                        .ok_or(SynthesisError::AssignmentMissing)
+                },
+            )?;
+            cs.enforce(|| "x * x = y", |lc| lc + x, |lc| lc + x, |lc| lc + y);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_generate_params_with_contributions() {
+        let mut rng = XorShiftRng::from_seed(TEST_SEED);
+        let mut first = RandomContribution::new(XorShiftRng::from_seed([1; 16]));
+        let mut second = RandomContribution::new(XorShiftRng::from_seed([2; 16]));
+
+        let (params, transcript) = generate_params(
+            SquareCircuit { x: None },
+            &mut rng,
+            &mut [&mut first, &mut second],
+        )
+        .expect("failed to generate params");
+        assert_eq!(transcript.len(), 3);
+        assert!(verify_transcript(&transcript));
+        assert!(transcript[0].delta_g1 != transcript[2].delta_g1);
+
+        let x = Fr::from(3u64);
+        let proof = groth16::create_random_proof(SquareCircuit { x: Some(x) }, &params, &mut rng)
+            .expect("failed to create proof");
+        let pvk = groth16::prepare_verifying_key(&params.vk);
+        assert!(groth16::verify_proof(&pvk, &proof, &[x.square()]).expect("failed to verify"));
+        assert!(!groth16::verify_proof(&pvk, &proof, &[x]).expect("failed to verify"));
+
+        // A key whose delta was replaced rather than rescaled is no valid contribution.
+        let mut forged = transcript[2].clone();
+        forged.delta_g1 = transcript[0].delta_g1;
+        assert!(!verify_contribution(&transcript[1], &forged));
+    }
+}
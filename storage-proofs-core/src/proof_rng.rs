@@ -0,0 +1,104 @@
+#[cfg(feature = "deterministic-proofs")]
+use std::sync::Mutex;
+
+#[cfg(feature = "deterministic-proofs")]
+use lazy_static::lazy_static;
+#[cfg(feature = "deterministic-proofs")]
+use log::warn;
+#[cfg(feature = "deterministic-proofs")]
+use rand::SeedableRng;
+use rand::{rngs::OsRng, RngCore};
+#[cfg(feature = "deterministic-proofs")]
+use rand_chacha::ChaCha20Rng;
+
+#[cfg(feature = "deterministic-proofs")]
+lazy_static! {
+    static ref PROOF_SEED: Mutex<Option<[u8; 32]>> = Mutex::new(None);
+}
+
+/// Makes every Groth16 proof draw its `r` and `s` randomness from a ChaCha20 rng seeded with
+/// `seed`, so that proving the same circuit twice results in byte-identical proofs. `None` restores
+/// the default of drawing them from the OS.
+///
+/// Proofs generated this way are NOT zero-knowledge, they leak the witness to anyone who knows the
+/// seed. This is meant for tests only, e.g. to compare proofs across implementations, never enable
+/// the `deterministic-proofs` feature in production.
+#[cfg(feature = "deterministic-proofs")]
+pub fn set_proof_seed(seed: Option<[u8; 32]>) {
+    if seed.is_some() {
+        warn!("deterministic-proofs: generating INSECURE proofs from a fixed seed");
+    }
+    *PROOF_SEED.lock().expect("proof seed poisoned") = seed;
+}
+
+/// Returns the rng the randomness of Groth16 proofs is drawn from. It is `OsRng`, unless a seed
+/// was set with `set_proof_seed`, in which case a new rng is seeded for every batch of proofs.
+#[cfg(feature = "deterministic-proofs")]
+pub fn proof_rng() -> Box<dyn RngCore> {
+    match *PROOF_SEED.lock().expect("proof seed poisoned") {
+        Some(seed) => Box::new(ChaCha20Rng::from_seed(seed)),
+        None => Box::new(OsRng),
+    }
+}
+
+/// Returns the rng the randomness of Groth16 proofs is drawn from.
+#[cfg(not(feature = "deterministic-proofs"))]
+pub fn proof_rng() -> Box<dyn RngCore> {
+    Box::new(OsRng)
+}
+
+#[cfg(all(test, feature = "deterministic-proofs"))]
+mod tests {
+    use super::*;
+
+    use bellperson::{gadgets::num::AllocatedNum, groth16, Circuit, ConstraintSystem};
+    use blstrs::{Bls12, Scalar as Fr};
+    use rand_xorshift::XorShiftRng;
+
+    use crate::TEST_SEED;
+
+    struct Square(Option<Fr>);
+
+    impl Circuit<Fr> for Square {
+        fn synthesize<CS: ConstraintSystem<Fr>>(
+            self,
+            cs: &mut CS,
+        ) -> Result<(), bellperson::SynthesisError> {
+            let x = AllocatedNum::alloc(cs.namespace(|| "x"), || {
+                self.0.ok_or(bellperson::SynthesisError::AssignmentMissing)
+            })?;
+            x.square(cs.namespace(|| "x^2"))?
+                .inputize(cs.namespace(|| "y"))
+        }
+    }
+
+    fn prove(params: &groth16::Parameters<Bls12>) -> Vec<u8> {
+        let circuit = Square(Some(Fr::from(3u64)));
+        let proofs = groth16::create_random_proof_batch(vec![circuit], params, &mut proof_rng())
+            .expect("failed to create proof");
+        let mut bytes = Vec::new();
+        proofs[0].write(&mut bytes).expect("failed to write proof");
+        bytes
+    }
+
+    #[test]
+    fn test_deterministic_proofs() {
+        let mut rng = XorShiftRng::from_seed(TEST_SEED);
+        let params = groth16::generate_random_parameters::<Bls12, _, _>(Square(None), &mut rng)
+            .expect("failed to generate params");
+
+        set_proof_seed(Some([7; 32]));
+        let first = prove(&params);
+        let second = prove(&params);
+        set_proof_seed(None);
+        let random = prove(&params);
+
+        assert_eq!(first, second);
+        assert_ne!(first, random);
+
+        let pvk = groth16::prepare_verifying_key(&params.vk);
+        let proof = groth16::Proof::<Bls12>::read(&first[..]).expect("failed to read proof");
+        let inputs = [Fr::from(9u64)];
+        assert!(groth16::verify_proof(&pvk, &proof, &inputs).expect("failed to verify"));
+    }
+}
@@ -1,9 +1,20 @@
 use std::collections::BTreeSet;
-use std::fmt::{self, Display, Formatter};
+use std::fmt::{self, Debug, Display, Formatter};
+use std::str::FromStr;
 
+use anyhow::{ensure, Context};
 use blstrs::Scalar as Fr;
 use byteorder::{ByteOrder, LittleEndian};
-use serde::{Deserialize, Serialize};
+use rand::{
+    distributions::{Distribution, Standard},
+    Rng,
+};
+use serde::{
+    de::{self, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::error::Result;
 
 /// An ordered set of `SectorId`s.
 pub type OrderedSectorSet = BTreeSet<SectorId>;
@@ -45,3 +56,206 @@ impl SectorId {
         buf
     }
 }
+
+fn parse_bytes32(s: &str, what: &str) -> Result<[u8; 32]> {
+    let bytes = hex::decode(s.trim_start_matches("0x"))
+        .with_context(|| format!("{} is not hex encoded", what))?;
+    ensure!(
+        bytes.len() == 32,
+        "{} must be 32 bytes, got {}",
+        what,
+        bytes.len()
+    );
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&bytes);
+    Ok(out)
+}
+
+// Accepts the hex encoding of 32 bytes, as well as a sequence of them, which is how `[u8; 32]` is
+// serialized in human readable formats.
+struct Bytes32Visitor(&'static str);
+
+impl<'de> Visitor<'de> for Bytes32Visitor {
+    type Value = [u8; 32];
+
+    fn expecting(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "a hex encoded {} of 32 bytes", self.0)
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> std::result::Result<Self::Value, E> {
+        parse_bytes32(s, self.0).map_err(E::custom)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(
+        self,
+        mut seq: A,
+    ) -> std::result::Result<Self::Value, A::Error> {
+        let mut bytes = [0u8; 32];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = seq
+                .next_element()?
+                .ok_or_else(|| de::Error::invalid_length(i, &self))?;
+        }
+        if seq.next_element::<u8>()?.is_some() {
+            return Err(de::Error::invalid_length(33, &self));
+        }
+        Ok(bytes)
+    }
+}
+
+// Defines a newtype around 32 bytes. It is serialized as hex string in human readable formats
+// (e.g. JSON) and as plain bytes otherwise, which keeps binary encodings (e.g. bincode) compatible
+// with the `[u8; 32]` it replaces.
+macro_rules! bytes32_newtype {
+    ($(#[$meta:meta])* $name:ident, $what:expr) => {
+        $(#[$meta])*
+        #[derive(Default, Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
+        pub struct $name(pub [u8; 32]);
+
+        impl $name {
+            pub fn as_bytes(&self) -> &[u8; 32] {
+                &self.0
+            }
+
+            pub fn into_bytes(self) -> [u8; 32] {
+                self.0
+            }
+        }
+
+        impl From<[u8; 32]> for $name {
+            fn from(bytes: [u8; 32]) -> Self {
+                $name(bytes)
+            }
+        }
+
+        impl From<$name> for [u8; 32] {
+            fn from(value: $name) -> Self {
+                value.0
+            }
+        }
+
+        impl AsRef<[u8]> for $name {
+            fn as_ref(&self) -> &[u8] {
+                &self.0
+            }
+        }
+
+        impl PartialEq<[u8; 32]> for $name {
+            fn eq(&self, other: &[u8; 32]) -> bool {
+                &self.0 == other
+            }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+                f.write_str(&hex::encode(self.0))
+            }
+        }
+
+        impl Debug for $name {
+            fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+                write!(f, "{}({})", stringify!($name), self)
+            }
+        }
+
+        /// Parses the hex encoding of the bytes, optionally prefixed with `0x`.
+        impl FromStr for $name {
+            type Err = anyhow::Error;
+
+            fn from_str(s: &str) -> Result<Self> {
+                parse_bytes32(s, $what).map($name)
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(
+                &self,
+                serializer: S,
+            ) -> std::result::Result<S::Ok, S::Error> {
+                if serializer.is_human_readable() {
+                    serializer.collect_str(self)
+                } else {
+                    self.0.serialize(serializer)
+                }
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(
+                deserializer: D,
+            ) -> std::result::Result<Self, D::Error> {
+                if deserializer.is_human_readable() {
+                    deserializer.deserialize_any(Bytes32Visitor($what)).map($name)
+                } else {
+                    <[u8; 32]>::deserialize(deserializer).map($name)
+                }
+            }
+        }
+
+        impl Distribution<$name> for Standard {
+            fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> $name {
+                $name(rng.gen())
+            }
+        }
+    };
+}
+
+bytes32_newtype!(
+    /// Identifier of the prover (miner) a sector belongs to.
+    ProverId,
+    "prover id"
+);
+
+bytes32_newtype!(
+    /// Randomness a sector is sealed with: the ticket its replica id is derived from, or the seed
+    /// its interactive porep challenges are derived from.
+    Ticket,
+    "ticket"
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bytes32_newtype_serde() {
+        let mut bytes = [0u8; 32];
+        bytes[0] = 0xab;
+        bytes[31] = 0x01;
+        let prover_id = ProverId::from(bytes);
+        let hex = format!("ab{}01", "00".repeat(30));
+
+        assert_eq!(prover_id.to_string(), hex);
+        assert_eq!(format!("{:?}", prover_id), format!("ProverId({})", hex));
+        assert_eq!(hex.parse::<ProverId>().expect("invalid hex"), prover_id);
+        assert_eq!(
+            format!("0x{}", hex)
+                .parse::<ProverId>()
+                .expect("invalid hex"),
+            prover_id
+        );
+        assert!("abcd".parse::<ProverId>().is_err());
+        assert!("xyz".parse::<Ticket>().is_err());
+
+        let json = serde_json::to_string(&prover_id).expect("failed to serialize");
+        assert_eq!(json, format!("\"{}\"", hex));
+        let decoded: ProverId = serde_json::from_str(&json).expect("failed to deserialize");
+        assert_eq!(decoded, prover_id);
+        // JSON written before the newtype was introduced.
+        let json = serde_json::to_string(&bytes).expect("failed to serialize");
+        let decoded: ProverId = serde_json::from_str(&json).expect("failed to deserialize");
+        assert_eq!(decoded, prover_id);
+        assert!(serde_json::from_str::<ProverId>("[1, 2, 3]").is_err());
+
+        // Binary encodings are the same as the ones of the plain bytes.
+        let ticket = Ticket::from(bytes);
+        let encoded = bincode::serialize(&ticket).expect("failed to serialize");
+        assert_eq!(
+            encoded,
+            bincode::serialize(&bytes).expect("failed to serialize")
+        );
+        let decoded: Ticket = bincode::deserialize(&encoded).expect("failed to deserialize");
+        assert_eq!(decoded, ticket);
+        assert_eq!(decoded, bytes);
+    }
+}
@@ -31,6 +31,32 @@ pub struct Settings {
     pub multicore_sdr_producers: usize,
     pub multicore_sdr_producer_stride: u64,
     pub multicore_sdr_lookahead: usize,
+    /// Map each parent cache file once per process and share the mapping between all labelers,
+    /// rather than mapping a window of it per labeler.
+    pub use_shared_parent_cache: bool,
+    /// Upper bound (in bytes) of the memory used for building tree_d. If it is `0`, tree_d is
+    /// built in one go.
+    pub tree_d_max_memory: usize,
+    /// Number of threads used for reading ahead the replica data challenged by a window PoSt,
+    /// before the proof is generated. If it is `0`, no data is read ahead.
+    pub window_post_prefetch_threads: usize,
+    /// Size (in bytes) of the explicit hugepages the multicore SDR layer buffers are allocated
+    /// from, either 2 MiB (`2097152`) or 1 GiB (`1073741824`). The parent cache windows are
+    /// advised to use transparent hugepages then. If it is `0`, regular pages are used. If no
+    /// hugepages of that size are available, regular pages are used as well.
+    pub sdr_hugepage_size: usize,
+    /// Check that the circuit of the first partition is satisfied before a seal proof is
+    /// generated, see `filecoin_proofs::check_circuit_satisfied`. This is slow and uses a lot of
+    /// memory, it is meant for debugging only.
+    pub check_circuit_before_proving: bool,
+    /// Record the constraints of every circuit once and replay them for all partitions, instead
+    /// of synthesizing them again for each partition. Only the witnesses are computed per
+    /// partition then, which saves CPU time before the proofs are generated on the GPU.
+    pub use_synthesis_cache: bool,
+    /// Build tree_c on the CPU in pre-commit phase1, from the labels of the last layer while they
+    /// are generated, instead of in phase2. Phase2 then only builds tree_r_last. This replaces the
+    /// GPU column builder, it is meant for systems without a GPU.
+    pub build_tree_c_in_phase1: bool,
 }
 
 impl Default for Settings {
@@ -55,6 +81,13 @@ impl Default for Settings {
             multicore_sdr_producers: 3,
             multicore_sdr_producer_stride: 128,
             multicore_sdr_lookahead: 800,
+            use_shared_parent_cache: false,
+            tree_d_max_memory: 0,
+            window_post_prefetch_threads: 0,
+            sdr_hugepage_size: 0,
+            check_circuit_before_proving: false,
+            use_synthesis_cache: false,
+            build_tree_c_in_phase1: false,
         }
     }
 }
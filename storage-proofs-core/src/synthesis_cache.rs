@@ -0,0 +1,524 @@
+use std::collections::{hash_map::DefaultHasher, HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+
+use anyhow::ensure;
+use bellperson::{Circuit, ConstraintSystem, Index, LinearCombination, SynthesisError, Variable};
+use blstrs::Scalar as Fr;
+use ff::Field;
+use lazy_static::lazy_static;
+use log::info;
+
+use crate::error::Result;
+
+/// The number of shapes that are kept, a shape of a production circuit takes several GiB.
+const MAX_CACHED_SHAPES: usize = 2;
+
+lazy_static! {
+    static ref SHAPES: Mutex<ShapeCache> = Mutex::new(ShapeCache::new(MAX_CACHED_SHAPES));
+}
+
+// Variables are stored as their index shifted left by one bit, the lowest bit is set for inputs.
+const INPUT_BIT: u32 = 1;
+
+/// The constraints of a circuit, without the values of its variables.
+///
+/// The linear combinations are stored in a compact form: every term refers to its coefficient
+/// in a table of the distinct coefficients, which are few for the circuits of the proofs.
+#[derive(Debug, Default)]
+pub struct CircuitShape {
+    /// The number of public inputs, including the constant one input.
+    num_inputs: usize,
+    num_aux: usize,
+    coeffs: Vec<Fr>,
+    /// The variable and the index of the coefficient of every term.
+    terms: Vec<(u32, u32)>,
+    /// The end of every linear combination within `terms`, three per constraint.
+    lc_ends: Vec<usize>,
+    /// The hash of the order in which the variables were allocated and the constraints enforced.
+    trace: u64,
+}
+
+impl CircuitShape {
+    pub fn num_constraints(&self) -> usize {
+        self.lc_ends.len() / 3
+    }
+
+    pub fn num_inputs(&self) -> usize {
+        self.num_inputs
+    }
+
+    pub fn num_aux(&self) -> usize {
+        self.num_aux
+    }
+
+    /// Adds the terms of the linear combination at `index` to `lc`.
+    fn extend_lc(&self, mut lc: LinearCombination<Fr>, index: usize) -> LinearCombination<Fr> {
+        let start = if index == 0 {
+            0
+        } else {
+            self.lc_ends[index - 1]
+        };
+        for (var, coeff) in &self.terms[start..self.lc_ends[index]] {
+            let index = (var >> 1) as usize;
+            let var = if var & INPUT_BIT == INPUT_BIT {
+                Variable::new_unchecked(Index::Input(index))
+            } else {
+                Variable::new_unchecked(Index::Aux(index))
+            };
+            lc = lc + (self.coeffs[*coeff as usize], var);
+        }
+        lc
+    }
+}
+
+/// The recently used shapes, at most `capacity` of them, the least recently used is evicted.
+struct ShapeCache {
+    capacity: usize,
+    /// Ordered from the least to the most recently used.
+    shapes: VecDeque<(String, Arc<CircuitShape>)>,
+}
+
+impl ShapeCache {
+    fn new(capacity: usize) -> Self {
+        ShapeCache {
+            capacity,
+            shapes: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    fn get(&mut self, key: &str) -> Option<Arc<CircuitShape>> {
+        let position = self.shapes.iter().position(|(k, _)| k == key)?;
+        let entry = self.shapes.remove(position)?;
+        let shape = Arc::clone(&entry.1);
+        self.shapes.push_back(entry);
+        Some(shape)
+    }
+
+    /// Inserts `shape`, unless a shape for `key` was inserted meanwhile, and returns the cached
+    /// shape.
+    fn insert(&mut self, key: &str, shape: Arc<CircuitShape>) -> Arc<CircuitShape> {
+        if let Some(cached) = self.get(key) {
+            return cached;
+        }
+        if self.shapes.len() == self.capacity {
+            if let Some((evicted, _)) = self.shapes.pop_front() {
+                info!("evicting circuit shape {}", evicted);
+            }
+        }
+        self.shapes.push_back((key.to_string(), Arc::clone(&shape)));
+        shape
+    }
+}
+
+/// Hashes the order in which a circuit allocates its variables and enforces its constraints.
+struct Trace(DefaultHasher);
+
+impl Trace {
+    fn new() -> Self {
+        Trace(DefaultHasher::new())
+    }
+
+    fn alloc(&mut self) {
+        0u8.hash(&mut self.0);
+    }
+
+    fn alloc_input(&mut self) {
+        1u8.hash(&mut self.0);
+    }
+
+    fn enforce(&mut self) {
+        2u8.hash(&mut self.0);
+    }
+
+    fn finish(&self) -> u64 {
+        self.0.finish()
+    }
+}
+
+/// Records the constraints of a circuit, the values of the variables are never computed.
+struct ShapeCS {
+    shape: CircuitShape,
+    coeff_indices: HashMap<[u8; 32], u32>,
+    trace: Trace,
+}
+
+impl ShapeCS {
+    fn push_lc(&mut self, lc: &LinearCombination<Fr>) {
+        for (var, coeff) in lc.iter() {
+            let var = match var.get_unchecked() {
+                Index::Input(index) => ((index as u32) << 1) | INPUT_BIT,
+                Index::Aux(index) => (index as u32) << 1,
+            };
+            let coeffs = &mut self.shape.coeffs;
+            let coeff = *self
+                .coeff_indices
+                .entry(coeff.to_bytes_le())
+                .or_insert_with(|| {
+                    coeffs.push(*coeff);
+                    (coeffs.len() - 1) as u32
+                });
+            self.shape.terms.push((var, coeff));
+        }
+        self.shape.lc_ends.push(self.shape.terms.len());
+    }
+}
+
+impl ConstraintSystem<Fr> for ShapeCS {
+    type Root = Self;
+
+    fn new() -> Self {
+        ShapeCS {
+            shape: CircuitShape {
+                // The constant one input.
+                num_inputs: 1,
+                ..Default::default()
+            },
+            coeff_indices: HashMap::new(),
+            trace: Trace::new(),
+        }
+    }
+
+    fn alloc<F, A, AR>(&mut self, _annotation: A, _f: F) -> Result<Variable, SynthesisError>
+    where
+        F: FnOnce() -> Result<Fr, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.trace.alloc();
+        self.shape.num_aux += 1;
+        Ok(Variable::new_unchecked(Index::Aux(self.shape.num_aux - 1)))
+    }
+
+    fn alloc_input<F, A, AR>(&mut self, _annotation: A, _f: F) -> Result<Variable, SynthesisError>
+    where
+        F: FnOnce() -> Result<Fr, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.trace.alloc_input();
+        self.shape.num_inputs += 1;
+        Ok(Variable::new_unchecked(Index::Input(
+            self.shape.num_inputs - 1,
+        )))
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, _annotation: A, a: LA, b: LB, c: LC)
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+        LA: FnOnce(LinearCombination<Fr>) -> LinearCombination<Fr>,
+        LB: FnOnce(LinearCombination<Fr>) -> LinearCombination<Fr>,
+        LC: FnOnce(LinearCombination<Fr>) -> LinearCombination<Fr>,
+    {
+        self.trace.enforce();
+        self.push_lc(&a(LinearCombination::zero()));
+        self.push_lc(&b(LinearCombination::zero()));
+        self.push_lc(&c(LinearCombination::zero()));
+    }
+
+    fn push_namespace<NR, N>(&mut self, _name_fn: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+    }
+
+    fn pop_namespace(&mut self) {}
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+}
+
+/// Computes the values of the variables of a circuit, the constraints are never built.
+struct WitnessCS {
+    inputs: Vec<Fr>,
+    aux: Vec<Fr>,
+    trace: Trace,
+}
+
+impl ConstraintSystem<Fr> for WitnessCS {
+    type Root = Self;
+
+    fn new() -> Self {
+        WitnessCS {
+            inputs: vec![Fr::ONE],
+            aux: Vec::new(),
+            trace: Trace::new(),
+        }
+    }
+
+    fn alloc<F, A, AR>(&mut self, _annotation: A, f: F) -> Result<Variable, SynthesisError>
+    where
+        F: FnOnce() -> Result<Fr, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.trace.alloc();
+        self.aux.push(f()?);
+        Ok(Variable::new_unchecked(Index::Aux(self.aux.len() - 1)))
+    }
+
+    fn alloc_input<F, A, AR>(&mut self, _annotation: A, f: F) -> Result<Variable, SynthesisError>
+    where
+        F: FnOnce() -> Result<Fr, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.trace.alloc_input();
+        self.inputs.push(f()?);
+        Ok(Variable::new_unchecked(Index::Input(self.inputs.len() - 1)))
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, _annotation: A, _a: LA, _b: LB, _c: LC)
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+        LA: FnOnce(LinearCombination<Fr>) -> LinearCombination<Fr>,
+        LB: FnOnce(LinearCombination<Fr>) -> LinearCombination<Fr>,
+        LC: FnOnce(LinearCombination<Fr>) -> LinearCombination<Fr>,
+    {
+        self.trace.enforce();
+    }
+
+    fn push_namespace<NR, N>(&mut self, _name_fn: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+    }
+
+    fn pop_namespace(&mut self) {}
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+}
+
+/// Returns the shape of the circuits identified by `key`. It is recorded from the circuit
+/// returned by `blank_circuit` the first time, and shared afterwards while it is one of the
+/// recently used shapes.
+pub fn circuit_shape<C, F>(key: &str, blank_circuit: F) -> Result<Arc<CircuitShape>>
+where
+    C: Circuit<Fr>,
+    F: FnOnce() -> C,
+{
+    if let Some(shape) = SHAPES.lock().expect("synthesis cache poisoned").get(key) {
+        return Ok(shape);
+    }
+
+    // Recording takes long, other shapes can be used meanwhile.
+    info!("recording circuit shape {}", key);
+    let mut cs = ShapeCS::new();
+    blank_circuit().synthesize(&mut cs)?;
+    cs.shape.trace = cs.trace.finish();
+    let shape = Arc::new(cs.shape);
+    info!(
+        "recorded circuit shape {}: {} constraints, {} terms, {} coefficients",
+        key,
+        shape.num_constraints(),
+        shape.terms.len(),
+        shape.coeffs.len()
+    );
+
+    Ok(SHAPES
+        .lock()
+        .expect("synthesis cache poisoned")
+        .insert(key, shape))
+}
+
+/// A circuit whose witness was computed up front, its constraints are replayed from a shared
+/// [`CircuitShape`] when it is synthesized.
+pub struct CachedCircuit {
+    shape: Arc<CircuitShape>,
+    inputs: Vec<Fr>,
+    aux: Vec<Fr>,
+}
+
+impl CachedCircuit {
+    /// Computes the witness of `circuit`, which must have the given shape.
+    pub fn new<C: Circuit<Fr>>(shape: Arc<CircuitShape>, circuit: C) -> Result<Self> {
+        let mut cs = WitnessCS::new();
+        circuit.synthesize(&mut cs)?;
+        ensure!(
+            cs.inputs.len() == shape.num_inputs && cs.aux.len() == shape.num_aux,
+            "circuit does not match the cached shape: {} inputs and {} aux variables, expected \
+             {} and {}",
+            cs.inputs.len(),
+            cs.aux.len(),
+            shape.num_inputs,
+            shape.num_aux
+        );
+        ensure!(
+            cs.trace.finish() == shape.trace,
+            "circuit does not match the cached shape: the variables are allocated or the \
+             constraints enforced in a different order"
+        );
+
+        Ok(CachedCircuit {
+            shape,
+            inputs: cs.inputs,
+            aux: cs.aux,
+        })
+    }
+}
+
+impl Circuit<Fr> for CachedCircuit {
+    fn synthesize<CS: ConstraintSystem<Fr>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        // Inputs and auxiliary variables are numbered independently, allocating them in their
+        // original order results in the same variables.
+        for (i, value) in self.inputs.into_iter().enumerate().skip(1) {
+            cs.alloc_input(|| format!("input {}", i), || Ok(value))?;
+        }
+        for (i, value) in self.aux.into_iter().enumerate() {
+            cs.alloc(|| format!("aux {}", i), || Ok(value))?;
+        }
+        for constraint in 0..self.shape.num_constraints() {
+            cs.enforce(
+                || format!("constraint {}", constraint),
+                |lc| self.shape.extend_lc(lc, 3 * constraint),
+                |lc| self.shape.extend_lc(lc, 3 * constraint + 1),
+                |lc| self.shape.extend_lc(lc, 3 * constraint + 2),
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bellperson::{gadgets::num::AllocatedNum, util_cs::test_cs::TestConstraintSystem};
+
+    /// Proves knowledge of `x` with `x^(2^rounds) + x == y`, `y` being the public input.
+    struct Powers {
+        x: Option<Fr>,
+        rounds: usize,
+    }
+
+    impl Circuit<Fr> for Powers {
+        fn synthesize<CS: ConstraintSystem<Fr>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let x = AllocatedNum::alloc(cs.namespace(|| "x"), || {
+                self.x.ok_or(SynthesisError::AssignmentMissing)
+            })?;
+            let mut power = x.clone();
+            for round in 0..self.rounds {
+                power = power.square(cs.namespace(|| format!("square {}", round)))?;
+            }
+            let y = AllocatedNum::alloc_input(cs.namespace(|| "y"), || {
+                Ok(power.get_value().ok_or(SynthesisError::AssignmentMissing)?
+                    + x.get_value().ok_or(SynthesisError::AssignmentMissing)?)
+            })?;
+            cs.enforce(
+                || "sum",
+                |lc| lc + power.get_variable() + x.get_variable(),
+                |lc| lc + CS::one(),
+                |lc| lc + y.get_variable(),
+            );
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_cached_circuit() {
+        let shape = circuit_shape("test-powers-3", || Powers { x: None, rounds: 3 })
+            .expect("failed to record shape");
+        assert_eq!(shape.num_constraints(), 4);
+        assert_eq!(shape.num_inputs(), 2);
+        assert_eq!(shape.num_aux(), 4);
+
+        let again = circuit_shape("test-powers-3", || -> Powers {
+            panic!("the shape is not cached")
+        })
+        .expect("failed to get shape");
+        assert!(Arc::ptr_eq(&shape, &again));
+
+        for x in 1..4u64 {
+            let x = Fr::from(x);
+            let mut expected = TestConstraintSystem::<Fr>::new();
+            Powers {
+                x: Some(x),
+                rounds: 3,
+            }
+            .synthesize(&mut expected)
+            .expect("failed to synthesize");
+
+            let circuit = CachedCircuit::new(
+                Arc::clone(&shape),
+                Powers {
+                    x: Some(x),
+                    rounds: 3,
+                },
+            )
+            .expect("failed to compute witness");
+            let mut cs = TestConstraintSystem::<Fr>::new();
+            circuit.synthesize(&mut cs).expect("failed to synthesize");
+
+            assert!(cs.is_satisfied());
+            assert_eq!(cs.num_constraints(), expected.num_constraints());
+            let values = |cs: &TestConstraintSystem<Fr>| -> Vec<Fr> {
+                cs.get_inputs().iter().map(|(value, _)| *value).collect()
+            };
+            assert_eq!(values(&cs), values(&expected));
+            assert!(cs.verify(&[x.pow_vartime([8]) + x]));
+        }
+
+        let other = Powers {
+            x: Some(Fr::ONE),
+            rounds: 2,
+        };
+        assert!(CachedCircuit::new(shape, other).is_err());
+    }
+
+    /// Allocates one auxiliary variable and one input, in the order given by `input_first`.
+    struct Ordered {
+        input_first: bool,
+    }
+
+    impl Circuit<Fr> for Ordered {
+        fn synthesize<CS: ConstraintSystem<Fr>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let (aux, input) = if self.input_first {
+                let input = cs.alloc_input(|| "input", || Ok(Fr::ONE))?;
+                (cs.alloc(|| "aux", || Ok(Fr::ONE))?, input)
+            } else {
+                let aux = cs.alloc(|| "aux", || Ok(Fr::ONE))?;
+                (aux, cs.alloc_input(|| "input", || Ok(Fr::ONE))?)
+            };
+            cs.enforce(
+                || "equal",
+                |lc| lc + aux,
+                |lc| lc + CS::one(),
+                |lc| lc + input,
+            );
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_cached_circuit_different_order() {
+        let shape = circuit_shape("test-ordered", || Ordered { input_first: false })
+            .expect("failed to record shape");
+
+        assert!(CachedCircuit::new(Arc::clone(&shape), Ordered { input_first: false }).is_ok());
+        assert!(CachedCircuit::new(shape, Ordered { input_first: true }).is_err());
+    }
+
+    #[test]
+    fn test_shape_cache_eviction() {
+        let shape = || Arc::new(CircuitShape::default());
+        let mut cache = ShapeCache::new(2);
+
+        let a = cache.insert("a", shape());
+        cache.insert("b", shape());
+        assert!(Arc::ptr_eq(&cache.insert("a", shape()), &a));
+
+        // "b" is the least recently used.
+        cache.insert("c", shape());
+        assert!(cache.get("b").is_none());
+        assert!(Arc::ptr_eq(&cache.get("a").expect("a was evicted"), &a));
+        assert!(cache.get("c").is_some());
+    }
+}
@@ -0,0 +1,53 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use filecoin_hashers::{poseidon::PoseidonDomain, Domain};
+use rand::thread_rng;
+use storage_proofs_porep::stacked::{derive_challenges, ChallengeDerivation};
+
+// The porep challenges per partition of production sectors.
+const CHALLENGES_PER_PARTITION: usize = 18;
+
+fn challenges_gen_benchmark(c: &mut Criterion) {
+    // 2KiB, 512MiB, 32GiB and 64GiB sectors.
+    let sector_nodes = vec![1 << 6, 1 << 24, 1 << 30, 1 << 31];
+
+    let rng = &mut thread_rng();
+    let replica_id = PoseidonDomain::random(rng);
+    let comm_r = PoseidonDomain::random(rng);
+    let seed = [7u8; 32];
+
+    let mut group = c.benchmark_group("challenges-gen");
+    for derivation in ChallengeDerivation::ALL {
+        for nodes in &sector_nodes {
+            let challenges = derive_challenges(
+                derivation,
+                *nodes,
+                CHALLENGES_PER_PARTITION,
+                &replica_id,
+                &comm_r,
+                &seed,
+            );
+            group.throughput(Throughput::Elements(challenges as u64));
+            group.bench_with_input(
+                BenchmarkId::new(derivation.to_string(), nodes),
+                nodes,
+                |b, nodes| {
+                    b.iter(|| {
+                        black_box(derive_challenges(
+                            derivation,
+                            *nodes,
+                            CHALLENGES_PER_PARTITION,
+                            &replica_id,
+                            &comm_r,
+                            &seed,
+                        ))
+                    })
+                },
+            );
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, challenges_gen_benchmark);
+criterion_main!(benches);
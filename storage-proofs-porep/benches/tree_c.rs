@@ -0,0 +1,106 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use filecoin_hashers::{blake2s::Blake2sHasher, poseidon::PoseidonHasher, Domain, Hasher};
+use generic_array::typenum::{U0, U8};
+use memmap2::MmapMut;
+use rand::thread_rng;
+use storage_proofs_core::{
+    api_version::ApiVersion, drgraph::BASE_DEGREE, merkle::DiskTree, proof::ProofScheme,
+    test_helper::setup_replica,
+};
+use storage_proofs_porep::stacked::{
+    LayerChallenges, SetupParams, StackedDrg, EXP_DEGREE, TREE_D_ARITY,
+};
+use tempfile::{tempdir, TempDir};
+
+type Tree = DiskTree<PoseidonHasher, U8, U0, U0>;
+
+/// Pre-commits a sector of `nodes` nodes, with tree_c built in phase2 or streamed in phase1.
+fn tree_c_benchmark(c: &mut Criterion) {
+    let nodes = 1 << 16;
+    let sp = SetupParams {
+        nodes,
+        degree: BASE_DEGREE,
+        expansion_degree: EXP_DEGREE,
+        porep_id: [32; 32],
+        layer_challenges: LayerChallenges::new(2, 1),
+        api_version: ApiVersion::V1_2_0,
+        api_features: vec![],
+        tree_d_arity: TREE_D_ARITY,
+    };
+    let pp = StackedDrg::<Tree, Blake2sHasher>::setup(&sp).unwrap();
+
+    let mut rng = thread_rng();
+    let replica_id = <PoseidonHasher as Hasher>::Domain::random(&mut rng);
+    let data: Vec<u8> = (0..nodes)
+        .flat_map(|_| <PoseidonHasher as Hasher>::Domain::random(&mut rng).into_bytes())
+        .collect();
+    let setup = || -> (TempDir, MmapMut) {
+        let cache_dir = tempdir().unwrap();
+        let replica = setup_replica(&data, &cache_dir.path().join("replica"));
+        (cache_dir, replica)
+    };
+
+    let mut group = c.benchmark_group("tree-c");
+    group.sample_size(10);
+
+    group.bench_function("phase2", |b| {
+        b.iter_batched(
+            setup,
+            |(cache_dir, mut replica)| {
+                let replica_path = cache_dir.path().join("replica");
+                let (labels, _) = StackedDrg::<Tree, Blake2sHasher>::replicate_phase1(
+                    &pp,
+                    &replica_id,
+                    cache_dir.path(),
+                )
+                .unwrap();
+                black_box(
+                    StackedDrg::<Tree, Blake2sHasher>::replicate_phase2(
+                        &pp,
+                        labels,
+                        replica.as_mut().into(),
+                        None,
+                        cache_dir.path().to_path_buf(),
+                        replica_path,
+                    )
+                    .unwrap(),
+                )
+            },
+            BatchSize::PerIteration,
+        )
+    });
+
+    group.bench_function("streamed-in-phase1", |b| {
+        b.iter_batched(
+            setup,
+            |(cache_dir, mut replica)| {
+                let replica_path = cache_dir.path().join("replica");
+                let (labels, _, comm_c) =
+                    StackedDrg::<Tree, Blake2sHasher>::replicate_phase1_with_tree_c(
+                        &pp,
+                        &replica_id,
+                        cache_dir.path(),
+                    )
+                    .unwrap();
+                black_box(
+                    StackedDrg::<Tree, Blake2sHasher>::replicate_phase2_with_tree_c(
+                        &pp,
+                        labels,
+                        comm_c,
+                        replica.as_mut().into(),
+                        None,
+                        cache_dir.path().to_path_buf(),
+                        replica_path,
+                    )
+                    .unwrap(),
+                )
+            },
+            BatchSize::PerIteration,
+        )
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, tree_c_benchmark);
+criterion_main!(benches);
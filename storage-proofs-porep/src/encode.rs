@@ -17,6 +17,10 @@ pub fn decode<T: Domain>(key: T, value: T) -> T {
     let mut result: Fr = value.into();
     let key: Fr = key.into();
 
-    result -= key;
+    decode_fr(&mut result, key);
     result.into()
 }
+
+pub fn decode_fr(value: &mut Fr, key: Fr) {
+    *value -= key;
+}
@@ -1,10 +1,10 @@
 use bellperson::{gadgets::num::AllocatedNum, ConstraintSystem, SynthesisError};
 use blstrs::Scalar as Fr;
-use filecoin_hashers::{POSEIDON_CONSTANTS_11, POSEIDON_CONSTANTS_2};
-use generic_array::typenum::{U11, U2};
+use filecoin_hashers::PoseidonArity;
+use generic_array::typenum::{U11, U2, U4, U8};
 use neptune::circuit::poseidon_hash;
 
-/// Hash a list of bits.
+/// Hash a column, which has an element per layer. Columns of 2, 4, 8 and 11 layers are supported.
 pub fn hash_single_column<CS>(
     cs: CS,
     column: &[AllocatedNum<Fr>],
@@ -13,12 +13,25 @@ where
     CS: ConstraintSystem<Fr>,
 {
     match column.len() {
-        2 => poseidon_hash::<CS, Fr, U2>(cs, column.to_vec(), &*POSEIDON_CONSTANTS_2),
-        11 => poseidon_hash::<CS, Fr, U11>(cs, column.to_vec(), &*POSEIDON_CONSTANTS_11),
+        2 => hash_column::<CS, U2>(cs, column),
+        4 => hash_column::<CS, U4>(cs, column),
+        8 => hash_column::<CS, U8>(cs, column),
+        11 => hash_column::<CS, U11>(cs, column),
         _ => panic!("unsupported column size: {}", column.len()),
     }
 }
 
+fn hash_column<CS, ColumnArity>(
+    cs: CS,
+    column: &[AllocatedNum<Fr>],
+) -> Result<AllocatedNum<Fr>, SynthesisError>
+where
+    CS: ConstraintSystem<Fr>,
+    ColumnArity: PoseidonArity,
+{
+    poseidon_hash::<CS, Fr, ColumnArity>(cs, column.to_vec(), ColumnArity::PARAMETERS())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -105,4 +118,34 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_hash_single_column_circuit_layers() {
+        let mut rng = XorShiftRng::from_seed(TEST_SEED);
+
+        for layers in [2, 4, 8, 11] {
+            let mut cs = TestConstraintSystem::<Fr>::new();
+
+            let vals: Vec<Fr> = (0..layers).map(|_| Fr::random(&mut rng)).collect();
+            let vals_opt = vals
+                .iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    AllocatedNum::alloc(cs.namespace(|| format!("num_{}", i)), || Ok(*v))
+                        .expect("alloc failed")
+                })
+                .collect::<Vec<_>>();
+
+            let out = hash_single_column(cs.namespace(|| "hash_single_column"), &vals_opt)
+                .expect("hash_single_column function failed");
+
+            assert!(cs.is_satisfied(), "constraints not satisfied");
+            assert_eq!(
+                vanilla_hash_single_column(&vals),
+                out.get_value().expect("get_value failed"),
+                "circuit and non circuit do not match for {} layers",
+                layers
+            );
+        }
+    }
 }
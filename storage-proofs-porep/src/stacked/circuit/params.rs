@@ -19,6 +19,7 @@ use crate::stacked::{
     circuit::{column_proof::ColumnProof, create_label_circuit, hash::hash_single_column},
     vanilla::{
         Proof as VanillaProof, PublicParams, ReplicaColumnProof as VanillaReplicaColumnProof,
+        TOTAL_PARENTS,
     },
 };
 
@@ -216,20 +217,14 @@ impl<Tree: MerkleTreeTrait, G: 'static + Hasher> Proof<Tree, G> {
                 }
             }
 
-            // Duplicate parents, according to the hashing algorithm.
-            let mut expanded_parents = parents.clone();
-            if layer > 1 {
-                expanded_parents.extend_from_slice(&parents); // 28
-                expanded_parents.extend_from_slice(&parents[..9]); // 37
-            } else {
-                // layer 1 only has drg parents
-                expanded_parents.extend_from_slice(&parents); // 12
-                expanded_parents.extend_from_slice(&parents); // 18
-                expanded_parents.extend_from_slice(&parents); // 24
-                expanded_parents.extend_from_slice(&parents); // 30
-                expanded_parents.extend_from_slice(&parents); // 36
-                expanded_parents.push(parents[0].clone()); // 37
-            };
+            // Duplicate parents, according to the hashing algorithm. With the production degrees
+            // these are 14 + 14 + 9 parents, or 6 * 6 + 1 drg parents on layer 1.
+            let expanded_parents: Vec<_> = parents
+                .iter()
+                .cycle()
+                .take(TOTAL_PARENTS)
+                .cloned()
+                .collect();
 
             // Reconstruct the label
             let label = create_label_circuit(
@@ -6,7 +6,7 @@ use blstrs::Scalar as Fr;
 use filecoin_hashers::{HashFunction, Hasher};
 use fr32::u64_into_fr;
 use storage_proofs_core::{
-    compound_proof::{CircuitComponent, CompoundProof},
+    compound_proof::{self, CircuitComponent, CompoundProof},
     drgraph::Graph,
     error::Result,
     gadgets::{constraint, por::PoRCompound},
@@ -17,7 +17,7 @@ use storage_proofs_core::{
     util::reverse_bit_numbering,
 };
 
-use crate::stacked::{circuit::params::Proof, StackedDrg};
+use crate::stacked::{circuit::params::Proof, StackedDrg, BINARY_ARITY};
 
 /// Stacked DRG based Proof of Replication.
 ///
@@ -199,10 +199,33 @@ impl<C: Circuit<Fr>, P: ParameterSetMetadata, Tree: MerkleTreeTrait, G: Hasher>
     }
 }
 
+/// Checks that the circuit supports a `tree_d` of arity `arity`, its `comm_d` inclusion proofs are
+/// only implemented for binary data trees.
+pub fn check_circuit_tree_d_arity(arity: usize) -> Result<()> {
+    ensure!(
+        arity == BINARY_ARITY,
+        "tree_d arity {} is not supported by the circuit (supported arities: [{}])",
+        arity,
+        BINARY_ARITY,
+    );
+    Ok(())
+}
+
 impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher>
     CompoundProof<'a, StackedDrg<'a, Tree, G>, StackedCircuit<'a, Tree, G>>
     for StackedCompound<Tree, G>
 {
+    fn setup(
+        sp: &compound_proof::SetupParams<'a, StackedDrg<'a, Tree, G>>,
+    ) -> Result<compound_proof::PublicParams<'a, StackedDrg<'a, Tree, G>>> {
+        check_circuit_tree_d_arity(sp.vanilla_params.tree_d_arity)?;
+        Ok(compound_proof::PublicParams {
+            vanilla_params: StackedDrg::<Tree, G>::setup(&sp.vanilla_params)?,
+            partitions: sp.partitions,
+            priority: sp.priority,
+        })
+    }
+
     fn generate_public_inputs(
         pub_in: &<StackedDrg<'_, Tree, G> as ProofScheme<'_>>::PublicInputs,
         pub_params: &<StackedDrg<'_, Tree, G> as ProofScheme<'_>>::PublicParams,
@@ -301,6 +324,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher>
             !vanilla_proof.is_empty(),
             "Cannot create a circuit with no vanilla proofs"
         );
+        check_circuit_tree_d_arity(public_params.tree_d_arity)?;
 
         let comm_r_last = vanilla_proof[0].comm_r_last();
         let comm_c = vanilla_proof[0].comm_c();
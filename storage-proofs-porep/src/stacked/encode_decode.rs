@@ -0,0 +1,299 @@
+//! Node-level encoding and decoding of replica data.
+//!
+//! A replica node is the field sum of the data node and the key (the label of the last
+//! layer) at the same position: `replica = data + key`. Decoding is the corresponding
+//! field subtraction. The slice and batch helpers below apply the same operation to many
+//! nodes at once and are parallelized with rayon.
+//!
+//! The slice helpers work on the canonical little-endian bytes of the nodes directly, as the
+//! field addition is the same in and out of Montgomery form. The nodes are processed in groups
+//! of [`LANES`], with the limbs of a group laid out so that every step is the same operation on
+//! all lanes, which the compiler turns into vector instructions.
+
+use std::convert::TryInto;
+
+use anyhow::{ensure, Result};
+use blstrs::Scalar as Fr;
+use filecoin_hashers::Domain;
+use rayon::prelude::{IndexedParallelIterator, ParallelIterator};
+use rayon::slice::{ParallelSlice, ParallelSliceMut};
+use storage_proofs_core::util::NODE_SIZE;
+
+pub use crate::encode::{decode, decode_fr, encode, encode_fr};
+
+/// Number of field elements processed per task by the batch functions.
+const BATCH_CHUNK_SIZE: usize = 4096;
+
+/// Number of nodes the slice functions add or subtract at once.
+const LANES: usize = 4;
+
+/// The little-endian limbs of the modulus of the BLS12-381 scalar field.
+const MODULUS: [u64; 4] = [
+    0xffff_ffff_0000_0001,
+    0x53bd_a402_fffe_5bfe,
+    0x3339_d808_09a1_d805,
+    0x73ed_a753_299d_7d48,
+];
+
+/// The limbs of `LANES` field elements, `limbs[i][lane]` is limb `i` of the element in `lane`.
+type Limbs = [[u64; LANES]; 4];
+
+/// Encodes `data` in place, where `data` holds `keys.len()` nodes of `NODE_SIZE` bytes.
+pub fn encode_slice<D: Domain>(keys: &[D], data: &mut [u8]) -> Result<()> {
+    apply_slice(keys, data, false)
+}
+
+/// Decodes `data` in place, where `data` holds `keys.len()` nodes of `NODE_SIZE` bytes.
+pub fn decode_slice<D: Domain>(keys: &[D], data: &mut [u8]) -> Result<()> {
+    apply_slice(keys, data, true)
+}
+
+/// Encodes each element of `keys` in place with the matching element of `values`,
+/// leaving `keys[i] = keys[i] + values[i]`.
+pub fn encode_fr_batch(keys: &mut [Fr], values: &[Fr]) -> Result<()> {
+    ensure!(
+        keys.len() == values.len(),
+        "key count {} does not match value count {}",
+        keys.len(),
+        values.len()
+    );
+
+    keys.par_chunks_mut(BATCH_CHUNK_SIZE)
+        .zip(values.par_chunks(BATCH_CHUNK_SIZE))
+        .for_each(|(keys, values)| {
+            for (key, value) in keys.iter_mut().zip(values.iter()) {
+                encode_fr(key, *value);
+            }
+        });
+
+    Ok(())
+}
+
+/// Decodes each element of `values` in place with the matching element of `keys`,
+/// leaving `values[i] = values[i] - keys[i]`.
+pub fn decode_fr_batch(keys: &[Fr], values: &mut [Fr]) -> Result<()> {
+    ensure!(
+        keys.len() == values.len(),
+        "key count {} does not match value count {}",
+        keys.len(),
+        values.len()
+    );
+
+    values
+        .par_chunks_mut(BATCH_CHUNK_SIZE)
+        .zip(keys.par_chunks(BATCH_CHUNK_SIZE))
+        .for_each(|(values, keys)| {
+            for (value, key) in values.iter_mut().zip(keys.iter()) {
+                decode_fr(value, *key);
+            }
+        });
+
+    Ok(())
+}
+
+fn apply_slice<D: Domain>(keys: &[D], data: &mut [u8], subtract: bool) -> Result<()> {
+    ensure!(
+        data.len() == keys.len() * NODE_SIZE,
+        "data length {} does not match {} nodes",
+        data.len(),
+        keys.len()
+    );
+
+    data.par_chunks_mut(BATCH_CHUNK_SIZE * NODE_SIZE)
+        .zip(keys.par_chunks(BATCH_CHUNK_SIZE))
+        .enumerate()
+        .try_for_each(|(chunk, (data, keys))| -> Result<()> {
+            for (group, (data, keys)) in data
+                .chunks_mut(LANES * NODE_SIZE)
+                .zip(keys.chunks(LANES))
+                .enumerate()
+            {
+                let first_node = chunk * BATCH_CHUNK_SIZE + group * LANES;
+                let mut values = [[0u64; LANES]; 4];
+                let mut key_limbs = [[0u64; LANES]; 4];
+                for (lane, (node_bytes, key)) in data.chunks(NODE_SIZE).zip(keys).enumerate() {
+                    load_limbs(&mut values, lane, node_bytes);
+                    ensure!(
+                        is_canonical(&values, lane),
+                        "node {} is not a valid field element",
+                        first_node + lane
+                    );
+                    load_limbs(&mut key_limbs, lane, AsRef::<[u8]>::as_ref(key));
+                }
+
+                let result = if subtract {
+                    sub_limbs(&values, &key_limbs)
+                } else {
+                    add_limbs(&values, &key_limbs)
+                };
+                for (lane, node_bytes) in data.chunks_mut(NODE_SIZE).enumerate() {
+                    for (i, limb_bytes) in node_bytes.chunks_mut(8).enumerate() {
+                        limb_bytes.copy_from_slice(&result[i][lane].to_le_bytes());
+                    }
+                }
+            }
+            Ok(())
+        })
+}
+
+fn load_limbs(limbs: &mut Limbs, lane: usize, bytes: &[u8]) {
+    for (i, limb_bytes) in bytes.chunks(8).enumerate() {
+        limbs[i][lane] = u64::from_le_bytes(limb_bytes.try_into().expect("8 byte limb"));
+    }
+}
+
+fn is_canonical(limbs: &Limbs, lane: usize) -> bool {
+    for i in (0..4).rev() {
+        if limbs[i][lane] != MODULUS[i] {
+            return limbs[i][lane] < MODULUS[i];
+        }
+    }
+    false
+}
+
+/// `a + b` modulo the field modulus, for canonical `a` and `b`.
+#[inline(always)]
+fn add_limbs(a: &Limbs, b: &Limbs) -> Limbs {
+    let mut sum = [[0u64; LANES]; 4];
+    let mut carry = [0u64; LANES];
+    for i in 0..4 {
+        for lane in 0..LANES {
+            let s = a[i][lane].wrapping_add(b[i][lane]);
+            let s_carry = s.wrapping_add(carry[lane]);
+            carry[lane] = ((s < a[i][lane]) | (s_carry < s)) as u64;
+            sum[i][lane] = s_carry;
+        }
+    }
+
+    // The modulus is below 2^255, so the sum does not overflow and is reduced by subtracting
+    // the modulus once, unless that borrows.
+    let mut reduced = [[0u64; LANES]; 4];
+    let mut borrow = [0u64; LANES];
+    for i in 0..4 {
+        for lane in 0..LANES {
+            let d = sum[i][lane].wrapping_sub(MODULUS[i]);
+            let d_borrow = d.wrapping_sub(borrow[lane]);
+            borrow[lane] = ((sum[i][lane] < MODULUS[i]) | (d < borrow[lane])) as u64;
+            reduced[i][lane] = d_borrow;
+        }
+    }
+    for i in 0..4 {
+        for lane in 0..LANES {
+            let keep_sum = 0u64.wrapping_sub(borrow[lane]);
+            sum[i][lane] = (sum[i][lane] & keep_sum) | (reduced[i][lane] & !keep_sum);
+        }
+    }
+    sum
+}
+
+/// `a - b` modulo the field modulus, for canonical `a` and `b`.
+#[inline(always)]
+fn sub_limbs(a: &Limbs, b: &Limbs) -> Limbs {
+    let mut diff = [[0u64; LANES]; 4];
+    let mut borrow = [0u64; LANES];
+    for i in 0..4 {
+        for lane in 0..LANES {
+            let d = a[i][lane].wrapping_sub(b[i][lane]);
+            let d_borrow = d.wrapping_sub(borrow[lane]);
+            borrow[lane] = ((a[i][lane] < b[i][lane]) | (d < borrow[lane])) as u64;
+            diff[i][lane] = d_borrow;
+        }
+    }
+
+    // Add the modulus back if the subtraction borrowed.
+    let mut carry = [0u64; LANES];
+    for i in 0..4 {
+        for lane in 0..LANES {
+            let modulus = MODULUS[i] & 0u64.wrapping_sub(borrow[lane]);
+            let s = diff[i][lane].wrapping_add(modulus);
+            let s_carry = s.wrapping_add(carry[lane]);
+            carry[lane] = ((s < modulus) | (s_carry < s)) as u64;
+            diff[i][lane] = s_carry;
+        }
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ff::{Field, PrimeField};
+    use filecoin_hashers::{poseidon::PoseidonDomain, sha256::Sha256Domain};
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+    use storage_proofs_core::TEST_SEED;
+
+    #[test]
+    fn test_encode_decode_slice_roundtrip() {
+        let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+        let nodes = 1024;
+
+        let keys: Vec<PoseidonDomain> = (0..nodes).map(|_| PoseidonDomain::random(rng)).collect();
+        let original: Vec<u8> = (0..nodes)
+            .flat_map(|_| PoseidonDomain::random(rng).into_bytes())
+            .collect();
+
+        let mut data = original.clone();
+        encode_slice(&keys, &mut data).expect("encode_slice failure");
+        assert_ne!(data, original);
+
+        for (i, key) in keys.iter().enumerate() {
+            let node =
+                PoseidonDomain::try_from_bytes(&original[i * NODE_SIZE..(i + 1) * NODE_SIZE])
+                    .expect("try_from_bytes failure");
+            let expected = encode(*key, node);
+            assert_eq!(
+                &data[i * NODE_SIZE..(i + 1) * NODE_SIZE],
+                AsRef::<[u8]>::as_ref(&expected)
+            );
+        }
+
+        decode_slice(&keys, &mut data).expect("decode_slice failure");
+        assert_eq!(data, original);
+
+        assert!(encode_slice(&keys[1..], &mut data).is_err());
+    }
+
+    #[test]
+    fn test_encode_decode_fr_batch_roundtrip() {
+        let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+        let count = BATCH_CHUNK_SIZE + 17;
+
+        let keys: Vec<Fr> = (0..count).map(|_| Fr::random(&mut *rng)).collect();
+        let values: Vec<Fr> = (0..count).map(|_| Fr::random(&mut *rng)).collect();
+
+        let mut encoded = keys.clone();
+        encode_fr_batch(&mut encoded, &values).expect("encode_fr_batch failure");
+        for ((encoded, key), value) in encoded.iter().zip(&keys).zip(&values) {
+            assert_eq!(*encoded, *key + *value);
+        }
+
+        decode_fr_batch(&keys, &mut encoded).expect("decode_fr_batch failure");
+        assert_eq!(encoded, values);
+    }
+
+    #[test]
+    fn test_encode_decode_slice_edge_cases() {
+        let max = -Fr::ONE;
+        let values = [Fr::ZERO, Fr::ONE, max, max, Fr::from(7), Fr::ZERO];
+        let keys = [Fr::ZERO, max, Fr::ONE, max, max, max];
+        let keys: Vec<Sha256Domain> = keys.iter().map(|key| (*key).into()).collect();
+
+        let mut data: Vec<u8> = values.iter().flat_map(|value| value.to_repr()).collect();
+        encode_slice(&keys, &mut data).expect("encode_slice failure");
+        for ((node, key), value) in data.chunks(NODE_SIZE).zip(&keys).zip(&values) {
+            let key: Fr = (*key).into();
+            assert_eq!(node, &(*value + key).to_repr()[..]);
+        }
+
+        decode_slice(&keys, &mut data).expect("decode_slice failure");
+        for (node, value) in data.chunks(NODE_SIZE).zip(&values) {
+            assert_eq!(node, &value.to_repr()[..]);
+        }
+
+        // Nodes that are not field elements are rejected.
+        data[NODE_SIZE..2 * NODE_SIZE].fill(0xff);
+        assert!(decode_slice(&keys, &mut data).is_err());
+    }
+}
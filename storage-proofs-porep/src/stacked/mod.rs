@@ -1,5 +1,6 @@
 mod circuit;
 
+pub mod encode_decode;
 pub(crate) mod vanilla;
 
 pub use circuit::*;
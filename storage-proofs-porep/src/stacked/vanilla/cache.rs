@@ -1,14 +1,15 @@
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::{remove_file, File};
-use std::io;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::ops::Deref;
 use std::path::{Path, PathBuf};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex, Weak};
 
 use anyhow::{bail, ensure, Context};
 use byteorder::{ByteOrder, LittleEndian};
 use filecoin_hashers::Hasher;
 use lazy_static::lazy_static;
-use log::{info, trace};
+use log::{info, trace, warn};
 use memmap2::{Mmap, MmapOptions};
 use rayon::prelude::{IndexedParallelIterator, ParallelIterator, ParallelSliceMut};
 use serde::{Deserialize, Serialize};
@@ -21,16 +22,22 @@ use storage_proofs_core::{
     util::NODE_SIZE,
 };
 
-use crate::stacked::vanilla::graph::{StackedGraph, DEGREE};
+use crate::stacked::vanilla::graph::{StackedGraph, DEGREE, EXP_DEGREE};
 
 /// u32 = 4 bytes
 const NODE_BYTES: usize = 4;
 
 pub const PARENT_CACHE_DATA: &str = include_str!("../../../parent_cache.json");
 
+/// The name of the manifest that lists the digests of the parent caches within a directory. It
+/// has the same format as `parent_cache.json` and is used to verify caches which aren't part of
+/// the official manifest, e.g. caches that were generated for other porep ids and distributed to
+/// sealing workers.
+pub const PARENT_CACHE_MANIFEST: &str = "parent_cache_manifest.json";
+
 pub type ParentCacheDataMap = BTreeMap<String, ParentCacheData>;
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub struct ParentCacheData {
     pub digest: String,
     pub sector_size: u64,
@@ -40,6 +47,135 @@ lazy_static! {
     pub static ref PARENT_CACHE: ParentCacheDataMap =
         serde_json::from_str(PARENT_CACHE_DATA).expect("Invalid parent_cache.json");
     static ref PARENT_CACHE_ACCESS_LOCK: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+    /// The parent cache files that are currently mapped once for all users in the process.
+    static ref SHARED_PARENT_CACHES: Mutex<HashMap<PathBuf, Weak<SharedParentCache>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// A read-only mapping of a whole parent cache file, shared by all labelers in the process.
+///
+/// The mapping is reference-counted: it is created by the first `acquire` for a given file and
+/// unmapped when the last user drops it. This way concurrent sealing jobs on one machine use a
+/// single physical copy of the parent cache, rather than each one mapping its own windows.
+#[derive(Debug)]
+pub struct SharedParentCache {
+    path: PathBuf,
+    data: Mmap,
+    _file: LockedFile,
+}
+
+/// Memory residency of a shared parent cache.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedParentCacheStats {
+    pub path: PathBuf,
+    /// The number of parent caches currently using the shared mapping.
+    pub users: usize,
+    /// The size of the mapping in bytes.
+    pub mapped_bytes: usize,
+    /// The number of mapped bytes currently resident in physical memory.
+    pub resident_bytes: usize,
+}
+
+impl SharedParentCache {
+    /// Returns the shared mapping of the parent cache file at `path`, mapping the file if it is
+    /// not currently in use.
+    pub fn acquire(path: &Path) -> Result<Arc<Self>> {
+        let mut shared = SHARED_PARENT_CACHES
+            .lock()
+            .expect("shared parent cache lock failed");
+
+        if let Some(cache) = shared.get(path).and_then(Weak::upgrade) {
+            return Ok(cache);
+        }
+
+        let file = LockedFile::open_shared_read(path)
+            .with_context(|| format!("could not open path={}", path.display()))?;
+        let data = unsafe {
+            MmapOptions::new()
+                .map(file.as_ref())
+                .with_context(|| format!("could not mmap path={}", path.display()))?
+        };
+        info!("parent cache: sharing mapping of {}", path.display());
+
+        let cache = Arc::new(SharedParentCache {
+            path: path.to_path_buf(),
+            data,
+            _file: file,
+        });
+        shared.retain(|_, cache| cache.strong_count() > 0);
+        shared.insert(path.to_path_buf(), Arc::downgrade(&cache));
+
+        Ok(cache)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the size of the mapping in bytes.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Returns the number of bytes of the mapping that are resident in physical memory.
+    pub fn resident_bytes(&self) -> Result<usize> {
+        if self.data.is_empty() {
+            return Ok(0);
+        }
+
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let num_pages = (self.data.len() + page_size - 1) / page_size;
+        let mut residency = vec![0u8; num_pages];
+        let ret = unsafe {
+            libc::mincore(
+                self.data.as_ptr() as *mut libc::c_void,
+                self.data.len(),
+                residency.as_mut_ptr() as _,
+            )
+        };
+        if ret != 0 {
+            return Err(io::Error::last_os_error()).context("mincore failed");
+        }
+
+        let resident_pages = residency.iter().filter(|&&page| page & 1 == 1).count();
+        Ok((resident_pages * page_size).min(self.data.len()))
+    }
+
+    /// Stops sharing the mapping of `path`; current users keep their mapping, while subsequent
+    /// users map the file anew.
+    fn evict(path: &Path) {
+        SHARED_PARENT_CACHES
+            .lock()
+            .expect("shared parent cache lock failed")
+            .remove(path);
+    }
+}
+
+/// Returns the residency stats of all parent caches that are currently shared.
+pub fn shared_parent_cache_stats() -> Result<Vec<SharedParentCacheStats>> {
+    let caches: Vec<Arc<SharedParentCache>> = SHARED_PARENT_CACHES
+        .lock()
+        .expect("shared parent cache lock failed")
+        .values()
+        .filter_map(Weak::upgrade)
+        .collect();
+
+    caches
+        .iter()
+        .map(|cache| {
+            Ok(SharedParentCacheStats {
+                path: cache.path.clone(),
+                // Do not count the reference held for collecting the stats.
+                users: Arc::strong_count(cache) - 1,
+                mapped_bytes: cache.len(),
+                resident_bytes: cache.resident_bytes()?,
+            })
+        })
+        .collect()
 }
 
 // StackedGraph will hold two different (but related) `ParentCache`,
@@ -54,10 +190,29 @@ pub struct ParentCache {
     pub digest: String,
 }
 
+#[derive(Debug)]
+enum CacheMap {
+    /// A window of the cache file, mapped for a single `ParentCache`.
+    Window(Mmap),
+    /// The whole cache file, mapped once for all `ParentCache`s of the process.
+    Shared(Arc<SharedParentCache>),
+}
+
+impl Deref for CacheMap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            CacheMap::Window(data) => data,
+            CacheMap::Shared(cache) => &cache.data,
+        }
+    }
+}
+
 #[derive(Debug)]
 struct CacheData {
     /// This is a large list of fixed (parent) sized arrays.
-    data: Mmap,
+    data: CacheMap,
     /// Offset in nodes.
     offset: u32,
     /// Len in nodes.
@@ -79,13 +234,13 @@ impl CacheData {
         let offset = new_offset as usize * DEGREE * NODE_BYTES;
         let len = self.len as usize * DEGREE * NODE_BYTES;
 
-        self.data = unsafe {
+        self.data = CacheMap::Window(unsafe {
             MmapOptions::new()
                 .offset(offset as u64)
                 .len(len)
                 .map(self.file.as_ref())
                 .context("could not shift mmap}")?
-        };
+        });
         self.offset = new_offset;
 
         Ok(())
@@ -142,12 +297,46 @@ impl CacheData {
         };
 
         Ok(Self {
-            data,
+            data: CacheMap::Window(data),
             file,
             len,
             offset,
         })
     }
+
+    /// Opens the cache through the process wide shared mapping of the whole cache file.
+    fn open_shared(cache_entries: u32, path: &Path) -> Result<Self> {
+        let min_cache_size = cache_entries as usize * DEGREE * NODE_BYTES;
+
+        let file = LockedFile::open_shared_read(path)
+            .with_context(|| format!("could not open path={}", path.display()))?;
+        let shared = SharedParentCache::acquire(path)?;
+        if shared.len() < min_cache_size {
+            bail!(
+                "corrupted cache: {}, expected at least {}, got {} bytes",
+                path.display(),
+                min_cache_size,
+                shared.len()
+            );
+        }
+
+        Ok(Self {
+            data: CacheMap::Shared(shared),
+            file,
+            len: cache_entries,
+            offset: 0,
+        })
+    }
+
+    /// Opens the cache either through the shared mapping or as a window of `len` nodes,
+    /// depending on the `use_shared_parent_cache` setting.
+    fn open_for(len: u32, cache_entries: u32, path: &Path) -> Result<Self> {
+        if SETTINGS.use_shared_parent_cache {
+            Self::open_shared(cache_entries, path)
+        } else {
+            Self::open(0, len, path)
+        }
+    }
 }
 
 impl ParentCache {
@@ -157,6 +346,43 @@ impl ParentCache {
         G: Graph<H> + ParameterSetMetadata + Send + Sync,
     {
         let path = cache_path(cache_entries, graph);
+        Self::open_or_generate(len, cache_entries, graph, path)
+    }
+
+    /// Like `new`, but the cache lives in `dir` instead of the configured parent cache directory.
+    pub fn new_in<H, G>(
+        len: u32,
+        cache_entries: u32,
+        graph: &StackedGraph<H, G>,
+        dir: &Path,
+    ) -> Result<Self>
+    where
+        H: Hasher,
+        G: Graph<H> + ParameterSetMetadata + Send + Sync,
+    {
+        let path = dir.join(cache_file_name(cache_entries, graph));
+        Self::open_or_generate(len, cache_entries, graph, path)
+    }
+
+    fn open_or_generate<H, G>(
+        len: u32,
+        cache_entries: u32,
+        graph: &StackedGraph<H, G>,
+        path: PathBuf,
+    ) -> Result<Self>
+    where
+        H: Hasher,
+        G: Graph<H> + ParameterSetMetadata + Send + Sync,
+    {
+        ensure!(
+            graph.has_production_degrees(),
+            "the parent cache requires base degree {} and expansion degree {}, got {} and {}",
+            BASE_DEGREE,
+            EXP_DEGREE,
+            graph.base_graph().degree(),
+            graph.expansion_degree(),
+        );
+
         let generation_key = path.display().to_string();
         let mut generated = PARENT_CACHE_ACCESS_LOCK
             .lock()
@@ -221,12 +447,15 @@ impl ParentCache {
                         "".to_string(),
                     )
                 }
-                Some(pcd) => (
-                    Some(pcd),
-                    SETTINGS.verify_cache,
-                    true, // is_production since it exists in the manifest
-                    pcd.digest.clone(),
-                ),
+                Some(pcd) => {
+                    let digest = pcd.digest.clone();
+                    (
+                        Some(pcd),
+                        SETTINGS.verify_cache,
+                        true, // is_production since it exists in the manifest
+                        digest,
+                    )
+                }
             };
 
         info!(
@@ -275,6 +504,7 @@ impl ParentCache {
                         path.display()
                     );
                     // delete invalid cache
+                    SharedParentCache::evict(path);
                     remove_file(path)?;
                     ensure!(
                         Self::generate(len, graph.size() as u32, graph, path).is_ok(),
@@ -288,7 +518,7 @@ impl ParentCache {
         }
 
         Ok(ParentCache {
-            cache: CacheData::open(0, len, path)?,
+            cache: CacheData::open_for(len, cache_entries, path)?,
             path: path.to_path_buf(),
             num_cache_entries: cache_entries,
             sector_size: graph.size() * NODE_SIZE,
@@ -371,7 +601,7 @@ impl ParentCache {
         })?;
 
         Ok(ParentCache {
-            cache: CacheData::open(0, len, path)?,
+            cache: CacheData::open_for(len, cache_entries, path)?,
             path: path.to_path_buf(),
             num_cache_entries: cache_entries,
             sector_size,
@@ -421,12 +651,67 @@ fn parent_cache_id(path: &Path) -> String {
         .to_string()
 }
 
-/// Get the correct parent cache data for a given cache id.
-fn get_parent_cache_data(path: &Path) -> Option<&ParentCacheData> {
-    PARENT_CACHE.get(&parent_cache_id(path))
+/// Get the correct parent cache data for a given cache id. Entries of the official manifest take
+/// precedence over the ones of a `PARENT_CACHE_MANIFEST` next to the cache.
+fn get_parent_cache_data(path: &Path) -> Option<ParentCacheData> {
+    let id = parent_cache_id(path);
+    if let Some(pcd) = PARENT_CACHE.get(&id) {
+        return Some(pcd.clone());
+    }
+
+    let dir = path.parent()?;
+    match read_parent_cache_manifest(dir) {
+        Ok(mut manifest) => manifest.remove(&id),
+        Err(err) => {
+            warn!(
+                "ignoring invalid parent cache manifest in {}: {:?}",
+                dir.display(),
+                err
+            );
+            None
+        }
+    }
+}
+
+/// Reads the `PARENT_CACHE_MANIFEST` in `dir`, it is empty if there is none.
+pub fn read_parent_cache_manifest(dir: &Path) -> Result<ParentCacheDataMap> {
+    let path = dir.join(PARENT_CACHE_MANIFEST);
+    if !path.exists() {
+        return Ok(ParentCacheDataMap::new());
+    }
+
+    let file =
+        File::open(&path).with_context(|| format!("could not open path={}", path.display()))?;
+    let manifest = serde_json::from_reader(BufReader::new(file))
+        .with_context(|| format!("invalid parent cache manifest {}", path.display()))?;
+    Ok(manifest)
+}
+
+/// Writes `manifest` as the `PARENT_CACHE_MANIFEST` of `dir`, replacing an existing one.
+pub fn write_parent_cache_manifest(dir: &Path, manifest: &ParentCacheDataMap) -> Result<()> {
+    let path = dir.join(PARENT_CACHE_MANIFEST);
+    let tmp_path = path.with_extension("json.tmp");
+
+    let file = File::create(&tmp_path)
+        .with_context(|| format!("could not create path={}", tmp_path.display()))?;
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer_pretty(&mut writer, manifest)?;
+    writer.flush()?;
+    std::fs::rename(&tmp_path, &path)
+        .with_context(|| format!("could not rename to path={}", path.display()))?;
+
+    Ok(())
 }
 
 fn cache_path<H, G>(cache_entries: u32, graph: &StackedGraph<H, G>) -> PathBuf
+where
+    H: Hasher,
+    G: Graph<H> + ParameterSetMetadata + Send + Sync,
+{
+    PathBuf::from(parent_cache_dir_name()).join(cache_file_name(cache_entries, graph))
+}
+
+fn cache_file_name<H, G>(cache_entries: u32, graph: &StackedGraph<H, G>) -> String
 where
     H: Hasher,
     G: Graph<H> + ParameterSetMetadata + Send + Sync,
@@ -440,11 +725,7 @@ where
     }
     hasher.update(cache_entries.to_le_bytes());
     let h = hasher.finalize();
-    PathBuf::from(parent_cache_dir_name()).join(format!(
-        "v{}-sdr-parent-{}.cache",
-        VERSION,
-        hex::encode(h),
-    ))
+    format!("v{}-sdr-parent-{}.cache", VERSION, hex::encode(h))
 }
 
 #[cfg(test)]
@@ -454,7 +735,7 @@ mod tests {
     use filecoin_hashers::poseidon::PoseidonHasher;
     use storage_proofs_core::api_version::ApiVersion;
 
-    use crate::stacked::vanilla::graph::{StackedBucketGraph, EXP_DEGREE};
+    use crate::stacked::vanilla::graph::StackedBucketGraph;
 
     #[test]
     fn test_read_full_range() {
@@ -482,6 +763,56 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_shared_parent_cache() {
+        fil_logger::maybe_init();
+        let nodes = 40u32;
+        let graph = StackedBucketGraph::<PoseidonHasher>::new_stacked(
+            nodes as usize,
+            BASE_DEGREE,
+            EXP_DEGREE,
+            [2u8; 32],
+            ApiVersion::V1_1_0,
+        )
+        .expect("new_stacked failure");
+
+        // Make sure the cache file exists.
+        let cache = ParentCache::new(nodes, nodes, &graph).expect("parent cache new failure");
+        let path = cache.path.clone();
+
+        let mut shared_1 = CacheData::open_shared(nodes, &path).expect("open_shared failure");
+        let shared_2 = CacheData::open_shared(nodes, &path).expect("open_shared failure");
+        match (&shared_1.data, &shared_2.data) {
+            (CacheMap::Shared(a), CacheMap::Shared(b)) => assert!(Arc::ptr_eq(a, b)),
+            _ => panic!("expected shared mappings"),
+        }
+
+        let stats = shared_parent_cache_stats().expect("stats failure");
+        let stat = stats
+            .iter()
+            .find(|stat| stat.path == path)
+            .expect("missing shared parent cache stats");
+        assert_eq!(stat.users, 2);
+        assert_eq!(stat.mapped_bytes, nodes as usize * DEGREE * NODE_BYTES);
+        assert!(stat.resident_bytes <= stat.mapped_bytes);
+
+        for node in 0..nodes {
+            let mut expected_parents = [0; DEGREE];
+            graph
+                .parents(node as usize, &mut expected_parents)
+                .expect("graph parents failure");
+            assert!(shared_1.contains(node));
+            assert_eq!(shared_1.read(node), expected_parents);
+        }
+        shared_1.reset().expect("shared cache reset failure");
+
+        // The mapping is released once the last user is dropped.
+        drop(shared_1);
+        drop(shared_2);
+        let stats = shared_parent_cache_stats().expect("stats failure");
+        assert!(stats.iter().all(|stat| stat.path != path));
+    }
+
     #[test]
     #[cfg(feature = "isolated-testing")]
     fn test_parallel_generation_and_read_partial_range_v1_0() {
@@ -621,4 +952,52 @@ mod tests {
             assert_eq!(expected_parents, parents);
         }
     }
+
+    #[test]
+    fn test_parent_cache_manifest() {
+        fil_logger::maybe_init();
+        let nodes = 32u32;
+        let graph = StackedBucketGraph::<PoseidonHasher>::new_stacked(
+            nodes as usize,
+            BASE_DEGREE,
+            EXP_DEGREE,
+            [7u8; 32],
+            ApiVersion::V1_1_0,
+        )
+        .expect("new_stacked failure");
+        let dir = tempfile::tempdir().expect("tempdir failure");
+
+        let cache =
+            ParentCache::new_in(nodes, nodes, &graph, dir.path()).expect("parent cache failure");
+        assert_eq!(cache.path.parent(), Some(dir.path()));
+        assert!(get_parent_cache_data(&cache.path).is_none());
+
+        let data = ParentCacheData {
+            digest: cache.digest.clone(),
+            sector_size: cache.sector_size as u64,
+        };
+        let mut manifest = ParentCacheDataMap::new();
+        manifest.insert(parent_cache_id(&cache.path), data.clone());
+        write_parent_cache_manifest(dir.path(), &manifest).expect("write manifest failure");
+        assert_eq!(
+            read_parent_cache_manifest(dir.path()).expect("read manifest failure"),
+            manifest
+        );
+        assert_eq!(get_parent_cache_data(&cache.path), Some(data));
+
+        // A regenerated cache must match the manifest entry.
+        let path = cache.path.clone();
+        drop(cache);
+        remove_file(&path).expect("remove failure");
+        let mut manifest = ParentCacheDataMap::new();
+        manifest.insert(
+            parent_cache_id(&path),
+            ParentCacheData {
+                digest: "00".to_string(),
+                sector_size: nodes as u64 * NODE_SIZE as u64,
+            },
+        );
+        write_parent_cache_manifest(dir.path(), &manifest).expect("write manifest failure");
+        assert!(ParentCache::new_in(nodes, nodes, &graph, dir.path()).is_err());
+    }
 }
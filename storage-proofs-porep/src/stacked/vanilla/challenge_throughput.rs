@@ -0,0 +1,146 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use anyhow::ensure;
+use filecoin_hashers::{poseidon::PoseidonDomain, Domain};
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+use serde::{Deserialize, Serialize};
+use storage_proofs_core::error::Result;
+
+use crate::stacked::vanilla::challenges::LayerChallenges;
+
+/// The porep challenge derivations, which differ in their cost per challenge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChallengeDerivation {
+    /// One sha256 per challenge of the partition.
+    Interactive,
+    /// One chacha20 block per synthetic challenge, for the entire synthetic challenge set.
+    Synthetic,
+}
+
+impl ChallengeDerivation {
+    pub const ALL: [ChallengeDerivation; 2] = [
+        ChallengeDerivation::Interactive,
+        ChallengeDerivation::Synthetic,
+    ];
+}
+
+impl fmt::Display for ChallengeDerivation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChallengeDerivation::Interactive => write!(f, "interactive"),
+            ChallengeDerivation::Synthetic => write!(f, "synthetic"),
+        }
+    }
+}
+
+/// The measured cost of a challenge derivation.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ChallengeThroughput {
+    pub derivation: ChallengeDerivation,
+    pub sector_nodes: usize,
+    /// The number of challenges derived in total.
+    pub challenges: usize,
+    pub elapsed: Duration,
+}
+
+impl ChallengeThroughput {
+    pub fn challenges_per_second(&self) -> f64 {
+        self.challenges as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// The estimated time to derive `challenges` challenges.
+    pub fn estimate(&self, challenges: usize) -> Duration {
+        Duration::from_secs_f64(challenges as f64 / self.challenges_per_second())
+    }
+}
+
+/// Derives the challenges of a sector with `sector_nodes` nodes once. Returns the number of
+/// challenges that were derived. This is the unit of work both the criterion benchmarks and
+/// [`measure_challenge_derivation`] time.
+pub fn derive_challenges(
+    derivation: ChallengeDerivation,
+    sector_nodes: usize,
+    challenges_per_partition: usize,
+    replica_id: &PoseidonDomain,
+    comm_r: &PoseidonDomain,
+    seed: &[u8; 32],
+) -> usize {
+    match derivation {
+        ChallengeDerivation::Interactive => LayerChallenges::new(1, challenges_per_partition)
+            .derive(sector_nodes, replica_id, comm_r, seed, 0)
+            .len(),
+        ChallengeDerivation::Synthetic => {
+            LayerChallenges::new_synthetic(1, challenges_per_partition)
+                .derive_synthetic(sector_nodes, replica_id, comm_r)
+                .len()
+        }
+    }
+}
+
+/// Measures the throughput of a challenge derivation by deriving the challenges of a sector with
+/// `sector_nodes` nodes `iterations` times, from random inputs.
+pub fn measure_challenge_derivation(
+    derivation: ChallengeDerivation,
+    sector_nodes: usize,
+    challenges_per_partition: usize,
+    iterations: usize,
+) -> Result<ChallengeThroughput> {
+    ensure!(sector_nodes > 2, "too few sector nodes: {}", sector_nodes);
+    ensure!(iterations > 0, "at least one iteration is required");
+
+    let mut rng = StdRng::seed_from_u64(sector_nodes as u64);
+    let inputs: Vec<_> = (0..iterations)
+        .map(|_| {
+            let mut seed = [0u8; 32];
+            rng.fill_bytes(&mut seed);
+            (
+                PoseidonDomain::random(&mut rng),
+                PoseidonDomain::random(&mut rng),
+                seed,
+            )
+        })
+        .collect();
+
+    let start = Instant::now();
+    let challenges = inputs
+        .iter()
+        .map(|(replica_id, comm_r, seed)| {
+            derive_challenges(
+                derivation,
+                sector_nodes,
+                challenges_per_partition,
+                replica_id,
+                comm_r,
+                seed,
+            )
+        })
+        .sum();
+
+    Ok(ChallengeThroughput {
+        derivation,
+        sector_nodes,
+        challenges,
+        elapsed: start.elapsed(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_measure_challenge_derivation() {
+        let sector_nodes = 1 << 10;
+        for derivation in ChallengeDerivation::ALL {
+            let throughput = measure_challenge_derivation(derivation, sector_nodes, 10, 3)
+                .expect("measuring failed");
+            let expected = match derivation {
+                ChallengeDerivation::Interactive => 3 * 10,
+                ChallengeDerivation::Synthetic => 3 * sector_nodes,
+            };
+            assert_eq!(throughput.challenges, expected);
+            assert!(throughput.challenges_per_second() > 0.0);
+        }
+    }
+}
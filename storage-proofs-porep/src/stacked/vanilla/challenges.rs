@@ -1,12 +1,14 @@
 use std::fmt;
 
+use anyhow::{ensure, Result};
 use blstrs::Scalar as Fr;
 use log::trace;
 use num_bigint::BigUint;
 use serde::{Deserialize, Serialize};
 
 use filecoin_hashers::Domain;
-use sha2::{Digest, Sha256};
+use sha2::Digest;
+use storage_proofs_core::challenge_domain::ChallengeDomain;
 
 #[inline]
 fn bigint_to_challenge(bigint: BigUint, sector_nodes: usize) -> usize {
@@ -16,13 +18,16 @@ fn bigint_to_challenge(bigint: BigUint, sector_nodes: usize) -> usize {
     non_zero_node.to_u32_digits()[0] as usize
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct LayerChallenges {
     /// How many layers we are generating challenges for.
     layers: usize,
     /// The maximum count of challenges
     max_count: usize,
     pub use_synthetic: bool,
+    /// Not part of the debug output, the domain does not change the circuit.
+    #[serde(default)]
+    domain: ChallengeDomain,
 }
 
 /// Note that since this is used in the PublicParams 'identifier'
@@ -44,6 +49,7 @@ impl LayerChallenges {
             layers,
             max_count,
             use_synthetic: false,
+            domain: ChallengeDomain::Mainnet,
         }
     }
 
@@ -52,13 +58,45 @@ impl LayerChallenges {
             layers,
             max_count,
             use_synthetic: true,
+            domain: ChallengeDomain::Mainnet,
         }
     }
 
+    /// Creates the challenge configuration from its stored parts, e.g. when re-deriving challenges
+    /// from a persisted configuration.
+    pub const fn from_parts(layers: usize, max_count: usize, use_synthetic: bool) -> Self {
+        LayerChallenges {
+            layers,
+            max_count,
+            use_synthetic,
+            domain: ChallengeDomain::Mainnet,
+        }
+    }
+
+    /// Returns this configuration with the challenges derived in `domain`.
+    pub fn with_domain(mut self, domain: ChallengeDomain) -> Self {
+        self.domain = domain;
+        self
+    }
+
+    pub fn domain(&self) -> &ChallengeDomain {
+        &self.domain
+    }
+
     pub fn layers(&self) -> usize {
         self.layers
     }
 
+    /// The maximum porep challenge count per partition.
+    pub fn max_count(&self) -> usize {
+        self.max_count
+    }
+
+    /// Returns `true` if porep challenges are selected from the synthetic challenge set.
+    pub fn is_synthetic(&self) -> bool {
+        self.use_synthetic
+    }
+
     /// Porep challenge count per partition.
     pub fn challenges_count_all(&self) -> usize {
         self.max_count
@@ -94,19 +132,58 @@ impl LayerChallenges {
         seed: &[u8; 32],
         k: u8,
     ) -> Vec<usize> {
+        self.porep_provenances(sector_nodes, replica_id, seed, k)
+            .into_iter()
+            .map(|provenance| provenance.challenge)
+            .collect()
+    }
+
+    /// Returns the porep challenges for partition `k` along with the inputs and the intermediate
+    /// digest each challenge was derived from, so that the derivation can be audited
+    /// independently (see [`verify_challenge_derivation`]).
+    ///
+    /// Fails for synthetic porep, whose challenges are taken from the synthetic challenges
+    /// instead.
+    pub fn derive_porep_with_provenance<D: Domain>(
+        &self,
+        sector_nodes: usize,
+        replica_id: &D,
+        seed: &[u8; 32],
+        k: u8,
+    ) -> Result<Vec<ChallengeProvenance>> {
+        ensure!(sector_nodes > 2, "Too few sector_nodes: {}", sector_nodes);
+        ensure!(
+            !self.use_synthetic,
+            "provenance is only available for interactive porep challenges"
+        );
+        Ok(self.porep_provenances(sector_nodes, replica_id, seed, k))
+    }
+
+    fn porep_provenances<D: Domain>(
+        &self,
+        sector_nodes: usize,
+        replica_id: &D,
+        seed: &[u8; 32],
+        k: u8,
+    ) -> Vec<ChallengeProvenance> {
+        let mut replica_id_bytes = [0u8; 32];
+        replica_id_bytes.copy_from_slice(AsRef::<[u8]>::as_ref(replica_id));
+
         let partition_challenge_count = self.challenges_count_all();
         (0..partition_challenge_count)
             .map(|i| {
                 let j: u32 = ((partition_challenge_count * k as usize) + i) as u32;
-
-                let hash = Sha256::new()
-                    .chain_update(replica_id.into_bytes())
-                    .chain_update(seed)
-                    .chain_update(j.to_le_bytes())
-                    .finalize();
-
-                let bigint = BigUint::from_bytes_le(hash.as_ref());
-                bigint_to_challenge(bigint, sector_nodes)
+                let digest = porep_challenge_digest(&self.domain, &replica_id_bytes, seed, j);
+                let bigint = BigUint::from_bytes_le(&digest);
+
+                ChallengeProvenance {
+                    domain: self.domain,
+                    replica_id: replica_id_bytes,
+                    seed: *seed,
+                    challenge_index: j,
+                    digest,
+                    challenge: bigint_to_challenge(bigint, sector_nodes),
+                }
             })
             .collect()
     }
@@ -124,11 +201,9 @@ impl LayerChallenges {
         let partition_challenge_count = self.challenges_count_all();
         let replica_id: Fr = (*replica_id).into();
         let comm_r: Fr = (*comm_r).into();
-        SynthChallenges::default(sector_nodes, &replica_id, &comm_r).gen_porep_partition_challenges(
-            partition_challenge_count,
-            seed,
-            k as usize,
-        )
+        SynthChallenges::default(sector_nodes, &replica_id, &comm_r)
+            .with_domain(self.domain)
+            .gen_porep_partition_challenges(partition_challenge_count, seed, k as usize)
     }
 
     /// Returns the synthetic challenge indexes of the porep challenges for partition `k`.
@@ -148,11 +223,9 @@ impl LayerChallenges {
         let partition_challenge_count = self.challenges_count_all();
         let replica_id: Fr = (*replica_id).into();
         let comm_r: Fr = (*comm_r).into();
-        SynthChallenges::default(sector_nodes, &replica_id, &comm_r).gen_partition_synth_indexes(
-            partition_challenge_count,
-            seed,
-            k as usize,
-        )
+        SynthChallenges::default(sector_nodes, &replica_id, &comm_r)
+            .with_domain(self.domain)
+            .gen_partition_synth_indexes(partition_challenge_count, seed, k as usize)
     }
 
     /// Returns the entire synthetic challenge set.
@@ -165,7 +238,8 @@ impl LayerChallenges {
         assert!(self.use_synthetic);
         let replica_id: Fr = (*replica_id).into();
         let comm_r: Fr = (*comm_r).into();
-        let synth = SynthChallenges::default(sector_nodes, &replica_id, &comm_r);
+        let synth =
+            SynthChallenges::default(sector_nodes, &replica_id, &comm_r).with_domain(self.domain);
         trace!(
             "generating entire synthetic challenge set (num_synth_challenges = {})",
             synth.num_synth_challenges,
@@ -174,7 +248,56 @@ impl LayerChallenges {
     }
 }
 
-#[derive(Debug, Default)]
+/// The hash preimage and the intermediate digest an interactive porep challenge was derived from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChallengeProvenance {
+    #[serde(default)]
+    pub domain: ChallengeDomain,
+    pub replica_id: [u8; 32],
+    pub seed: [u8; 32],
+    /// The index of the challenge across all partitions, i.e. `k * challenges_per_partition + i`.
+    pub challenge_index: u32,
+    /// `sha256([tag ||] replica_id || seed || challenge_index)`, with the index encoded as
+    /// little-endian and the tag of the domain, if any.
+    pub digest: [u8; 32],
+    /// The challenged node derived from `digest`.
+    pub challenge: usize,
+}
+
+#[inline]
+fn porep_challenge_digest(
+    domain: &ChallengeDomain,
+    replica_id: &[u8; 32],
+    seed: &[u8; 32],
+    challenge_index: u32,
+) -> [u8; 32] {
+    domain
+        .sha256()
+        .chain_update(replica_id)
+        .chain_update(seed)
+        .chain_update(challenge_index.to_le_bytes())
+        .finalize()
+        .into()
+}
+
+/// Recomputes the derivation of an interactive porep challenge from its provenance and returns
+/// `true` if both the digest and the challenged node match.
+pub fn verify_challenge_derivation(sector_nodes: usize, provenance: &ChallengeProvenance) -> bool {
+    if sector_nodes <= 2 {
+        return false;
+    }
+    let digest = porep_challenge_digest(
+        &provenance.domain,
+        &provenance.replica_id,
+        &provenance.seed,
+        provenance.challenge_index,
+    );
+    let challenge = bigint_to_challenge(BigUint::from_bytes_le(&digest), sector_nodes);
+
+    digest == provenance.digest && challenge == provenance.challenge
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ChallengeRequirements {
     pub minimum_challenges: usize,
 }
@@ -195,19 +318,32 @@ pub mod synthetic {
     pub const SYNTHETIC_POREP_VANILLA_PROOFS_KEY: &str = "syn-porep-vanilla-proofs";
     pub const SYNTHETIC_POREP_VANILLA_PROOFS_EXT: &str = "dat";
 
-    // Default synthetic challenge count for production sector sizes.
-    const DEFAULT_SYNTH_CHALLENGE_COUNT: usize = 1 << 18;
+    /// Default synthetic challenge count for production sector sizes.
+    pub const DEFAULT_SYNTH_CHALLENGE_COUNT: usize = 1 << 18;
     const SYNTH_CHALLENGE_SIZE: usize = 32;
     const SYNTH_INDEX_SIZE: usize = 4;
     const CHACHA20_KEY_SIZE: usize = 32;
     const CHACHA20_NONCE: &[u8; 12] = b"synth-porep\x00";
 
-    // The prf used to generate synthetic challenges.
-    fn chacha20_gen(replica_id: &[u8; 32], comm_r: &[u8; 32]) -> ChaCha20 {
-        let key = Blake2b::new()
+    // The blake2b state keyed with `key` that the tag of the domain, if any, was written to.
+    fn blake2b_state(key: &[u8], domain: &ChallengeDomain) -> blake2b_simd::State {
+        let mut state = Blake2b::new()
             .hash_length(CHACHA20_KEY_SIZE)
-            .key(b"filecoin.io|PoRep|1|Synthetic|1|Generation")
-            .to_state()
+            .key(key)
+            .to_state();
+        if let Some(tag) = domain.tag() {
+            state.update(tag);
+        }
+        state
+    }
+
+    // The prf used to generate synthetic challenges.
+    fn chacha20_gen(
+        domain: &ChallengeDomain,
+        replica_id: &[u8; 32],
+        comm_r: &[u8; 32],
+    ) -> ChaCha20 {
+        let key = blake2b_state(b"filecoin.io|PoRep|1|Synthetic|1|Generation", domain)
             .update(replica_id)
             .update(comm_r)
             .finalize();
@@ -216,11 +352,12 @@ pub mod synthetic {
 
     // The prf used to select the synthetic challenges used as porep challenge (i.e. the prf used to
     // generate synthetic challenge indices).
-    fn chacha20_select(replica_id: &[u8; 32], rand: &[u8; 32]) -> ChaCha20 {
-        let key = Blake2b::new()
-            .hash_length(CHACHA20_KEY_SIZE)
-            .key(b"filecoin.io|PoRep|1|Synthetic|1|Selection")
-            .to_state()
+    fn chacha20_select(
+        domain: &ChallengeDomain,
+        replica_id: &[u8; 32],
+        rand: &[u8; 32],
+    ) -> ChaCha20 {
+        let key = blake2b_state(b"filecoin.io|PoRep|1|Synthetic|1|Selection", domain)
             .update(replica_id)
             .update(rand)
             .finalize();
@@ -231,6 +368,7 @@ pub mod synthetic {
         sector_nodes: usize,
         replica_id: [u8; 32],
         comm_r: [u8; 32],
+        domain: ChallengeDomain,
         // The number of synthetic challenges to generate; the porep challenge set will be a subset of
         // these generated synthetic challenges.
         pub(crate) num_synth_challenges: usize,
@@ -247,8 +385,9 @@ pub mod synthetic {
                 sector_nodes: self.sector_nodes,
                 replica_id: self.replica_id,
                 comm_r: self.comm_r,
+                domain: self.domain,
                 num_synth_challenges: self.num_synth_challenges,
-                chacha20: chacha20_gen(&self.replica_id, &self.comm_r),
+                chacha20: chacha20_gen(&self.domain, &self.replica_id, &self.comm_r),
                 i: 0,
             };
             synth.seek(self.i);
@@ -286,11 +425,13 @@ pub mod synthetic {
             );
             let replica_id = replica_id.to_repr();
             let comm_r = comm_r.to_repr();
-            let chacha20 = chacha20_gen(&replica_id, &comm_r);
+            let domain = ChallengeDomain::Mainnet;
+            let chacha20 = chacha20_gen(&domain, &replica_id, &comm_r);
             SynthChallenges {
                 sector_nodes,
                 replica_id,
                 comm_r,
+                domain,
                 num_synth_challenges,
                 chacha20,
                 i: 0,
@@ -302,6 +443,16 @@ pub mod synthetic {
             Self::new(sector_nodes, replica_id, comm_r, num_synth_challenges)
         }
 
+        /// Returns these synthetic challenges derived in `domain`, positioned at the same
+        /// challenge.
+        pub fn with_domain(mut self, domain: ChallengeDomain) -> Self {
+            let i = self.i;
+            self.domain = domain;
+            self.chacha20 = chacha20_gen(&domain, &self.replica_id, &self.comm_r);
+            self.seek(i);
+            self
+        }
+
         /// Seeks to the `i`-th synthetic challenge; seeking to `i` results in the next call to
         /// `SynthChallenges::next` returning the `i`-th synthetic challenge.
         pub(super) fn seek(&mut self, i: usize) {
@@ -328,7 +479,7 @@ pub mod synthetic {
         ) -> Vec<usize> {
             let first_porep_challenge = k * num_partition_challenges;
 
-            let mut chacha20 = chacha20_select(&self.replica_id, rand);
+            let mut chacha20 = chacha20_select(&self.domain, &self.replica_id, rand);
             chacha20
                 .try_seek((first_porep_challenge * SYNTH_INDEX_SIZE) as u32)
                 .expect("seeking should not exceed keystream length");
@@ -393,6 +544,65 @@ mod test {
         assert_eq!(expected as usize, calculated_count);
     }
 
+    #[test]
+    fn test_layer_challenges_serde_roundtrip() {
+        for challenges in [
+            LayerChallenges::new(11, 18),
+            LayerChallenges::new_synthetic(11, 18),
+            LayerChallenges::from_parts(2, 1, true),
+        ] {
+            let json = serde_json::to_string(&challenges).expect("serialize failed");
+            let restored: LayerChallenges =
+                serde_json::from_str(&json).expect("deserialize failed");
+            assert_eq!(restored, challenges);
+            assert_eq!(restored.layers(), challenges.layers());
+            assert_eq!(restored.max_count(), challenges.max_count());
+            assert_eq!(restored.is_synthetic(), challenges.is_synthetic());
+            // The debug output feeds into parameter identifiers and must not change.
+            assert_eq!(format!("{:?}", restored), format!("{:?}", challenges));
+        }
+    }
+
+    #[test]
+    fn test_challenge_derivation_provenance() {
+        let sector_nodes = 1 << 30;
+        let challenges = LayerChallenges::new(11, 18);
+        let rng = &mut thread_rng();
+        let replica_id: Sha256Domain = Sha256Domain::random(rng);
+        let seed: [u8; 32] = rng.gen();
+
+        for k in 0..3 {
+            let provenances = challenges
+                .derive_porep_with_provenance(sector_nodes, &replica_id, &seed, k)
+                .expect("provenance failed");
+            let derived = challenges.derive(sector_nodes, &replica_id, &replica_id, &seed, k);
+            assert_eq!(
+                provenances.iter().map(|p| p.challenge).collect::<Vec<_>>(),
+                derived
+            );
+
+            for provenance in &provenances {
+                assert!(verify_challenge_derivation(sector_nodes, provenance));
+
+                let mut tampered = provenance.clone();
+                tampered.seed[0] ^= 1;
+                assert!(!verify_challenge_derivation(sector_nodes, &tampered));
+
+                let mut tampered = provenance.clone();
+                tampered.challenge += 1;
+                assert!(!verify_challenge_derivation(sector_nodes, &tampered));
+            }
+        }
+
+        let synthetic = LayerChallenges::new_synthetic(11, 18);
+        assert!(synthetic
+            .derive_porep_with_provenance(sector_nodes, &replica_id, &seed, 0)
+            .is_err());
+        assert!(challenges
+            .derive_porep_with_provenance(2, &replica_id, &seed, 0)
+            .is_err());
+    }
+
     #[test]
     fn challenge_derivation() {
         let n = 200;
@@ -534,4 +744,59 @@ mod test {
             synth.gen_porep_challenges(num_porep_challenges, &porep_challenge_randomness);
         assert_eq!(porep_challenges, expected_porep_challenges);
     }
+
+    #[test]
+    fn test_challenge_domain_separation() {
+        let sector_nodes = 1 << 10;
+        let rng = &mut thread_rng();
+        let replica_id: Sha256Domain = Sha256Domain::random(rng);
+        let comm_r: Sha256Domain = Sha256Domain::random(rng);
+        let seed: [u8; 32] = rng.gen();
+        let calibnet = ChallengeDomain::network("calibnet");
+
+        for challenges in [
+            LayerChallenges::new(2, 18),
+            LayerChallenges::new_synthetic(2, 18),
+        ] {
+            let mainnet = challenges
+                .clone()
+                .with_domain(ChallengeDomain::Mainnet)
+                .derive(sector_nodes, &replica_id, &comm_r, &seed, 1);
+            assert_eq!(
+                mainnet,
+                challenges.derive(sector_nodes, &replica_id, &comm_r, &seed, 1)
+            );
+
+            let custom = challenges.clone().with_domain(calibnet);
+            assert_eq!(format!("{:?}", custom), format!("{:?}", challenges));
+            assert_ne!(
+                custom.derive(sector_nodes, &replica_id, &comm_r, &seed, 1),
+                mainnet
+            );
+        }
+
+        let challenges = LayerChallenges::new(2, 18).with_domain(calibnet);
+        for provenance in challenges
+            .derive_porep_with_provenance(sector_nodes, &replica_id, &seed, 0)
+            .expect("provenance failed")
+        {
+            assert_eq!(provenance.domain, calibnet);
+            assert!(verify_challenge_derivation(sector_nodes, &provenance));
+
+            let mut tampered = provenance.clone();
+            tampered.domain = ChallengeDomain::Mainnet;
+            assert!(!verify_challenge_derivation(sector_nodes, &tampered));
+        }
+
+        let replica_id = Fr::from(55);
+        let comm_r = Fr::from(101);
+        let mainnet: Vec<usize> = SynthChallenges::new(sector_nodes, &replica_id, &comm_r, 21)
+            .with_domain(ChallengeDomain::Mainnet)
+            .collect();
+        let mut custom = SynthChallenges::new(sector_nodes, &replica_id, &comm_r, 21);
+        custom.seek(5);
+        let custom: Vec<usize> = custom.with_domain(calibnet).collect();
+        assert_eq!(custom.len(), 16);
+        assert_ne!(custom, mainnet[5..]);
+    }
 }
@@ -29,16 +29,24 @@ pub fn clear_cache_dir(cache_path: &Path) -> Result<()> {
         trace!("tree d deleted");
     }
 
+    clear_tree_c(cache_path)?;
+
+    let labels_glob = StoreConfig::data_path(cache_path, &format!("{}*", LABEL_LAYER_KEY));
+    remove_files_with_glob(&labels_glob)?;
+    trace!("layers deleted");
+
+    Ok(())
+}
+
+/// Discards the persisted tree c. It is regenerated from the labels if it is needed for proving
+/// again, which trades CPU time for disk space.
+pub fn clear_tree_c(cache_path: &Path) -> Result<()> {
     // TreeC might be split into several sub-tree. They have the same file name, but a number
     // attached separated by a dash. Hence add a glob after the identifier.
     let tree_c_glob = StoreConfig::data_path(cache_path, &format!("{}*", CacheKey::CommCTree));
     remove_files_with_glob(&tree_c_glob)?;
     trace!("tree c deleted");
 
-    let labels_glob = StoreConfig::data_path(cache_path, &format!("{}*", LABEL_LAYER_KEY));
-    remove_files_with_glob(&labels_glob)?;
-    trace!("layers deleted");
-
     Ok(())
 }
 
@@ -1,13 +1,14 @@
 use std::fs::{self, create_dir_all, remove_file, rename, File};
 use std::io::{self, BufReader};
 use std::path::Path;
+use std::sync::mpsc::SyncSender;
 
 use anyhow::Context;
 use filecoin_hashers::Hasher;
 use log::{info, warn};
 use merkletree::{merkle::Element, store::StoreConfig};
 use storage_proofs_core::{
-    cache_key::CacheKey, drgraph::Graph, error::Result, merkle::MerkleTreeTrait,
+    cache_key::CacheKey, drgraph::Graph, error::Result, merkle::MerkleTreeTrait, util::NODE_SIZE,
 };
 
 use crate::stacked::vanilla::{proof::LayerState, StackedBucketGraph};
@@ -16,6 +17,61 @@ use crate::stacked::vanilla::{proof::LayerState, StackedBucketGraph};
 pub mod multi;
 pub mod single;
 
+/// Number of nodes (64 MiB) per range of labels that is streamed while the last layer is
+/// generated.
+pub const LABELS_STREAM_NODES: usize = (1 << 26) / NODE_SIZE;
+
+/// Number of streamed ranges that may be queued for the receiver.
+pub const LABELS_STREAM_QUEUE: usize = 4;
+
+/// The labels of a range of nodes of the last layer, streamed while the labels are generated.
+///
+/// The ranges are sent in order, all previous layers are stored on disk by the time a range is
+/// sent. Labeling never waits for the receiver: once the queue is full, the rest of the layer is
+/// not streamed and has to be read from disk after labeling.
+#[derive(Debug)]
+pub struct LabeledRange {
+    /// The first node of the range.
+    pub start: usize,
+    /// The labels of the nodes of the range.
+    pub labels: Vec<u8>,
+}
+
+/// Streams `labels`, which start at node `start`, in ranges of at most `LABELS_STREAM_NODES`
+/// nodes. Returns `false` if a range could not be sent, because the receiver fell behind or hung
+/// up, no further ranges of the layer must be sent then.
+pub(crate) fn send_labels(
+    labels_tx: &SyncSender<LabeledRange>,
+    start: usize,
+    labels: &[u8],
+) -> bool {
+    labels
+        .chunks(LABELS_STREAM_NODES * NODE_SIZE)
+        .enumerate()
+        .all(|(i, chunk)| {
+            labels_tx
+                .try_send(LabeledRange {
+                    start: start + i * LABELS_STREAM_NODES,
+                    labels: chunk.to_vec(),
+                })
+                .is_ok()
+        })
+}
+
+/// Returns the `StoreConfig` with which the labels of `layer` are stored.
+pub(crate) fn layer_config<P: AsRef<Path>>(
+    cache_path: P,
+    layer: usize,
+    nodes: usize,
+) -> StoreConfig {
+    StoreConfig {
+        path: cache_path.as_ref().to_path_buf(),
+        id: CacheKey::label_layer(layer),
+        size: Some(nodes),
+        rows_to_discard: 0,
+    }
+}
+
 /// Prepares the necessary `StoreConfig`s with which the layers are stored.
 /// Also checks for already existing layers and marks them as such.
 pub fn prepare_layers<P, Tree: 'static + MerkleTreeTrait>(
@@ -26,12 +82,7 @@ pub fn prepare_layers<P, Tree: 'static + MerkleTreeTrait>(
 where
     P: AsRef<Path>,
 {
-    let label_configs = (1..=layers).map(|layer| StoreConfig {
-        path: cache_path.as_ref().to_path_buf(),
-        id: CacheKey::label_layer(layer),
-        size: Some(graph.size()),
-        rows_to_discard: 0,
-    });
+    let label_configs = (1..=layers).map(|layer| layer_config(&cache_path, layer, graph.size()));
 
     let mut states = Vec::with_capacity(layers);
     for (layer, label_config) in (1..=layers).zip(label_configs) {
@@ -108,3 +159,28 @@ pub fn is_layer_written<Tree: 'static + MerkleTreeTrait>(
 
     Ok(true)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::mpsc::sync_channel;
+
+    #[test]
+    fn test_send_labels_does_not_block() {
+        let (labels_tx, labels_rx) = sync_channel(1);
+        let labels = vec![7u8; 4 * NODE_SIZE];
+
+        assert!(send_labels(&labels_tx, 0, &labels));
+        // The queue is full, the labels are dropped instead of waiting for the receiver.
+        assert!(!send_labels(&labels_tx, 4, &labels));
+
+        let range = labels_rx.recv().expect("no labels streamed");
+        assert_eq!(range.start, 0);
+        assert_eq!(range.labels, labels);
+        assert!(labels_rx.try_recv().is_err());
+
+        drop(labels_rx);
+        assert!(!send_labels(&labels_tx, 4, &labels));
+    }
+}
@@ -4,6 +4,7 @@ use std::mem::{self, size_of};
 use std::path::Path;
 use std::sync::{
     atomic::{AtomicU64, Ordering::SeqCst},
+    mpsc::SyncSender,
     Arc, MutexGuard,
 };
 use std::thread;
@@ -26,11 +27,14 @@ use storage_proofs_core::{
     settings::SETTINGS,
     util::NODE_SIZE,
 };
+use tracing::info_span;
 
 use crate::stacked::vanilla::{
     cache::ParentCache,
     cores::{bind_core, checkout_core_group, CoreIndex},
-    create_label::{prepare_layers, read_layer, write_layer},
+    create_label::{
+        prepare_layers, read_layer, send_labels, write_layer, LabeledRange, LABELS_STREAM_NODES,
+    },
     graph::{StackedBucketGraph, DEGREE, EXP_DEGREE},
     memory_handling::{setup_create_label_memory, CacheReader},
     params::{Labels, LabelsCache},
@@ -201,6 +205,7 @@ fn create_label_runner(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn create_layer_labels(
     parents_cache: &CacheReader<u32>,
     replica_id: &[u8],
@@ -209,6 +214,7 @@ fn create_layer_labels(
     num_nodes: u64,
     cur_layer: u32,
     core_group: Arc<Option<MutexGuard<'_, Vec<CoreIndex>>>>,
+    mut labels_tx: Option<&SyncSender<LabeledRange>>,
 ) {
     info!("Creating labels for layer {}", cur_layer);
     // num_producers is the number of producer threads
@@ -424,6 +430,20 @@ fn create_layer_labels(
                 }
                 i += 1;
                 cur_slot = (cur_slot + 1) % lookahead;
+
+                // Stream the labels once a range is complete, nodes before `i` are final.
+                if let Some(tx) = labels_tx {
+                    let stream_nodes = LABELS_STREAM_NODES as u64;
+                    if i % stream_nodes == 0 || i == num_nodes {
+                        let start = ((i - 1) / stream_nodes * stream_nodes) as usize;
+                        let labels = unsafe {
+                            &layer_labels.as_slice()[start * NODE_WORDS..i as usize * NODE_WORDS]
+                        };
+                        if !send_labels(tx, start, labels.as_byte_slice()) {
+                            labels_tx = None;
+                        }
+                    }
+                }
             }
         }
 
@@ -447,6 +467,7 @@ pub fn create_labels_for_encoding<
     layers: usize,
     replica_id: T,
     cache_path: P,
+    labels_tx: Option<&SyncSender<LabeledRange>>,
 ) -> Result<(Labels<Tree>, Vec<LayerState>)> {
     info!("create labels");
 
@@ -477,6 +498,7 @@ pub fn create_labels_for_encoding<
     )?;
 
     for (layer, layer_state) in (1..=layers).zip(layer_states.iter()) {
+        let _span = info_span!("label_layer", layer).entered();
         info!("Layer {}", layer);
 
         if layer_state.generated {
@@ -484,6 +506,9 @@ pub fn create_labels_for_encoding<
 
             // load the already generated layer into exp_labels
             read_layer(&layer_state.config, &mut exp_labels)?;
+            if let Some(labels_tx) = labels_tx.filter(|_| layer == layers) {
+                send_labels(labels_tx, 0, &exp_labels);
+            }
             continue;
         }
 
@@ -505,6 +530,8 @@ pub fn create_labels_for_encoding<
             node_count,
             layer as u32,
             core_group.clone(),
+            // Only the last layer is streamed.
+            labels_tx.filter(|_| layer == layers),
         );
 
         // Cache reset happens in two parts.
@@ -595,6 +622,7 @@ pub fn create_labels_for_decoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
             node_count,
             layer as u32,
             core_group.clone(),
+            None,
         );
 
         // Cache reset happens in two parts.
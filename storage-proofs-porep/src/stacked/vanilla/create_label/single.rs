@@ -1,24 +1,31 @@
 use std::marker::PhantomData;
 use std::mem;
+use std::ops::Range;
 use std::path::Path;
+use std::sync::mpsc::SyncSender;
 
-use anyhow::{Context, Result};
-use filecoin_hashers::Hasher;
+use anyhow::{ensure, Context, Result};
+use filecoin_hashers::{Domain, Hasher};
 use generic_array::typenum::Unsigned;
 use log::info;
 use merkletree::store::{DiskStore, Store, StoreConfig};
 use sha2raw::Sha256;
 use storage_proofs_core::{
+    cache_key::CacheKey,
     drgraph::Graph,
     merkle::MerkleTreeTrait,
     util::{data_at_node_offset, NODE_SIZE},
 };
+use tracing::info_span;
 
 use crate::stacked::vanilla::{
     cache::ParentCache,
-    create_label::{prepare_layers, read_layer, write_layer},
+    create_label::{
+        is_layer_written, prepare_layers, read_layer, send_labels, write_layer, LabeledRange,
+        LABELS_STREAM_NODES,
+    },
     proof::LayerState,
-    Labels, LabelsCache, StackedBucketGraph,
+    LabelingProof, Labels, LabelsCache, StackedBucketGraph, TOTAL_PARENTS,
 };
 
 #[allow(clippy::type_complexity)]
@@ -28,10 +35,11 @@ pub fn create_labels_for_encoding<
     P: AsRef<Path>,
 >(
     graph: &StackedBucketGraph<Tree::Hasher>,
-    parents_cache: &mut ParentCache,
+    mut parents_cache: Option<&mut ParentCache>,
     layers: usize,
     replica_id: T,
     cache_path: P,
+    labels_tx: Option<&SyncSender<LabeledRange>>,
 ) -> Result<(Labels<Tree>, Vec<LayerState>)> {
     info!("generate labels");
 
@@ -43,39 +51,53 @@ pub fn create_labels_for_encoding<
     let mut exp_labels = vec![0u8; layer_size]; // Buffer for labels of the previous layer, needed for expander parents
 
     for (layer, layer_state) in (1..=layers).zip(layer_states.iter()) {
+        let _span = info_span!("label_layer", layer).entered();
         info!("generating layer: {}", layer);
         if layer_state.generated {
             info!("skipping layer {}, already generated", layer);
 
             // load the already generated layer into exp_labels
             read_layer(&layer_state.config, &mut exp_labels)?;
+            if let Some(labels_tx) = labels_tx.filter(|_| layer == layers) {
+                send_labels(labels_tx, 0, &exp_labels);
+            }
             continue;
         }
 
-        parents_cache.reset()?;
+        if let Some(parents_cache) = parents_cache.as_deref_mut() {
+            parents_cache.reset()?;
+        }
 
-        if layer == 1 {
-            for node in 0..graph.size() {
-                create_label(
-                    graph,
-                    Some(parents_cache),
-                    &replica_id,
-                    &mut layer_labels,
-                    layer,
-                    node,
-                )?;
+        // Only the last layer is streamed.
+        let mut labels_tx = labels_tx.filter(|_| layer == layers);
+        for start in (0..graph.size()).step_by(LABELS_STREAM_NODES) {
+            let end = usize::min(start + LABELS_STREAM_NODES, graph.size());
+            for node in start..end {
+                if layer == 1 {
+                    create_label(
+                        graph,
+                        parents_cache.as_deref_mut(),
+                        &replica_id,
+                        &mut layer_labels,
+                        layer,
+                        node,
+                    )?;
+                } else {
+                    create_label_exp(
+                        graph,
+                        parents_cache.as_deref_mut(),
+                        &replica_id,
+                        &exp_labels,
+                        &mut layer_labels,
+                        layer,
+                        node,
+                    )?;
+                }
             }
-        } else {
-            for node in 0..graph.size() {
-                create_label_exp(
-                    graph,
-                    Some(parents_cache),
-                    &replica_id,
-                    &exp_labels,
-                    &mut layer_labels,
-                    layer,
-                    node,
-                )?;
+            if let Some(tx) = labels_tx {
+                if !send_labels(tx, start, &layer_labels[start * NODE_SIZE..end * NODE_SIZE]) {
+                    labels_tx = None;
+                }
             }
         }
 
@@ -106,7 +128,7 @@ pub fn create_labels_for_encoding<
 #[allow(clippy::type_complexity)]
 pub fn create_labels_for_decoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]>>(
     graph: &StackedBucketGraph<Tree::Hasher>,
-    parents_cache: &mut ParentCache,
+    mut parents_cache: Option<&mut ParentCache>,
     layers: usize,
     replica_id: T,
     config: StoreConfig,
@@ -124,13 +146,15 @@ pub fn create_labels_for_decoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
     for layer in 1..=layers {
         info!("generating layer: {}", layer);
 
-        parents_cache.reset()?;
+        if let Some(parents_cache) = parents_cache.as_deref_mut() {
+            parents_cache.reset()?;
+        }
 
         if layer == 1 {
             for node in 0..graph.size() {
                 create_label(
                     graph,
-                    Some(parents_cache),
+                    parents_cache.as_deref_mut(),
                     &replica_id,
                     &mut layer_labels,
                     layer,
@@ -141,7 +165,7 @@ pub fn create_labels_for_decoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
             for node in 0..graph.size() {
                 create_label_exp(
                     graph,
-                    Some(parents_cache),
+                    parents_cache.as_deref_mut(),
                     &replica_id,
                     &exp_labels,
                     &mut layer_labels,
@@ -175,6 +199,94 @@ pub fn create_labels_for_decoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
     Ok(LabelsCache::<Tree> { labels })
 }
 
+/// Recomputes the labels of `nodes` on `layer` from the label layers stored in `cache_path` and
+/// compares them against the stored labels. Only the labels of `nodes` and their parents are
+/// read, the layers are not loaded into memory.
+///
+/// Every label is derived from the *stored* labels of its parents, thus the returned node is the
+/// first one within `nodes` whose stored label is inconsistent with its parents, i.e. either the
+/// label itself is corrupted or one of its parents is. Returns `None` if all labels match.
+pub fn find_divergent_label<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]>, P: AsRef<Path>>(
+    graph: &StackedBucketGraph<Tree::Hasher>,
+    replica_id: T,
+    cache_path: P,
+    layer: usize,
+    nodes: Range<usize>,
+) -> Result<Option<usize>> {
+    ensure!(layer > 0, "layers are 1-indexed");
+    ensure!(
+        nodes.end <= graph.size(),
+        "node range {:?} exceeds the sector's {} nodes",
+        nodes,
+        graph.size(),
+    );
+
+    let layer_store = |layer: usize| -> Result<DiskStore<<Tree::Hasher as Hasher>::Domain>> {
+        let config = StoreConfig {
+            path: cache_path.as_ref().to_path_buf(),
+            id: CacheKey::label_layer(layer),
+            size: Some(graph.size()),
+            rows_to_discard: 0,
+        };
+        ensure!(
+            is_layer_written::<Tree>(graph, &config)?,
+            "labels for layer {} are missing or have an invalid size",
+            layer,
+        );
+        DiskStore::new_from_disk(graph.size(), Tree::Arity::to_usize(), &config)
+    };
+
+    // Only the labels of the nodes and their parents are read. The expander parents of all
+    // layers but the first are taken from the previous layer.
+    let layer_labels = layer_store(layer)?;
+    let exp_labels = if layer > 1 {
+        Some(layer_store(layer - 1)?)
+    } else {
+        None
+    };
+    let replica_id = <Tree::Hasher as Hasher>::Domain::try_from_bytes(replica_id.as_ref())?;
+
+    let base_degree = graph.base_graph().degree();
+    let mut parents = vec![0u32; graph.degree()];
+    for node in nodes {
+        // The first node has no parents, the parents of all others are repeated up to
+        // `TOTAL_PARENTS` like `create_label` does.
+        let parents_data = if node == 0 {
+            Vec::new()
+        } else {
+            let degree = match exp_labels {
+                Some(_) => graph.degree(),
+                None => base_degree,
+            };
+            graph.parents(node, &mut parents)?;
+            let parents_data = parents[..degree]
+                .iter()
+                .enumerate()
+                .map(|(i, parent)| match exp_labels {
+                    Some(ref exp_labels) if i >= base_degree => {
+                        exp_labels.read_at(*parent as usize)
+                    }
+                    _ => layer_labels.read_at(*parent as usize),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            parents_data
+                .into_iter()
+                .cycle()
+                .take(TOTAL_PARENTS)
+                .collect()
+        };
+
+        let label = LabelingProof::<Tree::Hasher>::new(layer as u32, node as u64, parents_data)
+            .create_label(&replica_id);
+        if label != layer_labels.read_at(node)? {
+            info!("label of node {} on layer {} diverges", node, layer);
+            return Ok(Some(node));
+        }
+    }
+
+    Ok(None)
+}
+
 pub fn create_label<H: Hasher, T: AsRef<[u8]>>(
     graph: &StackedBucketGraph<H>,
     cache: Option<&mut ParentCache>,
@@ -0,0 +1,316 @@
+//! Differential testing of the CPU and GPU tree builders.
+//!
+//! The GPU builders for tree_c and tree_r_last are implemented by neptune's kernels, independent
+//! of the CPU builders. They must produce identical trees, a divergence, e.g. after upgrading
+//! neptune, would produce sectors that fail their proofs. The functions here build both trees
+//! for random small sector configurations with both builders and check that the roots and the
+//! persisted stores are identical. They can be called from the tests of any crate that enables
+//! the `gpu-differential-testing` feature.
+
+use std::any::TypeId;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, ensure, Context};
+use filecoin_hashers::{poseidon::PoseidonHasher, sha256::Sha256Hasher, Domain, Hasher};
+use generic_array::typenum::{Unsigned, U11, U2, U4, U8};
+use merkletree::{
+    merkle::get_merkle_tree_len,
+    store::{DiskStore, Store, StoreConfig},
+};
+use proptest::{
+    sample::select,
+    strategy::Strategy,
+    test_runner::{Config, TestCaseError, TestRunner},
+};
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use storage_proofs_core::{
+    cache_key::{CacheKey, LABEL_LAYER_KEY},
+    error::Result,
+    merkle::{get_base_tree_count, split_config, MerkleTreeTrait, PoseidonArity},
+    util::default_rows_to_discard,
+};
+use tempfile::tempdir;
+
+use crate::stacked::vanilla::{Data, Labels, LabelsCache, StackedDrg, TreeRElementData};
+
+type Drg<'a, Tree> = StackedDrg<'a, Tree, Sha256Hasher>;
+
+/// The number of layers, i.e. the column arities, that are checked.
+const LAYERS: [usize; 4] = [2, 4, 8, 11];
+
+const REPLICA_KEY: &str = "replica";
+
+/// A sector configuration to build the trees for.
+#[derive(Clone, Copy, Debug)]
+pub struct TreeBuilderCase {
+    /// The number of leaves of each base tree, it must be a power of the base tree arity.
+    pub base_tree_leafs: usize,
+    /// The number of layers, which is the arity of the column hashes of tree_c.
+    pub layers: usize,
+    /// The seed of the random labels and replica.
+    pub seed: u64,
+}
+
+fn write_random_nodes<Tree: MerkleTreeTrait>(path: &Path, nodes: usize, seed: u64) -> Result<()> {
+    let mut rng = XorShiftRng::seed_from_u64(seed);
+    let mut file = File::create(path).with_context(|| format!("could not create {:?}", path))?;
+    for _ in 0..nodes {
+        let node = <Tree::Hasher as Hasher>::Domain::random(&mut rng);
+        file.write_all(AsRef::<[u8]>::as_ref(&node))?;
+    }
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Checks that `dir_a` and `dir_b` contain the same files with identical contents.
+fn compare_dirs(dir_a: &Path, dir_b: &Path) -> Result<()> {
+    let list = |dir: &Path| -> Result<Vec<PathBuf>> {
+        let mut names = fs::read_dir(dir)?
+            .map(|entry| Ok(PathBuf::from(entry?.file_name())))
+            .collect::<Result<Vec<_>>>()?;
+        names.sort();
+        Ok(names)
+    };
+    let names = list(dir_a)?;
+    ensure!(names == list(dir_b)?, "different stores were written");
+    for name in names {
+        let a = fs::read(dir_a.join(&name))?;
+        let b = fs::read(dir_b.join(&name))?;
+        ensure!(a == b, "the stores {:?} differ", name);
+    }
+    Ok(())
+}
+
+fn tree_config<Tree: MerkleTreeTrait>(
+    dir: &Path,
+    cache_key: CacheKey,
+    base_tree_leafs: usize,
+    rows_to_discard: usize,
+) -> Result<StoreConfig> {
+    Ok(StoreConfig {
+        path: dir.to_path_buf(),
+        id: cache_key.to_string(),
+        size: Some(get_merkle_tree_len(
+            base_tree_leafs,
+            Tree::Arity::to_usize(),
+        )?),
+        rows_to_discard,
+    })
+}
+
+/// Builds tree_c from random labels with both builders and compares the results.
+pub fn check_tree_c<Tree: 'static + MerkleTreeTrait>(case: TreeBuilderCase) -> Result<()> {
+    match case.layers {
+        2 => check_tree_c_with_arity::<Tree, U2>(case),
+        4 => check_tree_c_with_arity::<Tree, U4>(case),
+        8 => check_tree_c_with_arity::<Tree, U8>(case),
+        11 => check_tree_c_with_arity::<Tree, U11>(case),
+        layers => Err(anyhow!("unsupported number of layers {}", layers)),
+    }
+}
+
+fn check_tree_c_with_arity<Tree, ColumnArity>(case: TreeBuilderCase) -> Result<()>
+where
+    Tree: 'static + MerkleTreeTrait,
+    ColumnArity: 'static + PoseidonArity,
+{
+    let tree_count = get_base_tree_count::<Tree>();
+    let sector_nodes = case.base_tree_leafs * tree_count;
+
+    let labels_dir = tempdir()?;
+    let label_configs = (1..=case.layers)
+        .map(|layer| {
+            let config = StoreConfig {
+                path: labels_dir.path().to_path_buf(),
+                id: format!("{}-{}", LABEL_LAYER_KEY, layer),
+                size: Some(sector_nodes),
+                rows_to_discard: 0,
+            };
+            write_random_nodes::<Tree>(
+                &StoreConfig::data_path(&config.path, &config.id),
+                sector_nodes,
+                case.seed.wrapping_add(layer as u64),
+            )?;
+            Ok(config)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let labels = LabelsCache::<Tree>::new(&Labels::new(label_configs))?;
+
+    let cpu_dir = tempdir()?;
+    let gpu_dir = tempdir()?;
+    let cpu_configs = split_config(
+        tree_config::<Tree>(cpu_dir.path(), CacheKey::CommCTree, case.base_tree_leafs, 0)?,
+        tree_count,
+    )?;
+    let gpu_configs = split_config(
+        tree_config::<Tree>(gpu_dir.path(), CacheKey::CommCTree, case.base_tree_leafs, 0)?,
+        tree_count,
+    )?;
+
+    let cpu_root = Drg::<Tree>::generate_tree_c_cpu::<ColumnArity, Tree::Arity>(
+        case.base_tree_leafs,
+        tree_count,
+        cpu_configs,
+        &labels,
+    )?
+    .root();
+    let gpu_root = Drg::<Tree>::generate_tree_c_gpu::<ColumnArity, Tree::Arity>(
+        case.base_tree_leafs,
+        tree_count,
+        gpu_configs,
+        &labels,
+    )?
+    .root();
+
+    ensure!(
+        cpu_root == gpu_root,
+        "tree_c roots differ for {:?}: cpu {:?}, gpu {:?}",
+        case,
+        cpu_root,
+        gpu_root
+    );
+    compare_dirs(cpu_dir.path(), gpu_dir.path())
+        .with_context(|| format!("tree_c stores differ for {:?}", case))
+}
+
+fn tree_r_data_cpu<Tree: 'static + MerkleTreeTrait>(
+    source: &DiskStore<<Tree::Hasher as Hasher>::Domain>,
+    _data: Option<&mut Data<'_>>,
+    start: usize,
+    end: usize,
+) -> Result<TreeRElementData<Tree>> {
+    Ok(TreeRElementData::ElementList(
+        source.read_range(start..end)?,
+    ))
+}
+
+fn tree_r_data_gpu<Tree: 'static + MerkleTreeTrait>(
+    source: &DiskStore<<Tree::Hasher as Hasher>::Domain>,
+    _data: Option<&mut Data<'_>>,
+    start: usize,
+    end: usize,
+) -> Result<TreeRElementData<Tree>> {
+    let nodes = source.read_range(start..end)?;
+    Ok(TreeRElementData::FrList(
+        nodes.into_par_iter().map(Into::into).collect(),
+    ))
+}
+
+/// Builds tree_r_last over a random replica with both builders and compares the results.
+pub fn check_tree_r_last<Tree: 'static + MerkleTreeTrait>(case: TreeBuilderCase) -> Result<()> {
+    let tree_count = get_base_tree_count::<Tree>();
+    let sector_nodes = case.base_tree_leafs * tree_count;
+    let rows_to_discard = default_rows_to_discard(case.base_tree_leafs, Tree::Arity::to_usize());
+
+    let replica_dir = tempdir()?;
+    let replica_config = StoreConfig {
+        path: replica_dir.path().to_path_buf(),
+        id: REPLICA_KEY.to_string(),
+        size: Some(sector_nodes),
+        rows_to_discard: 0,
+    };
+    let replica_path: PathBuf = StoreConfig::data_path(&replica_config.path, &replica_config.id);
+    write_random_nodes::<Tree>(&replica_path, sector_nodes, case.seed)?;
+    let source = DiskStore::new_from_disk(sector_nodes, Tree::Arity::to_usize(), &replica_config)?;
+
+    let cpu_dir = tempdir()?;
+    let gpu_dir = tempdir()?;
+    let cpu_root = Drg::<Tree>::generate_tree_r_last_cpu(
+        &mut Data::empty(),
+        case.base_tree_leafs,
+        tree_count,
+        tree_config::<Tree>(
+            cpu_dir.path(),
+            CacheKey::CommRLastTree,
+            case.base_tree_leafs,
+            rows_to_discard,
+        )?,
+        replica_path.clone(),
+        &source,
+        tree_r_data_cpu::<Tree>,
+    )?
+    .root();
+    let gpu_root = Drg::<Tree>::generate_tree_r_last_gpu(
+        &mut Data::empty(),
+        case.base_tree_leafs,
+        tree_count,
+        tree_config::<Tree>(
+            gpu_dir.path(),
+            CacheKey::CommRLastTree,
+            case.base_tree_leafs,
+            rows_to_discard,
+        )?,
+        replica_path,
+        &source,
+        tree_r_data_gpu::<Tree>,
+    )?
+    .root();
+
+    ensure!(
+        cpu_root == gpu_root,
+        "tree_r_last roots differ for {:?}: cpu {:?}, gpu {:?}",
+        case,
+        cpu_root,
+        gpu_root
+    );
+    compare_dirs(cpu_dir.path(), gpu_dir.path())
+        .with_context(|| format!("tree_r_last stores differ for {:?}", case))
+}
+
+/// Checks `cases` random configurations with base trees of up to `arity^max_height` leaves.
+/// A failure is shrunk to a minimal configuration, which is part of the returned error.
+pub fn check_random_configurations<Tree: 'static + MerkleTreeTrait>(
+    cases: u32,
+    max_height: u32,
+) -> Result<()> {
+    ensure!(
+        TypeId::of::<Tree::Hasher>() == TypeId::of::<PoseidonHasher>(),
+        "the GPU tree builders only support Poseidon"
+    );
+    ensure!(max_height > 0, "the base trees need at least one level");
+
+    let arity = Tree::Arity::to_usize();
+    let strategy = (1..=max_height, select(LAYERS.to_vec()), 0..u64::MAX).prop_map(
+        move |(height, layers, seed)| TreeBuilderCase {
+            base_tree_leafs: arity.pow(height),
+            layers,
+            seed,
+        },
+    );
+
+    let mut runner = TestRunner::new(Config {
+        cases,
+        ..Config::default()
+    });
+    runner
+        .run(&strategy, |case| {
+            check_tree_c::<Tree>(case)
+                .and_then(|_| check_tree_r_last::<Tree>(case))
+                .map_err(|err| TestCaseError::fail(format!("{:?}", err)))
+        })
+        .map_err(|err| anyhow!("CPU and GPU tree builders diverge: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use generic_array::typenum::U0;
+    use storage_proofs_core::merkle::LCTree;
+
+    #[test]
+    fn test_tree_builders_agree_base_tree() {
+        check_random_configurations::<LCTree<PoseidonHasher, U8, U0, U0>>(4, 3)
+            .expect("tree builders diverge");
+    }
+
+    #[test]
+    fn test_tree_builders_agree_sub_tree() {
+        check_random_configurations::<LCTree<PoseidonHasher, U8, U2, U0>>(4, 2)
+            .expect("tree builders diverge");
+    }
+}
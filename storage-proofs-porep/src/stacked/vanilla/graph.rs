@@ -1,6 +1,8 @@
+use std::cell::RefCell;
 use std::convert::{TryFrom, TryInto};
 use std::fmt::{self, Debug, Formatter};
 use std::marker::PhantomData;
+use std::path::Path;
 
 use anyhow::ensure;
 use filecoin_hashers::Hasher;
@@ -10,7 +12,7 @@ use storage_proofs_core::{
     api_version::ApiVersion,
     crypto::{
         derive_porep_domain_seed,
-        feistel::{self, FeistelPrecomputed},
+        feistel::{self, FeistelBatchBuffers, FeistelPrecomputed, FeistelRoundKeys},
         FEISTEL_DST,
     },
     drgraph::{BucketGraph, Graph, BASE_DEGREE},
@@ -21,13 +23,57 @@ use storage_proofs_core::{
     PoRepID,
 };
 
-use crate::stacked::vanilla::cache::ParentCache;
+use crate::stacked::vanilla::{cache::ParentCache, proof::TOTAL_PARENTS};
 
 /// The expansion degree used for Stacked Graphs.
 pub const EXP_DEGREE: usize = 8;
 
 pub(crate) const DEGREE: usize = BASE_DEGREE + EXP_DEGREE;
 
+thread_local! {
+    // The buffers of the batched Feistel permutation of the expansion parents, reused for all
+    // nodes the current thread generates parents for.
+    static FEISTEL_BUFFERS: RefCell<FeistelBatchBuffers> = RefCell::new(Default::default());
+}
+
+/// Returns true if `porep_id` is laid out like the porep id of a registered seal proof, i.e. the
+/// registered proof id followed by a zero nonce.
+pub fn is_production_porep_id(porep_id: &PoRepID) -> bool {
+    porep_id[8..].iter().all(|byte| *byte == 0)
+}
+
+/// Checks the degrees of a stacked graph for `porep_id` and `api_version`.
+///
+/// Production configurations must use `BASE_DEGREE` base and `EXP_DEGREE` expansion parents,
+/// which all api versions released so far require. Other configurations may use any degrees
+/// [`StackedGraph::new`] accepts, they are labeled and proven without the parent cache.
+pub fn check_degrees(
+    porep_id: &PoRepID,
+    api_version: ApiVersion,
+    base_degree: usize,
+    expansion_degree: usize,
+) -> Result<()> {
+    if !is_production_porep_id(porep_id) {
+        return Ok(());
+    }
+
+    let (expected_base_degree, expected_expansion_degree) = match api_version {
+        ApiVersion::V1_0_0 | ApiVersion::V1_1_0 | ApiVersion::V1_2_0 => (BASE_DEGREE, EXP_DEGREE),
+    };
+    ensure!(
+        base_degree == expected_base_degree && expansion_degree == expected_expansion_degree,
+        "unsupported graph degrees (base: {}, expansion: {}) for porep id {:?} and api version \
+        {}, expected base degree {} and expansion degree {}",
+        base_degree,
+        expansion_degree,
+        porep_id,
+        api_version,
+        expected_base_degree,
+        expected_expansion_degree,
+    );
+    Ok(())
+}
+
 #[derive(Clone)]
 pub struct StackedGraph<H, G>
 where
@@ -38,6 +84,7 @@ where
     base_graph: G,
     pub(crate) feistel_keys: [feistel::Index; 4],
     feistel_precomputed: FeistelPrecomputed,
+    feistel_round_keys: FeistelRoundKeys,
     api_version: ApiVersion,
     id: String,
     _h: PhantomData<H>,
@@ -95,6 +142,9 @@ where
     H: Hasher,
     G: Graph<H> + ParameterSetMetadata + Sync + Send,
 {
+    /// Creates a stacked graph with `base_degree` DRG parents and `expansion_degree` expander
+    /// parents per node. Production configurations are additionally restricted by
+    /// [`check_degrees`].
     pub fn new(
         base_graph: Option<G>,
         nodes: usize,
@@ -103,8 +153,17 @@ where
         porep_id: PoRepID,
         api_version: ApiVersion,
     ) -> Result<Self> {
-        assert_eq!(base_degree, BASE_DEGREE);
-        assert_eq!(expansion_degree, EXP_DEGREE);
+        ensure!(
+            base_degree >= 2,
+            "base degree must be at least 2, got {}",
+            base_degree
+        );
+        ensure!(
+            (1..=EXP_DEGREE).contains(&expansion_degree),
+            "expansion degree must be within [1, {}], got {}",
+            EXP_DEGREE,
+            expansion_degree
+        );
         ensure!(nodes <= u32::MAX as usize, "too many nodes");
 
         let base_graph = match base_graph {
@@ -125,6 +184,7 @@ where
             expansion_degree,
             feistel_keys,
             feistel_precomputed: feistel::precompute((expansion_degree * nodes) as feistel::Index),
+            feistel_round_keys: FeistelRoundKeys::new(&feistel_keys),
             api_version,
             _h: PhantomData,
         };
@@ -132,6 +192,12 @@ where
         Ok(res)
     }
 
+    /// Returns true if the graph has `BASE_DEGREE` base and `EXP_DEGREE` expansion parents, which
+    /// the parent cache and the multicore labeling are laid out for.
+    pub fn has_production_degrees(&self) -> bool {
+        self.base_graph.degree() == BASE_DEGREE && self.expansion_degree == EXP_DEGREE
+    }
+
     /// Returns a reference to the parent cache.
     pub fn parent_cache(&self) -> Result<ParentCache> {
         // Number of nodes to be cached in memory
@@ -143,6 +209,15 @@ where
 
         ParentCache::new(cache_size, cache_entries, self)
     }
+
+    /// Returns the parent cache in `dir`, generating it if it doesn't exist yet.
+    pub fn parent_cache_in(&self, dir: &Path) -> Result<ParentCache> {
+        let default_cache_size = SETTINGS.sdr_parents_cache_size;
+        let cache_entries = self.size() as u32;
+        let cache_size = cache_entries.min(default_cache_size);
+
+        ParentCache::new_in(cache_size, cache_entries, self, dir)
+    }
     pub fn copy_parents_data_exp(
         &self,
         node: u32,
@@ -154,12 +229,16 @@ where
         if let Some(ref mut cache) = cache {
             let cache_parents = cache.read(node)?;
             Ok(self.copy_parents_data_inner_exp(&cache_parents, base_data, exp_data, hasher))
-        } else {
+        } else if self.has_production_degrees() {
             let mut cache_parents = [0u32; DEGREE];
 
             self.parents(node as usize, &mut cache_parents[..])
                 .expect("parents failure");
             Ok(self.copy_parents_data_inner_exp(&cache_parents, base_data, exp_data, hasher))
+        } else {
+            let mut parents = vec![0u32; self.degree()];
+            self.parents(node as usize, &mut parents)?;
+            Ok(self.copy_parents_data_repeated(&parents, base_data, Some(exp_data), hasher))
         }
     }
 
@@ -173,15 +252,42 @@ where
         if let Some(ref mut cache) = cache {
             let cache_parents = cache.read(node)?;
             Ok(self.copy_parents_data_inner(&cache_parents, base_data, hasher))
-        } else {
+        } else if self.has_production_degrees() {
             let mut cache_parents = [0u32; BASE_DEGREE];
 
             self.base_parents(node as usize, &mut cache_parents[..])
                 .expect("parents failure");
             Ok(self.copy_parents_data_inner(&cache_parents, base_data, hasher))
+        } else {
+            let mut parents = vec![0u32; self.base_graph.degree()];
+            self.base_parents(node as usize, &mut parents)?;
+            Ok(self.copy_parents_data_repeated(&parents, base_data, None, hasher))
         }
     }
 
+    /// Hashes the parents of a graph with custom degrees, repeated up to `TOTAL_PARENTS` like
+    /// the unrolled production versions below do. Expansion parents are read from `exp_data`.
+    fn copy_parents_data_repeated(
+        &self,
+        parents: &[u32],
+        base_data: &[u8],
+        exp_data: Option<&[u8]>,
+        mut hasher: Sha256,
+    ) -> [u8; 32] {
+        let base_degree = self.base_graph.degree();
+        let parents_data: Vec<&[u8]> = (0..parents.len())
+            .map(|i| match exp_data {
+                Some(exp_data) if i >= base_degree => read_node(i, parents, exp_data),
+                _ => read_node(i, parents, base_data),
+            })
+            .cycle()
+            .take(TOTAL_PARENTS)
+            .collect();
+
+        hasher.input(&parents_data[..TOTAL_PARENTS - 1]);
+        hasher.finish_with(parents_data[TOTAL_PARENTS - 1])
+    }
+
     fn copy_parents_data_inner_exp(
         &self,
         cache_parents: &[u32],
@@ -372,6 +478,14 @@ where
             self.feistel_precomputed,
         );
 
+        self.collapse_permuted(transformed)
+    }
+
+    /// Collapse the output in the matrix search space to the row of the corresponding
+    /// node (losing the column information, that will be regenerated later when calling
+    /// back this function in the `reversed` direction).
+    #[inline]
+    fn collapse_permuted(&self, transformed: feistel::Index) -> u32 {
         match self.api_version {
             ApiVersion::V1_0_0 => transformed as u32 / self.expansion_degree as u32,
             ApiVersion::V1_1_0 | ApiVersion::V1_2_0 => {
@@ -379,16 +493,36 @@ where
                     .expect("invalid transformation")
             }
         }
-
-        // Collapse the output in the matrix search space to the row of the corresponding
-        // node (losing the column information, that will be regenerated later when calling
-        // back this function in the `reversed` direction).
     }
 
+    /// Assigns all expansion parents of `node` at once, permuting the whole row of the
+    /// search space with a single batched Feistel evaluation (see `correspondent`).
     pub fn generate_expanded_parents(&self, node: usize, expanded_parents: &mut [u32]) {
         debug_assert_eq!(expanded_parents.len(), self.expansion_degree);
-        for (i, el) in expanded_parents.iter_mut().enumerate() {
-            *el = self.correspondent(node, i);
+        debug_assert!(self.expansion_degree <= EXP_DEGREE);
+
+        let first = (node * self.expansion_degree) as feistel::Index;
+        let mut indexes = [0 as feistel::Index; EXP_DEGREE];
+        let mut permuted = [0 as feistel::Index; EXP_DEGREE];
+        let indexes = &mut indexes[..self.expansion_degree];
+        let permuted = &mut permuted[..self.expansion_degree];
+        for (i, index) in indexes.iter_mut().enumerate() {
+            *index = first + i as feistel::Index;
+        }
+
+        FEISTEL_BUFFERS.with(|buffers| {
+            feistel::permute_batch(
+                self.size() as feistel::Index * self.expansion_degree as feistel::Index,
+                indexes,
+                permuted,
+                &self.feistel_round_keys,
+                self.feistel_precomputed,
+                &mut buffers.borrow_mut(),
+            )
+        });
+
+        for (el, transformed) in expanded_parents.iter_mut().zip(permuted.iter()) {
+            *el = self.collapse_permuted(*transformed);
         }
     }
 
@@ -632,6 +766,34 @@ mod tests {
         panic!();
     }
 
+    // Checks that the batched expansion parents match the per-parent `correspondent` path.
+    #[test]
+    fn test_expanded_parents_batch_matches_correspondent() {
+        const N_NODES: usize = 1 << 10;
+
+        for (porep_id_value, api_version) in [(1u64, ApiVersion::V1_0_0), (5, ApiVersion::V1_2_0)] {
+            let mut porep_id = [0u8; 32];
+            porep_id[..8].copy_from_slice(&porep_id_value.to_le_bytes());
+
+            let graph = StackedBucketGraph::<PoseidonHasher>::new_stacked(
+                N_NODES,
+                BASE_DEGREE,
+                EXP_DEGREE,
+                porep_id,
+                api_version,
+            )
+            .expect("stacked bucket graph new_stacked failed");
+
+            let mut exp_parents = [0u32; EXP_DEGREE];
+            for node in 0..N_NODES {
+                graph.generate_expanded_parents(node, &mut exp_parents);
+                for (i, parent) in exp_parents.iter().enumerate() {
+                    assert_eq!(*parent, graph.correspondent(node, i));
+                }
+            }
+        }
+    }
+
     // Checks that the distribution of parent node indexes within a sector is within a set bound.
     #[test]
     fn test_exp_parent_histogram() {
@@ -690,4 +852,58 @@ mod tests {
 
         assert!(success);
     }
+
+    #[test]
+    fn test_custom_degrees() {
+        let nodes = 1 << 10;
+        let porep_id = [7u8; 32];
+
+        let graph = StackedBucketGraph::<PoseidonHasher>::new_stacked(
+            nodes,
+            3,
+            2,
+            porep_id,
+            ApiVersion::V1_2_0,
+        )
+        .expect("stacked bucket graph new_stacked failed");
+        assert_eq!(graph.degree(), 5);
+
+        let mut parents = [0u32; 5];
+        for node in 2..nodes {
+            graph.parents(node, &mut parents).expect("parents failed");
+            // The base parents precede the node, the expansion parents are in the previous layer.
+            assert!(parents[..3].iter().all(|parent| (*parent as usize) < node));
+            assert!(parents[3..].iter().all(|parent| (*parent as usize) < nodes));
+        }
+
+        // Only production configurations are restricted to the production degrees, the parent
+        // cache is laid out for them in any case.
+        let (base_degree, expansion_degree) =
+            (graph.base_graph().degree(), graph.expansion_degree());
+        assert!(!graph.has_production_degrees());
+        assert!(
+            check_degrees(&porep_id, ApiVersion::V1_2_0, base_degree, expansion_degree).is_ok()
+        );
+        let mut production_id = [0u8; 32];
+        production_id[0] = 8;
+        assert!(is_production_porep_id(&production_id));
+        for api_version in [ApiVersion::V1_0_0, ApiVersion::V1_1_0, ApiVersion::V1_2_0] {
+            assert!(
+                check_degrees(&production_id, api_version, base_degree, expansion_degree).is_err()
+            );
+            assert!(check_degrees(&production_id, api_version, BASE_DEGREE, EXP_DEGREE).is_ok());
+        }
+        assert!(graph.parent_cache().is_err());
+
+        for (base_degree, expansion_degree) in [(1, EXP_DEGREE), (BASE_DEGREE, 0), (2, 9)] {
+            assert!(StackedBucketGraph::<PoseidonHasher>::new_stacked(
+                nodes,
+                base_degree,
+                expansion_degree,
+                porep_id,
+                ApiVersion::V1_2_0,
+            )
+            .is_err());
+        }
+    }
 }
@@ -1,18 +1,22 @@
 use blstrs::Scalar as Fr;
-use filecoin_hashers::{POSEIDON_CONSTANTS_11, POSEIDON_CONSTANTS_2};
+use filecoin_hashers::PoseidonArity;
+use generic_array::typenum::{U11, U2, U4, U8};
 use neptune::poseidon::Poseidon;
 
-/// Hash all elements in the given column.
+/// Hash all elements in the given column. The column has an element per layer, columns of 2, 4, 8
+/// and 11 layers are supported.
 pub fn hash_single_column(column: &[Fr]) -> Fr {
     match column.len() {
-        2 => {
-            let mut hasher = Poseidon::new_with_preimage(column, &*POSEIDON_CONSTANTS_2);
-            hasher.hash()
-        }
-        11 => {
-            let mut hasher = Poseidon::new_with_preimage(column, &*POSEIDON_CONSTANTS_11);
-            hasher.hash()
-        }
+        2 => hash_column::<U2>(column),
+        4 => hash_column::<U4>(column),
+        8 => hash_column::<U8>(column),
+        11 => hash_column::<U11>(column),
         _ => panic!("unsupported column size: {}", column.len()),
     }
 }
+
+/// Hash a column of `ColumnArity` elements.
+pub fn hash_column<ColumnArity: PoseidonArity>(column: &[Fr]) -> Fr {
+    let mut hasher = Poseidon::new_with_preimage(column, ColumnArity::PARAMETERS());
+    hasher.hash()
+}
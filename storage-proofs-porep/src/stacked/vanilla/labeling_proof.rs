@@ -25,7 +25,7 @@ impl<H: Hasher> LabelingProof<H> {
         }
     }
 
-    fn create_label(&self, replica_id: &H::Domain) -> H::Domain {
+    pub(crate) fn create_label(&self, replica_id: &H::Domain) -> H::Domain {
         let mut hasher = Sha256::new();
         let mut buffer = [0u8; 64];
 
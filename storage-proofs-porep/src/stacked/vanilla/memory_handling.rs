@@ -9,8 +9,9 @@ use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 
 use anyhow::Result;
 use byte_slice_cast::{AsSliceOf, FromByteSlice};
-use log::{info, trace};
+use log::{info, trace, warn};
 use memmap2::{Mmap, MmapMut, MmapOptions};
+use storage_proofs_core::settings::SETTINGS;
 
 pub struct CacheReader<T> {
     file: File,
@@ -188,13 +189,16 @@ impl<T: FromByteSlice> CacheReader<T> {
     }
 
     fn map_buf(offset: u64, len: usize, file: &File) -> Result<Mmap> {
-        unsafe {
+        let buf = unsafe {
             MmapOptions::new()
                 .offset(offset)
                 .len(len)
-                .map_copy_read_only(file)
-                .map_err(|e| e.into())
+                .map_copy_read_only(file)?
+        };
+        if SETTINGS.sdr_hugepage_size > 0 {
+            advise_hugepages(&buf);
         }
+        Ok(buf)
     }
 
     #[inline]
@@ -274,7 +278,74 @@ impl<T: FromByteSlice> CacheReader<T> {
     }
 }
 
+/// Advises the kernel to back `buf` with transparent hugepages. File backed mappings can only be
+/// hugepage backed by the kernel if it supports it for read-only file mappings, hence this is
+/// best effort.
+#[cfg(target_os = "linux")]
+fn advise_hugepages(buf: &Mmap) {
+    let res = unsafe {
+        libc::madvise(
+            buf.as_ptr() as *mut libc::c_void,
+            buf.len(),
+            libc::MADV_HUGEPAGE,
+        )
+    };
+    if res != 0 {
+        trace!(
+            "failed to advise hugepages: {:?}",
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn advise_hugepages(_buf: &Mmap) {}
+
+/// Allocates a layer from explicit hugepages of `hugepage_size` bytes. Returns `None` if that
+/// isn't possible, e.g. because not enough hugepages are reserved.
+#[cfg(target_os = "linux")]
+fn allocate_layer_hugepages(sector_size: usize, hugepage_size: usize) -> Option<MmapMut> {
+    if !hugepage_size.is_power_of_two() || sector_size % hugepage_size != 0 {
+        warn!(
+            "hugepage size {} is invalid for sector size {}, using regular pages",
+            hugepage_size, sector_size
+        );
+        return None;
+    }
+    let page_bits = hugepage_size.trailing_zeros() as u8;
+    match MmapOptions::new()
+        .len(sector_size)
+        .huge(Some(page_bits))
+        .map_anon()
+    {
+        Ok(layer) => {
+            info!("allocated layer from {} byte hugepages", hugepage_size);
+            Some(layer)
+        }
+        Err(err) => {
+            warn!(
+                "failed to allocate layer from {} byte hugepages, using regular pages: {:?}",
+                hugepage_size, err
+            );
+            None
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn allocate_layer_hugepages(_sector_size: usize, _hugepage_size: usize) -> Option<MmapMut> {
+    warn!("hugepages are only supported on Linux, using regular pages");
+    None
+}
+
 fn allocate_layer(sector_size: usize) -> Result<MmapMut> {
+    if SETTINGS.sdr_hugepage_size > 0 {
+        // Hugepages are never swapped, hence there is no need to lock them.
+        if let Some(layer) = allocate_layer_hugepages(sector_size, SETTINGS.sdr_hugepage_size) {
+            return Ok(layer);
+        }
+    }
+
     match MmapOptions::new()
         .len(sector_size)
         .map_anon()
@@ -2,9 +2,15 @@
 mod macros;
 
 pub mod create_label;
+#[cfg(all(
+    feature = "gpu-differential-testing",
+    any(feature = "cuda", feature = "opencl")
+))]
+pub mod differential;
 pub(crate) mod hash;
 
 mod cache;
+mod challenge_throughput;
 mod challenges;
 mod clear_files;
 mod column;
@@ -19,18 +25,33 @@ mod memory_handling;
 mod params;
 mod proof;
 mod proof_scheme;
+mod thread_pool;
+mod tree_d_opener;
 #[cfg(feature = "multicore-sdr")]
 mod utils;
 
+pub use cache::{
+    read_parent_cache_manifest, shared_parent_cache_stats, write_parent_cache_manifest,
+    ParentCache, ParentCacheData, ParentCacheDataMap, SharedParentCache, SharedParentCacheStats,
+    PARENT_CACHE_MANIFEST,
+};
+pub use challenge_throughput::{
+    derive_challenges, measure_challenge_derivation, ChallengeDerivation, ChallengeThroughput,
+};
 pub use challenges::{
-    synthetic::SYNTHETIC_POREP_VANILLA_PROOFS_EXT, synthetic::SYNTHETIC_POREP_VANILLA_PROOFS_KEY,
-    ChallengeRequirements, LayerChallenges, SynthChallenges,
+    synthetic::DEFAULT_SYNTH_CHALLENGE_COUNT, synthetic::SYNTHETIC_POREP_VANILLA_PROOFS_EXT,
+    synthetic::SYNTHETIC_POREP_VANILLA_PROOFS_KEY, verify_challenge_derivation,
+    ChallengeProvenance, ChallengeRequirements, LayerChallenges, SynthChallenges,
 };
-pub use clear_files::{clear_cache_dir, clear_synthetic_proofs};
+pub use clear_files::{clear_cache_dir, clear_synthetic_proofs, clear_tree_c};
 pub use column::Column;
 pub use column_proof::ColumnProof;
 pub use encoding_proof::EncodingProof;
-pub use graph::{StackedBucketGraph, StackedGraph, EXP_DEGREE};
+pub use graph::{
+    check_degrees, is_production_porep_id, StackedBucketGraph, StackedGraph, EXP_DEGREE,
+};
 pub use labeling_proof::LabelingProof;
 pub use params::*;
 pub use proof::{StackedDrg, TreeRElementData, TOTAL_PARENTS};
+pub use thread_pool::{install_thread_pool, PorepThreadPool};
+pub use tree_d_opener::TreeDOpener;
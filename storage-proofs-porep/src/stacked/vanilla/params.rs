@@ -1,18 +1,22 @@
+use std::fs::File;
 use std::io::{self, Read, Seek, SeekFrom, Write};
 use std::iter;
 use std::marker::PhantomData;
 use std::mem;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 
-use anyhow::Context;
-use filecoin_hashers::{Domain, Hasher};
+use anyhow::{bail, ensure, Context};
+use blstrs::Scalar as Fr;
+use filecoin_hashers::{Domain, HashFunction, Hasher};
 use fr32::bytes_into_fr_repr_safe;
 use generic_array::typenum::{Unsigned, U2};
-use log::trace;
+use log::{info, trace};
 use merkletree::{
     merkle::get_merkle_tree_leafs,
-    store::{DiskStore, Store, StoreConfig},
+    store::{DiskStore, ExternalReader, LevelCacheStore, Store, StoreConfig},
 };
+use rand::{seq::index, thread_rng};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use storage_proofs_core::{
@@ -20,9 +24,9 @@ use storage_proofs_core::{
     drgraph::{Graph, BASE_DEGREE},
     error::Result,
     merkle::{
-        create_disk_tree, create_lc_tree, get_base_tree_count, split_config,
-        split_config_and_replica, BinaryMerkleTree, DiskTree, LCTree, MerkleProof,
-        MerkleProofTrait, MerkleTreeTrait,
+        create_disk_tree, create_lc_tree, create_tree_from_stores, get_base_tree_count,
+        get_base_tree_leafs, split_config, split_config_and_replica, BinaryMerkleTree, DiskTree,
+        LCTree, MerkleProof, MerkleProofTrait, MerkleTreeTrait, MerkleTreeWrapper,
     },
     parameter_cache::ParameterSetMetadata,
     util::{data_at_node, NODE_SIZE},
@@ -30,14 +34,33 @@ use storage_proofs_core::{
 
 use crate::stacked::vanilla::{
     Column, ColumnProof, EncodingProof, LabelingProof, LayerChallenges, StackedBucketGraph,
-    EXP_DEGREE, SYNTHETIC_POREP_VANILLA_PROOFS_EXT, SYNTHETIC_POREP_VANILLA_PROOFS_KEY,
-    TOTAL_PARENTS,
+    SynthChallenges, TreeDOpener, EXP_DEGREE, SYNTHETIC_POREP_VANILLA_PROOFS_EXT,
+    SYNTHETIC_POREP_VANILLA_PROOFS_KEY, TOTAL_PARENTS,
 };
 
 pub const BINARY_ARITY: usize = 2;
 pub const QUAD_ARITY: usize = 4;
 pub const OCT_ARITY: usize = 8;
 
+/// Arity of the data tree (`tree_d`) that is used by default.
+pub const TREE_D_ARITY: usize = BINARY_ARITY;
+
+/// Checks that the vanilla proofs support a `tree_d` of arity `arity` built with hasher `G`, see
+/// `SetupParams::tree_d_arity`.
+///
+/// The `comm_d` inclusion proofs (vanilla and synthetic) are currently only implemented for
+/// binary data trees, regardless of the hasher.
+pub fn check_tree_d_arity<G: Hasher>(arity: usize) -> Result<()> {
+    ensure!(
+        arity == TREE_D_ARITY,
+        "unsupported tree_d arity {} with hasher {} (supported arities: [{}])",
+        arity,
+        G::name(),
+        TREE_D_ARITY,
+    );
+    Ok(())
+}
+
 #[derive(Debug, Clone)]
 pub struct SetupParams {
     // Number of nodes
@@ -52,6 +75,10 @@ pub struct SetupParams {
     pub layer_challenges: LayerChallenges,
     pub api_version: ApiVersion,
     pub api_features: Vec<ApiFeature>,
+
+    // Arity of tree_d, an experimental knob that is validated against the supported arities of
+    // the vanilla proofs (`check_tree_d_arity`) and, for the compound proof, of the circuit.
+    pub tree_d_arity: usize,
 }
 
 #[derive(Debug)]
@@ -61,6 +88,7 @@ where
 {
     pub graph: StackedBucketGraph<Tree::Hasher>,
     pub layer_challenges: LayerChallenges,
+    pub tree_d_arity: usize,
     _t: PhantomData<Tree>,
 }
 
@@ -72,6 +100,7 @@ where
         Self {
             graph: self.graph.clone(),
             layer_challenges: self.layer_challenges.clone(),
+            tree_d_arity: self.tree_d_arity,
             _t: Default::default(),
         }
     }
@@ -85,9 +114,16 @@ where
         PublicParams {
             graph,
             layer_challenges,
+            tree_d_arity: TREE_D_ARITY,
             _t: PhantomData,
         }
     }
+
+    /// Uses a `tree_d` of arity `tree_d_arity` instead of [`TREE_D_ARITY`].
+    pub fn with_tree_d_arity(mut self, tree_d_arity: usize) -> Self {
+        self.tree_d_arity = tree_d_arity;
+        self
+    }
 }
 
 impl<Tree> ParameterSetMetadata for PublicParams<Tree>
@@ -114,6 +150,7 @@ where
 {
     fn from(other: &PublicParams<Tree>) -> PublicParams<Tree> {
         PublicParams::new(other.graph.clone(), other.layer_challenges.clone())
+            .with_tree_d_arity(other.tree_d_arity)
     }
 }
 
@@ -447,7 +484,7 @@ impl<Proof: MerkleProofTrait> ReplicaColumnProof<Proof> {
 ///         4.6.1) Parent's column (32 bytes per layer)
 ///         4.6.2) Parent's proof_c (32 bytes for leaf_c and 32 bytes per path_c sibling)
 ///     4.7) Challenge's proof_r (32 bytes for leaf_r and 32 bytes per path_r sibling)
-pub(crate) struct SynthProofs;
+pub struct SynthProofs;
 
 impl SynthProofs {
     /// Serializes and writes synthetic proofs `proofs` into `writer`.
@@ -748,6 +785,84 @@ impl SynthProofs {
             .collect()
     }
 
+    /// Spot-checks `num_samples` randomly selected synthetic proofs read from `reader` against
+    /// the synthetic challenges and `comm_r` of `pub_inputs`.
+    ///
+    /// This is intended to be called after the synthetic proofs have been written and before the
+    /// interactive porep challenge seed is available, so that a corrupt synthetic proofs file is
+    /// detected while it can still be regenerated.
+    pub fn validate<Tree, G, R>(
+        mut reader: R,
+        pub_params: &PublicParams<Tree>,
+        pub_inputs: &PublicInputs<<Tree::Hasher as Hasher>::Domain, <G as Hasher>::Domain>,
+        num_samples: usize,
+    ) -> Result<()>
+    where
+        Tree: 'static + MerkleTreeTrait,
+        G: 'static + Hasher,
+        R: Read + Seek,
+    {
+        ensure!(
+            pub_params.layer_challenges.use_synthetic,
+            "synth-porep must be enabled to validate synthetic proofs",
+        );
+        let tau = pub_inputs
+            .tau
+            .as_ref()
+            .context("comm_r must be set prior to validating synthetic proofs")?;
+
+        let graph = &pub_params.graph;
+        let sector_nodes = graph.size();
+        let num_layers = pub_params.layer_challenges.layers();
+
+        let replica_id: Fr = pub_inputs.replica_id.into();
+        let comm_r: Fr = tau.comm_r.into();
+        let mut synth_challenges = SynthChallenges::default(sector_nodes, &replica_id, &comm_r)
+            .with_domain(*pub_params.layer_challenges.domain());
+        let num_synth_challenges = synth_challenges.num_synth_challenges;
+
+        let file_len = reader.seek(SeekFrom::End(0))? as usize;
+        let expected_len = 3 * NODE_SIZE
+            + num_synth_challenges * Self::proof_size::<Tree>(sector_nodes, num_layers);
+        ensure!(
+            file_len == expected_len,
+            "synthetic proofs file has invalid size {} (expected {})",
+            file_len,
+            expected_len,
+        );
+
+        let mut selected = index::sample(
+            &mut thread_rng(),
+            num_synth_challenges,
+            num_samples.min(num_synth_challenges),
+        )
+        .into_vec();
+        selected.sort_unstable();
+
+        let proofs =
+            Self::read::<Tree, G, R>(reader, sector_nodes, num_layers, selected.iter().copied())?;
+
+        for (synth_index, proof) in selected.into_iter().zip(proofs.iter()) {
+            let actual_comm_r =
+                <Tree::Hasher as Hasher>::Function::hash2(&proof.comm_c(), &proof.comm_r_last());
+            ensure!(
+                actual_comm_r == tau.comm_r,
+                "synthetic proof {} does not match comm_c/comm_r_last of comm_r",
+                synth_index,
+            );
+
+            let challenge = synth_challenges.gen_synth_challenge(synth_index);
+            ensure!(
+                proof.verify(pub_params, pub_inputs, challenge, graph),
+                "synthetic proof {} (challenge {}) failed verification",
+                synth_index,
+                challenge,
+            );
+        }
+
+        Ok(())
+    }
+
     /// Returns the size of a single challenge's serialized synthetic proof.
     pub fn proof_size<Tree: MerkleTreeTrait>(sector_nodes: usize, num_layers: usize) -> usize {
         // The number of node indices associated with each challenge proof: one node index for the
@@ -788,6 +903,102 @@ impl SynthProofs {
     }
 }
 
+/// Random access to the synthetic proofs stored in a synthetic vanilla proofs file.
+///
+/// The file's size is validated against the per-proof size implied by the sector size and
+/// number of layers when it is opened, so that callers can address proofs by index rather than
+/// by raw byte offset.
+#[derive(Debug)]
+pub struct SynthProofsFile<Tree: MerkleTreeTrait, G: Hasher> {
+    path: PathBuf,
+    file: File,
+    sector_nodes: usize,
+    num_layers: usize,
+    num_proofs: usize,
+    _tree: PhantomData<Tree>,
+    _g: PhantomData<G>,
+}
+
+impl<Tree: MerkleTreeTrait, G: Hasher> SynthProofsFile<Tree, G> {
+    /// Opens the synthetic proofs file at `path` written for a sector of `sector_nodes` nodes
+    /// replicated with `num_layers` layers.
+    pub fn open<P: AsRef<Path>>(path: P, sector_nodes: usize, num_layers: usize) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = File::open(&path)
+            .with_context(|| format!("failed to open synthetic vanilla proofs file: {:?}", path))?;
+        let file_len = file
+            .metadata()
+            .with_context(|| format!("failed to stat synthetic vanilla proofs file: {:?}", path))?
+            .len() as usize;
+
+        let roots_size = 3 * NODE_SIZE;
+        let proof_size = SynthProofs::proof_size::<Tree>(sector_nodes, num_layers);
+        ensure!(
+            file_len >= roots_size && (file_len - roots_size) % proof_size == 0,
+            "synthetic vanilla proofs file {:?} has invalid size {} (proof size {})",
+            path,
+            file_len,
+            proof_size,
+        );
+
+        Ok(SynthProofsFile {
+            path,
+            file,
+            sector_nodes,
+            num_layers,
+            num_proofs: (file_len - roots_size) / proof_size,
+            _tree: PhantomData,
+            _g: PhantomData,
+        })
+    }
+
+    /// Returns the number of synthetic proofs in the file.
+    pub fn len(&self) -> usize {
+        self.num_proofs
+    }
+
+    /// Returns `true` if the file contains no synthetic proofs.
+    pub fn is_empty(&self) -> bool {
+        self.num_proofs == 0
+    }
+
+    /// Returns the serialized size of a single synthetic proof in the file.
+    pub fn proof_size(&self) -> usize {
+        SynthProofs::proof_size::<Tree>(self.sector_nodes, self.num_layers)
+    }
+
+    /// Reads the synthetic proof at `index`.
+    pub fn get(&self, index: usize) -> Result<Proof<Tree, G>> {
+        ensure!(
+            index < self.num_proofs,
+            "synthetic proof index {} out of range (file contains {} proofs)",
+            index,
+            self.num_proofs,
+        );
+        let mut proofs = self.read(iter::once(index))?;
+        Ok(proofs.remove(0))
+    }
+
+    /// Returns an iterator over the synthetic proofs whose indexes are in `range`.
+    pub fn iter_range(
+        &self,
+        range: Range<usize>,
+    ) -> Result<impl Iterator<Item = Result<Proof<Tree, G>>> + '_> {
+        ensure!(
+            range.start <= range.end && range.end <= self.num_proofs,
+            "synthetic proof range {:?} out of range (file contains {} proofs)",
+            range,
+            self.num_proofs,
+        );
+        Ok(range.map(move |index| self.get(index)))
+    }
+
+    fn read(&self, indexes: impl Iterator<Item = usize>) -> Result<Vec<Proof<Tree, G>>> {
+        SynthProofs::read(&self.file, self.sector_nodes, self.num_layers, indexes)
+            .with_context(|| format!("failed to read synthetic proofs from file: {:?}", self.path))
+    }
+}
+
 pub type TransformedLayers<Tree, G> = (
     Tau<<<Tree as MerkleTreeTrait>::Hasher as Hasher>::Domain, <G as Hasher>::Domain>,
     PersistentAux<<<Tree as MerkleTreeTrait>::Hasher as Hasher>::Domain>,
@@ -852,7 +1063,7 @@ impl<Tree: MerkleTreeTrait, G: Hasher> TemporaryAux<Tree, G> {
             })
             .collect();
 
-        let tree_d_size = get_merkle_tree_len(sector_nodes, BINARY_ARITY)
+        let tree_d_size = get_merkle_tree_len(sector_nodes, TREE_D_ARITY)
             .expect("Tree must have enough leaves and have an arity of power of two");
         let tree_d_config = StoreConfig {
             path: cache_path.clone(),
@@ -926,14 +1137,29 @@ impl<Tree: MerkleTreeTrait, G: Hasher> TemporaryAux<Tree, G> {
     }
 }
 
+/// `tree_r_last` of a replica whose base layer is read through `R`.
+pub type ReplicaTree<Tree, R> = MerkleTreeWrapper<
+    <Tree as MerkleTreeTrait>::Hasher,
+    LevelCacheStore<<<Tree as MerkleTreeTrait>::Hasher as Hasher>::Domain, R>,
+    <Tree as MerkleTreeTrait>::Arity,
+    <Tree as MerkleTreeTrait>::SubTreeArity,
+    <Tree as MerkleTreeTrait>::TopTreeArity,
+>;
+
+/// The trees and labels of a replica, opened for proving.
+///
+/// `R` is the reader of the replica, i.e. the base layer of `tree_r_last`. It is the replica file
+/// unless the cache was built with `from_stores`.
 #[derive(Debug)]
-pub struct TemporaryAuxCache<Tree: MerkleTreeTrait, G: Hasher> {
+pub struct TemporaryAuxCache<Tree: MerkleTreeTrait, G: Hasher, R: Read + Send + Sync = File> {
     /// The encoded nodes for 1..layers.
     pub labels: LabelsCache<Tree>,
     pub tree_d: Option<BinaryMerkleTree<G>>,
+    /// Opens tree_d from the staged data instead, see `set_tree_d_data`.
+    pub tree_d_opener: Option<TreeDOpener<G>>,
 
     // Notably this is a LevelCacheTree instead of a full merkle.
-    pub tree_r_last: LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
+    pub tree_r_last: ReplicaTree<Tree, R>,
 
     // Store the 'rows_to_discard' value from the tree_r_last
     // StoreConfig for later use (i.e. proof generation).
@@ -956,35 +1182,56 @@ impl<Tree: MerkleTreeTrait, G: Hasher> TemporaryAuxCache<Tree, G> {
         let (tree_d, tree_c) = if skip_labels {
             (None, None)
         } else {
-            // tree_d_size stored in the config is the base tree size
-            let tree_d_size = t_aux.tree_d_config.size.expect("config size failure");
-            let tree_d_leafs = get_merkle_tree_leafs(tree_d_size, BINARY_ARITY)?;
-            trace!(
-                "Instantiating tree d with size {} and leafs {}",
-                tree_d_size,
-                tree_d_leafs,
-            );
-            let tree_d_store: DiskStore<G::Domain> =
-                DiskStore::new_from_disk(tree_d_size, BINARY_ARITY, &t_aux.tree_d_config)
-                    .context("tree_d_store")?;
-            let tree_d = BinaryMerkleTree::<G>::from_data_store(tree_d_store, tree_d_leafs)
-                .context("tree_d")?;
+            // tree_d is not built if comm_d was computed from the pieces. In that case the data
+            // is opened from the staged sector, see `set_tree_d_data`.
+            let tree_d_config = &t_aux.tree_d_config;
+            let tree_d = if StoreConfig::data_path(&tree_d_config.path, &tree_d_config.id).exists()
+            {
+                // tree_d_size stored in the config is the base tree size
+                let tree_d_size = tree_d_config.size.expect("config size failure");
+                let tree_d_leafs = get_merkle_tree_leafs(tree_d_size, TREE_D_ARITY)?;
+                trace!(
+                    "Instantiating tree d with size {} and leafs {}",
+                    tree_d_size,
+                    tree_d_leafs,
+                );
+                let tree_d_store: DiskStore<G::Domain> =
+                    DiskStore::new_from_disk(tree_d_size, TREE_D_ARITY, tree_d_config)
+                        .context("tree_d_store")?;
+                Some(
+                    BinaryMerkleTree::<G>::from_data_store(tree_d_store, tree_d_leafs)
+                        .context("tree_d")?,
+                )
+            } else {
+                info!("tree d not found on disk, the staged data must be given for proving");
+                None
+            };
 
             let configs = split_config(t_aux.tree_c_config.clone(), tree_count)?;
 
-            // tree_c_size stored in the config is the base tree size
-            let tree_c_size = t_aux.tree_c_config.size.expect("config size failure");
-            trace!(
-                "Instantiating tree c [count {}] with size {} and arity {}",
-                tree_count,
-                tree_c_size,
-                Tree::Arity::to_usize(),
-            );
-            let tree_c = create_disk_tree::<
-                DiskTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
-            >(tree_c_size, &configs)?;
-
-            (Some(tree_d), Some(tree_c))
+            // tree_c may have been removed after precommit in order to save disk space. In that
+            // case it is regenerated from the labels when proving.
+            let tree_c = if configs
+                .iter()
+                .all(|config| StoreConfig::data_path(&config.path, &config.id).exists())
+            {
+                // tree_c_size stored in the config is the base tree size
+                let tree_c_size = t_aux.tree_c_config.size.expect("config size failure");
+                trace!(
+                    "Instantiating tree c [count {}] with size {} and arity {}",
+                    tree_count,
+                    tree_c_size,
+                    Tree::Arity::to_usize(),
+                );
+                Some(create_disk_tree::<
+                    DiskTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
+                >(tree_c_size, &configs)?)
+            } else {
+                info!("tree c not found on disk, it will be regenerated from the labels");
+                None
+            };
+
+            (tree_d, tree_c)
         };
 
         // tree_r_last_size stored in the config is the base tree size
@@ -1015,6 +1262,7 @@ impl<Tree: MerkleTreeTrait, G: Hasher> TemporaryAuxCache<Tree, G> {
             Ok(TemporaryAuxCache {
                 labels: LabelsCache::new(&Labels::new(Vec::new())).context("labels_cache")?,
                 tree_d: None, //tree_d,
+                tree_d_opener: None,
                 tree_r_last,
                 tree_r_last_config_rows_to_discard,
                 tree_c: None, //tree_c,
@@ -1025,6 +1273,7 @@ impl<Tree: MerkleTreeTrait, G: Hasher> TemporaryAuxCache<Tree, G> {
             Ok(TemporaryAuxCache {
                 labels: LabelsCache::new(&t_aux.labels).context("labels_cache")?,
                 tree_d,
+                tree_d_opener: None,
                 tree_r_last,
                 tree_r_last_config_rows_to_discard,
                 tree_c,
@@ -1033,6 +1282,122 @@ impl<Tree: MerkleTreeTrait, G: Hasher> TemporaryAuxCache<Tree, G> {
             })
         }
     }
+}
+
+impl<Tree: MerkleTreeTrait, G: Hasher, R: Read + Send + Sync> TemporaryAuxCache<Tree, G, R> {
+    /// Creates the cache from stores which were already opened by the caller, instead of
+    /// deriving their location from the `t_aux` configs.
+    ///
+    /// `replica_readers` contain one reader per base tree of `tree_r_last`, they are used to
+    /// read the replica (the base layer of `tree_r_last`), which may live anywhere.
+    /// `replica_path` is only kept for reference.
+    pub fn from_stores(
+        t_aux: &TemporaryAux<Tree, G>,
+        stores: TemporaryAuxStores<Tree, G, R>,
+        replica_readers: Vec<ExternalReader<R>>,
+        replica_path: PathBuf,
+    ) -> Result<Self> {
+        let tree_count = get_base_tree_count::<Tree>();
+        let TemporaryAuxStores {
+            labels,
+            tree_d,
+            tree_c,
+            mut tree_r_last,
+        } = stores;
+
+        ensure!(
+            labels.is_empty() || labels.len() == t_aux.labels.len(),
+            "expected {} label stores, got {}",
+            t_aux.labels.len(),
+            labels.len()
+        );
+        ensure!(
+            tree_r_last.len() == tree_count && replica_readers.len() == tree_count,
+            "expected {} tree_r_last stores and replica readers, got {} and {}",
+            tree_count,
+            tree_r_last.len(),
+            replica_readers.len()
+        );
+
+        let tree_d = match tree_d {
+            Some(store) => {
+                // tree_d_size stored in the config is the base tree size
+                let tree_d_size = t_aux.tree_d_config.size.expect("config size failure");
+                let tree_d_leafs = get_merkle_tree_leafs(tree_d_size, TREE_D_ARITY)?;
+                Some(
+                    BinaryMerkleTree::<G>::from_data_store(store, tree_d_leafs)
+                        .context("tree_d")?,
+                )
+            }
+            None => None,
+        };
+
+        let tree_c = match tree_c {
+            Some(stores) => {
+                ensure!(
+                    stores.len() == tree_count,
+                    "expected {} tree_c stores, got {}",
+                    tree_count,
+                    stores.len()
+                );
+                // tree_c_size stored in the config is the base tree size
+                let tree_c_size = t_aux.tree_c_config.size.expect("config size failure");
+                Some(
+                    create_tree_from_stores(get_base_tree_leafs::<Tree>(tree_c_size)?, stores)
+                        .context("tree_c")?,
+                )
+            }
+            None => None,
+        };
+
+        for (store, reader) in tree_r_last.iter_mut().zip(replica_readers.into_iter()) {
+            store.set_external_reader(reader)?;
+        }
+        // tree_r_last_size stored in the config is the base tree size
+        let tree_r_last_size = t_aux.tree_r_last_config.size.expect("config size failure");
+        let tree_r_last =
+            create_tree_from_stores(get_base_tree_leafs::<Tree>(tree_r_last_size)?, tree_r_last)
+                .context("tree_r_last")?;
+
+        Ok(TemporaryAuxCache {
+            labels: LabelsCache { labels },
+            tree_d,
+            tree_d_opener: None,
+            tree_r_last,
+            tree_r_last_config_rows_to_discard: t_aux.tree_r_last_config.rows_to_discard,
+            tree_c,
+            replica_path,
+            t_aux: t_aux.clone(),
+        })
+    }
+
+    /// Generates the openings of tree_d from the staged (unsealed) sector in `data_path`, so that
+    /// tree_d is not needed for proving. The data is hashed once when this is called.
+    pub fn set_tree_d_data(&mut self, data_path: &Path) -> Result<()> {
+        // tree_d_size stored in the config is the base tree size
+        let tree_d_size = self.t_aux.tree_d_config.size.expect("config size failure");
+        let tree_d_leafs = get_merkle_tree_leafs(tree_d_size, TREE_D_ARITY)?;
+        self.tree_d_opener = Some(TreeDOpener::new(data_path, tree_d_leafs)?);
+        Ok(())
+    }
+
+    /// The root of tree_d, if it can be opened.
+    pub fn tree_d_root(&self) -> Option<G::Domain> {
+        match (&self.tree_d_opener, &self.tree_d) {
+            (Some(opener), _) => Some(opener.root()),
+            (None, Some(tree_d)) => Some(tree_d.root()),
+            (None, None) => None,
+        }
+    }
+
+    /// Opens leaf `challenge` of tree_d, from the staged data if it was set.
+    pub fn tree_d_proof(&self, challenge: usize) -> Result<MerkleProof<G, U2>> {
+        match (&self.tree_d_opener, &self.tree_d) {
+            (Some(opener), _) => opener.gen_proof(challenge),
+            (None, Some(tree_d)) => tree_d.gen_proof(challenge),
+            (None, None) => bail!("tree_d is unavailable and the staged data was not set"),
+        }
+    }
 
     pub fn labels_for_layer(&self, layer: usize) -> &DiskStore<<Tree::Hasher as Hasher>::Domain> {
         self.labels.labels_for_layer(layer)
@@ -1055,6 +1420,20 @@ impl<Tree: MerkleTreeTrait, G: Hasher> TemporaryAuxCache<Tree, G> {
     }
 }
 
+/// Already opened stores for the trees of a replica, see `TemporaryAuxCache::from_stores`.
+///
+/// The labels, `tree_d` and `tree_c` may be left out when they are not needed for proving
+/// (e.g. with SyntheticPoRep). `R` is the reader of the replica, see `TemporaryAuxCache`.
+pub struct TemporaryAuxStores<Tree: MerkleTreeTrait, G: Hasher, R: Read + Send + Sync = File> {
+    /// The label stores for layers 1..layers.
+    pub labels: Vec<DiskStore<<Tree::Hasher as Hasher>::Domain>>,
+    pub tree_d: Option<DiskStore<G::Domain>>,
+    /// One store per base tree.
+    pub tree_c: Option<Vec<DiskStore<<Tree::Hasher as Hasher>::Domain>>>,
+    /// One store per base tree, the external replica reader is set by the cache.
+    pub tree_r_last: Vec<LevelCacheStore<<Tree::Hasher as Hasher>::Domain, R>>,
+}
+
 type VerifyCallback = fn(&StoreConfig, usize, usize) -> Result<()>;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -1257,7 +1636,10 @@ mod tests {
         parameter_cache::ParameterSetMetadata, proof::ProofScheme, util::NODE_SIZE,
     };
 
-    use crate::stacked::{LayerChallenges, SetupParams, StackedDrg, EXP_DEGREE};
+    use crate::stacked::{
+        LayerChallenges, SetupParams, StackedDrg, SynthProofs, SynthProofsFile, EXP_DEGREE,
+        TREE_D_ARITY,
+    };
 
     // The identifier is used for the parameter file filenames. It must not change, as the
     // filenames are fixed for the official parameter files. Hence staticly assert certain
@@ -1273,6 +1655,7 @@ mod tests {
             layer_challenges: LayerChallenges::new(11, 18),
             api_version: ApiVersion::V1_1_0,
             api_features: vec![],
+            tree_d_arity: TREE_D_ARITY,
         };
         let public_params_32gib =
             StackedDrg::<OctTree32Gib, Sha256Hasher>::setup(&setup_params_32gib)
@@ -1288,10 +1671,71 @@ mod tests {
             layer_challenges: LayerChallenges::new(11, 18),
             api_version: ApiVersion::V1_1_0,
             api_features: vec![],
+            tree_d_arity: TREE_D_ARITY,
         };
         let public_params_64gib =
             StackedDrg::<OctTree64Gib, Sha256Hasher>::setup(&setup_params_64gib)
                 .expect("setup failed");
         assert_eq!(public_params_64gib.identifier(), "layered_drgporep::PublicParams{ graph: stacked_graph::StackedGraph{expansion_degree: 8 base_graph: drgraph::BucketGraph{size: 2147483648; degree: 6; hasher: poseidon_hasher} }, challenges: LayerChallenges { layers: 11, max_count: 18 }, tree: merkletree-poseidon_hasher-8-8-2 }");
     }
+
+    #[test]
+    fn test_unsupported_tree_d_arity() {
+        type Tree = DiskTree<PoseidonHasher, U8, U0, U0>;
+        let mut setup_params = SetupParams {
+            nodes: 64,
+            degree: BASE_DEGREE,
+            expansion_degree: EXP_DEGREE,
+            porep_id: [1u8; 32],
+            layer_challenges: LayerChallenges::new(2, 1),
+            api_version: ApiVersion::V1_1_0,
+            api_features: vec![],
+            tree_d_arity: TREE_D_ARITY,
+        };
+        let public_params =
+            StackedDrg::<Tree, Sha256Hasher>::setup(&setup_params).expect("setup failed");
+        assert_eq!(public_params.tree_d_arity, TREE_D_ARITY);
+
+        for arity in [0, 1, 3, 4, 8] {
+            setup_params.tree_d_arity = arity;
+            assert!(StackedDrg::<Tree, Sha256Hasher>::setup(&setup_params).is_err());
+            assert!(StackedDrg::<Tree, PoseidonHasher>::setup(&setup_params).is_err());
+        }
+    }
+
+    #[test]
+    fn test_synth_proofs_file_access() {
+        type Tree = DiskTree<PoseidonHasher, U8, U0, U0>;
+        let sector_nodes = 1 << 6;
+        let num_layers = 2;
+        let proof_size = SynthProofs::proof_size::<Tree>(sector_nodes, num_layers);
+
+        let dir = tempfile::tempdir().expect("tempdir failure");
+        let path = dir.path().join("synth-proofs");
+        std::fs::write(&path, vec![0u8; 3 * NODE_SIZE + 3 * proof_size]).expect("write failure");
+
+        let file = SynthProofsFile::<Tree, Sha256Hasher>::open(&path, sector_nodes, num_layers)
+            .expect("open failure");
+        assert_eq!(file.len(), 3);
+        assert_eq!(file.proof_size(), proof_size);
+
+        let proof = file.get(2).expect("get failure");
+        assert_eq!(proof.replica_column_proofs.drg_parents.len(), BASE_DEGREE);
+        assert_eq!(proof.replica_column_proofs.exp_parents.len(), EXP_DEGREE);
+        assert!(file.get(3).is_err());
+
+        let proofs = file
+            .iter_range(1..3)
+            .expect("iter_range failure")
+            .collect::<anyhow::Result<Vec<_>>>()
+            .expect("read failure");
+        assert_eq!(proofs.len(), 2);
+        assert!(file.iter_range(2..4).is_err());
+
+        // A truncated file is rejected on open.
+        std::fs::write(&path, vec![0u8; 3 * NODE_SIZE + proof_size - 1]).expect("write failure");
+        assert!(
+            SynthProofsFile::<Tree, Sha256Hasher>::open(&path, sector_nodes, num_layers).is_err()
+        );
+    }
 }
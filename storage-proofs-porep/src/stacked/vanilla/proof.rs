@@ -1,18 +1,23 @@
 use std::any::TypeId;
 use std::fs::{self, File};
-use std::io::{BufReader, BufWriter, Write};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::iter;
 use std::marker::PhantomData;
+use std::mem;
 use std::panic::panic_any;
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, Mutex, RwLock};
+use std::sync::{
+    mpsc::{sync_channel, SyncSender},
+    Arc, Mutex, RwLock,
+};
 
-use anyhow::{ensure, Context};
+use anyhow::{anyhow, bail, ensure, Context};
 use bincode::deserialize;
 use blstrs::Scalar as Fr;
 use fdlimit::raise_fd_limit;
 use ff::PrimeField;
 use filecoin_hashers::{poseidon::PoseidonHasher, Domain, HashFunction, Hasher, PoseidonArity};
-use generic_array::typenum::{Unsigned, U0, U11, U2, U8};
+use generic_array::typenum::{Unsigned, U0, U11, U2, U4, U8};
 use lazy_static::lazy_static;
 use log::{error, info, trace, warn};
 use merkletree::{
@@ -20,7 +25,8 @@ use merkletree::{
     store::{DiskStore, Store, StoreConfig},
 };
 use rayon::prelude::{
-    IndexedParallelIterator, IntoParallelIterator, ParallelIterator, ParallelSliceMut,
+    IndexedParallelIterator, IntoParallelIterator, ParallelIterator, ParallelSlice,
+    ParallelSliceMut,
 };
 use storage_proofs_core::{
     cache_key::CacheKey,
@@ -36,35 +42,52 @@ use storage_proofs_core::{
     settings::SETTINGS,
     util::{default_rows_to_discard, NODE_SIZE},
 };
-use yastl::Pool;
+use tracing::info_span;
 
 use crate::{
-    encode::{decode, encode, encode_fr},
-    stacked::vanilla::{
-        challenges::LayerChallenges,
-        column::Column,
-        create_label,
-        graph::StackedBucketGraph,
-        hash::hash_single_column,
-        params::{
-            get_node, Labels, LabelsCache, PersistentAux, Proof, PublicInputs, PublicParams,
-            ReplicaColumnProof, SynthProofs, Tau, TemporaryAux, TemporaryAuxCache,
-            TransformedLayers, BINARY_ARITY,
+    encode::{encode, encode_fr},
+    stacked::{
+        encode_decode::decode_slice,
+        vanilla::{
+            challenges::LayerChallenges,
+            clear_files::clear_tree_c,
+            column::Column,
+            create_label::{self, LabeledRange},
+            graph::StackedBucketGraph,
+            hash::{hash_column, hash_single_column},
+            params::{
+                get_node, Labels, LabelsCache, PersistentAux, Proof, PublicInputs, PublicParams,
+                ReplicaColumnProof, SynthProofs, Tau, TemporaryAux, TemporaryAuxCache,
+                TransformedLayers, TREE_D_ARITY,
+            },
+            thread_pool::current_thread_pool,
+            EncodingProof, LabelingProof,
         },
-        EncodingProof, LabelingProof,
     },
 };
 
 pub const TOTAL_PARENTS: usize = 37;
 
+/// Number of nodes (64 MiB) read from the key layer per window when unsealing.
+const DECODE_WINDOW_NODES: usize = (1 << 26) / NODE_SIZE;
+
 lazy_static! {
     /// Ensure that only one `TreeBuilder` or `ColumnTreeBuilder` uses the GPU at a time.
     /// Curently, this is accomplished by only instantiating at most one at a time.
     /// It might be possible to relax this constraint, but in that case, only one builder
     /// should actually be active at any given time, so the mutex should still be used.
     static ref GPU_LOCK: Mutex<()> = Mutex::new(());
+}
 
-    static ref THREAD_POOL: Pool = Pool::new(num_cpus::get());
+/// Removes a tree_c that was regenerated for proving when dropped, also if proving fails.
+struct RegeneratedTreeCGuard<'a>(&'a Path);
+
+impl Drop for RegeneratedTreeCGuard<'_> {
+    fn drop(&mut self) {
+        if let Err(err) = clear_tree_c(self.0) {
+            warn!("failed to remove the regenerated tree_c: {:?}", err);
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -79,6 +102,142 @@ pub struct LayerState {
     pub generated: bool,
 }
 
+/// Builds tree_c on the CPU from the labels of the last layer, range by range, while they are
+/// generated. The other layers of a range are read from disk, as they are stored before the last
+/// layer is labeled.
+struct StreamedTreeC<Tree: MerkleTreeTrait> {
+    layers: usize,
+    labels: Labels<Tree>,
+    /// The labels of all layers but the last, opened once the first range is hashed.
+    stores: Vec<DiskStore<<Tree::Hasher as Hasher>::Domain>>,
+    configs: Vec<StoreConfig>,
+    nodes_count: usize,
+    /// The column hashes of the base tree that is built next.
+    hashes: Vec<<Tree::Hasher as Hasher>::Domain>,
+    trees_built: usize,
+}
+
+impl<Tree: 'static + MerkleTreeTrait> StreamedTreeC<Tree> {
+    fn new(
+        graph: &StackedBucketGraph<Tree::Hasher>,
+        layers: usize,
+        cache_path: &Path,
+    ) -> Result<Self> {
+        let tree_count = get_base_tree_count::<Tree>();
+        let nodes_count = graph.size() / tree_count;
+        let tree_c_config = StoreConfig {
+            path: cache_path.to_path_buf(),
+            id: CacheKey::CommCTree.to_string(),
+            size: Some(get_merkle_tree_len(nodes_count, Tree::Arity::to_usize())?),
+            rows_to_discard: 0,
+        };
+
+        Ok(StreamedTreeC {
+            layers,
+            labels: Labels::new(
+                (1..=layers)
+                    .map(|layer| create_label::layer_config(cache_path, layer, graph.size()))
+                    .collect(),
+            ),
+            stores: Vec::new(),
+            configs: split_config(tree_c_config, tree_count)?,
+            nodes_count,
+            hashes: Vec::with_capacity(nodes_count),
+            trees_built: 0,
+        })
+    }
+
+    /// The first node whose column is not hashed yet.
+    fn next_node(&self) -> usize {
+        self.trees_built * self.nodes_count + self.hashes.len()
+    }
+
+    /// Hashes the columns of the nodes starting at `start`, `labels` are their labels of the last
+    /// layer. The ranges must be hashed in order.
+    fn hash_range(&mut self, start: usize, labels: &[u8]) -> Result<()> {
+        ensure!(
+            start == self.next_node(),
+            "labels of node {} streamed out of order",
+            start
+        );
+        if self.stores.is_empty() {
+            self.stores = (1..self.layers)
+                .map(|layer| self.labels.labels_for_layer(layer))
+                .collect::<Result<Vec<_>>>()?;
+        }
+
+        let end = start + labels.len() / NODE_SIZE;
+        let rows = self
+            .stores
+            .iter()
+            .map(|store| {
+                let mut row = vec![0u8; labels.len()];
+                store.read_range_into(start, end, &mut row)?;
+                Ok(row)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let range_hashes = (0..end - start)
+            .into_par_iter()
+            .map(|node| {
+                let column = rows
+                    .iter()
+                    .map(Vec::as_slice)
+                    .chain(iter::once(labels))
+                    .map(|row| get_node::<Tree::Hasher>(row, node).map(Into::into))
+                    .collect::<Result<Vec<Fr>>>()?;
+                Ok(hash_single_column(&column).into())
+            })
+            .collect::<Result<Vec<<Tree::Hasher as Hasher>::Domain>>>()?;
+        self.hashes.extend(range_hashes);
+
+        while self.hashes.len() >= self.nodes_count {
+            let rest = self.hashes.split_off(self.nodes_count);
+            let base_hashes = mem::replace(&mut self.hashes, rest);
+            info!(
+                "building base tree_c {}/{}",
+                self.trees_built + 1,
+                self.configs.len()
+            );
+            DiskTree::<Tree::Hasher, Tree::Arity, U0, U0>::from_par_iter_with_config(
+                base_hashes.into_par_iter(),
+                self.configs[self.trees_built].clone(),
+            )?;
+            self.trees_built += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Hashes the ranges that were not streamed from the stored last layer, and returns the root
+    /// of tree_c. Must only be called once all layers are stored.
+    fn finish(mut self) -> Result<<Tree::Hasher as Hasher>::Domain> {
+        let total_nodes = self.nodes_count * self.configs.len();
+        if self.next_node() < total_nodes {
+            info!(
+                "reading the labels of nodes {}..{} back to build tree_c",
+                self.next_node(),
+                total_nodes
+            );
+            let last_layer = self.labels.labels_for_last_layer()?;
+            while self.next_node() < total_nodes {
+                let start = self.next_node();
+                let end = usize::min(start + create_label::LABELS_STREAM_NODES, total_nodes);
+                let mut labels = vec![0u8; (end - start) * NODE_SIZE];
+                last_layer.read_range_into(start, end, &mut labels)?;
+                self.hash_range(start, &labels)?;
+            }
+        }
+
+        let tree_c = create_disk_tree::<
+            DiskTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
+        >(
+            self.configs[0].size.expect("config size failure"),
+            &self.configs,
+        )?;
+        Ok(tree_c.root())
+    }
+}
+
 pub enum TreeRElementData<Tree: MerkleTreeTrait> {
     FrList(Vec<Fr>),
     ElementList(Vec<<Tree::Hasher as Hasher>::Domain>),
@@ -94,12 +253,34 @@ pub type PrepareTreeRDataCallback<Tree: 'static + MerkleTreeTrait> =
     ) -> Result<TreeRElementData<Tree>>;
 
 impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tree, G> {
+    /// Same as `ProofScheme::prove_all_partitions`, but proves from a cache whose replica is read
+    /// through `R`, e.g. one created with `TemporaryAuxCache::from_stores`.
+    pub fn prove_all_partitions_with_cache<R: Read + Send + Sync>(
+        pub_params: &PublicParams<Tree>,
+        pub_inputs: &PublicInputs<<Tree::Hasher as Hasher>::Domain, <G as Hasher>::Domain>,
+        p_aux: &PersistentAux<<Tree::Hasher as Hasher>::Domain>,
+        t_aux: &TemporaryAuxCache<Tree, G, R>,
+        partition_count: usize,
+    ) -> Result<Vec<Vec<Proof<Tree, G>>>> {
+        ensure!(partition_count > 0, "partitions must not be 0");
+
+        Self::prove_layers(
+            &pub_params.graph,
+            pub_inputs,
+            p_aux,
+            t_aux,
+            &pub_params.layer_challenges,
+            pub_params.layer_challenges.layers(),
+            partition_count,
+        )
+    }
+
     #[allow(clippy::too_many_arguments)]
-    pub(crate) fn prove_layers(
+    pub(crate) fn prove_layers<R: Read + Send + Sync>(
         graph: &StackedBucketGraph<Tree::Hasher>,
         pub_inputs: &PublicInputs<<Tree::Hasher as Hasher>::Domain, <G as Hasher>::Domain>,
         p_aux: &PersistentAux<<Tree::Hasher as Hasher>::Domain>,
-        t_aux: &TemporaryAuxCache<Tree, G>,
+        t_aux: &TemporaryAuxCache<Tree, G, R>,
         layer_challenges: &LayerChallenges,
         layers: usize,
         partition_count: usize,
@@ -116,10 +297,10 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         // Sanity checks on restored trees.
         assert!(pub_inputs.tau.is_some());
         // Skip this check in the case of synthetic porep
-        if t_aux.tree_d.is_some() {
-            assert_eq!(
-                pub_inputs.tau.as_ref().expect("as_ref failure").comm_d,
-                t_aux.tree_d.as_ref().expect("failed to get tree_d").root()
+        if let Some(tree_d_root) = t_aux.tree_d_root() {
+            ensure!(
+                pub_inputs.tau.as_ref().expect("as_ref failure").comm_d == tree_d_root,
+                "comm_d does not match the root of tree_d"
             );
         }
 
@@ -165,6 +346,35 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             "read_synth_porep: {}, gen_synth_porep {}",
             read_synth_proofs, gen_synth_proofs
         );
+
+        // If tree_c was removed from disk, rebuild it from the labels for the duration of proving.
+        // The guard is declared first, so that the tree is closed before the guard removes it.
+        let regenerate_tree_c = t_aux.tree_c.is_none() && t_aux.labels.len() == layers;
+        let regenerated_tree_c_guard = if regenerate_tree_c {
+            Some(RegeneratedTreeCGuard(&t_aux.t_aux.tree_c_config.path))
+        } else {
+            None
+        };
+        let regenerated_tree_c = if regenerate_tree_c {
+            Some(Self::regenerate_tree_c(
+                graph_size,
+                layers,
+                &t_aux.labels,
+                &t_aux.t_aux.tree_c_config,
+            )?)
+        } else {
+            None
+        };
+        let tree_c = t_aux
+            .tree_c
+            .as_ref()
+            .or(regenerated_tree_c.as_ref())
+            .context("tree_c is unavailable")?;
+        ensure!(
+            p_aux.comm_c == tree_c.root(),
+            "comm_c does not match the root of tree_c"
+        );
+
         let get_drg_parents_columns = |x: usize| -> Result<Vec<Column<Tree::Hasher>>> {
             let base_degree = graph.base_graph().degree();
 
@@ -202,7 +412,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                 // Derive the set of challenges we are proving over.
                 let challenges = pub_inputs.challenges(layer_challenges, graph_size, Some(k));
 
-                THREAD_POOL.scoped(|scope| {
+                current_thread_pool().pool().scoped(|scope| {
                     // Stacked commitment specifics
                     challenges
                         .into_par_iter()
@@ -212,11 +422,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                             assert!(challenge < graph.size(), "Invalid challenge");
                             assert!(challenge > 0, "Invalid challenge");
 
-                            let comm_d_proof = t_aux
-                                .tree_d
-                                .as_ref()
-                                .expect("failed to get tree_d")
-                                .gen_proof(challenge)?;
+                            let comm_d_proof = t_aux.tree_d_proof(challenge)?;
 
                             let comm_d_proof_inner = comm_d_proof.clone();
                             let challenge_inner = challenge;
@@ -227,11 +433,6 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                             // Stacked replica column openings
                             let rcp = {
                                 let (c_x, drg_parents, exp_parents) = {
-                                    assert!(t_aux.tree_c.is_some());
-                                    let tree_c =
-                                        t_aux.tree_c.as_ref().expect("failed to get tree_c");
-                                    assert_eq!(p_aux.comm_c, tree_c.root());
-
                                     // All labels in C_X
                                     trace!("  c_x");
                                     let c_x = t_aux.column(challenge as u32)?.into_proof(tree_c)?;
@@ -357,6 +558,10 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             })
             .collect::<Result<Vec<Vec<Proof<Tree, G>>>>>()?;
 
+        // Discard the regenerated tree_c again, so that it doesn't take up disk space.
+        drop(regenerated_tree_c);
+        drop(regenerated_tree_c_guard);
+
         // If synthetic vanilla proofs were generated, persist them here.
         if gen_synth_proofs {
             assert!(
@@ -371,12 +576,35 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         Ok(vanilla_proofs)
     }
 
-    fn write_synth_proofs(
+    /// Rebuilds tree_c from the labels at the location of the original tree_c.
+    fn regenerate_tree_c(
+        graph_size: usize,
+        layers: usize,
+        labels: &LabelsCache<Tree>,
+        tree_c_config: &StoreConfig,
+    ) -> Result<DiskTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>> {
+        let tree_count = get_base_tree_count::<Tree>();
+        let nodes_count = graph_size / tree_count;
+        let configs = split_config(tree_c_config.clone(), tree_count)?;
+
+        info!("regenerating tree_c from {} layers of labels", layers);
+        match layers {
+            2 => Self::generate_tree_c::<U2, Tree::Arity>(nodes_count, tree_count, configs, labels),
+            4 => Self::generate_tree_c::<U4, Tree::Arity>(nodes_count, tree_count, configs, labels),
+            8 => Self::generate_tree_c::<U8, Tree::Arity>(nodes_count, tree_count, configs, labels),
+            11 => {
+                Self::generate_tree_c::<U11, Tree::Arity>(nodes_count, tree_count, configs, labels)
+            }
+            _ => bail!("Unsupported column arity"),
+        }
+    }
+
+    fn write_synth_proofs<R: Read + Send + Sync>(
         synth_proofs: &[Proof<Tree, G>],
         pub_inputs: &PublicInputs<<Tree::Hasher as Hasher>::Domain, <G as Hasher>::Domain>,
         graph: &StackedBucketGraph<Tree::Hasher>,
         layer_challenges: &LayerChallenges,
-        t_aux: &TemporaryAuxCache<Tree, G>,
+        t_aux: &TemporaryAuxCache<Tree, G, R>,
     ) -> Result<()> {
         use crate::stacked::vanilla::SynthChallenges;
 
@@ -385,7 +613,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             "comm_r must be set prior to generating synthetic challenges",
         );
 
-        THREAD_POOL.scoped(|scope| {
+        current_thread_pool().pool().scoped(|scope| {
             // Verify synth proofs prior to writing because `ProofScheme`'s verification API is not
             // amenable to prover-only verification (i.e. the API uses public values, whereas synthetic
             // proofs are known only to the prover).
@@ -396,7 +624,8 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                 .as_ref()
                 .map(|tau| tau.comm_r.into())
                 .expect("unwrapping should not fail");
-            let synth_challenges = SynthChallenges::default(graph.size(), &replica_id, &comm_r);
+            let synth_challenges = SynthChallenges::default(graph.size(), &replica_id, &comm_r)
+                .with_domain(*layer_challenges.domain());
             assert_eq!(synth_proofs.len(), synth_challenges.num_synth_challenges);
             for (challenge, proof) in synth_challenges.zip(synth_proofs) {
                 let proof_inner = proof.clone();
@@ -435,11 +664,11 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         Ok(())
     }
 
-    fn read_porep_proofs_from_synth(
+    fn read_porep_proofs_from_synth<R: Read + Send + Sync>(
         sector_nodes: usize,
         pub_inputs: &PublicInputs<<Tree::Hasher as Hasher>::Domain, <G as Hasher>::Domain>,
         layer_challenges: &LayerChallenges,
-        t_aux: &TemporaryAuxCache<Tree, G>,
+        t_aux: &TemporaryAuxCache<Tree, G, R>,
         partition_count: usize,
     ) -> Result<Vec<Vec<Proof<Tree, G>>>> {
         ensure!(
@@ -515,33 +744,77 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
 
         let last_layer_labels = labels.labels_for_last_layer()?;
         let size = Store::len(last_layer_labels);
+        ensure!(
+            data.len() == size * NODE_SIZE,
+            "replica length {} does not match the {} key nodes",
+            data.len(),
+            size
+        );
 
-        for (key, encoded_node_bytes) in last_layer_labels
-            .read_range(0..size)?
-            .into_iter()
-            .zip(data.chunks_mut(NODE_SIZE))
-        {
-            let encoded_node =
-                <Tree::Hasher as Hasher>::Domain::try_from_bytes(encoded_node_bytes)?;
-            let data_node = decode::<<Tree::Hasher as Hasher>::Domain>(key, encoded_node);
+        // The key layer is read in large windows on a dedicated thread while the previous
+        // window is decoded across all cores, so that disk reads overlap with decoding.
+        // The channel holds a single window, which bounds memory to two windows in flight.
+        crossbeam::thread::scope(|s| -> Result<()> {
+            let (keys_tx, keys_rx) = sync_channel::<Result<Vec<u8>>>(1);
+
+            s.spawn(move |_| {
+                for start in (0..size).step_by(DECODE_WINDOW_NODES) {
+                    let end = usize::min(start + DECODE_WINDOW_NODES, size);
+                    let mut keys = vec![0u8; (end - start) * NODE_SIZE];
+                    let read = last_layer_labels
+                        .read_range_into(start, end, &mut keys)
+                        .map(|_| keys);
+                    let failed = read.is_err();
+                    // The receiver is gone if decoding failed; stop reading in that case.
+                    if keys_tx.send(read).is_err() || failed {
+                        break;
+                    }
+                }
+            });
 
-            // store result in the data
-            encoded_node_bytes.copy_from_slice(AsRef::<[u8]>::as_ref(&data_node));
-        }
+            for (window, keys) in data
+                .chunks_mut(DECODE_WINDOW_NODES * NODE_SIZE)
+                .zip(keys_rx.iter())
+            {
+                let keys = keys?
+                    .par_chunks(NODE_SIZE)
+                    .map(<Tree::Hasher as Hasher>::Domain::try_from_bytes)
+                    .collect::<Result<Vec<_>>>()?;
+                decode_slice(&keys, window)?;
+            }
+
+            Ok(())
+        })
+        .map_err(|_| anyhow!("decode reader thread panicked"))??;
 
         Ok(())
     }
 
-    /// Generates the layers as needed for encoding.
+    /// Generates the layers as needed for encoding. The labels of the last layer are streamed to
+    /// `labels_tx`, if given, while they are generated.
     fn generate_labels_for_encoding<P>(
         graph: &StackedBucketGraph<Tree::Hasher>,
         layer_challenges: &LayerChallenges,
         replica_id: &<Tree::Hasher as Hasher>::Domain,
         cache_path: P,
+        labels_tx: Option<SyncSender<LabeledRange>>,
     ) -> Result<(Labels<Tree>, Vec<LayerState>)>
     where
         P: AsRef<Path>,
     {
+        if !graph.has_production_degrees() {
+            // The parent cache is laid out for the production degrees only.
+            info!("single core replication without parent cache");
+            return create_label::single::create_labels_for_encoding(
+                graph,
+                None,
+                layer_challenges.layers(),
+                replica_id,
+                &cache_path,
+                labels_tx.as_ref(),
+            );
+        }
+
         let mut parent_cache = graph.parent_cache()?;
 
         #[cfg(feature = "multicore-sdr")]
@@ -554,15 +827,17 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                     layer_challenges.layers(),
                     replica_id,
                     &cache_path,
+                    labels_tx.as_ref(),
                 )
             } else {
                 info!("single core replication");
                 create_label::single::create_labels_for_encoding(
                     graph,
-                    &mut parent_cache,
+                    Some(&mut parent_cache),
                     layer_challenges.layers(),
                     replica_id,
                     &cache_path,
+                    labels_tx.as_ref(),
                 )
             }
         }
@@ -572,10 +847,11 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             info!("single core replication");
             create_label::single::create_labels_for_encoding(
                 graph,
-                &mut parent_cache,
+                Some(&mut parent_cache),
                 layer_challenges.layers(),
                 replica_id,
                 &cache_path,
+                labels_tx.as_ref(),
             )
         }
     }
@@ -587,6 +863,18 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         replica_id: &<Tree::Hasher as Hasher>::Domain,
         config: StoreConfig,
     ) -> Result<LabelsCache<Tree>> {
+        if !graph.has_production_degrees() {
+            // The parent cache is laid out for the production degrees only.
+            info!("single core replication without parent cache");
+            return create_label::single::create_labels_for_decoding(
+                graph,
+                None,
+                layer_challenges.layers(),
+                replica_id,
+                config,
+            );
+        }
+
         let mut parent_cache = graph.parent_cache()?;
 
         #[cfg(feature = "multicore-sdr")]
@@ -604,7 +892,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                 info!("single core replication");
                 create_label::single::create_labels_for_decoding(
                     graph,
-                    &mut parent_cache,
+                    Some(&mut parent_cache),
                     layer_challenges.layers(),
                     replica_id,
                     config,
@@ -617,7 +905,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             info!("single core replication");
             create_label::single::create_labels_for_decoding(
                 graph,
-                &mut parent_cache,
+                Some(&mut parent_cache),
                 layer_challenges.layers(),
                 replica_id,
                 config,
@@ -633,6 +921,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         tree_data: &[u8],
         config: StoreConfig,
     ) -> Result<BinaryMerkleTree<K>> {
+        let _span = info_span!("build_tree_d").entered();
         trace!("building tree (size: {})", tree_data.len());
 
         let leafs = tree_data.len() / NODE_SIZE;
@@ -673,6 +962,8 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         ColumnArity: 'static + PoseidonArity,
         TreeArity: PoseidonArity,
     {
+        let _span = info_span!("build_tree_c", tree_count).entered();
+
         if Self::use_gpu_column_builder() {
             Self::generate_tree_c_gpu::<ColumnArity, TreeArity>(
                 nodes_count,
@@ -701,6 +992,8 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         ColumnArity: 'static + PoseidonArity,
         TreeArity: PoseidonArity,
     {
+        let _span = info_span!("build_tree_c", tree_count).entered();
+
         Self::generate_tree_c_cpu::<ColumnArity, TreeArity>(
             nodes_count,
             tree_count,
@@ -711,7 +1004,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
 
     #[allow(clippy::needless_range_loop)]
     #[cfg(any(feature = "cuda", feature = "opencl"))]
-    fn generate_tree_c_gpu<ColumnArity, TreeArity>(
+    pub(crate) fn generate_tree_c_gpu<ColumnArity, TreeArity>(
         nodes_count: usize,
         tree_count: usize,
         configs: Vec<StoreConfig>,
@@ -753,7 +1046,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             let (builder_tx, builder_rx) = channel(0);
 
             let config_count = configs.len(); // Don't move config into closure below.
-            THREAD_POOL.scoped(|s| {
+            current_thread_pool().pool().scoped(|s| {
                 // This channel will receive the finished tree data to be written to disk.
                 let (writer_tx, writer_rx) = channel::<(Vec<Fr>, Vec<Fr>)>(0);
 
@@ -969,7 +1262,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         })
     }
 
-    fn generate_tree_c_cpu<ColumnArity, TreeArity>(
+    pub(crate) fn generate_tree_c_cpu<ColumnArity, TreeArity>(
         nodes_count: usize,
         tree_count: usize,
         configs: Vec<StoreConfig>,
@@ -988,8 +1281,9 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                 let mut hashes: Vec<<Tree::Hasher as Hasher>::Domain> =
                     vec![<Tree::Hasher as Hasher>::Domain::default(); nodes_count];
 
-                THREAD_POOL.scoped(|s| {
-                    let n = num_cpus::get();
+                let thread_pool = current_thread_pool();
+                thread_pool.pool().scoped(|s| {
+                    let n = thread_pool.num_threads();
 
                     // only split if we have at least two elements per thread
                     let num_chunks = if n > nodes_count * 2 { 1 } else { n };
@@ -1013,7 +1307,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                                     })
                                     .collect();
 
-                                *hash = hash_single_column(&data).into();
+                                *hash = hash_column::<ColumnArity>(&data).into();
                             }
                         });
                     }
@@ -1173,6 +1467,8 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         source: &DiskStore<<Tree::Hasher as Hasher>::Domain>,
         callback: Option<PrepareTreeRDataCallback<Tree>>,
     ) -> Result<LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>> {
+        let _span = info_span!("build_tree_r_last", tree_count).entered();
+
         let encode_data = match callback {
             Some(x) => x,
             None => Self::prepare_tree_r_data,
@@ -1211,6 +1507,8 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         source: &DiskStore<<Tree::Hasher as Hasher>::Domain>,
         callback: Option<PrepareTreeRDataCallback<Tree>>,
     ) -> Result<LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>> {
+        let _span = info_span!("build_tree_r_last", tree_count).entered();
+
         let encode_data = match callback {
             Some(x) => x,
             None => Self::prepare_tree_r_data,
@@ -1228,7 +1526,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
     }
 
     #[cfg(any(feature = "cuda", feature = "opencl"))]
-    fn generate_tree_r_last_gpu(
+    pub(crate) fn generate_tree_r_last_gpu(
         data: &mut Data<'_>,
         nodes_count: usize,
         tree_count: usize,
@@ -1264,7 +1562,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         let configs = &configs;
         let tree_r_last_config = &tree_r_last_config;
 
-        THREAD_POOL.scoped(|s| {
+        current_thread_pool().pool().scoped(|s| {
             // This channel will receive the finished tree data to be written to disk.
             let (writer_tx, writer_rx) = channel::<Vec<Fr>>(0);
 
@@ -1403,7 +1701,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         )
     }
 
-    fn generate_tree_r_last_cpu(
+    pub(crate) fn generate_tree_r_last_cpu(
         data: &mut Data<'_>,
         nodes_count: usize,
         tree_count: usize,
@@ -1470,6 +1768,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         )
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn transform_and_replicate_layers(
         graph: &StackedBucketGraph<Tree::Hasher>,
         layer_challenges: &LayerChallenges,
@@ -1479,6 +1778,10 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         cache_path: PathBuf,
         replica_path: PathBuf,
         label_configs: Labels<Tree>,
+        // The root of the tree_c that was already built in phase1, if any.
+        tree_c_root: Option<<Tree::Hasher as Hasher>::Domain>,
+        // comm_d if it was computed from the pieces, tree_d is then not built.
+        comm_d: Option<G::Domain>,
     ) -> Result<TransformedLayers<Tree, G>> {
         trace!("transform_and_replicate_layers");
         let total_nodes_count = graph.size();
@@ -1489,13 +1792,13 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         let tree_count = get_base_tree_count::<Tree>();
         let nodes_count = graph.size() / tree_count;
 
-        // Ensure that the node count will work for binary and oct arities.
-        let binary_arity_valid = is_merkle_tree_size_valid(nodes_count, BINARY_ARITY);
+        // Ensure that the node count will work for the tree_d and tree_r arities.
+        let tree_d_arity_valid = is_merkle_tree_size_valid(nodes_count, TREE_D_ARITY);
         let other_arity_valid = is_merkle_tree_size_valid(nodes_count, Tree::Arity::to_usize());
         trace!(
-            "is_merkle_tree_size_valid({}, BINARY_ARITY) = {}",
+            "is_merkle_tree_size_valid({}, TREE_D_ARITY) = {}",
             nodes_count,
-            binary_arity_valid
+            tree_d_arity_valid
         );
         trace!(
             "is_merkle_tree_size_valid({}, {}) = {}",
@@ -1503,7 +1806,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             Tree::Arity::to_usize(),
             other_arity_valid
         );
-        assert!(binary_arity_valid);
+        assert!(tree_d_arity_valid);
         assert!(other_arity_valid);
 
         let layers = layer_challenges.layers();
@@ -1514,7 +1817,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         let tree_d_config = StoreConfig {
             path: cache_path.clone(),
             id: CacheKey::CommDTree.to_string(),
-            size: Some(get_merkle_tree_len(total_nodes_count, BINARY_ARITY)?),
+            size: Some(get_merkle_tree_len(total_nodes_count, TREE_D_ARITY)?),
             rows_to_discard: 0,
         };
 
@@ -1556,60 +1859,84 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             None => error!("Failed to raise the fd limit"),
         };
 
-        let tree_c_root = match layers {
-            2 => {
-                let tree_c = Self::generate_tree_c::<U2, Tree::Arity>(
-                    nodes_count,
-                    tree_count,
-                    configs,
-                    &labels,
-                )?;
-                tree_c.root()
-            }
-            8 => {
-                let tree_c = Self::generate_tree_c::<U8, Tree::Arity>(
-                    nodes_count,
-                    tree_count,
-                    configs,
-                    &labels,
-                )?;
-                tree_c.root()
-            }
-            11 => {
-                let tree_c = Self::generate_tree_c::<U11, Tree::Arity>(
-                    nodes_count,
-                    tree_count,
-                    configs,
-                    &labels,
-                )?;
-                tree_c.root()
+        let tree_c_root = match tree_c_root {
+            Some(tree_c_root) => {
+                info!("using tree_c built in phase1");
+                tree_c_root
             }
-            _ => panic_any("Unsupported column arity"),
+            None => match layers {
+                2 => {
+                    let tree_c = Self::generate_tree_c::<U2, Tree::Arity>(
+                        nodes_count,
+                        tree_count,
+                        configs,
+                        &labels,
+                    )?;
+                    tree_c.root()
+                }
+                4 => {
+                    let tree_c = Self::generate_tree_c::<U4, Tree::Arity>(
+                        nodes_count,
+                        tree_count,
+                        configs,
+                        &labels,
+                    )?;
+                    tree_c.root()
+                }
+                8 => {
+                    let tree_c = Self::generate_tree_c::<U8, Tree::Arity>(
+                        nodes_count,
+                        tree_count,
+                        configs,
+                        &labels,
+                    )?;
+                    tree_c.root()
+                }
+                11 => {
+                    let tree_c = Self::generate_tree_c::<U11, Tree::Arity>(
+                        nodes_count,
+                        tree_count,
+                        configs,
+                        &labels,
+                    )?;
+                    tree_c.root()
+                }
+                _ => panic_any("Unsupported column arity"),
+            },
         };
         info!("tree_c done");
 
-        // Build the MerkleTree over the original data (if needed).
-        let tree_d = match data_tree {
-            Some(t) => {
-                trace!("using existing original data merkle tree");
-                assert_eq!(t.len(), 2 * (data.len() / NODE_SIZE) - 1);
-
-                t
+        let tree_d_root = match comm_d {
+            Some(comm_d) => {
+                trace!("using comm_d computed from the pieces, tree_d is not built");
+                comm_d
             }
             None => {
-                trace!("building merkle tree for the original data");
-                data.ensure_data()?;
-                measure_op(Operation::CommD, || {
-                    Self::build_binary_tree::<G>(data.as_ref(), tree_d_config.clone())
-                })?
+                // Build the MerkleTree over the original data (if needed).
+                let tree_d = match data_tree {
+                    Some(t) => {
+                        trace!("using existing original data merkle tree");
+                        assert_eq!(t.len(), 2 * (data.len() / NODE_SIZE) - 1);
+
+                        t
+                    }
+                    None => {
+                        trace!("building merkle tree for the original data");
+                        data.ensure_data()?;
+                        measure_op(Operation::CommD, || {
+                            Self::build_binary_tree::<G>(data.as_ref(), tree_d_config.clone())
+                        })?
+                    }
+                };
+                assert_eq!(
+                    tree_d_config.size.expect("config size failure"),
+                    tree_d.len()
+                );
+                let tree_d_root = tree_d.root();
+                drop(tree_d);
+                tree_d_root
             }
         };
-        assert_eq!(
-            tree_d_config.size.expect("config size failure"),
-            tree_d.len()
-        );
-        let tree_d_root = tree_d.root();
-        drop(tree_d);
 
         // Encode original data into the last layer.
         let last_layer_labels = labels.labels_for_last_layer()?;
@@ -1675,12 +2002,71 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                 &pp.layer_challenges,
                 replica_id,
                 cache_path,
+                None,
             )
         })?;
 
         Ok(labels_and_layer_states)
     }
 
+    /// Phase1 of replication, which also builds tree_c. The labels of the last layer are streamed
+    /// to the column hasher while they are generated, so that the columns are hashed while the
+    /// last layer is labeled, instead of reading all layers back from disk in phase2.
+    ///
+    /// Returns comm_c next to the labels, pass it to `replicate_phase2_with_tree_c`.
+    #[allow(clippy::type_complexity)]
+    pub fn replicate_phase1_with_tree_c<P>(
+        pp: &'a PublicParams<Tree>,
+        replica_id: &<Tree::Hasher as Hasher>::Domain,
+        cache_path: P,
+    ) -> Result<(
+        Labels<Tree>,
+        Vec<LayerState>,
+        <Tree::Hasher as Hasher>::Domain,
+    )>
+    where
+        P: AsRef<Path>,
+    {
+        info!("replicate_phase1_with_tree_c");
+        let cache_path = cache_path.as_ref();
+
+        crossbeam::thread::scope(|s| -> Result<_> {
+            // Labeling never waits for the column hasher, the ranges it could not keep up with
+            // are read back from disk once labeling is done.
+            let (labels_tx, labels_rx) = sync_channel(create_label::LABELS_STREAM_QUEUE);
+            let tree_c = s.spawn(move |_| -> Result<_> {
+                let _span = info_span!("build_tree_c").entered();
+                let mut tree_c = StreamedTreeC::<Tree>::new(
+                    &pp.graph,
+                    pp.layer_challenges.layers(),
+                    cache_path,
+                )?;
+                for range in labels_rx {
+                    tree_c.hash_range(range.start, &range.labels)?;
+                }
+                Ok(tree_c)
+            });
+
+            let labels_and_layer_states = measure_op(Operation::EncodeWindowTimeAll, || {
+                Self::generate_labels_for_encoding(
+                    &pp.graph,
+                    &pp.layer_challenges,
+                    replica_id,
+                    cache_path,
+                    Some(labels_tx),
+                )
+            });
+
+            let tree_c = tree_c
+                .join()
+                .map_err(|_| anyhow!("tree_c builder thread panicked"))?;
+            let (labels, layer_states) = labels_and_layer_states?;
+            let tree_c_root = tree_c?.finish()?;
+            Ok((labels, layer_states, tree_c_root))
+        })
+        .map_err(|_| anyhow!("replicate_phase1_with_tree_c thread panicked"))?
+    }
+
     /// Phase2 of replication.
     #[allow(clippy::type_complexity)]
     pub fn replicate_phase2(
@@ -1707,6 +2093,80 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             cache_path,
             replica_path,
             label_configs,
+            None,
+            None,
+        )?;
+
+        Ok((tau, (paux, taux)))
+    }
+
+    /// Phase2 of replication, for labels generated with `replicate_phase1_with_tree_c`. The
+    /// tree_c built in phase1 is used as is, `comm_c` must be the root returned by phase1.
+    #[allow(clippy::type_complexity)]
+    pub fn replicate_phase2_with_tree_c(
+        pp: &'a PublicParams<Tree>,
+        label_configs: Labels<Tree>,
+        comm_c: <Tree::Hasher as Hasher>::Domain,
+        data: Data<'a>,
+        data_tree: Option<BinaryMerkleTree<G>>,
+        cache_path: PathBuf,
+        replica_path: PathBuf,
+    ) -> Result<(
+        Tau<<Tree::Hasher as Hasher>::Domain, <G as Hasher>::Domain>,
+        (
+            PersistentAux<<Tree::Hasher as Hasher>::Domain>,
+            TemporaryAux<Tree, G>,
+        ),
+    )> {
+        info!("replicate_phase2_with_tree_c");
+
+        let (tau, paux, taux) = Self::transform_and_replicate_layers(
+            &pp.graph,
+            &pp.layer_challenges,
+            data,
+            data_tree,
+            cache_path,
+            replica_path,
+            label_configs,
+            Some(comm_c),
+            None,
+        )?;
+
+        Ok((tau, (paux, taux)))
+    }
+
+    /// Phase2 of replication, without building tree_d. `comm_d` must be the root of tree_d over
+    /// `data`, e.g. computed from the pieces of the sector. The data is opened from the staged
+    /// sector when proving, see `TemporaryAuxCache::set_tree_d_data`. `comm_c` is the root of the
+    /// tree_c built by `replicate_phase1_with_tree_c`, if the labels were generated with it.
+    #[allow(clippy::type_complexity, clippy::too_many_arguments)]
+    pub fn replicate_phase2_with_comm_d(
+        pp: &'a PublicParams<Tree>,
+        label_configs: Labels<Tree>,
+        comm_d: G::Domain,
+        comm_c: Option<<Tree::Hasher as Hasher>::Domain>,
+        data: Data<'a>,
+        cache_path: PathBuf,
+        replica_path: PathBuf,
+    ) -> Result<(
+        Tau<<Tree::Hasher as Hasher>::Domain, <G as Hasher>::Domain>,
+        (
+            PersistentAux<<Tree::Hasher as Hasher>::Domain>,
+            TemporaryAux<Tree, G>,
+        ),
+    )> {
+        info!("replicate_phase2_with_comm_d");
+
+        let (tau, paux, taux) = Self::transform_and_replicate_layers(
+            &pp.graph,
+            &pp.layer_challenges,
+            data,
+            None,
+            cache_path,
+            replica_path,
+            label_configs,
+            comm_c,
+            Some(comm_d),
         )?;
 
         Ok((tau, (paux, taux)))
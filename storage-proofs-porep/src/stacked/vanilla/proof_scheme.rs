@@ -3,13 +3,17 @@ use filecoin_hashers::{HashFunction, Hasher};
 use log::{error, trace};
 use rayon::prelude::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
 use storage_proofs_core::{
-    drgraph::Graph, error::Result, merkle::MerkleTreeTrait, proof::ProofScheme,
+    api_version::ApiFeature,
+    drgraph::{Graph, BASE_DEGREE},
+    error::Result,
+    merkle::MerkleTreeTrait,
+    proof::ProofScheme,
 };
 
 use crate::stacked::vanilla::{
     challenges::ChallengeRequirements,
-    graph::StackedBucketGraph,
-    params::{PrivateInputs, Proof, PublicInputs, PublicParams, SetupParams},
+    graph::{check_degrees, StackedBucketGraph, EXP_DEGREE},
+    params::{check_tree_d_arity, PrivateInputs, Proof, PublicInputs, PublicParams, SetupParams},
     proof::StackedDrg,
 };
 
@@ -24,6 +28,17 @@ impl<'a, 'c, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> ProofScheme<'
     type Requirements = ChallengeRequirements;
 
     fn setup(sp: &Self::SetupParams) -> Result<Self::PublicParams> {
+        check_tree_d_arity::<G>(sp.tree_d_arity)?;
+        check_degrees(&sp.porep_id, sp.api_version, sp.degree, sp.expansion_degree)?;
+        // The synthetic proofs file is laid out for the production degrees.
+        ensure!(
+            !sp.api_features.contains(&ApiFeature::SyntheticPoRep)
+                || (sp.degree == BASE_DEGREE && sp.expansion_degree == EXP_DEGREE),
+            "synthetic porep requires base degree {} and expansion degree {}",
+            BASE_DEGREE,
+            EXP_DEGREE,
+        );
+
         let graph = StackedBucketGraph::<Tree::Hasher>::new_stacked(
             sp.nodes,
             sp.degree,
@@ -32,7 +47,10 @@ impl<'a, 'c, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> ProofScheme<'
             sp.api_version,
         )?;
 
-        Ok(PublicParams::new(graph, sp.layer_challenges.clone()))
+        Ok(
+            PublicParams::new(graph, sp.layer_challenges.clone())
+                .with_tree_d_arity(sp.tree_d_arity),
+        )
     }
 
     fn prove<'b>(
@@ -60,15 +78,12 @@ impl<'a, 'c, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> ProofScheme<'
         partition_count: usize,
     ) -> Result<Vec<Self::Proof>> {
         trace!("prove_all_partitions");
-        ensure!(partition_count > 0, "partitions must not be 0");
 
-        Self::prove_layers(
-            &pub_params.graph,
+        Self::prove_all_partitions_with_cache(
+            pub_params,
             pub_inputs,
             &priv_inputs.p_aux,
             &priv_inputs.t_aux,
-            &pub_params.layer_challenges,
-            pub_params.layer_challenges.layers(),
             partition_count,
         )
     }
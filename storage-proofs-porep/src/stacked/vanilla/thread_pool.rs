@@ -0,0 +1,106 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::Arc;
+
+use lazy_static::lazy_static;
+use yastl::Pool;
+
+lazy_static! {
+    static ref GLOBAL_POOL: Arc<PorepThreadPool> = Arc::new(PorepThreadPool::new(num_cpus::get()));
+}
+
+thread_local! {
+    static INSTALLED_POOL: RefCell<Option<Arc<PorepThreadPool>>> = RefCell::new(None);
+}
+
+/// The pool running the scoped work of the stacked DRG prover: building tree_c (including the
+/// column hashing) and tree_r_last, and generating the vanilla and synthetic proofs.
+///
+/// Unless a pool is installed with [`install_thread_pool`], a global pool with one thread per
+/// CPU is used.
+pub struct PorepThreadPool {
+    pool: Pool,
+    num_threads: usize,
+}
+
+impl fmt::Debug for PorepThreadPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PorepThreadPool")
+            .field("num_threads", &self.num_threads)
+            .finish()
+    }
+}
+
+impl PorepThreadPool {
+    /// Creates a pool of `num_threads` threads, at least two as the GPU tree builders run a
+    /// producer and a writer on the pool at the same time.
+    pub fn new(num_threads: usize) -> Self {
+        let num_threads = num_threads.max(2);
+        PorepThreadPool {
+            pool: Pool::new(num_threads),
+            num_threads,
+        }
+    }
+
+    pub fn num_threads(&self) -> usize {
+        self.num_threads
+    }
+
+    pub(crate) fn pool(&self) -> &Pool {
+        &self.pool
+    }
+}
+
+/// Restores the previously installed pool, also when the operation panics.
+struct InstalledPoolGuard(Option<Arc<PorepThreadPool>>);
+
+impl Drop for InstalledPoolGuard {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        INSTALLED_POOL.with(|installed| *installed.borrow_mut() = previous);
+    }
+}
+
+/// Runs `op` with `pool` as the [`PorepThreadPool`] of the prover calls made on the current
+/// thread.
+pub fn install_thread_pool<R>(pool: Arc<PorepThreadPool>, op: impl FnOnce() -> R) -> R {
+    let previous = INSTALLED_POOL.with(|installed| installed.borrow_mut().replace(pool));
+    let _guard = InstalledPoolGuard(previous);
+    op()
+}
+
+/// The pool installed on the current thread, or the global one.
+pub(crate) fn current_thread_pool() -> Arc<PorepThreadPool> {
+    INSTALLED_POOL
+        .with(|installed| installed.borrow().clone())
+        .unwrap_or_else(|| Arc::clone(&GLOBAL_POOL))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_install_thread_pool() {
+        let global = current_thread_pool();
+        let pool = Arc::new(PorepThreadPool::new(3));
+
+        let num_threads = install_thread_pool(Arc::clone(&pool), || {
+            // Nested installs are undone on return.
+            install_thread_pool(Arc::new(PorepThreadPool::new(2)), || {
+                assert_eq!(current_thread_pool().num_threads(), 2);
+            });
+            current_thread_pool().num_threads()
+        });
+        assert_eq!(num_threads, 3);
+        assert!(Arc::ptr_eq(&current_thread_pool(), &global));
+
+        // Other threads keep using the global pool.
+        install_thread_pool(pool, || {
+            let other = std::thread::spawn(|| current_thread_pool().num_threads())
+                .join()
+                .expect("thread failed");
+            assert_eq!(other, global.num_threads());
+        });
+    }
+}
@@ -0,0 +1,215 @@
+use std::fs::File;
+use std::path::Path;
+
+use anyhow::{ensure, Context, Result};
+use filecoin_hashers::{Domain, HashFunction, Hasher};
+use generic_array::typenum::U2;
+use log::info;
+use memmap2::{Mmap, MmapOptions};
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use storage_proofs_core::{merkle::MerkleProof, util::NODE_SIZE};
+
+/// The height of the subtrees of tree_d that are hashed from the data whenever one of their
+/// leaves is opened, i.e. 2^13 leaves or 256 KiB of data.
+const SUBTREE_HEIGHT: usize = 13;
+
+/// Generates openings of tree_d directly from the data it is built over, so that tree_d never has
+/// to be stored, e.g. when comm_d was computed from the pieces of the sector.
+///
+/// The data is hashed once when the opener is created, only the rows above the subtrees of
+/// `SUBTREE_HEIGHT` are kept in memory. Opening a leaf hashes the subtree it is in again.
+#[derive(Debug)]
+pub struct TreeDOpener<G: Hasher> {
+    // `None` if there is no data, mapping an empty file fails.
+    data: Option<Mmap>,
+    leaves: usize,
+    subtree_height: usize,
+    // The rows from the roots of the subtrees up to the root of tree_d.
+    rows: Vec<Vec<G::Domain>>,
+}
+
+impl<G: Hasher> TreeDOpener<G> {
+    /// Creates the opener of a tree_d with `leaves` leaves over the data in `data_path`. The data
+    /// may be shorter than the tree, the missing leaves are zero, like the padding of a staged
+    /// sector. `/dev/zero` results in a tree_d of zeros.
+    pub fn new(data_path: &Path, leaves: usize) -> Result<Self> {
+        ensure!(
+            leaves.is_power_of_two(),
+            "tree_d must have a power of two leaves, not {}",
+            leaves
+        );
+
+        let file =
+            File::open(data_path).with_context(|| format!("could not open {:?}", data_path))?;
+        let len = file.metadata()?.len() as usize;
+        ensure!(
+            len <= leaves * NODE_SIZE,
+            "{:?} does not fit into a tree_d of {} leaves",
+            data_path,
+            leaves
+        );
+        let data = if len == 0 {
+            None
+        } else {
+            Some(unsafe {
+                MmapOptions::new()
+                    .map(&file)
+                    .with_context(|| format!("could not mmap {:?}", data_path))?
+            })
+        };
+
+        let mut opener = TreeDOpener {
+            data,
+            leaves,
+            subtree_height: SUBTREE_HEIGHT.min(leaves.trailing_zeros() as usize),
+            rows: Vec::new(),
+        };
+
+        info!("tree_d opener: hashing {:?}", data_path);
+        let subtree_bytes = NODE_SIZE << opener.subtree_height;
+        let data_subtrees = (len + subtree_bytes - 1) / subtree_bytes;
+        let zero_root = root(&opener.hash_subtree(&vec![0; subtree_bytes])?);
+        let subtree_roots = (0..leaves >> opener.subtree_height)
+            .into_par_iter()
+            .map(|subtree| {
+                if subtree < data_subtrees {
+                    Ok(root(&opener.hash_subtree(&opener.subtree_data(subtree))?))
+                } else {
+                    Ok(zero_root)
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut rows = vec![subtree_roots];
+        while rows[rows.len() - 1].len() > 1 {
+            let height = opener.subtree_height + rows.len() - 1;
+            let row = G::Function::hash_nodes_batch(&rows[rows.len() - 1], 2, height);
+            rows.push(row);
+        }
+        opener.rows = rows;
+
+        Ok(opener)
+    }
+
+    /// The root of tree_d, i.e. comm_d.
+    pub fn root(&self) -> G::Domain {
+        root(&self.rows)
+    }
+
+    /// Generates the opening of leaf `challenge`, it is the same as the one of a tree_d that was
+    /// built over the data.
+    pub fn gen_proof(&self, challenge: usize) -> Result<MerkleProof<G, U2>> {
+        ensure!(
+            challenge < self.leaves,
+            "challenge {} is out of range of tree_d with {} leaves",
+            challenge,
+            self.leaves
+        );
+
+        let subtree = challenge >> self.subtree_height;
+        let subtree_rows = self.hash_subtree(&self.subtree_data(subtree))?;
+        let leaf_index = challenge - (subtree << self.subtree_height);
+
+        let below = subtree_rows[..self.subtree_height]
+            .iter()
+            .enumerate()
+            .map(|(height, row)| (row, leaf_index >> height));
+        let above = self.rows[..self.rows.len() - 1]
+            .iter()
+            .enumerate()
+            .map(|(i, row)| (row, subtree >> i));
+        let path = below
+            .chain(above)
+            .map(|(row, index)| (vec![row[index ^ 1]], index & 1))
+            .collect();
+
+        Ok(MerkleProof::from_parts(
+            subtree_rows[0][leaf_index],
+            self.root(),
+            path,
+        ))
+    }
+
+    // The data of `subtree`, padded with zeros.
+    fn subtree_data(&self, subtree: usize) -> Vec<u8> {
+        let subtree_bytes = NODE_SIZE << self.subtree_height;
+        let mut bytes = vec![0; subtree_bytes];
+        if let Some(data) = &self.data {
+            let start = (subtree * subtree_bytes).min(data.len());
+            let end = (start + subtree_bytes).min(data.len());
+            bytes[..end - start].copy_from_slice(&data[start..end]);
+        }
+        bytes
+    }
+
+    // Returns all rows of the subtree over `bytes`, from the leaves up to its root.
+    fn hash_subtree(&self, bytes: &[u8]) -> Result<Vec<Vec<G::Domain>>> {
+        let leaves = bytes
+            .chunks_exact(NODE_SIZE)
+            .map(G::Domain::try_from_bytes)
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut rows = vec![leaves];
+        for height in 0..self.subtree_height {
+            let row = G::Function::hash_nodes_batch(&rows[height], 2, height);
+            rows.push(row);
+        }
+        Ok(rows)
+    }
+}
+
+fn root<D: Copy>(rows: &[Vec<D>]) -> D {
+    rows.last().expect("tree has no rows")[0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    use filecoin_hashers::sha256::Sha256Hasher;
+    use rand::{Rng, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+    use storage_proofs_core::{
+        merkle::{create_base_merkle_tree, BinaryMerkleTree, MerkleProofTrait, MerkleTreeTrait},
+        TEST_SEED,
+    };
+    use tempfile::NamedTempFile;
+
+    fn check_openings(leaves: usize, data_len: usize) {
+        let mut rng = XorShiftRng::from_seed(TEST_SEED);
+        let data: Vec<u8> = (0..data_len).map(|_| rng.gen()).collect();
+        let mut file = NamedTempFile::new().expect("failed to create tempfile");
+        file.write_all(&data).expect("failed to write data");
+
+        let mut padded = data;
+        padded.resize(leaves * NODE_SIZE, 0);
+        let tree_d =
+            create_base_merkle_tree::<BinaryMerkleTree<Sha256Hasher>>(None, leaves, &padded)
+                .expect("failed to build tree_d");
+
+        let opener =
+            TreeDOpener::<Sha256Hasher>::new(file.path(), leaves).expect("failed to create opener");
+        assert_eq!(opener.root(), tree_d.root());
+
+        for challenge in [0, 1, leaves / 2 + 3, leaves - 1] {
+            let proof = opener.gen_proof(challenge).expect("failed to open leaf");
+            let expected = tree_d.gen_proof(challenge).expect("failed to open leaf");
+            assert!(proof.validate(challenge));
+            assert_eq!(proof.leaf(), expected.leaf());
+            assert_eq!(proof.root(), expected.root());
+            assert_eq!(proof.path(), expected.path());
+        }
+        assert!(opener.gen_proof(leaves).is_err());
+    }
+
+    #[test]
+    fn test_tree_d_opener() {
+        // A single subtree, below `SUBTREE_HEIGHT`.
+        check_openings(64, 64 * NODE_SIZE);
+        // Several subtrees, the last ones are beyond the data and zero.
+        check_openings(1 << 15, 10_000 * NODE_SIZE + 5);
+        check_openings(1 << 14, 0);
+    }
+}
@@ -20,7 +20,7 @@ use storage_proofs_core::{
 };
 use storage_proofs_porep::stacked::{
     self, LayerChallenges, PrivateInputs, PublicInputs, SetupParams, StackedCompound, StackedDrg,
-    TemporaryAuxCache, EXP_DEGREE,
+    TemporaryAuxCache, EXP_DEGREE, TREE_D_ARITY,
 };
 use tempfile::tempdir;
 
@@ -28,32 +28,39 @@ mod common;
 
 #[test]
 fn test_stacked_porep_circuit_poseidon_base_2() {
-    test_stacked_porep_circuit::<DiskTree<PoseidonHasher, U2, U0, U0>>(22, 1_206_212);
+    test_stacked_porep_circuit::<DiskTree<PoseidonHasher, U2, U0, U0>>(2, 22, Some(1_206_212));
 }
 
 #[test]
 fn test_stacked_input_circuit_poseidon_base_8() {
-    test_stacked_porep_circuit::<DiskTree<PoseidonHasher, U8, U0, U0>>(22, 1_199_620);
+    test_stacked_porep_circuit::<DiskTree<PoseidonHasher, U8, U0, U0>>(2, 22, Some(1_199_620));
 }
 
 #[test]
 fn test_stacked_input_circuit_poseidon_sub_8_4() {
-    test_stacked_porep_circuit::<DiskTree<PoseidonHasher, U8, U4, U0>>(22, 1_296_576);
+    test_stacked_porep_circuit::<DiskTree<PoseidonHasher, U8, U4, U0>>(2, 22, Some(1_296_576));
 }
 
 #[test]
 fn test_stacked_input_circuit_poseidon_top_8_4_2() {
-    test_stacked_porep_circuit::<DiskTree<PoseidonHasher, U8, U4, U2>>(22, 1_346_982);
+    test_stacked_porep_circuit::<DiskTree<PoseidonHasher, U8, U4, U2>>(2, 22, Some(1_346_982));
+}
+
+// The number of constraints grows with the number of layers, only the satisfiability and the
+// public inputs are checked.
+#[test]
+fn test_stacked_porep_circuit_poseidon_base_8_four_layers() {
+    test_stacked_porep_circuit::<DiskTree<PoseidonHasher, U8, U0, U0>>(4, 22, None);
 }
 
 fn test_stacked_porep_circuit<Tree: MerkleTreeTrait + 'static>(
+    num_layers: usize,
     expected_inputs: usize,
-    expected_constraints: usize,
+    expected_constraints: Option<usize>,
 ) {
     let nodes = 8 * get_base_tree_count::<Tree>();
     let degree = BASE_DEGREE;
     let expansion_degree = EXP_DEGREE;
-    let num_layers = 2;
     let layer_challenges = LayerChallenges::new(num_layers, 1);
 
     let mut rng = XorShiftRng::from_seed(TEST_SEED);
@@ -80,6 +87,7 @@ fn test_stacked_porep_circuit<Tree: MerkleTreeTrait + 'static>(
         layer_challenges,
         api_version: ApiVersion::V1_1_0,
         api_features: vec![],
+        tree_d_arity: TREE_D_ARITY,
     };
 
     let pp = StackedDrg::<Tree, Sha256Hasher>::setup(&sp).expect("setup failed");
@@ -134,11 +142,13 @@ fn test_stacked_porep_circuit<Tree: MerkleTreeTrait + 'static>(
             .expect("failed to synthesize circuit");
 
         assert_eq!(cs.num_inputs(), expected_inputs, "wrong number of inputs");
-        assert_eq!(
-            cs.num_constraints(),
-            expected_constraints,
-            "wrong number of constraints"
-        );
+        if let Some(expected_constraints) = expected_constraints {
+            assert_eq!(
+                cs.num_constraints(),
+                expected_constraints,
+                "wrong number of constraints"
+            );
+        }
     }
     let mut cs = TestConstraintSystem::<Fr>::new();
 
@@ -149,11 +159,13 @@ fn test_stacked_porep_circuit<Tree: MerkleTreeTrait + 'static>(
 
     assert!(cs.is_satisfied(), "constraints not satisfied");
     assert_eq!(cs.num_inputs(), expected_inputs, "wrong number of inputs");
-    assert_eq!(
-        cs.num_constraints(),
-        expected_constraints,
-        "wrong number of constraints"
-    );
+    if let Some(expected_constraints) = expected_constraints {
+        assert_eq!(
+            cs.num_constraints(),
+            expected_constraints,
+            "wrong number of constraints"
+        );
+    }
 
     assert_eq!(cs.get_input(0, "ONE"), Fr::ONE);
 
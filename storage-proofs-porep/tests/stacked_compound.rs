@@ -21,7 +21,7 @@ use storage_proofs_core::{
 };
 use storage_proofs_porep::stacked::{
     self, ChallengeRequirements, LayerChallenges, PrivateInputs, PublicInputs, SetupParams,
-    StackedCompound, StackedDrg, TemporaryAuxCache, EXP_DEGREE,
+    StackedCompound, StackedDrg, TemporaryAuxCache, EXP_DEGREE, TREE_D_ARITY,
 };
 use tempfile::tempdir;
 
@@ -45,6 +45,36 @@ fn test_stacked_compound_poseidon_top_8_4_2() {
     test_stacked_compound::<DiskTree<PoseidonHasher, U8, U4, U2>>();
 }
 
+#[test]
+fn test_stacked_compound_unsupported_tree_d_arity() {
+    type Tree = DiskTree<PoseidonHasher, U8, U0, U0>;
+
+    let mut setup_params = compound_proof::SetupParams {
+        vanilla_params: SetupParams {
+            nodes: 64,
+            degree: BASE_DEGREE,
+            expansion_degree: EXP_DEGREE,
+            porep_id: [55; 32],
+            layer_challenges: LayerChallenges::new(2, 1),
+            api_version: ApiVersion::V1_1_0,
+            api_features: vec![],
+            tree_d_arity: TREE_D_ARITY,
+        },
+        partitions: Some(1),
+        priority: false,
+    };
+    let public_params =
+        StackedCompound::<Tree, Sha256Hasher>::setup(&setup_params).expect("setup failed");
+    assert_eq!(public_params.vanilla_params.tree_d_arity, TREE_D_ARITY);
+
+    // The circuit rejects the arity before the vanilla proofs are set up.
+    setup_params.vanilla_params.tree_d_arity = 8;
+    let err = StackedCompound::<Tree, Sha256Hasher>::setup(&setup_params)
+        .err()
+        .expect("tree_d arity 8 must be rejected");
+    assert!(err.to_string().contains("not supported by the circuit"));
+}
+
 fn test_stacked_compound<Tree: 'static + MerkleTreeTrait>() {
     let nodes = 8 * get_base_tree_count::<Tree>();
 
@@ -71,6 +101,7 @@ fn test_stacked_compound<Tree: 'static + MerkleTreeTrait>() {
             layer_challenges,
             api_version: ApiVersion::V1_1_0,
             api_features: vec![],
+            tree_d_arity: TREE_D_ARITY,
         },
         partitions: Some(partition_count),
         priority: false,
@@ -1,4 +1,4 @@
-use std::fs::remove_file;
+use std::fs::{read, remove_file, write};
 
 use blstrs::Scalar as Fr;
 use ff::{Field, PrimeField};
@@ -8,14 +8,17 @@ use filecoin_hashers::{
 use fr32::fr_into_bytes;
 use generic_array::typenum::{U0, U2, U4, U8};
 use glob::glob;
-use merkletree::store::{Store, StoreConfig};
+use merkletree::store::{DiskStore, LevelCacheStore, Store, StoreConfig};
 use rand::{Rng, SeedableRng};
 use rand_xorshift::XorShiftRng;
 use storage_proofs_core::{
     api_version::ApiVersion,
     cache_key::CacheKey,
     drgraph::BASE_DEGREE,
-    merkle::{get_base_tree_count, DiskTree, MerkleTreeTrait},
+    merkle::{
+        backend_external_reader, get_base_tree_count, BackendReader, DiskTree, FileBackend,
+        MerkleTreeTrait,
+    },
     proof::ProofScheme,
     table_tests,
     test_helper::setup_replica,
@@ -23,8 +26,9 @@ use storage_proofs_core::{
     TEST_SEED,
 };
 use storage_proofs_porep::stacked::{
-    self, LayerChallenges, PrivateInputs, PublicInputs, SetupParams, StackedBucketGraph,
-    StackedDrg, TemporaryAuxCache, EXP_DEGREE,
+    self, create_label::single::find_divergent_label, LayerChallenges, PrivateInputs, PublicInputs,
+    SetupParams, StackedBucketGraph, StackedDrg, TemporaryAuxCache, TemporaryAuxStores, EXP_DEGREE,
+    TREE_D_ARITY,
 };
 use tempfile::tempdir;
 
@@ -111,6 +115,7 @@ fn test_extract_all<Tree: 'static + MerkleTreeTrait>() {
         layer_challenges,
         api_version: ApiVersion::V1_2_0,
         api_features: vec![],
+        tree_d_arity: TREE_D_ARITY,
     };
 
     let pp = StackedDrg::<Tree, Blake2sHasher>::setup(&sp).expect("setup failed");
@@ -206,6 +211,7 @@ fn test_stacked_porep_resume_seal() {
         layer_challenges,
         api_version: ApiVersion::V1_2_0,
         api_features: vec![],
+        tree_d_arity: TREE_D_ARITY,
     };
 
     let pp = StackedDrg::<Tree, Blake2sHasher>::setup(&sp).expect("setup failed");
@@ -282,6 +288,152 @@ fn test_stacked_porep_resume_seal() {
     cache_dir.close().expect("Failed to remove cache dir");
 }
 
+#[test]
+fn test_stacked_porep_tree_c_from_stream() {
+    type Tree = DiskTree<PoseidonHasher, U8, U8, U2>;
+
+    let mut rng = XorShiftRng::from_seed(TEST_SEED);
+    let replica_id = <PoseidonHasher as Hasher>::Domain::random(&mut rng);
+    let nodes = 64 * get_base_tree_count::<Tree>();
+
+    let data: Vec<u8> = (0..nodes)
+        .flat_map(|_| {
+            let v = <PoseidonHasher as Hasher>::Domain::random(&mut rng);
+            v.into_bytes()
+        })
+        .collect();
+
+    let cache_dir1 = tempdir().expect("tempdir failure");
+    let cache_dir2 = tempdir().expect("tempdir failure");
+    let replica_path1 = cache_dir1.path().join("replica-path");
+    let replica_path2 = cache_dir2.path().join("replica-path");
+    let mut mmapped_data1 = setup_replica(&data, &replica_path1);
+    let mut mmapped_data2 = setup_replica(&data, &replica_path2);
+
+    let sp = SetupParams {
+        nodes,
+        degree: BASE_DEGREE,
+        expansion_degree: EXP_DEGREE,
+        porep_id: [32; 32],
+        layer_challenges: LayerChallenges::new(DEFAULT_STACKED_LAYERS, 5),
+        api_version: ApiVersion::V1_2_0,
+        api_features: vec![],
+        tree_d_arity: TREE_D_ARITY,
+    };
+    let pp = StackedDrg::<Tree, Blake2sHasher>::setup(&sp).expect("setup failed");
+
+    let (tau, (p_aux, _)) = common::transform_and_replicate_layers::<Tree, Blake2sHasher>(
+        &pp,
+        &replica_id,
+        (mmapped_data1.as_mut()).into(),
+        cache_dir1.path().to_path_buf(),
+        replica_path1,
+    );
+
+    let (labels, _, comm_c) = StackedDrg::<Tree, Blake2sHasher>::replicate_phase1_with_tree_c(
+        &pp,
+        &replica_id,
+        cache_dir2.path(),
+    )
+    .expect("label generation failed");
+    assert_eq!(comm_c, p_aux.comm_c);
+
+    let (streamed_tau, (streamed_p_aux, _)) = StackedDrg::replicate_phase2_with_tree_c(
+        &pp,
+        labels,
+        comm_c,
+        (mmapped_data2.as_mut()).into(),
+        None,
+        cache_dir2.path().to_path_buf(),
+        replica_path2,
+    )
+    .expect("failed to transform");
+    assert_eq!(streamed_tau, tau);
+    assert_eq!(streamed_p_aux, p_aux);
+    assert_eq!(&mmapped_data1[..], &mmapped_data2[..]);
+
+    let tree_c_pattern = cache_dir1.path().to_string_lossy() + "/*tree-c*";
+    let mut tree_c_files = 0;
+    for entry in glob(&tree_c_pattern).expect("invalid glob pattern") {
+        let path = entry.expect("failed to read glob entry");
+        let streamed_path = cache_dir2
+            .path()
+            .join(path.file_name().expect("no file name"));
+        assert_eq!(
+            read(&path).expect("failed to read tree_c"),
+            read(&streamed_path).expect("failed to read streamed tree_c")
+        );
+        tree_c_files += 1;
+    }
+    assert_eq!(tree_c_files, get_base_tree_count::<Tree>());
+}
+
+#[test]
+fn test_stacked_porep_find_divergent_label() {
+    type Tree = DiskTree<PoseidonHasher, U8, U0, U0>;
+
+    let mut rng = XorShiftRng::from_seed(TEST_SEED);
+    let replica_id = <PoseidonHasher as Hasher>::Domain::random(&mut rng);
+    let nodes = 64 * get_base_tree_count::<Tree>();
+
+    let sp = SetupParams {
+        nodes,
+        degree: BASE_DEGREE,
+        expansion_degree: EXP_DEGREE,
+        porep_id: [32; 32],
+        layer_challenges: LayerChallenges::new(DEFAULT_STACKED_LAYERS, 5),
+        api_version: ApiVersion::V1_2_0,
+        api_features: vec![],
+        tree_d_arity: TREE_D_ARITY,
+    };
+    let pp = StackedDrg::<Tree, Blake2sHasher>::setup(&sp).expect("setup failed");
+
+    let cache_dir = tempdir().expect("tempdir failure");
+    StackedDrg::<Tree, Blake2sHasher>::replicate_phase1(&pp, &replica_id, cache_dir.path())
+        .expect("label generation failed");
+
+    for layer in [1, DEFAULT_STACKED_LAYERS] {
+        let divergent = find_divergent_label::<Tree, _, _>(
+            &pp.graph,
+            replica_id,
+            cache_dir.path(),
+            layer,
+            0..nodes,
+        )
+        .expect("failed to check labels");
+        assert_eq!(divergent, None);
+    }
+
+    // Corrupt a single label on a middle layer.
+    let (layer, corrupted_node) = (3, 17);
+    let data_path = StoreConfig::data_path(cache_dir.path(), &CacheKey::label_layer(layer));
+    let mut labels = read(&data_path).expect("failed to read layer");
+    labels[corrupted_node * NODE_SIZE] ^= 1;
+    write(&data_path, &labels).expect("failed to write layer");
+
+    let divergent = find_divergent_label::<Tree, _, _>(
+        &pp.graph,
+        replica_id,
+        cache_dir.path(),
+        layer,
+        0..nodes,
+    )
+    .expect("failed to check labels");
+    assert_eq!(divergent, Some(corrupted_node));
+
+    let divergent = find_divergent_label::<Tree, _, _>(
+        &pp.graph,
+        replica_id,
+        cache_dir.path(),
+        layer,
+        0..corrupted_node,
+    )
+    .expect("failed to check labels");
+    assert_eq!(divergent, None);
+
+    cache_dir.close().expect("Failed to remove cache dir");
+}
+
 table_tests! {
     test_prove_verify_fixed {
        test_stacked_porep_prove_verify(64);
@@ -316,7 +468,44 @@ fn test_prove_verify_fixed(n: usize) {
     test_prove_verify::<DiskTree<PoseidonHasher, U8, U8, U2>>(n, challenges);
 }
 
+// Research networks may run with fewer layers, the column hashes of tree_c then have a smaller
+// arity.
+#[test]
+fn test_stacked_porep_prove_verify_reduced_layers() {
+    for layers in [2, 4] {
+        let challenges = LayerChallenges::new(layers, 5);
+
+        test_prove_verify::<DiskTree<Sha256Hasher, U8, U0, U0>>(64, challenges.clone());
+        test_prove_verify::<DiskTree<PoseidonHasher, U8, U2, U0>>(64, challenges.clone());
+        test_prove_verify::<DiskTree<PoseidonHasher, U8, U8, U2>>(64, challenges);
+    }
+}
+
+// Research graphs may use other degrees, they are labeled without the parent cache.
+#[test]
+fn test_stacked_porep_prove_verify_custom_degrees() {
+    let challenges = LayerChallenges::new(DEFAULT_STACKED_LAYERS, 5);
+
+    for (degree, expansion_degree) in [(3, 2), (8, 4)] {
+        test_prove_verify_with_degrees::<DiskTree<PoseidonHasher, U8, U0, U0>>(
+            64,
+            challenges.clone(),
+            degree,
+            expansion_degree,
+        );
+    }
+}
+
 fn test_prove_verify<Tree: 'static + MerkleTreeTrait>(n: usize, challenges: LayerChallenges) {
+    test_prove_verify_with_degrees::<Tree>(n, challenges, BASE_DEGREE, EXP_DEGREE);
+}
+
+fn test_prove_verify_with_degrees<Tree: 'static + MerkleTreeTrait>(
+    n: usize,
+    challenges: LayerChallenges,
+    degree: usize,
+    expansion_degree: usize,
+) {
     // This will be called multiple times, only the first one succeeds, and that is ok.
     // femme::pretty::Logger::new()
     //     .start(log::LevelFilter::Trace)
@@ -325,8 +514,6 @@ fn test_prove_verify<Tree: 'static + MerkleTreeTrait>(n: usize, challenges: Laye
     let nodes = n * get_base_tree_count::<Tree>();
     let mut rng = XorShiftRng::from_seed(TEST_SEED);
 
-    let degree = BASE_DEGREE;
-    let expansion_degree = EXP_DEGREE;
     let replica_id: <Tree::Hasher as Hasher>::Domain =
         <Tree::Hasher as Hasher>::Domain::random(&mut rng);
     let data: Vec<u8> = (0..nodes)
@@ -353,6 +540,7 @@ fn test_prove_verify<Tree: 'static + MerkleTreeTrait>(n: usize, challenges: Laye
         layer_challenges: challenges,
         api_version: ApiVersion::V1_2_0,
         api_features: vec![],
+        tree_d_arity: TREE_D_ARITY,
     };
 
     let pp = StackedDrg::<Tree, Blake2sHasher>::setup(&sp).expect("setup failed");
@@ -407,6 +595,186 @@ fn test_prove_verify<Tree: 'static + MerkleTreeTrait>(n: usize, challenges: Laye
     cache_dir.close().expect("Failed to remove cache dir");
 }
 
+#[test]
+fn test_stacked_porep_prove_verify_without_tree_c() {
+    type Tree = DiskTree<PoseidonHasher, U8, U2, U0>;
+
+    let nodes = 64 * get_base_tree_count::<Tree>();
+    let mut rng = XorShiftRng::from_seed(TEST_SEED);
+
+    let replica_id = <PoseidonHasher as Hasher>::Domain::random(&mut rng);
+    let data: Vec<u8> = (0..nodes)
+        .flat_map(|_| fr_into_bytes(&Fr::random(&mut rng)))
+        .collect();
+
+    let cache_dir = tempdir().expect("tempdir failure");
+    let config = StoreConfig::new(cache_dir.path(), CacheKey::CommDTree.to_string(), 0);
+    let replica_path = cache_dir.path().join("replica-path");
+    let mut mmapped_data = setup_replica(&data, &replica_path);
+
+    let sp = SetupParams {
+        nodes,
+        degree: BASE_DEGREE,
+        expansion_degree: EXP_DEGREE,
+        porep_id: [92; 32],
+        layer_challenges: LayerChallenges::new(DEFAULT_STACKED_LAYERS, 5),
+        api_version: ApiVersion::V1_2_0,
+        api_features: vec![],
+        tree_d_arity: TREE_D_ARITY,
+    };
+
+    let pp = StackedDrg::<Tree, Blake2sHasher>::setup(&sp).expect("setup failed");
+    let (tau, (p_aux, t_aux)) = common::transform_and_replicate_layers::<Tree, Blake2sHasher>(
+        &pp,
+        &replica_id,
+        (mmapped_data.as_mut()).into(),
+        config.path,
+        replica_path.clone(),
+    );
+
+    // Remove tree_c, it is regenerated from the labels during proving.
+    stacked::clear_tree_c(cache_dir.path()).expect("failed to remove tree_c");
+    let tree_c_glob =
+        StoreConfig::data_path(cache_dir.path(), &format!("{}*", CacheKey::CommCTree));
+    let tree_c_exists = || {
+        glob(&tree_c_glob.to_string_lossy())
+            .expect("invalid glob")
+            .next()
+            .is_some()
+    };
+    assert!(!tree_c_exists());
+
+    let pub_inputs =
+        PublicInputs::<<PoseidonHasher as Hasher>::Domain, <Blake2sHasher as Hasher>::Domain> {
+            replica_id,
+            seed: Some(rng.gen()),
+            tau: Some(tau),
+            k: None,
+        };
+
+    let t_aux = TemporaryAuxCache::<Tree, Blake2sHasher>::new(&t_aux, replica_path, false)
+        .expect("failed to restore contents of t_aux");
+    assert!(t_aux.tree_c.is_none());
+
+    let priv_inputs = PrivateInputs { p_aux, t_aux };
+
+    let all_partition_proofs =
+        &StackedDrg::<Tree, Blake2sHasher>::prove_all_partitions(&pp, &pub_inputs, &priv_inputs, 2)
+            .expect("failed to generate partition proofs");
+
+    // The regenerated tree_c is discarded after proving.
+    assert!(!tree_c_exists());
+
+    let proofs_are_valid = StackedDrg::<Tree, Blake2sHasher>::verify_all_partitions(
+        &pp,
+        &pub_inputs,
+        all_partition_proofs,
+    )
+    .expect("failed to verify partition proofs");
+    assert!(proofs_are_valid);
+
+    cache_dir.close().expect("Failed to remove cache dir");
+}
+
+#[test]
+fn test_stacked_porep_prove_from_stores() {
+    type Tree = DiskTree<PoseidonHasher, U8, U0, U0>;
+
+    let nodes = 64;
+    let layers = 2;
+    let mut rng = XorShiftRng::from_seed(TEST_SEED);
+
+    let replica_id = <PoseidonHasher as Hasher>::Domain::random(&mut rng);
+    let data: Vec<u8> = (0..nodes)
+        .flat_map(|_| fr_into_bytes(&Fr::random(&mut rng)))
+        .collect();
+
+    let cache_dir = tempdir().expect("tempdir failure");
+    let config = StoreConfig::new(cache_dir.path(), CacheKey::CommDTree.to_string(), 0);
+    let replica_path = cache_dir.path().join("replica-path");
+    let mut mmapped_data = setup_replica(&data, &replica_path);
+
+    let sp = SetupParams {
+        nodes,
+        degree: BASE_DEGREE,
+        expansion_degree: EXP_DEGREE,
+        porep_id: [92; 32],
+        layer_challenges: LayerChallenges::new(layers, 5),
+        api_version: ApiVersion::V1_2_0,
+        api_features: vec![],
+        tree_d_arity: TREE_D_ARITY,
+    };
+
+    let pp = StackedDrg::<Tree, Blake2sHasher>::setup(&sp).expect("setup failed");
+    let (tau, (p_aux, t_aux)) = common::transform_and_replicate_layers::<Tree, Blake2sHasher>(
+        &pp,
+        &replica_id,
+        (mmapped_data.as_mut()).into(),
+        config.path,
+        replica_path.clone(),
+    );
+
+    let pub_inputs =
+        PublicInputs::<<PoseidonHasher as Hasher>::Domain, <Blake2sHasher as Hasher>::Domain> {
+            replica_id,
+            seed: Some(rng.gen()),
+            tau: Some(tau),
+            k: None,
+        };
+
+    // Open the stores by hand and read the replica through a backend instead of the file.
+    let size = |config: &StoreConfig| config.size.expect("config size failure");
+    let stores = TemporaryAuxStores::<Tree, Blake2sHasher, BackendReader<FileBackend>> {
+        labels: (1..=layers)
+            .map(|layer| t_aux.labels.labels_for_layer(layer))
+            .collect::<Result<_, _>>()
+            .expect("failed to open labels"),
+        tree_d: Some(
+            DiskStore::new_from_disk(size(&t_aux.tree_d_config), 2, &t_aux.tree_d_config)
+                .expect("failed to open tree_d"),
+        ),
+        tree_c: Some(vec![DiskStore::new_from_disk(
+            size(&t_aux.tree_c_config),
+            8,
+            &t_aux.tree_c_config,
+        )
+        .expect("failed to open tree_c")]),
+        tree_r_last: vec![LevelCacheStore::new_from_disk(
+            size(&t_aux.tree_r_last_config),
+            8,
+            &t_aux.tree_r_last_config,
+        )
+        .expect("failed to open tree_r_last")],
+    };
+    let replica_backend = FileBackend::open(&replica_path).expect("failed to open replica");
+    let t_aux_cache = TemporaryAuxCache::from_stores(
+        &t_aux,
+        stores,
+        vec![backend_external_reader(replica_backend, 0)],
+        replica_path,
+    )
+    .expect("failed to create t_aux cache from stores");
+
+    let all_partition_proofs = &StackedDrg::<Tree, Blake2sHasher>::prove_all_partitions_with_cache(
+        &pp,
+        &pub_inputs,
+        &p_aux,
+        &t_aux_cache,
+        2,
+    )
+    .expect("failed to generate partition proofs");
+
+    let proofs_are_valid = StackedDrg::<Tree, Blake2sHasher>::verify_all_partitions(
+        &pp,
+        &pub_inputs,
+        all_partition_proofs,
+    )
+    .expect("failed to verify partition proofs");
+    assert!(proofs_are_valid);
+
+    cache_dir.close().expect("Failed to remove cache dir");
+}
+
 // We are seeing a bug, in which setup never terminates for some sector sizes. This test is to
 // debug that and should remain as a regression test.
 #[test]
@@ -423,6 +791,7 @@ fn test_stacked_porep_setup_terminates() {
         layer_challenges,
         api_version: ApiVersion::V1_2_0,
         api_features: vec![],
+        tree_d_arity: TREE_D_ARITY,
     };
 
     // When this fails, the call to setup should panic, but seems to actually hang (i.e. neither return nor panic) for some reason.
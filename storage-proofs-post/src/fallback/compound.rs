@@ -4,7 +4,6 @@ use anyhow::{anyhow, ensure};
 use bellperson::Circuit;
 use blstrs::Scalar as Fr;
 use filecoin_hashers::Hasher;
-use sha2::{Digest, Sha256};
 use storage_proofs_core::{
     compound_proof::{CircuitComponent, CompoundProof},
     error::Result,
@@ -17,7 +16,8 @@ use storage_proofs_core::{
 };
 
 use crate::fallback::{
-    generate_leaf_challenge_inner, get_challenge_index, FallbackPoSt, FallbackPoStCircuit, Sector,
+    generate_leaf_challenge_inner, get_challenge_index, leaf_challenge_hasher, FallbackPoSt,
+    FallbackPoStCircuit, Sector,
 };
 
 pub struct FallbackPoStCompound<Tree>
@@ -66,9 +66,8 @@ impl<'a, Tree: 'static + MerkleTreeTrait>
             inputs.push(sector.comm_r.into());
 
             // avoid rehashing fixed inputs
-            let mut challenge_hasher = Sha256::new();
-            challenge_hasher.update(AsRef::<[u8]>::as_ref(&pub_inputs.randomness));
-            challenge_hasher.update(&u64::from(sector.id).to_le_bytes()[..]);
+            let challenge_hasher =
+                leaf_challenge_hasher(pub_params, &pub_inputs.randomness, u64::from(sector.id));
 
             // 2. Inputs for verifying inclusion paths
             for n in 0..pub_params.challenge_count {
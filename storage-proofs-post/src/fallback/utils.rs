@@ -1,4 +1,8 @@
-use storage_proofs_core::api_version::ApiVersion;
+use anyhow::ensure;
+use filecoin_hashers::Domain;
+use storage_proofs_core::{api_version::ApiVersion, error::Result};
+
+use super::vanilla::PublicInputs;
 
 /// Selects the challenge index used to determine the leaf challenge for PoSt
 pub fn get_challenge_index(
@@ -14,3 +18,42 @@ pub fn get_challenge_index(
         ApiVersion::V1_2_0 => challenge_index,
     } as u64)
 }
+
+/// Number of partitions needed to prove `num_sectors` sectors, with `sector_count` sectors per
+/// partition. There is always at least one partition.
+pub fn partition_count(num_sectors: usize, sector_count: usize) -> usize {
+    assert!(sector_count > 0, "sector count must be non-zero");
+    std::cmp::max(1, (num_sectors + sector_count - 1) / sector_count)
+}
+
+/// Splits the public inputs of a window PoSt into the public inputs of its partitions.
+///
+/// Partition `k` proves the `k`-th chunk of `sector_count` consecutive sectors, which is the
+/// layout used by `prove_all_partitions` and `verify_all_partitions`. The sectors must be sorted
+/// by id without duplicates, the order in which `filecoin-proofs` passes them.
+pub fn partition_public_inputs<T: Domain>(
+    pub_inputs: &PublicInputs<T>,
+    sector_count: usize,
+) -> Result<Vec<PublicInputs<T>>> {
+    ensure!(sector_count > 0, "sector count must be non-zero");
+    ensure!(!pub_inputs.sectors.is_empty(), "no sectors to partition");
+    ensure!(
+        pub_inputs
+            .sectors
+            .windows(2)
+            .all(|pair| pair[0].id < pair[1].id),
+        "sectors must be sorted by id without duplicates"
+    );
+
+    Ok(pub_inputs
+        .sectors
+        .chunks(sector_count)
+        .enumerate()
+        .map(|(k, sectors)| PublicInputs {
+            randomness: pub_inputs.randomness,
+            prover_id: pub_inputs.prover_id,
+            sectors: sectors.to_vec(),
+            k: Some(k),
+        })
+        .collect())
+}
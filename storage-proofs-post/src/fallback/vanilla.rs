@@ -14,6 +14,7 @@ use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use storage_proofs_core::{
     api_version::ApiVersion,
+    challenge_domain::ChallengeDomain,
     error::{Error, Result},
     merkle::{MerkleProof, MerkleProofTrait, MerkleTreeTrait, MerkleTreeWrapper},
     parameter_cache::ParameterSetMetadata,
@@ -33,6 +34,7 @@ pub struct SetupParams {
     /// Number of challenged sectors.
     pub sector_count: usize,
     pub api_version: ApiVersion,
+    pub challenge_domain: ChallengeDomain,
 }
 
 #[derive(Debug, Clone)]
@@ -44,6 +46,7 @@ pub struct PublicParams {
     /// Number of challenged sectors.
     pub sector_count: usize,
     pub api_version: ApiVersion,
+    pub challenge_domain: ChallengeDomain,
 }
 
 #[derive(Debug, Default)]
@@ -165,6 +168,78 @@ impl<P: MerkleProofTrait> SectorProof<P> {
     ) -> &Vec<MerkleProof<P::Hasher, P::Arity, P::SubTreeArity, P::TopTreeArity>> {
         &self.inclusion_proofs
     }
+
+    /// Encodes the proof as `comm_c || comm_r_last || count || inclusion proofs`, with the
+    /// count as little endian u32 and the inclusion proofs as in `MerkleProof::to_bytes`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&self.comm_c.into_bytes());
+        bytes.extend_from_slice(&self.comm_r_last.into_bytes());
+        bytes.extend_from_slice(&(self.inclusion_proofs.len() as u32).to_le_bytes());
+        for proof in &self.inclusion_proofs {
+            bytes.extend_from_slice(&proof.to_bytes());
+        }
+        bytes
+    }
+
+    /// Decodes a proof from the format written by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        ensure!(
+            bytes.len() >= 2 * NODE_SIZE + 4,
+            "sector proof bytes too short"
+        );
+
+        let comm_c = <P::Hasher as Hasher>::Domain::try_from_bytes(&bytes[..NODE_SIZE])?;
+        let comm_r_last =
+            <P::Hasher as Hasher>::Domain::try_from_bytes(&bytes[NODE_SIZE..2 * NODE_SIZE])?;
+        let count = LittleEndian::read_u32(&bytes[2 * NODE_SIZE..2 * NODE_SIZE + 4]) as usize;
+
+        let mut pos = 2 * NODE_SIZE + 4;
+        let mut inclusion_proofs = Vec::with_capacity(count);
+        for _ in 0..count {
+            ensure!(pos + 4 <= bytes.len(), "sector proof bytes too short");
+            let base_len = LittleEndian::read_u32(&bytes[pos..pos + 4]) as usize;
+            let len = MerkleProof::<P::Hasher, P::Arity, P::SubTreeArity, P::TopTreeArity>::serialized_len(base_len);
+            ensure!(pos + len <= bytes.len(), "sector proof bytes too short");
+
+            inclusion_proofs.push(MerkleProof::from_bytes(&bytes[pos..pos + len])?);
+            pos += len;
+        }
+        ensure!(pos == bytes.len(), "trailing bytes after sector proof");
+
+        Ok(SectorProof {
+            inclusion_proofs,
+            comm_c,
+            comm_r_last,
+        })
+    }
+}
+
+/// Version tag of the `sector_proof_cache_key` scheme.
+pub const SECTOR_PROOF_CACHE_KEY_VERSION: &[u8] = b"fallback-post-sector-proof-v1";
+
+/// Key for caching the vanilla proof of a single sector.
+///
+/// A sector proof only depends on the replica and on the challenged leafs. The randomness is
+/// part of the key as well, so that entries of different windows are kept apart. The key is the
+/// sha256 of `SECTOR_PROOF_CACHE_KEY_VERSION || comm_r || randomness || challenges`, with every
+/// challenged leaf index as little endian u64, in the order they were proven. Services which
+/// precompute the tree reads for a randomness window can store the proofs under this key and
+/// replay them once the deadline opens.
+pub fn sector_proof_cache_key<T: Domain>(
+    comm_r: &T,
+    randomness: &T,
+    challenges: &[u64],
+) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(SECTOR_PROOF_CACHE_KEY_VERSION);
+    hasher.update(AsRef::<[u8]>::as_ref(comm_r));
+    hasher.update(AsRef::<[u8]>::as_ref(randomness));
+    for challenge in challenges {
+        hasher.update(&challenge.to_le_bytes()[..]);
+    }
+
+    hasher.finalize().into()
 }
 
 #[derive(Debug, Clone)]
@@ -180,9 +255,34 @@ pub fn generate_sector_challenges<T: Domain>(
     challenge_count: usize,
     sector_set_len: u64,
     prover_id: T,
+) -> Result<Vec<u64>> {
+    generate_sector_challenges_with_domain(
+        randomness,
+        challenge_count,
+        sector_set_len,
+        prover_id,
+        &ChallengeDomain::Mainnet,
+    )
+}
+
+/// Like [`generate_sector_challenges`], with the challenges derived in `challenge_domain`.
+pub fn generate_sector_challenges_with_domain<T: Domain>(
+    randomness: T,
+    challenge_count: usize,
+    sector_set_len: u64,
+    prover_id: T,
+    challenge_domain: &ChallengeDomain,
 ) -> Result<Vec<u64>> {
     (0..challenge_count)
-        .map(|n| generate_sector_challenge(randomness, n, sector_set_len, prover_id))
+        .map(|n| {
+            generate_sector_challenge_with_domain(
+                randomness,
+                n,
+                sector_set_len,
+                prover_id,
+                challenge_domain,
+            )
+        })
         .collect()
 }
 
@@ -193,7 +293,24 @@ pub fn generate_sector_challenge<T: Domain>(
     sector_set_len: u64,
     prover_id: T,
 ) -> Result<u64> {
-    let mut hasher = Sha256::new();
+    generate_sector_challenge_with_domain(
+        randomness,
+        n,
+        sector_set_len,
+        prover_id,
+        &ChallengeDomain::Mainnet,
+    )
+}
+
+/// Like [`generate_sector_challenge`], with the challenge derived in `challenge_domain`.
+pub fn generate_sector_challenge_with_domain<T: Domain>(
+    randomness: T,
+    n: usize,
+    sector_set_len: u64,
+    prover_id: T,
+    challenge_domain: &ChallengeDomain,
+) -> Result<u64> {
+    let mut hasher = challenge_domain.sha256();
     hasher.update(AsRef::<[u8]>::as_ref(&prover_id));
     hasher.update(AsRef::<[u8]>::as_ref(&randomness));
     hasher.update(&n.to_le_bytes()[..]);
@@ -215,9 +332,7 @@ pub fn generate_leaf_challenges<T: Domain>(
 ) -> Vec<u64> {
     let mut challenges = Vec::with_capacity(challenge_count);
 
-    let mut hasher = Sha256::new();
-    hasher.update(AsRef::<[u8]>::as_ref(&randomness));
-    hasher.update(&sector_id.to_le_bytes()[..]);
+    let hasher = leaf_challenge_hasher(pub_params, &randomness, sector_id);
 
     for challenge_index in 0..challenge_count {
         let challenge =
@@ -235,13 +350,24 @@ pub fn generate_leaf_challenge<T: Domain>(
     sector_id: u64,
     leaf_challenge_index: u64,
 ) -> u64 {
-    let mut hasher = Sha256::new();
-    hasher.update(AsRef::<[u8]>::as_ref(&randomness));
-    hasher.update(&sector_id.to_le_bytes()[..]);
+    let hasher = leaf_challenge_hasher(pub_params, &randomness, sector_id);
 
     generate_leaf_challenge_inner::<T>(hasher, pub_params, leaf_challenge_index)
 }
 
+/// Returns the hasher of the fixed inputs of all leaf challenges of a sector, to be passed to
+/// [`generate_leaf_challenge_inner`].
+pub fn leaf_challenge_hasher<T: Domain>(
+    pub_params: &PublicParams,
+    randomness: &T,
+    sector_id: u64,
+) -> Sha256 {
+    let mut hasher = pub_params.challenge_domain.sha256();
+    hasher.update(AsRef::<[u8]>::as_ref(randomness));
+    hasher.update(&sector_id.to_le_bytes()[..]);
+    hasher
+}
+
 pub fn generate_leaf_challenge_inner<T: Domain>(
     mut hasher: Sha256,
     pub_params: &PublicParams,
@@ -320,6 +446,7 @@ impl<'a, Tree: 'a + MerkleTreeTrait> ProofScheme<'a> for FallbackPoSt<'a, Tree>
             challenge_count: sp.challenge_count,
             sector_count: sp.sector_count,
             api_version: sp.api_version,
+            challenge_domain: sp.challenge_domain,
         })
     }
 
@@ -395,9 +522,11 @@ impl<'a, Tree: 'a + MerkleTreeTrait> ProofScheme<'a> for FallbackPoSt<'a, Tree>
                     );
 
                     // avoid rehashing fixed inputs
-                    let mut challenge_hasher = Sha256::new();
-                    challenge_hasher.update(AsRef::<[u8]>::as_ref(&pub_inputs.randomness));
-                    challenge_hasher.update(&u64::from(sector_id).to_le_bytes()[..]);
+                    let challenge_hasher = leaf_challenge_hasher(
+                        pub_params,
+                        &pub_inputs.randomness,
+                        u64::from(sector_id),
+                    );
 
                     let (inclusion_proofs, faults) = (0..pub_params.challenge_count)
                         .into_par_iter()
@@ -628,9 +757,8 @@ impl<'a, Tree: 'a + MerkleTreeTrait> ProofScheme<'a> for FallbackPoSt<'a, Tree>
                 );
 
                 // avoid rehashing fixed inputs
-                let mut challenge_hasher = Sha256::new();
-                challenge_hasher.update(AsRef::<[u8]>::as_ref(&pub_inputs.randomness));
-                challenge_hasher.update(&u64::from(sector_id).to_le_bytes()[..]);
+                let challenge_hasher =
+                    leaf_challenge_hasher(pub_params, &pub_inputs.randomness, u64::from(sector_id));
 
                 let is_valid_list = inclusion_proofs
                     .par_iter()
@@ -10,6 +10,7 @@ use rand::SeedableRng;
 use rand_xorshift::XorShiftRng;
 use storage_proofs_core::{
     api_version::ApiVersion,
+    challenge_domain::ChallengeDomain,
     compound_proof::CompoundProof,
     error::Result,
     merkle::{generate_tree, get_base_tree_count, DiskTree, LCTree, MerkleTreeTrait},
@@ -74,6 +75,7 @@ fn test_fallback_post<Tree: 'static + MerkleTreeTrait>(
         challenge_count: 5,
         sector_count,
         api_version: ApiVersion::V1_1_0,
+        challenge_domain: ChallengeDomain::Mainnet,
     };
 
     let temp_dir = tempdir().expect("tempdir failure");
@@ -209,6 +211,7 @@ fn test_fallback_post_circuit_poseidon_base_8_bench_cs() {
         challenge_count: 10,
         sector_count: 5,
         api_version: ApiVersion::V1_1_0,
+        challenge_domain: ChallengeDomain::Mainnet,
     };
 
     let pp = FallbackPoSt::<DiskTree<PoseidonHasher, U8, U0, U0>>::setup(&params)
@@ -10,6 +10,7 @@ use rand::SeedableRng;
 use rand_xorshift::XorShiftRng;
 use storage_proofs_core::{
     api_version::ApiVersion,
+    challenge_domain::ChallengeDomain,
     compound_proof::{self, CompoundProof},
     merkle::{generate_tree, get_base_tree_count, LCTree, MerkleTreeTrait},
     multi_proof::MultiProof,
@@ -86,6 +87,7 @@ fn fallback_post<Tree: 'static + MerkleTreeTrait>(
             challenge_count,
             sector_count,
             api_version,
+            challenge_domain: ChallengeDomain::Mainnet,
         },
         partitions: Some(partitions),
         priority: false,
@@ -4,6 +4,7 @@ use rand::SeedableRng;
 use rand_xorshift::XorShiftRng;
 use storage_proofs_core::{
     api_version::ApiVersion,
+    challenge_domain::ChallengeDomain,
     error::Error,
     merkle::{generate_tree, get_base_tree_count, LCTree, MerkleTreeTrait},
     proof::ProofScheme,
@@ -144,6 +145,7 @@ fn test_fallback_post<Tree: MerkleTreeTrait>(
         challenge_count: 10,
         sector_count,
         api_version,
+        challenge_domain: ChallengeDomain::Mainnet,
     };
 
     let randomness = <Tree::Hasher as Hasher>::Domain::random(rng);
@@ -200,7 +202,35 @@ fn test_fallback_post<Tree: MerkleTreeTrait>(
 
     assert!(is_valid);
 
+    // Every partition verifies on its own with the public inputs of the partition layout.
+    let partition_inputs = fallback::partition_public_inputs(&pub_inputs, pub_params.sector_count)
+        .expect("partitioning failed");
+    assert_eq!(
+        partition_inputs.len(),
+        fallback::partition_count(pub_inputs.sectors.len(), pub_params.sector_count)
+    );
+    assert_eq!(partition_inputs.len(), proof.len());
+    for (inputs, partition_proof) in partition_inputs.iter().zip(proof.iter()) {
+        assert!(
+            FallbackPoSt::<Tree>::verify(&pub_params, inputs, partition_proof)
+                .expect("verification failed")
+        );
+    }
+
+    // Sector proofs survive a round trip through their byte encoding.
+    for partition_proof in &proof {
+        for sector_proof in &partition_proof.sectors {
+            let bytes = sector_proof.to_bytes();
+            let decoded = fallback::SectorProof::<Tree::Proof>::from_bytes(&bytes)
+                .expect("sector proof decoding failed");
+            assert_eq!(decoded.to_bytes(), bytes);
+            assert_eq!(decoded.comm_r_last, sector_proof.comm_r_last);
+            assert!(fallback::SectorProof::<Tree::Proof>::from_bytes(&bytes[1..]).is_err());
+        }
+    }
+
     // Challenge generation algorithm changed in version `ApiVersion::V1_2_0`.
+    let challenge_gen_version = pub_params.api_version;
     let mismatched_challenge_gen_version = match pub_params.api_version {
         ApiVersion::V1_0_0 | ApiVersion::V1_1_0 => ApiVersion::V1_2_0,
         _ => ApiVersion::V1_1_0,
@@ -210,6 +240,14 @@ fn test_fallback_post<Tree: MerkleTreeTrait>(
         !FallbackPoSt::<Tree>::verify_all_partitions(&pub_params, &pub_inputs, &proof)
             .expect("verification failed")
     );
+
+    // Proofs of one network do not verify on another.
+    pub_params.api_version = challenge_gen_version;
+    pub_params.challenge_domain = ChallengeDomain::network("calibnet");
+    assert!(
+        !FallbackPoSt::<Tree>::verify_all_partitions(&pub_params, &pub_inputs, &proof)
+            .expect("verification failed")
+    );
 }
 
 #[test]
@@ -342,6 +380,7 @@ fn test_invalid_fallback_post<Tree: MerkleTreeTrait>(
         challenge_count: 10,
         sector_count,
         api_version,
+        challenge_domain: ChallengeDomain::Mainnet,
     };
 
     let randomness = <Tree::Hasher as Hasher>::Domain::random(rng);
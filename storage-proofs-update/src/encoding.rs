@@ -0,0 +1,257 @@
+//! The encoding of an updated replica, exposed for implementations of the encoding outside of
+//! this crate.
+//!
+//! A node of the new replica is `replica = sector_key + data * rho`, where `rho` depends on the
+//! `h` high bits of the node index, see [`rho`]. Decoding is `data = (replica - sector_key) *
+//! rho^-1`.
+
+use anyhow::ensure;
+use blstrs::Scalar as Fr;
+use ff::Field;
+use fr32::{bytes_into_fr, fr_into_bytes_slice};
+use storage_proofs_core::error::Result;
+
+use crate::{
+    constants::{TreeDDomain, TreeRDomain},
+    vanilla::{phi, rho, Rhos},
+};
+
+const FR_SIZE: usize = std::mem::size_of::<Fr>();
+
+/// Derives the `2^h` rhos of an update, the rho of a node is at the index given by the `h` high
+/// bits of the node index.
+pub fn derive_rhos(comm_d_new: &TreeDDomain, comm_r_old: &TreeRDomain, h: usize) -> Vec<Fr> {
+    let phi = phi(comm_d_new, comm_r_old);
+    (0..1u32 << h).map(|high| rho(&phi, high)).collect()
+}
+
+/// Derives the inverses of the `2^h` rhos of an update, which are used for decoding.
+pub fn derive_rho_invs(comm_d_new: &TreeDDomain, comm_r_old: &TreeRDomain, h: usize) -> Vec<Fr> {
+    derive_rhos(comm_d_new, comm_r_old, h)
+        .into_iter()
+        .map(|rho| rho.invert().expect("rho inversion should not fail"))
+        .collect()
+}
+
+/// Encodes a single node of the new replica.
+#[inline]
+pub fn encode_new_with_rho(sector_key: Fr, data: Fr, rho: Fr) -> Fr {
+    sector_key + data * rho
+}
+
+/// Decodes a single node of the new replica, `rho_inv` is the inverse of the node's rho.
+#[inline]
+pub fn decode_with_rho(replica: Fr, sector_key: Fr, rho_inv: Fr) -> Fr {
+    (replica - sector_key) * rho_inv
+}
+
+/// Encodes a batch of consecutive nodes of the new replica. `node_offset` is the index of the
+/// first node of the batch within the sector, it selects the rhos.
+pub fn encode_batch(
+    sector_key: &[Fr],
+    data: &[Fr],
+    rhos: &Rhos,
+    node_offset: usize,
+) -> Result<Vec<Fr>> {
+    ensure!(sector_key.len() == data.len(), "batch size mismatch");
+
+    Ok(sector_key
+        .iter()
+        .zip(data.iter())
+        .enumerate()
+        .map(|(i, (key, data))| encode_new_with_rho(*key, *data, rhos.get(node_offset + i)))
+        .collect())
+}
+
+/// Decodes a batch of consecutive nodes of the new replica. `node_offset` is the index of the
+/// first node of the batch within the sector, it selects the inverted rhos.
+pub fn decode_batch(
+    replica: &[Fr],
+    sector_key: &[Fr],
+    rho_invs: &Rhos,
+    node_offset: usize,
+) -> Result<Vec<Fr>> {
+    ensure!(replica.len() == sector_key.len(), "batch size mismatch");
+
+    Ok(replica
+        .iter()
+        .zip(sector_key.iter())
+        .enumerate()
+        .map(|(i, (replica, key))| decode_with_rho(*replica, *key, rho_invs.get(node_offset + i)))
+        .collect())
+}
+
+/// Encodes a slab of nodes, given as bytes, into `replica`. `node_offset` is the index of the
+/// first node of the slab within the sector, it selects the rhos.
+pub fn encode_slab(
+    sector_key: &[u8],
+    data: &[u8],
+    replica: &mut [u8],
+    rhos: &Rhos,
+    node_offset: usize,
+) -> Result<()> {
+    ensure!(
+        sector_key.len() == replica.len() && data.len() == replica.len(),
+        "slab size mismatch"
+    );
+    ensure!(
+        replica.len() % FR_SIZE == 0,
+        "slab must consist of whole nodes"
+    );
+
+    for (i, ((key, data), out)) in sector_key
+        .chunks_exact(FR_SIZE)
+        .zip(data.chunks_exact(FR_SIZE))
+        .zip(replica.chunks_exact_mut(FR_SIZE))
+        .enumerate()
+    {
+        let rho = rhos.get(node_offset + i);
+        let replica_fr = encode_new_with_rho(bytes_into_fr(key)?, bytes_into_fr(data)?, rho);
+        fr_into_bytes_slice(&replica_fr, out);
+    }
+
+    Ok(())
+}
+
+/// Decodes a slab of nodes, given as bytes, into `data`. `node_offset` is the index of the first
+/// node of the slab within the sector, it selects the inverted rhos.
+pub fn decode_slab(
+    replica: &[u8],
+    sector_key: &[u8],
+    data: &mut [u8],
+    rho_invs: &Rhos,
+    node_offset: usize,
+) -> Result<()> {
+    ensure!(
+        replica.len() == data.len() && sector_key.len() == data.len(),
+        "slab size mismatch"
+    );
+    ensure!(
+        data.len() % FR_SIZE == 0,
+        "slab must consist of whole nodes"
+    );
+
+    for (i, ((replica, key), out)) in replica
+        .chunks_exact(FR_SIZE)
+        .zip(sector_key.chunks_exact(FR_SIZE))
+        .zip(data.chunks_exact_mut(FR_SIZE))
+        .enumerate()
+    {
+        let rho_inv = rho_invs.get(node_offset + i);
+        let data_fr = decode_with_rho(bytes_into_fr(replica)?, bytes_into_fr(key)?, rho_inv);
+        fr_into_bytes_slice(&data_fr, out);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use filecoin_hashers::Domain;
+    use fr32::fr_into_bytes;
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+    use storage_proofs_core::TEST_SEED;
+
+    #[test]
+    fn test_encode_decode_vectors() {
+        let sector_key = Fr::ONE;
+        let data = Fr::from(0x0123_4567_89ab_cdef);
+        let rho = -Fr::from(3);
+        let expected: [u8; 32] = [
+            0x35, 0x96, 0xfc, 0x62, 0xc8, 0x2f, 0x96, 0xfc, 0xfe, 0x5b, 0xfe, 0xff, 0x02, 0xa4,
+            0xbd, 0x53, 0x05, 0xd8, 0xa1, 0x09, 0x08, 0xd8, 0x39, 0x33, 0x48, 0x7d, 0x9d, 0x29,
+            0x53, 0xa7, 0xed, 0x73,
+        ];
+
+        let replica = encode_new_with_rho(sector_key, data, rho);
+        assert_eq!(fr_into_bytes(&replica), expected);
+
+        let rho_inv = rho.invert().expect("rho inversion failed");
+        assert_eq!(decode_with_rho(replica, sector_key, rho_inv), data);
+    }
+
+    #[test]
+    fn test_slabs() {
+        let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+        let comm_d_new = TreeDDomain::random(rng);
+        let comm_r_old = TreeRDomain::random(rng);
+        let nodes_count = 64;
+        let h = 2;
+
+        let rhos = derive_rhos(&comm_d_new, &comm_r_old, h);
+        let rho_invs = derive_rho_invs(&comm_d_new, &comm_r_old, h);
+        assert_eq!(rhos.len(), 1 << h);
+        for (rho, rho_inv) in rhos.iter().zip(rho_invs.iter()) {
+            assert_eq!(*rho * rho_inv, Fr::ONE);
+        }
+
+        let phi = phi(&comm_d_new, &comm_r_old);
+        let rhos_map = Rhos::new(&phi, h, nodes_count);
+        let rho_invs_map = Rhos::new_inv(&phi, h, nodes_count);
+        for node in 0..nodes_count {
+            // With 64 nodes and `h = 2`, the high bits are the node index shifted by 4.
+            assert_eq!(rhos_map.get(node), rhos[node >> 4]);
+            assert_eq!(rho_invs_map.get(node), rho_invs[node >> 4]);
+        }
+
+        let random_nodes = |rng: &mut XorShiftRng| -> Vec<u8> {
+            (0..nodes_count)
+                .flat_map(|_| fr_into_bytes(&Fr::random(&mut *rng)))
+                .collect()
+        };
+        let sector_key = random_nodes(rng);
+        let data = random_nodes(rng);
+
+        // Encode the second half of the sector, the rhos must be selected by the sector offset.
+        let half = nodes_count / 2 * FR_SIZE;
+        let mut replica = vec![0u8; half];
+        encode_slab(
+            &sector_key[half..],
+            &data[half..],
+            &mut replica,
+            &rhos_map,
+            nodes_count / 2,
+        )
+        .expect("encoding failed");
+        for (i, node) in replica.chunks(FR_SIZE).enumerate() {
+            let offset = half + i * FR_SIZE;
+            let expected = encode_new_with_rho(
+                bytes_into_fr(&sector_key[offset..offset + FR_SIZE]).expect("invalid Fr"),
+                bytes_into_fr(&data[offset..offset + FR_SIZE]).expect("invalid Fr"),
+                rhos[(nodes_count / 2 + i) >> 4],
+            );
+            assert_eq!(bytes_into_fr(node).expect("invalid Fr"), expected);
+        }
+
+        let mut decoded = vec![0u8; half];
+        decode_slab(
+            &replica,
+            &sector_key[half..],
+            &mut decoded,
+            &rho_invs_map,
+            nodes_count / 2,
+        )
+        .expect("decoding failed");
+        assert_eq!(decoded, &data[half..]);
+
+        // The batch variants must agree with the slabs.
+        let to_frs = |bytes: &[u8]| -> Vec<Fr> {
+            bytes
+                .chunks(FR_SIZE)
+                .map(|node| bytes_into_fr(node).expect("invalid Fr"))
+                .collect()
+        };
+        let key_frs = to_frs(&sector_key[half..]);
+        let replica_frs =
+            encode_batch(&key_frs, &to_frs(&data[half..]), &rhos_map, nodes_count / 2)
+                .expect("batch encoding failed");
+        assert_eq!(replica_frs, to_frs(&replica));
+        let data_frs = decode_batch(&replica_frs, &key_frs, &rho_invs_map, nodes_count / 2)
+            .expect("batch decoding failed");
+        assert_eq!(data_frs, to_frs(&data[half..]));
+        assert!(encode_batch(&key_frs[1..], &data_frs, &rhos_map, 0).is_err());
+    }
+}
@@ -1,6 +1,7 @@
 pub mod circuit;
 pub mod compound;
 pub mod constants;
+pub mod encoding;
 pub(crate) mod gadgets;
 pub mod poseidon;
 pub mod vanilla;
@@ -41,6 +41,7 @@ use crate::{
         TreeDArity, TreeDDomain, TreeDHasher, TreeDStore, TreeRDomain, TreeRHasher,
         ALLOWED_SECTOR_SIZES, POSEIDON_CONSTANTS_GEN_RANDOMNESS,
     },
+    encoding::{decode_slab, encode_slab},
     Challenges,
 };
 
@@ -676,6 +677,43 @@ impl<TreeR> EmptySectorUpdate<TreeR>
 where
     TreeR: 'static + MerkleTreeTrait<Hasher = TreeRHasher>,
 {
+    /// Returns the config of the tree_d of an updated sector with `nodes_count` nodes, which is
+    /// stored as `id` in the directory `path`.
+    pub fn new_tree_d_config(
+        nodes_count: usize,
+        path: &Path,
+        id: &str,
+        rows_to_discard: usize,
+    ) -> Result<StoreConfig> {
+        Ok(StoreConfig {
+            path: path.into(),
+            id: id.to_string(),
+            size: Some(get_merkle_tree_len(nodes_count, TreeDArity::to_usize())?),
+            rows_to_discard,
+        })
+    }
+
+    /// Returns the config of the tree_r_last of an updated sector with `nodes_count` nodes. The
+    /// config is the one of the first base tree, the others are derived from it the same way
+    /// as for a sealed sector, see `split_config_and_replica`.
+    pub fn new_tree_r_last_config(
+        nodes_count: usize,
+        path: &Path,
+        id: &str,
+        rows_to_discard: usize,
+    ) -> Result<StoreConfig> {
+        let base_tree_nodes_count = nodes_count / get_base_tree_count::<TreeR>();
+        Ok(StoreConfig {
+            path: path.into(),
+            id: id.to_string(),
+            size: Some(get_merkle_tree_len(
+                base_tree_nodes_count,
+                TreeR::Arity::to_usize(),
+            )?),
+            rows_to_discard,
+        })
+    }
+
     pub fn instantiate_tree_d(
         tree_d_leafs: usize,
         tree_d_new_config: &StoreConfig,
@@ -892,6 +930,34 @@ where
             "sector_key_cache_path must be a directory"
         );
 
+        Self::encode_into_with_configs(
+            nodes_count,
+            tree_d_new_config,
+            tree_r_last_new_config,
+            comm_c,
+            comm_r_last_old,
+            new_replica_path,
+            sector_key_path,
+            staged_data_path,
+            h,
+        )
+    }
+
+    /// Same as `encode_into`, but makes no assumptions about the location of the trees. They
+    /// are written as described by `tree_d_new_config` and `tree_r_last_new_config`.
+    ///
+    /// Returns tuple of (comm_r_new, comm_r_last_new, comm_d_new)
+    pub fn encode_into_with_configs(
+        nodes_count: usize,
+        tree_d_new_config: StoreConfig,
+        tree_r_last_new_config: StoreConfig,
+        comm_c: TreeRDomain,
+        comm_r_last_old: TreeRDomain,
+        new_replica_path: &Path,
+        sector_key_path: &Path,
+        staged_data_path: &Path,
+        h: usize,
+    ) -> Result<(TreeRDomain, TreeRDomain, TreeDDomain)> {
         let tree_count = get_base_tree_count::<TreeR>();
         let base_tree_nodes_count = nodes_count / tree_count;
 
@@ -975,27 +1041,15 @@ where
             .into_par_iter()
             .zip(new_replica_data.par_chunks_mut(data_block_size))
             .try_for_each(|(chunk_index, replica_data)| -> Result<()> {
-                for i in (0..data_block_size as u64).step_by(FR_SIZE) {
-                    let input_index = (chunk_index as usize) + i as usize;
-                    let output_index = i as usize;
-
-                    // Get the `h` high bits from the node-index.
-                    let node_index = input_index / FR_SIZE;
-                    let rho = rhos.get(node_index);
-
-                    let sector_key_fr =
-                        bytes_into_fr(&sector_key_data[input_index..input_index + FR_SIZE])?;
-                    let staged_data_fr =
-                        bytes_into_fr(&staged_data[input_index..input_index + FR_SIZE])?;
-
-                    let new_replica_fr = sector_key_fr + (staged_data_fr * rho);
-                    fr_into_bytes_slice(
-                        &new_replica_fr,
-                        &mut replica_data[output_index..output_index + FR_SIZE],
-                    );
-                }
-
-                Ok(())
+                let start = chunk_index as usize;
+                let end = start + replica_data.len();
+                encode_slab(
+                    &sector_key_data[start..end],
+                    &staged_data[start..end],
+                    replica_data,
+                    &rhos,
+                    start / FR_SIZE,
+                )
             })?;
         new_replica_data.flush()?;
 
@@ -1107,27 +1161,15 @@ where
             .into_par_iter()
             .zip(out_data.par_chunks_mut(data_block_size))
             .try_for_each(|(chunk_index, output_data)| -> Result<()> {
-                for i in (0..data_block_size as u64).step_by(FR_SIZE) {
-                    let input_index = (chunk_index as usize) + i as usize;
-                    let output_index = i as usize;
-
-                    // Get the `h` high bits from the node-index.
-                    let node_index = input_index / FR_SIZE;
-                    let rho_inv = rho_invs.get(node_index);
-
-                    let sector_key_fr =
-                        bytes_into_fr(&sector_key_data[input_index..input_index + FR_SIZE])?;
-                    let replica_data_fr =
-                        bytes_into_fr(&replica_data[input_index..input_index + FR_SIZE])?;
-
-                    let out_data_fr = (replica_data_fr - sector_key_fr) * rho_inv;
-                    fr_into_bytes_slice(
-                        &out_data_fr,
-                        &mut output_data[output_index..output_index + FR_SIZE],
-                    );
-                }
-
-                Ok(())
+                let start = chunk_index as usize;
+                let end = start + output_data.len();
+                decode_slab(
+                    &replica_data[start..end],
+                    &sector_key_data[start..end],
+                    output_data,
+                    &rho_invs,
+                    start / FR_SIZE,
+                )
             })?;
         out_data.flush()?;
 